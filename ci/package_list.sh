@@ -7,6 +7,7 @@ RELEASE_PACKAGES=(
     tedge-agent
     tedge-watchdog
     tedge-apt-plugin
+    tedge-container-plugin
     c8y-remote-access-plugin
     c8y-firmware-plugin
 )
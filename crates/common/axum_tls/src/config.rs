@@ -2,9 +2,12 @@ use crate::load_cert;
 use crate::load_pkey;
 use crate::read_trust_store;
 use crate::ssl_config;
+use crate::ssl_config_with_signing_key;
 use anyhow::anyhow;
 use anyhow::Context;
 use camino::Utf8Path;
+use certificate::key_uri::KeyUri;
+use rustls::sign::SigningKey;
 use rustls::RootCertStore;
 use std::fmt::Debug;
 use std::fs::File;
@@ -13,6 +16,7 @@ use std::io::stderr;
 use std::io::Cursor;
 use std::io::IsTerminal;
 use std::path::Path;
+use std::sync::Arc;
 use tedge_config::OptionalConfig;
 use tracing::info;
 use yansi::Paint;
@@ -93,14 +97,24 @@ pub fn load_ssl_config(
         };
 
         info!(target: "HTTP Server", "{service_name} has HTTPS {enabled} (configured in `{cert_key}`/`{key_key}`) and certificate authentication {ca_state} (configured in `{ca_key}`)", );
-        Ok(Some(ssl_config(cert, key, trust_store)?))
+        let server_config = match key {
+            ServerKey::Der(key) => ssl_config(cert, key, trust_store)?,
+            ServerKey::Token(key) => ssl_config_with_signing_key(cert, key, trust_store),
+        };
+        Ok(Some(server_config))
     } else {
         info!(target: "HTTP Server", "{service_name} has HTTPS {disabled} (configured in `{cert_key}`/`{key_key}`) and certificate authentication {disabled} (configured in `{ca_key}`)");
         Ok(None)
     }
 }
 
-type CertKeyPair = (Vec<Vec<u8>>, Vec<u8>);
+type CertKeyPair = (Vec<Vec<u8>>, ServerKey);
+
+/// The server private key, either read from a PEM file or stored on a PKCS#11 token
+enum ServerKey {
+    Der(Vec<u8>),
+    Token(Arc<dyn SigningKey>),
+}
 
 fn load_certificate_and_key(
     cert_path: OptionalConfig<impl PemReader>,
@@ -110,14 +124,21 @@ fn load_certificate_and_key(
         .map_err(|e| anyhow!("{e}"))?;
 
     if let Some((cert_file, key_file)) = paths {
-        Ok(Some((
-            load_cert(cert_file).with_context(|| {
-                format!("reading certificate configured in `{}`", cert_path.key())
-            })?,
-            load_pkey(key_file).with_context(|| {
+        let cert = load_cert(cert_file)
+            .with_context(|| format!("reading certificate configured in `{}`", cert_path.key()))?;
+        let key = match key_file.pkcs11_uri() {
+            Some(uri) => ServerKey::Token(
+                uri.parse::<KeyUri>()
+                    .and_then(|uri| uri.signing_key())
+                    .with_context(|| {
+                        format!("loading private key configured in `{}`", key_path.key())
+                    })?,
+            ),
+            None => ServerKey::Der(load_pkey(key_file).with_context(|| {
                 format!("reading private key configured in `{}`", key_path.key())
-            })?,
-        )))
+            })?),
+        };
+        Ok(Some((cert, key)))
     } else {
         Ok(None)
     }
@@ -129,6 +150,11 @@ pub trait PemReader: Debug {
         Self: 'a;
 
     fn open(&self) -> io::Result<Self::Read<'_>>;
+
+    /// The PKCS#11 URI of a private key stored on a token, if this is not a PEM file
+    fn pkcs11_uri(&self) -> Option<&str> {
+        None
+    }
 }
 
 pub trait TrustStoreLoader {
@@ -161,10 +187,19 @@ impl PemReader for InjectedValue<String> {
 }
 
 impl<P: AsRef<Path> + Debug + ?Sized> PemReader for P {
-    type Read<'a> = File where Self: 'a;
+    type Read<'a>
+        = File
+    where
+        Self: 'a;
     fn open(&self) -> io::Result<File> {
         File::open(self)
     }
+
+    fn pkcs11_uri(&self) -> Option<&str> {
+        self.as_ref()
+            .to_str()
+            .filter(|path| path.starts_with("pkcs11:"))
+    }
 }
 
 impl<P: AsRef<Utf8Path> + 'static> TrustStoreLoader for P {
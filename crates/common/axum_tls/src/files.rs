@@ -2,7 +2,9 @@ use crate::config::PemReader;
 use anyhow::anyhow;
 use anyhow::Context;
 use camino::Utf8Path;
+use certificate::key_uri::TlsIdentity;
 use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::sign::SigningKey;
 use rustls::Certificate;
 use rustls::PrivateKey;
 use rustls::RootCertStore;
@@ -68,6 +70,24 @@ pub fn ssl_config(
         .context("invalid key or certificate")
 }
 
+/// Load the SSL configuration for rustls, the server key being stored on a PKCS#11 token
+pub fn ssl_config_with_signing_key(
+    certificate_chain: Vec<Vec<u8>>,
+    key: Arc<dyn SigningKey>,
+    root_certs: Option<RootCertStore>,
+) -> ServerConfig {
+    let config = ServerConfig::builder().with_safe_defaults();
+
+    let config = if let Some(root_certs) = root_certs {
+        config.with_client_cert_verifier(Arc::new(AllowAnyAuthenticatedClient::new(root_certs)))
+    } else {
+        config.with_no_client_auth()
+    };
+
+    let server_cert = certificate_chain.into_iter().map(Certificate).collect();
+    config.with_cert_resolver(Arc::new(TlsIdentity::new(server_cert, key)))
+}
+
 /// Load the server certificate
 pub fn load_cert(path: &(impl PemReader + ?Sized)) -> anyhow::Result<Vec<Vec<u8>>> {
     let file = path
@@ -281,6 +301,19 @@ mod tests {
             assert_server_works_with(config, cert).await;
         }
 
+        #[tokio::test]
+        async fn signing_key_loaded_from_key_uri() {
+            let key_uri = certificate::key_uri::KeyUri::from(std::path::Path::new(
+                "./test_data/ec.pkcs8.key",
+            ));
+            let chain = rustls_pemfile::certs(&mut Cursor::new(test_data("ec.crt"))).unwrap();
+            let cert = reqwest::tls::Certificate::from_der(&chain[0]).unwrap();
+
+            let config = ssl_config_with_signing_key(chain, key_uri.signing_key().unwrap(), None);
+
+            assert_server_works_with(config, cert).await;
+        }
+
         fn parse_key_to_item(pem: &str) -> Item {
             rustls_pemfile::read_one(&mut Cursor::new(pem))
                 .unwrap()
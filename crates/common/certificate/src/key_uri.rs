@@ -0,0 +1,416 @@
+//! Location of the device private key,
+//! either a PEM file or a PKCS#11 URI (RFC 7512) for a key stored on a token.
+//!
+//! ```
+//! use certificate::key_uri::KeyUri;
+//!
+//! let file: KeyUri = "/etc/tedge/device-certs/tedge-private-key.pem".parse().unwrap();
+//! assert!(matches!(file, KeyUri::File(_)));
+//!
+//! let token: KeyUri = "pkcs11:token=tedge;object=device-key?module-path=/usr/lib/softhsm/libsofthsm2.so"
+//!     .parse()
+//!     .unwrap();
+//! assert!(matches!(token, KeyUri::Pkcs11(_)));
+//! ```
+use crate::parse_root_certificate::read_cert_chain;
+use crate::parse_root_certificate::read_pvt_key;
+use crate::pkcs11::Pkcs11SigningKey;
+use crate::CertificateError;
+use rustls::client::ResolvesClientCert;
+use rustls::server::ClientHello;
+use rustls::server::ResolvesServerCert;
+use rustls::sign::CertifiedKey;
+use rustls::sign::SigningKey;
+use rustls::Certificate;
+use rustls::SignatureScheme;
+use std::fmt;
+use std::fmt::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+const PKCS11_SCHEME: &str = "pkcs11:";
+const FILE_SCHEME: &str = "file://";
+
+/// Where to find a private key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyUri {
+    /// A PEM-encoded private key stored in a file
+    File(PathBuf),
+
+    /// A private key stored on a PKCS#11 token, which never leaves the token
+    Pkcs11(Pkcs11Uri),
+}
+
+/// The subset of RFC 7512 attributes used to select a private key on a PKCS#11 token
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pkcs11Uri {
+    /// Label of the token
+    pub token: Option<String>,
+
+    /// Serial number of the token
+    pub serial: Option<String>,
+
+    /// Label of the private key
+    pub object: Option<String>,
+
+    /// Identifier of the private key
+    pub id: Option<Vec<u8>>,
+
+    /// PIN used to log in the token
+    pub pin_value: Option<String>,
+
+    /// Path to the PKCS#11 module of the token
+    pub module_path: Option<PathBuf>,
+}
+
+impl KeyUri {
+    /// Load the private key as a signing key for rustls
+    ///
+    /// When the key is stored on a PKCS#11 token, the returned key only holds a session on the token,
+    /// the signatures being computed by the token.
+    pub fn signing_key(&self) -> Result<Arc<dyn SigningKey>, CertificateError> {
+        match self {
+            KeyUri::File(path) => {
+                let key = read_pvt_key(path)?;
+                rustls::sign::any_supported_type(&key)
+                    .map_err(|_| CertificateError::UnknownPrivateKeyFormat)
+            }
+            KeyUri::Pkcs11(uri) => Ok(Arc::new(Pkcs11SigningKey::open(uri)?)),
+        }
+    }
+
+    /// The path of the key file, if the key is not stored on a token
+    pub fn file_path(&self) -> Option<&Path> {
+        match self {
+            KeyUri::File(path) => Some(path),
+            KeyUri::Pkcs11(_) => None,
+        }
+    }
+}
+
+impl From<PathBuf> for KeyUri {
+    fn from(path: PathBuf) -> Self {
+        KeyUri::File(path)
+    }
+}
+
+impl From<&Path> for KeyUri {
+    fn from(path: &Path) -> Self {
+        KeyUri::File(path.to_path_buf())
+    }
+}
+
+impl FromStr for KeyUri {
+    type Err = CertificateError;
+
+    fn from_str(uri: &str) -> Result<Self, Self::Err> {
+        if let Some(attributes) = uri.strip_prefix(PKCS11_SCHEME) {
+            Ok(KeyUri::Pkcs11(Pkcs11Uri::parse_attributes(attributes)?))
+        } else if let Some(path) = uri.strip_prefix(FILE_SCHEME) {
+            Ok(KeyUri::File(PathBuf::from(path)))
+        } else if uri.is_empty() {
+            Err(invalid_uri("empty key URI"))
+        } else {
+            Ok(KeyUri::File(PathBuf::from(uri)))
+        }
+    }
+}
+
+/// Display the URI of the key, hiding the PIN if any
+impl fmt::Display for KeyUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyUri::File(path) => write!(f, "{}", path.display()),
+            KeyUri::Pkcs11(uri) => write!(f, "{uri}"),
+        }
+    }
+}
+
+impl Pkcs11Uri {
+    fn parse_attributes(attributes: &str) -> Result<Self, CertificateError> {
+        let (path, query) = match attributes.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (attributes, ""),
+        };
+
+        let mut uri = Pkcs11Uri::default();
+        for attribute in path.split(';').filter(|a| !a.is_empty()) {
+            let (name, value) = split_attribute(attribute)?;
+            match name {
+                "token" => uri.token = Some(percent_decode_str(value)?),
+                "serial" => uri.serial = Some(percent_decode_str(value)?),
+                "object" => uri.object = Some(percent_decode_str(value)?),
+                "id" => uri.id = Some(percent_decode(value)?),
+                "type" if value == "private" => {}
+                "type" => {
+                    return Err(invalid_uri(&format!(
+                        "expecting a private key, not an object of type {value}"
+                    )))
+                }
+                // Attributes that are not used to select the key are ignored
+                "manufacturer"
+                | "model"
+                | "library-manufacturer"
+                | "library-description"
+                | "library-version"
+                | "slot-description"
+                | "slot-manufacturer"
+                | "slot-id" => {}
+                _ => return Err(invalid_uri(&format!("unsupported path attribute: {name}"))),
+            }
+        }
+
+        for attribute in query.split('&').filter(|a| !a.is_empty()) {
+            let (name, value) = split_attribute(attribute)?;
+            match name {
+                "pin-value" => uri.pin_value = Some(percent_decode_str(value)?),
+                "module-path" => uri.module_path = Some(PathBuf::from(percent_decode_str(value)?)),
+                _ => return Err(invalid_uri(&format!("unsupported query attribute: {name}"))),
+            }
+        }
+
+        if uri.object.is_none() && uri.id.is_none() {
+            return Err(invalid_uri(
+                "the private key must be selected by an object label or an id",
+            ));
+        }
+
+        Ok(uri)
+    }
+}
+
+impl fmt::Display for Pkcs11Uri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut path = Vec::new();
+        if let Some(token) = &self.token {
+            path.push(format!("token={}", percent_encode(token.as_bytes())));
+        }
+        if let Some(serial) = &self.serial {
+            path.push(format!("serial={}", percent_encode(serial.as_bytes())));
+        }
+        if let Some(object) = &self.object {
+            path.push(format!("object={}", percent_encode(object.as_bytes())));
+        }
+        if let Some(id) = &self.id {
+            let id = id.iter().fold(String::new(), |mut id, byte| {
+                let _ = write!(id, "%{byte:02X}");
+                id
+            });
+            path.push(format!("id={id}"));
+        }
+
+        let mut query = Vec::new();
+        if self.pin_value.is_some() {
+            query.push("pin-value=***".to_string());
+        }
+        if let Some(module_path) = &self.module_path {
+            query.push(format!(
+                "module-path={}",
+                percent_encode(module_path.to_string_lossy().as_bytes())
+            ));
+        }
+
+        write!(f, "{PKCS11_SCHEME}{}", path.join(";"))?;
+        if !query.is_empty() {
+            write!(f, "?{}", query.join("&"))?;
+        }
+        Ok(())
+    }
+}
+
+/// A TLS identity: a certificate chain along a private key possibly stored on a PKCS#11 token
+///
+/// This identity can be used on the client side as well as on the server side of a TLS connection.
+pub struct TlsIdentity {
+    certified_key: Arc<CertifiedKey>,
+}
+
+impl TlsIdentity {
+    /// Load the certificate chain stored in a PEM file along the private key pointed by the URI
+    pub fn load(cert_path: impl AsRef<Path>, key_uri: &KeyUri) -> Result<Self, CertificateError> {
+        let cert_chain = read_cert_chain(cert_path)?;
+        let key = key_uri.signing_key()?;
+        Ok(Self::new(cert_chain, key))
+    }
+
+    pub fn new(cert_chain: Vec<Certificate>, key: Arc<dyn SigningKey>) -> Self {
+        TlsIdentity {
+            certified_key: Arc::new(CertifiedKey::new(cert_chain, key)),
+        }
+    }
+}
+
+impl ResolvesClientCert for TlsIdentity {
+    fn resolve(
+        &self,
+        _acceptable_issuers: &[&[u8]],
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        Some(self.certified_key.clone())
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+impl ResolvesServerCert for TlsIdentity {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.certified_key.clone())
+    }
+}
+
+fn split_attribute(attribute: &str) -> Result<(&str, &str), CertificateError> {
+    attribute
+        .split_once('=')
+        .ok_or_else(|| invalid_uri(&format!("missing value for attribute: {attribute}")))
+}
+
+fn percent_decode_str(value: &str) -> Result<String, CertificateError> {
+    String::from_utf8(percent_decode(value)?)
+        .map_err(|_| invalid_uri(&format!("not an UTF-8 value: {value}")))
+}
+
+fn percent_decode(value: &str) -> Result<Vec<u8>, CertificateError> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut chars = value.bytes();
+    while let Some(byte) = chars.next() {
+        if byte == b'%' {
+            let hex = [
+                chars.next().unwrap_or_default(),
+                chars.next().unwrap_or_default(),
+            ];
+            let decoded = std::str::from_utf8(&hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| invalid_uri(&format!("invalid percent-encoding: {value}")))?;
+            bytes.push(decoded);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    Ok(bytes)
+}
+
+fn percent_encode(value: &[u8]) -> String {
+    value
+        .iter()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (*byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+fn invalid_uri(reason: &str) -> CertificateError {
+    CertificateError::InvalidKeyUri(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyCertPair;
+    use crate::KeyKind;
+    use crate::NewCertificateConfig;
+    use rustls::SignatureAlgorithm;
+
+    #[test]
+    fn parse_a_file_path() {
+        assert_eq!(
+            "/etc/tedge/device-certs/tedge-private-key.pem"
+                .parse::<KeyUri>()
+                .unwrap(),
+            KeyUri::File("/etc/tedge/device-certs/tedge-private-key.pem".into())
+        );
+        assert_eq!(
+            "file:///etc/tedge/device-certs/tedge-private-key.pem"
+                .parse::<KeyUri>()
+                .unwrap(),
+            KeyUri::File("/etc/tedge/device-certs/tedge-private-key.pem".into())
+        );
+    }
+
+    #[test]
+    fn parse_a_pkcs11_uri() {
+        let uri: KeyUri = "pkcs11:token=my%20token;object=device-key;id=%01%A2;type=private?pin-value=1234&module-path=/usr/lib/softhsm/libsofthsm2.so"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            uri,
+            KeyUri::Pkcs11(Pkcs11Uri {
+                token: Some("my token".to_string()),
+                serial: None,
+                object: Some("device-key".to_string()),
+                id: Some(vec![0x01, 0xA2]),
+                pin_value: Some("1234".to_string()),
+                module_path: Some("/usr/lib/softhsm/libsofthsm2.so".into()),
+            })
+        );
+
+        // The PIN is not displayed
+        assert_eq!(
+            uri.to_string(),
+            "pkcs11:token=my%20token;object=device-key;id=%01%A2?pin-value=***&module-path=/usr/lib/softhsm/libsofthsm2.so"
+        );
+    }
+
+    #[test]
+    fn reject_invalid_pkcs11_uris() {
+        for uri in [
+            "pkcs11:token=tedge",
+            "pkcs11:object=key;type=cert",
+            "pkcs11:object=key;foo=bar",
+            "pkcs11:object=key?pin-source=file:/etc/pin",
+            "pkcs11:object=key%2",
+            "pkcs11:object",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    uri.parse::<KeyUri>(),
+                    Err(CertificateError::InvalidKeyUri(_))
+                ),
+                "{uri} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn load_a_signing_key_from_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("cert.pem");
+        let key_path = dir.path().join("key.pem");
+
+        let cert = KeyCertPair::new_selfsigned_certificate(
+            &NewCertificateConfig::default(),
+            "device-id",
+            &KeyKind::New,
+        )
+        .unwrap();
+        std::fs::write(&cert_path, cert.certificate_pem_string().unwrap()).unwrap();
+        std::fs::write(&key_path, cert.private_key_pem_string().unwrap().as_str()).unwrap();
+
+        let key_uri = KeyUri::from(key_path);
+        let key = key_uri.signing_key().unwrap();
+        assert_eq!(key.algorithm(), SignatureAlgorithm::ECDSA);
+
+        let identity = TlsIdentity::load(&cert_path, &key_uri).unwrap();
+        let certified_key = ResolvesClientCert::resolve(&identity, &[], &[]).unwrap();
+        assert_eq!(certified_key.cert.len(), 1);
+        assert!(certified_key
+            .key
+            .choose_scheme(&[SignatureScheme::ECDSA_NISTP256_SHA256])
+            .is_some());
+    }
+
+    #[test]
+    fn loading_a_missing_key_file_fails() {
+        let key_uri = KeyUri::from(PathBuf::from("/non/existent/key.pem"));
+        assert!(key_uri.signing_key().is_err());
+    }
+}
@@ -11,7 +11,10 @@ use time::Duration;
 use time::OffsetDateTime;
 use zeroize::Zeroizing;
 pub mod device_id;
+pub mod key_uri;
 pub mod parse_root_certificate;
+mod pkcs11;
+pub mod pkcs7;
 pub struct PemCertificate {
     pem: x509_parser::pem::Pem,
 }
@@ -69,6 +72,11 @@ impl PemCertificate {
             .map_err(CertificateError::X509Error)
     }
 
+    pub fn expiration_time(&self) -> Result<OffsetDateTime, CertificateError> {
+        let x509 = PemCertificate::extract_certificate(&self.pem)?;
+        Ok(x509.tbs_certificate.validity.not_after.to_datetime())
+    }
+
     pub fn thumbprint(&self) -> Result<String, CertificateError> {
         let bytes = Sha1::digest(&self.pem.contents).as_slice().to_vec();
         let strs: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
@@ -95,6 +103,127 @@ impl PemCertificate {
     }
 }
 
+/// A chain of PEM-encoded certificates, starting with the device certificate
+/// and followed by the certificates of the intermediate and root CAs, if any.
+pub struct PemCertificateChain {
+    pems: Vec<x509_parser::pem::Pem>,
+}
+
+impl PemCertificateChain {
+    pub fn from_pem_file(path: impl AsRef<Path>) -> Result<PemCertificateChain, CertificateError> {
+        let content = std::fs::read_to_string(path)?;
+        PemCertificateChain::from_pem_string(&content)
+    }
+
+    pub fn from_pem_string(content: &str) -> Result<PemCertificateChain, CertificateError> {
+        let pems = x509_parser::pem::Pem::iter_from_buffer(content.as_bytes())
+            .collect::<Result<Vec<_>, _>>()?;
+        if pems.is_empty() {
+            return Err(CertificateError::InvalidCertificateChain(
+                "no certificate found".into(),
+            ));
+        }
+        Ok(PemCertificateChain { pems })
+    }
+
+    pub fn from_der_certificates(
+        certificates: Vec<Vec<u8>>,
+    ) -> Result<PemCertificateChain, CertificateError> {
+        if certificates.is_empty() {
+            return Err(CertificateError::InvalidCertificateChain(
+                "no certificate found".into(),
+            ));
+        }
+        let pems = certificates
+            .into_iter()
+            .map(|contents| x509_parser::pem::Pem {
+                label: "CERTIFICATE".to_string(),
+                contents,
+            })
+            .collect();
+        Ok(PemCertificateChain { pems })
+    }
+
+    /// The DER-encoded certificates of the chain
+    pub fn der_certificates(&self) -> impl Iterator<Item = &[u8]> {
+        self.pems.iter().map(|pem| pem.contents.as_slice())
+    }
+
+    pub fn to_pem_string(&self) -> String {
+        let mut content = String::new();
+        for pem in self.pems.iter() {
+            content.push_str(&format!("-----BEGIN {}-----\n", pem.label));
+            let encoded = base64::encode(&pem.contents);
+            for line in encoded.as_bytes().chunks(64) {
+                // base64 is ASCII, hence a chunk is a valid UTF-8 string
+                content.push_str(std::str::from_utf8(line).unwrap_or_default());
+                content.push('\n');
+            }
+            content.push_str(&format!("-----END {}-----\n", pem.label));
+        }
+        content
+    }
+
+    /// The device certificate, i.e. the first certificate of the chain
+    pub fn device_certificate(&self) -> PemCertificate {
+        PemCertificate {
+            pem: self.pems[0].clone(),
+        }
+    }
+
+    /// Check that the chain can be used along the given private key
+    ///
+    /// - the device certificate must be issued for the public key of that private key,
+    /// - each certificate must be signed by the next one in the chain,
+    ///   the last one being possibly signed by a root CA that is not part of the chain,
+    /// - all the certificates must be currently valid.
+    pub fn validate_against_key(&self, private_key_pem: &str) -> Result<(), CertificateError> {
+        let key_pair = KeyPair::from_pem(private_key_pem)?;
+        let certificates = self
+            .pems
+            .iter()
+            .map(PemCertificate::extract_certificate)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let device_public_key = &certificates[0].public_key().subject_public_key.data;
+        if device_public_key.as_ref() != key_pair.public_key_raw() {
+            return Err(CertificateError::InvalidCertificateChain(
+                "the device certificate doesn't match the private key".into(),
+            ));
+        }
+
+        for certificate in certificates.iter() {
+            if !certificate.validity().is_valid() {
+                return Err(CertificateError::InvalidCertificateChain(format!(
+                    "the certificate of {} is not currently valid",
+                    certificate.subject()
+                )));
+            }
+        }
+
+        for pair in certificates.windows(2) {
+            let (certificate, issuer) = (&pair[0], &pair[1]);
+            if certificate.issuer() != issuer.subject() {
+                return Err(CertificateError::InvalidCertificateChain(format!(
+                    "the certificate of {} is not issued by {}",
+                    certificate.subject(),
+                    issuer.subject()
+                )));
+            }
+            certificate
+                .verify_signature(Some(issuer.public_key()))
+                .map_err(|err| {
+                    CertificateError::InvalidCertificateChain(format!(
+                        "the signature of the certificate of {} cannot be verified: {err}",
+                        certificate.subject()
+                    ))
+                })?;
+        }
+
+        Ok(())
+    }
+}
+
 pub enum KeyKind {
     /// Create a new key
     New,
@@ -123,6 +252,40 @@ impl KeyCertPair {
         not_before: OffsetDateTime,
         cert_kind: &KeyKind,
     ) -> Result<KeyCertPair, CertificateError> {
+        let mut params = KeyCertPair::certificate_params(config, id, cert_kind)?;
+
+        let not_after = not_before + Duration::days(config.validity_period_days.into());
+        params.not_before = not_before;
+        params.not_after = not_after;
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained); // IsCa::SelfSignedOnly is rejected by C8Y
+
+        Ok(KeyCertPair {
+            certificate: Zeroizing::new(Certificate::from_params(params)?),
+        })
+    }
+
+    /// Prepare a certificate signing request for the device
+    ///
+    /// The subject and the subject alternative name of the request are derived from the device id.
+    /// The request has then to be signed by a CA, using [KeyCertPair::certificate_signing_request_pem_string].
+    pub fn new_certificate_signing_request(
+        config: &NewCertificateConfig,
+        id: &str,
+        key_kind: &KeyKind,
+    ) -> Result<KeyCertPair, CertificateError> {
+        let mut params = KeyCertPair::certificate_params(config, id, key_kind)?;
+        params.subject_alt_names = vec![rcgen::SanType::DnsName(id.to_string())];
+
+        Ok(KeyCertPair {
+            certificate: Zeroizing::new(Certificate::from_params(params)?),
+        })
+    }
+
+    fn certificate_params(
+        config: &NewCertificateConfig,
+        id: &str,
+        key_kind: &KeyKind,
+    ) -> Result<CertificateParams, CertificateError> {
         KeyCertPair::check_identifier(id, config.max_cn_size)?;
         let mut distinguished_name = rcgen::DistinguishedName::new();
         distinguished_name.push(rcgen::DnType::CommonName, id);
@@ -132,27 +295,28 @@ impl KeyCertPair {
             &config.organizational_unit_name,
         );
 
-        let not_after = not_before + Duration::days(config.validity_period_days.into());
-
         let mut params = CertificateParams::default();
         params.distinguished_name = distinguished_name;
-        params.not_before = not_before;
-        params.not_after = not_after;
         params.alg = &rcgen::PKCS_ECDSA_P256_SHA256; // ECDSA signing using the P-256 curves and SHA-256 hashing as per RFC 5758
-        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained); // IsCa::SelfSignedOnly is rejected by C8Y
-        if let KeyKind::Reuse { keypair_pem } = cert_kind {
+        if let KeyKind::Reuse { keypair_pem } = key_kind {
             params.key_pair = Some(KeyPair::from_pem(keypair_pem)?);
         }
 
-        Ok(KeyCertPair {
-            certificate: Zeroizing::new(Certificate::from_params(params)?),
-        })
+        Ok(params)
     }
 
     pub fn certificate_pem_string(&self) -> Result<String, CertificateError> {
         Ok(self.certificate.serialize_pem()?)
     }
 
+    pub fn certificate_signing_request_pem_string(&self) -> Result<String, CertificateError> {
+        Ok(self.certificate.serialize_request_pem()?)
+    }
+
+    pub fn certificate_signing_request_der(&self) -> Result<Vec<u8>, CertificateError> {
+        Ok(self.certificate.serialize_request_der()?)
+    }
+
     pub fn private_key_pem_string(&self) -> Result<Zeroizing<String>, CertificateError> {
         Ok(Zeroizing::new(self.certificate.serialize_private_key_pem()))
     }
@@ -219,6 +383,18 @@ pub enum CertificateError {
 
     #[error(transparent)]
     CertParse(#[from] rustls::Error),
+
+    #[error("Invalid certificate chain: {0}")]
+    InvalidCertificateChain(String),
+
+    #[error("Invalid PKCS#7 message: {0}")]
+    InvalidPkcs7(String),
+
+    #[error("Invalid private key URI: {0}")]
+    InvalidKeyUri(String),
+
+    #[error("PKCS#11 error: {0}")]
+    Pkcs11Error(String),
 }
 
 pub struct NewCertificateConfig {
@@ -244,6 +420,7 @@ mod tests {
     use super::*;
     use std::error::Error;
     use time::macros::datetime;
+    use x509_parser::prelude::FromDer;
 
     impl KeyCertPair {
         fn new_selfsigned_certificate_with_new_key(
@@ -404,6 +581,169 @@ mod tests {
         assert_eq!(thumbprint, expected_thumbprint);
     }
 
+    #[test]
+    fn csr_subject_and_san_are_derived_from_the_device_id() {
+        let config = NewCertificateConfig::default();
+        let id = "my-device-id";
+
+        let csr = KeyCertPair::new_certificate_signing_request(&config, id, &KeyKind::New)
+            .expect("Fail to create a CSR");
+        let csr_pem = csr
+            .certificate_signing_request_pem_string()
+            .expect("Fail to serialize the CSR");
+
+        let pem = pem::parse(csr_pem).expect("Fail to decode the CSR PEM");
+        assert_eq!(pem.tag, "CERTIFICATE REQUEST");
+        let (_, request) =
+            x509_parser::certification_request::X509CertificationRequest::from_der(&pem.contents)
+                .expect("Fail to parse the CSR");
+        request
+            .verify_signature()
+            .expect("The CSR must be signed by the device key");
+        assert_eq!(
+            request.certification_request_info.subject.to_string(),
+            "CN=my-device-id, O=Thin Edge, OU=Test Device"
+        );
+        let san = request
+            .requested_extensions()
+            .and_then(|mut extensions| {
+                extensions.find_map(|extension| match extension {
+                    x509_parser::extensions::ParsedExtension::SubjectAlternativeName(san) => {
+                        Some(format!("{:?}", san.general_names))
+                    }
+                    _ => None,
+                })
+            })
+            .expect("The CSR must have a subject alternative name");
+        assert!(san.contains("my-device-id"));
+    }
+
+    #[test]
+    fn csr_reuses_the_existing_key() {
+        let config = NewCertificateConfig::default();
+        let id = "my-device-id";
+        let self_signed =
+            KeyCertPair::new_selfsigned_certificate_with_new_key(&config, id).unwrap();
+        let keypair_pem = self_signed.private_key_pem_string().unwrap().to_string();
+
+        let csr = KeyCertPair::new_certificate_signing_request(
+            &config,
+            id,
+            &KeyKind::Reuse {
+                keypair_pem: keypair_pem.clone(),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(*csr.private_key_pem_string().unwrap(), keypair_pem);
+    }
+
+    #[test]
+    fn ca_signed_chain_is_validated_against_the_device_key() {
+        let (ca, device_cert_pem, device_key_pem) = new_ca_signed_device_certificate("my-device");
+        let chain_pem = format!("{device_cert_pem}{}", ca.serialize_pem().unwrap());
+
+        let chain = PemCertificateChain::from_pem_string(&chain_pem).unwrap();
+        chain.validate_against_key(&device_key_pem).unwrap();
+        assert_eq!(
+            chain.device_certificate().subject_common_name().unwrap(),
+            "my-device"
+        );
+
+        // The device certificate alone is also accepted
+        let chain = PemCertificateChain::from_pem_string(&device_cert_pem).unwrap();
+        chain.validate_against_key(&device_key_pem).unwrap();
+    }
+
+    #[test]
+    fn chain_is_rejected_when_the_key_does_not_match() {
+        let (ca, device_cert_pem, _) = new_ca_signed_device_certificate("my-device");
+        let (_, _, other_key_pem) = new_ca_signed_device_certificate("other-device");
+        let chain_pem = format!("{device_cert_pem}{}", ca.serialize_pem().unwrap());
+
+        let chain = PemCertificateChain::from_pem_string(&chain_pem).unwrap();
+        let err = chain.validate_against_key(&other_key_pem).unwrap_err();
+        assert!(
+            err.to_string().contains("doesn't match the private key"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn chain_is_rejected_when_not_signed_by_the_next_certificate() {
+        let (_, device_cert_pem, device_key_pem) = new_ca_signed_device_certificate("my-device");
+        let other_ca = new_ca("Test CA");
+        let chain_pem = format!("{device_cert_pem}{}", other_ca.serialize_pem().unwrap());
+
+        let chain = PemCertificateChain::from_pem_string(&chain_pem).unwrap();
+        let err = chain.validate_against_key(&device_key_pem).unwrap_err();
+        assert!(err.to_string().contains("cannot be verified"), "{err}");
+    }
+
+    #[test]
+    fn chain_is_serialized_as_pem() {
+        let (ca, device_cert_pem, _) = new_ca_signed_device_certificate("my-device");
+        let chain_pem = format!("{device_cert_pem}{}", ca.serialize_pem().unwrap());
+
+        let chain = PemCertificateChain::from_pem_string(&chain_pem).unwrap();
+        let reparsed = PemCertificateChain::from_pem_string(&chain.to_pem_string()).unwrap();
+        assert_eq!(
+            reparsed.der_certificates().collect::<Vec<_>>(),
+            chain.der_certificates().collect::<Vec<_>>()
+        );
+        assert_eq!(reparsed.der_certificates().count(), 2);
+    }
+
+    #[test]
+    fn expiration_time_is_the_not_after_date() {
+        let config = NewCertificateConfig {
+            validity_period_days: 10,
+            ..Default::default()
+        };
+        let birthdate = datetime!(2021-03-31 16:39:57 +01:00);
+        let keypair = KeyCertPair::new_selfsigned_certificate_at(
+            &config,
+            "some-id",
+            birthdate,
+            &KeyKind::New,
+        )
+        .unwrap();
+
+        let pem = pem_of_keypair(&keypair);
+        assert_eq!(
+            pem.expiration_time().unwrap(),
+            datetime!(2021-04-10 15:39:57 UTC)
+        );
+    }
+
+    #[test]
+    fn empty_chain_is_rejected() {
+        assert!(matches!(
+            PemCertificateChain::from_pem_string(""),
+            Err(CertificateError::InvalidCertificateChain(_))
+        ));
+    }
+
+    fn new_ca(name: &str) -> Certificate {
+        let mut params = CertificateParams::default();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, name);
+        params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        Certificate::from_params(params).unwrap()
+    }
+
+    /// Return a CA along a device certificate and key, the certificate being signed by the CA
+    fn new_ca_signed_device_certificate(id: &str) -> (Certificate, String, String) {
+        let ca = new_ca("Test CA");
+        let config = NewCertificateConfig::default();
+        let csr = KeyCertPair::new_certificate_signing_request(&config, id, &KeyKind::New).unwrap();
+        let device_cert_pem = csr.certificate.serialize_pem_with_signer(&ca).unwrap();
+        let device_key_pem = csr.private_key_pem_string().unwrap().to_string();
+        (ca, device_cert_pem, device_key_pem)
+    }
+
     #[test]
     fn check_translate_rustls_error() -> Result<(), anyhow::Error> {
         let expired_error = rustls::Error::InvalidCertificate(rustls::CertificateError::Expired);
@@ -11,22 +11,48 @@ use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
 
+use crate::key_uri::KeyUri;
+use crate::key_uri::TlsIdentity;
 use crate::CertificateError;
 
+/// Create a TLS client config authenticated by the given certificate and private key
+///
+/// The private key can be stored in a file or on a PKCS#11 token.
 pub fn create_tls_config(
     root_certificates: PathBuf,
-    client_private_key: PathBuf,
+    client_private_key: &KeyUri,
     client_certificate: PathBuf,
 ) -> Result<ClientConfig, CertificateError> {
     let root_cert_store = new_root_store(&root_certificates)?;
-    let pvt_key = read_pvt_key(client_private_key)?;
-    let cert_chain = read_cert_chain(client_certificate)?;
+    let identity = TlsIdentity::load(client_certificate, client_private_key)?;
 
     Ok(ClientConfig::builder()
         .with_safe_defaults()
         .with_root_certificates(root_cert_store)
-        .with_client_auth_cert(cert_chain, pvt_key)?)
+        .with_client_cert_resolver(Arc::new(identity)))
+}
+
+/// Create a TLS client config authenticated by the given identity, trusting the system root certificates
+///
+/// This is the TLS config used by the HTTP clients, where the private key can be stored on a PKCS#11 token.
+pub fn create_tls_config_with_native_roots(
+    identity: TlsIdentity,
+) -> Result<ClientConfig, CertificateError> {
+    let mut root_cert_store = RootCertStore::empty();
+    let native_certs = rustls_native_certs::load_native_certs()?;
+    root_cert_store.add_parsable_certificates(
+        &native_certs
+            .into_iter()
+            .map(|cert| cert.0)
+            .collect::<Vec<_>>(),
+    );
+
+    Ok(ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_cert_store)
+        .with_client_cert_resolver(Arc::new(identity)))
 }
 
 pub fn add_certs_from_file(
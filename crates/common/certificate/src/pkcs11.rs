@@ -0,0 +1,359 @@
+//! Minimal PKCS#11 client, used to sign TLS handshakes with a private key stored on a token.
+//!
+//! The PKCS#11 module of the token (e.g. `libsofthsm2.so`) is loaded at runtime using [cryptoki]
+//! and only the few functions required to find a private key and sign with it are used.
+//! The private key itself never leaves the token.
+use crate::key_uri::Pkcs11Uri;
+use crate::pkcs7::write_tlv;
+use crate::CertificateError;
+use cryptoki::context::CInitializeArgs;
+use cryptoki::context::Pkcs11;
+use cryptoki::error::Error;
+use cryptoki::error::RvError;
+use cryptoki::mechanism::rsa::PkcsMgfType;
+use cryptoki::mechanism::rsa::PkcsPssParams;
+use cryptoki::mechanism::Mechanism;
+use cryptoki::mechanism::MechanismType;
+use cryptoki::object::Attribute;
+use cryptoki::object::AttributeType;
+use cryptoki::object::KeyType as CkKeyType;
+use cryptoki::object::ObjectClass;
+use cryptoki::object::ObjectHandle;
+use cryptoki::session::Session;
+use cryptoki::session::UserType;
+use cryptoki::slot::Slot;
+use cryptoki::types::AuthPin;
+use rustls::sign::Signer;
+use rustls::sign::SigningKey;
+use rustls::SignatureAlgorithm;
+use rustls::SignatureScheme;
+use sha2::Digest;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+/// DER-encoded OID of the NIST P-256 curve, as returned for `CKA_EC_PARAMS`
+const OID_P256: &[u8] = &[0x06, 0x08, 0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
+
+/// DER-encoded OID of the NIST P-384 curve, as returned for `CKA_EC_PARAMS`
+const OID_P384: &[u8] = &[0x06, 0x05, 0x2B, 0x81, 0x04, 0x00, 0x22];
+
+/// The kind of private key found on the token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyType {
+    Rsa,
+    EcP256,
+    EcP384,
+}
+
+/// A private key stored on a PKCS#11 token
+pub struct Pkcs11SigningKey {
+    session: Arc<TokenSession>,
+    key_type: KeyType,
+}
+
+impl Pkcs11SigningKey {
+    /// Open a session on the token and find the private key designated by the URI
+    pub fn open(uri: &Pkcs11Uri) -> Result<Self, CertificateError> {
+        let module_path = uri
+            .module_path
+            .as_deref()
+            .ok_or_else(|| pkcs11_error("the URI of the key has no module-path"))?;
+        let module = load_module(module_path)?;
+        let slot = find_slot(&module, uri)?;
+        let (session, key_type) = TokenSession::open(&module, slot, uri)?;
+        Ok(Pkcs11SigningKey {
+            session: Arc::new(session),
+            key_type,
+        })
+    }
+}
+
+impl SigningKey for Pkcs11SigningKey {
+    fn choose_scheme(&self, offered: &[SignatureScheme]) -> Option<Box<dyn Signer>> {
+        let supported: &[SignatureScheme] = match self.key_type {
+            KeyType::EcP256 => &[SignatureScheme::ECDSA_NISTP256_SHA256],
+            KeyType::EcP384 => &[SignatureScheme::ECDSA_NISTP384_SHA384],
+            KeyType::Rsa => &[
+                SignatureScheme::RSA_PSS_SHA384,
+                SignatureScheme::RSA_PSS_SHA256,
+                SignatureScheme::RSA_PKCS1_SHA384,
+                SignatureScheme::RSA_PKCS1_SHA256,
+            ],
+        };
+        supported
+            .iter()
+            .find(|scheme| offered.contains(scheme))
+            .map(|scheme| -> Box<dyn Signer> {
+                Box::new(Pkcs11Signer {
+                    session: self.session.clone(),
+                    scheme: *scheme,
+                })
+            })
+    }
+
+    fn algorithm(&self) -> SignatureAlgorithm {
+        match self.key_type {
+            KeyType::Rsa => SignatureAlgorithm::RSA,
+            KeyType::EcP256 | KeyType::EcP384 => SignatureAlgorithm::ECDSA,
+        }
+    }
+}
+
+struct Pkcs11Signer {
+    session: Arc<TokenSession>,
+    scheme: SignatureScheme,
+}
+
+impl Signer for Pkcs11Signer {
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, rustls::Error> {
+        let sign = |mechanism: Mechanism, data: &[u8]| {
+            self.session
+                .sign(&mechanism, data)
+                .map_err(|err| rustls::Error::General(err.to_string()))
+        };
+        match self.scheme {
+            SignatureScheme::ECDSA_NISTP256_SHA256 => {
+                let signature = sign(Mechanism::Ecdsa, &sha2::Sha256::digest(message))?;
+                Ok(ecdsa_signature_to_der(&signature))
+            }
+            SignatureScheme::ECDSA_NISTP384_SHA384 => {
+                let signature = sign(Mechanism::Ecdsa, &sha2::Sha384::digest(message))?;
+                Ok(ecdsa_signature_to_der(&signature))
+            }
+            SignatureScheme::RSA_PSS_SHA256 => sign(
+                Mechanism::Sha256RsaPkcsPss(PkcsPssParams {
+                    hash_alg: MechanismType::SHA256,
+                    mgf: PkcsMgfType::MGF1_SHA256,
+                    s_len: 32.into(),
+                }),
+                message,
+            ),
+            SignatureScheme::RSA_PSS_SHA384 => sign(
+                Mechanism::Sha384RsaPkcsPss(PkcsPssParams {
+                    hash_alg: MechanismType::SHA384,
+                    mgf: PkcsMgfType::MGF1_SHA384,
+                    s_len: 48.into(),
+                }),
+                message,
+            ),
+            SignatureScheme::RSA_PKCS1_SHA256 => sign(Mechanism::Sha256RsaPkcs, message),
+            SignatureScheme::RSA_PKCS1_SHA384 => sign(Mechanism::Sha384RsaPkcs, message),
+            scheme => Err(rustls::Error::General(format!(
+                "unsupported signature scheme: {scheme:?}"
+            ))),
+        }
+    }
+
+    fn scheme(&self) -> SignatureScheme {
+        self.scheme
+    }
+}
+
+/// Load and initialize a PKCS#11 module
+///
+/// A module is loaded once per process and never finalized, as other sessions might still be using it.
+fn load_module(path: &Path) -> Result<Pkcs11, CertificateError> {
+    static MODULES: OnceLock<Mutex<HashMap<PathBuf, Pkcs11>>> = OnceLock::new();
+    let mut modules = MODULES.get_or_init(Default::default).lock().unwrap();
+    if let Some(module) = modules.get(path) {
+        return Ok(module.clone());
+    }
+
+    let module = Pkcs11::new(path).map_err(|err| {
+        pkcs11_error(&format!(
+            "cannot load the PKCS#11 module {}: {err}",
+            path.display()
+        ))
+    })?;
+    match module.initialize(CInitializeArgs::OsThreads) {
+        Ok(()) | Err(Error::Pkcs11(RvError::CryptokiAlreadyInitialized)) => {}
+        Err(err) => return Err(check("C_Initialize", err)),
+    }
+    modules.insert(path.to_path_buf(), module.clone());
+    Ok(module)
+}
+
+/// Find the slot of the token designated by the URI
+fn find_slot(module: &Pkcs11, uri: &Pkcs11Uri) -> Result<Slot, CertificateError> {
+    let slots = module
+        .get_slots_with_token()
+        .map_err(|err| check("C_GetSlotList", err))?;
+    for slot in slots {
+        let info = module
+            .get_token_info(slot)
+            .map_err(|err| check("C_GetTokenInfo", err))?;
+        let matches = |expected: &Option<String>, actual: &str| {
+            expected
+                .as_ref()
+                .map_or(true, |expected| actual == expected.trim_end_matches(' '))
+        };
+        if matches(&uri.token, info.label()) && matches(&uri.serial, info.serial_number()) {
+            return Ok(slot);
+        }
+    }
+
+    Err(pkcs11_error("no token matching the key URI"))
+}
+
+/// A session opened on a token, along the private key used to sign
+///
+/// A PKCS#11 session cannot be used concurrently, hence the lock around any operation.
+struct TokenSession {
+    session: Mutex<Session>,
+    key: ObjectHandle,
+}
+
+impl TokenSession {
+    /// Open a session on the token and find the private key designated by the URI
+    fn open(
+        module: &Pkcs11,
+        slot: Slot,
+        uri: &Pkcs11Uri,
+    ) -> Result<(Self, KeyType), CertificateError> {
+        let session = module
+            .open_ro_session(slot)
+            .map_err(|err| check("C_OpenSession", err))?;
+
+        if let Some(pin) = &uri.pin_value {
+            match session.login(UserType::User, Some(&AuthPin::new(pin.clone()))) {
+                Ok(()) | Err(Error::Pkcs11(RvError::UserAlreadyLoggedIn)) => {}
+                Err(err) => return Err(check("C_Login", err)),
+            }
+        }
+
+        let (key, key_type) = find_private_key(&session, uri)?;
+        let session = TokenSession {
+            session: Mutex::new(session),
+            key,
+        };
+        Ok((session, key_type))
+    }
+
+    fn sign(&self, mechanism: &Mechanism, data: &[u8]) -> Result<Vec<u8>, CertificateError> {
+        let session = self.session.lock().unwrap();
+        session
+            .sign(mechanism, self.key, data)
+            .map_err(|err| check("C_Sign", err))
+    }
+}
+
+/// Find the private key designated by the URI, returning its handle and type
+fn find_private_key(
+    session: &Session,
+    uri: &Pkcs11Uri,
+) -> Result<(ObjectHandle, KeyType), CertificateError> {
+    let mut template = vec![Attribute::Class(ObjectClass::PRIVATE_KEY)];
+    if let Some(label) = &uri.object {
+        template.push(Attribute::Label(label.clone().into_bytes()));
+    }
+    if let Some(id) = &uri.id {
+        template.push(Attribute::Id(id.clone()));
+    }
+    let key = session
+        .find_objects(&template)
+        .map_err(|err| check("C_FindObjects", err))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| pkcs11_error("no private key matching the key URI"))?;
+
+    let attributes = session
+        .get_attributes(key, &[AttributeType::KeyType, AttributeType::EcParams])
+        .map_err(|err| check("C_GetAttributeValue", err))?;
+    let key_type = attributes.iter().find_map(|attribute| match attribute {
+        Attribute::KeyType(key_type) => Some(*key_type),
+        _ => None,
+    });
+    let ec_params = attributes.iter().find_map(|attribute| match attribute {
+        Attribute::EcParams(params) => Some(params.as_slice()),
+        _ => None,
+    });
+
+    let key_type = match key_type {
+        Some(CkKeyType::RSA) => KeyType::Rsa,
+        Some(CkKeyType::EC) => match ec_params {
+            Some(OID_P256) => KeyType::EcP256,
+            Some(OID_P384) => KeyType::EcP384,
+            _ => return Err(pkcs11_error("unsupported elliptic curve")),
+        },
+        Some(key_type) => return Err(pkcs11_error(&format!("unsupported key type: {key_type}"))),
+        None => return Err(pkcs11_error("the type of the private key is unknown")),
+    };
+    Ok((key, key_type))
+}
+
+/// Convert a raw ECDSA signature `r || s`, as returned by PKCS#11,
+/// into the DER-encoded `Ecdsa-Sig-Value` expected by TLS
+fn ecdsa_signature_to_der(signature: &[u8]) -> Vec<u8> {
+    let (r, s) = signature.split_at(signature.len() / 2);
+    let integer = |value: &[u8]| {
+        let value = match value.iter().position(|byte| *byte != 0) {
+            Some(start) => &value[start..],
+            None => &[0],
+        };
+        if value[0] & 0x80 != 0 {
+            write_tlv(0x02, &[&[0], value].concat())
+        } else {
+            write_tlv(0x02, value)
+        }
+    };
+    write_tlv(0x30, &[integer(r), integer(s)].concat())
+}
+
+fn check(function: &str, err: Error) -> CertificateError {
+    pkcs11_error(&format!("{function} failed: {err}"))
+}
+
+fn pkcs11_error(reason: &str) -> CertificateError {
+    CertificateError::Pkcs11Error(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_uri::KeyUri;
+
+    #[test]
+    fn ecdsa_signatures_are_der_encoded() {
+        let mut r = vec![0u8; 32];
+        r[0] = 0x80;
+        let mut s = vec![0u8; 32];
+        s[30] = 0x01;
+        s[31] = 0x02;
+
+        let der = ecdsa_signature_to_der(&[r.clone(), s].concat());
+
+        // r gets a leading zero not to be negative, s leading zeros are removed
+        let mut expected = vec![0x30, 0x27, 0x02, 0x21, 0x00];
+        expected.extend(r);
+        expected.extend([0x02, 0x02, 0x01, 0x02]);
+        assert_eq!(der, expected);
+    }
+
+    #[test]
+    fn a_module_path_is_required() {
+        let uri: KeyUri = "pkcs11:token=tedge;object=device-key".parse().unwrap();
+        let KeyUri::Pkcs11(uri) = uri else {
+            panic!("expecting a PKCS#11 URI")
+        };
+        assert!(matches!(
+            Pkcs11SigningKey::open(&uri),
+            Err(CertificateError::Pkcs11Error(_))
+        ));
+    }
+
+    #[test]
+    fn loading_a_missing_module_fails() {
+        let uri: KeyUri =
+            "pkcs11:token=tedge;object=device-key?module-path=/non/existent/libpkcs11.so"
+                .parse()
+                .unwrap();
+        assert!(matches!(
+            uri.signing_key(),
+            Err(CertificateError::Pkcs11Error(_))
+        ));
+    }
+}
@@ -0,0 +1,204 @@
+//! Minimal support of the PKCS#7 certs-only messages (RFC 2315),
+//! as used by EST servers (RFC 7030) to return issued certificates.
+//!
+//! Such a message is a degenerate `SignedData` with no content nor signature,
+//! but only a set of DER-encoded certificates.
+use crate::CertificateError;
+
+const INTEGER: u8 = 0x02;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const CONTEXT_SPECIFIC_0: u8 = 0xA0;
+
+/// 1.2.840.113549.1.7.1
+const OID_DATA: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x01];
+
+/// 1.2.840.113549.1.7.2
+const OID_SIGNED_DATA: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x02];
+
+/// Extract the DER-encoded certificates of a PKCS#7 certs-only message
+pub fn certificates_from_pkcs7(der: &[u8]) -> Result<Vec<Vec<u8>>, CertificateError> {
+    let (content_info, _) = read_tlv(der, SEQUENCE)?;
+    let (content_type, content) = read_tlv(content_info.value, OBJECT_IDENTIFIER)?;
+    if content_type.value != OID_SIGNED_DATA {
+        return Err(invalid("not a SignedData message"));
+    }
+    let (content, _) = read_tlv(content, CONTEXT_SPECIFIC_0)?;
+    let (signed_data, _) = read_tlv(content.value, SEQUENCE)?;
+    let (_version, fields) = read_tlv(signed_data.value, INTEGER)?;
+    let (_digest_algorithms, fields) = read_tlv(fields, SET)?;
+    let (_encapsulated_content, fields) = read_tlv(fields, SEQUENCE)?;
+    let (certificates, _) = read_tlv(fields, CONTEXT_SPECIFIC_0)
+        .map_err(|_| invalid("no certificates in the SignedData message"))?;
+
+    let mut ders = Vec::new();
+    let mut input = certificates.value;
+    while !input.is_empty() {
+        let (certificate, rest) = read_tlv(input, SEQUENCE)?;
+        ders.push(certificate.raw.to_vec());
+        input = rest;
+    }
+    Ok(ders)
+}
+
+/// Build a PKCS#7 certs-only message from DER-encoded certificates
+pub fn pkcs7_from_certificates(certificates: &[Vec<u8>]) -> Vec<u8> {
+    let version = write_tlv(INTEGER, &[1]);
+    let digest_algorithms = write_tlv(SET, &[]);
+    let encapsulated_content = write_tlv(SEQUENCE, &write_tlv(OBJECT_IDENTIFIER, OID_DATA));
+    let certificates = write_tlv(CONTEXT_SPECIFIC_0, &certificates.concat());
+    let signer_infos = write_tlv(SET, &[]);
+    let signed_data = write_tlv(
+        SEQUENCE,
+        &[
+            version,
+            digest_algorithms,
+            encapsulated_content,
+            certificates,
+            signer_infos,
+        ]
+        .concat(),
+    );
+
+    let content_type = write_tlv(OBJECT_IDENTIFIER, OID_SIGNED_DATA);
+    let content = write_tlv(CONTEXT_SPECIFIC_0, &signed_data);
+    write_tlv(SEQUENCE, &[content_type, content].concat())
+}
+
+/// A DER-encoded tag-length-value
+struct Tlv<'a> {
+    /// The whole encoding, tag and length included
+    raw: &'a [u8],
+
+    /// The value only
+    value: &'a [u8],
+}
+
+/// Read a TLV with the expected tag, returning the TLV and the remaining input
+fn read_tlv(input: &[u8], expected_tag: u8) -> Result<(Tlv, &[u8]), CertificateError> {
+    let (&tag, rest) = input.split_first().ok_or_else(|| invalid("truncated"))?;
+    if tag != expected_tag {
+        return Err(invalid(&format!(
+            "unexpected tag 0x{tag:02X}, expecting 0x{expected_tag:02X}"
+        )));
+    }
+
+    let (&first, rest) = rest.split_first().ok_or_else(|| invalid("truncated"))?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let len_size = (first & 0x7F) as usize;
+        if len_size == 0 || len_size > 4 || rest.len() < len_size {
+            return Err(invalid("unsupported length"));
+        }
+        let len = rest[..len_size]
+            .iter()
+            .fold(0usize, |len, byte| (len << 8) | *byte as usize);
+        (len, &rest[len_size..])
+    };
+    if rest.len() < len {
+        return Err(invalid("truncated"));
+    }
+
+    let header_size = input.len() - rest.len();
+    let tlv = Tlv {
+        raw: &input[..header_size + len],
+        value: &rest[..len],
+    };
+    Ok((tlv, &rest[len..]))
+}
+
+pub(crate) fn write_tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut tlv = vec![tag];
+    let len = value.len();
+    if len < 0x80 {
+        tlv.push(len as u8);
+    } else {
+        let len_bytes: Vec<u8> = len
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|byte| *byte == 0)
+            .collect();
+        tlv.push(0x80 | len_bytes.len() as u8);
+        tlv.extend(len_bytes);
+    }
+    tlv.extend_from_slice(value);
+    tlv
+}
+
+fn invalid(reason: &str) -> CertificateError {
+    CertificateError::InvalidPkcs7(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyCertPair;
+    use crate::KeyKind;
+    use crate::NewCertificateConfig;
+    use crate::PemCertificateChain;
+
+    #[test]
+    fn certificates_are_extracted_from_a_pkcs7_message() {
+        let config = NewCertificateConfig::default();
+        let certificates: Vec<Vec<u8>> = ["device-1", "device-2"]
+            .iter()
+            .map(|id| {
+                let cert =
+                    KeyCertPair::new_selfsigned_certificate(&config, id, &KeyKind::New).unwrap();
+                let pem = cert.certificate_pem_string().unwrap();
+                pem::parse(pem).unwrap().contents
+            })
+            .collect();
+
+        let pkcs7 = pkcs7_from_certificates(&certificates);
+        let extracted = certificates_from_pkcs7(&pkcs7).unwrap();
+        assert_eq!(extracted, certificates);
+
+        let chain = PemCertificateChain::from_der_certificates(extracted).unwrap();
+        assert_eq!(
+            chain.device_certificate().subject_common_name().unwrap(),
+            "device-1"
+        );
+    }
+
+    #[test]
+    fn invalid_pkcs7_messages_are_rejected() {
+        assert!(certificates_from_pkcs7(&[]).is_err());
+        assert!(certificates_from_pkcs7(&[0x30, 0x05, 0x02]).is_err());
+
+        // A SignedData message without certificates
+        let pkcs7 = pkcs7_from_certificates(&[]);
+        assert_eq!(
+            certificates_from_pkcs7(&pkcs7).unwrap(),
+            Vec::<Vec<u8>>::new()
+        );
+
+        // Not a SignedData message
+        let data = write_tlv(
+            SEQUENCE,
+            &[
+                write_tlv(OBJECT_IDENTIFIER, OID_DATA),
+                write_tlv(CONTEXT_SPECIFIC_0, &[]),
+            ]
+            .concat(),
+        );
+        assert!(matches!(
+            certificates_from_pkcs7(&data),
+            Err(CertificateError::InvalidPkcs7(_))
+        ));
+    }
+
+    #[test]
+    fn long_lengths_are_encoded_and_decoded() {
+        let value = vec![0x42; 300];
+        let tlv = write_tlv(SEQUENCE, &value);
+        assert_eq!(&tlv[..4], &[SEQUENCE, 0x82, 0x01, 0x2C]);
+
+        let (decoded, rest) = read_tlv(&tlv, SEQUENCE).unwrap();
+        assert_eq!(decoded.value, value.as_slice());
+        assert_eq!(decoded.raw, tlv.as_slice());
+        assert!(rest.is_empty());
+    }
+}
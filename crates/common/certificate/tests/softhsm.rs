@@ -0,0 +1,143 @@
+//! Sign TLS handshakes with a private key stored on a SoftHSM token.
+//!
+//! The test is skipped when SoftHSM is not installed.
+//! The path of the module can be given with the `SOFTHSM2_MODULE` environment variable.
+use certificate::key_uri::KeyUri;
+use cryptoki::context::CInitializeArgs;
+use cryptoki::context::Pkcs11;
+use cryptoki::mechanism::Mechanism;
+use cryptoki::object::Attribute;
+use cryptoki::session::UserType;
+use cryptoki::types::AuthPin;
+use rustls::SignatureAlgorithm;
+use rustls::SignatureScheme;
+use sha2::Digest;
+use std::path::PathBuf;
+
+const USER_PIN: &str = "1234";
+const SO_PIN: &str = "0000";
+
+/// DER-encoded OID of the NIST P-256 curve
+const OID_P256: &[u8] = &[0x06, 0x08, 0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
+
+#[test]
+fn sign_with_a_softhsm_key() {
+    let Some(module) = softhsm_module() else {
+        eprintln!("SoftHSM is not installed: skipping the test");
+        return;
+    };
+
+    // The tokens are stored in a temporary directory
+    let dir = tempfile::tempdir().unwrap();
+    let tokens = dir.path().join("tokens");
+    std::fs::create_dir(&tokens).unwrap();
+    let conf = dir.path().join("softhsm2.conf");
+    std::fs::write(
+        &conf,
+        format!("directories.tokendir = {}\n", tokens.display()),
+    )
+    .unwrap();
+    std::env::set_var("SOFTHSM2_CONF", &conf);
+
+    // Create a token with an EC private key, as `softhsm2-util` and `pkcs11-tool` would do.
+    // This context is kept till the end of the test, as dropping it finalizes the module.
+    let pkcs11 = Pkcs11::new(&module).unwrap();
+    pkcs11.initialize(CInitializeArgs::OsThreads).unwrap();
+    let slot = pkcs11.get_all_slots().unwrap()[0];
+    pkcs11
+        .init_token(slot, &AuthPin::new(SO_PIN.into()), "tedge")
+        .unwrap();
+    let slot = pkcs11.get_slots_with_token().unwrap()[0];
+    let session = pkcs11.open_rw_session(slot).unwrap();
+    session
+        .login(UserType::So, Some(&AuthPin::new(SO_PIN.into())))
+        .unwrap();
+    session.init_pin(&AuthPin::new(USER_PIN.into())).unwrap();
+    session.logout().unwrap();
+    session
+        .login(UserType::User, Some(&AuthPin::new(USER_PIN.into())))
+        .unwrap();
+    let (public_key, _) = session
+        .generate_key_pair(
+            &Mechanism::EccKeyPairGen,
+            &[
+                Attribute::Token(true),
+                Attribute::Verify(true),
+                Attribute::EcParams(OID_P256.to_vec()),
+            ],
+            &[
+                Attribute::Token(true),
+                Attribute::Private(true),
+                Attribute::Sign(true),
+                Attribute::Label(b"device-key".to_vec()),
+            ],
+        )
+        .unwrap();
+
+    let uri: KeyUri = format!(
+        "pkcs11:token=tedge;object=device-key?pin-value={USER_PIN}&module-path={}",
+        module.display()
+    )
+    .parse()
+    .unwrap();
+    let key = uri.signing_key().unwrap();
+    assert_eq!(key.algorithm(), SignatureAlgorithm::ECDSA);
+
+    let signer = key
+        .choose_scheme(&[
+            SignatureScheme::RSA_PSS_SHA256,
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+        ])
+        .unwrap();
+    assert_eq!(signer.scheme(), SignatureScheme::ECDSA_NISTP256_SHA256);
+
+    // The signature is DER-encoded as expected by TLS, and verified by the public key
+    let message = b"some handshake data";
+    let signature = signer.sign(message).unwrap();
+    session
+        .verify(
+            &Mechanism::Ecdsa,
+            public_key,
+            &sha2::Sha256::digest(message),
+            &ecdsa_signature_from_der(&signature),
+        )
+        .unwrap();
+
+    // A key can be loaded more than once
+    assert!(uri.signing_key().is_ok());
+
+    drop(session);
+    drop(pkcs11);
+}
+
+fn softhsm_module() -> Option<PathBuf> {
+    if let Ok(module) = std::env::var("SOFTHSM2_MODULE") {
+        return Some(PathBuf::from(module));
+    }
+    [
+        "/usr/lib/softhsm/libsofthsm2.so",
+        "/usr/lib/x86_64-linux-gnu/softhsm/libsofthsm2.so",
+        "/usr/lib/aarch64-linux-gnu/softhsm/libsofthsm2.so",
+        "/usr/local/lib/softhsm/libsofthsm2.so",
+    ]
+    .into_iter()
+    .map(PathBuf::from)
+    .find(|path| path.exists())
+}
+
+/// Convert a DER-encoded P-256 `Ecdsa-Sig-Value` back into the raw `r || s` used by PKCS#11
+fn ecdsa_signature_from_der(der: &[u8]) -> Vec<u8> {
+    assert_eq!(der[0], 0x30);
+    let mut raw = Vec::new();
+    let mut rest = &der[2..];
+    for _ in 0..2 {
+        assert_eq!(rest[0], 0x02);
+        let len = rest[1] as usize;
+        let integer = &rest[2..2 + len];
+        let integer = &integer[integer.len().saturating_sub(32)..];
+        raw.extend(std::iter::repeat(0).take(32 - integer.len()));
+        raw.extend(integer);
+        rest = &rest[2 + len..];
+    }
+    raw
+}
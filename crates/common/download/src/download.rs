@@ -10,7 +10,7 @@ use log::warn;
 use nix::sys::statvfs;
 pub use partial_response::InvalidResponseError;
 use reqwest::header;
-use reqwest::Identity;
+use rustls::ClientConfig;
 use serde::Deserialize;
 use serde::Serialize;
 use std::fs;
@@ -101,13 +101,13 @@ pub struct Downloader {
     target_filename: PathBuf,
     target_permission: PermissionEntry,
     backoff: ExponentialBackoff,
-    identity: Option<Identity>,
+    identity: Option<ClientConfig>,
 }
 
 impl Downloader {
     /// Creates a new downloader which downloads to a target directory and uses
     /// default permissions.
-    pub fn new(target_path: PathBuf, identity: Option<Identity>) -> Self {
+    pub fn new(target_path: PathBuf, identity: Option<ClientConfig>) -> Self {
         Self {
             target_filename: target_path,
             target_permission: PermissionEntry::default(),
@@ -121,7 +121,7 @@ impl Downloader {
     pub fn with_permission(
         target_path: PathBuf,
         target_permission: PermissionEntry,
-        identity: Option<Identity>,
+        identity: Option<ClientConfig>,
     ) -> Self {
         Self {
             target_filename: target_path,
@@ -386,7 +386,7 @@ impl Downloader {
         let operation = || async {
             let mut client = reqwest::Client::builder();
             if let Some(identity) = &self.identity {
-                client = client.identity(identity.clone());
+                client = client.use_preconfigured_tls(identity.clone());
             }
             let mut request = client.build()?.get(url.url());
             if let Some(Auth::Bearer(token)) = &url.auth {
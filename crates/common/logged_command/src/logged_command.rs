@@ -6,6 +6,8 @@ use std::process::Output;
 use std::process::Stdio;
 use std::time::Duration;
 use tokio::fs::File;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWrite;
 use tokio::io::AsyncWriteExt;
 use tokio::io::BufWriter;
@@ -56,6 +58,57 @@ impl LoggingChild {
         outcome
     }
 
+    /// Wait for the child to terminate, calling `on_line` on each line as soon as printed on stdout
+    ///
+    /// The whole stdout and stderr are returned and logged as with [LoggingChild::wait_with_output].
+    pub async fn wait_with_streamed_output(
+        mut self,
+        logger: &mut BufWriter<File>,
+        mut on_line: impl FnMut(&str),
+    ) -> Result<Output, std::io::Error> {
+        // Close stdin, so the child is not blocked waiting for some input
+        drop(self.inner_child.stdin.take());
+
+        let stdout = self.inner_child.stdout.take();
+        let stderr = self.inner_child.stderr.take();
+
+        let read_stdout = async {
+            let mut content = Vec::new();
+            if let Some(stdout) = stdout {
+                let mut lines = tokio::io::BufReader::new(stdout).lines();
+                while let Some(line) = lines.next_line().await? {
+                    on_line(&line);
+                    content.extend_from_slice(line.as_bytes());
+                    content.push(b'\n');
+                }
+            }
+            Ok::<_, std::io::Error>(content)
+        };
+        let read_stderr = async {
+            let mut content = Vec::new();
+            if let Some(mut stderr) = stderr {
+                stderr.read_to_end(&mut content).await?;
+            }
+            Ok::<_, std::io::Error>(content)
+        };
+
+        let (stdout, stderr) = tokio::join!(read_stdout, read_stderr);
+        let outcome = match (stdout, stderr) {
+            (Ok(stdout), Ok(stderr)) => self.inner_child.wait().await.map(|status| Output {
+                status,
+                stdout,
+                stderr,
+            }),
+            (Err(err), _) | (_, Err(err)) => Err(err),
+        };
+
+        if let Err(err) = LoggedCommand::log_outcome(&self.command_line, &outcome, logger).await {
+            error!("Fail to log the command execution: {}", err);
+        }
+
+        outcome
+    }
+
     async fn update_and_log_outcome(
         command_line: String,
         outcome: Result<Output, std::io::Error>,
@@ -192,6 +245,29 @@ impl LoggedCommand {
         outcome
     }
 
+    /// Execute the command, calling `on_line` on each line printed on stdout while the command is running
+    ///
+    /// The outcome is returned and logged as with [LoggedCommand::execute].
+    pub async fn execute_streaming(
+        mut self,
+        logger: &mut BufWriter<File>,
+        on_line: impl FnMut(&str),
+    ) -> Result<Output, std::io::Error> {
+        let child = match self.spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                let outcome = Err(err);
+                if let Err(err) =
+                    LoggedCommand::log_outcome(&self.command_line, &outcome, logger).await
+                {
+                    error!("Fail to log the command execution: {}", err);
+                }
+                return outcome;
+            }
+        };
+        child.wait_with_streamed_output(logger, on_line).await
+    }
+
     pub fn spawn(&mut self) -> Result<LoggingChild, std::io::Error> {
         let child = self.command.spawn()?;
         Ok(LoggingChild {
@@ -337,6 +413,51 @@ EOF
             log_content,
             r#"----- $ dummy-command
 error: No such file or directory (os error 2)
+"#
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn on_streamed_execution_each_stdout_line_is_handled_and_logged(
+    ) -> Result<(), anyhow::Error> {
+        // Prepare a log file
+        let tmp_dir = TempTedgeDir::new();
+        let tmp_file = tmp_dir.file("operation.log");
+        let log_file_path = tmp_file.path();
+        let log_file = File::create(&log_file_path).await?;
+        let mut logger = BufWriter::new(log_file);
+
+        // Prepare a command printing several lines on stdout and stderr
+        let mut command = LoggedCommand::new("sh");
+        command
+            .arg("-c")
+            .arg("echo first; echo oops >&2; echo second");
+
+        // Execute the command, collecting the lines as printed
+        let mut lines = vec![];
+        let output = command
+            .execute_streaming(&mut logger, |line| lines.push(line.to_string()))
+            .await?;
+
+        assert_eq!(lines, vec!["first", "second"]);
+        assert_eq!(output.stdout, b"first\nsecond\n");
+        assert_eq!(output.stderr, b"oops\n");
+
+        let log_content = String::from_utf8(std::fs::read(log_file_path)?)?;
+        assert_eq!(
+            log_content,
+            r#"----- $ sh "-c" "echo first; echo oops >&2; echo second"
+exit status: 0
+
+stdout <<EOF
+first
+second
+EOF
+
+stderr <<EOF
+oops
+EOF
 "#
         );
         Ok(())
@@ -1,5 +1,7 @@
 use crate::Message;
 use crate::TopicFilter;
+use certificate::key_uri::KeyUri;
+use certificate::key_uri::TlsIdentity;
 use certificate::parse_root_certificate;
 use certificate::CertificateError;
 use log::debug;
@@ -74,6 +76,11 @@ pub struct BrokerConfig {
 
     /// Certificate authentication configuration
     pub authentication: Option<AuthenticationConfig>,
+
+    /// User name sent to the broker on connect
+    ///
+    /// Default: None
+    pub username: Option<String>,
 }
 
 /// MQTT certificate authentication configuration.
@@ -106,16 +113,26 @@ impl Default for AuthenticationConfig {
 }
 
 #[derive(Clone)]
-struct ClientAuthConfig {
-    cert_chain: Vec<Certificate>,
-    key: Zeroizing<PrivateKey>,
+enum ClientAuthConfig {
+    /// A certificate chain along a private key loaded in memory
+    Pem {
+        cert_chain: Vec<Certificate>,
+        key: Zeroizing<PrivateKey>,
+    },
+
+    /// A certificate chain along a private key that is possibly stored on a PKCS#11 token
+    Identity(Arc<TlsIdentity>),
 }
 
 impl Debug for ClientAuthConfig {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("ClientAuthConfig")
-            .field("cert_chain", &self.cert_chain)
-            .finish()
+        match self {
+            ClientAuthConfig::Pem { cert_chain, .. } => f
+                .debug_struct("ClientAuthConfig")
+                .field("cert_chain", cert_chain)
+                .finish(),
+            ClientAuthConfig::Identity(_) => f.debug_struct("ClientAuthConfig").finish(),
+        }
     }
 }
 
@@ -159,6 +176,7 @@ impl Default for Config {
                 host: String::from("localhost"),
                 port: 1883,
                 authentication: None,
+                username: None,
             },
             session_name: None,
             subscriptions: TopicFilter::empty(),
@@ -191,6 +209,12 @@ impl Config {
         self
     }
 
+    /// Set the user name sent to the broker on connect
+    pub fn with_username(mut self, username: impl Into<String>) -> Self {
+        self.broker.username = Some(username.into());
+        self
+    }
+
     /// Set the session name
     pub fn with_session_name(self, name: impl Into<String>) -> Self {
         Self {
@@ -303,7 +327,7 @@ impl Config {
         let cert_chain = parse_root_certificate::read_cert_chain(cert_file)?;
         let key = parse_root_certificate::read_pvt_key(key_file)?;
 
-        let client_auth_config = ClientAuthConfig {
+        let client_auth_config = ClientAuthConfig::Pem {
             cert_chain,
             key: Zeroizing::new(PrivateKey(key)),
         };
@@ -314,6 +338,24 @@ impl Config {
         Ok(self)
     }
 
+    /// Provide client certificate and the URI of the private key for authentication.
+    ///
+    /// Contrary to [`Config::with_client_auth`], the private key can be stored on a PKCS#11 token.
+    pub fn with_client_identity(
+        &mut self,
+        cert_file: impl AsRef<Path>,
+        key_uri: &KeyUri,
+    ) -> Result<&mut Self, CertificateError> {
+        debug!("Using client certificate: {}", cert_file.as_ref().display());
+        debug!("Using client private key: {key_uri}");
+        let identity = TlsIdentity::load(cert_file, key_uri)?;
+
+        let authentication_config = self.broker.authentication.get_or_insert(Default::default());
+        authentication_config.client_auth = Some(ClientAuthConfig::Identity(Arc::new(identity)));
+
+        Ok(self)
+    }
+
     /// Wrap this config into an internal set of options for `rumqttc`.
     pub fn rumqttc_options(&self) -> Result<rumqttc::MqttOptions, rustls::Error> {
         let id = match &self.session_name {
@@ -328,6 +370,10 @@ impl Config {
         let mut mqtt_options =
             rumqttc::MqttOptions::new(id, &broker_config.host, broker_config.port);
 
+        if let Some(username) = &broker_config.username {
+            mqtt_options.set_credentials(username, "");
+        }
+
         if self.session_name.is_none() {
             // There is no point to have a session with a random name that will not be reused.
             mqtt_options.set_clean_session(true);
@@ -341,10 +387,12 @@ impl Config {
                 .with_root_certificates(authentication_config.cert_store.clone());
 
             let tls_config = match authentication_config.client_auth.clone() {
-                Some(client_auth_config) => tls_config.with_client_auth_cert(
-                    client_auth_config.cert_chain,
-                    client_auth_config.key.deref().0.clone(),
-                )?,
+                Some(ClientAuthConfig::Pem { cert_chain, key }) => {
+                    tls_config.with_client_auth_cert(cert_chain, key.deref().0.clone())?
+                }
+                Some(ClientAuthConfig::Identity(identity)) => {
+                    tls_config.with_client_cert_resolver(identity)
+                }
                 None => tls_config.with_no_client_auth(),
             };
 
@@ -32,6 +32,9 @@ pub struct Connection {
     /// The channel of the error messages received by this connection.
     pub errors: mpsc::UnboundedReceiver<MqttError>,
 
+    /// A channel notified each time the connection is re-established after having been lost.
+    pub reconnections: mpsc::UnboundedReceiver<()>,
+
     /// A channel to notify that all the published messages have been actually published.
     pub pub_done: oneshot::Receiver<()>,
 }
@@ -86,6 +89,7 @@ impl Connection {
         let (received_sender, received_receiver) = mpsc::unbounded();
         let (published_sender, published_receiver) = mpsc::unbounded();
         let (error_sender, error_receiver) = mpsc::unbounded();
+        let (reconnection_sender, reconnection_receiver) = mpsc::unbounded();
         let (pub_done_sender, pub_done_receiver) = oneshot::channel();
 
         let (mqtt_client, event_loop) =
@@ -96,6 +100,7 @@ impl Connection {
             event_loop,
             received_sender,
             error_sender.clone(),
+            reconnection_sender,
         ));
         tokio::spawn(Connection::sender_loop(
             mqtt_client,
@@ -109,6 +114,7 @@ impl Connection {
             received: received_receiver,
             published: published_sender,
             errors: error_receiver,
+            reconnections: reconnection_receiver,
             pub_done: pub_done_receiver,
         })
     }
@@ -200,6 +206,7 @@ impl Connection {
         mut event_loop: EventLoop,
         mut message_sender: mpsc::UnboundedSender<Message>,
         mut error_sender: mpsc::UnboundedSender<MqttError>,
+        mut reconnection_sender: mpsc::UnboundedSender<()>,
     ) -> Result<(), MqttError> {
         loop {
             match event_loop.poll().await {
@@ -214,6 +221,8 @@ impl Connection {
                         error!("MQTT connection Error {err}");
                     } else {
                         info!("MQTT connection re-established");
+                        // Errors on send are ignored: it just means the client has closed the receiving channel.
+                        let _ = reconnection_sender.send(()).await;
                         if let Some(ref imsg_fn) = config.initial_message {
                             // publish the initial message on connect
                             let message = imsg_fn.new_init_message();
@@ -227,9 +236,9 @@ impl Connection {
                                 .await?;
                         }
 
-                        if config.session_name.is_none() {
+                        if config.session_name.is_none() || config.clean_session {
                             // Workaround for  https://github.com/bytebeamio/rumqtt/issues/250
-                            // If session_name is not provided, then re-subscribe
+                            // If the session is not persisted by the broker, then re-subscribe
 
                             let subscriptions = config.subscriptions.filters();
                             // Need check here otherwise it will hang waiting for a SubAck, and none will come when there is no subscription.
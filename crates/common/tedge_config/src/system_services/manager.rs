@@ -36,7 +36,7 @@ pub trait SystemServiceManager: Debug {
         &self,
         service: SystemService,
     ) -> Result<bool, SystemServiceError> {
-        if self.is_service_running(service)? {
+        if self.is_service_running(service.clone())? {
             self.restart_service(service)?;
             Ok(true)
         } else {
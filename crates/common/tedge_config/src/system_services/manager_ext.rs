@@ -12,13 +12,13 @@ impl SystemServiceManagerExt for &dyn SystemServiceManager {
         let mut failed = false;
 
         let _ = writeln!(&mut wr, "Starting {} service.\n", service);
-        if let Err(err) = self.start_service(service) {
+        if let Err(err) = self.start_service(service.clone()) {
             let _ = writeln!(&mut wr, "Failed to stop {} service: {:?}", service, err);
             failed = true;
         }
 
         let _ = writeln!(&mut wr, "Persisting {} on reboot.\n", service);
-        if let Err(err) = self.enable_service(service) {
+        if let Err(err) = self.enable_service(service.clone()) {
             let _ = writeln!(&mut wr, "Failed to enable {} service: {:?}", service, err);
             failed = true;
         }
@@ -38,13 +38,13 @@ impl SystemServiceManagerExt for &dyn SystemServiceManager {
         let mut failed = false;
 
         let _ = writeln!(&mut wr, "Stopping {} service.\n", service);
-        if let Err(err) = self.stop_service(service) {
+        if let Err(err) = self.stop_service(service.clone()) {
             let _ = writeln!(&mut wr, "Failed to stop {} service: {:?}", service, err);
             failed = true;
         }
 
         let _ = writeln!(&mut wr, "Disabling {} service.\n", service);
-        if let Err(err) = self.disable_service(service) {
+        if let Err(err) = self.disable_service(service.clone()) {
             let _ = writeln!(&mut wr, "Failed to disable {} service: {:?}", service, err);
             failed = true;
         }
@@ -115,7 +115,7 @@ impl ExecCommand {
         config_path: Utf8PathBuf,
         service: SystemService,
     ) -> Result<Self, SystemServiceError> {
-        let replaced = replace_with_service_name(&config, service_cmd, &config_path, service)?;
+        let replaced = replace_with_service_name(&config, &service_cmd, &config_path, service)?;
         Self::try_new(replaced, service_cmd, config_path)
     }
 
@@ -143,7 +143,7 @@ impl fmt::Display for ExecCommand {
 
 fn replace_with_service_name(
     input_args: &[String],
-    service_cmd: ServiceCommand,
+    service_cmd: &ServiceCommand,
     config_path: impl Into<Utf8PathBuf>,
     service: SystemService,
 ) -> Result<Vec<String>, SystemServiceError> {
@@ -158,14 +158,14 @@ fn replace_with_service_name(
     let mut args = input_args.to_owned();
     for item in args.iter_mut() {
         if item == "{}" {
-            *item = SystemService::as_service_name(service).to_string();
+            *item = service.to_string();
         }
     }
 
     Ok(args)
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 enum ServiceCommand {
     CheckManager,
     Stop(SystemService),
@@ -190,39 +190,39 @@ impl ServiceCommand {
             ),
             Self::Stop(service) => ExecCommand::try_new_with_placeholder(
                 service_manager.init_config.stop.clone(),
-                ServiceCommand::Stop(*service),
+                ServiceCommand::Stop(service.clone()),
                 config_path,
-                *service,
+                service.clone(),
             ),
             Self::Restart(service) => ExecCommand::try_new_with_placeholder(
                 service_manager.init_config.restart.clone(),
-                ServiceCommand::Restart(*service),
+                ServiceCommand::Restart(service.clone()),
                 config_path,
-                *service,
+                service.clone(),
             ),
             Self::Start(service) => ExecCommand::try_new_with_placeholder(
                 service_manager.init_config.start.clone(),
-                ServiceCommand::Enable(*service),
+                ServiceCommand::Enable(service.clone()),
                 config_path,
-                *service,
+                service.clone(),
             ),
             Self::Enable(service) => ExecCommand::try_new_with_placeholder(
                 service_manager.init_config.enable.clone(),
-                ServiceCommand::Enable(*service),
+                ServiceCommand::Enable(service.clone()),
                 config_path,
-                *service,
+                service.clone(),
             ),
             Self::Disable(service) => ExecCommand::try_new_with_placeholder(
                 service_manager.init_config.disable.clone(),
-                ServiceCommand::Disable(*service),
+                ServiceCommand::Disable(service.clone()),
                 config_path,
-                *service,
+                service.clone(),
             ),
             Self::IsActive(service) => ExecCommand::try_new_with_placeholder(
                 service_manager.init_config.is_active.clone(),
-                ServiceCommand::IsActive(*service),
+                ServiceCommand::IsActive(service.clone()),
                 config_path,
-                *service,
+                service.clone(),
             ),
         }
     }
@@ -307,7 +307,7 @@ mod tests {
     fn replace_placeholder_with_service(input: Vec<String>, expected_output: Vec<String>) {
         let replaced_config = replace_with_service_name(
             &input,
-            ServiceCommand::Stop(SystemService::Mosquitto),
+            &ServiceCommand::Stop(SystemService::Mosquitto),
             "/dummy/path.toml",
             SystemService::Mosquitto,
         )
@@ -320,7 +320,7 @@ mod tests {
         let input = vec!["bin".to_string(), "arg1".to_string(), "arg2".to_string()];
         let system_config_error = replace_with_service_name(
             &input,
-            ServiceCommand::Stop(SystemService::Mosquitto),
+            &ServiceCommand::Stop(SystemService::Mosquitto),
             "dummy/path.toml",
             SystemService::Mosquitto,
         )
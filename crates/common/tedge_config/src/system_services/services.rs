@@ -1,25 +1,49 @@
+use crate::ProfileName;
+use crate::ProfiledCloud;
+use std::fmt;
+
 /// An enumeration of all supported system services.
-#[derive(Debug, Copy, Clone, strum_macros::Display, strum_macros::IntoStaticStr)]
+///
+/// A mapper service can be attached to a cloud profile,
+/// in which case the service name is suffixed by the profile name, e.g. `tedge-mapper-c8y@staging`.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SystemService {
-    #[strum(serialize = "mosquitto")]
     /// Mosquitto broker
     Mosquitto,
-    #[strum(serialize = "tedge-mapper-az")]
     /// Azure TEdge mapper
-    TEdgeMapperAz,
-    #[strum(serialize = "tedge-mapper-aws")]
+    TEdgeMapperAz(Option<ProfileName>),
     /// AWS TEdge mapper
-    TEdgeMapperAws,
-    #[strum(serialize = "tedge-mapper-c8y")]
+    TEdgeMapperAws(Option<ProfileName>),
     /// Cumulocity TEdge mapper
-    TEdgeMapperC8y,
-    #[strum(serialize = "tedge-agent")]
+    TEdgeMapperC8y(Option<ProfileName>),
     /// TEdge SM agent
     TEdgeSMAgent,
 }
 
 impl SystemService {
-    pub(crate) fn as_service_name(service: SystemService) -> &'static str {
-        service.into()
+    /// The mapper service for the given cloud profile
+    pub fn mapper(cloud: ProfiledCloud, profile: Option<ProfileName>) -> Self {
+        match cloud {
+            ProfiledCloud::C8y => SystemService::TEdgeMapperC8y(profile),
+            ProfiledCloud::Az => SystemService::TEdgeMapperAz(profile),
+            ProfiledCloud::Aws => SystemService::TEdgeMapperAws(profile),
+        }
+    }
+}
+
+impl fmt::Display for SystemService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (cloud, profile) = match self {
+            SystemService::Mosquitto => return write!(f, "mosquitto"),
+            SystemService::TEdgeSMAgent => return write!(f, "tedge-agent"),
+            SystemService::TEdgeMapperAz(profile) => (ProfiledCloud::Az, profile),
+            SystemService::TEdgeMapperAws(profile) => (ProfiledCloud::Aws, profile),
+            SystemService::TEdgeMapperC8y(profile) => (ProfiledCloud::C8y, profile),
+        };
+        write!(
+            f,
+            "tedge-mapper-{}",
+            cloud.connection_name(profile.as_ref())
+        )
     }
 }
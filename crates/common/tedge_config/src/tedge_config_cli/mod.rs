@@ -1,5 +1,6 @@
 pub mod config_setting;
 pub mod error;
+pub mod profiles;
 pub mod tedge_config_location;
 pub mod tedge_config_repository;
 
@@ -3,7 +3,9 @@ pub mod connect_url;
 pub mod flag;
 pub mod host_port;
 pub mod ipaddress;
+pub mod operations_protocol;
 pub mod port;
+pub mod reconciliation_policy;
 pub mod seconds;
 pub mod templates_set;
 
@@ -16,6 +18,8 @@ pub use self::flag::*;
 #[doc(inline)]
 pub use self::host_port::HostPort;
 pub use self::ipaddress::*;
+pub use self::operations_protocol::*;
 pub use self::port::*;
+pub use self::reconciliation_policy::*;
 pub use self::seconds::*;
 pub use self::templates_set::*;
@@ -0,0 +1,48 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+/// The protocol used by Cumulocity to deliver operations to the device
+/// and by the device to report the status of these operations
+#[derive(
+    Debug, Default, Clone, Copy, serde::Serialize, serde::Deserialize, Eq, PartialEq, doku::Document,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum OperationsProtocol {
+    /// SmartREST CSV messages on `s/ds` and `s/us`
+    #[default]
+    SmartRest,
+
+    /// JSON messages on `devicecontrol/notifications` and `devicecontrol/operations/update`
+    Json,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to parse operations protocol: {input}. Supported values are: smartrest, json")]
+pub struct InvalidOperationsProtocol {
+    input: String,
+}
+
+impl FromStr for OperationsProtocol {
+    type Err = InvalidOperationsProtocol;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "smartrest" => Ok(OperationsProtocol::SmartRest),
+            "json" => Ok(OperationsProtocol::Json),
+            _ => Err(InvalidOperationsProtocol {
+                input: input.to_string(),
+            }),
+        }
+    }
+}
+
+impl Display for OperationsProtocol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let output = match self {
+            OperationsProtocol::SmartRest => "smartrest",
+            OperationsProtocol::Json => "json",
+        };
+        output.fmt(f)
+    }
+}
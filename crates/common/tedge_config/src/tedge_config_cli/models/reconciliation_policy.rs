@@ -0,0 +1,53 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+/// What the c8y mapper does with the child devices and services registered on Cumulocity
+/// that are no longer known locally
+#[derive(
+    Debug, Default, Clone, Copy, serde::Serialize, serde::Deserialize, Eq, PartialEq, doku::Document,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ReconciliationPolicy {
+    /// Only report these entities in a summary event
+    #[default]
+    Report,
+
+    /// Mark these entities as unavailable on Cumulocity
+    Unavailable,
+
+    /// Delete these entities from Cumulocity
+    Delete,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to parse reconciliation policy: {input}. Supported values are: report, unavailable, delete")]
+pub struct InvalidReconciliationPolicy {
+    input: String,
+}
+
+impl FromStr for ReconciliationPolicy {
+    type Err = InvalidReconciliationPolicy;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "report" => Ok(ReconciliationPolicy::Report),
+            "unavailable" => Ok(ReconciliationPolicy::Unavailable),
+            "delete" => Ok(ReconciliationPolicy::Delete),
+            _ => Err(InvalidReconciliationPolicy {
+                input: input.to_string(),
+            }),
+        }
+    }
+}
+
+impl Display for ReconciliationPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let output = match self {
+            ReconciliationPolicy::Report => "report",
+            ReconciliationPolicy::Unavailable => "unavailable",
+            ReconciliationPolicy::Delete => "delete",
+        };
+        output.fmt(f)
+    }
+}
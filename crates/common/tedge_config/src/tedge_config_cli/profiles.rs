@@ -0,0 +1,413 @@
+//! Named cloud profiles
+//!
+//! Several connections to clouds of the same type can be configured side by side,
+//! each one with its own settings given under `<cloud>.profiles.<name>`,
+//! e.g. `c8y.profiles.staging.url`.
+//!
+//! The settings of a profile don't inherit from the default settings of the cloud:
+//! a profile is read by replacing the whole cloud group by the profile group.
+use crate::ParseKeyError;
+use crate::TEdgeConfig;
+use crate::TEdgeConfigDto;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+/// The name of a cloud profile, e.g. `staging` in `c8y.profiles.staging.url`
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProfileName(String);
+
+impl ProfileName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for ProfileName {
+    type Err = ProfileError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let is_valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if is_valid {
+            Ok(ProfileName(name.to_string()))
+        } else {
+            Err(ProfileError::InvalidName(name.to_string()))
+        }
+    }
+}
+
+impl Display for ProfileName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The clouds for which named profiles can be defined
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ProfiledCloud {
+    C8y,
+    Az,
+    Aws,
+}
+
+impl ProfiledCloud {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProfiledCloud::C8y => "c8y",
+            ProfiledCloud::Az => "az",
+            ProfiledCloud::Aws => "aws",
+        }
+    }
+
+    /// The name identifying a connection to this cloud, i.e. `c8y` or `c8y@staging`
+    ///
+    /// This name is used to derive the names related to a connection:
+    /// the topic prefix (`c8y@staging/`), the bridge (`mosquitto-c8y@staging-bridge`)
+    /// and the mapper (`tedge-mapper-c8y@staging`).
+    pub fn connection_name(&self, profile: Option<&ProfileName>) -> String {
+        match profile {
+            None => self.as_str().to_string(),
+            Some(profile) => format!("{}@{profile}", self.as_str()),
+        }
+    }
+}
+
+impl FromStr for ProfiledCloud {
+    type Err = ProfileError;
+
+    fn from_str(cloud: &str) -> Result<Self, Self::Err> {
+        match cloud {
+            "c8y" => Ok(ProfiledCloud::C8y),
+            "az" | "azure" => Ok(ProfiledCloud::Az),
+            "aws" => Ok(ProfiledCloud::Aws),
+            _ => Err(ProfileError::UnsupportedCloud(cloud.to_string())),
+        }
+    }
+}
+
+impl Display for ProfiledCloud {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A configuration key, possibly targeting the settings of a profile
+///
+/// `c8y.profiles.staging.url` is parsed as the key `c8y.url` of the profile `staging` of `c8y`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProfiledKey<K> {
+    pub profile: Option<(ProfiledCloud, ProfileName)>,
+    pub key: K,
+}
+
+impl<K> From<K> for ProfiledKey<K> {
+    fn from(key: K) -> Self {
+        ProfiledKey { profile: None, key }
+    }
+}
+
+impl<K> FromStr for ProfiledKey<K>
+where
+    K: FromStr<Err = ParseKeyError>,
+{
+    type Err = ProfileError;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        let mut segments = key.splitn(4, '.');
+        if let (Some(cloud), Some("profiles"), Some(name), Some(rest)) = (
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+        ) {
+            if let Ok(cloud) = cloud.parse::<ProfiledCloud>() {
+                let name = name.parse()?;
+                let key = format!("{cloud}.{rest}").parse()?;
+                return Ok(ProfiledKey {
+                    profile: Some((cloud, name)),
+                    key,
+                });
+            }
+        }
+
+        Ok(ProfiledKey {
+            profile: None,
+            key: key.parse()?,
+        })
+    }
+}
+
+impl<K: Display> Display for ProfiledKey<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.profile {
+            None => self.key.fmt(f),
+            Some((cloud, name)) => {
+                let key = self.key.to_string();
+                let rest = key
+                    .strip_prefix(cloud.as_str())
+                    .and_then(|rest| rest.strip_prefix('.'))
+                    .unwrap_or(&key);
+                write!(f, "{cloud}.profiles.{name}.{rest}")
+            }
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ProfileError {
+    #[error("Invalid profile name: '{0}'. A profile name can only contain ASCII letters, digits, '_' and '-'")]
+    InvalidName(String),
+
+    #[error("Profiles are not supported for '{0}'. Supported clouds are: c8y, az, aws")]
+    UnsupportedCloud(String),
+
+    #[error("Unknown profile: '{name}' is not configured for {cloud}")]
+    UnknownProfile {
+        cloud: ProfiledCloud,
+        name: ProfileName,
+    },
+
+    #[error(transparent)]
+    InvalidKey(#[from] ParseKeyError),
+}
+
+impl TEdgeConfigDto {
+    /// The names of the profiles defined for a cloud
+    pub fn profile_names(&self, cloud: ProfiledCloud) -> Vec<ProfileName> {
+        let names: Vec<&String> = match cloud {
+            ProfiledCloud::C8y => self.c8y.profiles.keys().collect(),
+            ProfiledCloud::Az => self.az.profiles.keys().collect(),
+            ProfiledCloud::Aws => self.aws.profiles.keys().collect(),
+        };
+        names
+            .into_iter()
+            .filter_map(|name| name.parse().ok())
+            .collect()
+    }
+
+    /// A copy of this configuration where the settings of the cloud are those of the given profile
+    pub fn profile_view(
+        &self,
+        cloud: ProfiledCloud,
+        name: &ProfileName,
+    ) -> Result<TEdgeConfigDto, ProfileError> {
+        let unknown_profile = || ProfileError::UnknownProfile {
+            cloud,
+            name: name.clone(),
+        };
+        let mut view = self.clone();
+        match cloud {
+            ProfiledCloud::C8y => {
+                view.c8y = self
+                    .c8y
+                    .profiles
+                    .get(name.as_str())
+                    .cloned()
+                    .ok_or_else(unknown_profile)?
+            }
+            ProfiledCloud::Az => {
+                view.az = self
+                    .az
+                    .profiles
+                    .get(name.as_str())
+                    .cloned()
+                    .ok_or_else(unknown_profile)?
+            }
+            ProfiledCloud::Aws => {
+                view.aws = self
+                    .aws
+                    .profiles
+                    .get(name.as_str())
+                    .cloned()
+                    .ok_or_else(unknown_profile)?
+            }
+        }
+        Ok(view)
+    }
+
+    /// Update the settings of a profile, creating the profile if not defined yet
+    ///
+    /// The update is applied to a configuration where the cloud settings are those of the profile.
+    /// The profile is removed if left with no settings.
+    pub fn update_profile<R>(
+        &mut self,
+        cloud: ProfiledCloud,
+        name: &ProfileName,
+        update: impl FnOnce(&mut TEdgeConfigDto) -> R,
+    ) -> R {
+        let mut view = TEdgeConfigDto::default();
+        let name = name.to_string();
+        match cloud {
+            ProfiledCloud::C8y => {
+                view.c8y = self.c8y.profiles.remove(&name).unwrap_or_default();
+                let result = update(&mut view);
+                if view.c8y != Default::default() {
+                    self.c8y.profiles.insert(name, view.c8y);
+                }
+                result
+            }
+            ProfiledCloud::Az => {
+                view.az = self.az.profiles.remove(&name).unwrap_or_default();
+                let result = update(&mut view);
+                if view.az != Default::default() {
+                    self.az.profiles.insert(name, view.az);
+                }
+                result
+            }
+            ProfiledCloud::Aws => {
+                view.aws = self.aws.profiles.remove(&name).unwrap_or_default();
+                let result = update(&mut view);
+                if view.aws != Default::default() {
+                    self.aws.profiles.insert(name, view.aws);
+                }
+                result
+            }
+        }
+    }
+}
+
+impl TEdgeConfig {
+    /// The names of the profiles defined for a cloud
+    pub fn profile_names(&self, cloud: ProfiledCloud) -> Vec<ProfileName> {
+        self.dto.profile_names(cloud)
+    }
+
+    /// The configuration to be used for the given profile of a cloud, if any
+    ///
+    /// Return this configuration unchanged when no profile is given.
+    pub fn for_profile(
+        self,
+        cloud: ProfiledCloud,
+        profile: Option<&ProfileName>,
+    ) -> Result<TEdgeConfig, ProfileError> {
+        match profile {
+            None => Ok(self),
+            Some(name) => self.profile(cloud, name),
+        }
+    }
+
+    /// The configuration to be used for the given profile of a cloud
+    pub fn profile(
+        &self,
+        cloud: ProfiledCloud,
+        name: &ProfileName,
+    ) -> Result<TEdgeConfig, ProfileError> {
+        let dto = self.dto.profile_view(cloud, name)?;
+        Ok(TEdgeConfig::from_dto(&dto, &self.location))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ReadableKey;
+    use crate::TEdgeConfigLocation;
+    use crate::WritableKey;
+
+    #[test]
+    fn profile_keys_are_parsed_as_cloud_keys() {
+        let key: ProfiledKey<WritableKey> = "c8y.profiles.staging.url".parse().unwrap();
+
+        assert_eq!(
+            key.profile,
+            Some((ProfiledCloud::C8y, "staging".parse().unwrap()))
+        );
+        assert_eq!(key.key, WritableKey::C8yUrl);
+        assert_eq!(key.to_string(), "c8y.profiles.staging.url");
+    }
+
+    #[test]
+    fn keys_with_no_profiles_are_parsed_unchanged() {
+        let key: ProfiledKey<ReadableKey> = "c8y.url".parse().unwrap();
+
+        assert_eq!(key.profile, None);
+        assert_eq!(key.key, ReadableKey::C8yUrl);
+        assert_eq!(key.to_string(), "c8y.url");
+    }
+
+    #[test]
+    fn profile_keys_are_only_supported_for_cloud_keys() {
+        assert!("c8y.profiles.staging.device.id"
+            .parse::<ProfiledKey<ReadableKey>>()
+            .is_err());
+        assert!("mqtt.profiles.staging.bind.port"
+            .parse::<ProfiledKey<ReadableKey>>()
+            .is_err());
+        assert!("c8y.profiles.st@ging.url"
+            .parse::<ProfiledKey<ReadableKey>>()
+            .is_err());
+    }
+
+    #[test]
+    fn profile_settings_replace_the_cloud_settings() {
+        let staging: ProfileName = "staging".parse().unwrap();
+        let mut dto = TEdgeConfigDto::default();
+        dto.try_update_str(WritableKey::C8yUrl, "prod.example.com")
+            .unwrap();
+        dto.try_update_str(WritableKey::DeviceType, "my-device")
+            .unwrap();
+        dto.update_profile(ProfiledCloud::C8y, &staging, |dto| {
+            dto.try_update_str(WritableKey::C8yUrl, "staging.example.com")
+        })
+        .unwrap();
+
+        let config = TEdgeConfig::from_dto(&dto, &TEdgeConfigLocation::default());
+        assert_eq!(
+            config.profile_names(ProfiledCloud::C8y),
+            vec![staging.clone()]
+        );
+        assert_eq!(config.profile_names(ProfiledCloud::Az), vec![]);
+
+        let config = config
+            .for_profile(ProfiledCloud::C8y, Some(&staging))
+            .unwrap();
+        assert_eq!(
+            config.read_string(ReadableKey::C8yUrl).unwrap(),
+            "staging.example.com"
+        );
+        assert_eq!(
+            config.read_string(ReadableKey::DeviceType).unwrap(),
+            "my-device"
+        );
+    }
+
+    #[test]
+    fn unknown_profiles_are_rejected() {
+        let config =
+            TEdgeConfig::from_dto(&TEdgeConfigDto::default(), &TEdgeConfigLocation::default());
+
+        let result = config.for_profile(ProfiledCloud::Aws, Some(&"staging".parse().unwrap()));
+
+        assert!(matches!(result, Err(ProfileError::UnknownProfile { .. })));
+    }
+
+    #[test]
+    fn profiles_left_with_no_settings_are_removed() {
+        let staging: ProfileName = "staging".parse().unwrap();
+        let mut dto = TEdgeConfigDto::default();
+        dto.update_profile(ProfiledCloud::Az, &staging, |dto| {
+            dto.try_update_str(WritableKey::AzUrl, "staging.azure-devices.net")
+        })
+        .unwrap();
+        assert!(dto.az.profiles.contains_key("staging"));
+
+        dto.update_profile(ProfiledCloud::Az, &staging, |dto| {
+            dto.unset_key(WritableKey::AzUrl)
+        });
+        assert!(dto.az.profiles.is_empty());
+    }
+
+    #[test]
+    fn connection_names_are_suffixed_with_the_profile() {
+        let staging: ProfileName = "staging".parse().unwrap();
+
+        assert_eq!(ProfiledCloud::C8y.connection_name(None), "c8y");
+        assert_eq!(
+            ProfiledCloud::Aws.connection_name(Some(&staging)),
+            "aws@staging"
+        );
+    }
+}
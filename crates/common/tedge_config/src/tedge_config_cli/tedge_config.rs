@@ -1,6 +1,8 @@
 use crate::AutoFlag;
 use crate::ConnectUrl;
 use crate::HostPort;
+use crate::OperationsProtocol;
+use crate::ReconciliationPolicy;
 use crate::Seconds;
 use crate::TEdgeConfigLocation;
 use crate::TemplatesSet;
@@ -9,12 +11,14 @@ use crate::MQTT_TLS_PORT;
 use anyhow::anyhow;
 use anyhow::Context;
 use camino::Utf8PathBuf;
+use certificate::key_uri::KeyUri;
+use certificate::key_uri::TlsIdentity;
+use certificate::parse_root_certificate::create_tls_config_with_native_roots;
 use certificate::CertificateError;
 use certificate::PemCertificate;
 use doku::Document;
 use once_cell::sync::Lazy;
 use std::borrow::Cow;
-use std::io::Read;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::num::NonZeroU16;
@@ -40,19 +44,28 @@ impl<T> OptionalConfigError<T> for OptionalConfig<T> {
     }
 }
 
-pub struct TEdgeConfig(TEdgeConfigReader);
+pub struct TEdgeConfig {
+    reader: TEdgeConfigReader,
+    // Kept to build the configuration of a profile (see [TEdgeConfig::for_profile])
+    pub(crate) dto: TEdgeConfigDto,
+    pub(crate) location: TEdgeConfigLocation,
+}
 
 impl std::ops::Deref for TEdgeConfig {
     type Target = TEdgeConfigReader;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.reader
     }
 }
 
 impl TEdgeConfig {
     pub fn from_dto(dto: &TEdgeConfigDto, location: &TEdgeConfigLocation) -> Self {
-        Self(TEdgeConfigReader::from_dto(dto, location))
+        Self {
+            reader: TEdgeConfigReader::from_dto(dto, location),
+            dto: dto.clone(),
+            location: location.clone(),
+        }
     }
 
     pub fn mqtt_config(&self) -> Result<mqtt_channel::Config, CertificateError> {
@@ -331,17 +344,49 @@ define_tedge_config! {
         #[doku(as = "PathBuf")]
         key_path: Utf8PathBuf,
 
+        /// URI of the device's private key, either a file path or a PKCS#11 URI for a key stored on a token.
+        /// When set, this takes precedence over `device.key_path`
+        #[tedge_config(example = "pkcs11:token=tedge;object=device-key?module-path=/usr/lib/softhsm/libsofthsm2.so")]
+        key_uri: String,
+
         /// Path where the device's certificate is stored
         #[tedge_config(example = "/etc/tedge/device-certs/tedge-certificate.pem", default(function = "default_device_cert"))]
         #[doku(as = "PathBuf")]
         cert_path: Utf8PathBuf,
 
+        /// Path where the device's certificate signing request is stored
+        #[tedge_config(example = "/etc/tedge/device-certs/tedge.csr", default(function = "default_device_csr"))]
+        #[doku(as = "PathBuf")]
+        csr_path: Utf8PathBuf,
+
+        est: {
+            /// URL of the EST server used to renew the device certificate
+            #[tedge_config(example = "https://est.example.com/.well-known/est")]
+            url: String,
+
+            /// Path of the root certificates trusted to connect the EST server, in addition to the system ones
+            #[tedge_config(example = "/etc/tedge/device-certs/est-root-ca.pem")]
+            #[doku(as = "PathBuf")]
+            root_cert_path: Utf8PathBuf,
+        },
+
+        cert_renewal: {
+            /// How long before its expiry, in seconds, the device certificate is renewed
+            #[tedge_config(example = "2592000", default(value = 2592000_u64))]
+            window: Seconds,
+
+            /// The interval in seconds between two checks of the device certificate expiry
+            #[tedge_config(example = "3600", default(value = 3600_u64))]
+            interval: Seconds,
+        },
+
         /// The default device type
         #[tedge_config(example = "thin-edge.io", default(value = "thin-edge.io"))]
         #[tedge_config(rename = "type")]
         ty: String,
     },
 
+    #[tedge_config(profiles)]
     c8y: {
         /// Endpoint URL of Cumulocity tenant
         #[tedge_config(example = "your-tenant.cumulocity.com")]
@@ -361,6 +406,12 @@ define_tedge_config! {
             templates: TemplatesSet,
         },
 
+        operations: {
+            /// The protocol used to receive operations from Cumulocity and to report their status: `smartrest` or `json`
+            #[tedge_config(example = "smartrest", example = "json", default(variable = "OperationsProtocol::SmartRest"))]
+            protocol: OperationsProtocol,
+        },
+
 
         /// HTTP Endpoint for the Cumulocity tenant, with optional port.
         #[tedge_config(example = "http.your-tenant.cumulocity.com:1234")]
@@ -448,10 +499,65 @@ define_tedge_config! {
             /// Enable auto registration feature
             #[tedge_config(example = "true", default(value = true))]
             auto_register: bool,
+
+            /// The maximum number of telemetry messages cached for entities not registered yet
+            #[tedge_config(example = "100", default(value = 100u32))]
+            telemetry_cache_size: u32,
+
+            /// The time in seconds after which the telemetry messages cached for entities not registered yet are dropped
+            #[tedge_config(example = "3600", default(value = 3600_u64))]
+            telemetry_cache_ttl: Seconds,
+
+            /// The maximum number of registration and metadata messages cached for entities not registered yet
+            #[tedge_config(example = "1000", default(value = 1000u32))]
+            metadata_cache_size: u32,
+
+            /// The time in seconds after which the child devices and services whose parent is still not registered are evicted
+            #[tedge_config(example = "3600", default(value = 3600_u64))]
+            orphan_ttl: Seconds,
+        },
+
+        aggregation: {
+            /// Whether measurements are aggregated over time windows before being sent to Cumulocity
+            #[tedge_config(example = "true", default(value = false))]
+            enable: bool,
+
+            /// The length in seconds of the time windows over which measurements are aggregated
+            #[tedge_config(example = "60", default(value = 60_u64))]
+            window: Seconds,
+
+            /// The functions applied to the values of each measurement series over a time window: min, max, avg, last and count
+            #[tedge_config(example = "min,max,avg", default(value = "avg"))]
+            functions: TemplatesSet,
+
+            /// The measurement types to aggregate. When not set, all measurements are aggregated
+            #[tedge_config(example = "environment,vibration")]
+            types: TemplatesSet,
+        },
+
+        bulk_measurements: {
+            /// Whether measurements are grouped and sent in bulk to Cumulocity, rather than one message per measurement
+            #[tedge_config(example = "true", default(value = false))]
+            enable: bool,
+
+            /// The maximum time in milliseconds a measurement is held back to be sent in bulk with others
+            #[tedge_config(example = "1000", default(value = 1000u32))]
+            max_latency: u32,
+        },
+
+        reconciliation: {
+            /// Whether the child devices and services registered on Cumulocity are reconciled with the local entities when the mapper starts
+            #[tedge_config(example = "true", default(value = false))]
+            enable: bool,
+
+            /// What to do with the child devices and services registered on Cumulocity but no longer known locally: `report`, `unavailable` or `delete`
+            #[tedge_config(example = "report", example = "unavailable", example = "delete", default(variable = "ReconciliationPolicy::Report"))]
+            policy: ReconciliationPolicy,
         },
     },
 
     #[tedge_config(deprecated_name = "azure")] // for 0.1.0 compatibility
+    #[tedge_config(profiles)]
     az: {
         /// Endpoint URL of Azure IoT tenant
         #[tedge_config(example = "myazure.azure-devices.net")]
@@ -476,6 +582,7 @@ define_tedge_config! {
         topics: TemplatesSet,
     },
 
+    #[tedge_config(profiles)]
     aws: {
         /// Endpoint URL of AWS IoT tenant
         #[tedge_config(example = "your-endpoint.amazonaws.com")]
@@ -597,6 +704,29 @@ define_tedge_config! {
             #[doku(as = "PathBuf")]
             #[tedge_config(deprecated_key = "mqtt.external.keyfile")]
             key_file: Utf8PathBuf,
+        },
+
+        bridge: {
+            /// Connect the clouds using the bridge built into the mappers instead of the mosquitto bridge
+            #[tedge_config(note = "This is required when the local MQTT broker is not mosquitto or cannot be configured by thin-edge.")]
+            #[tedge_config(example = "true", default(value = false))]
+            built_in: bool,
+
+            queue: {
+                /// Buffer on disk the messages published by the mappers to the cloud while the bridge is down
+                #[tedge_config(note = "The queued messages are sent on reconnect, alarms and operation status first, measurements last.")]
+                #[tedge_config(example = "true", default(value = false))]
+                enable: bool,
+
+                /// The maximum size in bytes of the messages buffered while the bridge is down
+                #[tedge_config(note = "When the queue is full, the oldest messages with the lowest priority are dropped.")]
+                #[tedge_config(example = "10485760", default(value = 10485760_u64))]
+                max_size: u64,
+
+                /// The time in seconds after which the messages buffered while the bridge is down are dropped
+                #[tedge_config(example = "86400", default(value = 86400_u64))]
+                max_age: Seconds,
+            },
         }
     },
 
@@ -629,6 +759,7 @@ define_tedge_config! {
                 cert_file: Utf8PathBuf,
 
                 /// Path to the private key which is used by the agent when connecting to external services
+                #[tedge_config(note = "This can also be the PKCS#11 URI of a key stored on a token, e.g. `pkcs11:token=tedge;object=device-key?module-path=/usr/lib/softhsm/libsofthsm2.so`.")]
                 #[doku(as = "PathBuf")]
                 #[tedge_config(reader(private))]
                 key_file: Utf8PathBuf,
@@ -746,6 +877,13 @@ define_tedge_config! {
         maintainer: String,
     },
 
+    container: {
+        /// The container engine CLI used by the container software plugin: `docker`, `podman` or the path to a compatible CLI.
+        /// When not set, the first of `docker` and `podman` found in the path is used.
+        #[tedge_config(example = "podman", example = "/usr/bin/docker")]
+        runtime: String,
+    },
+
     enable: {
         /// Determines if thin-edge should use `sudo` when attempting to write to files possibly
         /// not owned by `tedge`.
@@ -806,6 +944,13 @@ fn default_device_cert(location: &TEdgeConfigLocation) -> Utf8PathBuf {
         .join("tedge-certificate.pem")
 }
 
+fn default_device_csr(location: &TEdgeConfigLocation) -> Utf8PathBuf {
+    location
+        .tedge_config_root_path()
+        .join("device-certs")
+        .join("tedge.csr")
+}
+
 fn default_mqtt_port() -> NonZeroU16 {
     NonZeroU16::try_from(1883).unwrap()
 }
@@ -887,8 +1032,22 @@ pub struct MqttAuthClientConfig {
     pub key_file: Utf8PathBuf,
 }
 
+impl TEdgeConfigReaderDevice {
+    /// The location of the device private key, `device.key_uri` if set, `device.key_path` otherwise
+    pub fn key_uri(&self) -> Result<KeyUri, CertificateError> {
+        match self.key_uri.or_none() {
+            Some(uri) => uri.parse(),
+            None => Ok(KeyUri::File(self.key_path.clone().into())),
+        }
+    }
+}
+
 impl TEdgeConfigReaderHttpClientAuth {
-    pub fn identity(&self) -> anyhow::Result<Option<reqwest::Identity>> {
+    /// The TLS config of the HTTP clients authenticated by the device certificate, if configured
+    ///
+    /// The private key can be stored in a PEM file or on a PKCS#11 token,
+    /// the TLS handshakes being then signed by the token.
+    pub fn identity(&self) -> anyhow::Result<Option<rustls::ClientConfig>> {
         use ReadableKey::*;
 
         let client_cert_key =
@@ -897,17 +1056,14 @@ impl TEdgeConfigReaderHttpClientAuth {
 
         Ok(match client_cert_key {
             Some((cert, key)) => {
-                let mut pem = std::fs::read(key).with_context(|| {
-                    format!("reading private key (from {HttpClientAuthKeyFile}): {key}")
+                let key_uri = key.as_str().parse::<KeyUri>().map_err(|err| {
+                    anyhow!("invalid private key (from {HttpClientAuthKeyFile}): {err}")
                 })?;
-                let mut cert_file = std::fs::File::open(cert).with_context(|| {
-                    format!("opening certificate (from {HttpClientAuthCertFile}): {cert}")
-                })?;
-                cert_file.read_to_end(&mut pem).with_context(|| {
-                    format!("reading certificate (from {HttpClientAuthCertFile}): {cert}")
+                let identity = TlsIdentity::load(cert, &key_uri).with_context(|| {
+                    format!("loading certificate (from {HttpClientAuthCertFile}): {cert} and private key (from {HttpClientAuthKeyFile}): {key_uri}")
                 })?;
 
-                Some(reqwest::Identity::from_pem(&pem)?)
+                Some(create_tls_config_with_native_roots(identity)?)
             }
             None => None,
         })
@@ -970,4 +1126,67 @@ mod tests {
 
         assert_eq!(reader.c8y.http.key(), "c8y.url");
     }
+
+    #[test]
+    fn device_key_uri_defaults_to_the_key_path() {
+        let mut dto = TEdgeConfigDto::default();
+        let reader = TEdgeConfigReader::from_dto(&dto, &TEdgeConfigLocation::default());
+        assert_eq!(
+            reader.device.key_uri().unwrap(),
+            KeyUri::File("/etc/tedge/device-certs/tedge-private-key.pem".into())
+        );
+
+        dto.device.key_uri = Some("pkcs11:token=tedge;object=device-key".to_string());
+        let reader = TEdgeConfigReader::from_dto(&dto, &TEdgeConfigLocation::default());
+        assert!(matches!(
+            reader.device.key_uri().unwrap(),
+            KeyUri::Pkcs11(_)
+        ));
+    }
+
+    #[test]
+    fn http_client_identity_is_built_from_a_pem_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert_file, key_file) = create_test_certificate(dir.path());
+        let mut dto = TEdgeConfigDto::default();
+        dto.http.client.auth.cert_file = Some(cert_file);
+        dto.http.client.auth.key_file = Some(key_file);
+        let reader = TEdgeConfigReader::from_dto(&dto, &TEdgeConfigLocation::default());
+
+        let tls_config = reader.http.client.auth.identity().unwrap().unwrap();
+        assert!(tls_config.client_auth_cert_resolver.has_certs());
+    }
+
+    #[test]
+    fn http_client_identity_can_use_a_pkcs11_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert_file, _) = create_test_certificate(dir.path());
+        let mut dto = TEdgeConfigDto::default();
+        dto.http.client.auth.cert_file = Some(cert_file);
+        dto.http.client.auth.key_file = Some(
+            "pkcs11:token=tedge;object=device-key?module-path=/non/existent/libpkcs11.so".into(),
+        );
+        let reader = TEdgeConfigReader::from_dto(&dto, &TEdgeConfigLocation::default());
+
+        // The key is looked up on the token, which module cannot be loaded here
+        let err = reader.http.client.auth.identity().unwrap_err();
+        assert!(
+            format!("{err:#}").contains("cannot load the PKCS#11 module"),
+            "{err:#}"
+        );
+    }
+
+    fn create_test_certificate(dir: &std::path::Path) -> (Utf8PathBuf, Utf8PathBuf) {
+        let cert = certificate::KeyCertPair::new_selfsigned_certificate(
+            &certificate::NewCertificateConfig::default(),
+            "my-device",
+            &certificate::KeyKind::New,
+        )
+        .unwrap();
+        let cert_file = Utf8PathBuf::try_from(dir.join("cert.pem")).unwrap();
+        let key_file = Utf8PathBuf::try_from(dir.join("key.pem")).unwrap();
+        std::fs::write(&cert_file, cert.certificate_pem_string().unwrap()).unwrap();
+        std::fs::write(&key_file, cert.private_key_pem_string().unwrap().as_bytes()).unwrap();
+        (cert_file, key_file)
+    }
 }
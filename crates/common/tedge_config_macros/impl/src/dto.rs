@@ -10,6 +10,7 @@ pub fn generate(
     name: proc_macro2::Ident,
     items: &[FieldOrGroup],
     doc_comment: &str,
+    with_profiles: bool,
 ) -> TokenStream {
     let mut idents = Vec::new();
     let mut tys = Vec::<syn::Type>::new();
@@ -37,7 +38,12 @@ pub fn generate(
                     let is_default = format!("{sub_dto_name}::is_default");
                     idents.push(&group.ident);
                     tys.push(parse_quote_spanned!(group.ident.span()=> #sub_dto_name));
-                    sub_dtos.push(Some(generate(sub_dto_name, &group.contents, "")));
+                    sub_dtos.push(Some(generate(
+                        sub_dto_name,
+                        &group.contents,
+                        "",
+                        group.profiles,
+                    )));
                     preserved_attrs.push(group.attrs.iter().filter(is_preserved).collect());
                     extra_attrs.push(quote! {
                         #[serde(default)]
@@ -48,8 +54,17 @@ pub fn generate(
         }
     }
 
+    let profiles = with_profiles.then(|| {
+        quote! {
+            /// Named profiles, each one with its own settings for this group
+            #[serde(default)]
+            #[serde(skip_serializing_if = "::std::collections::BTreeMap::is_empty")]
+            pub profiles: ::std::collections::BTreeMap<String, Self>,
+        }
+    });
+
     quote! {
-        #[derive(Debug, Default, ::serde::Deserialize, ::serde::Serialize, PartialEq)]
+        #[derive(Clone, Debug, Default, ::serde::Deserialize, ::serde::Serialize, PartialEq)]
         // We will add more configurations in the future, so this is
         // non_exhaustive (see
         // https://doc.rust-lang.org/reference/attributes/type_system.html)
@@ -63,6 +78,7 @@ pub fn generate(
                 #extra_attrs
                 pub #idents: #tys,
             )*
+            #profiles
         }
 
         impl #name {
@@ -38,6 +38,8 @@ pub struct ConfigurationAttributes {
     pub deprecated_names: Vec<SpannedValue<String>>,
     #[darling(default)]
     pub rename: Option<SpannedValue<String>>,
+    #[darling(default)]
+    pub profiles: bool,
 }
 
 #[derive(Debug)]
@@ -47,6 +49,7 @@ pub struct ConfigurationGroup {
     pub reader: ReaderSettings,
     pub deprecated_names: Vec<SpannedValue<String>>,
     pub rename: Option<SpannedValue<String>>,
+    pub profiles: bool,
     pub ident: syn::Ident,
     pub colon_token: Token![:],
     pub brace: syn::token::Brace,
@@ -64,6 +67,7 @@ impl Parse for ConfigurationGroup {
             reader: known_attributes.reader,
             deprecated_names: known_attributes.deprecated_names,
             rename: known_attributes.rename,
+            profiles: known_attributes.profiles,
             ident: input.parse()?,
             colon_token: input.parse()?,
             brace: syn::braced!(content in input),
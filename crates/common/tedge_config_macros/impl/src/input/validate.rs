@@ -45,6 +45,7 @@ pub struct ConfigurationGroup {
     pub rename: Option<SpannedValue<String>>,
     pub dto: GroupDtoSettings,
     pub reader: ReaderSettings,
+    pub profiles: bool,
     pub ident: syn::Ident,
     pub contents: Vec<FieldOrGroup>,
 }
@@ -81,6 +82,7 @@ impl TryFrom<super::parse::ConfigurationGroup> for ConfigurationGroup {
             rename: value.rename,
             dto: value.dto,
             reader: value.reader,
+            profiles: value.profiles,
             ident: value.ident,
             contents: combine_errors(value.content.into_iter().map(<_>::try_from))?,
         })
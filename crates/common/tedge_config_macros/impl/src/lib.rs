@@ -81,6 +81,7 @@ pub fn generate_configuration(tokens: TokenStream) -> Result<TokenStream, syn::E
         proc_macro2::Ident::new("TEdgeConfigDto", Span::call_site()),
         &input.groups,
         &dto_doc_comment,
+        false,
     );
 
     let reader_doc_comment = "A struct to read configured values from, designed to be accessed only
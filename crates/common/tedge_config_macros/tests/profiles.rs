@@ -0,0 +1,71 @@
+use tedge_config_macros::*;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReadError {
+    #[error(transparent)]
+    ConfigNotSet(#[from] ConfigNotSet),
+}
+
+define_tedge_config! {
+    #[tedge_config(profiles)]
+    c8y: {
+        url: String,
+        smartrest: {
+            templates: String,
+        }
+    },
+    device: {
+        id: String,
+    }
+}
+
+#[test]
+fn profiles_are_deserialized_with_the_group() {
+    let dto: TEdgeConfigDto = toml::from_str(
+        r#"
+        [c8y]
+        url = "prod.example.com"
+
+        [c8y.profiles.staging]
+        url = "staging.example.com"
+        smartrest.templates = "staging-template"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(dto.c8y.url.as_deref(), Some("prod.example.com"));
+    let staging = &dto.c8y.profiles["staging"];
+    assert_eq!(staging.url.as_deref(), Some("staging.example.com"));
+    assert_eq!(
+        staging.smartrest.templates.as_deref(),
+        Some("staging-template")
+    );
+}
+
+#[test]
+fn empty_profiles_are_not_serialized() {
+    let mut dto = TEdgeConfigDto::default();
+    dto.c8y.url = Some("prod.example.com".into());
+
+    let toml = toml::to_string(&dto).unwrap();
+
+    assert!(!toml.contains("profiles"), "{toml}");
+}
+
+#[test]
+fn a_reader_can_be_built_for_a_profile() {
+    let mut dto = TEdgeConfigDto::default();
+    dto.c8y.url = Some("prod.example.com".into());
+    let mut staging = dto.c8y.clone();
+    staging.url = Some("staging.example.com".into());
+    dto.c8y.profiles.insert("staging".into(), staging);
+
+    let mut profile_dto = dto.clone();
+    profile_dto.c8y = dto.c8y.profiles["staging"].clone();
+    let reader = TEdgeConfigReader::from_dto(&profile_dto, &TEdgeConfigLocation);
+
+    assert_eq!(
+        reader.c8y.url.or_none().map(String::as_str),
+        Some("staging.example.com")
+    );
+}
@@ -346,6 +346,49 @@ pub fn overwrite_file(file: &Path, content: &str) -> Result<(), FileError> {
     }
 }
 
+/// Create a new file, failing if it already exists, and try to hand it over to the given user and group.
+///
+/// Errors changing the ownership are ignored, the file being then owned by the user running the process.
+pub fn create_new_file_with_user_group(
+    path: impl AsRef<Path>,
+    user: &str,
+    group: &str,
+) -> io::Result<fs::File> {
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path.as_ref())?;
+
+    let _ = change_user_and_group(path.as_ref(), user, group);
+
+    Ok(file)
+}
+
+/// Atomically replace a file with a read-only file owned by the given user and group.
+///
+/// The new content is written aside and synced to disk, before being moved over the previous file, if any.
+pub fn replace_with_read_only_file(
+    path: impl AsRef<Path>,
+    content: &[u8],
+    user: &str,
+    group: &str,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut new_path = path.as_os_str().to_owned();
+    new_path.push(".new");
+    let new_path = PathBuf::from(new_path);
+
+    let _ = fs::remove_file(&new_path);
+    let mut file = create_new_file_with_user_group(&new_path, user, group)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+
+    // Prevent the file to be overwritten
+    crate::paths::set_permission(&file, 0o444)?;
+
+    fs::rename(&new_path, path)
+}
+
 pub fn change_user_and_group(file: &Path, user: &str, group: &str) -> Result<(), FileError> {
     debug!(
         "Changing ownership of file: {:?} with user: {} and group: {}",
@@ -8,7 +8,7 @@ use log::warn;
 use reqwest::header::CONTENT_LENGTH;
 use reqwest::header::CONTENT_TYPE;
 use reqwest::Body;
-use reqwest::Identity;
+use rustls::ClientConfig;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::time::Duration;
@@ -92,11 +92,11 @@ pub enum Auth {
 pub struct Uploader {
     source_filename: Utf8PathBuf,
     backoff: ExponentialBackoff,
-    identity: Option<Identity>,
+    identity: Option<ClientConfig>,
 }
 
 impl Uploader {
-    pub fn new(target_path: Utf8PathBuf, identity: Option<Identity>) -> Self {
+    pub fn new(target_path: Utf8PathBuf, identity: Option<ClientConfig>) -> Self {
         Self {
             source_filename: target_path,
             backoff: default_backoff(),
@@ -137,7 +137,7 @@ impl Uploader {
 
             let mut client = reqwest::Client::builder();
             if let Some(identity) = self.identity.clone() {
-                client = client.identity(identity);
+                client = client.use_preconfigured_tls(identity);
             }
             let client = client
                 .build()
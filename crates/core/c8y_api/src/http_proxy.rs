@@ -13,6 +13,9 @@ use tedge_config::TEdgeConfig;
 use tracing::error;
 use tracing::info;
 
+/// The maximum number of items Cumulocity returns in a single page
+const MAX_PAGE_SIZE: u32 = 2000;
+
 #[derive(thiserror::Error, Debug)]
 pub enum C8yEndPointError {
     #[error("Cumulocity internal id not found for the device: {0}")]
@@ -66,6 +69,35 @@ impl C8yEndPoint {
         url_update_swlist
     }
 
+    pub fn get_url_for_managed_object(&self, internal_id: &str) -> String {
+        let mut url_managed_object = self.get_base_url();
+        url_managed_object.push_str("/inventory/managedObjects/");
+        url_managed_object.push_str(internal_id);
+        url_managed_object
+    }
+
+    pub fn get_url_for_child_devices(&self, internal_id: &str) -> String {
+        let mut url_child_devices = self.get_url_for_managed_object(internal_id);
+        url_child_devices.push_str("/childDevices?pageSize=");
+        url_child_devices.push_str(&MAX_PAGE_SIZE.to_string());
+        url_child_devices
+    }
+
+    pub fn get_url_for_child_additions(&self, internal_id: &str) -> String {
+        let mut url_child_additions = self.get_url_for_managed_object(internal_id);
+        url_child_additions.push_str("/childAdditions?pageSize=");
+        url_child_additions.push_str(&MAX_PAGE_SIZE.to_string());
+        url_child_additions
+    }
+
+    pub fn get_url_for_external_ids(&self, internal_id: &str) -> String {
+        let mut url_external_ids = self.get_base_url();
+        url_external_ids.push_str("/identity/globalIds/");
+        url_external_ids.push_str(internal_id);
+        url_external_ids.push_str("/externalIds");
+        url_external_ids
+    }
+
     pub fn get_url_for_internal_id(&self, device_id: String) -> String {
         let mut url_get_id = self.get_base_url();
         url_get_id.push_str("/identity/externalIds/c8y_Serial/");
@@ -112,6 +144,7 @@ impl C8yEndPoint {
 
 pub struct C8yMqttJwtTokenRetriever {
     mqtt_config: mqtt_channel::Config,
+    topic_prefix: String,
 }
 
 impl C8yMqttJwtTokenRetriever {
@@ -122,12 +155,22 @@ impl C8yMqttJwtTokenRetriever {
     }
 
     pub fn new(mqtt_config: mqtt_channel::Config) -> Self {
-        let topic = TopicFilter::new_unchecked("c8y/s/dat");
+        Self::with_topic_prefix(mqtt_config, "c8y")
+    }
+
+    /// A retriever exchanging with the bridge on `{topic_prefix}/s/uat` and `{topic_prefix}/s/dat`
+    ///
+    /// The prefix is not `c8y` when connected to Cumulocity using a profile, e.g. `c8y@staging`.
+    pub fn with_topic_prefix(mqtt_config: mqtt_channel::Config, topic_prefix: &str) -> Self {
+        let topic = TopicFilter::new_unchecked(&format!("{topic_prefix}/s/dat"));
         let mqtt_config = mqtt_config
             .with_no_session() // Ignore any already published tokens, possibly stale.
             .with_subscriptions(topic);
 
-        C8yMqttJwtTokenRetriever { mqtt_config }
+        C8yMqttJwtTokenRetriever {
+            mqtt_config,
+            topic_prefix: topic_prefix.to_string(),
+        }
     }
 
     pub async fn get_jwt_token(&mut self) -> Result<SmartRestJwtResponse, JwtError> {
@@ -138,8 +181,11 @@ impl C8yMqttJwtTokenRetriever {
             mqtt_con
                 .published
                 .publish(
-                    mqtt_channel::Message::new(&Topic::new_unchecked("c8y/s/uat"), "".to_string())
-                        .with_qos(mqtt_channel::QoS::AtMostOnce),
+                    mqtt_channel::Message::new(
+                        &Topic::new_unchecked(&format!("{}/s/uat", self.topic_prefix)),
+                        "".to_string(),
+                    )
+                    .with_qos(mqtt_channel::QoS::AtMostOnce),
                 )
                 .await?;
             info!("JWT token requested");
@@ -199,6 +245,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn get_url_for_children_returns_correct_addresses() {
+        let c8y = C8yEndPoint::new("test_host", "test_device");
+
+        assert_eq!(
+            c8y.get_url_for_child_devices("12345"),
+            "https://test_host/inventory/managedObjects/12345/childDevices?pageSize=2000"
+        );
+        assert_eq!(
+            c8y.get_url_for_child_additions("12345"),
+            "https://test_host/inventory/managedObjects/12345/childAdditions?pageSize=2000"
+        );
+        assert_eq!(
+            c8y.get_url_for_external_ids("12345"),
+            "https://test_host/identity/globalIds/12345/externalIds"
+        );
+    }
+
     #[test]
     fn get_url_for_sw_list_returns_correct_address() {
         let mut c8y = C8yEndPoint::new("test_host", "test_device");
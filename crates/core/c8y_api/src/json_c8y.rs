@@ -72,6 +72,58 @@ impl InternalIdResponse {
     }
 }
 
+/// The references to the child devices or the child additions of a managed object
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct C8yManagedObjectReferences {
+    #[serde(default)]
+    pub references: Vec<C8yManagedObjectReference>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct C8yManagedObjectReference {
+    pub managed_object: C8yManagedObject,
+}
+
+impl C8yManagedObjectReferences {
+    pub fn new(internal_ids: &[&str]) -> Self {
+        let references = internal_ids
+            .iter()
+            .map(|id| C8yManagedObjectReference {
+                managed_object: C8yManagedObject { id: id.to_string() },
+            })
+            .collect();
+        C8yManagedObjectReferences { references }
+    }
+}
+
+/// The external ids of a managed object
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct C8yExternalIds {
+    #[serde(default)]
+    pub external_ids: Vec<C8yExternalId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct C8yExternalId {
+    pub external_id: String,
+    #[serde(rename = "type")]
+    pub id_type: String,
+}
+
+impl C8yExternalIds {
+    /// The `c8y_Serial` external id, used by thin-edge to identify the devices and services
+    pub fn serial(&self) -> Option<&str> {
+        self.external_ids
+            .iter()
+            .find(|id| id.id_type == "c8y_Serial")
+            .map(|id| id.external_id.as_str())
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Eq, PartialEq)]
 pub struct C8ySoftwareModuleItem {
     pub name: String,
@@ -378,6 +430,43 @@ impl fmt::Display for AlarmSeverity {
     }
 }
 
+/// The status of a Cumulocity operation, as updated with JSON over MQTT
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(tag = "status", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum C8yOperationStatus {
+    Executing,
+    Successful,
+    Failed {
+        #[serde(rename = "failureReason")]
+        failure_reason: String,
+    },
+}
+
+impl<'a> Jsonify<'a> for C8yOperationStatus {}
+
+impl C8yOperationStatus {
+    /// Extract the operation name and status from a SmartREST `501`, `502` or `503` message
+    ///
+    /// Any result attached to a `503` message is ignored.
+    pub fn from_smartrest(smartrest: &str) -> Option<(String, Self)> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(smartrest.as_bytes());
+        let record = rdr.records().next()?.ok()?;
+        let operation = record.get(1)?.to_string();
+        let status = match record.get(0)? {
+            "501" => C8yOperationStatus::Executing,
+            "502" => C8yOperationStatus::Failed {
+                failure_reason: record.get(2).unwrap_or_default().to_string(),
+            },
+            "503" => C8yOperationStatus::Successful,
+            _ => return None,
+        };
+        Some((operation, status))
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum C8yAlarmError {
     #[error("Unsupported alarm severity in topic: {0}")]
@@ -839,4 +928,43 @@ mod tests {
         }
         Ok(id.into())
     }
+
+    #[test]
+    fn operation_status_from_smartrest() {
+        use super::C8yOperationStatus;
+
+        assert_eq!(
+            C8yOperationStatus::from_smartrest("501,c8y_Restart"),
+            Some(("c8y_Restart".to_string(), C8yOperationStatus::Executing))
+        );
+        assert_eq!(
+            C8yOperationStatus::from_smartrest("503,c8y_LogfileRequest,http://some/url"),
+            Some((
+                "c8y_LogfileRequest".to_string(),
+                C8yOperationStatus::Successful
+            ))
+        );
+        assert_eq!(
+            C8yOperationStatus::from_smartrest(r#"502,c8y_Firmware,"Failed, badly""#),
+            Some((
+                "c8y_Firmware".to_string(),
+                C8yOperationStatus::Failed {
+                    failure_reason: "Failed, badly".to_string()
+                }
+            ))
+        );
+        assert_eq!(C8yOperationStatus::from_smartrest("114,c8y_Restart"), None);
+
+        assert_eq!(
+            C8yOperationStatus::Failed {
+                failure_reason: "oops".to_string()
+            }
+            .to_json(),
+            r#"{"status":"FAILED","failureReason":"oops"}"#
+        );
+        assert_eq!(
+            C8yOperationStatus::Executing.to_json(),
+            r#"{"status":"EXECUTING"}"#
+        );
+    }
 }
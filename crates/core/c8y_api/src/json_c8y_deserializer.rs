@@ -0,0 +1,394 @@
+//! Cumulocity operations delivered as JSON over MQTT
+//!
+//! When `c8y.operations.protocol` is set to `json`, Cumulocity publishes the operations
+//! on the `devicecontrol/notifications` topic, using the same JSON representation as the REST API:
+//!
+//! ```json
+//! {
+//!     "id": "123",
+//!     "status": "PENDING",
+//!     "externalSource": { "externalId": "device-id", "type": "c8y_Serial" },
+//!     "c8y_Restart": {}
+//! }
+//! ```
+//!
+//! The fragments of such an operation are translated into the requests
+//! built from SmartREST messages, so the mapper processes both the same way.
+use crate::smartrest::smartrest_deserializer::to_datetime;
+use crate::smartrest::smartrest_deserializer::SmartRestConfigDownloadRequest;
+use crate::smartrest::smartrest_deserializer::SmartRestConfigUploadRequest;
+use crate::smartrest::smartrest_deserializer::SmartRestFirmwareRequest;
+use crate::smartrest::smartrest_deserializer::SmartRestLogRequest;
+use crate::smartrest::smartrest_deserializer::SmartRestRestartRequest;
+use crate::smartrest::smartrest_deserializer::SmartRestUpdateSoftware;
+use crate::smartrest::smartrest_deserializer::SmartRestUpdateSoftwareModule;
+use crate::smartrest::smartrest_serializer::CumulocitySupportedOperations;
+use serde::Deserialize;
+use serde_json::Map;
+use serde_json::Value;
+use time::OffsetDateTime;
+
+/// The local topic on which Cumulocity operations are received as JSON
+pub const C8Y_JSON_OPERATIONS_TOPIC: &str = "c8y/devicecontrol/notifications";
+
+/// The local topic prefix on which the status of an operation is updated as JSON
+pub const C8Y_JSON_OPERATION_UPDATE_TOPIC: &str = "c8y/devicecontrol/operations/update";
+
+/// A Cumulocity operation, as delivered on the `devicecontrol/notifications` topic
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct C8yDeviceControlOperation {
+    /// The Cumulocity operation id, used to update the operation status
+    pub id: String,
+
+    /// The device targeted by this operation
+    pub external_source: C8yExternalSource,
+
+    /// All the other properties, among which the operation fragment
+    #[serde(flatten)]
+    pub fragments: Map<String, Value>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct C8yExternalSource {
+    pub external_id: String,
+    #[serde(rename = "type")]
+    pub source_type: String,
+}
+
+/// An operation natively supported by the mapper
+#[derive(Debug, PartialEq, Eq)]
+pub enum C8yDeviceControlRequest {
+    Restart(SmartRestRestartRequest),
+    SoftwareUpdate(SmartRestUpdateSoftware),
+    LogfileRequest(SmartRestLogRequest),
+    UploadConfigFile(SmartRestConfigUploadRequest),
+    DownloadConfigFile(SmartRestConfigDownloadRequest),
+    Firmware(SmartRestFirmwareRequest),
+}
+
+impl C8yDeviceControlRequest {
+    pub fn operation(&self) -> CumulocitySupportedOperations {
+        match self {
+            C8yDeviceControlRequest::Restart(_) => CumulocitySupportedOperations::C8yRestartRequest,
+            C8yDeviceControlRequest::SoftwareUpdate(_) => {
+                CumulocitySupportedOperations::C8ySoftwareUpdate
+            }
+            C8yDeviceControlRequest::LogfileRequest(_) => {
+                CumulocitySupportedOperations::C8yLogFileRequest
+            }
+            C8yDeviceControlRequest::UploadConfigFile(_) => {
+                CumulocitySupportedOperations::C8yUploadConfigFile
+            }
+            C8yDeviceControlRequest::DownloadConfigFile(_) => {
+                CumulocitySupportedOperations::C8yDownloadConfigFile
+            }
+            C8yDeviceControlRequest::Firmware(_) => CumulocitySupportedOperations::C8yFirmware,
+        }
+    }
+}
+
+impl C8yDeviceControlOperation {
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// The external id of the device targeted by this operation
+    pub fn device(&self) -> &str {
+        &self.external_source.external_id
+    }
+
+    /// The fragment with the given name, if any
+    pub fn fragment(&self, name: &str) -> Option<&Value> {
+        self.fragments.get(name)
+    }
+
+    /// Extract the request of an operation natively supported by the mapper
+    ///
+    /// Return `Ok(None)` if this operation has none of the supported operation fragments.
+    pub fn request(&self) -> Result<Option<C8yDeviceControlRequest>, serde_json::Error> {
+        let device = self.device().to_string();
+
+        if self.fragment("c8y_Restart").is_some() {
+            return Ok(Some(C8yDeviceControlRequest::Restart(
+                SmartRestRestartRequest {
+                    message_id: "510".into(),
+                    device,
+                },
+            )));
+        }
+
+        if let Some(modules) = self.fragment_as::<Vec<C8ySoftwareModule>>("c8y_SoftwareUpdate")? {
+            return Ok(Some(C8yDeviceControlRequest::SoftwareUpdate(
+                SmartRestUpdateSoftware {
+                    message_id: "528".into(),
+                    external_id: device,
+                    update_list: modules.into_iter().map(|module| module.into()).collect(),
+                },
+            )));
+        }
+
+        if let Some(request) = self.fragment_as::<C8yLogfileRequest>("c8y_LogfileRequest")? {
+            return Ok(Some(C8yDeviceControlRequest::LogfileRequest(
+                SmartRestLogRequest {
+                    message_id: "522".into(),
+                    device,
+                    log_type: request.log_file,
+                    date_from: request.date_from,
+                    date_to: request.date_to,
+                    search_text: request.search_text.filter(|text| !text.is_empty()),
+                    lines: request.maximum_lines,
+                },
+            )));
+        }
+
+        if let Some(request) = self.fragment_as::<C8yConfigFile>("c8y_UploadConfigFile")? {
+            return Ok(Some(C8yDeviceControlRequest::UploadConfigFile(
+                SmartRestConfigUploadRequest {
+                    message_id: "526".into(),
+                    device,
+                    config_type: request.config_type,
+                },
+            )));
+        }
+
+        if let Some(request) = self.fragment_as::<C8yConfigFile>("c8y_DownloadConfigFile")? {
+            return Ok(Some(C8yDeviceControlRequest::DownloadConfigFile(
+                SmartRestConfigDownloadRequest {
+                    message_id: "524".into(),
+                    device,
+                    url: request.url.unwrap_or_default(),
+                    config_type: request.config_type,
+                },
+            )));
+        }
+
+        if let Some(request) = self.fragment_as::<C8yFirmware>("c8y_Firmware")? {
+            return Ok(Some(C8yDeviceControlRequest::Firmware(
+                SmartRestFirmwareRequest {
+                    message_id: "515".into(),
+                    device,
+                    name: request.name,
+                    version: request.version,
+                    url: request.url,
+                },
+            )));
+        }
+
+        Ok(None)
+    }
+
+    fn fragment_as<T: serde::de::DeserializeOwned>(
+        &self,
+        name: &str,
+    ) -> Result<Option<T>, serde_json::Error> {
+        self.fragment(name)
+            .map(|fragment| serde_json::from_value(fragment.clone()))
+            .transpose()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct C8ySoftwareModule {
+    name: String,
+    version: Option<String>,
+    url: Option<String>,
+    action: String,
+    software_type: Option<String>,
+}
+
+impl From<C8ySoftwareModule> for SmartRestUpdateSoftwareModule {
+    fn from(module: C8ySoftwareModule) -> Self {
+        // The software type is given along the version as with SmartREST: `version::type`
+        let version = match (module.version, module.software_type) {
+            (Some(version), Some(software_type))
+                if !version.contains("::") && !software_type.is_empty() =>
+            {
+                Some(format!("{version}::{software_type}"))
+            }
+            (None, Some(software_type)) if !software_type.is_empty() => {
+                Some(format!("::{software_type}"))
+            }
+            (version, _) => version,
+        };
+        SmartRestUpdateSoftwareModule {
+            software: module.name,
+            version,
+            url: module.url.filter(|url| !url.trim().is_empty()),
+            action: module.action,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct C8yLogfileRequest {
+    log_file: String,
+    #[serde(deserialize_with = "to_datetime")]
+    date_from: OffsetDateTime,
+    #[serde(deserialize_with = "to_datetime")]
+    date_to: OffsetDateTime,
+    search_text: Option<String>,
+    maximum_lines: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct C8yConfigFile {
+    #[serde(rename = "type")]
+    config_type: String,
+    url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct C8yFirmware {
+    name: String,
+    version: String,
+    url: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use time::macros::datetime;
+
+    fn c8y_operation(fragments: Value) -> C8yDeviceControlOperation {
+        let mut operation = json!({
+            "id": "123",
+            "status": "PENDING",
+            "externalSource": { "externalId": "test-device", "type": "c8y_Serial" },
+        });
+        operation
+            .as_object_mut()
+            .unwrap()
+            .extend(fragments.as_object().unwrap().clone());
+        C8yDeviceControlOperation::from_json(&operation.to_string()).unwrap()
+    }
+
+    #[test]
+    fn deserialize_restart_operation() {
+        let operation = c8y_operation(json!({"c8y_Restart": {}}));
+
+        assert_eq!(operation.id, "123");
+        assert_eq!(operation.device(), "test-device");
+        assert_eq!(
+            operation.request().unwrap(),
+            Some(C8yDeviceControlRequest::Restart(SmartRestRestartRequest {
+                message_id: "510".into(),
+                device: "test-device".into(),
+            }))
+        );
+    }
+
+    #[test]
+    fn deserialize_software_update_operation() {
+        let operation = c8y_operation(json!({
+            "c8y_SoftwareUpdate": [
+                {"name": "nodered", "version": "1.0.0", "softwareType": "debian", "url": " ", "action": "install"},
+                {"name": "collectd", "version": "5.7::debian", "action": "delete"},
+            ]
+        }));
+
+        assert_eq!(
+            operation.request().unwrap(),
+            Some(C8yDeviceControlRequest::SoftwareUpdate(
+                SmartRestUpdateSoftware {
+                    message_id: "528".into(),
+                    external_id: "test-device".into(),
+                    update_list: vec![
+                        SmartRestUpdateSoftwareModule {
+                            software: "nodered".into(),
+                            version: Some("1.0.0::debian".into()),
+                            url: None,
+                            action: "install".into(),
+                        },
+                        SmartRestUpdateSoftwareModule {
+                            software: "collectd".into(),
+                            version: Some("5.7::debian".into()),
+                            url: None,
+                            action: "delete".into(),
+                        },
+                    ],
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn deserialize_log_file_request_operation() {
+        let operation = c8y_operation(json!({
+            "c8y_LogfileRequest": {
+                "logFile": "software-management",
+                "dateFrom": "2023-10-13T16:58:38+0100",
+                "dateTo": "2023-10-14T16:58:38+0100",
+                "searchText": "",
+                "maximumLines": 1000
+            }
+        }));
+
+        assert_eq!(
+            operation.request().unwrap(),
+            Some(C8yDeviceControlRequest::LogfileRequest(
+                SmartRestLogRequest {
+                    message_id: "522".into(),
+                    device: "test-device".into(),
+                    log_type: "software-management".into(),
+                    date_from: datetime!(2023-10-13 16:58:38 +01:00),
+                    date_to: datetime!(2023-10-14 16:58:38 +01:00),
+                    search_text: None,
+                    lines: 1000,
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn deserialize_config_operations() {
+        let operation = c8y_operation(json!({
+            "c8y_DownloadConfigFile": {"type": "mosquitto", "url": "http://www.my.url"}
+        }));
+        assert_eq!(
+            operation.request().unwrap(),
+            Some(C8yDeviceControlRequest::DownloadConfigFile(
+                SmartRestConfigDownloadRequest {
+                    message_id: "524".into(),
+                    device: "test-device".into(),
+                    url: "http://www.my.url".into(),
+                    config_type: "mosquitto".into(),
+                }
+            ))
+        );
+
+        let operation = c8y_operation(json!({"c8y_UploadConfigFile": {"type": "mosquitto"}}));
+        assert_eq!(
+            operation.request().unwrap(),
+            Some(C8yDeviceControlRequest::UploadConfigFile(
+                SmartRestConfigUploadRequest {
+                    message_id: "526".into(),
+                    device: "test-device".into(),
+                    config_type: "mosquitto".into(),
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn unknown_operations_have_no_request() {
+        let operation = c8y_operation(json!({"c8y_Custom": {"text": "hello"}}));
+
+        assert_eq!(operation.request().unwrap(), None);
+        assert_eq!(
+            operation.fragment("c8y_Custom"),
+            Some(&json!({"text": "hello"}))
+        );
+    }
+
+    #[test]
+    fn invalid_fragments_are_rejected() {
+        let operation = c8y_operation(json!({"c8y_Firmware": {"name": "core-image"}}));
+
+        assert!(operation.request().is_err());
+    }
+}
@@ -1,5 +1,6 @@
 pub mod http_proxy;
 pub mod json_c8y;
+pub mod json_c8y_deserializer;
 pub mod smartrest;
 pub mod utils;
 
@@ -37,6 +37,9 @@ pub enum SmartRestDeserializerError {
         hint: String,
     },
 
+    #[error("The SmartREST request for {operation} has no value for the field {field}.")]
+    MissingField { operation: String, field: String },
+
     #[error("Empty request")]
     EmptyRequest,
 
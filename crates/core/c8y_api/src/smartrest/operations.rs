@@ -5,6 +5,7 @@ use std::path::Path;
 use std::path::PathBuf;
 
 use crate::smartrest::error::OperationsError;
+use crate::smartrest::error::SmartRestDeserializerError;
 use crate::smartrest::smartrest_serializer::declare_supported_operations;
 use serde::Deserialize;
 use serde::Deserializer;
@@ -39,6 +40,65 @@ pub struct OnMessageExec {
     pub graceful_timeout: Duration,
     #[serde(default = "default_forceful_timeout", deserialize_with = "to_duration")]
     pub forceful_timeout: Duration,
+    workflow: Option<WorkflowMapping>,
+}
+
+/// Maps a SmartREST operation request onto a thin-edge command, in place of an `OnMessageExec` command
+///
+/// ```toml
+/// [exec]
+/// topic = "c8y/s/dc/custom-template"
+/// on_message = "dm101"
+///
+/// [exec.workflow]
+/// operation = "set_led"
+/// fields = ["color", "duration"]
+/// ```
+///
+/// Given the SmartREST message `dm101,device-id,red,10`,
+/// the mapper publishes the command `{"status":"init","color":"red","duration":"10"}`
+/// on the `te/<device>/cmd/set_led/<id>` topic,
+/// then translates the terminal states of this command into `501`/`502`/`503` responses.
+#[derive(Debug, Clone, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct WorkflowMapping {
+    /// The thin-edge operation, i.e. the `<operation>` of `te/<entity>/cmd/<operation>/<id>`
+    pub operation: String,
+
+    /// The names of the SmartREST fields following the message id and the device id
+    #[serde(default)]
+    pub fields: Vec<String>,
+}
+
+impl WorkflowMapping {
+    /// Build the init payload of the thin-edge command from a SmartREST operation request
+    pub fn command_payload(
+        &self,
+        smartrest: &str,
+    ) -> Result<serde_json::Value, SmartRestDeserializerError> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(smartrest.as_bytes());
+        let record = match rdr.records().next() {
+            Some(record) => record?,
+            None => return Err(SmartRestDeserializerError::EmptyRequest),
+        };
+
+        let mut payload = serde_json::Map::new();
+        payload.insert("status".to_string(), "init".into());
+        for (i, field) in self.fields.iter().enumerate() {
+            let value =
+                record
+                    .get(i + 2)
+                    .ok_or_else(|| SmartRestDeserializerError::MissingField {
+                        operation: self.operation.clone(),
+                        field: field.clone(),
+                    })?;
+            payload.insert(field.clone(), value.into());
+        }
+        Ok(payload.into())
+    }
 }
 
 fn to_result_format<'de, D>(deserializer: D) -> Result<ResultFormat, D::Error>
@@ -103,6 +163,10 @@ impl Operation {
             .unwrap_or_default()
     }
 
+    pub fn workflow(&self) -> Option<&WorkflowMapping> {
+        self.exec().and_then(|exec| exec.workflow.as_ref())
+    }
+
     pub fn template(&self) -> Option<String> {
         self.exec().and_then(|exec| exec.on_message.clone())
     }
@@ -164,6 +228,18 @@ impl Operations {
         None
     }
 
+    pub fn get_operation_by_name(&self, name: &str) -> Option<&Operation> {
+        self.operations.iter().find(|op| op.name == name)
+    }
+
+    /// The operation mapped onto the given thin-edge operation, if any
+    pub fn matching_workflow(&self, tedge_operation: &str) -> Option<&Operation> {
+        self.operations.iter().find(|op| {
+            op.workflow()
+                .map_or(false, |workflow| workflow.operation == tedge_operation)
+        })
+    }
+
     pub fn topics_for_operations(&self) -> HashSet<String> {
         self.operations
             .iter()
@@ -373,4 +449,51 @@ mod tests {
         let result = toml::from_str::<OnMessageExec>(r#"result_format = "foo""#);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn deserialize_workflow_mapping() {
+        let operation: Operation = toml::from_str(
+            r#"
+            [exec]
+            topic = "c8y/s/dc/custom-template"
+            on_message = "dm101"
+
+            [exec.workflow]
+            operation = "set_led"
+            fields = ["color", "duration"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(operation.command(), None);
+        assert_eq!(
+            operation.workflow(),
+            Some(&WorkflowMapping {
+                operation: "set_led".to_string(),
+                fields: vec!["color".to_string(), "duration".to_string()],
+            })
+        );
+    }
+
+    #[test]
+    fn workflow_mapping_builds_command_payload_from_smartrest_fields() {
+        let workflow = WorkflowMapping {
+            operation: "set_led".to_string(),
+            fields: vec!["color".to_string(), "pattern".to_string()],
+        };
+
+        let payload = workflow
+            .command_payload(r#"dm101,device-id,red,"on,off",ignored"#)
+            .unwrap();
+        assert_eq!(
+            payload,
+            serde_json::json!({"status": "init", "color": "red", "pattern": "on,off"})
+        );
+
+        let error = workflow.command_payload("dm101,device-id,red").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The SmartREST request for set_led has no value for the field pattern."
+        );
+    }
 }
@@ -25,13 +25,13 @@ pub fn fail_operation(operation: impl C8yOperation, reason: &str) -> String {
 }
 
 /// Generates a SmartREST message to set the provided operation to successful without a payload
-pub fn succeed_operation_no_payload(operation: CumulocitySupportedOperations) -> String {
+pub fn succeed_operation_no_payload(operation: impl C8yOperation) -> String {
     succeed_static_operation(operation, None::<&str>)
 }
 
 /// Generates a SmartREST message to set the provided operation to successful with an optional payload
 pub fn succeed_static_operation(
-    operation: CumulocitySupportedOperations,
+    operation: impl C8yOperation,
     payload: Option<impl AsRef<str>>,
 ) -> String {
     let mut wtr = csv::Writer::from_writer(vec![]);
@@ -1,4 +1,6 @@
 pub mod log_file;
 pub mod operation_logs;
 pub mod plugin;
+pub mod plugin_api;
 pub mod plugin_manager;
+pub mod update_plan;
@@ -1,17 +1,23 @@
+use crate::plugin_api::PluginApiVersion;
+use crate::plugin_api::PluginMessage;
+use crate::plugin_api::PluginModuleError;
 use async_trait::async_trait;
 use csv::ReaderBuilder;
 use download::Downloader;
 use logged_command::LoggedCommand;
-use reqwest::Identity;
+use logged_command::LoggingChild;
+use rustls::ClientConfig;
 use serde::Deserialize;
 use std::error::Error;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::Output;
+use tedge_api::messages::SoftwareUpdateProgress;
 use tedge_api::*;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use tokio::io::BufWriter;
+use tokio::sync::mpsc::UnboundedSender;
 use tracing::error;
 #[async_trait]
 pub trait Plugin {
@@ -77,7 +83,7 @@ pub trait Plugin {
         }
     }
 
-    fn identity(&self) -> Option<&Identity>;
+    fn identity(&self) -> Option<&ClientConfig>;
 
     async fn apply_all(
         &self,
@@ -150,7 +156,7 @@ pub trait Plugin {
         url: &DownloadInfo,
         logger: &mut BufWriter<File>,
         download_path: &Path,
-        identity: Option<&Identity>,
+        identity: Option<&ClientConfig>,
     ) -> Result<(), SoftwareError> {
         let downloader =
             Self::download_from_url(module, url, logger, download_path, identity).await?;
@@ -165,7 +171,7 @@ pub trait Plugin {
         url: &DownloadInfo,
         logger: &mut BufWriter<File>,
         download_path: &Path,
-        identity: Option<&Identity>,
+        identity: Option<&ClientConfig>,
     ) -> Result<Downloader, SoftwareError> {
         let sm_path = sm_path(&module.name, &module.version, download_path);
         let downloader = Downloader::new(sm_path, identity.map(|id| id.to_owned()));
@@ -231,13 +237,15 @@ struct ModuleInfo {
     version: Option<String>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ExternalPluginCommand {
     pub name: SoftwareType,
     pub path: PathBuf,
     pub sudo: Option<PathBuf>,
     pub max_packages: u32,
-    identity: Option<Identity>,
+    pub api_version: PluginApiVersion,
+    identity: Option<ClientConfig>,
+    progress: Option<UnboundedSender<SoftwareUpdateProgress>>,
 }
 
 impl ExternalPluginCommand {
@@ -246,14 +254,34 @@ impl ExternalPluginCommand {
         path: impl Into<PathBuf>,
         sudo: Option<PathBuf>,
         max_packages: u32,
-        identity: Option<Identity>,
+        identity: Option<ClientConfig>,
     ) -> ExternalPluginCommand {
         ExternalPluginCommand {
             name: name.into(),
             path: path.into(),
             sudo,
             max_packages,
+            api_version: PluginApiVersion::V1,
             identity,
+            progress: None,
+        }
+    }
+
+    /// Set the version of the plugin API implemented by this plugin
+    pub fn with_api_version(self, api_version: PluginApiVersion) -> Self {
+        ExternalPluginCommand {
+            api_version,
+            ..self
+        }
+    }
+
+    /// Forward to the given sender the progress reported by this plugin
+    ///
+    /// This is only effective for plugins implementing the v2 plugin API.
+    pub fn with_progress(self, progress: UnboundedSender<SoftwareUpdateProgress>) -> Self {
+        ExternalPluginCommand {
+            progress: Some(progress),
+            ..self
         }
     }
 
@@ -300,6 +328,61 @@ impl ExternalPluginCommand {
         Ok(output)
     }
 
+    /// Execute a plugin command, collecting the errors and forwarding the progress reported by a v2 plugin
+    ///
+    /// A v1 plugin is executed as with [ExternalPluginCommand::execute], no errors being ever collected.
+    pub async fn execute_reporting(
+        &self,
+        mut command: LoggedCommand,
+        logger: &mut BufWriter<File>,
+    ) -> Result<(Output, Vec<PluginModuleError>), SoftwareError> {
+        if self.api_version == PluginApiVersion::V1 {
+            let output = self.execute(command, logger).await?;
+            return Ok((output, vec![]));
+        }
+
+        let child = command.spawn().map_err(|err| self.plugin_error(err))?;
+        self.wait_reporting(child, logger).await
+    }
+
+    async fn wait_reporting(
+        &self,
+        child: LoggingChild,
+        logger: &mut BufWriter<File>,
+    ) -> Result<(Output, Vec<PluginModuleError>), SoftwareError> {
+        let mut errors = vec![];
+        let output = child
+            .wait_with_streamed_output(logger, |line| match PluginMessage::parse(line) {
+                Some(PluginMessage::Error(error)) => errors.push(error),
+                Some(PluginMessage::Progress(progress)) => {
+                    if let Some(sender) = &self.progress {
+                        let _ = sender.send(progress.for_plugin(&self.name));
+                    }
+                }
+                None => {}
+            })
+            .await
+            .map_err(|err| self.plugin_error(err))?;
+        Ok((output, errors))
+    }
+
+    /// The reason of a failed command: the errors reported by the plugin if any, otherwise its stderr
+    fn failure_reason(
+        &self,
+        output: Output,
+        errors: &[PluginModuleError],
+        module: Option<&SoftwareModule>,
+    ) -> Result<String, SoftwareError> {
+        let reported = match module {
+            Some(module) => PluginModuleError::reason_for(errors, Some(&module.name)),
+            None => PluginModuleError::reason(errors),
+        };
+        match reported {
+            Some(reason) => Ok(reason),
+            None => self.content(output.stderr),
+        }
+    }
+
     pub fn content(&self, bytes: Vec<u8>) -> Result<String, SoftwareError> {
         String::from_utf8(bytes).map_err(|err| self.plugin_error(err))
     }
@@ -337,14 +420,14 @@ const VERSION: &str = "version";
 impl Plugin for ExternalPluginCommand {
     async fn prepare(&self, logger: &mut BufWriter<File>) -> Result<(), SoftwareError> {
         let command = self.command(PREPARE, None)?;
-        let output = self.execute(command, logger).await?;
+        let (output, errors) = self.execute_reporting(command, logger).await?;
 
         if output.status.success() {
             Ok(())
         } else {
             Err(SoftwareError::Prepare {
                 software_type: self.name.clone(),
-                reason: self.content(output.stderr)?,
+                reason: self.failure_reason(output, &errors, None)?,
             })
         }
     }
@@ -355,14 +438,14 @@ impl Plugin for ExternalPluginCommand {
         logger: &mut BufWriter<File>,
     ) -> Result<(), SoftwareError> {
         let command = self.command(INSTALL, Some(module))?;
-        let output = self.execute(command, logger).await?;
+        let (output, errors) = self.execute_reporting(command, logger).await?;
 
         if output.status.success() {
             Ok(())
         } else {
             Err(SoftwareError::Install {
                 module: Box::new(module.clone()),
-                reason: self.content(output.stderr)?,
+                reason: self.failure_reason(output, &errors, Some(module))?,
             })
         }
     }
@@ -373,14 +456,14 @@ impl Plugin for ExternalPluginCommand {
         logger: &mut BufWriter<File>,
     ) -> Result<(), SoftwareError> {
         let command = self.command(REMOVE, Some(module))?;
-        let output = self.execute(command, logger).await?;
+        let (output, errors) = self.execute_reporting(command, logger).await?;
 
         if output.status.success() {
             Ok(())
         } else {
             Err(SoftwareError::Remove {
                 module: Box::new(module.clone()),
-                reason: self.content(output.stderr)?,
+                reason: self.failure_reason(output, &errors, Some(module))?,
             })
         }
     }
@@ -427,13 +510,16 @@ impl Plugin for ExternalPluginCommand {
             child_stdin.write_all(action.as_bytes()).await?
         }
 
-        let output = child.wait_with_output(logger).await?;
+        let (output, errors) = match self.api_version {
+            PluginApiVersion::V1 => (child.wait_with_output(logger).await?, vec![]),
+            PluginApiVersion::V2 => self.wait_reporting(child, logger).await?,
+        };
         match output.status.code() {
             Some(0) => Ok(()),
             Some(1) => Err(SoftwareError::UpdateListNotSupported(self.name.clone())),
             Some(_) => Err(SoftwareError::UpdateList {
                 software_type: self.name.clone(),
-                reason: self.content(output.stderr)?,
+                reason: self.failure_reason(output, &errors, None)?,
             }),
             None => Err(SoftwareError::UpdateList {
                 software_type: self.name.clone(),
@@ -444,14 +530,14 @@ impl Plugin for ExternalPluginCommand {
 
     async fn finalize(&self, logger: &mut BufWriter<File>) -> Result<(), SoftwareError> {
         let command = self.command(FINALIZE, None)?;
-        let output = self.execute(command, logger).await?;
+        let (output, errors) = self.execute_reporting(command, logger).await?;
 
         if output.status.success() {
             Ok(())
         } else {
             Err(SoftwareError::Finalize {
                 software_type: self.name.clone(),
-                reason: self.content(output.stderr)?,
+                reason: self.failure_reason(output, &errors, None)?,
             })
         }
     }
@@ -516,7 +602,7 @@ impl Plugin for ExternalPluginCommand {
         }
     }
 
-    fn identity(&self) -> Option<&Identity> {
+    fn identity(&self) -> Option<&ClientConfig> {
         self.identity.as_ref()
     }
 }
@@ -0,0 +1,167 @@
+use serde::Deserialize;
+use tedge_api::messages::SoftwareUpdateProgress;
+use tedge_api::SoftwareType;
+
+/// Sub-command used to query the version of the plugin API implemented by a plugin.
+///
+/// A plugin that doesn't support this sub-command is assumed to implement the version 1.
+pub const API_VERSION: &str = "api-version";
+
+/// The version of the protocol used by the agent to interact with a plugin.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum PluginApiVersion {
+    /// Only the exit status and the stderr of the plugin are used to report the outcome of a command.
+    #[default]
+    V1,
+
+    /// On top of the exit status, the plugin reports progress and errors as JSON lines on stdout.
+    V2,
+}
+
+impl PluginApiVersion {
+    /// Parse the output of the `api-version` sub-command
+    pub fn from_output(stdout: &str) -> PluginApiVersion {
+        match stdout.trim() {
+            "2" => PluginApiVersion::V2,
+            _ => PluginApiVersion::V1,
+        }
+    }
+}
+
+/// A message printed by a v2 plugin on stdout, one JSON object per line.
+///
+/// Lines that are not such JSON objects are simply logged.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum PluginMessage {
+    Error(PluginModuleError),
+    Progress(PluginProgress),
+}
+
+impl PluginMessage {
+    pub fn parse(line: &str) -> Option<PluginMessage> {
+        serde_json::from_str(line).ok()
+    }
+}
+
+/// Progress of a plugin command, e.g. `{"module":"nginx","percent":40,"phase":"download"}`
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PluginProgress {
+    pub module: Option<String>,
+    pub percent: Option<u8>,
+    pub phase: Option<String>,
+}
+
+impl PluginProgress {
+    pub fn for_plugin(self, plugin_type: &SoftwareType) -> SoftwareUpdateProgress {
+        SoftwareUpdateProgress {
+            plugin_type: plugin_type.clone(),
+            module: self.module,
+            percent: self.percent.map(|percent| percent.min(100)),
+            phase: self.phase,
+        }
+    }
+}
+
+/// Error reported by a plugin, e.g. `{"module":"nginx","version":"1.2","error":"dependency conflict"}`
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PluginModuleError {
+    pub module: Option<String>,
+    pub version: Option<String>,
+    pub error: String,
+}
+
+impl PluginModuleError {
+    /// Build the failure reason for a module, from the errors reported by the plugin
+    ///
+    /// Return `None` if no errors have been reported for that module.
+    pub fn reason_for(errors: &[PluginModuleError], module_name: Option<&str>) -> Option<String> {
+        let reasons: Vec<&str> = errors
+            .iter()
+            .filter(|error| match (module_name, &error.module) {
+                (Some(name), Some(module)) => name == module,
+                _ => true,
+            })
+            .map(|error| error.error.as_str())
+            .collect();
+        if reasons.is_empty() {
+            None
+        } else {
+            Some(reasons.join("\n"))
+        }
+    }
+
+    /// Build the failure reason of a command applied to several modules
+    pub fn reason(errors: &[PluginModuleError]) -> Option<String> {
+        let reasons: Vec<String> = errors
+            .iter()
+            .map(|error| match &error.module {
+                Some(module) => format!("{module}: {}", error.error),
+                None => error.error.clone(),
+            })
+            .collect();
+        if reasons.is_empty() {
+            None
+        } else {
+            Some(reasons.join("\n"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_plugin_messages() {
+        assert_eq!(
+            PluginMessage::parse(r#"{"module":"nginx","percent":40,"phase":"download"}"#),
+            Some(PluginMessage::Progress(PluginProgress {
+                module: Some("nginx".to_string()),
+                percent: Some(40),
+                phase: Some("download".to_string()),
+            }))
+        );
+        assert_eq!(
+            PluginMessage::parse(r#"{"module":"nginx","error":"dependency conflict"}"#),
+            Some(PluginMessage::Error(PluginModuleError {
+                module: Some("nginx".to_string()),
+                version: None,
+                error: "dependency conflict".to_string(),
+            }))
+        );
+        assert_eq!(PluginMessage::parse("Reading package lists..."), None);
+        assert_eq!(PluginMessage::parse(r#"{"unexpected":"field"}"#), None);
+    }
+
+    #[test]
+    fn failure_reasons_are_built_from_module_errors() {
+        let errors = vec![
+            PluginModuleError {
+                module: Some("nginx".to_string()),
+                version: None,
+                error: "dependency conflict".to_string(),
+            },
+            PluginModuleError {
+                module: None,
+                version: None,
+                error: "disk full".to_string(),
+            },
+        ];
+
+        assert_eq!(
+            PluginModuleError::reason_for(&errors, Some("nginx")),
+            Some("dependency conflict\ndisk full".to_string())
+        );
+        assert_eq!(
+            PluginModuleError::reason_for(&errors[..1], Some("curl")),
+            None
+        );
+        assert_eq!(
+            PluginModuleError::reason(&errors),
+            Some("nginx: dependency conflict\ndisk full".to_string())
+        );
+    }
+}
@@ -2,21 +2,35 @@ use crate::log_file::LogFile;
 use crate::plugin::ExternalPluginCommand;
 use crate::plugin::Plugin;
 use crate::plugin::LIST;
+use crate::plugin_api::PluginApiVersion;
+use crate::plugin_api::API_VERSION;
+use crate::update_plan::batches;
+use crate::update_plan::module_type;
+use crate::update_plan::ordered_updates;
+use crate::update_plan::rollback_updates;
+use crate::update_plan::InstalledModules;
 use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::fs;
 use std::io::ErrorKind;
 use std::io::{self};
 use std::path::Path;
 use std::path::PathBuf;
-use std::process::Command;
 use std::process::Stdio;
+use std::time::SystemTime;
 use tedge_api::messages::CommandStatus;
 use tedge_api::messages::SoftwareListCommand;
 use tedge_api::messages::SoftwareUpdateCommand;
+use tedge_api::messages::SoftwareUpdateProgress;
 use tedge_api::SoftwareError;
+use tedge_api::SoftwareModuleUpdate;
 use tedge_api::SoftwareType;
 use tedge_api::DEFAULT;
 use tedge_config::TEdgeConfigLocation;
+use tokio::fs::File;
+use tokio::io::BufWriter;
+use tokio::process::Command;
+use tokio::sync::mpsc::UnboundedSender;
 use tracing::error;
 use tracing::info;
 use tracing::warn;
@@ -54,6 +68,9 @@ pub struct ExternalPlugins {
     default_plugin_type: Option<SoftwareType>,
     sudo: Option<PathBuf>,
     config_location: TEdgeConfigLocation,
+
+    /// The API version of each plugin, along the modification time of the plugin file when probed
+    api_versions: HashMap<PathBuf, (SystemTime, PluginApiVersion)>,
 }
 
 impl Plugins for ExternalPlugins {
@@ -93,7 +110,7 @@ impl Plugins for ExternalPlugins {
 }
 
 impl ExternalPlugins {
-    pub fn open(
+    pub async fn open(
         plugin_dir: impl Into<PathBuf>,
         default_plugin_type: Option<String>,
         sudo: Option<PathBuf>,
@@ -105,8 +122,9 @@ impl ExternalPlugins {
             default_plugin_type: default_plugin_type.clone(),
             sudo,
             config_location,
+            api_versions: HashMap::new(),
         };
-        if let Err(e) = plugins.load() {
+        if let Err(e) = plugins.load().await {
             warn!(
                 "Reading the plugins directory ({:?}): failed with: {e:?}",
                 &plugins.plugin_dir
@@ -135,7 +153,7 @@ impl ExternalPlugins {
         Ok(plugins)
     }
 
-    pub fn load(&mut self) -> anyhow::Result<()> {
+    pub async fn load(&mut self) -> anyhow::Result<()> {
         self.plugin_map.clear();
 
         let config = tedge_config::TEdgeConfigRepository::new(self.config_location.clone())
@@ -164,6 +182,7 @@ impl ExternalPlugins {
                     .stdout(Stdio::null())
                     .stderr(Stdio::null())
                     .status()
+                    .await
                 {
                     Ok(code) if code.success() => {
                         info!("Plugin activated: {}", path.display());
@@ -201,13 +220,16 @@ impl ExternalPlugins {
                 if let Some(file_name) = path.file_name() {
                     if let Some(plugin_name) = file_name.to_str() {
                         let identity = config.http.client.auth.identity()?;
+                        let api_version = self.plugin_api_version(&path).await;
+                        info!("Plugin {plugin_name} implements the API {api_version:?}");
                         let plugin = ExternalPluginCommand::new(
                             plugin_name,
                             &path,
                             self.sudo.clone(),
                             config.software.plugin.max_packages,
                             identity,
-                        );
+                        )
+                        .with_api_version(api_version);
                         self.plugin_map.insert(plugin_name.into(), plugin);
                     }
                 }
@@ -217,6 +239,55 @@ impl ExternalPlugins {
         Ok(())
     }
 
+    /// The version of the plugin API implemented by a plugin
+    ///
+    /// A plugin is only asked once for its API version, unless the plugin file is updated.
+    async fn plugin_api_version(&mut self, path: &Path) -> PluginApiVersion {
+        let modified = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if let (Some(modified), Some((probed, api_version))) =
+            (modified, self.api_versions.get(path))
+        {
+            if *probed == modified {
+                return *api_version;
+            }
+        }
+
+        let api_version = self.probe_api_version(path).await;
+        if let Some(modified) = modified {
+            self.api_versions
+                .insert(path.to_path_buf(), (modified, api_version));
+        }
+        api_version
+    }
+
+    /// Ask a plugin for the version of the plugin API it implements
+    ///
+    /// A plugin failing to answer is assumed to implement the version 1.
+    async fn probe_api_version(&self, path: &Path) -> PluginApiVersion {
+        let mut command = if let Some(sudo) = &self.sudo {
+            let mut command = Command::new(sudo);
+            command.arg(path);
+            command
+        } else {
+            Command::new(path)
+        };
+
+        match command
+            .arg(API_VERSION)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .await
+        {
+            Ok(output) if output.status.success() => {
+                PluginApiVersion::from_output(&String::from_utf8_lossy(&output.stdout))
+            }
+            _ => PluginApiVersion::V1,
+        }
+    }
+
     pub fn empty(&self) -> bool {
         self.plugin_map.is_empty()
     }
@@ -254,14 +325,31 @@ impl ExternalPlugins {
         request: SoftwareUpdateCommand,
         mut log_file: LogFile,
         download_path: &Path,
+        progress: UnboundedSender<SoftwareUpdateProgress>,
     ) -> SoftwareUpdateCommand {
         let mut response = request.clone().with_status(CommandStatus::Executing);
         let logger = log_file.buffer();
-        let mut error_count = 0;
 
+        if request.has_dependencies() || request.payload.rollback {
+            return match self
+                .process_in_order(&request, &mut response, logger, download_path, progress)
+                .await
+            {
+                Ok(None) => response.with_status(CommandStatus::Successful),
+                Ok(Some(outcome)) => {
+                    let reason = ExternalPlugins::error_message(log_file.path(), 1)
+                        .map_or(outcome.clone(), |reason| format!("{reason}; {outcome}"));
+                    response.with_error(reason)
+                }
+                Err(err) => response.with_error(err.to_string()),
+            };
+        }
+
+        let mut error_count = 0;
         for software_type in request.modules_types() {
             let errors = if let Some(plugin) = self.by_software_type(&software_type) {
                 let updates = request.updates_for(&software_type);
+                let plugin = plugin.clone().with_progress(progress.clone());
                 plugin.apply_all(updates, logger, download_path).await
             } else {
                 vec![SoftwareError::UnknownSoftwareType {
@@ -282,6 +370,142 @@ impl ExternalPlugins {
         }
     }
 
+    /// Apply the updates one batch at a time, each update being applied after those it depends on
+    ///
+    /// The updates are stopped on the first failure, the remaining updates being reported as not applied.
+    /// If a rollback is requested, the updates already applied are then reverted.
+    ///
+    /// Return `Ok(None)` if all the updates have been applied,
+    /// or a description of the outcome if some have failed.
+    /// An error is returned if the updates cannot be applied at all.
+    async fn process_in_order(
+        &self,
+        request: &SoftwareUpdateCommand,
+        response: &mut SoftwareUpdateCommand,
+        logger: &mut BufWriter<File>,
+        download_path: &Path,
+        progress: UnboundedSender<SoftwareUpdateProgress>,
+    ) -> Result<Option<String>, SoftwareError> {
+        let planned = batches(ordered_updates(request)?);
+        for (software_type, _) in planned.iter() {
+            self.plugin(software_type)?;
+        }
+        let installed = if request.payload.rollback {
+            self.installed_modules(&planned, logger).await?
+        } else {
+            InstalledModules::new()
+        };
+
+        let mut failures = Failures::default();
+        let mut applied = vec![];
+        let mut remaining = planned.into_iter();
+        for (software_type, updates) in remaining.by_ref() {
+            let plugin = self.plugin(&software_type)?.clone();
+            let plugin = plugin.with_progress(progress.clone());
+            let errors = plugin
+                .apply_all(updates.clone(), logger, download_path)
+                .await;
+            if errors.is_empty() {
+                applied.extend(updates);
+                continue;
+            }
+
+            // When all the errors are related to specific modules, the other modules have been applied
+            if errors.iter().all(module_error) {
+                applied.extend(
+                    updates
+                        .into_iter()
+                        .filter(|update| !errors.iter().any(|error| is_error_of(error, update))),
+                );
+            }
+            failures.add(&software_type, errors);
+            break;
+        }
+
+        if failures.is_empty() {
+            return Ok(None);
+        }
+
+        for (software_type, updates) in remaining {
+            let not_applied = updates
+                .into_iter()
+                .map(|update| update_error(update, "Not applied, as a previous update failed"))
+                .collect();
+            failures.add(&software_type, not_applied);
+        }
+
+        let outcome = if request.payload.rollback {
+            let mut rollback_errors = vec![];
+            for (software_type, reverts) in batches(rollback_updates(&applied, &installed)) {
+                let plugin = self.plugin(&software_type)?;
+                let errors = plugin.apply_all(reverts, logger, download_path).await;
+                if !errors.is_empty() {
+                    rollback_errors.push((software_type, errors));
+                }
+            }
+
+            if rollback_errors.is_empty() {
+                for update in applied {
+                    let software_type = module_type(update.module());
+                    failures.add(&software_type, vec![update_error(update, "Rolled back")]);
+                }
+                "the updates already applied have been rolled back"
+            } else {
+                for (software_type, errors) in rollback_errors {
+                    failures.add(&software_type, errors);
+                }
+                "the rollback of the updates already applied failed"
+            }
+        } else {
+            "the remaining updates have not been applied"
+        };
+
+        for (software_type, errors) in failures.0 {
+            response.add_errors(&software_type, errors);
+        }
+        Ok(Some(outcome.to_string()))
+    }
+
+    /// Record the modules installed before an update, for the software types of the update
+    ///
+    /// The versions are listed using the `list` command of the plugin,
+    /// falling back to the `version` command for each updated module if the plugin fails to list its modules.
+    async fn installed_modules(
+        &self,
+        batches: &[(SoftwareType, Vec<SoftwareModuleUpdate>)],
+        logger: &mut BufWriter<File>,
+    ) -> Result<InstalledModules, SoftwareError> {
+        let mut installed = InstalledModules::new();
+        let mut listed: Vec<&SoftwareType> = vec![];
+        for (software_type, updates) in batches {
+            let plugin = self.plugin(software_type)?;
+            if listed.contains(&software_type) {
+                continue;
+            }
+            listed.push(software_type);
+
+            match plugin.list(logger).await {
+                Ok(modules) => {
+                    for module in modules {
+                        installed.insert((software_type.clone(), module.name), module.version);
+                    }
+                }
+                Err(_) => {
+                    for update in updates {
+                        let module = update.module();
+                        if let Some(version) = plugin.version(module, logger).await? {
+                            installed.insert(
+                                (software_type.clone(), module.name.clone()),
+                                Some(version),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        Ok(installed)
+    }
+
     fn error_message(log_file: &Path, error_count: i32) -> Option<String> {
         if error_count > 0 {
             let reason = if error_count == 1 {
@@ -300,8 +524,56 @@ impl ExternalPlugins {
     }
 }
 
-#[test]
-fn test_no_sm_plugin_dir() {
+/// The errors of a software update, grouped by software type
+#[derive(Default)]
+struct Failures(Vec<(SoftwareType, Vec<SoftwareError>)>);
+
+impl Failures {
+    fn add(&mut self, software_type: &SoftwareType, mut errors: Vec<SoftwareError>) {
+        match self.0.iter_mut().find(|(t, _)| t == software_type) {
+            Some((_, type_errors)) => type_errors.append(&mut errors),
+            None => self.0.push((software_type.clone(), errors)),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+fn module_error(error: &SoftwareError) -> bool {
+    matches!(
+        error,
+        SoftwareError::Install { .. } | SoftwareError::Remove { .. }
+    )
+}
+
+fn is_error_of(error: &SoftwareError, update: &SoftwareModuleUpdate) -> bool {
+    match (error, update) {
+        (SoftwareError::Install { module, .. }, SoftwareModuleUpdate::Install { module: m })
+        | (SoftwareError::Remove { module, .. }, SoftwareModuleUpdate::Remove { module: m }) => {
+            module.name == m.name
+        }
+        _ => false,
+    }
+}
+
+fn update_error(update: SoftwareModuleUpdate, reason: &str) -> SoftwareError {
+    let reason = reason.to_string();
+    match update {
+        SoftwareModuleUpdate::Install { module } => SoftwareError::Install {
+            module: Box::new(module),
+            reason,
+        },
+        SoftwareModuleUpdate::Remove { module } => SoftwareError::Remove {
+            module: Box::new(module),
+            reason,
+        },
+    }
+}
+
+#[tokio::test]
+async fn test_no_sm_plugin_dir() {
     let plugin_dir = tempfile::TempDir::new().unwrap();
 
     let actual = ExternalPlugins::open(
@@ -309,6 +581,7 @@ fn test_no_sm_plugin_dir() {
         None,
         None,
         TEdgeConfigLocation::default(),
-    );
+    )
+    .await;
     assert!(actual.is_ok());
 }
@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use tedge_api::messages::SoftwareModuleRef;
+use tedge_api::messages::SoftwareUpdateCommand;
+use tedge_api::SoftwareError;
+use tedge_api::SoftwareModule;
+use tedge_api::SoftwareModuleUpdate;
+use tedge_api::SoftwareName;
+use tedge_api::SoftwareType;
+use tedge_api::SoftwareVersion;
+
+/// The modules installed on the device before an update, with their versions.
+///
+/// A module which is not a key of this map was not installed.
+pub type InstalledModules = HashMap<(SoftwareType, SoftwareName), Option<SoftwareVersion>>;
+
+/// Order the updates of a request, so each update is applied after the updates it depends on.
+///
+/// The updates that don't depend on each other are kept in the order of the request.
+pub fn ordered_updates(
+    request: &SoftwareUpdateCommand,
+) -> Result<Vec<SoftwareModuleUpdate>, SoftwareError> {
+    let updates = request.updates_with_dependencies();
+
+    // For each update, the indexes of the updates that must be applied before
+    let mut dependencies = Vec::with_capacity(updates.len());
+    for (index, (update, depends_on)) in updates.iter().enumerate() {
+        let mut required = vec![];
+        for dependency in depends_on {
+            let matching: Vec<usize> = updates
+                .iter()
+                .enumerate()
+                .filter(|(other, (other_update, _))| {
+                    *other != index && dependency_matches(dependency, other_update.module())
+                })
+                .map(|(other, _)| other)
+                .collect();
+            if matching.is_empty() {
+                return Err(SoftwareError::InvalidDependencies {
+                    reason: format!(
+                        "{} depends on {}, which is not part of the update",
+                        update.module().name,
+                        dependency.name
+                    ),
+                });
+            }
+            required.extend(matching);
+        }
+        dependencies.push(required);
+    }
+
+    let mut ordered = Vec::with_capacity(updates.len());
+    let mut done = vec![false; updates.len()];
+    while ordered.len() < updates.len() {
+        let next = (0..updates.len()).find(|&index| {
+            !done[index] && dependencies[index].iter().all(|&required| done[required])
+        });
+        match next {
+            Some(index) => {
+                done[index] = true;
+                ordered.push(updates[index].0.clone());
+            }
+            None => {
+                let cycle: Vec<&str> = (0..updates.len())
+                    .filter(|&index| !done[index])
+                    .map(|index| updates[index].0.module().name.as_str())
+                    .collect();
+                return Err(SoftwareError::InvalidDependencies {
+                    reason: format!("circular dependencies between {}", cycle.join(", ")),
+                });
+            }
+        }
+    }
+
+    Ok(ordered)
+}
+
+/// Group consecutive updates of the same software type, so each group can be applied by a single plugin
+pub fn batches(
+    updates: Vec<SoftwareModuleUpdate>,
+) -> Vec<(SoftwareType, Vec<SoftwareModuleUpdate>)> {
+    let mut batches: Vec<(SoftwareType, Vec<SoftwareModuleUpdate>)> = vec![];
+    for update in updates {
+        let software_type = module_type(update.module());
+        match batches.last_mut() {
+            Some((batch_type, batch)) if batch_type == &software_type => batch.push(update),
+            _ => batches.push((software_type, vec![update])),
+        }
+    }
+    batches
+}
+
+/// The updates reverting the given updates, in the reverse order, to restore the installed modules
+pub fn rollback_updates(
+    applied: &[SoftwareModuleUpdate],
+    installed: &InstalledModules,
+) -> Vec<SoftwareModuleUpdate> {
+    let mut reverts = vec![];
+    for update in applied.iter().rev() {
+        let module = update.module();
+        let key = (module_type(module), module.name.clone());
+        let previous = installed.get(&key);
+        let revert = match (update, previous) {
+            (SoftwareModuleUpdate::Install { .. }, Some(version)) => {
+                SoftwareModuleUpdate::install(restored_module(module, version.clone()))
+            }
+            (SoftwareModuleUpdate::Install { .. }, None) => {
+                SoftwareModuleUpdate::remove(restored_module(module, None))
+            }
+            (SoftwareModuleUpdate::Remove { .. }, Some(version)) => {
+                SoftwareModuleUpdate::install(restored_module(module, version.clone()))
+            }
+            (SoftwareModuleUpdate::Remove { .. }, None) => continue,
+        };
+        reverts.push(revert);
+    }
+    reverts
+}
+
+/// The software type of a module of an update request
+pub fn module_type(module: &SoftwareModule) -> SoftwareType {
+    module
+        .module_type
+        .clone()
+        .unwrap_or_else(SoftwareModule::default_type)
+}
+
+fn dependency_matches(dependency: &SoftwareModuleRef, module: &SoftwareModule) -> bool {
+    dependency.matches(&module_type(module), &module.name)
+}
+
+fn restored_module(module: &SoftwareModule, version: Option<SoftwareVersion>) -> SoftwareModule {
+    SoftwareModule {
+        module_type: module.module_type.clone(),
+        name: module.name.clone(),
+        version,
+        url: None,
+        file_path: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tedge_api::messages::SoftwareModuleAction;
+    use tedge_api::messages::SoftwareModuleItem;
+    use tedge_api::messages::SoftwareRequestResponseSoftwareList;
+    use tedge_api::mqtt_topics::EntityTopicId;
+
+    #[test]
+    fn updates_are_ordered_after_their_dependencies() {
+        let request = request(vec![
+            (
+                "apt",
+                vec![item("app", &[("container", "db")]), item("curl", &[])],
+            ),
+            ("container", vec![item("db", &[("", "libssl")])]),
+            ("apt", vec![item("libssl", &[])]),
+        ]);
+
+        let ordered = ordered_updates(&request).unwrap();
+
+        assert_eq!(names(&ordered), vec!["curl", "libssl", "db", "app"]);
+    }
+
+    #[test]
+    fn updates_without_dependencies_are_kept_in_order() {
+        let request = request(vec![
+            ("apt", vec![item("a", &[]), item("b", &[])]),
+            ("container", vec![item("c", &[])]),
+        ]);
+
+        let ordered = ordered_updates(&request).unwrap();
+
+        assert_eq!(names(&ordered), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn unknown_and_circular_dependencies_are_rejected() {
+        let unknown = request(vec![("apt", vec![item("app", &[("apt", "unknown")])])]);
+        assert_eq!(
+            ordered_updates(&unknown),
+            Err(SoftwareError::InvalidDependencies {
+                reason: "app depends on unknown, which is not part of the update".into()
+            })
+        );
+
+        let circular = request(vec![(
+            "apt",
+            vec![
+                item("a", &[("", "b")]),
+                item("b", &[("", "a")]),
+                item("c", &[]),
+            ],
+        )]);
+        assert_eq!(
+            ordered_updates(&circular),
+            Err(SoftwareError::InvalidDependencies {
+                reason: "circular dependencies between a, b".into()
+            })
+        );
+    }
+
+    #[test]
+    fn consecutive_updates_of_the_same_type_are_batched() {
+        let request = request(vec![
+            ("apt", vec![item("a", &[]), item("b", &[])]),
+            ("container", vec![item("c", &[])]),
+            ("apt", vec![item("d", &[])]),
+        ]);
+
+        let batches = batches(ordered_updates(&request).unwrap());
+
+        let batches: Vec<(&str, Vec<&str>)> = batches
+            .iter()
+            .map(|(software_type, updates)| (software_type.as_str(), names(updates)))
+            .collect();
+        assert_eq!(
+            batches,
+            vec![
+                ("apt", vec!["a", "b"]),
+                ("container", vec!["c"]),
+                ("apt", vec!["d"])
+            ]
+        );
+    }
+
+    #[test]
+    fn rollback_restores_the_previous_versions() {
+        let applied = vec![
+            SoftwareModuleUpdate::install(module("apt", "upgraded", Some("2.0"))),
+            SoftwareModuleUpdate::install(module("apt", "new", Some("1.0"))),
+            SoftwareModuleUpdate::remove(module("container", "removed", None)),
+            SoftwareModuleUpdate::remove(module("container", "absent", None)),
+        ];
+        let installed = InstalledModules::from([
+            (("apt".into(), "upgraded".into()), Some("1.0".into())),
+            (("container".into(), "removed".into()), Some("3.1".into())),
+        ]);
+
+        let reverts = rollback_updates(&applied, &installed);
+
+        assert_eq!(
+            reverts,
+            vec![
+                SoftwareModuleUpdate::install(module("container", "removed", Some("3.1"))),
+                SoftwareModuleUpdate::remove(module("apt", "new", None)),
+                SoftwareModuleUpdate::install(module("apt", "upgraded", Some("1.0"))),
+            ]
+        );
+    }
+
+    fn request(update_list: Vec<(&str, Vec<SoftwareModuleItem>)>) -> SoftwareUpdateCommand {
+        let mut request =
+            SoftwareUpdateCommand::new(&EntityTopicId::default_main_device(), "1".into());
+        request.payload.update_list = update_list
+            .into_iter()
+            .map(
+                |(plugin_type, modules)| SoftwareRequestResponseSoftwareList {
+                    plugin_type: plugin_type.into(),
+                    modules,
+                },
+            )
+            .collect();
+        request
+    }
+
+    fn item(name: &str, depends_on: &[(&str, &str)]) -> SoftwareModuleItem {
+        SoftwareModuleItem {
+            name: name.into(),
+            version: None,
+            url: None,
+            action: Some(SoftwareModuleAction::Install),
+            reason: None,
+            depends_on: depends_on
+                .iter()
+                .map(|(module_type, name)| SoftwareModuleRef {
+                    module_type: (!module_type.is_empty()).then(|| module_type.to_string()),
+                    name: name.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    fn module(module_type: &str, name: &str, version: Option<&str>) -> SoftwareModule {
+        SoftwareModule {
+            module_type: Some(module_type.into()),
+            name: name.into(),
+            version: version.map(|v| v.into()),
+            url: None,
+            file_path: None,
+        }
+    }
+
+    fn names(updates: &[SoftwareModuleUpdate]) -> Vec<&str> {
+        updates
+            .iter()
+            .map(|update| update.module().name.as_str())
+            .collect()
+    }
+}
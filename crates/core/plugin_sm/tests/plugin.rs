@@ -4,11 +4,14 @@ mod tests {
     use plugin_sm::plugin::deserialize_module_info;
     use plugin_sm::plugin::ExternalPluginCommand;
     use plugin_sm::plugin::Plugin;
+    use plugin_sm::plugin_api::PluginApiVersion;
     use serial_test::serial;
     use std::fs;
     use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
     use std::path::PathBuf;
     use std::str::FromStr;
+    use tedge_api::messages::SoftwareUpdateProgress;
     use tedge_api::SoftwareError;
     use tedge_api::SoftwareModule;
     use tedge_api::SoftwareModuleUpdate;
@@ -403,6 +406,125 @@ mod tests {
         assert!(errors.is_empty());
     }
 
+    #[tokio::test]
+    async fn plugin_v2_reports_progress_while_installing() {
+        let plugin_dir = tempfile::TempDir::new().unwrap();
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let plugin = get_v2_plugin(&plugin_dir).with_progress(sender);
+
+        let module = SoftwareModule {
+            module_type: Some("v2".into()),
+            name: "nginx".into(),
+            version: None,
+            url: None,
+            file_path: None,
+        };
+        let mut logger = dev_null().await;
+        let res = plugin.install(&module, &mut logger).await;
+        assert_eq!(res, Ok(()));
+
+        drop(plugin);
+        let mut progress = vec![];
+        while let Some(update) = receiver.recv().await {
+            progress.push(update);
+        }
+        assert_eq!(
+            progress,
+            vec![
+                SoftwareUpdateProgress {
+                    plugin_type: "v2".into(),
+                    module: Some("nginx".into()),
+                    percent: Some(50),
+                    phase: Some("download".into()),
+                },
+                SoftwareUpdateProgress {
+                    plugin_type: "v2".into(),
+                    module: Some("nginx".into()),
+                    percent: Some(100),
+                    phase: Some("install".into()),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn plugin_v2_reports_structured_errors() {
+        let plugin_dir = tempfile::TempDir::new().unwrap();
+        let plugin = get_v2_plugin(&plugin_dir);
+
+        let module = SoftwareModule {
+            module_type: Some("v2".into()),
+            name: "nginx".into(),
+            version: None,
+            url: None,
+            file_path: None,
+        };
+        let mut logger = dev_null().await;
+        let res = plugin.remove(&module, &mut logger).await;
+
+        // The error reported on stdout is used as failure reason, rather than stderr
+        assert_eq!(
+            res,
+            Err(SoftwareError::Remove {
+                module: Box::new(module),
+                reason: "nginx is in use".into(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn plugin_v1_ignores_json_lines() {
+        let plugin_dir = tempfile::TempDir::new().unwrap();
+        let plugin = get_v2_plugin(&plugin_dir).with_api_version(PluginApiVersion::V1);
+
+        let module = SoftwareModule {
+            module_type: Some("v2".into()),
+            name: "nginx".into(),
+            version: None,
+            url: None,
+            file_path: None,
+        };
+        let mut logger = dev_null().await;
+        let res = plugin.remove(&module, &mut logger).await;
+
+        assert_eq!(
+            res,
+            Err(SoftwareError::Remove {
+                module: Box::new(module),
+                reason: "cannot remove\n".into(),
+            })
+        );
+    }
+
+    fn get_v2_plugin(plugin_dir: &tempfile::TempDir) -> ExternalPluginCommand {
+        let plugin_path = plugin_dir.path().join("v2");
+        fs::write(
+            &plugin_path,
+            r#"#!/bin/sh
+case "$1" in
+    api-version) echo 2 ;;
+    list) ;;
+    install)
+        echo "Fetching $2"
+        echo '{"module":"'$2'","percent":50,"phase":"download"}'
+        echo '{"module":"'$2'","percent":100,"phase":"install"}'
+        ;;
+    remove)
+        echo '{"module":"'$2'","error":"'$2' is in use"}'
+        echo "cannot remove" >&2
+        exit 2
+        ;;
+    *) exit 1 ;;
+esac
+"#,
+        )
+        .unwrap();
+        fs::set_permissions(&plugin_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        ExternalPluginCommand::new("v2", &plugin_path, None, 100, None)
+            .with_api_version(PluginApiVersion::V2)
+    }
+
     fn get_dummy_plugin_path() -> PathBuf {
         // Return a path to a dummy plugin in target directory.
         let package_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
@@ -1,32 +1,44 @@
 #[cfg(test)]
 mod tests {
 
+    use plugin_sm::log_file::LogFile;
+    use plugin_sm::plugin_api::PluginApiVersion;
     use plugin_sm::plugin_manager::ExternalPlugins;
     use plugin_sm::plugin_manager::Plugins;
     use std::fs::File;
+    use std::os::unix::fs::PermissionsExt;
     use std::path::PathBuf;
     use std::str::FromStr;
+    use tedge_api::messages::CommandStatus;
+    use tedge_api::messages::SoftwareModuleAction;
+    use tedge_api::messages::SoftwareModuleItem;
+    use tedge_api::messages::SoftwareModuleRef;
+    use tedge_api::messages::SoftwareRequestResponseSoftwareList;
+    use tedge_api::messages::SoftwareUpdateCommand;
+    use tedge_api::mqtt_topics::EntityTopicId;
     use tedge_config::TEdgeConfigLocation;
     use tempfile::NamedTempFile;
 
-    #[test]
-    fn plugin_manager_load_plugins_empty() {
+    #[tokio::test]
+    async fn plugin_manager_load_plugins_empty() {
         // Create empty plugins directory.
         let temp_dir = tempfile::tempdir().unwrap();
         let plugin_dir = temp_dir.path().to_owned();
 
         // Call open and load to register all plugins from given directory.
         let mut plugins =
-            ExternalPlugins::open(plugin_dir, None, None, TEdgeConfigLocation::default()).unwrap();
-        let _ = plugins.load();
+            ExternalPlugins::open(plugin_dir, None, None, TEdgeConfigLocation::default())
+                .await
+                .unwrap();
+        let _ = plugins.load().await;
 
         // Plugins registry should not register any plugin as no files in the directory are present.
         assert!(plugins.empty());
     }
 
     #[ignore = "dependency on tedge-dummy-plugin"]
-    #[test]
-    fn plugin_manager_load_plugins_some_non_executables() {
+    #[tokio::test]
+    async fn plugin_manager_load_plugins_some_non_executables() {
         // Create empty plugins directory.
         let temp_dir = tempfile::tempdir().unwrap();
 
@@ -36,15 +48,17 @@ mod tests {
 
         // Call open and load to register all plugins from given directory.
         let mut plugins =
-            ExternalPlugins::open(plugin_dir, None, None, TEdgeConfigLocation::default()).unwrap();
-        let _ = plugins.load();
+            ExternalPlugins::open(plugin_dir, None, None, TEdgeConfigLocation::default())
+                .await
+                .unwrap();
+        let _ = plugins.load().await;
 
         // Registry has registered no plugins.
         assert!(plugins.empty());
     }
 
-    #[test]
-    fn plugin_manager_load_plugins_some_by_plugins_none() {
+    #[tokio::test]
+    async fn plugin_manager_load_plugins_some_by_plugins_none() {
         // Create empty plugins directory.
         let temp_dir = tempfile::tempdir().unwrap();
 
@@ -55,8 +69,10 @@ mod tests {
 
         // Call open and load to register all plugins from given directory.
         let mut plugins =
-            ExternalPlugins::open(plugin_dir, None, None, TEdgeConfigLocation::default()).unwrap();
-        let _ = plugins.load();
+            ExternalPlugins::open(plugin_dir, None, None, TEdgeConfigLocation::default())
+                .await
+                .unwrap();
+        let _ = plugins.load().await;
 
         // Check if registry has loaded plugin of type `test`.
         assert!(plugins.by_software_type("test").is_none());
@@ -65,8 +81,8 @@ mod tests {
     }
 
     #[ignore = "dependency on tedge-dummy-plugin"]
-    #[test]
-    fn plugin_manager_load_plugins_some_by_plugins_some() {
+    #[tokio::test]
+    async fn plugin_manager_load_plugins_some_by_plugins_some() {
         // Create empty plugins directory.
         let temp_dir = tempfile::tempdir().unwrap();
 
@@ -102,8 +118,10 @@ mod tests {
 
         // Call open and load to register all plugins from given directory.
         let mut plugins =
-            ExternalPlugins::open(plugin_dir, None, None, TEdgeConfigLocation::default()).unwrap();
-        let _ = plugins.load();
+            ExternalPlugins::open(plugin_dir, None, None, TEdgeConfigLocation::default())
+                .await
+                .unwrap();
+        let _ = plugins.load().await;
 
         // Plugin registry shall have registered plugin with name as the file in plugin directory.
         assert!(plugins.by_software_type(&plugin_name1).is_some());
@@ -113,8 +131,8 @@ mod tests {
     }
 
     #[ignore = "dependency on tedge-dummy-plugin"]
-    #[test]
-    fn explicit_default_plugin() {
+    #[tokio::test]
+    async fn explicit_default_plugin() {
         let plugin_dir = tempfile::tempdir().unwrap();
         let plugin1 = create_some_plugin_in(&plugin_dir);
         let _res = std::fs::copy(get_dummy_plugin_path(), plugin1.path());
@@ -141,8 +159,9 @@ mod tests {
             None,
             TEdgeConfigLocation::default(),
         )
+        .await
         .unwrap();
-        plugins.load().unwrap();
+        plugins.load().await.unwrap();
 
         assert_eq!(
             plugins.by_software_type("default").unwrap().name,
@@ -152,8 +171,8 @@ mod tests {
     }
 
     #[ignore = "dependency on tedge-dummy-plugin"]
-    #[test]
-    fn implicit_default_plugin_with_only_one_plugin() {
+    #[tokio::test]
+    async fn implicit_default_plugin_with_only_one_plugin() {
         let plugin_dir = tempfile::tempdir().unwrap();
 
         let plugin = create_some_plugin_in(&plugin_dir);
@@ -173,8 +192,9 @@ mod tests {
             None,
             TEdgeConfigLocation::default(),
         )
+        .await
         .unwrap();
-        plugins.load().unwrap();
+        plugins.load().await.unwrap();
 
         assert_eq!(
             plugins.by_software_type("default").unwrap().name,
@@ -183,8 +203,8 @@ mod tests {
         assert_eq!(plugins.default().unwrap().name, plugin_name);
     }
 
-    #[test]
-    fn invalid_default_plugin_pass_through() -> anyhow::Result<()> {
+    #[tokio::test]
+    async fn invalid_default_plugin_pass_through() -> anyhow::Result<()> {
         let plugin_dir = tempfile::tempdir().unwrap();
         let plugin_file_path = plugin_dir.path().join("apt");
         let _ = File::create(plugin_file_path).unwrap();
@@ -194,13 +214,314 @@ mod tests {
             Some("dummy".into()),
             None,
             TEdgeConfigLocation::default(),
-        )?;
+        )
+        .await?;
         assert!(result.empty());
         assert!(result.default().is_none());
 
         Ok(())
     }
 
+    #[tokio::test]
+    async fn plugin_manager_detects_the_plugin_api_version() -> anyhow::Result<()> {
+        let plugin_dir = tempfile::tempdir().unwrap();
+        create_script_plugin_in(
+            &plugin_dir,
+            "v1",
+            "case \"$1\" in list) ;; *) exit 1 ;; esac",
+        );
+        create_script_plugin_in(
+            &plugin_dir,
+            "v2",
+            "case \"$1\" in list) ;; api-version) echo 2 ;; *) exit 1 ;; esac",
+        );
+
+        let plugins = ExternalPlugins::open(
+            plugin_dir.path(),
+            None,
+            None,
+            TEdgeConfigLocation::default(),
+        )
+        .await?;
+
+        assert_eq!(
+            plugins.by_software_type("v1").unwrap().api_version,
+            PluginApiVersion::V1
+        );
+        assert_eq!(
+            plugins.by_software_type("v2").unwrap().api_version,
+            PluginApiVersion::V2
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn plugins_are_asked_their_api_version_once_per_update() -> anyhow::Result<()> {
+        let plugin_dir = tempfile::tempdir().unwrap();
+        let probes = plugin_dir.path().join("probes");
+        let script = |version: &str| {
+            format!(
+                "case \"$1\" in list) ;; api-version) echo probed >> {}; echo {version} ;; *) exit 1 ;; esac",
+                probes.display()
+            )
+        };
+        create_script_plugin_in(&plugin_dir, "apt", &script("1"));
+
+        let mut plugins = ExternalPlugins::open(
+            plugin_dir.path(),
+            None,
+            None,
+            TEdgeConfigLocation::default(),
+        )
+        .await?;
+        plugins.load().await?;
+        assert_eq!(std::fs::read_to_string(&probes)?, "probed\n");
+        assert_eq!(
+            plugins.by_software_type("apt").unwrap().api_version,
+            PluginApiVersion::V1
+        );
+
+        // The plugin is asked again when updated
+        create_script_plugin_in(&plugin_dir, "apt", &script("2"));
+        plugins.load().await?;
+        assert_eq!(std::fs::read_to_string(&probes)?, "probed\nprobed\n");
+        assert_eq!(
+            plugins.by_software_type("apt").unwrap().api_version,
+            PluginApiVersion::V2
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn updates_are_applied_after_their_dependencies() -> anyhow::Result<()> {
+        let state_dir = tempfile::tempdir()?;
+        let plugin_dir = stateful_plugins(&state_dir);
+        let plugins = ExternalPlugins::open(
+            plugin_dir.path(),
+            None,
+            None,
+            TEdgeConfigLocation::default(),
+        )
+        .await?;
+
+        // The app requires the database, which is declared after the app
+        let request = update_request(
+            vec![
+                ("apt", vec![install("app", "1.0", &[("container", "db")])]),
+                ("container", vec![install("db", "5.0", &[])]),
+            ],
+            false,
+        );
+        let response = process(&plugins, &state_dir, request).await;
+
+        assert_eq!(response.status(), CommandStatus::Successful);
+        assert_eq!(installed(&state_dir, "container"), "db\t5.0\n");
+        assert_eq!(installed(&state_dir, "apt"), "app\t1.0\n");
+        assert_eq!(
+            std::fs::read_to_string(state_dir.path().join("history"))?,
+            "container install db\napt install app\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn applied_updates_are_rolled_back_on_failure() -> anyhow::Result<()> {
+        let state_dir = tempfile::tempdir()?;
+        std::fs::write(state_dir.path().join("apt"), "libssl\t1.0\n")?;
+        let plugin_dir = stateful_plugins(&state_dir);
+        let plugins = ExternalPlugins::open(
+            plugin_dir.path(),
+            None,
+            None,
+            TEdgeConfigLocation::default(),
+        )
+        .await?;
+
+        let request = update_request(
+            vec![
+                ("apt", vec![install("libssl", "2.0", &[])]),
+                (
+                    "container",
+                    vec![install("app", "1.0", &[("apt", "libssl")])],
+                ),
+                ("apt", vec![install("broken", "1.0", &[("", "app")])]),
+            ],
+            true,
+        );
+        let response = process(&plugins, &state_dir, request).await;
+
+        // The modules are restored as before the update
+        assert_eq!(installed(&state_dir, "apt"), "libssl\t1.0\n");
+        assert_eq!(installed(&state_dir, "container"), "");
+
+        assert!(matches!(
+            response.status(),
+            CommandStatus::Failed { reason } if reason.ends_with("; the updates already applied have been rolled back")
+        ));
+        let failures: Vec<(String, String, String)> = response
+            .payload
+            .failures
+            .iter()
+            .flat_map(|list| {
+                list.modules.iter().map(|module| {
+                    (
+                        list.plugin_type.clone(),
+                        module.name.clone(),
+                        module.reason.clone().unwrap_or_default(),
+                    )
+                })
+            })
+            .collect();
+        assert_eq!(
+            failures,
+            vec![
+                (
+                    "apt".to_string(),
+                    "broken".to_string(),
+                    "cannot install broken\n".to_string()
+                ),
+                (
+                    "apt".to_string(),
+                    "libssl".to_string(),
+                    "Rolled back".to_string()
+                ),
+                (
+                    "container".to_string(),
+                    "app".to_string(),
+                    "Rolled back".to_string()
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn circular_dependencies_are_rejected() -> anyhow::Result<()> {
+        let state_dir = tempfile::tempdir()?;
+        let plugin_dir = stateful_plugins(&state_dir);
+        let plugins = ExternalPlugins::open(
+            plugin_dir.path(),
+            None,
+            None,
+            TEdgeConfigLocation::default(),
+        )
+        .await?;
+
+        let request = update_request(
+            vec![
+                ("apt", vec![install("a", "1.0", &[("container", "b")])]),
+                ("container", vec![install("b", "1.0", &[("apt", "a")])]),
+            ],
+            false,
+        );
+        let response = process(&plugins, &state_dir, request).await;
+
+        assert_eq!(
+            response.status(),
+            CommandStatus::Failed {
+                reason: "Invalid dependencies between software updates: circular dependencies between a, b".into()
+            }
+        );
+        assert!(!state_dir.path().join("history").exists());
+        Ok(())
+    }
+
+    /// Create plugins for the `apt` and `container` types, which record the installed modules in the state directory
+    fn stateful_plugins(state_dir: &tempfile::TempDir) -> tempfile::TempDir {
+        let plugin_dir = tempfile::tempdir().unwrap();
+        for software_type in ["apt", "container"] {
+            let state = state_dir.path().join(software_type);
+            let history = state_dir.path().join("history");
+            create_script_plugin_in(
+                &plugin_dir,
+                software_type,
+                &format!(
+                    r#"STATE={state}
+touch "$STATE"
+TAB=$(printf '\t')
+case "$1" in
+    list) cat "$STATE" ;;
+    prepare|finalize) ;;
+    install)
+        if [ "$2" = broken ]; then echo "cannot install $2" >&2; exit 2; fi
+        grep -v "^$2$TAB" "$STATE" > "$STATE.tmp"; mv "$STATE.tmp" "$STATE"
+        printf '%s\t%s\n' "$2" "$4" >> "$STATE"
+        echo "{software_type} install $2" >> {history}
+        ;;
+    remove)
+        grep -v "^$2$TAB" "$STATE" > "$STATE.tmp"; mv "$STATE.tmp" "$STATE"
+        echo "{software_type} remove $2" >> {history}
+        ;;
+    *) exit 1 ;;
+esac"#,
+                    state = state.display(),
+                    history = history.display(),
+                ),
+            );
+        }
+        plugin_dir
+    }
+
+    fn installed(state_dir: &tempfile::TempDir, software_type: &str) -> String {
+        std::fs::read_to_string(state_dir.path().join(software_type)).unwrap()
+    }
+
+    fn install(name: &str, version: &str, depends_on: &[(&str, &str)]) -> SoftwareModuleItem {
+        SoftwareModuleItem {
+            name: name.into(),
+            version: Some(version.into()),
+            url: None,
+            action: Some(SoftwareModuleAction::Install),
+            reason: None,
+            depends_on: depends_on
+                .iter()
+                .map(|(module_type, name)| SoftwareModuleRef {
+                    module_type: (!module_type.is_empty()).then(|| module_type.to_string()),
+                    name: name.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    fn update_request(
+        update_list: Vec<(&str, Vec<SoftwareModuleItem>)>,
+        rollback: bool,
+    ) -> SoftwareUpdateCommand {
+        let mut request =
+            SoftwareUpdateCommand::new(&EntityTopicId::default_main_device(), "1".into());
+        request.payload.rollback = rollback;
+        request.payload.update_list = update_list
+            .into_iter()
+            .map(
+                |(plugin_type, modules)| SoftwareRequestResponseSoftwareList {
+                    plugin_type: plugin_type.into(),
+                    modules,
+                },
+            )
+            .collect();
+        request
+    }
+
+    async fn process(
+        plugins: &ExternalPlugins,
+        state_dir: &tempfile::TempDir,
+        request: SoftwareUpdateCommand,
+    ) -> SoftwareUpdateCommand {
+        let log_file = LogFile::try_new(state_dir.path().join("update.log"))
+            .await
+            .unwrap();
+        let (progress, _) = tokio::sync::mpsc::unbounded_channel();
+        plugins
+            .process(request, log_file, state_dir.path(), progress)
+            .await
+    }
+
+    fn create_script_plugin_in(dir: &tempfile::TempDir, name: &str, script: &str) {
+        let path = dir.path().join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
     fn create_some_plugin_in(dir: &tempfile::TempDir) -> NamedTempFile {
         tempfile::Builder::new()
             .suffix(".0")
@@ -1,6 +1,9 @@
+use camino::Utf8PathBuf;
 use tedge_config::OptionalConfigError;
 
 use super::create::CreateCertCmd;
+use super::create_csr::CreateCsrCmd;
+use super::import::ImportCertCmd;
 use super::remove::RemoveCertCmd;
 use super::renew::RenewCertCmd;
 use super::show::ShowCertCmd;
@@ -20,6 +23,31 @@ pub enum TEdgeCertCli {
         id: String,
     },
 
+    /// Create a certificate signing request for the device
+    ///
+    /// The request is created for the device private key, a new key being created if missing.
+    /// The request has then to be signed by the CA of your PKI,
+    /// the returned certificate being installed with `tedge cert import`.
+    CreateCsr {
+        /// The device identifier to be used as the common name for the certificate,
+        /// if not set, this is the current `device.id`
+        #[clap(long = "device-id")]
+        id: Option<String>,
+
+        /// Path where the request is stored, if not set, this is `device.csr_path`
+        #[clap(long = "output-path")]
+        output_path: Option<Utf8PathBuf>,
+    },
+
+    /// Import a CA-signed device certificate
+    ///
+    /// The certificate chain is checked against the device private key,
+    /// before being installed as the device certificate.
+    Import {
+        /// Path of the PEM file with the device certificate, followed by the CA certificates, if any
+        chain_path: Utf8PathBuf,
+    },
+
     /// Renew the device certificate
     Renew,
 
@@ -48,6 +76,28 @@ impl BuildCommand for TEdgeCertCli {
                 cmd.into_boxed()
             }
 
+            TEdgeCertCli::CreateCsr { id, output_path } => {
+                let id = match id {
+                    Some(id) => id,
+                    None => config.device.id.try_read(&config)?.clone(),
+                };
+                let cmd = CreateCsrCmd {
+                    id,
+                    key_path: config.device.key_path.clone(),
+                    csr_path: output_path.unwrap_or_else(|| config.device.csr_path.clone()),
+                };
+                cmd.into_boxed()
+            }
+
+            TEdgeCertCli::Import { chain_path } => {
+                let cmd = ImportCertCmd {
+                    chain_path,
+                    cert_path: config.device.cert_path.clone(),
+                    key_path: config.device.key_path.clone(),
+                };
+                cmd.into_boxed()
+            }
+
             TEdgeCertCli::Show => {
                 let cmd = ShowCertCmd {
                     cert_path: config.device.cert_path.clone(),
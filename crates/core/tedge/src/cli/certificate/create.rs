@@ -4,10 +4,8 @@ use camino::Utf8PathBuf;
 use certificate::KeyCertPair;
 use certificate::KeyKind;
 use certificate::NewCertificateConfig;
-use std::fs::File;
-use std::fs::OpenOptions;
 use std::io::prelude::*;
-use std::path::Path;
+use tedge_utils::file::create_new_file_with_user_group as create_new_file;
 use tedge_utils::paths::set_permission;
 use tedge_utils::paths::validate_parent_dir_exists;
 
@@ -60,7 +58,7 @@ impl CreateCertCmd {
         // Creating files with permission 644 owned by the MQTT broker
         let mut cert_file =
             create_new_file(&self.cert_path, crate::BROKER_USER, crate::BROKER_GROUP)
-                .map_err(|err| err.cert_context(self.cert_path.clone()))?;
+                .map_err(|err| CertError::IoError(err).cert_context(self.cert_path.clone()))?;
 
         let cert_pem = cert.certificate_pem_string()?;
         cert_file.write_all(cert_pem.as_bytes())?;
@@ -70,38 +68,34 @@ impl CreateCertCmd {
         set_permission(&cert_file, 0o444)?;
 
         if let KeyKind::New = key_kind {
-            let mut key_file =
-                create_new_file(&self.key_path, crate::BROKER_USER, crate::BROKER_GROUP)
-                    .map_err(|err| err.key_context(self.key_path.clone()))?;
-
-            // Make sure the key is secret, before write
-            set_permission(&key_file, 0o600)?;
-
-            // Zero the private key on drop
-            let cert_key = cert.private_key_pem_string()?;
-            key_file.write_all(cert_key.as_bytes())?;
-            key_file.sync_all()?;
-
-            // Prevent the key to be overwritten
-            set_permission(&key_file, 0o400)?;
+            create_private_key_file(&self.key_path, &cert)?;
         }
 
         Ok(())
     }
 }
 
-fn create_new_file(path: impl AsRef<Path>, user: &str, group: &str) -> Result<File, CertError> {
-    let file = OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .open(path.as_ref())?;
+/// Store the private key of a key pair, making sure the file is only readable by the MQTT broker
+/// and the thin-edge services, the agent using this key to renew the device certificate
+pub(crate) fn create_private_key_file(
+    key_path: &Utf8PathBuf,
+    cert: &KeyCertPair,
+) -> Result<(), CertError> {
+    let mut key_file = create_new_file(key_path, crate::BROKER_USER, crate::TEDGE_GROUP)
+        .map_err(|err| CertError::IoError(err).key_context(key_path.clone()))?;
+
+    // Make sure the key is secret, before write
+    set_permission(&key_file, 0o640)?;
+
+    // Zero the private key on drop
+    let cert_key = cert.private_key_pem_string()?;
+    key_file.write_all(cert_key.as_bytes())?;
+    key_file.sync_all()?;
 
-    // Ignore errors - This was the behavior with the now deprecated user manager.
-    // - When `tedge cert create` is not run as root, a certificate is created but owned by the user running the command.
-    // - A better approach could be to remove this `chown` and run the command as mosquitto.
-    let _ = tedge_utils::file::change_user_and_group(path.as_ref(), user, group);
+    // Prevent the key to be overwritten
+    set_permission(&key_file, 0o440)?;
 
-    Ok(file)
+    Ok(())
 }
 
 #[cfg(test)]
@@ -109,6 +103,7 @@ mod tests {
     use super::*;
     use assert_matches::assert_matches;
     use std::fs;
+    use std::path::Path;
     use tempfile::*;
 
     #[test]
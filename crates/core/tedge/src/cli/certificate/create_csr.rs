@@ -0,0 +1,150 @@
+use super::create::create_private_key_file;
+use super::error::CertError;
+use crate::command::Command;
+use camino::Utf8PathBuf;
+use certificate::KeyCertPair;
+use certificate::KeyKind;
+use certificate::NewCertificateConfig;
+use tedge_utils::paths::validate_parent_dir_exists;
+
+/// Create a certificate signing request for the device
+pub struct CreateCsrCmd {
+    /// The device identifier
+    pub id: String,
+
+    /// The path of the device private key, created if missing
+    pub key_path: Utf8PathBuf,
+
+    /// The path where the certificate signing request will be stored
+    pub csr_path: Utf8PathBuf,
+}
+
+impl Command for CreateCsrCmd {
+    fn description(&self) -> String {
+        format!(
+            "create a certificate signing request for the device {}.",
+            self.id
+        )
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let config = NewCertificateConfig::default();
+        self.create_certificate_signing_request(&config)?;
+        eprintln!(
+            "Certificate signing request was successfully created at {}",
+            self.csr_path
+        );
+        Ok(())
+    }
+}
+
+impl CreateCsrCmd {
+    pub fn create_certificate_signing_request(
+        &self,
+        config: &NewCertificateConfig,
+    ) -> Result<(), CertError> {
+        validate_parent_dir_exists(&self.csr_path).map_err(CertError::CsrPathError)?;
+        validate_parent_dir_exists(&self.key_path).map_err(CertError::KeyPathError)?;
+
+        // Reuse the private key of the device, if any
+        let key_kind = match std::fs::read_to_string(&self.key_path) {
+            Ok(keypair_pem) => KeyKind::Reuse { keypair_pem },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => KeyKind::New,
+            Err(err) => return Err(CertError::IoError(err).key_context(self.key_path.clone())),
+        };
+
+        let csr = KeyCertPair::new_certificate_signing_request(config, &self.id, &key_kind)?;
+
+        if let KeyKind::New = key_kind {
+            create_private_key_file(&self.key_path, &csr)?;
+        }
+
+        // A CSR is not secret and can be overwritten by a new one
+        let csr_pem = csr.certificate_signing_request_pem_string()?;
+        std::fs::write(&self.csr_path, csr_pem)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CreateCertCmd;
+    use std::fs;
+    use tempfile::*;
+
+    #[test]
+    fn create_csr_along_a_new_key() {
+        let dir = tempdir().unwrap();
+        let key_path = temp_file_path(&dir, "my-device-key.pem");
+        let csr_path = temp_file_path(&dir, "my-device.csr");
+
+        let cmd = CreateCsrCmd {
+            id: "my-device-id".into(),
+            key_path: key_path.clone(),
+            csr_path: csr_path.clone(),
+        };
+
+        cmd.create_certificate_signing_request(&NewCertificateConfig::default())
+            .unwrap();
+
+        assert_eq!(parse_pem_file(&key_path).tag, "PRIVATE KEY");
+        assert_eq!(parse_pem_file(&csr_path).tag, "CERTIFICATE REQUEST");
+    }
+
+    #[test]
+    fn create_csr_for_the_existing_key() {
+        let dir = tempdir().unwrap();
+        let cert_path = temp_file_path(&dir, "my-device-cert.pem");
+        let key_path = temp_file_path(&dir, "my-device-key.pem");
+        let csr_path = temp_file_path(&dir, "my-device.csr");
+
+        CreateCertCmd {
+            id: "my-device-id".into(),
+            cert_path,
+            key_path: key_path.clone(),
+        }
+        .create_test_certificate(&NewCertificateConfig::default())
+        .unwrap();
+        let key = fs::read_to_string(&key_path).unwrap();
+
+        let cmd = CreateCsrCmd {
+            id: "my-device-id".into(),
+            key_path: key_path.clone(),
+            csr_path: csr_path.clone(),
+        };
+        cmd.create_certificate_signing_request(&NewCertificateConfig::default())
+            .unwrap();
+
+        // The key is unchanged
+        assert_eq!(fs::read_to_string(&key_path).unwrap(), key);
+        assert_eq!(parse_pem_file(&csr_path).tag, "CERTIFICATE REQUEST");
+    }
+
+    #[test]
+    fn create_csr_in_non_existent_directory() {
+        let dir = tempdir().unwrap();
+        let key_path = temp_file_path(&dir, "my-device-key.pem");
+        let csr_path = Utf8PathBuf::from("/non/existent/csr/path");
+
+        let cmd = CreateCsrCmd {
+            id: "my-device-id".into(),
+            key_path,
+            csr_path,
+        };
+
+        let err = cmd
+            .create_certificate_signing_request(&NewCertificateConfig::default())
+            .unwrap_err();
+        assert!(matches!(err, CertError::CsrPathError { .. }));
+    }
+
+    fn temp_file_path(dir: &TempDir, filename: &str) -> Utf8PathBuf {
+        dir.path().join(filename).try_into().unwrap()
+    }
+
+    fn parse_pem_file(path: &Utf8PathBuf) -> pem::Pem {
+        pem::parse(fs::read(path).unwrap()).unwrap()
+    }
+}
@@ -84,6 +84,16 @@ pub enum CertError {
 
     #[error("This certificate {path} is not a self-signed certificate")]
     NotASelfSignedCertificate { path: Utf8PathBuf },
+
+    #[error("Invalid device.csr_path path: {0}")]
+    CsrPathError(PathsError),
+
+    #[error("Cannot read the certificate chain from {path}")]
+    CertificateChainReadFailed {
+        path: Utf8PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
 }
 
 impl CertError {
@@ -0,0 +1,178 @@
+use super::error::CertError;
+use crate::command::Command;
+use camino::Utf8PathBuf;
+use certificate::PemCertificateChain;
+use tedge_utils::file::replace_with_read_only_file;
+use tedge_utils::paths::validate_parent_dir_exists;
+
+/// Install a CA-signed certificate chain as the device certificate
+pub struct ImportCertCmd {
+    /// The path of the certificate chain to be imported
+    pub chain_path: Utf8PathBuf,
+
+    /// The path where the device certificate will be stored
+    pub cert_path: Utf8PathBuf,
+
+    /// The path of the device private key
+    pub key_path: Utf8PathBuf,
+}
+
+impl Command for ImportCertCmd {
+    fn description(&self) -> String {
+        format!("import the device certificate from {}", self.chain_path)
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        self.import_certificate()?;
+        eprintln!("Certificate was successfully imported");
+        Ok(())
+    }
+}
+
+impl ImportCertCmd {
+    pub fn import_certificate(&self) -> Result<(), CertError> {
+        let chain_pem = std::fs::read_to_string(&self.chain_path).map_err(|source| {
+            CertError::CertificateChainReadFailed {
+                path: self.chain_path.clone(),
+                source,
+            }
+        })?;
+        let key_pem = std::fs::read_to_string(&self.key_path)
+            .map_err(|e| CertError::IoError(e).key_context(self.key_path.clone()))?;
+
+        let chain = PemCertificateChain::from_pem_string(&chain_pem)?;
+        chain.validate_against_key(&key_pem)?;
+
+        validate_parent_dir_exists(&self.cert_path).map_err(CertError::CertPathError)?;
+
+        replace_with_read_only_file(
+            &self.cert_path,
+            chain_pem.as_bytes(),
+            crate::BROKER_USER,
+            crate::BROKER_GROUP,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::certificate::create_csr::CreateCsrCmd;
+    use crate::CreateCertCmd;
+    use certificate::NewCertificateConfig;
+    use certificate::PemCertificate;
+    use std::fs;
+    use tempfile::*;
+
+    #[test]
+    fn import_a_ca_signed_certificate() {
+        let dir = tempdir().unwrap();
+        let cert_path = temp_file_path(&dir, "my-device-cert.pem");
+        let key_path = temp_file_path(&dir, "my-device-key.pem");
+        let chain_path = temp_file_path(&dir, "chain.pem");
+
+        // The device starts with a self-signed certificate
+        CreateCertCmd {
+            id: "my-device-id".into(),
+            cert_path: cert_path.clone(),
+            key_path: key_path.clone(),
+        }
+        .create_test_certificate(&NewCertificateConfig::default())
+        .unwrap();
+
+        let ca = new_ca();
+        let device_cert = sign_device_key(&ca, "my-device-id", &key_path);
+        fs::write(
+            &chain_path,
+            format!("{device_cert}{}", ca.serialize_pem().unwrap()),
+        )
+        .unwrap();
+
+        let cmd = ImportCertCmd {
+            chain_path,
+            cert_path: cert_path.clone(),
+            key_path,
+        };
+        cmd.import_certificate().unwrap();
+
+        let pem = PemCertificate::from_pem_file(&cert_path).unwrap();
+        assert_eq!(pem.subject_common_name().unwrap(), "my-device-id");
+        assert_eq!(pem.issuer().unwrap(), "CN=Test CA");
+    }
+
+    #[test]
+    fn reject_a_certificate_issued_for_another_key() {
+        let dir = tempdir().unwrap();
+        let cert_path = temp_file_path(&dir, "my-device-cert.pem");
+        let key_path = temp_file_path(&dir, "my-device-key.pem");
+        let other_key_path = temp_file_path(&dir, "other-key.pem");
+        let chain_path = temp_file_path(&dir, "chain.pem");
+
+        for path in [&key_path, &other_key_path] {
+            CreateCsrCmd {
+                id: "my-device-id".into(),
+                key_path: path.clone(),
+                csr_path: temp_file_path(&dir, "my-device.csr"),
+            }
+            .create_certificate_signing_request(&NewCertificateConfig::default())
+            .unwrap();
+        }
+
+        let ca = new_ca();
+        let device_cert = sign_device_key(&ca, "my-device-id", &other_key_path);
+        fs::write(&chain_path, device_cert).unwrap();
+
+        let cmd = ImportCertCmd {
+            chain_path,
+            cert_path: cert_path.clone(),
+            key_path,
+        };
+        let err = cmd.import_certificate().unwrap_err();
+
+        assert!(matches!(err, CertError::CertificateError(_)));
+        assert!(!cert_path.exists());
+    }
+
+    #[test]
+    fn import_a_missing_chain() {
+        let dir = tempdir().unwrap();
+        let cmd = ImportCertCmd {
+            chain_path: temp_file_path(&dir, "chain.pem"),
+            cert_path: temp_file_path(&dir, "my-device-cert.pem"),
+            key_path: temp_file_path(&dir, "my-device-key.pem"),
+        };
+
+        let err = cmd.import_certificate().unwrap_err();
+        assert!(matches!(err, CertError::CertificateChainReadFailed { .. }));
+    }
+
+    fn new_ca() -> rcgen::Certificate {
+        let mut params = rcgen::CertificateParams::default();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "Test CA");
+        params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        rcgen::Certificate::from_params(params).unwrap()
+    }
+
+    /// Issue a certificate signed by the CA for the key stored at the given path
+    fn sign_device_key(ca: &rcgen::Certificate, id: &str, key_path: &Utf8PathBuf) -> String {
+        let key_pem = fs::read_to_string(key_path).unwrap();
+        let mut params = rcgen::CertificateParams::default();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, id);
+        params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+        params.key_pair = Some(rcgen::KeyPair::from_pem(&key_pem).unwrap());
+        rcgen::Certificate::from_params(params)
+            .unwrap()
+            .serialize_pem_with_signer(ca)
+            .unwrap()
+    }
+
+    fn temp_file_path(dir: &TempDir, filename: &str) -> Utf8PathBuf {
+        dir.path().join(filename).try_into().unwrap()
+    }
+}
@@ -2,7 +2,9 @@ pub use self::cli::TEdgeCertCli;
 
 mod cli;
 mod create;
+mod create_csr;
 mod error;
+mod import;
 mod remove;
 mod renew;
 mod show;
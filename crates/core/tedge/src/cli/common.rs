@@ -1,4 +1,6 @@
 use tedge_config::system_services::SystemService;
+use tedge_config::ProfileName;
+use tedge_config::ProfiledCloud;
 
 #[derive(Copy, Clone, Debug, strum_macros::Display, strum_macros::IntoStaticStr)]
 pub enum Cloud {
@@ -9,14 +11,23 @@ pub enum Cloud {
 }
 
 impl Cloud {
-    pub fn mapper_service(&self) -> SystemService {
+    pub fn mapper_service(&self, profile: Option<&ProfileName>) -> SystemService {
+        SystemService::mapper(self.profiled(), profile.cloned())
+    }
+
+    pub fn profiled(self) -> ProfiledCloud {
         match self {
-            Cloud::Aws => SystemService::TEdgeMapperAws,
-            Cloud::Azure => SystemService::TEdgeMapperAz,
-            Cloud::C8y => SystemService::TEdgeMapperC8y,
+            Cloud::Aws => ProfiledCloud::Aws,
+            Cloud::Azure => ProfiledCloud::Az,
+            Cloud::C8y => ProfiledCloud::C8y,
         }
     }
 
+    /// The name of the connection to this cloud, e.g. `c8y` or `c8y@staging`
+    pub fn connection_name(self, profile: Option<&ProfileName>) -> String {
+        self.profiled().connection_name(profile)
+    }
+
     pub fn as_str(self) -> &'static str {
         self.into()
     }
@@ -1,6 +1,7 @@
 use crate::cli::config::commands::*;
 use crate::command::*;
 use crate::ConfigError;
+use tedge_config::ProfiledKey;
 use tedge_config::ReadableKey;
 use tedge_config::WritableKey;
 
@@ -9,13 +10,17 @@ pub enum ConfigCmd {
     /// Get the value of the provided configuration key
     Get {
         /// Configuration key. Run `tedge config list --doc` for available keys
-        key: ReadableKey,
+        ///
+        /// The keys of a cloud profile are prefixed by `<cloud>.profiles.<profile>`, e.g. `c8y.profiles.staging.url`
+        key: ProfiledKey<ReadableKey>,
     },
 
     /// Set or update the provided configuration key with the given value
     Set {
         /// Configuration key. Run `tedge config list --doc` for available keys
-        key: WritableKey,
+        ///
+        /// The keys of a cloud profile are prefixed by `<cloud>.profiles.<profile>`, e.g. `c8y.profiles.staging.url`
+        key: ProfiledKey<WritableKey>,
 
         /// Configuration value.
         value: String,
@@ -24,7 +29,9 @@ pub enum ConfigCmd {
     /// Unset the provided configuration key
     Unset {
         /// Configuration key. Run `tedge config list --doc` for available keys
-        key: WritableKey,
+        ///
+        /// The keys of a cloud profile are prefixed by `<cloud>.profiles.<profile>`, e.g. `c8y.profiles.staging.url`
+        key: ProfiledKey<WritableKey>,
     },
 
     /// Print the configuration keys and their values
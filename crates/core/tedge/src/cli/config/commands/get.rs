@@ -1,9 +1,10 @@
+use tedge_config::ProfiledKey;
 use tedge_config::ReadableKey;
 
 use crate::command::Command;
 
 pub struct GetConfigCommand {
-    pub key: ReadableKey,
+    pub key: ProfiledKey<ReadableKey>,
     pub config: tedge_config::TEdgeConfig,
 }
 
@@ -13,7 +14,17 @@ impl Command for GetConfigCommand {
     }
 
     fn execute(&self) -> anyhow::Result<()> {
-        match self.config.read_string(self.key) {
+        let value = match &self.key.profile {
+            None => self.config.read_string(self.key.key),
+            Some((cloud, profile)) => match self.config.profile(*cloud, profile) {
+                Ok(config) => config.read_string(self.key.key),
+                Err(_) => {
+                    eprintln!("The provided config key: '{}' is not set", self.key);
+                    return Ok(());
+                }
+            },
+        };
+        match value {
             Ok(value) => {
                 println!("{}", value);
             }
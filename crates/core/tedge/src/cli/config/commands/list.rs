@@ -3,6 +3,8 @@ use crate::ConfigError;
 use pad::PadStr;
 use std::io::stdout;
 use std::io::IsTerminal;
+use tedge_config::ProfiledCloud;
+use tedge_config::ProfiledKey;
 use tedge_config::ReadableKey;
 use tedge_config::TEdgeConfig;
 use tedge_config::READABLE_KEYS;
@@ -37,7 +39,30 @@ fn print_config_list(config: &TEdgeConfig, all: bool) -> Result<(), ConfigError>
                 println!("{}={}", config_key, value);
             }
             None => {
-                keys_without_values.push(config_key);
+                keys_without_values.push(config_key.to_string());
+            }
+        }
+    }
+    for cloud in [ProfiledCloud::C8y, ProfiledCloud::Az, ProfiledCloud::Aws] {
+        for profile in config.profile_names(cloud) {
+            let profile_config = config.profile(cloud, &profile)?;
+            let cloud_prefix = format!("{cloud}.");
+            for config_key in ReadableKey::iter() {
+                if !config_key.as_str().starts_with(&cloud_prefix) {
+                    continue;
+                }
+                let key = ProfiledKey {
+                    profile: Some((cloud, profile.clone())),
+                    key: config_key,
+                };
+                match profile_config.read_string(config_key).ok() {
+                    Some(value) => {
+                        println!("{}={}", key, value);
+                    }
+                    None => {
+                        keys_without_values.push(key.to_string());
+                    }
+                }
             }
         }
     }
@@ -1,9 +1,10 @@
 use crate::command::Command;
+use tedge_config::ProfiledKey;
 use tedge_config::TEdgeConfigRepository;
 use tedge_config::WritableKey;
 
 pub struct SetConfigCommand {
-    pub key: WritableKey,
+    pub key: ProfiledKey<WritableKey>,
     pub value: String,
     pub config_repository: TEdgeConfigRepository,
 }
@@ -12,15 +13,20 @@ impl Command for SetConfigCommand {
     fn description(&self) -> String {
         format!(
             "set the configuration key: '{}' with value: {}.",
-            self.key.as_str(),
-            self.value
+            self.key, self.value
         )
     }
 
     fn execute(&self) -> anyhow::Result<()> {
+        let key = self.key.key;
         self.config_repository.update_toml(&|dto| {
-            dto.try_update_str(self.key, &self.value)
-                .map_err(|e| e.into())
+            match &self.key.profile {
+                None => dto.try_update_str(key, &self.value),
+                Some((cloud, profile)) => {
+                    dto.update_profile(*cloud, profile, |dto| dto.try_update_str(key, &self.value))
+                }
+            }
+            .map_err(|e| e.into())
         })?;
         Ok(())
     }
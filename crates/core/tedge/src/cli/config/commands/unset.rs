@@ -1,9 +1,10 @@
 use crate::command::Command;
+use tedge_config::ProfiledKey;
 use tedge_config::TEdgeConfigRepository;
 use tedge_config::WritableKey;
 
 pub struct UnsetConfigCommand {
-    pub key: WritableKey,
+    pub key: ProfiledKey<WritableKey>,
     pub config_repository: TEdgeConfigRepository,
 }
 
@@ -13,8 +14,14 @@ impl Command for UnsetConfigCommand {
     }
 
     fn execute(&self) -> anyhow::Result<()> {
+        let key = self.key.key;
         self.config_repository.update_toml(&|dto| {
-            dto.unset_key(self.key);
+            match &self.key.profile {
+                None => dto.unset_key(key),
+                Some((cloud, profile)) => {
+                    dto.update_profile(*cloud, profile, |dto| dto.unset_key(key))
+                }
+            }
             Ok(())
         })?;
         Ok(())
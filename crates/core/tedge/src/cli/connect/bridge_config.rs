@@ -2,6 +2,7 @@ use crate::cli::connect::ConnectError;
 
 use camino::Utf8PathBuf;
 use reqwest::Url;
+use tedge_config::ProfileName;
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct BridgeConfig {
@@ -91,12 +92,52 @@ impl BridgeConfig {
             return Err(ConnectError::Certificate);
         }
 
-        if !self.bridge_keyfile.exists() {
+        // A key stored on a PKCS#11 token, as supported by the built-in bridge, is not a file
+        if !self.bridge_keyfile.as_str().starts_with("pkcs11:") && !self.bridge_keyfile.exists() {
             return Err(ConnectError::Certificate);
         }
 
         Ok(())
     }
+
+    /// Attach this bridge to a named cloud profile
+    ///
+    /// The bridge configuration file, the connection, the local client id, the health topic
+    /// and the local topics are all renamed after the profile (e.g. `c8y@staging/s/us`),
+    /// so that several bridges to the same type of cloud can run side by side.
+    pub fn with_profile(self, profile: Option<&ProfileName>) -> Self {
+        let Some(profile) = profile else {
+            return self;
+        };
+        let cloud = self.cloud_name.clone();
+        let name = format!("{cloud}@{profile}");
+        BridgeConfig {
+            config_file: format!("{name}-bridge.conf"),
+            connection: format!("edge_to_{name}"),
+            local_clientid: format!("{}@{profile}", self.local_clientid),
+            notification_topic: self.notification_topic.replace(
+                &format!("/mosquitto-{cloud}-bridge/"),
+                &format!("/mosquitto-{name}-bridge/"),
+            ),
+            topics: self
+                .topics
+                .iter()
+                .map(|topic| rename_local_prefix(topic, &cloud, &name))
+                .collect(),
+            ..self
+        }
+    }
+}
+
+/// Rename the local prefix of a bridge topic rule: `pattern direction qos local-prefix remote-prefix`
+fn rename_local_prefix(topic: &str, cloud: &str, name: &str) -> String {
+    let mut tokens: Vec<String> = topic.split(' ').map(str::to_owned).collect();
+    if let Some(local_prefix) = tokens.get_mut(3) {
+        if let Some(suffix) = local_prefix.strip_prefix(&format!("{cloud}/")) {
+            *local_prefix = format!("{name}/{suffix}");
+        }
+    }
+    tokens.join(" ")
 }
 
 #[cfg(test)]
@@ -328,6 +369,27 @@ bridge_attempt_unsubscribe false
         Ok(())
     }
 
+    #[test]
+    fn test_validate_accepts_a_key_stored_on_a_token() -> anyhow::Result<()> {
+        let ca_file = tempfile::NamedTempFile::new()?;
+        let bridge_ca_path = Utf8Path::from_path(ca_file.path()).unwrap();
+
+        let cert_file = tempfile::NamedTempFile::new()?;
+        let bridge_certfile = Utf8Path::from_path(cert_file.path()).unwrap().to_owned();
+
+        let config = BridgeConfig {
+            address: "http://test.com".into(),
+            bridge_root_cert_path: bridge_ca_path.to_owned(),
+            bridge_certfile,
+            bridge_keyfile: "pkcs11:token=tedge;object=device-key".into(),
+            ..default_bridge_config()
+        };
+
+        assert!(config.validate().is_ok());
+
+        Ok(())
+    }
+
     // XXX: This test is flawed as it is not clear what it tests.
     // It can fail due to either `incorrect_url` OR `non_existent_path`.
     #[test]
@@ -379,6 +441,41 @@ bridge_attempt_unsubscribe false
         Ok(())
     }
 
+    #[test]
+    fn test_with_profile_renames_the_bridge() {
+        let config = BridgeConfig {
+            cloud_name: "c8y".into(),
+            config_file: "c8y-bridge.conf".into(),
+            connection: "edge_to_c8y".into(),
+            local_clientid: "Cumulocity".into(),
+            notification_topic: "te/device/main/service/mosquitto-c8y-bridge/status/health".into(),
+            topics: vec![
+                r#"s/us/# out 2 c8y/ """#.into(),
+                r#"s/dat in 1 c8y/ """#.into(),
+            ],
+            ..default_bridge_config()
+        };
+        let profile: ProfileName = "staging".parse().unwrap();
+
+        let config = config.with_profile(Some(&profile));
+
+        assert_eq!(config.cloud_name, "c8y");
+        assert_eq!(config.config_file, "c8y@staging-bridge.conf");
+        assert_eq!(config.connection, "edge_to_c8y@staging");
+        assert_eq!(config.local_clientid, "Cumulocity@staging");
+        assert_eq!(
+            config.notification_topic,
+            "te/device/main/service/mosquitto-c8y@staging-bridge/status/health"
+        );
+        assert_eq!(
+            config.topics,
+            vec![
+                r#"s/us/# out 2 c8y@staging/ """#.to_string(),
+                r#"s/dat in 1 c8y@staging/ """#.to_string(),
+            ]
+        );
+    }
+
     fn default_bridge_config() -> BridgeConfig {
         BridgeConfig {
             cloud_name: "az/c8y".into(),
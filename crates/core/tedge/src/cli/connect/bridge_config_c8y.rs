@@ -3,6 +3,7 @@ use camino::Utf8PathBuf;
 use std::process::Command;
 use tedge_config::AutoFlag;
 use tedge_config::HostPort;
+use tedge_config::OperationsProtocol;
 use tedge_config::TemplatesSet;
 use tedge_config::MQTT_TLS_PORT;
 use which::which;
@@ -19,6 +20,7 @@ pub struct BridgeConfigC8yParams {
     pub bridge_keyfile: Utf8PathBuf,
     pub smartrest_templates: TemplatesSet,
     pub include_local_clean_session: AutoFlag,
+    pub operations_protocol: OperationsProtocol,
 }
 
 impl From<BridgeConfigC8yParams> for BridgeConfig {
@@ -32,6 +34,7 @@ impl From<BridgeConfigC8yParams> for BridgeConfig {
             bridge_keyfile,
             smartrest_templates,
             include_local_clean_session,
+            operations_protocol,
         } = params;
 
         let mut topics: Vec<String> = vec![
@@ -55,6 +58,7 @@ impl From<BridgeConfigC8yParams> for BridgeConfig {
             // c8y JSON
             r#"inventory/managedObjects/update/# out 2 c8y/ """#.into(),
             r#"measurement/measurements/create out 2 c8y/ """#.into(),
+            r#"measurement/measurements/createBulk out 2 c8y/ """#.into(),
             r#"event/events/create out 2 c8y/ """#.into(),
             r#"alarm/alarms/create out 2 c8y/ """#.into(),
             r#"error in 2 c8y/ """#.into(),
@@ -79,6 +83,13 @@ impl From<BridgeConfigC8yParams> for BridgeConfig {
             .collect::<Vec<String>>();
         topics.extend(templates_set);
 
+        if operations_protocol == OperationsProtocol::Json {
+            topics.extend([
+                r#"devicecontrol/notifications in 2 c8y/ """#.into(),
+                r#"devicecontrol/operations/update/# out 2 c8y/ """#.into(),
+            ]);
+        }
+
         let include_local_clean_session = match include_local_clean_session {
             AutoFlag::True => true,
             AutoFlag::False => false,
@@ -157,6 +168,7 @@ fn test_bridge_config_from_c8y_params() -> anyhow::Result<()> {
         bridge_keyfile: "./test-private-key.pem".into(),
         smartrest_templates: TemplatesSet::try_from(vec!["abc", "def"])?,
         include_local_clean_session: AutoFlag::False,
+        operations_protocol: OperationsProtocol::SmartRest,
     };
 
     let bridge = BridgeConfig::from(params);
@@ -195,6 +207,7 @@ fn test_bridge_config_from_c8y_params() -> anyhow::Result<()> {
             // c8y JSON
             r#"inventory/managedObjects/update/# out 2 c8y/ """#.into(),
             r#"measurement/measurements/create out 2 c8y/ """#.into(),
+            r#"measurement/measurements/createBulk out 2 c8y/ """#.into(),
             r#"event/events/create out 2 c8y/ """#.into(),
             r#"alarm/alarms/create out 2 c8y/ """#.into(),
             r#"error in 2 c8y/ """#.into(),
@@ -224,3 +237,30 @@ fn test_bridge_config_from_c8y_params() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_bridge_config_c8y_json_operations() -> anyhow::Result<()> {
+    use std::convert::TryFrom;
+    let params = BridgeConfigC8yParams {
+        mqtt_host: HostPort::<MQTT_TLS_PORT>::try_from("test.test.io".to_string())?,
+        config_file: "c8y-bridge.conf".into(),
+        remote_clientid: "alpha".into(),
+        bridge_root_cert_path: Utf8PathBuf::from("./test_root.pem"),
+        bridge_certfile: "./test-certificate.pem".into(),
+        bridge_keyfile: "./test-private-key.pem".into(),
+        smartrest_templates: TemplatesSet::default(),
+        include_local_clean_session: AutoFlag::False,
+        operations_protocol: OperationsProtocol::Json,
+    };
+
+    let bridge = BridgeConfig::from(params);
+
+    assert!(bridge
+        .topics
+        .contains(&r#"devicecontrol/notifications in 2 c8y/ """#.to_string()));
+    assert!(bridge
+        .topics
+        .contains(&r#"devicecontrol/operations/update/# out 2 c8y/ """#.to_string()));
+
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use super::BridgeConfig;
 use super::ConnectError;
 use crate::cli::connect::CONNECTION_TIMEOUT;
+use certificate::key_uri::KeyUri;
 use certificate::parse_root_certificate::create_tls_config;
 use rumqttc::tokio_rustls::rustls::AlertDescription;
 use rumqttc::tokio_rustls::rustls::CertificateError;
@@ -20,6 +21,7 @@ use rumqttc::{self};
 // Connect directly to the c8y cloud over mqtt and publish device create message.
 pub fn create_device_with_direct_connection(
     bridge_config: &BridgeConfig,
+    key_uri: &KeyUri,
     device_type: &str,
 ) -> Result<(), ConnectError> {
     const DEVICE_ALREADY_EXISTS: &[u8] = b"41,100,Device already existing";
@@ -33,7 +35,7 @@ pub fn create_device_with_direct_connection(
 
     let tls_config = create_tls_config(
         bridge_config.bridge_root_cert_path.clone().into(),
-        bridge_config.bridge_keyfile.clone().into(),
+        key_uri,
         bridge_config.bridge_certfile.clone().into(),
     )?;
     mqtt_options.set_transport(Transport::tls_with_config(tls_config.into()));
@@ -94,7 +96,7 @@ pub fn create_device_with_direct_connection(
                     } else if let AlertDescription::HandshakeFailure = alert_description {
                         // Non-paired private key is set in device.key_path
                         eprintln!(
-                            "The private key is not paired with the certificate. Check your 'device.key_path'."
+                            "The private key is not paired with the certificate. Check your 'device.key_path' or 'device.key_uri'."
                         );
                         return Err(ConnectError::ConnectionCheckError);
                     }
@@ -1,4 +1,5 @@
 use tedge_config::system_services::service_manager;
+use tedge_config::ProfileName;
 
 use crate::cli::common::Cloud;
 use crate::cli::connect::*;
@@ -15,6 +16,14 @@ pub enum TEdgeConnectOpt {
         /// Test connection to Cumulocity
         #[clap(long = "test")]
         is_test_connection: bool,
+
+        /// Render the bridge configuration and validate it, without connecting to Cumulocity
+        #[clap(long = "dry-run", conflicts_with = "is_test_connection")]
+        is_dry_run: bool,
+
+        /// The cloud profile to connect, as configured by the `c8y.profiles.<profile>.*` settings
+        #[clap(long)]
+        profile: Option<ProfileName>,
     },
 
     /// Create connection to Azure
@@ -24,6 +33,14 @@ pub enum TEdgeConnectOpt {
         /// Test connection to Azure
         #[clap(long = "test")]
         is_test_connection: bool,
+
+        /// Render the bridge configuration and validate it, without connecting to Azure
+        #[clap(long = "dry-run", conflicts_with = "is_test_connection")]
+        is_dry_run: bool,
+
+        /// The cloud profile to connect, as configured by the `az.profiles.<profile>.*` settings
+        #[clap(long)]
+        profile: Option<ProfileName>,
     },
 
     /// Create connection to AWS
@@ -33,34 +50,60 @@ pub enum TEdgeConnectOpt {
         /// Test connection to AWS
         #[clap(long = "test")]
         is_test_connection: bool,
+
+        /// Render the bridge configuration and validate it, without connecting to AWS
+        #[clap(long = "dry-run", conflicts_with = "is_test_connection")]
+        is_dry_run: bool,
+
+        /// The cloud profile to connect, as configured by the `aws.profiles.<profile>.*` settings
+        #[clap(long)]
+        profile: Option<ProfileName>,
     },
 }
 
 impl BuildCommand for TEdgeConnectOpt {
     fn build_command(self, context: BuildContext) -> Result<Box<dyn Command>, crate::ConfigError> {
         Ok(match self {
-            TEdgeConnectOpt::C8y { is_test_connection } => ConnectCommand {
+            TEdgeConnectOpt::C8y {
+                is_test_connection,
+                is_dry_run,
+                profile,
+            } => ConnectCommand {
                 config_location: context.config_location.clone(),
                 config_repository: context.config_repository,
                 cloud: Cloud::C8y,
                 common_mosquitto_config: CommonMosquittoConfig::default(),
                 is_test_connection,
+                is_dry_run,
+                profile,
                 service_manager: service_manager(&context.config_location.tedge_config_root_path)?,
             },
-            TEdgeConnectOpt::Az { is_test_connection } => ConnectCommand {
+            TEdgeConnectOpt::Az {
+                is_test_connection,
+                is_dry_run,
+                profile,
+            } => ConnectCommand {
                 config_location: context.config_location.clone(),
                 config_repository: context.config_repository,
                 cloud: Cloud::Azure,
                 common_mosquitto_config: CommonMosquittoConfig::default(),
                 is_test_connection,
+                is_dry_run,
+                profile,
                 service_manager: service_manager(&context.config_location.tedge_config_root_path)?,
             },
-            TEdgeConnectOpt::Aws { is_test_connection } => ConnectCommand {
+            TEdgeConnectOpt::Aws {
+                is_test_connection,
+                is_dry_run,
+                profile,
+            } => ConnectCommand {
                 config_location: context.config_location.clone(),
                 config_repository: context.config_repository,
                 cloud: Cloud::Aws,
                 common_mosquitto_config: CommonMosquittoConfig::default(),
                 is_test_connection,
+                is_dry_run,
+                profile,
                 service_manager: service_manager(&context.config_location.tedge_config_root_path)?,
             },
         }
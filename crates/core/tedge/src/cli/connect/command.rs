@@ -4,6 +4,7 @@ use crate::cli::connect::*;
 use crate::command::Command;
 use crate::ConfigError;
 use camino::Utf8PathBuf;
+use certificate::key_uri::KeyUri;
 use rumqttc::Event;
 use rumqttc::Incoming;
 use rumqttc::Outgoing;
@@ -29,6 +30,7 @@ pub(crate) const CONNECTION_TIMEOUT: Duration = Duration::from_secs(60);
 const MOSQUITTO_RESTART_TIMEOUT_SECONDS: u64 = 5;
 const MQTT_TLS_PORT: u16 = 8883;
 const TEDGE_BRIDGE_CONF_DIR_PATH: &str = "mosquitto-conf";
+const BUILT_IN_BRIDGE_CONF_DIR_PATH: &str = "bridge";
 
 pub struct ConnectCommand {
     pub config_location: TEdgeConfigLocation,
@@ -36,6 +38,8 @@ pub struct ConnectCommand {
     pub cloud: Cloud,
     pub common_mosquitto_config: CommonMosquittoConfig,
     pub is_test_connection: bool,
+    pub is_dry_run: bool,
+    pub profile: Option<ProfileName>,
     pub service_manager: Arc<dyn SystemServiceManager>,
 }
 
@@ -46,7 +50,12 @@ pub enum DeviceStatus {
 
 impl Command for ConnectCommand {
     fn description(&self) -> String {
-        if self.is_test_connection {
+        if self.is_dry_run {
+            format!(
+                "check the connection to {} cloud, without any change.",
+                self.cloud.as_str()
+            )
+        } else if self.is_test_connection {
             format!("test connection to {} cloud.", self.cloud.as_str())
         } else {
             format!("connect {} cloud.", self.cloud.as_str())
@@ -54,7 +63,10 @@ impl Command for ConnectCommand {
     }
 
     fn execute(&self) -> anyhow::Result<()> {
-        let config = self.config_repository.load()?;
+        let config = self
+            .config_repository
+            .load()?
+            .for_profile(self.cloud.profiled(), self.profile.as_ref())?;
         if self.is_test_connection {
             let br_config = self.bridge_config(&config)?;
             if self.check_if_bridge_exists(&br_config) {
@@ -69,13 +81,17 @@ impl Command for ConnectCommand {
                 };
             } else {
                 return Err((ConnectError::DeviceNotConnected {
-                    cloud: self.cloud.as_str().into(),
+                    cloud: self.connection_name(),
                 })
                 .into());
             }
         }
 
         let bridge_config = self.bridge_config(&config)?;
+        if self.is_dry_run {
+            return self.dry_run(&config, &bridge_config);
+        }
+
         let updated_mosquitto_config = self
             .common_mosquitto_config
             .clone()
@@ -100,13 +116,21 @@ impl Command for ConnectCommand {
             );
 
         let device_type = &config.device.ty;
+        let key_uri = config.device.key_uri().map_err(ConfigError::from)?;
+        let built_in_mapper = config
+            .mqtt
+            .bridge
+            .built_in
+            .then(|| self.cloud.mapper_service(self.profile.as_ref()));
 
         match new_bridge(
             &bridge_config,
+            &key_uri,
             &updated_mosquitto_config,
             self.service_manager.as_ref(),
             &self.config_location,
             device_type,
+            built_in_mapper,
         ) {
             Ok(()) => println!("Successfully created bridge connection!\n"),
             Err(ConnectError::SystemServiceError(
@@ -133,9 +157,10 @@ impl Command for ConnectCommand {
             if which("tedge-mapper").is_err() {
                 println!("Warning: tedge-mapper is not installed.\n");
             } else {
-                self.service_manager
-                    .as_ref()
-                    .start_and_enable_service(self.cloud.mapper_service(), std::io::stdout());
+                self.service_manager.as_ref().start_and_enable_service(
+                    self.cloud.mapper_service(self.profile.as_ref()),
+                    std::io::stdout(),
+                );
             }
         }
 
@@ -149,6 +174,7 @@ impl Command for ConnectCommand {
                     .cloned()
                     .map(|u| u.to_string())
                     .unwrap_or_default(),
+                &self.connection_name(),
             );
             enable_software_management(&bridge_config, self.service_manager.as_ref());
         }
@@ -158,53 +184,81 @@ impl Command for ConnectCommand {
 }
 
 impl ConnectCommand {
+    /// The name of the connection, e.g. `c8y` or `c8y@staging` when connecting a cloud profile
+    fn connection_name(&self) -> String {
+        self.cloud.connection_name(self.profile.as_ref())
+    }
+
     fn bridge_config(&self, config: &TEdgeConfig) -> Result<BridgeConfig, ConfigError> {
-        match self.cloud {
-            Cloud::Azure => {
-                let params = BridgeConfigAzureParams {
-                    connect_url: config.az.url.or_config_not_set()?.clone(),
-                    mqtt_tls_port: MQTT_TLS_PORT,
-                    config_file: AZURE_CONFIG_FILENAME.into(),
-                    bridge_root_cert_path: config.az.root_cert_path.clone(),
-                    remote_clientid: config.device.id.try_read(config)?.clone(),
-                    bridge_certfile: config.device.cert_path.clone(),
-                    bridge_keyfile: config.device.key_path.clone(),
-                };
+        bridge_config(config, self.cloud, self.profile.as_ref())
+    }
 
-                Ok(BridgeConfig::from(params))
+    /// Render the bridge configuration and validate it, without any side effect
+    fn dry_run(&self, config: &TEdgeConfig, bridge_config: &BridgeConfig) -> anyhow::Result<()> {
+        let built_in = config.mqtt.bridge.built_in;
+        let mapper = self.cloud.mapper_service(self.profile.as_ref());
+        println!("Dry run: no changes are made to the system.\n");
+
+        println!("Validating the bridge configuration.\n");
+        let checks = [
+            (
+                "the bridge is not configured yet",
+                bridge_config_exists(&self.config_location, bridge_config)
+                    .map_err(|e| e.to_string()),
+            ),
+            (
+                "the bridge certificates exist",
+                bridge_config.validate().map_err(|e| e.to_string()),
+            ),
+        ];
+        let mut failures = 0;
+        for (check, result) in checks {
+            match result {
+                Ok(()) => println!("  PASS  {check}"),
+                Err(err) => {
+                    failures += 1;
+                    println!("  FAIL  {check}: {err}")
+                }
             }
-            Cloud::Aws => {
-                let params = BridgeConfigAwsParams {
-                    connect_url: config.aws.url.or_config_not_set()?.clone(),
-                    mqtt_tls_port: MQTT_TLS_PORT,
-                    config_file: AWS_CONFIG_FILENAME.into(),
-                    bridge_root_cert_path: config.aws.root_cert_path.clone(),
-                    remote_clientid: config.device.id.try_read(config)?.clone(),
-                    bridge_certfile: config.device.cert_path.clone(),
-                    bridge_keyfile: config.device.key_path.clone(),
-                };
+        }
 
-                Ok(BridgeConfig::from(params))
+        let path = get_bridge_config_file_path(&self.config_location, bridge_config, built_in);
+        let mut rendered = Vec::new();
+        match bridge_config.serialize(&mut rendered) {
+            Ok(()) => println!(
+                "\nBridge configuration to be written to {path}:\n\n{}",
+                String::from_utf8_lossy(&rendered)
+            ),
+            Err(err) => {
+                failures += 1;
+                println!("\nThe bridge configuration cannot be rendered: {err}\n")
             }
-            Cloud::C8y => {
-                let params = BridgeConfigC8yParams {
-                    mqtt_host: config.c8y.mqtt.or_config_not_set()?.clone(),
-                    config_file: C8Y_CONFIG_FILENAME.into(),
-                    bridge_root_cert_path: config.c8y.root_cert_path.clone(),
-                    remote_clientid: config.device.id.try_read(config)?.clone(),
-                    bridge_certfile: config.device.cert_path.clone(),
-                    bridge_keyfile: config.device.key_path.clone(),
-                    smartrest_templates: config.c8y.smartrest.templates.clone(),
-                    include_local_clean_session: config
-                        .c8y
-                        .bridge
-                        .include
-                        .local_cleansession
-                        .clone(),
-                };
+        }
 
-                Ok(BridgeConfig::from(params))
-            }
+        println!("Actions to be taken on connect:\n");
+        if bridge_config.cloud_name.eq("c8y") {
+            println!(
+                "  - create the device {} in Cumulocity",
+                bridge_config.remote_clientid
+            );
+        }
+        println!("  - write {path}");
+        if built_in {
+            println!("  - restart {mapper}, running the built-in bridge");
+        } else {
+            println!("  - restart mosquitto");
+        }
+        if bridge_config.use_mapper {
+            println!("  - start and enable {mapper}");
+        }
+        if bridge_config.use_agent {
+            println!("  - start and enable tedge-agent");
+        }
+        println!();
+
+        match failures {
+            0 => Ok(()),
+            failures => Err(ConnectError::DryRunFailed { failures }.into()),
         }
     }
 
@@ -213,29 +267,82 @@ impl ConnectCommand {
             "Sending packets to check connection. This may take up to {} seconds.\n",
             WAIT_FOR_CHECK_SECONDS
         );
+        let prefix = self.connection_name();
         match self.cloud {
-            Cloud::Azure => check_device_status_azure(config),
-            Cloud::Aws => check_device_status_aws(config),
-            Cloud::C8y => check_device_status_c8y(config),
+            Cloud::Azure => check_device_status_azure(config, &prefix),
+            Cloud::Aws => check_device_status_aws(config, &prefix),
+            Cloud::C8y => check_device_status_c8y(config, &prefix),
         }
     }
 
     fn check_if_bridge_exists(&self, br_config: &BridgeConfig) -> bool {
-        let bridge_conf_path = self
-            .config_location
-            .tedge_config_root_path
-            .join(TEDGE_BRIDGE_CONF_DIR_PATH)
-            .join(br_config.config_file.clone());
-
-        Path::new(&bridge_conf_path).exists()
+        [false, true].into_iter().any(|built_in| {
+            get_bridge_config_file_path(&self.config_location, br_config, built_in).exists()
+        })
     }
 }
 
+/// The bridge configuration for a cloud, possibly attached to a profile
+pub(crate) fn bridge_config(
+    config: &TEdgeConfig,
+    cloud: Cloud,
+    profile: Option<&ProfileName>,
+) -> Result<BridgeConfig, ConfigError> {
+    let bridge_config = match cloud {
+        Cloud::Azure => {
+            let params = BridgeConfigAzureParams {
+                connect_url: config.az.url.or_config_not_set()?.clone(),
+                mqtt_tls_port: MQTT_TLS_PORT,
+                config_file: AZURE_CONFIG_FILENAME.into(),
+                bridge_root_cert_path: config.az.root_cert_path.clone(),
+                remote_clientid: config.device.id.try_read(config)?.clone(),
+                bridge_certfile: config.device.cert_path.clone(),
+                bridge_keyfile: bridge_key_file(config)?,
+            };
+
+            BridgeConfig::from(params)
+        }
+        Cloud::Aws => {
+            let params = BridgeConfigAwsParams {
+                connect_url: config.aws.url.or_config_not_set()?.clone(),
+                mqtt_tls_port: MQTT_TLS_PORT,
+                config_file: AWS_CONFIG_FILENAME.into(),
+                bridge_root_cert_path: config.aws.root_cert_path.clone(),
+                remote_clientid: config.device.id.try_read(config)?.clone(),
+                bridge_certfile: config.device.cert_path.clone(),
+                bridge_keyfile: bridge_key_file(config)?,
+            };
+
+            BridgeConfig::from(params)
+        }
+        Cloud::C8y => {
+            let params = BridgeConfigC8yParams {
+                mqtt_host: config.c8y.mqtt.or_config_not_set()?.clone(),
+                config_file: C8Y_CONFIG_FILENAME.into(),
+                bridge_root_cert_path: config.c8y.root_cert_path.clone(),
+                remote_clientid: config.device.id.try_read(config)?.clone(),
+                bridge_certfile: config.device.cert_path.clone(),
+                bridge_keyfile: bridge_key_file(config)?,
+                smartrest_templates: config.c8y.smartrest.templates.clone(),
+                include_local_clean_session: config.c8y.bridge.include.local_cleansession.clone(),
+                operations_protocol: config.c8y.operations.protocol,
+            };
+
+            BridgeConfig::from(params)
+        }
+    };
+
+    Ok(bridge_config.with_profile(profile))
+}
+
 // Check the connection by using the jwt token retrieval over the mqtt.
 // If successful in getting the jwt token '71,xxxxx', the connection is established.
-fn check_device_status_c8y(tedge_config: &TEdgeConfig) -> Result<DeviceStatus, ConnectError> {
-    const C8Y_TOPIC_BUILTIN_JWT_TOKEN_DOWNSTREAM: &str = "c8y/s/dat";
-    const C8Y_TOPIC_BUILTIN_JWT_TOKEN_UPSTREAM: &str = "c8y/s/uat";
+fn check_device_status_c8y(
+    tedge_config: &TEdgeConfig,
+    prefix: &str,
+) -> Result<DeviceStatus, ConnectError> {
+    let c8y_topic_builtin_jwt_token_downstream = format!("{prefix}/s/dat");
+    let c8y_topic_builtin_jwt_token_upstream = format!("{prefix}/s/uat");
     const CLIENT_ID: &str = "check_connection_c8y";
 
     let mut mqtt_options = tedge_config
@@ -253,14 +360,14 @@ fn check_device_status_c8y(tedge_config: &TEdgeConfig) -> Result<DeviceStatus, C
         .set_connection_timeout(CONNECTION_TIMEOUT.as_secs());
     let mut acknowledged = false;
 
-    client.subscribe(C8Y_TOPIC_BUILTIN_JWT_TOKEN_DOWNSTREAM, AtLeastOnce)?;
+    client.subscribe(&c8y_topic_builtin_jwt_token_downstream, AtLeastOnce)?;
 
     for event in connection.iter() {
         match event {
             Ok(Event::Incoming(Packet::SubAck(_))) => {
                 // We are ready to get the response, hence send the request
                 client.publish(
-                    C8Y_TOPIC_BUILTIN_JWT_TOKEN_UPSTREAM,
+                    &c8y_topic_builtin_jwt_token_upstream,
                     rumqttc::QoS::AtMostOnce,
                     false,
                     "",
@@ -307,11 +414,15 @@ fn check_device_status_c8y(tedge_config: &TEdgeConfig) -> Result<DeviceStatus, C
 // First the mqtt client will subscribe to a topic az/$iothub/twin/res/#, listen to the
 // device twin property output.
 // Empty payload will be published to az/$iothub/twin/GET/?$rid=1, here 1 is request ID.
+// When connecting a cloud profile, the az/ prefix is replaced by the profile prefix, e.g. az@staging/.
 // The result will be published by the iothub on the az/$iothub/twin/res/{status}/?$rid={request id}.
 // Here if the status is 200 then it's success.
-fn check_device_status_azure(tedge_config: &TEdgeConfig) -> Result<DeviceStatus, ConnectError> {
-    const AZURE_TOPIC_DEVICE_TWIN_DOWNSTREAM: &str = r##"az/twin/res/#"##;
-    const AZURE_TOPIC_DEVICE_TWIN_UPSTREAM: &str = r#"az/twin/GET/?$rid=1"#;
+fn check_device_status_azure(
+    tedge_config: &TEdgeConfig,
+    prefix: &str,
+) -> Result<DeviceStatus, ConnectError> {
+    let azure_topic_device_twin_downstream = format!(r##"{prefix}/twin/res/#"##);
+    let azure_topic_device_twin_upstream = format!(r#"{prefix}/twin/GET/?$rid=1"#);
     const CLIENT_ID: &str = "check_connection_az";
     const REGISTRATION_PAYLOAD: &[u8] = b"";
     const REGISTRATION_OK: &str = "200";
@@ -326,14 +437,14 @@ fn check_device_status_azure(tedge_config: &TEdgeConfig) -> Result<DeviceStatus,
     let (mut client, mut connection) = rumqttc::Client::new(mqtt_options, 10);
     let mut acknowledged = false;
 
-    client.subscribe(AZURE_TOPIC_DEVICE_TWIN_DOWNSTREAM, AtLeastOnce)?;
+    client.subscribe(&azure_topic_device_twin_downstream, AtLeastOnce)?;
 
     for event in connection.iter() {
         match event {
             Ok(Event::Incoming(Packet::SubAck(_))) => {
                 // We are ready to get the response, hence send the request
                 client.publish(
-                    AZURE_TOPIC_DEVICE_TWIN_UPSTREAM,
+                    &azure_topic_device_twin_upstream,
                     AtLeastOnce,
                     false,
                     REGISTRATION_PAYLOAD,
@@ -378,9 +489,12 @@ fn check_device_status_azure(tedge_config: &TEdgeConfig) -> Result<DeviceStatus,
     }
 }
 
-fn check_device_status_aws(tedge_config: &TEdgeConfig) -> Result<DeviceStatus, ConnectError> {
-    const AWS_TOPIC_PUB_CHECK_CONNECTION: &str = r#"aws/test-connection"#;
-    const AWS_TOPIC_SUB_CHECK_CONNECTION: &str = r#"aws/connection-success"#;
+fn check_device_status_aws(
+    tedge_config: &TEdgeConfig,
+    prefix: &str,
+) -> Result<DeviceStatus, ConnectError> {
+    let aws_topic_pub_check_connection = format!("{prefix}/test-connection");
+    let aws_topic_sub_check_connection = format!("{prefix}/connection-success");
     const CLIENT_ID: &str = "check_connection_aws";
     const REGISTRATION_PAYLOAD: &[u8] = b"";
 
@@ -393,14 +507,14 @@ fn check_device_status_aws(tedge_config: &TEdgeConfig) -> Result<DeviceStatus, C
     let (mut client, mut connection) = rumqttc::Client::new(mqtt_options, 10);
     let mut acknowledged = false;
 
-    client.subscribe(AWS_TOPIC_SUB_CHECK_CONNECTION, AtLeastOnce)?;
+    client.subscribe(&aws_topic_sub_check_connection, AtLeastOnce)?;
 
     for event in connection.iter() {
         match event {
             Ok(Event::Incoming(Packet::SubAck(_))) => {
                 // We are ready to get the response, hence send the request
                 client.publish(
-                    AWS_TOPIC_PUB_CHECK_CONNECTION,
+                    &aws_topic_pub_check_connection,
                     AtLeastOnce,
                     false,
                     REGISTRATION_PAYLOAD,
@@ -444,13 +558,44 @@ fn check_device_status_aws(tedge_config: &TEdgeConfig) -> Result<DeviceStatus, C
     }
 }
 
+/// The device private key, as referred to by the bridge configuration
+///
+/// The mosquitto bridge requires a key file,
+/// while the built-in bridge also accepts the URI of a key stored on a PKCS#11 token.
+fn bridge_key_file(config: &TEdgeConfig) -> Result<Utf8PathBuf, ConfigError> {
+    if !config.mqtt.bridge.built_in {
+        return device_key_file(config);
+    }
+    match config.device.key_uri()? {
+        KeyUri::File(path) => Ok(Utf8PathBuf::try_from(path).map_err(|err| err.into_io_error())?),
+        KeyUri::Pkcs11(_) => Ok(Utf8PathBuf::from(
+            config.device.key_uri.or_none().cloned().unwrap_or_default(),
+        )),
+    }
+}
+
+/// The file of the device private key, which is required by the mosquitto bridge
+fn device_key_file(config: &TEdgeConfig) -> Result<Utf8PathBuf, ConfigError> {
+    match config.device.key_uri()? {
+        KeyUri::File(path) => Ok(Utf8PathBuf::try_from(path).map_err(|err| err.into_io_error())?),
+        key_uri @ KeyUri::Pkcs11(_) => Err(ConfigError::KeyNotSupportedByBridge {
+            key_uri: key_uri.to_string(),
+        }),
+    }
+}
+
 fn new_bridge(
     bridge_config: &BridgeConfig,
+    key_uri: &KeyUri,
     common_mosquitto_config: &CommonMosquittoConfig,
     service_manager: &dyn SystemServiceManager,
     config_location: &TEdgeConfigLocation,
     device_type: &str,
+    built_in_mapper: Option<SystemService>,
 ) -> Result<(), ConnectError> {
+    // The built-in bridge is run by the mapper of the cloud, rather than by mosquitto
+    let built_in = built_in_mapper.is_some();
+
     println!("Checking if {} is available.\n", service_manager.name());
     let service_manager_result = service_manager.check_operational();
 
@@ -471,15 +616,22 @@ fn new_bridge(
 
     if bridge_config.cloud_name.eq("c8y") {
         println!("Creating the device in Cumulocity cloud.\n");
-        c8y_direct_connection::create_device_with_direct_connection(bridge_config, device_type)?;
+        c8y_direct_connection::create_device_with_direct_connection(
+            bridge_config,
+            key_uri,
+            device_type,
+        )?;
     }
 
     println!("Saving configuration for requested bridge.\n");
-    if let Err(err) =
-        write_bridge_config_to_file(config_location, bridge_config, common_mosquitto_config)
-    {
+    if let Err(err) = write_bridge_config_to_file(
+        config_location,
+        bridge_config,
+        common_mosquitto_config,
+        built_in,
+    ) {
         // We want to preserve previous errors and therefore discard result of this function.
-        let _ = clean_up(config_location, bridge_config);
+        let _ = clean_up(config_location, bridge_config, built_in);
         return Err(err);
     }
 
@@ -490,6 +642,11 @@ fn new_bridge(
         return Err(err.into());
     }
 
+    if let Some(mapper) = built_in_mapper {
+        // The bridge is run by the mapper, not by mosquitto
+        return restart_bridge_mapper(mapper, bridge_config, service_manager, config_location);
+    }
+
     restart_mosquitto(bridge_config, service_manager, config_location)?;
 
     println!(
@@ -502,13 +659,39 @@ fn new_bridge(
 
     println!("Enabling mosquitto service on reboots.\n");
     if let Err(err) = service_manager.enable_service(SystemService::Mosquitto) {
-        clean_up(config_location, bridge_config)?;
+        clean_up(config_location, bridge_config, false)?;
         return Err(err.into());
     }
 
     Ok(())
 }
 
+fn restart_bridge_mapper(
+    mapper: SystemService,
+    bridge_config: &BridgeConfig,
+    service_manager: &dyn SystemServiceManager,
+    config_location: &TEdgeConfigLocation,
+) -> Result<(), ConnectError> {
+    println!("Restarting {mapper} service, running the built-in bridge.\n");
+    if let Err(err) = service_manager
+        .restart_service(mapper.clone())
+        .and_then(|_| service_manager.enable_service(mapper.clone()))
+    {
+        clean_up(config_location, bridge_config, true)?;
+        return Err(err.into());
+    }
+
+    println!(
+        "Awaiting {mapper} to connect. This may take up to {} seconds.\n",
+        MOSQUITTO_RESTART_TIMEOUT_SECONDS
+    );
+    std::thread::sleep(std::time::Duration::from_secs(
+        MOSQUITTO_RESTART_TIMEOUT_SECONDS,
+    ));
+
+    Ok(())
+}
+
 fn restart_mosquitto(
     bridge_config: &BridgeConfig,
     service_manager: &dyn SystemServiceManager,
@@ -516,7 +699,7 @@ fn restart_mosquitto(
 ) -> Result<(), ConnectError> {
     println!("Restarting mosquitto service.\n");
     if let Err(err) = service_manager.restart_service(SystemService::Mosquitto) {
-        clean_up(config_location, bridge_config)?;
+        clean_up(config_location, bridge_config, false)?;
         return Err(err.into());
     }
 
@@ -544,8 +727,9 @@ fn enable_software_management(
 fn clean_up(
     config_location: &TEdgeConfigLocation,
     bridge_config: &BridgeConfig,
+    built_in: bool,
 ) -> Result<(), ConnectError> {
-    let path = get_bridge_config_file_path(config_location, bridge_config);
+    let path = get_bridge_config_file_path(config_location, bridge_config, built_in);
     std::fs::remove_file(path).or_else(ok_if_not_found)?;
     Ok(())
 }
@@ -554,11 +738,14 @@ fn bridge_config_exists(
     config_location: &TEdgeConfigLocation,
     bridge_config: &BridgeConfig,
 ) -> Result<(), ConnectError> {
-    let path = get_bridge_config_file_path(config_location, bridge_config);
-    if Path::new(&path).exists() {
-        return Err(ConnectError::ConfigurationExists {
-            cloud: bridge_config.cloud_name.to_string(),
-        });
+    // A cloud is connected either by mosquitto or by the built-in bridge, but not both
+    for built_in in [false, true] {
+        let path = get_bridge_config_file_path(config_location, bridge_config, built_in);
+        if Path::new(&path).exists() {
+            return Err(ConnectError::ConfigurationExists {
+                cloud: bridge_config.cloud_name.to_string(),
+            });
+        }
     }
     Ok(())
 }
@@ -567,21 +754,25 @@ fn write_bridge_config_to_file(
     config_location: &TEdgeConfigLocation,
     bridge_config: &BridgeConfig,
     common_mosquitto_config: &CommonMosquittoConfig,
+    built_in: bool,
 ) -> Result<(), ConnectError> {
     let dir_path = config_location
         .tedge_config_root_path
-        .join(TEDGE_BRIDGE_CONF_DIR_PATH);
+        .join(bridge_config_dir(built_in));
 
     // This will forcefully create directory structure if it doesn't exist, we should find better way to do it, maybe config should deal with it?
     create_directories(dir_path)?;
 
-    let common_config_path =
-        get_common_mosquitto_config_file_path(config_location, common_mosquitto_config);
-    let mut common_draft = DraftFile::new(common_config_path)?.with_mode(0o644);
-    common_mosquitto_config.serialize(&mut common_draft)?;
-    common_draft.persist()?;
+    // The built-in bridge doesn't depend on mosquitto
+    if !built_in {
+        let common_config_path =
+            get_common_mosquitto_config_file_path(config_location, common_mosquitto_config);
+        let mut common_draft = DraftFile::new(common_config_path)?.with_mode(0o644);
+        common_mosquitto_config.serialize(&mut common_draft)?;
+        common_draft.persist()?;
+    }
 
-    let config_path = get_bridge_config_file_path(config_location, bridge_config);
+    let config_path = get_bridge_config_file_path(config_location, bridge_config, built_in);
     let mut config_draft = DraftFile::new(config_path)?.with_mode(0o644);
     bridge_config.serialize(&mut config_draft)?;
     config_draft.persist()?;
@@ -589,16 +780,26 @@ fn write_bridge_config_to_file(
     Ok(())
 }
 
-fn get_bridge_config_file_path(
+pub(crate) fn get_bridge_config_file_path(
     config_location: &TEdgeConfigLocation,
     bridge_config: &BridgeConfig,
+    built_in: bool,
 ) -> Utf8PathBuf {
     config_location
         .tedge_config_root_path
-        .join(TEDGE_BRIDGE_CONF_DIR_PATH)
+        .join(bridge_config_dir(built_in))
         .join(&bridge_config.config_file)
 }
 
+/// The directory of the bridge configuration files, read by mosquitto or by the mappers
+fn bridge_config_dir(built_in: bool) -> &'static str {
+    if built_in {
+        BUILT_IN_BRIDGE_CONF_DIR_PATH
+    } else {
+        TEDGE_BRIDGE_CONF_DIR_PATH
+    }
+}
+
 fn get_common_mosquitto_config_file_path(
     config_location: &TEdgeConfigLocation,
     common_mosquitto_config: &CommonMosquittoConfig,
@@ -610,8 +811,12 @@ fn get_common_mosquitto_config_file_path(
 }
 
 // To confirm the connected c8y tenant is the one that user configured.
-fn check_connected_c8y_tenant_as_configured(tedge_config: &TEdgeConfig, configured_url: &str) {
-    match get_connected_c8y_url(tedge_config) {
+fn check_connected_c8y_tenant_as_configured(
+    tedge_config: &TEdgeConfig,
+    configured_url: &str,
+    prefix: &str,
+) {
+    match get_connected_c8y_url(tedge_config, prefix) {
         Ok(url) if url == configured_url => {}
         Ok(url) => println!(
             "Warning: Connecting to {}, but the configured URL is {}.\n\
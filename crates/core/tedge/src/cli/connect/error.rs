@@ -48,6 +48,9 @@ pub enum ConnectError {
     #[error("Device is not connected to {cloud} cloud")]
     DeviceNotConnected { cloud: String },
 
+    #[error("The bridge configuration is not valid: {failures} check(s) failed")]
+    DryRunFailed { failures: usize },
+
     #[error("Unknown device status")]
     UnknownDeviceStatus,
 
@@ -17,4 +17,4 @@ mod cli;
 mod command;
 mod common_mosquitto_config;
 mod error;
-mod jwt_token;
+pub(crate) mod jwt_token;
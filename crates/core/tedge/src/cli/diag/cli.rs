@@ -0,0 +1,54 @@
+use crate::cli::common::Cloud;
+use crate::cli::diag::connect::DiagConnectCommand;
+use crate::command::*;
+use tedge_config::ProfileName;
+
+#[derive(clap::Subcommand, Debug)]
+pub enum TEdgeDiagCli {
+    /// Check the connection to a cloud, step by step, printing a pass/fail report
+    ///
+    /// The checks cover DNS resolution, TCP reachability, the TLS handshake using the device
+    /// certificate and the root certificates, the clock skew and, for Cumulocity, the connected tenant.
+    #[clap(subcommand)]
+    Connect(TEdgeDiagConnectCli),
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum TEdgeDiagConnectCli {
+    /// Check the connection to Cumulocity
+    C8y {
+        /// The cloud profile to check, as configured by the `c8y.profiles.<profile>.*` settings
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
+    /// Check the connection to Azure
+    Az {
+        /// The cloud profile to check, as configured by the `az.profiles.<profile>.*` settings
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
+    /// Check the connection to AWS
+    Aws {
+        /// The cloud profile to check, as configured by the `aws.profiles.<profile>.*` settings
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
+}
+
+impl BuildCommand for TEdgeDiagCli {
+    fn build_command(self, context: BuildContext) -> Result<Box<dyn Command>, crate::ConfigError> {
+        let TEdgeDiagCli::Connect(cloud) = self;
+        let (cloud, profile) = match cloud {
+            TEdgeDiagConnectCli::C8y { profile } => (Cloud::C8y, profile),
+            TEdgeDiagConnectCli::Az { profile } => (Cloud::Azure, profile),
+            TEdgeDiagConnectCli::Aws { profile } => (Cloud::Aws, profile),
+        };
+        Ok(DiagConnectCommand {
+            config_location: context.config_location,
+            config_repository: context.config_repository,
+            cloud,
+            profile,
+        }
+        .into_boxed())
+    }
+}
@@ -0,0 +1,504 @@
+use crate::cli::common::Cloud;
+use crate::cli::connect::bridge_config;
+use crate::cli::connect::get_bridge_config_file_path;
+use crate::cli::connect::jwt_token::get_connected_c8y_url;
+use crate::cli::connect::BridgeConfig;
+use crate::cli::diag::error::DiagError;
+use crate::command::Command;
+use certificate::parse_root_certificate::create_tls_config;
+use certificate::translate_rustls_error;
+use certificate::PemCertificate;
+use rumqttc::tokio_rustls::rustls;
+use rumqttc::tokio_rustls::rustls::AlertDescription;
+use rumqttc::tokio_rustls::rustls::ClientConnection;
+use rumqttc::tokio_rustls::rustls::ServerName;
+use std::fmt::Display;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
+use tedge_config::ProfileName;
+use tedge_config::TEdgeConfig;
+use tedge_config::TEdgeConfigLocation;
+use tedge_config::TEdgeConfigRepository;
+
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(10);
+const TLS_ALERT_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(60);
+
+const CONFIGURATION: &str = "Configuration";
+const CERTIFICATE_FILES: &str = "Certificate files";
+const DEVICE_CERTIFICATE: &str = "Device certificate";
+const DNS: &str = "DNS resolution";
+const TCP: &str = "TCP connection";
+const TLS: &str = "TLS handshake";
+const CLOCK_SKEW: &str = "Clock skew";
+const TENANT: &str = "Connected tenant";
+
+pub struct DiagConnectCommand {
+    pub config_location: TEdgeConfigLocation,
+    pub config_repository: TEdgeConfigRepository,
+    pub cloud: Cloud,
+    pub profile: Option<ProfileName>,
+}
+
+impl Command for DiagConnectCommand {
+    fn description(&self) -> String {
+        format!(
+            "check the connection to {} cloud.",
+            self.cloud.connection_name(self.profile.as_ref())
+        )
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let report = self.run_checks();
+        report.render(&mut std::io::stdout())?;
+        match report.failures() {
+            0 => Ok(()),
+            failures => Err(DiagError::ChecksFailed { failures }.into()),
+        }
+    }
+}
+
+impl DiagConnectCommand {
+    fn run_checks(&self) -> DiagReport {
+        let mut report = DiagReport::default();
+
+        let config = match self.load_config() {
+            Ok(config) => config,
+            Err(err) => {
+                report.fail(CONFIGURATION, err);
+                return report;
+            }
+        };
+        let bridge_config = match bridge_config(&config, self.cloud, self.profile.as_ref()) {
+            Ok(bridge_config) => bridge_config,
+            Err(err) => {
+                report.fail(CONFIGURATION, err);
+                return report;
+            }
+        };
+        report.pass(
+            CONFIGURATION,
+            format!(
+                "{} bridge to {}",
+                self.cloud.connection_name(self.profile.as_ref()),
+                bridge_config.address
+            ),
+        );
+
+        report.record(
+            CERTIFICATE_FILES,
+            bridge_config
+                .validate()
+                .map(|()| "root certificates, device certificate and key found"),
+        );
+        report.record(
+            DEVICE_CERTIFICATE,
+            check_device_certificate(&config, &bridge_config),
+        );
+
+        let host = host_of(&bridge_config.address);
+        let addresses = match resolve(&bridge_config.address) {
+            Ok(addresses) => {
+                report.pass(DNS, format!("{host} resolved to {}", addresses[0].ip()));
+                addresses
+            }
+            Err(err) => {
+                report.fail(DNS, err);
+                report.skip_all(&[TCP, TLS, CLOCK_SKEW], "the cloud host is unknown");
+                return self.check_tenant(report, &config, &bridge_config);
+            }
+        };
+
+        let tcp = match connect(&addresses) {
+            Ok((tcp, address)) => {
+                report.pass(TCP, format!("connected to {address}"));
+                tcp
+            }
+            Err(err) => {
+                report.fail(TCP, err);
+                report.skip_all(&[TLS, CLOCK_SKEW], "the cloud host is not reachable");
+                return self.check_tenant(report, &config, &bridge_config);
+            }
+        };
+
+        report.record(TLS, check_tls(tcp, host, &config, &bridge_config));
+        report.record(CLOCK_SKEW, check_clock_skew(host));
+
+        self.check_tenant(report, &config, &bridge_config)
+    }
+
+    fn load_config(&self) -> anyhow::Result<TEdgeConfig> {
+        let config = self
+            .config_repository
+            .load()?
+            .for_profile(self.cloud.profiled(), self.profile.as_ref())?;
+        Ok(config)
+    }
+
+    /// Check that the bridge is connected to the configured Cumulocity tenant
+    fn check_tenant(
+        &self,
+        mut report: DiagReport,
+        config: &TEdgeConfig,
+        bridge_config: &BridgeConfig,
+    ) -> DiagReport {
+        if !matches!(self.cloud, Cloud::C8y) {
+            return report;
+        }
+
+        let bridge_exists = [false, true].into_iter().any(|built_in| {
+            get_bridge_config_file_path(&self.config_location, bridge_config, built_in).exists()
+        });
+        if !bridge_exists {
+            report.skip(TENANT, "the bridge is not configured");
+            return report;
+        }
+
+        let configured_url = config
+            .c8y
+            .mqtt
+            .or_none()
+            .map(|u| u.to_string())
+            .unwrap_or_default();
+        let prefix = self.cloud.connection_name(self.profile.as_ref());
+        match get_connected_c8y_url(config, &prefix) {
+            Ok(url) if url == configured_url => report.pass(TENANT, url),
+            Ok(url) => report.fail(
+                TENANT,
+                format!("connected to {url}, but the configured URL is {configured_url}"),
+            ),
+            Err(err) => report.fail(
+                TENANT,
+                format!("no JWT token received from the bridge: {err}"),
+            ),
+        }
+        report
+    }
+}
+
+/// The outcome of a diagnostic check
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum_macros::Display)]
+pub enum CheckStatus {
+    #[strum(serialize = "PASS")]
+    Pass,
+    #[strum(serialize = "FAIL")]
+    Fail,
+    #[strum(serialize = "SKIP")]
+    Skip,
+}
+
+#[derive(Debug)]
+pub struct CheckResult {
+    pub check: &'static str,
+    pub status: CheckStatus,
+    pub details: String,
+}
+
+/// The results of the diagnostic checks, in the order of execution
+#[derive(Debug, Default)]
+pub struct DiagReport {
+    results: Vec<CheckResult>,
+}
+
+impl DiagReport {
+    fn add(&mut self, check: &'static str, status: CheckStatus, details: impl Display) {
+        self.results.push(CheckResult {
+            check,
+            status,
+            details: details.to_string(),
+        })
+    }
+
+    fn pass(&mut self, check: &'static str, details: impl Display) {
+        self.add(check, CheckStatus::Pass, details)
+    }
+
+    fn fail(&mut self, check: &'static str, details: impl Display) {
+        self.add(check, CheckStatus::Fail, details)
+    }
+
+    fn skip(&mut self, check: &'static str, details: impl Display) {
+        self.add(check, CheckStatus::Skip, details)
+    }
+
+    fn skip_all(&mut self, checks: &[&'static str], reason: &str) {
+        for check in checks {
+            self.skip(check, reason)
+        }
+    }
+
+    fn record(&mut self, check: &'static str, result: Result<impl Display, impl Display>) {
+        match result {
+            Ok(details) => self.pass(check, details),
+            Err(err) => self.fail(check, err),
+        }
+    }
+
+    pub fn failures(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|result| result.status == CheckStatus::Fail)
+            .count()
+    }
+
+    /// Render the report as a table with one line per check
+    pub fn render<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        const CHECK: &str = "CHECK";
+        let width = self
+            .results
+            .iter()
+            .map(|result| result.check.len())
+            .chain([CHECK.len()])
+            .max()
+            .unwrap_or_default();
+
+        writeln!(writer, "{CHECK:<width$}  RESULT  DETAILS")?;
+        for result in &self.results {
+            writeln!(
+                writer,
+                "{:<width$}  {:<6}  {}",
+                result.check, result.status, result.details
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The host of a `host:port` address
+fn host_of(address: &str) -> &str {
+    address
+        .rsplit_once(':')
+        .map(|(host, _port)| host)
+        .unwrap_or(address)
+}
+
+fn resolve(address: &str) -> Result<Vec<SocketAddr>, String> {
+    match address.to_socket_addrs() {
+        Ok(addresses) => {
+            let addresses: Vec<_> = addresses.collect();
+            if addresses.is_empty() {
+                Err(format!("{address} has no address"))
+            } else {
+                Ok(addresses)
+            }
+        }
+        Err(err) => Err(format!("cannot resolve {address}: {err}")),
+    }
+}
+
+fn connect(addresses: &[SocketAddr]) -> Result<(TcpStream, SocketAddr), String> {
+    let mut errors = vec![];
+    for address in addresses {
+        match TcpStream::connect_timeout(address, NETWORK_TIMEOUT) {
+            Ok(tcp) => return Ok((tcp, *address)),
+            Err(err) => errors.push(format!("{address}: {err}")),
+        }
+    }
+    Err(errors.join(", "))
+}
+
+/// Check that the device certificate is valid and issued for the device
+fn check_device_certificate(
+    config: &TEdgeConfig,
+    bridge_config: &BridgeConfig,
+) -> Result<String, String> {
+    let certificate = PemCertificate::from_pem_file(&bridge_config.bridge_certfile)
+        .map_err(|err| format!("cannot read {}: {err}", bridge_config.bridge_certfile))?;
+    let subject = certificate
+        .subject_common_name()
+        .map_err(|err| err.to_string())?;
+    let expiration = certificate
+        .expiration_time()
+        .map_err(|err| err.to_string())?;
+    let not_after = certificate.not_after().map_err(|err| err.to_string())?;
+
+    if SystemTime::from(expiration) < SystemTime::now() {
+        return Err(format!(
+            "the certificate of {subject} expired on {not_after}"
+        ));
+    }
+    let device_id = config
+        .device
+        .id
+        .try_read(config)
+        .map_err(|err| err.to_string())?;
+    if subject != *device_id {
+        return Err(format!(
+            "the certificate is issued for {subject}, while the device id is {device_id}"
+        ));
+    }
+    Ok(format!("issued for {subject}, valid till {not_after}"))
+}
+
+/// Check the TLS handshake with the cloud, using the device certificate and the root certificates
+fn check_tls(
+    mut tcp: TcpStream,
+    host: &str,
+    config: &TEdgeConfig,
+    bridge_config: &BridgeConfig,
+) -> Result<String, String> {
+    let key_uri = config.device.key_uri().map_err(|err| err.to_string())?;
+    let tls_config = create_tls_config(
+        bridge_config.bridge_root_cert_path.clone().into(),
+        &key_uri,
+        bridge_config.bridge_certfile.clone().into(),
+    )
+    .map_err(|err| err.to_string())?;
+    let server_name =
+        ServerName::try_from(host).map_err(|err| format!("invalid host {host}: {err}"))?;
+    let mut connection =
+        ClientConnection::new(Arc::new(tls_config), server_name).map_err(|err| err.to_string())?;
+
+    let _ = tcp.set_read_timeout(Some(NETWORK_TIMEOUT));
+    let _ = tcp.set_write_timeout(Some(NETWORK_TIMEOUT));
+    while connection.is_handshaking() {
+        connection
+            .complete_io(&mut tcp)
+            .map_err(|err| describe_tls_error(&err))?;
+    }
+
+    // With TLS 1.3, a device certificate rejected by the cloud is only notified after the handshake
+    let _ = tcp.set_read_timeout(Some(TLS_ALERT_TIMEOUT));
+    let mut stream = rustls::Stream::new(&mut connection, &mut tcp);
+    if let Err(err) = stream.read(&mut [0u8; 1]) {
+        if err.kind() == std::io::ErrorKind::InvalidData {
+            return Err(describe_tls_error(&err));
+        }
+    }
+
+    let version = connection
+        .protocol_version()
+        .map(|version| format!("{version:?}"))
+        .unwrap_or_default();
+    Ok(format!("{version} session established with {host}"))
+}
+
+fn describe_tls_error(err: &std::io::Error) -> String {
+    let Some(tls_error) = err
+        .get_ref()
+        .and_then(|err| err.downcast_ref::<rustls::Error>())
+    else {
+        return err.to_string();
+    };
+    match tls_error {
+        rustls::Error::AlertReceived(
+            AlertDescription::CertificateUnknown | AlertDescription::BadCertificate,
+        ) => "the device certificate is not trusted by the cloud".to_string(),
+        rustls::Error::AlertReceived(AlertDescription::HandshakeFailure) => {
+            "the handshake has been rejected by the cloud, check the device certificate and key"
+                .to_string()
+        }
+        rustls::Error::InvalidCertificate(rustls::CertificateError::UnknownIssuer) => {
+            "the cloud certificate is not trusted by the device, check the root certificates"
+                .to_string()
+        }
+        _ => match translate_rustls_error(tls_error) {
+            Some(err) => err.to_string(),
+            None => tls_error.to_string(),
+        },
+    }
+}
+
+/// Compare the device clock with the `Date` returned by the HTTPS endpoint of the cloud host
+fn check_clock_skew(host: &str) -> Result<String, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(NETWORK_TIMEOUT)
+        .build()
+        .map_err(|err| err.to_string())?;
+    let response = client
+        .head(format!("https://{host}/"))
+        .send()
+        .map_err(|err| format!("cannot get the cloud time: {err}"))?;
+    let date = response
+        .headers()
+        .get(reqwest::header::DATE)
+        .and_then(|date| date.to_str().ok())
+        .ok_or_else(|| "the cloud response has no Date header".to_string())?;
+    let cloud_time = httpdate::parse_http_date(date).map_err(|err| err.to_string())?;
+    clock_skew(SystemTime::now(), cloud_time)
+}
+
+fn clock_skew(device_time: SystemTime, cloud_time: SystemTime) -> Result<String, String> {
+    let (skew, relation) = match device_time.duration_since(cloud_time) {
+        Ok(skew) => (skew, "ahead of"),
+        Err(err) => (err.duration(), "behind"),
+    };
+    let skew = skew.as_secs();
+    if skew > MAX_CLOCK_SKEW.as_secs() {
+        Err(format!(
+            "the device clock is {skew} seconds {relation} the cloud clock"
+        ))
+    } else {
+        Ok(format!("{skew} seconds"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn report_is_rendered_as_a_table() {
+        let mut report = DiagReport::default();
+        report.pass(DNS, "example.com resolved to 127.0.0.1");
+        report.fail(TCP, "connection refused");
+        report.skip(TLS, "the cloud host is not reachable");
+
+        let mut rendered = Vec::new();
+        report.render(&mut rendered).unwrap();
+
+        assert_eq!(
+            String::from_utf8(rendered).unwrap(),
+            "\
+CHECK           RESULT  DETAILS
+DNS resolution  PASS    example.com resolved to 127.0.0.1
+TCP connection  FAIL    connection refused
+TLS handshake   SKIP    the cloud host is not reachable
+"
+        );
+        assert_eq!(report.failures(), 1);
+    }
+
+    #[test]
+    fn host_is_extracted_from_address() {
+        assert_eq!(
+            host_of("example.cumulocity.com:8883"),
+            "example.cumulocity.com"
+        );
+        assert_eq!(host_of("example.com"), "example.com");
+    }
+
+    #[test]
+    fn tcp_check_reports_unreachable_hosts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let addresses = resolve(&address.to_string()).unwrap();
+        assert!(connect(&addresses).is_ok());
+
+        drop(listener);
+        assert!(connect(&addresses).is_err());
+    }
+
+    #[test]
+    fn clock_skew_is_checked_in_both_directions() {
+        let cloud_time = SystemTime::now();
+
+        assert!(clock_skew(cloud_time + Duration::from_secs(10), cloud_time).is_ok());
+        assert!(clock_skew(cloud_time - Duration::from_secs(10), cloud_time).is_ok());
+        assert_eq!(
+            clock_skew(cloud_time + Duration::from_secs(600), cloud_time),
+            Err("the device clock is 600 seconds ahead of the cloud clock".to_string())
+        );
+        assert_eq!(
+            clock_skew(cloud_time - Duration::from_secs(600), cloud_time),
+            Err("the device clock is 600 seconds behind the cloud clock".to_string())
+        );
+    }
+}
@@ -0,0 +1,5 @@
+#[derive(thiserror::Error, Debug)]
+pub enum DiagError {
+    #[error("{failures} connection check(s) failed")]
+    ChecksFailed { failures: usize },
+}
@@ -0,0 +1,5 @@
+mod cli;
+mod connect;
+mod error;
+
+pub use cli::*;
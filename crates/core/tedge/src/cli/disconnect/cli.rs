@@ -2,44 +2,56 @@ use crate::cli::common::Cloud;
 use crate::cli::disconnect::disconnect_bridge::*;
 use crate::command::*;
 use tedge_config::system_services::service_manager;
-
-const C8Y_CONFIG_FILENAME: &str = "c8y-bridge.conf";
-const AZURE_CONFIG_FILENAME: &str = "az-bridge.conf";
-const AWS_CONFIG_FILENAME: &str = "aws-bridge.conf";
+use tedge_config::ProfileName;
 
 #[derive(clap::Subcommand, Debug)]
 pub enum TEdgeDisconnectBridgeCli {
     /// Remove bridge connection to Cumulocity.
-    C8y,
+    C8y {
+        /// The cloud profile to disconnect, as configured by the `c8y.profiles.<profile>.*` settings
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
     /// Remove bridge connection to Azure.
-    Az,
+    Az {
+        /// The cloud profile to disconnect, as configured by the `az.profiles.<profile>.*` settings
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
     /// Remove bridge connection to AWS.
-    Aws,
+    Aws {
+        /// The cloud profile to disconnect, as configured by the `aws.profiles.<profile>.*` settings
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
 }
 
 impl BuildCommand for TEdgeDisconnectBridgeCli {
     fn build_command(self, context: BuildContext) -> Result<Box<dyn Command>, crate::ConfigError> {
         let cmd = match self {
-            TEdgeDisconnectBridgeCli::C8y => DisconnectBridgeCommand {
+            TEdgeDisconnectBridgeCli::C8y { profile } => DisconnectBridgeCommand {
                 config_location: context.config_location.clone(),
-                config_file: C8Y_CONFIG_FILENAME.into(),
+                config_file: bridge_config_file(Cloud::C8y, profile.as_ref()),
                 cloud: Cloud::C8y,
+                profile,
                 use_mapper: true,
                 use_agent: true,
                 service_manager: service_manager(&context.config_location.tedge_config_root_path)?,
             },
-            TEdgeDisconnectBridgeCli::Az => DisconnectBridgeCommand {
+            TEdgeDisconnectBridgeCli::Az { profile } => DisconnectBridgeCommand {
                 config_location: context.config_location.clone(),
-                config_file: AZURE_CONFIG_FILENAME.into(),
+                config_file: bridge_config_file(Cloud::Azure, profile.as_ref()),
                 cloud: Cloud::Azure,
+                profile,
                 use_mapper: true,
                 use_agent: false,
                 service_manager: service_manager(&context.config_location.tedge_config_root_path)?,
             },
-            TEdgeDisconnectBridgeCli::Aws => DisconnectBridgeCommand {
+            TEdgeDisconnectBridgeCli::Aws { profile } => DisconnectBridgeCommand {
                 config_location: context.config_location.clone(),
-                config_file: AWS_CONFIG_FILENAME.into(),
+                config_file: bridge_config_file(Cloud::Aws, profile.as_ref()),
                 cloud: Cloud::Aws,
+                profile,
                 use_mapper: true,
                 use_agent: false,
                 service_manager: service_manager(&context.config_location.tedge_config_root_path)?,
@@ -48,3 +60,8 @@ impl BuildCommand for TEdgeDisconnectBridgeCli {
         Ok(cmd.into_boxed())
     }
 }
+
+/// The name of the bridge configuration file, e.g. `c8y-bridge.conf` or `c8y@staging-bridge.conf`
+pub(crate) fn bridge_config_file(cloud: Cloud, profile: Option<&ProfileName>) -> String {
+    format!("{}-bridge.conf", cloud.connection_name(profile))
+}
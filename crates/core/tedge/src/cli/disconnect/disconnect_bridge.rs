@@ -3,16 +3,19 @@ use crate::cli::disconnect::error::*;
 use crate::command::*;
 use std::sync::Arc;
 use tedge_config::system_services::*;
+use tedge_config::ProfileName;
 use tedge_config::TEdgeConfigLocation;
 use which::which;
 
 const TEDGE_BRIDGE_CONF_DIR_PATH: &str = "mosquitto-conf";
+const BUILT_IN_BRIDGE_CONF_DIR_PATH: &str = "bridge";
 
 #[derive(Debug)]
 pub struct DisconnectBridgeCommand {
     pub config_location: TEdgeConfigLocation,
     pub config_file: String,
     pub cloud: Cloud,
+    pub profile: Option<ProfileName>,
     pub use_mapper: bool,
     pub use_agent: bool,
     pub service_manager: Arc<dyn SystemServiceManager>,
@@ -38,7 +41,7 @@ impl DisconnectBridgeCommand {
 
     fn stop_bridge(&self) -> Result<(), DisconnectBridgeError> {
         // If this fails, do not continue with applying changes and stopping/disabling tedge-mapper.
-        self.remove_bridge_config_file()?;
+        let built_in = self.remove_bridge_config_file()?;
 
         if let Err(SystemServiceError::ServiceManagerUnavailable { cmd: _, name }) =
             self.service_manager.check_operational()
@@ -50,15 +53,19 @@ impl DisconnectBridgeCommand {
             return Ok(());
         }
 
-        // Ignore failure
-        let _ = self.apply_changes_to_mosquitto();
+        // The built-in bridge is stopped along the mapper
+        if !built_in {
+            // Ignore failure
+            let _ = self.apply_changes_to_mosquitto();
+        }
 
         let mut failed = false;
         // Only C8Y changes the status of tedge-mapper
         if self.use_mapper && which("tedge-mapper").is_ok() {
-            failed = self
-                .service_manager()
-                .stop_and_disable_service(self.cloud.mapper_service(), std::io::stdout());
+            failed = self.service_manager().stop_and_disable_service(
+                self.cloud.mapper_service(self.profile.as_ref()),
+                std::io::stdout(),
+            );
         }
 
         match failed {
@@ -67,7 +74,24 @@ impl DisconnectBridgeCommand {
         }
     }
 
-    fn remove_bridge_config_file(&self) -> Result<(), DisconnectBridgeError> {
+    /// Remove the bridge configuration, returning `true` if this was a built-in bridge
+    fn remove_bridge_config_file(&self) -> Result<bool, DisconnectBridgeError> {
+        let built_in_conf_path = self
+            .config_location
+            .tedge_config_root_path
+            .join(BUILT_IN_BRIDGE_CONF_DIR_PATH)
+            .join(&self.config_file);
+        if built_in_conf_path.exists() {
+            println!("Removing {} built-in bridge.\n", self.cloud);
+            return match std::fs::remove_file(&built_in_conf_path) {
+                Ok(()) => Ok(true),
+                Err(e) => Err(DisconnectBridgeError::FileOperationFailed(
+                    e,
+                    built_in_conf_path.into(),
+                )),
+            };
+        }
+
         // Check if bridge exists and stop with code 0 if it doesn't.
         let bridge_conf_path = self
             .config_location
@@ -79,7 +103,7 @@ impl DisconnectBridgeCommand {
         match std::fs::remove_file(&bridge_conf_path) {
             // If we find the bridge config file we remove it
             // and carry on to see if we need to restart mosquitto.
-            Ok(()) => Ok(()),
+            Ok(()) => Ok(false),
 
             // If bridge config file was not found we assume that the bridge doesn't exist,
             // We finish early returning exit code 0.
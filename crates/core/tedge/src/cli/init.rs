@@ -5,7 +5,9 @@ use anyhow::bail;
 use anyhow::Context;
 use clap::Subcommand;
 use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
+use tedge_utils::file::change_user_and_group;
 use tedge_utils::file::create_directory;
 use tedge_utils::file::PermissionEntry;
 
@@ -53,7 +55,10 @@ impl TEdgeInitCmd {
             Component::augment_subcommands(clap::Command::new("tedge"))
                 .get_subcommands()
                 .map(|c| c.get_name().to_owned())
-                .chain(["tedge-apt-plugin".to_owned()])
+                .chain([
+                    "tedge-apt-plugin".to_owned(),
+                    "tedge-container-plugin".to_owned(),
+                ])
                 .collect();
 
         for component in &component_subcommands {
@@ -154,6 +159,15 @@ impl TEdgeInitCmd {
 
         let config = self.context.config_repository.load()?;
 
+        // Grant read access to the device private key to the thin-edge services,
+        // for the agent to renew the device certificate
+        let key_path = &config.device.key_path;
+        if key_path.is_file() {
+            change_user_and_group(key_path.as_std_path(), crate::BROKER_USER, &self.group)?;
+            std::fs::set_permissions(key_path, std::fs::Permissions::from_mode(0o440))
+                .with_context(|| format!("setting the permissions of {key_path}"))?;
+        }
+
         create_directory(
             config.logs.path.clone(),
             PermissionEntry::new(
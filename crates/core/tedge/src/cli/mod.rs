@@ -18,10 +18,12 @@ mod certificate;
 mod common;
 pub mod config;
 mod connect;
+mod diag;
 mod disconnect;
 mod init;
 mod mqtt;
 mod reconnect;
+mod workflow;
 
 #[derive(clap::Parser, Debug)]
 #[clap(
@@ -96,6 +98,10 @@ pub enum TEdgeOpt {
     #[clap(subcommand)]
     Connect(connect::TEdgeConnectOpt),
 
+    /// Diagnose the device setup
+    #[clap(subcommand)]
+    Diag(diag::TEdgeDiagCli),
+
     /// Remove bridge connection for a provider
     #[clap(subcommand)]
     Disconnect(disconnect::TEdgeDisconnectBridgeCli),
@@ -107,6 +113,10 @@ pub enum TEdgeOpt {
     /// Publish a message on a topic and subscribe a topic.
     #[clap(subcommand)]
     Mqtt(mqtt::TEdgeMqttCli),
+
+    /// Validate, visualize and simulate operation workflows
+    #[clap(subcommand)]
+    Workflow(workflow::TEdgeWorkflowCli),
 }
 
 fn styles() -> clap::builder::Styles {
@@ -163,9 +173,11 @@ impl BuildCommand for TEdgeOpt {
             TEdgeOpt::Cert(opt) => opt.build_command(context),
             TEdgeOpt::Config(opt) => opt.build_command(context),
             TEdgeOpt::Connect(opt) => opt.build_command(context),
+            TEdgeOpt::Diag(opt) => opt.build_command(context),
             TEdgeOpt::Disconnect(opt) => opt.build_command(context),
             TEdgeOpt::Mqtt(opt) => opt.build_command(context),
             TEdgeOpt::Reconnect(opt) => opt.build_command(context),
+            TEdgeOpt::Workflow(opt) => opt.build_command(context),
         }
     }
 }
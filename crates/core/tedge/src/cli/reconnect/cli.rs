@@ -1,22 +1,32 @@
 use crate::cli::common::Cloud;
 use crate::cli::connect::CommonMosquittoConfig;
+use crate::cli::disconnect::bridge_config_file;
 use crate::command::*;
 use tedge_config::system_services::service_manager;
+use tedge_config::ProfileName;
 
 use super::command::ReconnectBridgeCommand;
 
-const C8Y_CONFIG_FILENAME: &str = "c8y-bridge.conf";
-const AZURE_CONFIG_FILENAME: &str = "az-bridge.conf";
-const AWS_CONFIG_FILENAME: &str = "aws-bridge.conf";
-
 #[derive(clap::Subcommand, Debug)]
 pub enum TEdgeReconnectCli {
     /// Remove bridge connection to Cumulocity.
-    C8y,
+    C8y {
+        /// The cloud profile to reconnect, as configured by the `c8y.profiles.<profile>.*` settings
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
     /// Remove bridge connection to Azure.
-    Az,
+    Az {
+        /// The cloud profile to reconnect, as configured by the `az.profiles.<profile>.*` settings
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
     /// Remove bridge connection to AWS.
-    Aws,
+    Aws {
+        /// The cloud profile to reconnect, as configured by the `aws.profiles.<profile>.*` settings
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
 }
 
 impl BuildCommand for TEdgeReconnectCli {
@@ -27,33 +37,36 @@ impl BuildCommand for TEdgeReconnectCli {
         let common_mosquitto_config = CommonMosquittoConfig::default();
 
         let cmd = match self {
-            TEdgeReconnectCli::C8y => ReconnectBridgeCommand {
+            TEdgeReconnectCli::C8y { profile } => ReconnectBridgeCommand {
                 config_location,
                 config_repository,
                 service_manager,
                 common_mosquitto_config,
-                config_file: C8Y_CONFIG_FILENAME.into(),
+                config_file: bridge_config_file(Cloud::C8y, profile.as_ref()),
                 cloud: Cloud::C8y,
+                profile,
                 use_mapper: true,
                 use_agent: true,
             },
-            TEdgeReconnectCli::Az => ReconnectBridgeCommand {
+            TEdgeReconnectCli::Az { profile } => ReconnectBridgeCommand {
                 config_location,
                 config_repository,
                 service_manager,
                 common_mosquitto_config,
-                config_file: AZURE_CONFIG_FILENAME.into(),
+                config_file: bridge_config_file(Cloud::Azure, profile.as_ref()),
                 cloud: Cloud::Azure,
+                profile,
                 use_mapper: true,
                 use_agent: false,
             },
-            TEdgeReconnectCli::Aws => ReconnectBridgeCommand {
+            TEdgeReconnectCli::Aws { profile } => ReconnectBridgeCommand {
                 config_location,
                 config_repository,
                 service_manager,
                 common_mosquitto_config,
-                config_file: AWS_CONFIG_FILENAME.into(),
+                config_file: bridge_config_file(Cloud::Aws, profile.as_ref()),
                 cloud: Cloud::Aws,
+                profile,
                 use_mapper: true,
                 use_agent: false,
             },
@@ -0,0 +1,69 @@
+use super::graph::GraphFormat;
+use super::graph::GraphWorkflowCmd;
+use super::simulate::SimulateWorkflowCmd;
+use super::simulate::StateOutcome;
+use super::validate::ValidateWorkflowCmd;
+use crate::command::BuildCommand;
+use crate::command::BuildContext;
+use crate::command::Command;
+use crate::ConfigError;
+use camino::Utf8PathBuf;
+
+#[derive(clap::Subcommand, Debug)]
+pub enum TEdgeWorkflowCli {
+    /// Check a workflow definition, reporting all the issues found
+    Validate {
+        /// Path to the workflow TOML definition
+        file: Utf8PathBuf,
+    },
+
+    /// Print the state machine of a workflow as a graph
+    Graph {
+        /// Path to the workflow TOML definition
+        file: Utf8PathBuf,
+
+        /// Graph format
+        #[clap(long, value_enum, default_value_t)]
+        format: GraphFormat,
+    },
+
+    /// Walk the states of a workflow, with simulated action outcomes
+    ///
+    /// By default, all the actions are successful.
+    /// Scripted outcomes can be given per state, as exit codes or next states,
+    /// the outcomes given for a state being consumed in order, the last one being then repeated.
+    Simulate {
+        /// Path to the workflow TOML definition
+        file: Utf8PathBuf,
+
+        /// The initial command payload, the status defaulting to "init"
+        #[clap(long)]
+        payload: Option<String>,
+
+        /// The outcome of a state action: <state>=<exit-code> or <state>=<next-state>
+        #[clap(long = "outcome")]
+        outcomes: Vec<StateOutcome>,
+    },
+}
+
+impl BuildCommand for TEdgeWorkflowCli {
+    fn build_command(self, _context: BuildContext) -> Result<Box<dyn Command>, ConfigError> {
+        let cmd = match self {
+            TEdgeWorkflowCli::Validate { file } => ValidateWorkflowCmd { path: file }.into_boxed(),
+            TEdgeWorkflowCli::Graph { file, format } => {
+                GraphWorkflowCmd { path: file, format }.into_boxed()
+            }
+            TEdgeWorkflowCli::Simulate {
+                file,
+                payload,
+                outcomes,
+            } => SimulateWorkflowCmd {
+                path: file,
+                payload,
+                outcomes,
+            }
+            .into_boxed(),
+        };
+        Ok(cmd)
+    }
+}
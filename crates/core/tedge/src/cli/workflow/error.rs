@@ -0,0 +1,29 @@
+use camino::Utf8PathBuf;
+
+#[derive(thiserror::Error, Debug)]
+pub enum WorkflowError {
+    #[error("Failed to read {path}")]
+    ReadError {
+        path: Utf8PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Invalid workflow definition {path}: {reason}")]
+    ParseError { path: Utf8PathBuf, reason: String },
+
+    #[error("Invalid workflow definition {path}: {count} error(s) found")]
+    InvalidWorkflow { path: Utf8PathBuf, count: usize },
+
+    #[error("Invalid command payload: {0}")]
+    InvalidPayload(#[from] serde_json::Error),
+
+    #[error("The command payload is not a JSON object")]
+    PayloadNotAnObject,
+
+    #[error("Invalid step outcome '{outcome}', expecting '<state>=<exit-code or next-state>'")]
+    InvalidOutcome { outcome: String },
+
+    #[error("Simulation stopped in {state} state: {reason}")]
+    SimulationStopped { state: String, reason: String },
+}
@@ -0,0 +1,187 @@
+use super::load_workflow;
+use crate::command::Command;
+use camino::Utf8PathBuf;
+use tedge_api::workflow::OperationAction;
+use tedge_api::workflow::OperationWorkflow;
+use tedge_api::workflow::StateName;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    #[default]
+    Dot,
+
+    /// Mermaid state diagram
+    Mermaid,
+}
+
+/// Print the state machine of a workflow as a graph
+pub struct GraphWorkflowCmd {
+    pub path: Utf8PathBuf,
+    pub format: GraphFormat,
+}
+
+impl Command for GraphWorkflowCmd {
+    fn description(&self) -> String {
+        format!("print the state machine of the workflow {}", self.path)
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let workflow = load_workflow(&self.path)?;
+        let graph = match self.format {
+            GraphFormat::Dot => dot_graph(&workflow),
+            GraphFormat::Mermaid => mermaid_graph(&workflow),
+        };
+        print!("{graph}");
+        Ok(())
+    }
+}
+
+/// The states of a workflow, starting with `init`, then sorted by name
+fn sorted_states(workflow: &OperationWorkflow) -> Vec<(&StateName, &OperationAction)> {
+    let mut states: Vec<_> = workflow.states.iter().collect();
+    states.sort_by_key(|(state, _)| (state.as_str() != "init", state.as_str()));
+    states
+}
+
+/// The transitions from a state, including the transition on timeout if any
+///
+/// No transitions are given for a delegated action, as the next state is then chosen by the delegate.
+fn transitions(
+    workflow: &OperationWorkflow,
+    state: &str,
+    action: &OperationAction,
+) -> Vec<(String, StateName)> {
+    let mut transitions = action.transitions().unwrap_or_default();
+    if let Some(timeout) = workflow.timeouts.get(state) {
+        transitions.push(("timeout".to_string(), timeout.on_timeout.status.clone()));
+    }
+    transitions
+}
+
+fn dot_graph(workflow: &OperationWorkflow) -> String {
+    let quote = |text: &str| {
+        let text = text
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        format!("\"{text}\"")
+    };
+    let mut graph = format!("digraph {} {{\n", quote(&workflow.operation.to_string()));
+    for (state, action) in sorted_states(workflow) {
+        let shape = match action {
+            OperationAction::Clear => "doublecircle",
+            _ => "box",
+        };
+        let label = format!("{state}\n{action}");
+        graph.push_str(&format!(
+            "    {} [shape={shape}, label={}];\n",
+            quote(state),
+            quote(&label)
+        ));
+    }
+    for (state, action) in sorted_states(workflow) {
+        for (label, next_state) in transitions(workflow, state, action) {
+            graph.push_str(&format!(
+                "    {} -> {} [label={}];\n",
+                quote(state),
+                quote(&next_state),
+                quote(&label)
+            ));
+        }
+    }
+    graph.push_str("}\n");
+    graph
+}
+
+fn mermaid_graph(workflow: &OperationWorkflow) -> String {
+    // Mermaid state ids are restricted to alphanumeric characters and underscores
+    let id = |state: &str| {
+        let id: String = state
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        format!("s_{id}")
+    };
+    let mut graph = "stateDiagram-v2\n".to_string();
+    for (state, _) in sorted_states(workflow) {
+        graph.push_str(&format!("    state \"{state}\" as {}\n", id(state)));
+    }
+    graph.push_str(&format!("    [*] --> {}\n", id("init")));
+    for (state, action) in sorted_states(workflow) {
+        if let OperationAction::Clear = action {
+            graph.push_str(&format!("    {} --> [*]\n", id(state)));
+        }
+        for (label, next_state) in transitions(workflow, state, action) {
+            let (from, to) = (id(state), id(&next_state));
+            graph.push_str(&format!("    {from} --> {to} : {label}\n"));
+        }
+    }
+    graph
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workflow() -> OperationWorkflow {
+        toml::from_str(
+            r#"
+operation = "check"
+
+[init]
+action = "proceed"
+on_success = "run-check"
+
+[run-check]
+script = "/some/check.sh"
+on_success = "successful"
+
+[successful]
+action = "cleanup"
+
+[failed]
+action = "cleanup"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn print_workflow_as_dot_graph() {
+        assert_eq!(
+            dot_graph(&workflow()),
+            r#"digraph "check" {
+    "init" [shape=box, label="init\nmove to run-check state"];
+    "failed" [shape=doublecircle, label="failed\nwait for the requester to finalize the command"];
+    "run-check" [shape=box, label="run-check\n/some/check.sh"];
+    "successful" [shape=doublecircle, label="successful\nwait for the requester to finalize the command"];
+    "init" -> "run-check" [label="proceed"];
+    "run-check" -> "successful" [label="success"];
+    "run-check" -> "failed" [label="error"];
+    "run-check" -> "failed" [label="kill"];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn print_workflow_as_mermaid_graph() {
+        assert_eq!(
+            mermaid_graph(&workflow()),
+            r#"stateDiagram-v2
+    state "init" as s_init
+    state "failed" as s_failed
+    state "run-check" as s_run_check
+    state "successful" as s_successful
+    [*] --> s_init
+    s_init --> s_run_check : proceed
+    s_failed --> [*]
+    s_run_check --> s_successful : success
+    s_run_check --> s_failed : error
+    s_run_check --> s_failed : kill
+    s_successful --> [*]
+"#
+        );
+    }
+}
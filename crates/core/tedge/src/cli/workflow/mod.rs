@@ -0,0 +1,23 @@
+pub use self::cli::TEdgeWorkflowCli;
+pub use self::error::WorkflowError;
+
+mod cli;
+mod error;
+mod graph;
+mod simulate;
+mod validate;
+
+use camino::Utf8Path;
+use tedge_api::workflow::OperationWorkflow;
+
+/// Load a workflow definition from a TOML file
+fn load_workflow(path: &Utf8Path) -> Result<OperationWorkflow, WorkflowError> {
+    let content = std::fs::read_to_string(path).map_err(|source| WorkflowError::ReadError {
+        path: path.to_owned(),
+        source,
+    })?;
+    toml::from_str(&content).map_err(|err| WorkflowError::ParseError {
+        path: path.to_owned(),
+        reason: err.message().to_string(),
+    })
+}
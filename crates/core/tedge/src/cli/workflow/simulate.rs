@@ -0,0 +1,384 @@
+use super::load_workflow;
+use super::WorkflowError;
+use crate::command::Command;
+use camino::Utf8PathBuf;
+use serde_json::json;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::process::Output;
+use std::str::FromStr;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::workflow::GenericCommandState;
+use tedge_api::workflow::GenericStateUpdate;
+use tedge_api::workflow::OperationAction;
+use tedge_api::workflow::OperationWorkflow;
+
+/// Maximum number of steps of a simulation, to stop on workflows that loop
+const MAX_STEPS: usize = 100;
+
+/// The simulated outcome of the action of a state
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StepOutcome {
+    /// The exit code of a script, or the success (0) or failure (non-zero) of any other action
+    ExitCode(u8),
+
+    /// The next state as chosen by a delegate or returned on a script stdout
+    NextState(String),
+}
+
+/// A `<state>=<outcome>` command line argument
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StateOutcome {
+    pub state: String,
+    pub outcome: StepOutcome,
+}
+
+impl FromStr for StateOutcome {
+    type Err = WorkflowError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (state, outcome) = s
+            .split_once('=')
+            .filter(|(state, outcome)| !state.is_empty() && !outcome.is_empty())
+            .ok_or_else(|| WorkflowError::InvalidOutcome {
+                outcome: s.to_string(),
+            })?;
+        let outcome = match outcome.parse::<u8>() {
+            Ok(code) => StepOutcome::ExitCode(code),
+            Err(_) => StepOutcome::NextState(outcome.to_string()),
+        };
+        Ok(StateOutcome {
+            state: state.to_string(),
+            outcome,
+        })
+    }
+}
+
+/// Walk the states of a workflow, with simulated action outcomes
+pub struct SimulateWorkflowCmd {
+    pub path: Utf8PathBuf,
+    pub payload: Option<String>,
+    pub outcomes: Vec<StateOutcome>,
+}
+
+impl Command for SimulateWorkflowCmd {
+    fn description(&self) -> String {
+        format!("simulate the workflow {}", self.path)
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let workflow = load_workflow(&self.path)?;
+        let payload = match &self.payload {
+            None => json!({}),
+            Some(payload) => serde_json::from_str(payload).map_err(WorkflowError::from)?,
+        };
+        let mut simulation = Simulation::new(&workflow, payload, self.outcomes.clone())?;
+        let result = simulation.run(|step| println!("{step}"));
+        println!("{}", simulation.state.payload);
+        Ok(result?)
+    }
+}
+
+struct Simulation<'a> {
+    workflow: &'a OperationWorkflow,
+    state: GenericCommandState,
+    outcomes: HashMap<String, VecDeque<StepOutcome>>,
+}
+
+impl<'a> Simulation<'a> {
+    fn new(
+        workflow: &'a OperationWorkflow,
+        mut payload: Value,
+        outcomes: Vec<StateOutcome>,
+    ) -> Result<Self, WorkflowError> {
+        let Some(fields) = payload.as_object_mut() else {
+            return Err(WorkflowError::PayloadNotAnObject);
+        };
+        let status = fields
+            .entry("status")
+            .or_insert_with(|| "init".into())
+            .as_str()
+            .unwrap_or("init")
+            .to_string();
+        let topic = MqttSchema::default().topic_for(
+            &EntityTopicId::default_main_device(),
+            &Channel::Command {
+                operation: workflow.operation.clone(),
+                cmd_id: "simulation".to_string(),
+            },
+        );
+        let state = GenericCommandState {
+            topic,
+            status,
+            payload,
+        };
+
+        let mut step_outcomes: HashMap<String, VecDeque<StepOutcome>> = HashMap::new();
+        for StateOutcome { state, outcome } in outcomes {
+            step_outcomes.entry(state).or_default().push_back(outcome);
+        }
+
+        Ok(Simulation {
+            workflow,
+            state,
+            outcomes: step_outcomes,
+        })
+    }
+
+    /// The outcome of the next execution of the action of the current state
+    ///
+    /// The outcomes given for a state are consumed in order, the last one being then repeated.
+    fn outcome(&mut self) -> Option<StepOutcome> {
+        let outcomes = self.outcomes.get_mut(&self.state.status)?;
+        if outcomes.len() > 1 {
+            outcomes.pop_front()
+        } else {
+            outcomes.front().cloned()
+        }
+    }
+
+    /// Run the simulation till a terminal state, reporting each step
+    fn run(&mut self, mut report: impl FnMut(String)) -> Result<(), WorkflowError> {
+        for _ in 0..MAX_STEPS {
+            let status = self.state.status.clone();
+            let Some(action) = self.workflow.states.get(&status) else {
+                return Err(self.stop("no such state is defined"));
+            };
+            let action = action.inject_state(&self.state);
+            if let OperationAction::Clear = action {
+                report(format!("{status}: {action}"));
+                return Ok(());
+            }
+
+            let outcome = self.outcome();
+            let (next_state, note) = self.step(&action, outcome)?;
+            report(format!("{status}: {action}{note} => {}", next_state.status));
+            self.state = next_state;
+        }
+        Err(self.stop("too many steps, the workflow might loop"))
+    }
+
+    fn step(
+        &self,
+        action: &OperationAction,
+        outcome: Option<StepOutcome>,
+    ) -> Result<(GenericCommandState, String), WorkflowError> {
+        let state = self.state.clone();
+        let exit_code = match &outcome {
+            None => 0,
+            Some(StepOutcome::ExitCode(code)) => *code,
+            Some(StepOutcome::NextState(next_state)) => {
+                // Whatever the action, a delegate or a script can freely choose the next state
+                return Ok((state.move_to(next_state.clone()), String::new()));
+            }
+        };
+        let note = if exit_code == 0 {
+            String::new()
+        } else {
+            format!(" (exit {exit_code})")
+        };
+
+        let next_state = match action {
+            OperationAction::MoveTo(next_state) => state.move_to(next_state.clone()),
+            OperationAction::BuiltIn => {
+                if state.status != "executing" && self.workflow.states.contains_key("executing") {
+                    state.move_to("executing".to_string())
+                } else if exit_code == 0 {
+                    state.update(GenericStateUpdate::successful())
+                } else {
+                    state.fail_with(format!("builtin action failed with code {exit_code}"))
+                }
+            }
+            OperationAction::Delegate(participant) => {
+                let reason = format!(
+                    "waiting for {participant}, use --outcome {}=<next-state> to proceed",
+                    state.status
+                );
+                return Err(self.stop(&reason));
+            }
+            OperationAction::Restart {
+                on_success,
+                on_error,
+                ..
+            } => {
+                if exit_code == 0 {
+                    state.move_to(on_success.clone())
+                } else {
+                    state.move_to(on_error.clone())
+                }
+            }
+            OperationAction::Operation {
+                on_success,
+                on_error,
+                ..
+            } => {
+                if exit_code == 0 {
+                    state.update(on_success.clone())
+                } else {
+                    state.update(on_error.clone())
+                }
+            }
+            OperationAction::Script(script, handlers) => {
+                let output = Output {
+                    status: ExitStatus::from_raw((exit_code as i32) << 8),
+                    stdout: vec![],
+                    stderr: vec![],
+                };
+                let output = Ok(output);
+                if let Some(delay) = handlers.retry_delay(&output, state.attempt()) {
+                    let note = format!("{note} retry in {}s", delay.as_secs());
+                    return Ok((state.retry(), note));
+                }
+                state.update_with_script_output(script.command.clone(), output, handlers.clone())
+            }
+            OperationAction::BgScript(_, handlers) => state.update(handlers.on_exec.clone()),
+            OperationAction::Evaluate(handlers) => {
+                let update = handlers.state_update(&state);
+                state.update(update)
+            }
+            OperationAction::Clear => state,
+        };
+        Ok((next_state, note))
+    }
+
+    fn stop(&self, reason: &str) -> WorkflowError {
+        WorkflowError::SimulationStopped {
+            state: self.state.status.clone(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workflow() -> OperationWorkflow {
+        toml::from_str(
+            r#"
+operation = "check"
+
+[init]
+action = "proceed"
+on_success = "check"
+
+[check]
+script = "/some/check.sh ${.payload.target}"
+on_exit.0 = "successful"
+on_exit.1 = "confirm"
+on_exit._ = "failed"
+
+[confirm]
+on_condition = [ { if = "${.payload.force} == true", then = "check" } ]
+on_success = "waiting"
+
+[waiting]
+action = "waiting operator"
+
+[successful]
+action = "cleanup"
+
+[failed]
+action = "cleanup"
+"#,
+        )
+        .unwrap()
+    }
+
+    fn simulate(payload: Value, outcomes: &[&str]) -> (Vec<String>, Result<(), WorkflowError>) {
+        let workflow = workflow();
+        let outcomes = outcomes.iter().map(|o| o.parse().unwrap()).collect();
+        let mut simulation = Simulation::new(&workflow, payload, outcomes).unwrap();
+        let mut steps = vec![];
+        let result = simulation.run(|step| steps.push(step));
+        (steps, result)
+    }
+
+    #[test]
+    fn simulate_successful_path() {
+        let (steps, result) = simulate(json!({"target": "x"}), &[]);
+        assert!(result.is_ok());
+        assert_eq!(
+            steps,
+            vec![
+                "init: move to check state => check",
+                "check: /some/check.sh x => successful",
+                "successful: wait for the requester to finalize the command",
+            ]
+        );
+    }
+
+    #[test]
+    fn simulate_with_scripted_exit_codes() {
+        let (steps, result) = simulate(
+            json!({"target": "x", "force": true}),
+            &["check=1", "check=2"],
+        );
+        assert!(result.is_ok());
+        assert_eq!(
+            steps,
+            vec![
+                "init: move to check state => check",
+                "check: /some/check.sh x (exit 1) => confirm",
+                "confirm: evaluate conditions => check",
+                "check: /some/check.sh x (exit 2) => failed",
+                "failed: wait for the requester to finalize the command",
+            ]
+        );
+    }
+
+    #[test]
+    fn simulation_stops_on_delegated_steps() {
+        let (steps, result) = simulate(json!({"target": "x"}), &["check=1"]);
+        assert_eq!(
+            steps,
+            vec![
+                "init: move to check state => check",
+                "check: /some/check.sh x (exit 1) => confirm",
+                "confirm: evaluate conditions => waiting",
+            ]
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Simulation stopped in waiting state: waiting for operator, use --outcome waiting=<next-state> to proceed"
+        );
+
+        let (_, result) = simulate(json!({"target": "x"}), &["check=1", "waiting=successful"]);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn simulation_stops_on_loops() {
+        let (steps, result) = simulate(json!({"target": "x", "force": true}), &["check=1"]);
+        assert_eq!(steps.len(), MAX_STEPS);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Simulation stopped in confirm state: too many steps, the workflow might loop"
+        );
+    }
+
+    #[test]
+    fn parse_state_outcomes() {
+        assert_eq!(
+            "check=1".parse::<StateOutcome>().unwrap(),
+            StateOutcome {
+                state: "check".to_string(),
+                outcome: StepOutcome::ExitCode(1)
+            }
+        );
+        assert_eq!(
+            "waiting=successful".parse::<StateOutcome>().unwrap(),
+            StateOutcome {
+                state: "waiting".to_string(),
+                outcome: StepOutcome::NextState("successful".to_string())
+            }
+        );
+        assert!("check".parse::<StateOutcome>().is_err());
+        assert!("=1".parse::<StateOutcome>().is_err());
+    }
+}
@@ -0,0 +1,33 @@
+use super::load_workflow;
+use super::WorkflowError;
+use crate::command::Command;
+use camino::Utf8PathBuf;
+
+/// Check a workflow definition, reporting all the issues found
+pub struct ValidateWorkflowCmd {
+    pub path: Utf8PathBuf,
+}
+
+impl Command for ValidateWorkflowCmd {
+    fn description(&self) -> String {
+        format!("validate the workflow definition {}", self.path)
+    }
+
+    fn execute(&self) -> anyhow::Result<()> {
+        let workflow = load_workflow(&self.path)?;
+        let errors = workflow.validate();
+        if errors.is_empty() {
+            println!("{}: valid {} workflow", self.path, workflow.operation);
+            return Ok(());
+        }
+
+        for error in errors.iter() {
+            eprintln!("{}: {error}", self.path);
+        }
+        Err(WorkflowError::InvalidWorkflow {
+            path: self.path.clone(),
+            count: errors.len(),
+        }
+        .into())
+    }
+}
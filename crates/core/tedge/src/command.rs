@@ -88,7 +88,7 @@ pub trait Command {
     ///
     /// impl SomeStruct {
     ///     fn build_command(self, config: TEdgeConfig) -> Result<Box<dyn Command>, ConfigError> {
-    ///         let cmd = GetConfigCommand { config, key: ReadableKey::MqttBindPort };
+    ///         let cmd = GetConfigCommand { config, key: ReadableKey::MqttBindPort.into() };
     ///         Ok(cmd.into_boxed())
     ///     }
     /// }
@@ -127,12 +127,12 @@ pub trait Command {
 ///         let cmd = match self {
 ///             ConfigCmd::Set { key, value } => SetConfigCommand {
 ///                 config_repository: context.config_repository,
-///                 key,
+///                 key: key.into(),
 ///                 value,
 ///             }.into_boxed(),
 ///             ConfigCmd::Get { key } => GetConfigCommand {
 ///                 config: context.config_repository.load()?,
-///                 key,
+///                 key: key.into(),
 ///             }.into_boxed(),
 ///         };
 ///         Ok(cmd)
@@ -30,4 +30,13 @@ pub enum TEdgeError {
 
     #[error(transparent)]
     FromConfigNotSet(#[from] tedge_config::ConfigNotSet),
+
+    #[error(transparent)]
+    FromProfile(#[from] tedge_config::ProfileError),
+
+    #[error(transparent)]
+    FromCertificate(#[from] certificate::CertificateError),
+
+    #[error("The mosquitto bridge cannot use the private key {key_uri}, which is stored on a PKCS#11 token.")]
+    KeyNotSupportedByBridge { key_uri: String },
 }
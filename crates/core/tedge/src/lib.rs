@@ -6,3 +6,6 @@ pub mod error;
 pub type ConfigError = crate::error::TEdgeError;
 const BROKER_USER: &str = "mosquitto";
 const BROKER_GROUP: &str = "mosquitto";
+
+/// The group of the thin-edge services, which are granted read access to the device private key
+const TEDGE_GROUP: &str = "tedge";
@@ -11,6 +11,8 @@ use tedge::Component;
 use tedge::TEdgeOptMulticall;
 use tedge_apt_plugin::AptCli;
 use tedge_config::system_services::set_log_level;
+use tedge_container_plugin::ContainerCli;
+use tedge_container_plugin::ModuleKind;
 
 fn main() -> anyhow::Result<()> {
     let executable_name = executable_name();
@@ -20,6 +22,13 @@ fn main() -> anyhow::Result<()> {
         tedge_apt_plugin::run_and_exit(try_opt);
     }
 
+    if let Some(name @ ("container" | "container-group" | "tedge-container-plugin")) =
+        executable_name.as_deref()
+    {
+        let try_opt = ContainerCli::try_parse();
+        tedge_container_plugin::run_and_exit(try_opt, ModuleKind::from_executable_name(name));
+    }
+
     let opt = parse_multicall_if_known(&executable_name);
     match opt {
         TEdgeOptMulticall::Component(Component::TedgeMapper(mapper_opt)) => {
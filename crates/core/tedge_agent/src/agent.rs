@@ -1,3 +1,9 @@
+use crate::cert_renewal::builder::CertRenewalBuilder;
+use crate::cert_renewal::config::CertRenewalConfig;
+use crate::entity_manager::actor::EntityManager;
+use crate::entity_manager::actor::EntityStoreClient;
+use crate::entity_manager::new_entity_store;
+use crate::entity_manager::server::EntityStoreState;
 use crate::file_transfer_server::actor::FileTransferServerBuilder;
 use crate::file_transfer_server::actor::FileTransferServerConfig;
 use crate::restart_manager::builder::RestartManagerBuilder;
@@ -16,20 +22,27 @@ use flockfile::check_another_instance_is_not_running;
 use flockfile::Flockfile;
 use flockfile::FlockfileError;
 use log::error;
-use reqwest::Identity;
+use rustls::ClientConfig;
 use std::ffi::OsStr;
 use std::fmt::Debug;
 use std::net::SocketAddr;
 use std::path::Path;
 use std::sync::Arc;
+use tedge_actors::adapt;
 use tedge_actors::Concurrent;
 use tedge_actors::ConvertingActor;
 use tedge_actors::ConvertingActorBuilder;
 use tedge_actors::MessageSink;
 use tedge_actors::MessageSource;
+use tedge_actors::NoConfig;
+use tedge_actors::NullSender;
 use tedge_actors::Runtime;
+use tedge_actors::Sequential;
 use tedge_actors::ServerActorBuilder;
+use tedge_actors::ServiceProvider;
+use tedge_api::mqtt_topics::ChannelFilter;
 use tedge_api::mqtt_topics::DeviceTopicId;
+use tedge_api::mqtt_topics::EntityFilter;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
 use tedge_api::mqtt_topics::Service;
@@ -50,8 +63,10 @@ use tedge_mqtt_ext::MqttConfig;
 use tedge_mqtt_ext::TopicFilter;
 use tedge_script_ext::ScriptActor;
 use tedge_signal_ext::SignalActor;
+use tedge_timer_ext::TimerActor;
 use tedge_uploader_ext::UploaderActor;
 use tedge_utils::file::create_directory_with_defaults;
+use tokio::sync::Mutex;
 use tracing::info;
 use tracing::instrument;
 use tracing::warn;
@@ -64,17 +79,19 @@ pub(crate) struct AgentConfig {
     pub http_config: FileTransferServerConfig,
     pub restart_config: RestartManagerConfig,
     pub sw_update_config: SoftwareManagerConfig,
+    pub cert_renewal_config: Option<CertRenewalConfig>,
     pub config_dir: Utf8PathBuf,
     pub tmp_dir: Arc<Utf8Path>,
     pub run_dir: Utf8PathBuf,
     pub use_lock: bool,
     pub log_dir: Utf8PathBuf,
+    pub state_dir: Utf8PathBuf,
     pub data_dir: DataDir,
     pub operations_dir: Utf8PathBuf,
     pub mqtt_device_topic_id: EntityTopicId,
     pub mqtt_topic_root: Arc<str>,
     pub service_type: String,
-    pub identity: Option<Identity>,
+    pub identity: Option<ClientConfig>,
     pub is_sudo_enabled: bool,
     pub capabilities: Capabilities,
 }
@@ -113,8 +130,12 @@ impl AgentConfig {
         let http_bind_address = tedge_config.http.bind.address;
         let http_port = tedge_config.http.bind.port;
 
+        // For agent specific
+        let log_dir = tedge_config.logs.path.join("agent");
+
         let http_config = FileTransferServerConfig {
             file_transfer_dir: data_dir.file_transfer_dir(),
+            log_dir: log_dir.clone(),
             cert_path: tedge_config.http.cert_path.clone(),
             key_path: tedge_config.http.key_path.clone(),
             ca_path: tedge_config.http.ca_path.clone(),
@@ -128,12 +149,19 @@ impl AgentConfig {
         // Software update config
         let sw_update_config = SoftwareManagerConfig::from_tedge_config(tedge_config_location)?;
 
+        // Certificate renewal config, only when an EST server is configured
+        let cert_renewal_config = CertRenewalConfig::from_tedge_config(
+            MqttSchema::with_root(mqtt_topic_root.to_string()),
+            &mqtt_device_topic_id,
+            config_dir.clone(),
+            &tedge_config,
+        );
+
         // For flockfile
         let run_dir = tedge_config.run.path.clone();
         let use_lock = tedge_config.run.lock_files;
 
-        // For agent specific
-        let log_dir = tedge_config.logs.path.join("agent");
+        let state_dir = tedge_config.agent.state.path.clone();
         let operations_dir = config_dir.join("operations");
 
         let identity = tedge_config.http.client.auth.identity()?;
@@ -151,12 +179,14 @@ impl AgentConfig {
             http_config,
             restart_config,
             sw_update_config,
+            cert_renewal_config,
             config_dir,
             run_dir,
             tmp_dir,
             use_lock,
             data_dir,
             log_dir,
+            state_dir,
             operations_dir,
             mqtt_topic_root,
             mqtt_device_topic_id,
@@ -223,16 +253,22 @@ impl Agent {
         // Software update actor
         let mut software_update_builder = SoftwareManagerBuilder::new(self.config.sw_update_config);
 
+        // Timer actor, used to enforce the state timeouts and retries of the workflows
+        let mut timer_actor_builder = TimerActor::builder();
+
         // Converter actor
         let converter_actor_builder = TedgeOperationConverterBuilder::new(
             self.config.mqtt_topic_root.as_ref(),
             self.config.mqtt_device_topic_id.clone(),
             workflows,
             self.config.log_dir.clone(),
+            self.config.state_dir.clone(),
+            self.config.config_dir.clone(),
             &mut software_update_builder,
             &mut restart_actor_builder,
             &mut mqtt_actor_builder,
             &mut script_runner,
+            &mut timer_actor_builder,
         );
 
         // Shutdown on SIGINT
@@ -295,7 +331,7 @@ impl Agent {
             let log_manager_config = LogManagerConfig::from_options(LogManagerOptions {
                 config_dir: self.config.config_dir.clone().into(),
                 tmp_dir: self.config.config_dir.into(),
-                mqtt_schema,
+                mqtt_schema: mqtt_schema.clone(),
                 mqtt_device_topic_id: self.config.mqtt_device_topic_id.clone(),
             })?;
             Some(
@@ -311,6 +347,37 @@ impl Agent {
             None
         };
 
+        // TODO: replace with a call to entity store when we stop assuming default MQTT schema
+        let is_main_device =
+            self.config.mqtt_device_topic_id == EntityTopicId::default_main_device();
+
+        // Entity manager, owning the entity store exposed over HTTP
+        // and kept in sync with the entity registration messages
+        let entity_manager = if is_main_device {
+            let mut entity_manager =
+                create_entity_manager(mqtt_schema.clone(), &mut mqtt_actor_builder);
+            let entity_store_state = EntityStoreState {
+                entity_store: Arc::new(Mutex::new(EntityStoreClient::new(
+                    "HTTP => EntityManager",
+                    &mut entity_manager,
+                ))),
+                mqtt_schema,
+                mqtt_publisher: mqtt_actor_builder.get_sender(),
+            };
+            Some((entity_manager, entity_store_state))
+        } else {
+            None
+        };
+
+        // Certificate renewal actor, renewing the certificate used by the cloud bridges
+        let cert_renewal_builder = match self.config.cert_renewal_config {
+            Some(cert_renewal_config) if is_main_device => Some(CertRenewalBuilder::new(
+                cert_renewal_config,
+                &mut mqtt_actor_builder,
+            )),
+            _ => None,
+        };
+
         // Spawn all
         runtime.spawn(signal_actor_builder).await?;
         runtime.spawn(mqtt_actor_builder).await?;
@@ -326,20 +393,24 @@ impl Agent {
         runtime.spawn(restart_actor_builder).await?;
         runtime.spawn(software_update_builder).await?;
         runtime.spawn(script_runner).await?;
+        runtime.spawn(timer_actor_builder).await?;
         runtime.spawn(converter_actor_builder).await?;
         runtime.spawn(health_actor).await?;
+        if let Some(cert_renewal_builder) = cert_renewal_builder {
+            runtime.spawn(cert_renewal_builder).await?;
+        }
 
-        // TODO: replace with a call to entity store when we stop assuming default MQTT schema
-        let is_main_device =
-            self.config.mqtt_device_topic_id == EntityTopicId::default_main_device();
-        if is_main_device {
+        if let Some((entity_manager, entity_store_state)) = entity_manager {
             info!(
                 "Running as a main device, starting tedge_to_te_converter and file transfer actors"
             );
 
             let file_transfer_server_builder =
-                FileTransferServerBuilder::try_bind(self.config.http_config).await?;
+                FileTransferServerBuilder::try_bind(self.config.http_config)
+                    .await?
+                    .with_entity_store(entity_store_state);
             runtime.spawn(tedge_to_te_converter).await?;
+            runtime.spawn(entity_manager).await?;
             runtime.spawn(file_transfer_server_builder).await?;
         } else {
             info!("Running as a child device, tedge_to_te_converter and file transfer actors disabled");
@@ -415,3 +486,18 @@ pub fn create_tedge_to_te_converter(
 
     Ok(tedge_converter_actor)
 }
+
+fn create_entity_manager(
+    mqtt_schema: MqttSchema,
+    mqtt_actor_builder: &mut MqttActorBuilder,
+) -> ServerActorBuilder<EntityManager, Sequential> {
+    let subscriptions = mqtt_schema.topics(EntityFilter::AnyEntity, ChannelFilter::EntityMetadata);
+    let mut entity_manager =
+        EntityManager::builder(new_entity_store(mqtt_schema.clone()), mqtt_schema);
+
+    // The entity registration messages are forwarded to the entity manager, ignoring its responses
+    let requests = entity_manager.connect_consumer(NoConfig, NullSender.into());
+    mqtt_actor_builder.register_peer(subscriptions, adapt(&requests));
+
+    entity_manager
+}
@@ -0,0 +1,259 @@
+use crate::cert_renewal::config::CertRenewalConfig;
+use crate::cert_renewal::error::CertRenewalError;
+use crate::cert_renewal::est_client::EstClient;
+use async_trait::async_trait;
+use certificate::KeyCertPair;
+use certificate::KeyKind;
+use certificate::NewCertificateConfig;
+use certificate::PemCertificate;
+use serde_json::json;
+use tedge_actors::Actor;
+use tedge_actors::ChannelError;
+use tedge_actors::MessageReceiver;
+use tedge_actors::NoMessage;
+use tedge_actors::RuntimeError;
+use tedge_actors::Sender;
+use tedge_actors::SimpleMessageBox;
+use tedge_api::mqtt_topics::Channel;
+use tedge_config::system_services::service_manager;
+use tedge_config_manager::TedgeWriteStatus;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::QoS;
+use tedge_utils::file::replace_with_read_only_file;
+use tedge_write::CopyOptions;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+const CERT_RENEWED_EVENT: &str = "cert_renewed";
+const CERT_EXPIRING_ALARM: &str = "cert_expiring";
+
+/// The owner of the device certificate, which is read by the MQTT broker
+const BROKER_USER: &str = "mosquitto";
+const BROKER_GROUP: &str = "mosquitto";
+
+/// Renew the device certificate using an EST server, when the certificate is about to expire
+pub struct CertRenewalActor {
+    config: CertRenewalConfig,
+    message_box: SimpleMessageBox<NoMessage, MqttMessage>,
+}
+
+#[async_trait]
+impl Actor for CertRenewalActor {
+    fn name(&self) -> &str {
+        "CertRenewalActor"
+    }
+
+    async fn run(mut self) -> Result<(), RuntimeError> {
+        loop {
+            self.check_certificate().await?;
+
+            tokio::select! {
+                _ = tokio::time::sleep(self.config.check_interval) => {}
+                _ = self.message_box.recv_signal() => return Ok(()),
+            }
+        }
+    }
+}
+
+impl CertRenewalActor {
+    pub fn new(
+        config: CertRenewalConfig,
+        message_box: SimpleMessageBox<NoMessage, MqttMessage>,
+    ) -> Self {
+        Self {
+            config,
+            message_box,
+        }
+    }
+
+    async fn check_certificate(&mut self) -> Result<(), ChannelError> {
+        let expiration = match PemCertificate::from_pem_file(&self.config.cert_path)
+            .and_then(|cert| cert.expiration_time())
+        {
+            Ok(expiration) => expiration,
+            Err(err) => {
+                warn!(
+                    "Cannot read the expiration date of the device certificate {}: {err}",
+                    self.config.cert_path
+                );
+                return Ok(());
+            }
+        };
+
+        if OffsetDateTime::now_utc() + self.config.renewal_window < expiration {
+            return Ok(());
+        }
+
+        info!(
+            "The device certificate expires at {}, renewing it",
+            format_time(expiration)
+        );
+        match self.renew_certificate().await {
+            Ok(new_expiration) => {
+                info!(
+                    "The device certificate has been renewed, now expiring at {}",
+                    format_time(new_expiration)
+                );
+                self.publish_renewed_event(new_expiration).await?;
+                // Clear any alarm raised on a previous failure, possibly before a restart
+                self.clear_expiring_alarm().await?;
+            }
+            Err(err) => {
+                error!("Fail to renew the device certificate: {err}");
+                self.raise_expiring_alarm(expiration, err).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Re-enroll the device, returning the expiration time of the new certificate
+    async fn renew_certificate(&self) -> Result<OffsetDateTime, CertRenewalError> {
+        let cert_path = &self.config.cert_path;
+        let key_path = &self.config.key_path;
+
+        let keypair_pem = tokio::fs::read_to_string(key_path).await?;
+        let id = PemCertificate::from_pem_file(cert_path)?.subject_common_name()?;
+        let csr = KeyCertPair::new_certificate_signing_request(
+            &NewCertificateConfig::default(),
+            &id,
+            &KeyKind::Reuse {
+                keypair_pem: keypair_pem.clone(),
+            },
+        )?;
+
+        let est_client = EstClient::try_new(
+            &self.config.est_url,
+            cert_path,
+            key_path,
+            self.config.est_root_cert_path.as_deref(),
+        )?;
+        let chain = est_client
+            .simple_reenroll(&csr.certificate_signing_request_der()?)
+            .await?;
+        chain.validate_against_key(&keypair_pem)?;
+        let expiration = chain.device_certificate().expiration_time()?;
+
+        let config = self.config.clone();
+        let chain_pem = chain.to_pem_string();
+        tokio::task::spawn_blocking(move || install_certificate(&config, &chain_pem))
+            .await
+            .map_err(std::io::Error::other)??;
+
+        self.reconnect_bridges().await;
+        Ok(expiration)
+    }
+
+    /// Restart the services running the cloud bridges, for these bridges to use the new certificate
+    async fn reconnect_bridges(&self) {
+        for service in self.config.bridge_services.iter().cloned() {
+            let config_dir = self.config.config_dir.clone();
+            let service_name = service.to_string();
+            let restart = tokio::task::spawn_blocking(move || {
+                service_manager(&config_dir)?.restart_service_if_running(service)
+            })
+            .await;
+            match restart {
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => {
+                    warn!("Fail to restart {service_name} with the new certificate: {err}")
+                }
+                Err(err) => warn!("Fail to restart {service_name} with the new certificate: {err}"),
+            }
+        }
+    }
+
+    async fn publish_renewed_event(
+        &mut self,
+        expiration: OffsetDateTime,
+    ) -> Result<(), ChannelError> {
+        let topic = self.config.mqtt_schema.topic_for(
+            &self.config.device_topic_id,
+            &Channel::Event {
+                event_type: CERT_RENEWED_EVENT.to_string(),
+            },
+        );
+        let payload = json!({
+            "text": "Device certificate renewed",
+            "time": format_time(OffsetDateTime::now_utc()),
+            "notAfter": format_time(expiration),
+        });
+        let message = MqttMessage::new(&topic, payload.to_string()).with_qos(QoS::AtLeastOnce);
+        self.message_box.send(message).await
+    }
+
+    async fn raise_expiring_alarm(
+        &mut self,
+        expiration: OffsetDateTime,
+        err: CertRenewalError,
+    ) -> Result<(), ChannelError> {
+        let payload = json!({
+            "text": format!("The device certificate expires at {} and cannot be renewed: {err}", format_time(expiration)),
+            "severity": "major",
+            "time": format_time(OffsetDateTime::now_utc()),
+        });
+        self.publish_expiring_alarm(payload.to_string()).await
+    }
+
+    async fn clear_expiring_alarm(&mut self) -> Result<(), ChannelError> {
+        self.publish_expiring_alarm("".to_string()).await
+    }
+
+    async fn publish_expiring_alarm(&mut self, payload: String) -> Result<(), ChannelError> {
+        let topic = self.config.mqtt_schema.topic_for(
+            &self.config.device_topic_id,
+            &Channel::Alarm {
+                alarm_type: CERT_EXPIRING_ALARM.to_string(),
+            },
+        );
+        let message = MqttMessage::new(&topic, payload)
+            .with_qos(QoS::AtLeastOnce)
+            .with_retain();
+        self.message_box.send(message).await
+    }
+}
+
+/// Install the renewed certificate as `tedge cert import` does
+///
+/// As the device certificates are owned by root, the certificate is written using `tedge-write`,
+/// the agent being granted the permission to run it with sudo.
+fn install_certificate(
+    config: &CertRenewalConfig,
+    chain_pem: &str,
+) -> Result<(), CertRenewalError> {
+    match config.use_tedge_write {
+        TedgeWriteStatus::Disabled => {
+            replace_with_read_only_file(
+                &config.cert_path,
+                chain_pem.as_bytes(),
+                BROKER_USER,
+                BROKER_GROUP,
+            )?;
+        }
+
+        TedgeWriteStatus::Enabled { sudo } => {
+            let renewed_cert_path = config.tmp_dir.join("renewed-device-cert.pem");
+            std::fs::write(&renewed_cert_path, chain_pem)?;
+            let copy = CopyOptions {
+                from: &renewed_cert_path,
+                to: &config.cert_path,
+                sudo,
+                mode: Some(0o444),
+                user: Some(BROKER_USER),
+                group: Some(BROKER_GROUP),
+            }
+            .copy();
+            let _ = std::fs::remove_file(&renewed_cert_path);
+            copy.map_err(CertRenewalError::TedgeWrite)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn format_time(time: OffsetDateTime) -> String {
+    time.format(&Rfc3339).unwrap_or_else(|_| time.to_string())
+}
@@ -0,0 +1,51 @@
+use crate::cert_renewal::actor::CertRenewalActor;
+use crate::cert_renewal::config::CertRenewalConfig;
+use std::convert::Infallible;
+use tedge_actors::Builder;
+use tedge_actors::DynSender;
+use tedge_actors::MessageSink;
+use tedge_actors::MessageSource;
+use tedge_actors::NoConfig;
+use tedge_actors::NoMessage;
+use tedge_actors::RuntimeRequest;
+use tedge_actors::RuntimeRequestSink;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tedge_mqtt_ext::MqttMessage;
+
+pub struct CertRenewalBuilder {
+    config: CertRenewalConfig,
+    message_box: SimpleMessageBoxBuilder<NoMessage, MqttMessage>,
+}
+
+impl CertRenewalBuilder {
+    pub fn new(
+        config: CertRenewalConfig,
+        mqtt: &mut impl MessageSink<MqttMessage, NoConfig>,
+    ) -> Self {
+        let mut message_box = SimpleMessageBoxBuilder::new("CertRenewal", 16);
+        message_box.add_sink(mqtt);
+
+        Self {
+            config,
+            message_box,
+        }
+    }
+}
+
+impl RuntimeRequestSink for CertRenewalBuilder {
+    fn get_signal_sender(&self) -> DynSender<RuntimeRequest> {
+        self.message_box.get_signal_sender()
+    }
+}
+
+impl Builder<CertRenewalActor> for CertRenewalBuilder {
+    type Error = Infallible;
+
+    fn try_build(self) -> Result<CertRenewalActor, Self::Error> {
+        Ok(self.build())
+    }
+
+    fn build(self) -> CertRenewalActor {
+        CertRenewalActor::new(self.config, self.message_box.build())
+    }
+}
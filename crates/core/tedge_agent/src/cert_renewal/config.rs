@@ -0,0 +1,87 @@
+use camino::Utf8PathBuf;
+use certificate::key_uri::KeyUri;
+use std::time::Duration;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_config::system_services::SystemService;
+use tedge_config::ProfiledCloud;
+use tedge_config::TEdgeConfig;
+use tedge_config_manager::TedgeWriteStatus;
+use tracing::warn;
+
+#[derive(Debug, Clone)]
+pub struct CertRenewalConfig {
+    pub mqtt_schema: MqttSchema,
+    pub device_topic_id: EntityTopicId,
+    pub config_dir: Utf8PathBuf,
+    pub tmp_dir: Utf8PathBuf,
+    pub cert_path: Utf8PathBuf,
+    pub key_path: Utf8PathBuf,
+    pub est_url: String,
+    pub est_root_cert_path: Option<Utf8PathBuf>,
+    pub renewal_window: Duration,
+    pub check_interval: Duration,
+
+    /// How the renewed certificate is written, the device certificates being owned by root
+    pub use_tedge_write: TedgeWriteStatus,
+
+    /// The services running the cloud bridges, to be restarted with the renewed certificate
+    pub bridge_services: Vec<SystemService>,
+}
+
+impl CertRenewalConfig {
+    /// Build the renewal config from the tedge config,
+    /// returning `None` when no EST server is configured
+    pub fn from_tedge_config(
+        mqtt_schema: MqttSchema,
+        device_topic_id: &EntityTopicId,
+        config_dir: Utf8PathBuf,
+        tedge_config: &TEdgeConfig,
+    ) -> Option<CertRenewalConfig> {
+        let est_url = tedge_config.device.est.url.or_none()?.clone();
+
+        // A CSR cannot be signed with a key stored on a PKCS#11 token
+        let key_path = match tedge_config.device.key_uri() {
+            Ok(KeyUri::File(path)) => Utf8PathBuf::try_from(path).ok()?,
+            Ok(key_uri) => {
+                warn!("The device certificate will not be renewed: the private key {key_uri} is stored on a PKCS#11 token");
+                return None;
+            }
+            Err(err) => {
+                warn!("The device certificate will not be renewed: {err}");
+                return None;
+            }
+        };
+        // These are the mappers of all the cloud profiles when the bridge is built into the mappers,
+        // the MQTT broker otherwise
+        let bridge_services = if tedge_config.mqtt.bridge.built_in {
+            [ProfiledCloud::C8y, ProfiledCloud::Az, ProfiledCloud::Aws]
+                .into_iter()
+                .flat_map(|cloud| {
+                    std::iter::once(None)
+                        .chain(tedge_config.profile_names(cloud).into_iter().map(Some))
+                        .map(move |profile| SystemService::mapper(cloud, profile))
+                })
+                .collect()
+        } else {
+            vec![SystemService::Mosquitto]
+        };
+
+        Some(CertRenewalConfig {
+            mqtt_schema,
+            device_topic_id: device_topic_id.clone(),
+            config_dir,
+            tmp_dir: tedge_config.tmp.path.clone(),
+            cert_path: tedge_config.device.cert_path.clone(),
+            key_path,
+            est_url,
+            est_root_cert_path: tedge_config.device.est.root_cert_path.or_none().cloned(),
+            renewal_window: tedge_config.device.cert_renewal.window.duration(),
+            check_interval: tedge_config.device.cert_renewal.interval.duration(),
+            use_tedge_write: TedgeWriteStatus::Enabled {
+                sudo: tedge_config.enable.sudo,
+            },
+            bridge_services,
+        })
+    }
+}
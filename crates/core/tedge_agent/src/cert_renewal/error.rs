@@ -0,0 +1,18 @@
+#[derive(Debug, thiserror::Error)]
+#[allow(clippy::enum_variant_names)]
+pub enum CertRenewalError {
+    #[error(transparent)]
+    FromIo(#[from] std::io::Error),
+
+    #[error(transparent)]
+    FromCertificate(#[from] certificate::CertificateError),
+
+    #[error(transparent)]
+    FromReqwest(#[from] reqwest::Error),
+
+    #[error("The EST server returned an invalid response: {0}")]
+    InvalidEstResponse(String),
+
+    #[error("Fail to install the renewed certificate: {0}")]
+    TedgeWrite(anyhow::Error),
+}
@@ -0,0 +1,99 @@
+use crate::cert_renewal::error::CertRenewalError;
+use camino::Utf8Path;
+use certificate::pkcs7::certificates_from_pkcs7;
+use certificate::PemCertificateChain;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Certificate;
+use reqwest::Identity;
+
+const EST_WELL_KNOWN_PATH: &str = "/.well-known/est";
+
+/// A client of an EST server (RFC 7030), limited to the re-enrollment of the device
+pub struct EstClient {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl EstClient {
+    /// Create a client authenticated by the current device certificate and key
+    ///
+    /// The server certificate is checked against the system root certificates,
+    /// plus the certificates stored at `root_cert_path`, if any.
+    pub fn try_new(
+        url: &str,
+        cert_path: &Utf8Path,
+        key_path: &Utf8Path,
+        root_cert_path: Option<&Utf8Path>,
+    ) -> Result<Self, CertRenewalError> {
+        let mut pem = std::fs::read(key_path)?;
+        pem.extend(std::fs::read(cert_path)?);
+        let mut builder = reqwest::Client::builder().identity(Identity::from_pem(&pem)?);
+        if let Some(root_cert_path) = root_cert_path {
+            let root_certs = PemCertificateChain::from_pem_file(root_cert_path)?;
+            for cert in root_certs.der_certificates() {
+                builder = builder.add_root_certificate(Certificate::from_der(cert)?);
+            }
+        }
+
+        Ok(EstClient {
+            base_url: est_base_url(url),
+            client: builder.build()?,
+        })
+    }
+
+    /// Send a `simplereenroll` request with the given DER-encoded CSR
+    ///
+    /// Return the certificate chain issued by the EST server.
+    pub async fn simple_reenroll(
+        &self,
+        csr_der: &[u8],
+    ) -> Result<PemCertificateChain, CertRenewalError> {
+        let url = format!("{}/simplereenroll", self.base_url);
+        let response = self
+            .client
+            .post(url)
+            .header(CONTENT_TYPE, "application/pkcs10")
+            .header("Content-Transfer-Encoding", "base64")
+            .body(base64::encode(csr_der))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let body = response.text().await?;
+        let body: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+        let pkcs7 = base64::decode(body)
+            .map_err(|err| CertRenewalError::InvalidEstResponse(err.to_string()))?;
+        let certificates = certificates_from_pkcs7(&pkcs7)?;
+        Ok(PemCertificateChain::from_der_certificates(certificates)?)
+    }
+}
+
+/// The EST operations are served under `/.well-known/est`, possibly followed by a CA label
+fn est_base_url(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    if url.contains(EST_WELL_KNOWN_PATH) {
+        url.to_string()
+    } else {
+        format!("{url}{EST_WELL_KNOWN_PATH}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("https://est.example.com", "https://est.example.com/.well-known/est"; "host only")]
+    #[test_case("https://est.example.com/", "https://est.example.com/.well-known/est"; "trailing slash")]
+    #[test_case(
+        "https://est.example.com/.well-known/est/",
+        "https://est.example.com/.well-known/est"
+    )]
+    #[test_case(
+        "https://est.example.com/.well-known/est/devices",
+        "https://est.example.com/.well-known/est/devices"
+    )]
+    fn est_operations_are_under_the_well_known_path(url: &str, expected: &str) {
+        assert_eq!(est_base_url(url), expected);
+    }
+}
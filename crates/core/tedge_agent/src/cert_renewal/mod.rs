@@ -0,0 +1,8 @@
+pub mod actor;
+pub mod builder;
+pub mod config;
+pub mod error;
+pub mod est_client;
+
+#[cfg(test)]
+mod tests;
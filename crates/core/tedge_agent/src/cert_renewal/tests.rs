@@ -0,0 +1,304 @@
+use crate::cert_renewal::builder::CertRenewalBuilder;
+use crate::cert_renewal::config::CertRenewalConfig;
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::Router;
+use certificate::pkcs7::pkcs7_from_certificates;
+use certificate::PemCertificate;
+use std::net::SocketAddr;
+use std::os::unix::fs::PermissionsExt;
+use std::sync::Arc;
+use std::time::Duration;
+use tedge_actors::test_helpers::MessageReceiverExt;
+use tedge_actors::test_helpers::TimedMessageBox;
+use tedge_actors::Actor;
+use tedge_actors::Builder;
+use tedge_actors::DynError;
+use tedge_actors::MessageReceiver;
+use tedge_actors::SimpleMessageBox;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_config::system_services::SystemService;
+use tedge_config::ProfiledCloud;
+use tedge_config::TEdgeConfig;
+use tedge_config::TEdgeConfigDto;
+use tedge_config::TEdgeConfigLocation;
+use tedge_config::WritableKey;
+use tedge_config_manager::TedgeWriteStatus;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_test_utils::fs::TempTedgeDir;
+use time::OffsetDateTime;
+
+const TEST_TIMEOUT_MS: Duration = Duration::from_millis(5000);
+
+#[tokio::test]
+async fn renew_a_certificate_about_to_expire() -> Result<(), DynError> {
+    let pki = TestPki::new();
+    let temp_dir = TempTedgeDir::new();
+    pki.install_device_certificate(&temp_dir, time::Duration::days(2));
+
+    let est_url = spawn_est_server(pki.clone(), StatusCode::OK).await;
+    let mut mqtt_box = spawn_cert_renewal(&temp_dir, &est_url).await?;
+
+    let event = mqtt_box.recv().await.expect("cert_renewed event");
+    assert_eq!(event.topic.name, "te/device/main///e/cert_renewed");
+    let payload: serde_json::Value = serde_json::from_str(event.payload_str()?)?;
+    assert_eq!(payload["text"], "Device certificate renewed");
+
+    let clear = mqtt_box.recv().await.expect("cert_expiring alarm clearing");
+    assert_eq!(clear.topic.name, "te/device/main///a/cert_expiring");
+    assert!(clear.retain);
+    assert_eq!(clear.payload_str()?, "");
+
+    // The new certificate, issued by the EST server CA, is valid for a year
+    let cert = PemCertificate::from_pem_file(temp_dir.path().join("device-cert.pem"))?;
+    assert_eq!(cert.issuer()?, "CN=Test EST CA");
+    assert_eq!(cert.subject_common_name()?, "my-device");
+    assert!(cert.expiration_time()? > OffsetDateTime::now_utc() + time::Duration::days(300));
+
+    // The new certificate cannot be overwritten
+    let mode = std::fs::metadata(temp_dir.path().join("device-cert.pem"))?
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o444);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn renew_a_certificate_without_write_access_to_the_current_one() -> Result<(), DynError> {
+    let pki = TestPki::new();
+    let temp_dir = TempTedgeDir::new();
+    pki.install_device_certificate(&temp_dir, time::Duration::days(2));
+
+    // The files are read-only, as created by `tedge cert create`
+    let cert_path = temp_dir.path().join("device-cert.pem");
+    let key_path = temp_dir.path().join("device-key.pem");
+    std::fs::set_permissions(&cert_path, std::fs::Permissions::from_mode(0o444))?;
+    std::fs::set_permissions(&key_path, std::fs::Permissions::from_mode(0o440))?;
+
+    let est_url = spawn_est_server(pki.clone(), StatusCode::OK).await;
+    let mut mqtt_box = spawn_cert_renewal(&temp_dir, &est_url).await?;
+
+    let event = mqtt_box.recv().await.expect("cert_renewed event");
+    assert_eq!(event.topic.name, "te/device/main///e/cert_renewed");
+
+    let cert = PemCertificate::from_pem_file(&cert_path)?;
+    assert_eq!(cert.issuer()?, "CN=Test EST CA");
+
+    Ok(())
+}
+
+#[test]
+fn the_mappers_of_all_the_cloud_profiles_are_restarted_when_the_bridge_is_built_in() {
+    let mut dto = TEdgeConfigDto::default();
+    dto.try_update_str(WritableKey::DeviceEstUrl, "https://est.example.com")
+        .unwrap();
+    dto.try_update_str(WritableKey::MqttBridgeBuiltIn, "true")
+        .unwrap();
+    dto.update_profile(ProfiledCloud::C8y, &"staging".parse().unwrap(), |dto| {
+        dto.try_update_str(WritableKey::C8yUrl, "staging.example.com")
+    })
+    .unwrap();
+    let tedge_config = TEdgeConfig::from_dto(&dto, &TEdgeConfigLocation::default());
+
+    let config = CertRenewalConfig::from_tedge_config(
+        MqttSchema::default(),
+        &EntityTopicId::default_main_device(),
+        "/etc/tedge".into(),
+        &tedge_config,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config
+            .bridge_services
+            .iter()
+            .map(|service| service.to_string())
+            .collect::<Vec<_>>(),
+        vec![
+            "tedge-mapper-c8y",
+            "tedge-mapper-c8y@staging",
+            "tedge-mapper-az",
+            "tedge-mapper-aws",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn raise_an_alarm_when_the_renewal_fails() -> Result<(), DynError> {
+    let pki = TestPki::new();
+    let temp_dir = TempTedgeDir::new();
+    pki.install_device_certificate(&temp_dir, time::Duration::days(2));
+    let initial_cert = std::fs::read_to_string(temp_dir.path().join("device-cert.pem"))?;
+
+    let est_url = spawn_est_server(pki.clone(), StatusCode::INTERNAL_SERVER_ERROR).await;
+    let mut mqtt_box = spawn_cert_renewal(&temp_dir, &est_url).await?;
+
+    let alarm = mqtt_box.recv().await.expect("cert_expiring alarm");
+    assert_eq!(alarm.topic.name, "te/device/main///a/cert_expiring");
+    assert!(alarm.retain);
+    let payload: serde_json::Value = serde_json::from_str(alarm.payload_str()?)?;
+    assert_eq!(payload["severity"], "major");
+    assert!(payload["text"]
+        .as_str()
+        .unwrap()
+        .contains("cannot be renewed"));
+
+    // The current certificate is left unchanged
+    let cert = std::fs::read_to_string(temp_dir.path().join("device-cert.pem"))?;
+    assert_eq!(cert, initial_cert);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn do_not_renew_a_certificate_far_from_expiry() -> Result<(), DynError> {
+    let pki = TestPki::new();
+    let temp_dir = TempTedgeDir::new();
+    pki.install_device_certificate(&temp_dir, time::Duration::days(100));
+
+    let est_url = spawn_est_server(pki.clone(), StatusCode::OK).await;
+    let mut mqtt_box = spawn_cert_renewal(&temp_dir, &est_url)
+        .await?
+        .with_timeout(Duration::from_millis(500));
+
+    assert!(mqtt_box.recv().await.is_none());
+
+    Ok(())
+}
+
+/// A CA issuing certificates for a device key
+#[derive(Clone)]
+struct TestPki {
+    ca: Arc<rcgen::Certificate>,
+    device_key_pem: String,
+}
+
+impl TestPki {
+    fn new() -> Self {
+        let mut params = rcgen::CertificateParams::default();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "Test EST CA");
+        params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = rcgen::Certificate::from_params(params).unwrap();
+
+        let device_key = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+        TestPki {
+            ca: Arc::new(ca),
+            device_key_pem: device_key.serialize_pem(),
+        }
+    }
+
+    fn issue_device_certificate(&self, validity: time::Duration) -> rcgen::Certificate {
+        let mut params = rcgen::CertificateParams::default();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "my-device");
+        params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+        params.not_before = OffsetDateTime::now_utc() - time::Duration::days(1);
+        params.not_after = OffsetDateTime::now_utc() + validity;
+        params.key_pair = Some(rcgen::KeyPair::from_pem(&self.device_key_pem).unwrap());
+        rcgen::Certificate::from_params(params).unwrap()
+    }
+
+    fn install_device_certificate(&self, temp_dir: &TempTedgeDir, validity: time::Duration) {
+        let cert = self
+            .issue_device_certificate(validity)
+            .serialize_pem_with_signer(&self.ca)
+            .unwrap();
+        temp_dir.file("device-cert.pem").with_raw_content(&cert);
+        temp_dir
+            .file("device-key.pem")
+            .with_raw_content(&self.device_key_pem);
+
+        // Make the restart of the MQTT broker a no-op
+        temp_dir.file("system.toml").with_raw_content(
+            r#"
+            [init]
+            name = "test"
+            is_available = ["true"]
+            restart = ["true"]
+            stop = ["true"]
+            enable = ["true"]
+            disable = ["true"]
+            is_active = ["false"]
+            "#,
+        );
+    }
+}
+
+/// Stub of an EST server, responding with a new certificate to any re-enrollment request
+async fn simple_reenroll(
+    State((pki, status)): State<(TestPki, StatusCode)>,
+    headers: HeaderMap,
+    body: String,
+) -> (StatusCode, [(&'static str, &'static str); 1], String) {
+    let content_type = [(
+        "content-type",
+        "application/pkcs7-mime; smime-type=certs-only",
+    )];
+    if status != StatusCode::OK {
+        return (status, content_type, "".to_string());
+    }
+    if headers.get(CONTENT_TYPE).map(|v| v.as_bytes()) != Some(b"application/pkcs10") {
+        return (StatusCode::BAD_REQUEST, content_type, "".to_string());
+    }
+    if base64::decode(body).is_err() {
+        return (StatusCode::BAD_REQUEST, content_type, "".to_string());
+    }
+
+    let device_cert = pki
+        .issue_device_certificate(time::Duration::days(365))
+        .serialize_der_with_signer(&pki.ca)
+        .unwrap();
+    let ca_cert = pki.ca.serialize_der().unwrap();
+    let pkcs7 = pkcs7_from_certificates(&[device_cert, ca_cert]);
+    (StatusCode::OK, content_type, base64::encode(pkcs7))
+}
+
+async fn spawn_est_server(pki: TestPki, status: StatusCode) -> String {
+    let app = Router::new()
+        .route("/.well-known/est/simplereenroll", post(simple_reenroll))
+        .with_state((pki, status));
+    let listener = std::net::TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(app.into_make_service());
+    tokio::spawn(server);
+    format!("http://127.0.0.1:{port}")
+}
+
+async fn spawn_cert_renewal(
+    temp_dir: &TempTedgeDir,
+    est_url: &str,
+) -> Result<TimedMessageBox<SimpleMessageBox<MqttMessage, MqttMessage>>, DynError> {
+    let mut mqtt_builder: SimpleMessageBoxBuilder<MqttMessage, MqttMessage> =
+        SimpleMessageBoxBuilder::new("MQTT", 5);
+
+    let config = CertRenewalConfig {
+        mqtt_schema: MqttSchema::default(),
+        device_topic_id: EntityTopicId::default_main_device(),
+        config_dir: temp_dir.utf8_path_buf(),
+        tmp_dir: temp_dir.utf8_path_buf(),
+        cert_path: temp_dir.utf8_path().join("device-cert.pem"),
+        key_path: temp_dir.utf8_path().join("device-key.pem"),
+        est_url: est_url.to_string(),
+        est_root_cert_path: None,
+        renewal_window: Duration::from_secs(30 * 24 * 3600),
+        check_interval: Duration::from_secs(3600),
+        use_tedge_write: TedgeWriteStatus::Disabled,
+        bridge_services: vec![SystemService::Mosquitto],
+    };
+    let actor = CertRenewalBuilder::new(config, &mut mqtt_builder).build();
+    tokio::spawn(async move { actor.run().await });
+
+    Ok(mqtt_builder.build().with_timeout(TEST_TIMEOUT_MS))
+}
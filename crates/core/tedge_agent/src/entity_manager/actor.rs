@@ -0,0 +1,223 @@
+use crate::entity_manager::error::EntityStoreApiError as Error;
+use async_trait::async_trait;
+use tedge_actors::ClientMessageBox;
+use tedge_actors::Sequential;
+use tedge_actors::Server;
+use tedge_actors::ServerActorBuilder;
+use tedge_actors::ServerConfig;
+use tedge_api::entity_store::EntityMetadata;
+use tedge_api::entity_store::EntityRegistrationMessage;
+use tedge_api::entity_store::EntityType;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::EntityStore;
+use tedge_mqtt_ext::MqttMessage;
+use tracing::error;
+
+/// A request to the entity manager, the actor owning the entity store of the agent
+#[derive(Debug)]
+pub enum EntityStoreRequest {
+    /// Get the metadata of an entity
+    Get(EntityTopicId),
+
+    /// List the entities, possibly filtered by parent and type
+    List {
+        parent: Option<EntityTopicId>,
+        r#type: Option<EntityType>,
+    },
+
+    /// Register a new entity or update an existing one
+    Register(EntityRegistrationMessage),
+
+    /// Deregister an entity along its descendants
+    Deregister(EntityTopicId),
+
+    /// Keep the store in sync with an entity registration message published over MQTT
+    MqttMessage(MqttMessage),
+}
+
+#[derive(Debug)]
+pub enum EntityStoreResponse {
+    Get(Option<EntityMetadata>),
+    List(Vec<EntityMetadata>),
+    Register(Result<RegisteredEntity, Error>),
+    Deregister(Result<Vec<EntityMetadata>, Error>),
+    Ok,
+}
+
+/// The outcome of a successful registration
+#[derive(Debug)]
+pub struct RegisteredEntity {
+    pub entity: EntityMetadata,
+
+    /// `false` when an existing entity has been updated
+    pub created: bool,
+}
+
+impl From<MqttMessage> for EntityStoreRequest {
+    fn from(message: MqttMessage) -> Self {
+        EntityStoreRequest::MqttMessage(message)
+    }
+}
+
+/// A client of the entity manager
+pub type EntityStoreClient = ClientMessageBox<EntityStoreRequest, EntityStoreResponse>;
+
+/// The sole owner of the entity store of the agent
+///
+/// The store is queried and updated by the HTTP endpoints,
+/// and kept in sync with the entity registration messages published over MQTT.
+pub struct EntityManager {
+    entity_store: EntityStore,
+    mqtt_schema: MqttSchema,
+}
+
+impl EntityManager {
+    pub fn builder(
+        entity_store: EntityStore,
+        mqtt_schema: MqttSchema,
+    ) -> ServerActorBuilder<EntityManager, Sequential> {
+        let server = EntityManager {
+            entity_store,
+            mqtt_schema,
+        };
+        ServerActorBuilder::new(server, &ServerConfig::default(), Sequential)
+    }
+
+    fn list(
+        &self,
+        parent: Option<EntityTopicId>,
+        entity_type: Option<EntityType>,
+    ) -> Vec<EntityMetadata> {
+        let mut entities: Vec<EntityMetadata> = self
+            .entity_store
+            .iter()
+            .map(|(_, entity)| entity)
+            .filter(|entity| parent.is_none() || entity.parent == parent)
+            .filter(|entity| entity_type.is_none() || Some(&entity.r#type) == entity_type.as_ref())
+            .cloned()
+            .collect();
+        entities.sort_by(|a, b| a.topic_id.as_str().cmp(b.topic_id.as_str()));
+        entities
+    }
+
+    fn register(
+        &mut self,
+        registration: EntityRegistrationMessage,
+    ) -> Result<RegisteredEntity, Error> {
+        if registration.r#type == EntityType::MainDevice {
+            return Err(Error::MainDevice);
+        }
+
+        let topic_id = registration.topic_id.clone();
+        let created = self.entity_store.get(&topic_id).is_none();
+        self.entity_store.try_register(registration)?;
+        let entity = self
+            .entity_store
+            .get(&topic_id)
+            .cloned()
+            .ok_or(Error::UnknownEntity(topic_id.to_string()))?;
+
+        Ok(RegisteredEntity { entity, created })
+    }
+
+    fn deregister(&mut self, topic_id: EntityTopicId) -> Result<Vec<EntityMetadata>, Error> {
+        if &topic_id == self.entity_store.main_device() {
+            return Err(Error::MainDevice);
+        }
+
+        let removed_entities = self.entity_store.deregister_entity(&topic_id);
+        if removed_entities.is_empty() {
+            return Err(Error::UnknownEntity(topic_id.to_string()));
+        }
+
+        Ok(removed_entities)
+    }
+
+    fn sync(&mut self, message: MqttMessage) {
+        let Ok((topic_id, Channel::EntityMetadata)) =
+            self.mqtt_schema.entity_channel_of(&message.topic)
+        else {
+            return;
+        };
+
+        if message.payload_bytes().is_empty() {
+            self.entity_store.deregister_entity(&topic_id);
+        } else if let Ok(registration) = EntityRegistrationMessage::try_from(&message) {
+            if let Err(err) = self.entity_store.update(registration) {
+                error!("Entity registration failed: {err}");
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Server for EntityManager {
+    type Request = EntityStoreRequest;
+    type Response = EntityStoreResponse;
+
+    fn name(&self) -> &str {
+        "EntityManager"
+    }
+
+    async fn handle(&mut self, request: Self::Request) -> Self::Response {
+        match request {
+            EntityStoreRequest::Get(topic_id) => {
+                EntityStoreResponse::Get(self.entity_store.get(&topic_id).cloned())
+            }
+            EntityStoreRequest::List { parent, r#type } => {
+                EntityStoreResponse::List(self.list(parent, r#type))
+            }
+            EntityStoreRequest::Register(registration) => {
+                EntityStoreResponse::Register(self.register(registration))
+            }
+            EntityStoreRequest::Deregister(topic_id) => {
+                EntityStoreResponse::Deregister(self.deregister(topic_id))
+            }
+            EntityStoreRequest::MqttMessage(message) => {
+                self.sync(message);
+                EntityStoreResponse::Ok
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity_manager::new_entity_store;
+    use tedge_mqtt_ext::Topic;
+
+    #[tokio::test]
+    async fn the_store_is_kept_in_sync_with_the_registration_messages() {
+        let mqtt_schema = MqttSchema::default();
+        let mut entity_manager =
+            EntityManager::builder(new_entity_store(mqtt_schema.clone()), mqtt_schema);
+        let mut client = EntityStoreClient::new("Test => EntityManager", &mut entity_manager);
+        tokio::spawn(entity_manager.run());
+
+        let child = EntityTopicId::default_child_device("child1").unwrap();
+        let registration = MqttMessage::new(
+            &Topic::new_unchecked("te/device/child1//"),
+            r#"{"@type": "child-device"}"#,
+        );
+        client.await_response(registration.into()).await.unwrap();
+        let response = client
+            .await_response(EntityStoreRequest::Get(child.clone()))
+            .await
+            .unwrap();
+        assert!(
+            matches!(response, EntityStoreResponse::Get(Some(entity)) if entity.topic_id == child)
+        );
+
+        let deregistration =
+            MqttMessage::new(&Topic::new_unchecked("te/device/child1//"), "").with_retain();
+        client.await_response(deregistration.into()).await.unwrap();
+        let response = client
+            .await_response(EntityStoreRequest::Get(child))
+            .await
+            .unwrap();
+        assert!(matches!(response, EntityStoreResponse::Get(None)));
+    }
+}
@@ -0,0 +1,54 @@
+use axum::response::IntoResponse;
+use hyper::StatusCode;
+use tedge_actors::ChannelError;
+use tedge_api::entity_store;
+
+#[derive(Debug, thiserror::Error)]
+pub enum EntityStoreApiError {
+    #[error("Invalid entity topic id: {0:?}")]
+    InvalidTopicId(String),
+
+    #[error("Invalid entity type: {0:?}, expecting 'device', 'child-device' or 'service'")]
+    InvalidEntityType(String),
+
+    #[error("Invalid registration payload: {0}")]
+    InvalidPayload(String),
+
+    #[error("The main device cannot be registered nor deregistered")]
+    MainDevice,
+
+    #[error("Unknown entity: {0}")]
+    UnknownEntity(String),
+
+    #[error(transparent)]
+    FromEntityStore(#[from] entity_store::Error),
+
+    #[error(transparent)]
+    FromChannel(#[from] ChannelError),
+
+    #[error("Unexpected response from the entity manager")]
+    UnexpectedResponse,
+}
+
+impl IntoResponse for EntityStoreApiError {
+    fn into_response(self) -> axum::response::Response {
+        use EntityStoreApiError as E;
+        let error_message = self.to_string();
+        match self {
+            E::FromChannel(_) | E::UnexpectedResponse => {
+                tracing::error!("{error_message}");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Internal error".to_owned(),
+                )
+                    .into_response()
+            }
+            E::UnknownEntity(_) => (StatusCode::NOT_FOUND, error_message).into_response(),
+            E::InvalidTopicId(_)
+            | E::InvalidEntityType(_)
+            | E::InvalidPayload(_)
+            | E::MainDevice
+            | E::FromEntityStore(_) => (StatusCode::BAD_REQUEST, error_message).into_response(),
+        }
+    }
+}
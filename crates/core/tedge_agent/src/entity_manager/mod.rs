@@ -0,0 +1,26 @@
+pub mod actor;
+pub mod error;
+pub mod server;
+
+use tedge_api::entity_store::EntityRegistrationMessage;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::pending_entity_store::PendingEntityStoreConfig;
+use tedge_api::EntityStore;
+
+/// Creates an entity store with the main device as only registered entity.
+///
+/// The agent has no cloud specific naming scheme,
+/// hence the entities registered without an explicit `@id` are identified by their topic id.
+pub(crate) fn new_entity_store(mqtt_schema: MqttSchema) -> EntityStore {
+    let main_device_id = EntityTopicId::default_main_device().to_string();
+    EntityStore::with_main_device_and_default_service_type(
+        mqtt_schema,
+        EntityRegistrationMessage::main_device(main_device_id),
+        "service".to_string(),
+        |topic_id, _| topic_id.as_str().into(),
+        |id| Ok(id.into()),
+        PendingEntityStoreConfig::default(),
+    )
+    .expect("a main device registration message")
+}
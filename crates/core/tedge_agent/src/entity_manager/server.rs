@@ -0,0 +1,441 @@
+use crate::entity_manager::actor::EntityStoreClient;
+use crate::entity_manager::actor::EntityStoreRequest;
+use crate::entity_manager::actor::EntityStoreResponse;
+use crate::entity_manager::error::EntityStoreApiError as Error;
+use axum::body::Bytes;
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::routing::get;
+use axum::Json;
+use axum::Router;
+use hyper::StatusCode;
+use serde::Deserialize;
+use serde_json::Map;
+use serde_json::Value as JsonValue;
+use std::sync::Arc;
+use tedge_actors::DynSender;
+use tedge_actors::Sender;
+use tedge_api::entity_store::EntityMetadata;
+use tedge_api::entity_store::EntityRegistrationMessage;
+use tedge_api::entity_store::EntityType;
+use tedge_api::mqtt_topics::Channel;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::mqtt_topics::MqttSchema;
+use tedge_mqtt_ext::MqttMessage;
+use tokio::sync::Mutex;
+
+const TOPIC_ID_KEY: &str = "@topic-id";
+
+/// The state shared by the entity store HTTP endpoints
+#[derive(Clone)]
+pub(crate) struct EntityStoreState {
+    pub entity_store: Arc<Mutex<EntityStoreClient>>,
+    pub mqtt_schema: MqttSchema,
+    pub mqtt_publisher: DynSender<MqttMessage>,
+}
+
+impl EntityStoreState {
+    /// Forward a request to the entity manager, the actor owning the entity store
+    async fn request(&self, request: EntityStoreRequest) -> Result<EntityStoreResponse, Error> {
+        let mut entity_store = self.entity_store.lock().await;
+        Ok(entity_store.await_response(request).await?)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct EntityFilter {
+    parent: Option<String>,
+    #[serde(rename = "type")]
+    r#type: Option<String>,
+}
+
+pub(crate) fn entity_store_router(state: EntityStoreState) -> Router {
+    Router::new()
+        .route(
+            "/tedge/entity-store/v1/entities",
+            get(list_entities).post(register_entity),
+        )
+        .route(
+            "/tedge/entity-store/v1/entities/*topic_id",
+            get(get_entity).delete(deregister_entity),
+        )
+        .with_state(state)
+}
+
+async fn list_entities(
+    State(state): State<EntityStoreState>,
+    Query(filter): Query<EntityFilter>,
+) -> Result<Json<Vec<JsonValue>>, Error> {
+    let parent = filter.parent.as_deref().map(parse_topic_id).transpose()?;
+    let entity_type = filter
+        .r#type
+        .as_deref()
+        .map(parse_entity_type)
+        .transpose()?;
+
+    let request = EntityStoreRequest::List {
+        parent,
+        r#type: entity_type,
+    };
+    let EntityStoreResponse::List(entities) = state.request(request).await? else {
+        return Err(Error::UnexpectedResponse);
+    };
+
+    Ok(Json(entities.iter().map(entity_as_json).collect()))
+}
+
+async fn get_entity(
+    State(state): State<EntityStoreState>,
+    Path(topic_id): Path<String>,
+) -> Result<Json<JsonValue>, Error> {
+    let topic_id = parse_topic_id(&topic_id)?;
+
+    let request = EntityStoreRequest::Get(topic_id.clone());
+    let EntityStoreResponse::Get(entity) = state.request(request).await? else {
+        return Err(Error::UnexpectedResponse);
+    };
+
+    entity
+        .map(|entity| Json(entity_as_json(&entity)))
+        .ok_or(Error::UnknownEntity(topic_id.to_string()))
+}
+
+async fn register_entity(
+    State(mut state): State<EntityStoreState>,
+    body: Bytes,
+) -> Result<(StatusCode, Json<JsonValue>), Error> {
+    let registration = parse_registration(&body)?;
+
+    let request = EntityStoreRequest::Register(registration.clone());
+    let EntityStoreResponse::Register(registered) = state.request(request).await? else {
+        return Err(Error::UnexpectedResponse);
+    };
+    let registered = registered?;
+    let status = if registered.created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+
+    // Notify the other components, as the mappers, of the new entity
+    let message = registration.to_mqtt_message(&state.mqtt_schema);
+    state.mqtt_publisher.send(message).await?;
+
+    Ok((status, Json(entity_as_json(&registered.entity))))
+}
+
+async fn deregister_entity(
+    State(mut state): State<EntityStoreState>,
+    Path(topic_id): Path<String>,
+) -> Result<Json<Vec<String>>, Error> {
+    let topic_id = parse_topic_id(&topic_id)?;
+
+    let request = EntityStoreRequest::Deregister(topic_id);
+    let EntityStoreResponse::Deregister(removed_entities) = state.request(request).await? else {
+        return Err(Error::UnexpectedResponse);
+    };
+    let removed_entities = removed_entities?;
+
+    let mut removed_topic_ids = vec![];
+    for entity in removed_entities {
+        let topic = state
+            .mqtt_schema
+            .topic_for(&entity.topic_id, &Channel::EntityMetadata);
+        let message = MqttMessage::new(&topic, "").with_retain();
+        state.mqtt_publisher.send(message).await?;
+        removed_topic_ids.push(entity.topic_id.to_string());
+    }
+
+    Ok(Json(removed_topic_ids))
+}
+
+fn parse_topic_id(topic_id: &str) -> Result<EntityTopicId, Error> {
+    topic_id
+        .parse()
+        .map_err(|_| Error::InvalidTopicId(topic_id.to_string()))
+}
+
+fn parse_entity_type(entity_type: &str) -> Result<EntityType, Error> {
+    entity_type
+        .parse()
+        .map_err(|_| Error::InvalidEntityType(entity_type.to_string()))
+}
+
+/// Parses a registration payload extended with the `@topic-id` of the entity
+fn parse_registration(body: &[u8]) -> Result<EntityRegistrationMessage, Error> {
+    let JsonValue::Object(mut payload) =
+        serde_json::from_slice(body).map_err(|err| Error::InvalidPayload(err.to_string()))?
+    else {
+        return Err(Error::InvalidPayload("expecting a JSON object".to_string()));
+    };
+
+    let Some(JsonValue::String(topic_id)) = payload.remove(TOPIC_ID_KEY) else {
+        return Err(Error::InvalidPayload(format!(
+            "missing {TOPIC_ID_KEY} string property"
+        )));
+    };
+    let topic_id = parse_topic_id(&topic_id)?;
+
+    EntityRegistrationMessage::from_json(topic_id, JsonValue::Object(payload))
+        .ok_or_else(|| Error::InvalidPayload("invalid entity registration".to_string()))
+}
+
+/// Returns the registration payload of an entity extended with its `@topic-id`
+///
+/// The `@id` is only given when explicitly set, i.e. when different from the topic id.
+fn entity_as_json(entity: &EntityMetadata) -> JsonValue {
+    let mut payload = Map::new();
+    payload.insert(TOPIC_ID_KEY.to_string(), entity.topic_id.as_str().into());
+
+    let mut registration = EntityRegistrationMessage::from(entity);
+    if entity.external_id.as_ref() == entity.topic_id.as_str() {
+        registration.external_id = None;
+    }
+    payload.append(&mut registration.into_json());
+
+    JsonValue::Object(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity_manager::actor::EntityManager;
+    use crate::entity_manager::new_entity_store;
+    use axum::response::Response;
+    use futures::channel::mpsc;
+    use futures::StreamExt;
+    use hyper::Body;
+    use hyper::Method;
+    use hyper::Request;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn only_the_main_device_is_registered_at_start() {
+        let (app, _) = app();
+
+        let response = call(&app, Method::GET, "/tedge/entity-store/v1/entities", None).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            body_as_json(response).await,
+            json!([{"@topic-id": "device/main//", "@type": "device"}])
+        );
+    }
+
+    #[tokio::test]
+    async fn registered_entities_are_published_and_can_be_retrieved() {
+        let (app, mut published) = app();
+
+        let child = json!({
+            "@topic-id": "device/child1//",
+            "@type": "child-device",
+            "@id": "child-001",
+            "name": "Child 1",
+        });
+        let response = call(
+            &app,
+            Method::POST,
+            "/tedge/entity-store/v1/entities",
+            Some(child),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let message = published.next().await.unwrap();
+        assert_eq!(message.topic.name, "te/device/child1//");
+        assert!(message.retain);
+
+        let response = call(
+            &app,
+            Method::GET,
+            "/tedge/entity-store/v1/entities/device/child1//",
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            body_as_json(response).await,
+            json!({
+                "@topic-id": "device/child1//",
+                "@type": "child-device",
+                "@id": "child-001",
+                "@parent": "device/main//",
+                "name": "Child 1",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn entities_can_be_filtered_by_parent_and_type() {
+        let (app, _published) = app();
+        for entity in [
+            json!({"@topic-id": "device/child1//", "@type": "child-device"}),
+            json!({"@topic-id": "device/child2//", "@type": "child-device", "@parent": "device/child1//"}),
+            json!({"@topic-id": "device/child1/service/collectd", "@type": "service"}),
+        ] {
+            let response = call(
+                &app,
+                Method::POST,
+                "/tedge/entity-store/v1/entities",
+                Some(entity),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        let response = call(
+            &app,
+            Method::GET,
+            "/tedge/entity-store/v1/entities?parent=device/child1//",
+            None,
+        )
+        .await;
+        let topic_ids = entity_topic_ids(body_as_json(response).await);
+        assert_eq!(
+            topic_ids,
+            vec!["device/child1/service/collectd", "device/child2//"]
+        );
+
+        let response = call(
+            &app,
+            Method::GET,
+            "/tedge/entity-store/v1/entities?type=child-device",
+            None,
+        )
+        .await;
+        let topic_ids = entity_topic_ids(body_as_json(response).await);
+        assert_eq!(topic_ids, vec!["device/child1//", "device/child2//"]);
+
+        let response = call(
+            &app,
+            Method::GET,
+            "/tedge/entity-store/v1/entities?type=sensor",
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn registration_is_rejected_when_the_parent_is_unknown() {
+        let (app, _published) = app();
+
+        let child = json!({
+            "@topic-id": "device/child2//",
+            "@type": "child-device",
+            "@parent": "device/child1//",
+        });
+        let response = call(
+            &app,
+            Method::POST,
+            "/tedge/entity-store/v1/entities",
+            Some(child),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn deregistration_removes_the_entity_and_its_descendants() {
+        let (app, mut published) = app();
+        for entity in [
+            json!({"@topic-id": "device/child1//", "@type": "child-device"}),
+            json!({"@topic-id": "device/child2//", "@type": "child-device", "@parent": "device/child1//"}),
+        ] {
+            call(
+                &app,
+                Method::POST,
+                "/tedge/entity-store/v1/entities",
+                Some(entity),
+            )
+            .await;
+            published.next().await.unwrap();
+        }
+
+        let response = call(
+            &app,
+            Method::DELETE,
+            "/tedge/entity-store/v1/entities/device/child1//",
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            body_as_json(response).await,
+            json!(["device/child1//", "device/child2//"])
+        );
+
+        for topic in ["te/device/child1//", "te/device/child2//"] {
+            let message = published.next().await.unwrap();
+            assert_eq!(message.topic.name, topic);
+            assert!(message.payload_bytes().is_empty());
+        }
+
+        let response = call(
+            &app,
+            Method::GET,
+            "/tedge/entity-store/v1/entities/device/child2//",
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn the_main_device_cannot_be_deregistered() {
+        let (app, _published) = app();
+
+        let response = call(
+            &app,
+            Method::DELETE,
+            "/tedge/entity-store/v1/entities/device/main//",
+            None,
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    fn app() -> (Router, mpsc::Receiver<MqttMessage>) {
+        let (sender, receiver) = mpsc::channel(10);
+        let mqtt_schema = MqttSchema::default();
+        let mut entity_manager =
+            EntityManager::builder(new_entity_store(mqtt_schema.clone()), mqtt_schema.clone());
+        let entity_store = EntityStoreClient::new("HTTP => EntityManager", &mut entity_manager);
+        tokio::spawn(entity_manager.run());
+
+        let state = EntityStoreState {
+            entity_store: Arc::new(Mutex::new(entity_store)),
+            mqtt_schema,
+            mqtt_publisher: sender.into(),
+        };
+        (entity_store_router(state), receiver)
+    }
+
+    async fn call(app: &Router, method: Method, uri: &str, body: Option<JsonValue>) -> Response {
+        let body = body.map_or(Body::empty(), |json| Body::from(json.to_string()));
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(body)
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    async fn body_as_json(response: Response) -> JsonValue {
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn entity_topic_ids(entities: JsonValue) -> Vec<String> {
+        entities
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entity| entity[TOPIC_ID_KEY].as_str().unwrap().to_string())
+            .collect()
+    }
+}
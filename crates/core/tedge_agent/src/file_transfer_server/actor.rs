@@ -1,3 +1,4 @@
+use crate::entity_manager::server::EntityStoreState;
 use crate::file_transfer_server::error::FileTransferError;
 use crate::file_transfer_server::http_rest::http_file_transfer_server;
 use anyhow::Context;
@@ -23,7 +24,9 @@ use tracing::log::info;
 
 pub struct FileTransferServerActor {
     file_transfer_dir: Utf8PathBuf,
+    log_dir: Utf8PathBuf,
     rustls_config: Option<ServerConfig>,
+    entity_store: Option<EntityStoreState>,
     signal_receiver: mpsc::Receiver<RuntimeRequest>,
     listener: TcpListener,
 }
@@ -33,6 +36,8 @@ pub struct FileTransferServerActor {
 // hence they need to be separate types
 pub(crate) struct FileTransferServerConfig<CertKeyPath = Utf8PathBuf, CaPath = Utf8PathBuf> {
     pub file_transfer_dir: Utf8PathBuf,
+    /// The directory where the agent stores the workflow command logs
+    pub log_dir: Utf8PathBuf,
     pub cert_path: OptionalConfig<CertKeyPath>,
     pub key_path: OptionalConfig<CertKeyPath>,
     pub ca_path: OptionalConfig<CaPath>,
@@ -47,8 +52,13 @@ impl Actor for FileTransferServerActor {
     }
 
     async fn run(mut self) -> Result<(), RuntimeError> {
-        let server =
-            http_file_transfer_server(self.listener, self.file_transfer_dir, self.rustls_config)?;
+        let server = http_file_transfer_server(
+            self.listener,
+            self.file_transfer_dir,
+            self.log_dir,
+            self.rustls_config,
+            self.entity_store,
+        )?;
 
         tokio::select! {
             result = server => {
@@ -65,7 +75,9 @@ impl Actor for FileTransferServerActor {
 
 pub struct FileTransferServerBuilder {
     file_transfer_dir: Utf8PathBuf,
+    log_dir: Utf8PathBuf,
     rustls_config: Option<ServerConfig>,
+    entity_store: Option<EntityStoreState>,
     signal_sender: mpsc::Sender<RuntimeRequest>,
     signal_receiver: mpsc::Receiver<RuntimeRequest>,
     listener: TcpListener,
@@ -88,11 +100,21 @@ impl FileTransferServerBuilder {
                 "File transfer service",
             )?,
             file_transfer_dir: config.file_transfer_dir,
+            log_dir: config.log_dir,
+            entity_store: None,
             signal_sender,
             signal_receiver,
             listener,
         })
     }
+
+    /// Exposes the entity store over the HTTP API
+    pub(crate) fn with_entity_store(self, entity_store: EntityStoreState) -> Self {
+        Self {
+            entity_store: Some(entity_store),
+            ..self
+        }
+    }
 }
 
 impl RuntimeRequestSink for FileTransferServerBuilder {
@@ -107,7 +129,9 @@ impl Builder<FileTransferServerActor> for FileTransferServerBuilder {
     fn try_build(self) -> Result<FileTransferServerActor, Self::Error> {
         Ok(FileTransferServerActor {
             file_transfer_dir: self.file_transfer_dir,
+            log_dir: self.log_dir,
             rustls_config: self.rustls_config,
+            entity_store: self.entity_store,
             signal_receiver: self.signal_receiver,
             listener: self.listener,
         })
@@ -355,6 +379,7 @@ mod tests {
     fn http_config(ttd: &TempTedgeDir, bind_port: u16) -> TestConfig {
         TestConfig {
             file_transfer_dir: DataDir::from(ttd.utf8_path_buf()).file_transfer_dir(),
+            log_dir: ttd.utf8_path().join("logs"),
             cert_path: OptionalConfig::empty("http.cert_path"),
             key_path: OptionalConfig::empty("http.key_path"),
             ca_path: OptionalConfig::empty("http.ca_path"),
@@ -382,6 +407,7 @@ mod tests {
 
         Ok(TestConfig {
             file_transfer_dir: DataDir::from(ttd.utf8_path_buf()).file_transfer_dir(),
+            log_dir: ttd.utf8_path().join("logs"),
             cert_path: OptionalConfig::present(InjectedValue(cert), "http.cert_path"),
             key_path: OptionalConfig::present(InjectedValue(key), "http.key_path"),
             ca_path: root_certs
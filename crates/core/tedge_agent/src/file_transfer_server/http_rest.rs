@@ -1,7 +1,11 @@
+use crate::entity_manager::server::entity_store_router;
+use crate::entity_manager::server::EntityStoreState;
 use crate::file_transfer_server::error::FileTransferError;
 use anyhow::anyhow;
 use anyhow::Context;
 use axum::body::StreamBody;
+use axum::extract::Path;
+use axum::extract::State;
 use axum::routing::get;
 use axum::Router;
 use camino::Utf8Path;
@@ -91,6 +95,13 @@ async fn download_file(
     }
 }
 
+async fn download_workflow_log(
+    State(log_dir): State<FileTransferDir>,
+    Path((operation, cmd_id)): Path<(String, String)>,
+) -> Result<StreamBody<ReaderStream<BufReader<File>>>, Error> {
+    download_file(log_dir.workflow_log(&operation, &cmd_id)?).await
+}
+
 // Not a typo, snake_case for: 'err is "is a directory"'
 fn err_is_is_a_directory(e: &io::Error) -> bool {
     // At the time of writing, `ErrorKind::IsADirectory` is feature-gated (https://github.com/rust-lang/rust/issues/86442)
@@ -134,9 +145,14 @@ async fn stream_request_body_to_path(
 pub(crate) fn http_file_transfer_server(
     listener: TcpListener,
     file_transfer_dir: Utf8PathBuf,
+    log_dir: Utf8PathBuf,
     rustls_config: Option<ServerConfig>,
+    entity_store: Option<EntityStoreState>,
 ) -> Result<impl Future<Output = io::Result<()>>, FileTransferError> {
-    let router = http_file_transfer_router(file_transfer_dir);
+    let mut router = http_file_transfer_router(file_transfer_dir, log_dir);
+    if let Some(entity_store) = entity_store {
+        router = router.merge(entity_store_router(entity_store));
+    }
     let listener = listener.into_std()?;
 
     let server = if let Some(rustls_config) = rustls_config {
@@ -150,13 +166,20 @@ pub(crate) fn http_file_transfer_server(
     Ok(server)
 }
 
-fn http_file_transfer_router(file_transfer_dir: Utf8PathBuf) -> Router {
+fn http_file_transfer_router(file_transfer_dir: Utf8PathBuf, log_dir: Utf8PathBuf) -> Router {
+    let workflow_logs = Router::new()
+        .route(
+            "/tedge/workflow-logs/:operation/:cmd_id",
+            get(download_workflow_log),
+        )
+        .with_state(FileTransferDir::new(log_dir));
     Router::new()
         .route(
             "/tedge/file-transfer/*path",
             get(download_file).put(upload_file).delete(delete_file),
         )
         .with_state(FileTransferDir::new(file_transfer_dir))
+        .merge(workflow_logs)
 }
 
 #[cfg(test)]
@@ -337,6 +360,53 @@ mod tests {
         assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
+    #[tokio::test]
+    async fn workflow_logs_can_be_downloaded_from_the_api() {
+        let (ttd, app) = app();
+        ttd.dir("logs")
+            .file("workflow-software_update-123.log")
+            .with_raw_content("init: 2023-11-20T10:00:00Z");
+
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("/tedge/workflow-logs/software_update/123")
+            .body(Body::empty())
+            .expect("request builder");
+        let response = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            std::str::from_utf8(&body).unwrap(),
+            "init: 2023-11-20T10:00:00Z"
+        );
+
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("/tedge/workflow-logs/software_update/456")
+            .body(Body::empty())
+            .expect("request builder");
+        let response = app.oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test_case(Method::PUT)]
+    #[test_case(Method::DELETE)]
+    #[tokio::test]
+    async fn workflow_logs_are_read_only(method: Method) {
+        let (ttd, app) = app();
+        ttd.dir("logs")
+            .file("workflow-software_update-123.log")
+            .with_raw_content("init: 2023-11-20T10:00:00Z");
+
+        let req = Request::builder()
+            .method(method)
+            .uri("/tedge/workflow-logs/software_update/123")
+            .body(Body::empty())
+            .expect("request builder");
+        let response = app.oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
     #[test_case(Method::GET, StatusCode::NOT_FOUND)]
     #[test_case(Method::PUT, StatusCode::CONFLICT)]
     #[test_case(Method::DELETE, StatusCode::NOT_FOUND)]
@@ -394,7 +464,8 @@ mod tests {
     fn app() -> (TempTedgeDir, Router) {
         let ttd = TempTedgeDir::new();
         let ftd = DataDir::from(ttd.utf8_path_buf()).file_transfer_dir();
-        let router = http_file_transfer_router(ftd);
+        let log_dir = ttd.utf8_path().join("logs");
+        let router = http_file_transfer_router(ftd, log_dir);
         (ttd, router)
     }
 
@@ -19,6 +19,21 @@ impl FileTransferDir {
     }
 }
 
+impl FileTransferDir {
+    /// Return the path of the audit log of a workflow command, this directory being the agent log directory
+    pub(super) fn workflow_log(
+        &self,
+        operation: &str,
+        cmd_id: &str,
+    ) -> Result<FileTransferPath, FileTransferRequestError> {
+        let request_path = RequestPath(format!("workflow-{operation}-{cmd_id}.log").into());
+        if operation.contains('/') || cmd_id.contains('/') {
+            return Err(FileTransferRequestError::InvalidPath { path: request_path });
+        }
+        local_path_for_file(request_path, &self.0)
+    }
+}
+
 /// The paths inferred from a request to the File Transfer Service
 pub struct FileTransferPath {
     /// The full path, i.e. the absolute path on disk the request corresponds to
@@ -8,6 +8,7 @@
 //! It also has following capabilities:
 //!
 //! - File transfer HTTP server
+//! - Device certificate renewal
 //! - Restart management
 //! - Software management
 
@@ -21,6 +22,8 @@ use tedge_config::DEFAULT_TEDGE_CONFIG_PATH;
 use tracing::log::warn;
 
 mod agent;
+mod cert_renewal;
+mod entity_manager;
 mod file_transfer_server;
 mod restart_manager;
 mod software_manager;
@@ -82,6 +82,7 @@ impl Actor for SoftwareManagerActor {
             sudo,
             self.config.config_location.clone(),
         )
+        .await
         .map_err(|err| RuntimeError::ActorError(Box::new(err)))?;
 
         if plugins.empty() {
@@ -206,20 +207,40 @@ impl SoftwareManagerActor {
             return Ok(());
         }
 
-        plugins.load()?;
+        plugins.load().await?;
         plugins.update_default(&get_default_plugin(&self.config.config_location)?)?;
 
         self.state_repository.store(&request.clone().into()).await?;
 
         // Send 'executing'
         let executing_response = request.clone().with_status(CommandStatus::Executing);
-        self.output_sender.send(executing_response.into()).await?;
+        self.output_sender
+            .send(executing_response.clone().into())
+            .await?;
 
         let response = match operation_logs.new_log_file(LogKind::SoftwareUpdate).await {
             Ok(log_file) => {
-                plugins
-                    .process(request, log_file, self.config.tmp_dir.as_std_path())
-                    .await
+                // Forward as 'executing' updates the progress reported by the plugins
+                let (progress_sender, mut progress_receiver) =
+                    tokio::sync::mpsc::unbounded_channel();
+                let output_sender = &mut self.output_sender;
+                let forward_progress = async {
+                    while let Some(progress) = progress_receiver.recv().await {
+                        let update = executing_response.clone().with_progress(progress);
+                        output_sender.send(update.into()).await?;
+                    }
+                    Ok::<_, SoftwareManagerError>(())
+                };
+                let process = plugins.process(
+                    request,
+                    log_file,
+                    self.config.tmp_dir.as_std_path(),
+                    progress_sender,
+                );
+
+                let (response, forwarded) = tokio::join!(process, forward_progress);
+                forwarded?;
+                response
             }
             Err(err) => {
                 error!("{}", err);
@@ -70,6 +70,7 @@ async fn test_new_software_update_operation() -> Result<(), DynError> {
         action: Some(SoftwareModuleAction::Install),
         url: None,
         reason: None,
+        depends_on: vec![],
     };
     let debian_list = SoftwareRequestResponseSoftwareList {
         plugin_type: "debian".into(),
@@ -83,6 +84,8 @@ async fn test_new_software_update_operation() -> Result<(), DynError> {
             status: CommandStatus::Scheduled,
             update_list: vec![debian_list],
             failures: vec![],
+            progress: None,
+            rollback: false,
         },
     };
     converter_box.send(command.into()).await?;
@@ -1,9 +1,15 @@
 use crate::software_manager::actor::SoftwareCommand;
+use crate::state_repository::error::StateError;
+use crate::state_repository::state::AgentStateRepository;
 use async_trait::async_trait;
 use camino::Utf8PathBuf;
 use log::error;
 use log::info;
+use serde::Serialize;
+use std::os::unix::process::ExitStatusExt;
 use std::process::Output;
+use std::time::Duration;
+use std::time::Instant;
 use tedge_actors::fan_in_message_type;
 use tedge_actors::Actor;
 use tedge_actors::ClientMessageBox;
@@ -20,28 +26,48 @@ use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
 use tedge_api::mqtt_topics::OperationType;
 use tedge_api::workflow::GenericCommandState;
+use tedge_api::workflow::GenericStateUpdate;
 use tedge_api::workflow::OperationAction;
+use tedge_api::workflow::PendingCommand;
 use tedge_api::workflow::WorkflowExecutionError;
 use tedge_api::workflow::WorkflowSupervisor;
 use tedge_mqtt_ext::MqttMessage;
 use tedge_mqtt_ext::QoS;
+use tedge_mqtt_ext::Topic;
 use tedge_script_ext::Execute;
+use tedge_timer_ext::SetTimeout;
+use tedge_timer_ext::Timeout;
 use time::format_description;
 use time::OffsetDateTime;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 
-fan_in_message_type!(AgentInput[MqttMessage, SoftwareCommand, RestartCommand] : Debug);
+/// The events triggered by the timers set by the agent on the commands in progress
+#[derive(Debug)]
+pub enum CommandTimer {
+    /// The command stayed too long in the given state and has to be moved to the given state update
+    StateTimeout(GenericCommandState, GenericStateUpdate),
+
+    /// The action of the given state has to be re-triggered, unless the command moved to another state
+    Retry(GenericCommandState),
+}
+
+pub type CommandSetTimeout = SetTimeout<CommandTimer>;
+pub type CommandTimeout = Timeout<CommandTimer>;
+
+fan_in_message_type!(AgentInput[MqttMessage, SoftwareCommand, RestartCommand, CommandTimeout] : Debug);
 
 pub struct TedgeOperationConverterActor {
     pub(crate) mqtt_schema: MqttSchema,
     pub(crate) device_topic_id: EntityTopicId,
     pub(crate) workflows: WorkflowSupervisor,
     pub(crate) log_dir: Utf8PathBuf,
+    pub(crate) state_repository: AgentStateRepository<Vec<GenericCommandState>>,
     pub(crate) input_receiver: LoggingReceiver<AgentInput>,
     pub(crate) software_sender: LoggingSender<SoftwareCommand>,
     pub(crate) restart_sender: LoggingSender<RestartCommand>,
     pub(crate) mqtt_publisher: LoggingSender<MqttMessage>,
+    pub(crate) timer_sender: LoggingSender<CommandSetTimeout>,
     pub(crate) script_runner: ClientMessageBox<Execute, std::io::Result<Output>>,
 }
 
@@ -53,6 +79,7 @@ impl Actor for TedgeOperationConverterActor {
 
     async fn run(mut self) -> Result<(), RuntimeError> {
         self.publish_operation_capabilities().await?;
+        self.process_pending_commands().await?;
 
         while let Some(input) = self.input_receiver.recv().await {
             match input {
@@ -68,6 +95,9 @@ impl Actor for TedgeOperationConverterActor {
                 AgentInput::RestartCommand(cmd) => {
                     self.process_restart_response(cmd).await?;
                 }
+                AgentInput::CommandTimeout(timeout) => {
+                    self.process_command_timeout(timeout.event).await?;
+                }
             }
         }
         Ok(())
@@ -96,16 +126,31 @@ impl TedgeOperationConverterActor {
         };
 
         let mut log_file = CommandLog::new(self.log_dir.clone(), &operation, &cmd_id).await;
+        if let Ok(Some(state)) = GenericCommandState::from_command_message(&message) {
+            self.resume_parent_operation(&mut log_file, &state).await?;
+        }
+
         match self
             .workflows
             .get_workflow_current_action(&operation, &message)
         {
             Ok(None) => {
+                self.workflows.remove_command(&message.topic.name);
+                self.persist_pending_commands().await;
                 log_file
                     .log_step("", "The command has been fully processed")
                     .await;
             }
             Ok(Some((state, action))) => {
+                if !self.workflows.record_command_state(&state, &action) {
+                    info!(
+                        "Ignoring {operation} operation {} step which has already been processed",
+                        state.status
+                    );
+                    return Ok(());
+                }
+                self.persist_pending_commands().await;
+                self.set_state_timeout(&state).await?;
                 self.process_workflow_action(
                     &mut log_file,
                     message,
@@ -147,6 +192,8 @@ impl TedgeOperationConverterActor {
         action: OperationAction,
     ) -> Result<(), RuntimeError> {
         log_file.log_state_action(&state, &action).await;
+        let mut transition = WorkflowTransition::new(&state, &action);
+        let log_topic = Topic::new_unchecked(&format!("{}/log", message.topic.name));
 
         match action {
             OperationAction::Clear => {
@@ -154,25 +201,23 @@ impl TedgeOperationConverterActor {
                     "Waiting {} {operation} operation to be cleared",
                     state.status
                 );
-                Ok(())
             }
             OperationAction::MoveTo(next_step) => {
                 info!("Moving {operation} operation to state: {next_step}");
                 let new_state = state.move_to(next_step);
+                transition.next = Some(new_state.status.clone());
                 self.publish_command_state(operation, cmd_id, new_state)
-                    .await
+                    .await?;
             }
             OperationAction::BuiltIn => {
                 let step = &state.status;
                 info!("Processing {operation} operation {step} step");
                 self.process_internal_operation(target, operation, cmd_id, message)
-                    .await
+                    .await?;
             }
             OperationAction::Delegate(participant) => {
                 let step = &state.status;
                 info!("Delegating {operation} operation {step} step to: {participant}");
-                // TODO fail the operation on timeout
-                Ok(())
             }
             OperationAction::Restart {
                 on_exec,
@@ -190,7 +235,6 @@ impl TedgeOperationConverterActor {
                     on_error,
                 );
                 self.restart_sender.send(cmd).await?;
-                Ok(())
             }
             OperationAction::Script(script, handlers) => {
                 let step = &state.status;
@@ -210,18 +254,38 @@ impl TedgeOperationConverterActor {
                         (None, _) => command,
                     }
                 };
+                let started_at = Instant::now();
                 let output = self.script_runner.await_response(command).await?;
+                transition.set_script_outcome(&output, started_at.elapsed());
                 log_file.log_script_output(&output).await;
 
-                let new_state = state.update_with_script_output(script_name, output, handlers);
-                self.publish_command_state(operation, cmd_id, new_state)
-                    .await
+                let attempt = state.attempt();
+                if let Some(delay) = handlers.retry_delay(&output, attempt) {
+                    info!(
+                        "Retrying {operation} operation {step} step in {}s (attempt #{attempt})",
+                        delay.as_secs()
+                    );
+                    log_file
+                        .log_step(step, &format!("Retry in {}s", delay.as_secs()))
+                        .await;
+                    transition.next = Some(step.clone());
+                    let retry = CommandTimer::Retry(state.clone());
+                    self.timer_sender
+                        .send(CommandSetTimeout::new(delay, retry))
+                        .await?;
+                } else {
+                    let new_state = state.update_with_script_output(script_name, output, handlers);
+                    transition.next = Some(new_state.status.clone());
+                    self.publish_command_state(operation, cmd_id, new_state)
+                        .await?;
+                }
             }
             OperationAction::BgScript(script, handlers) => {
                 let next_state = &handlers.on_exec.status;
                 info!(
                     "Moving {operation} operation to {next_state} state before running: {script}"
                 );
+                transition.next = Some(next_state.clone());
                 let new_state = state.update(handlers.on_exec);
                 self.publish_command_state(operation, cmd_id, new_state)
                     .await?;
@@ -230,9 +294,181 @@ impl TedgeOperationConverterActor {
                 let command = Execute::new(script.command, script.args);
                 let output = self.script_runner.await_response(command).await?;
                 log_file.log_script_output(&output).await;
-                Ok(())
+            }
+            OperationAction::Operation {
+                operation: sub_operation,
+                input,
+                ..
+            } => {
+                let step = &state.status;
+                info!(
+                    "Triggering {sub_operation} operation from {operation} operation {step} step"
+                );
+                match self
+                    .workflows
+                    .start_sub_operation(&state, &sub_operation, input)
+                {
+                    Some(sub_command) => {
+                        self.mqtt_publisher.send(sub_command.into_message()).await?;
+                    }
+                    None => {
+                        let reason = format!("Cannot trigger {sub_operation} operation");
+                        let new_state = state.fail_with(reason);
+                        transition.next = Some(new_state.status.clone());
+                        self.publish_command_state(operation, cmd_id, new_state)
+                            .await?;
+                    }
+                }
+            }
+            OperationAction::Evaluate(handlers) => {
+                let update = handlers.state_update(&state);
+                let next_state = &update.status;
+                info!("Moving {operation} operation to {next_state} state");
+                transition.next = Some(next_state.clone());
+                let new_state = state.update(update);
+                self.publish_command_state(operation, cmd_id, new_state)
+                    .await?;
             }
         }
+
+        log_file.log_transition(&transition).await;
+        let log_message =
+            MqttMessage::new(&log_topic, transition.to_json_string()).with_qos(QoS::AtLeastOnce);
+        self.mqtt_publisher.send(log_message).await?;
+        Ok(())
+    }
+
+    /// Resume or fail the commands which were in progress when the agent stopped
+    async fn process_pending_commands(&mut self) -> Result<(), RuntimeError> {
+        let commands = match self.state_repository.load().await {
+            Ok(Some(commands)) => commands,
+            Ok(None) => return Ok(()),
+            Err(StateError::LoadingFromFileFailed { source, .. })
+                if source.kind() == std::io::ErrorKind::NotFound =>
+            {
+                // file missing means no workflow has ever been executed, so just do nothing
+                return Ok(());
+            }
+            Err(err) => {
+                error!("Fail to load the pending workflow commands: {err}");
+                return Ok(());
+            }
+        };
+
+        let pending_commands = self.workflows.load_pending_commands(commands);
+
+        // The timeouts are re-armed from scratch for the commands left untouched
+        for state in self.workflows.pending_commands() {
+            self.set_state_timeout(&state).await?;
+        }
+
+        for pending_command in pending_commands {
+            match pending_command {
+                PendingCommand::Resume(state, action) => {
+                    let message = state.clone().into_message();
+                    let Ok((target, Channel::Command { operation, cmd_id })) =
+                        self.mqtt_schema.entity_channel_of(&message.topic)
+                    else {
+                        continue;
+                    };
+                    info!("Resuming {operation} operation {} step", state.status);
+                    let mut log_file =
+                        CommandLog::new(self.log_dir.clone(), &operation, &cmd_id).await;
+                    self.process_workflow_action(
+                        &mut log_file,
+                        message,
+                        target,
+                        operation,
+                        cmd_id,
+                        state,
+                        action,
+                    )
+                    .await?;
+                }
+                PendingCommand::Fail(state) => {
+                    info!(
+                        "Failing {}: {}",
+                        state.topic.name,
+                        state.failure_reason().unwrap_or_default()
+                    );
+                    self.mqtt_publisher.send(state.into_message()).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Set a timer on the current state of a command, if a timeout is defined for this state
+    async fn set_state_timeout(&mut self, state: &GenericCommandState) -> Result<(), RuntimeError> {
+        if let Some(timeout) = self.workflows.state_timeout(state) {
+            let event = CommandTimer::StateTimeout(state.clone(), timeout.on_timeout);
+            self.timer_sender
+                .send(CommandSetTimeout::new(timeout.timeout, event))
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn process_command_timeout(&mut self, timer: CommandTimer) -> Result<(), RuntimeError> {
+        match timer {
+            CommandTimer::StateTimeout(state, on_timeout) => {
+                // Ignore the timeout if the command already moved to another state
+                if self.workflows.is_current_state(&state) {
+                    info!(
+                        "Timeout on {} in {} state: moving to {} state",
+                        state.topic.name, state.status, on_timeout.status
+                    );
+                    self.mqtt_publisher
+                        .send(state.update(on_timeout).into_message())
+                        .await?;
+                }
+            }
+            CommandTimer::Retry(state) => {
+                // Ignore the retry if the command has been cleared or moved to another state
+                if self.workflows.is_current_state(&state) {
+                    self.mqtt_publisher
+                        .send(state.retry().into_message())
+                        .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Persist the latest state of the commands in progress, to be able to resume them after a restart
+    async fn persist_pending_commands(&mut self) {
+        let commands = self.workflows.pending_commands();
+        if let Err(err) = self.state_repository.store(&commands).await {
+            error!("Fail to persist the pending workflow commands: {err}");
+        }
+    }
+
+    /// Resume the command that triggered a sub-operation, when the latter reaches a terminal state
+    async fn resume_parent_operation(
+        &mut self,
+        log_file: &mut CommandLog,
+        sub_command: &GenericCommandState,
+    ) -> Result<(), RuntimeError> {
+        if let Some(parent) = self.workflows.resume_parent_operation(sub_command) {
+            let sub_operation_status = &sub_command.status;
+            let parent_topic = &parent.topic.name;
+            let next_step = &parent.status;
+            info!("Resuming {parent_topic} in {next_step} state: {sub_operation_status}");
+            log_file
+                .log_step(
+                    sub_operation_status,
+                    &format!("Resuming {parent_topic} in {next_step} state"),
+                )
+                .await;
+
+            // The sub-operation command is cleared, as the requester is the parent command
+            let clear_sub_command = MqttMessage::new(&sub_command.topic, "")
+                .with_qos(QoS::AtLeastOnce)
+                .with_retain();
+            self.mqtt_publisher.send(parent.into_message()).await?;
+            self.mqtt_publisher.send(clear_sub_command).await?;
+        }
+        Ok(())
     }
 
     async fn process_internal_operation(
@@ -334,6 +570,86 @@ impl TedgeOperationConverterActor {
     }
 }
 
+/// A step of a command, as recorded in the command audit log
+#[derive(Debug, Serialize)]
+struct WorkflowTransition {
+    /// The state of the command when the action has been triggered
+    status: String,
+
+    /// When the action has been triggered
+    time: String,
+
+    /// The action triggered for this state
+    action: String,
+
+    /// The exit code of the script, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+
+    /// The signal that killed the script, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signal: Option<i32>,
+
+    /// The tail of the script stdout, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stdout: Option<String>,
+
+    /// The execution time of the script in seconds, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<f64>,
+
+    /// The next state, when known by the agent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next: Option<String>,
+}
+
+impl WorkflowTransition {
+    /// The maximum number of bytes of script stdout recorded in a transition
+    const STDOUT_EXCERPT_SIZE: usize = 1024;
+
+    fn new(state: &GenericCommandState, action: &OperationAction) -> Self {
+        let time = OffsetDateTime::now_utc()
+            .format(&format_description::well_known::Rfc3339)
+            .unwrap_or_default();
+        WorkflowTransition {
+            status: state.status.clone(),
+            time,
+            action: action.to_string(),
+            exit_code: None,
+            signal: None,
+            stdout: None,
+            duration: None,
+            next: None,
+        }
+    }
+
+    fn set_script_outcome(&mut self, outcome: &std::io::Result<Output>, duration: Duration) {
+        self.duration = Some(duration.as_secs_f64());
+        if let Ok(output) = outcome {
+            self.exit_code = output.status.code();
+            self.signal = output.status.signal();
+            self.stdout = Self::stdout_excerpt(&output.stdout);
+        }
+    }
+
+    fn stdout_excerpt(stdout: &[u8]) -> Option<String> {
+        let stdout = String::from_utf8_lossy(stdout);
+        let stdout = stdout.trim();
+        if stdout.is_empty() {
+            return None;
+        }
+        let mut start = stdout.len().saturating_sub(Self::STDOUT_EXCERPT_SIZE);
+        while !stdout.is_char_boundary(start) {
+            start += 1;
+        }
+        Some(stdout[start..].to_string())
+    }
+
+    fn to_json_string(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
 struct CommandLog {
     path: Utf8PathBuf,
     file: Option<File>,
@@ -373,6 +689,13 @@ Action: {action}
         self.log_step(step, &message).await
     }
 
+    async fn log_transition(&mut self, transition: &WorkflowTransition) {
+        let message = format!("Transition: {}\n", transition.to_json_string());
+        if let Err(err) = self.write(&message).await {
+            error!("Fail to log to {}: {err}", self.path)
+        }
+    }
+
     async fn log_step(&mut self, step: &str, action: &str) {
         let now = OffsetDateTime::now_utc()
             .format(&format_description::well_known::Rfc3339)
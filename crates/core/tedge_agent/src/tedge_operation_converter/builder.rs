@@ -1,5 +1,8 @@
 use crate::software_manager::actor::SoftwareCommand;
+use crate::state_repository::state::AgentStateRepository;
 use crate::tedge_operation_converter::actor::AgentInput;
+use crate::tedge_operation_converter::actor::CommandSetTimeout;
+use crate::tedge_operation_converter::actor::CommandTimeout;
 use crate::tedge_operation_converter::actor::TedgeOperationConverterActor;
 use camino::Utf8PathBuf;
 use log::error;
@@ -20,6 +23,7 @@ use tedge_api::mqtt_topics::EntityFilter;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
 use tedge_api::mqtt_topics::OperationType;
+use tedge_api::workflow::GenericCommandState;
 use tedge_api::workflow::WorkflowSupervisor;
 use tedge_api::RestartCommand;
 use tedge_mqtt_ext::MqttMessage;
@@ -31,10 +35,12 @@ pub struct TedgeOperationConverterBuilder {
     device_topic_id: EntityTopicId,
     workflows: WorkflowSupervisor,
     log_dir: Utf8PathBuf,
+    state_repository: AgentStateRepository<Vec<GenericCommandState>>,
     input_receiver: LoggingReceiver<AgentInput>,
     software_sender: LoggingSender<SoftwareCommand>,
     restart_sender: LoggingSender<RestartCommand>,
     mqtt_publisher: LoggingSender<MqttMessage>,
+    timer_sender: LoggingSender<CommandSetTimeout>,
     script_runner: ClientMessageBox<Execute, std::io::Result<Output>>,
     signal_sender: mpsc::Sender<RuntimeRequest>,
 }
@@ -46,10 +52,13 @@ impl TedgeOperationConverterBuilder {
         device_topic_id: EntityTopicId,
         mut workflows: WorkflowSupervisor,
         log_dir: Utf8PathBuf,
+        state_dir: Utf8PathBuf,
+        config_dir: Utf8PathBuf,
         software_actor: &mut impl ServiceProvider<SoftwareCommand, SoftwareCommand, NoConfig>,
         restart_actor: &mut impl ServiceProvider<RestartCommand, RestartCommand, NoConfig>,
         mqtt_actor: &mut impl ServiceProvider<MqttMessage, MqttMessage, TopicFilter>,
         script_runner: &mut impl ServiceProvider<Execute, std::io::Result<Output>, NoConfig>,
+        timer_actor: &mut impl ServiceProvider<CommandSetTimeout, CommandTimeout, NoConfig>,
     ) -> Self {
         let mqtt_schema = MqttSchema::with_root(mqtt_topic_root.to_string());
         let (input_sender, input_receiver) = mpsc::channel(10);
@@ -68,6 +77,9 @@ impl TedgeOperationConverterBuilder {
         let restart_sender = restart_actor.connect_consumer(NoConfig, input_sender.clone().into());
         let restart_sender = LoggingSender::new("RestartSender".into(), restart_sender);
 
+        let timer_sender = timer_actor.connect_consumer(NoConfig, input_sender.clone().into());
+        let timer_sender = LoggingSender::new("TimerSender".into(), timer_sender);
+
         let mqtt_publisher = mqtt_actor.connect_consumer(
             Self::subscriptions(&mqtt_schema, &device_topic_id),
             input_sender.into(),
@@ -76,6 +88,9 @@ impl TedgeOperationConverterBuilder {
 
         let script_runner = ClientMessageBox::new("Operation Script Runner", script_runner);
 
+        let state_repository =
+            AgentStateRepository::new(state_dir, config_dir, "workflow-current-commands");
+
         for capability in Self::capabilities() {
             let operation = capability.to_string();
             if let Err(err) = workflows.register_builtin_workflow(capability) {
@@ -88,10 +103,12 @@ impl TedgeOperationConverterBuilder {
             device_topic_id,
             workflows,
             log_dir,
+            state_repository,
             input_receiver,
             software_sender,
             restart_sender,
             mqtt_publisher,
+            timer_sender,
             signal_sender,
             script_runner,
         }
@@ -129,10 +146,12 @@ impl Builder<TedgeOperationConverterActor> for TedgeOperationConverterBuilder {
             device_topic_id: self.device_topic_id,
             workflows: self.workflows,
             log_dir: self.log_dir,
+            state_repository: self.state_repository,
             input_receiver: self.input_receiver,
             software_sender: self.software_sender,
             restart_sender: self.restart_sender,
             mqtt_publisher: self.mqtt_publisher,
+            timer_sender: self.timer_sender,
             script_runner: self.script_runner,
         }
     }
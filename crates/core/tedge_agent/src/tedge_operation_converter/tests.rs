@@ -1,5 +1,6 @@
 use crate::software_manager::actor::SoftwareCommand;
 use crate::tedge_operation_converter::builder::TedgeOperationConverterBuilder;
+use serde_json::json;
 use std::process::Output;
 use std::time::Duration;
 use tedge_actors::test_helpers::MessageReceiverExt;
@@ -23,9 +24,12 @@ use tedge_api::workflow::WorkflowSupervisor;
 use tedge_api::RestartCommand;
 use tedge_api::SoftwareUpdateCommand;
 use tedge_mqtt_ext::test_helpers::assert_received_contains_str;
+use tedge_mqtt_ext::test_helpers::assert_received_includes_json;
 use tedge_mqtt_ext::MqttMessage;
 use tedge_mqtt_ext::Topic;
 use tedge_script_ext::Execute;
+use tedge_test_utils::fs::TempTedgeDir;
+use tedge_timer_ext::TimerActor;
 
 const TEST_TIMEOUT_MS: Duration = Duration::from_millis(5000);
 
@@ -73,6 +77,7 @@ async fn convert_incoming_software_update_request() -> Result<(), DynError> {
         action: Some(SoftwareModuleAction::Install),
         url: None,
         reason: None,
+        depends_on: vec![],
     };
     let debian_list = SoftwareRequestResponseSoftwareList {
         plugin_type: "debian".into(),
@@ -88,6 +93,8 @@ async fn convert_incoming_software_update_request() -> Result<(), DynError> {
                 status: CommandStatus::Scheduled,
                 update_list: vec![debian_list],
                 failures: vec![],
+                progress: None,
+                rollback: false,
             },
         }])
         .await;
@@ -234,6 +241,347 @@ async fn convert_outgoing_restart_response() -> Result<(), DynError> {
     Ok(())
 }
 
+#[tokio::test]
+async fn trigger_sub_operation_and_resume_on_completion() -> Result<(), DynError> {
+    let workflow = toml::from_str(
+        r#"
+operation = "software_update"
+
+[init]
+operation = "restart"
+input = { reason = "${.payload.reason}" }
+on_success = "scheduled"
+on_error = "failed"
+"#,
+    )?;
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(workflow)?;
+    let (_software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter_with_workflows(
+            "device/main//",
+            workflows,
+            TempTedgeDir::new(),
+        )
+        .await?;
+    skip_capability_messages(&mut mqtt_box, "device/main//").await;
+
+    // A software update triggers a restart
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/software_update/123"),
+            r#"{ "status": "init", "reason": "new kernel" }"#,
+        ))
+        .await?;
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [(
+            "te/device/main///cmd/restart/sub-software_update-123",
+            r#"{"reason":"new kernel","status":"init"}"#,
+        )],
+    )
+    .await;
+    assert_received_includes_json(
+        &mut mqtt_box,
+        [(
+            "te/device/main///cmd/software_update/123/log",
+            json!({"status": "init", "action": "trigger restart operation and await its completion"}),
+        )],
+    )
+    .await;
+
+    // The software update is resumed when the restart completes
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/restart/sub-software_update-123"),
+            r#"{ "status": "successful", "reason": "new kernel" }"#,
+        ))
+        .await?;
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/software_update/123",
+                r#"{"reason":"new kernel","status":"scheduled"}"#,
+            ),
+            ("te/device/main///cmd/restart/sub-software_update-123", ""),
+        ],
+    )
+    .await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn move_to_timeout_state_when_delegated_step_takes_too_long() -> Result<(), DynError> {
+    let workflow = toml::from_str(
+        r#"
+operation = "software_update"
+
+[init]
+action = "proceed"
+on_success = "scheduled"
+
+[scheduled]
+action = "waiting some-participant"
+timeout_second = 1
+on_timeout = { status = "failed", reason = "no response" }
+on_success = "successful"
+"#,
+    )?;
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(workflow)?;
+    let (_software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter_with_workflows(
+            "device/main//",
+            workflows,
+            TempTedgeDir::new(),
+        )
+        .await?;
+    skip_capability_messages(&mut mqtt_box, "device/main//").await;
+
+    // The delegate never moves the command out of the scheduled state
+    let scheduled = MqttMessage::new(
+        &Topic::new_unchecked("te/device/main///cmd/software_update/123"),
+        r#"{ "status": "scheduled" }"#,
+    );
+    mqtt_box.send(scheduled).await?;
+    assert_received_includes_json(
+        &mut mqtt_box,
+        [(
+            "te/device/main///cmd/software_update/123/log",
+            json!({"status": "scheduled", "action": "wait for some-participant to perform required actions"}),
+        )],
+    )
+    .await;
+
+    // Hence the command is moved to the timeout state
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [(
+            "te/device/main///cmd/software_update/123",
+            r#"{"reason":"no response","status":"failed"}"#,
+        )],
+    )
+    .await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn ignore_timeout_when_delegated_step_is_completed() -> Result<(), DynError> {
+    let workflow = toml::from_str(
+        r#"
+operation = "software_update"
+
+[scheduled]
+action = "waiting some-participant"
+timeout_second = 1
+on_timeout = "timeout"
+on_success = "successful"
+
+[done]
+action = "proceed"
+on_success = "successful"
+"#,
+    )?;
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(workflow)?;
+    let tmp_dir = TempTedgeDir::new();
+    let log_file = tmp_dir
+        .utf8_path_buf()
+        .join("workflow-software_update-123.log");
+    let (_software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter_with_workflows("device/main//", workflows, tmp_dir).await?;
+    skip_capability_messages(&mut mqtt_box, "device/main//").await;
+
+    // The delegate moves the command out of the scheduled state before the timeout
+    let topic = Topic::new_unchecked("te/device/main///cmd/software_update/123");
+    mqtt_box
+        .send(MqttMessage::new(&topic, r#"{ "status": "scheduled" }"#))
+        .await?;
+    mqtt_box
+        .send(MqttMessage::new(&topic, r#"{ "status": "done" }"#))
+        .await?;
+    assert_received_includes_json(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/software_update/123/log",
+                json!({"status": "scheduled"}),
+            ),
+            (
+                "te/device/main///cmd/software_update/123",
+                json!({"status": "successful"}),
+            ),
+            (
+                "te/device/main///cmd/software_update/123/log",
+                json!({"status": "done", "next": "successful"}),
+            ),
+        ],
+    )
+    .await;
+
+    // The transitions are also recorded in the command log
+    let log = std::fs::read_to_string(&log_file)?;
+    assert!(log.contains(r#"Transition: {"status":"scheduled""#));
+    assert!(log.contains(r#""next":"successful"}"#));
+
+    // Hence the timeout is ignored
+    mqtt_box
+        .send(MqttMessage::new(&topic, r#"{ "status": "successful" }"#))
+        .await?;
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(mqtt_box.recv().await.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn abandon_retry_when_command_is_cleared() -> Result<(), DynError> {
+    let workflow = toml::from_str(
+        r#"
+operation = "software_update"
+
+[scheduled]
+script = "/some/download.sh"
+retry = { max = 3, delay_second = 1 }
+on_success = "successful"
+on_error = "failed"
+"#,
+    )?;
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(workflow)?;
+    let (_software_box, _restart_box, mut mqtt_box, mut script_box) =
+        spawn_mqtt_operation_converter_with_scripts(
+            "device/main//",
+            workflows,
+            TempTedgeDir::new(),
+        )
+        .await?;
+    skip_capability_messages(&mut mqtt_box, "device/main//").await;
+
+    // The script fails and a retry is scheduled
+    let topic = Topic::new_unchecked("te/device/main///cmd/software_update/123");
+    mqtt_box
+        .send(MqttMessage::new(&topic, r#"{ "status": "scheduled" }"#))
+        .await?;
+    let execute = script_box.recv().await.expect("a script to be executed");
+    assert_eq!(execute.command, "/some/download.sh");
+    script_box
+        .send(Ok(Output {
+            status: std::os::unix::process::ExitStatusExt::from_raw(1 << 8),
+            stdout: vec![],
+            stderr: b"network error".to_vec(),
+        }))
+        .await?;
+
+    // The command is cleared before the retry delay elapses
+    mqtt_box
+        .send(MqttMessage::new(&topic, "").with_retain())
+        .await?;
+
+    // Hence the retry is abandoned: the command is not brought back
+    let deadline = tokio::time::Instant::now() + Duration::from_millis(1500);
+    while let Ok(Some(message)) = tokio::time::timeout_at(deadline, mqtt_box.recv()).await {
+        assert!(
+            message.topic != topic || message.payload_bytes().is_empty(),
+            "Unexpected message: {message:?}"
+        );
+    }
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), script_box.recv())
+            .await
+            .is_err(),
+        "The script is not expected to be re-executed"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn resume_pending_commands_on_restart() -> Result<(), DynError> {
+    let workflow = toml::from_str(
+        r#"
+operation = "do_something"
+
+[init]
+action = "proceed"
+on_success = "scheduled"
+
+[scheduled]
+script = "/some/script.sh"
+on_success = "successful"
+"#,
+    )?;
+    let mut workflows = WorkflowSupervisor::default();
+    workflows.register_custom_workflow(workflow)?;
+
+    // Commands were in progress when the agent stopped
+    let tmp_dir = TempTedgeDir::new();
+    tmp_dir.file("workflow-current-commands").with_raw_content(
+        r#"[
+            {"topic":{"name":"te/device/main///cmd/do_something/1"},"status":"init","payload":{"status":"init"}},
+            {"topic":{"name":"te/device/main///cmd/do_something/2"},"status":"scheduled","payload":{"status":"scheduled"}}
+        ]"#,
+    );
+    let (_software_box, _restart_box, mut mqtt_box) =
+        spawn_mqtt_operation_converter_with_workflows("device/main//", workflows, tmp_dir).await?;
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            ("te/device/main///cmd/do_something", "{}"),
+            ("te/device/main///cmd/restart", "{}"),
+            ("te/device/main///cmd/software_list", "{}"),
+            ("te/device/main///cmd/software_update", "{}"),
+        ],
+    )
+    .await;
+
+    // The command with no side effect is resumed, the interrupted script is failed
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [
+            (
+                "te/device/main///cmd/do_something/1",
+                r#"{"status":"scheduled"}"#,
+            ),
+            (
+                "te/device/main///cmd/do_something/1/log",
+                r#""next":"scheduled"}"#,
+            ),
+            (
+                "te/device/main///cmd/do_something/2",
+                r#"{"reason":"Agent restarted during scheduled state","status":"failed"}"#,
+            ),
+        ],
+    )
+    .await;
+
+    // The retained messages of the commands already processed are ignored
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/do_something/1"),
+            r#"{"status":"init"}"#,
+        ))
+        .await?;
+    mqtt_box
+        .send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/do_something/3"),
+            r#"{"status":"init"}"#,
+        ))
+        .await?;
+    assert_received_contains_str(
+        &mut mqtt_box,
+        [(
+            "te/device/main///cmd/do_something/3",
+            r#"{"status":"scheduled"}"#,
+        )],
+    )
+    .await;
+
+    Ok(())
+}
+
 async fn spawn_mqtt_operation_converter(
     device_topic_id: &str,
 ) -> Result<
@@ -243,6 +591,44 @@ async fn spawn_mqtt_operation_converter(
         TimedMessageBox<SimpleMessageBox<MqttMessage, MqttMessage>>,
     ),
     DynError,
+> {
+    spawn_mqtt_operation_converter_with_workflows(
+        device_topic_id,
+        WorkflowSupervisor::default(),
+        TempTedgeDir::new(),
+    )
+    .await
+}
+
+async fn spawn_mqtt_operation_converter_with_workflows(
+    device_topic_id: &str,
+    workflows: WorkflowSupervisor,
+    tmp_dir: TempTedgeDir,
+) -> Result<
+    (
+        TimedMessageBox<SimpleMessageBox<SoftwareCommand, SoftwareCommand>>,
+        TimedMessageBox<SimpleMessageBox<RestartCommand, RestartCommand>>,
+        TimedMessageBox<SimpleMessageBox<MqttMessage, MqttMessage>>,
+    ),
+    DynError,
+> {
+    let (software_box, restart_box, mqtt_box, _script_box) =
+        spawn_mqtt_operation_converter_with_scripts(device_topic_id, workflows, tmp_dir).await?;
+    Ok((software_box, restart_box, mqtt_box))
+}
+
+async fn spawn_mqtt_operation_converter_with_scripts(
+    device_topic_id: &str,
+    workflows: WorkflowSupervisor,
+    tmp_dir: TempTedgeDir,
+) -> Result<
+    (
+        TimedMessageBox<SimpleMessageBox<SoftwareCommand, SoftwareCommand>>,
+        TimedMessageBox<SimpleMessageBox<RestartCommand, RestartCommand>>,
+        TimedMessageBox<SimpleMessageBox<MqttMessage, MqttMessage>>,
+        TimedMessageBox<SimpleMessageBox<Execute, std::io::Result<Output>>>,
+    ),
+    DynError,
 > {
     let mut software_builder: SimpleMessageBoxBuilder<SoftwareCommand, SoftwareCommand> =
         SimpleMessageBoxBuilder::new("Software", 5);
@@ -252,28 +638,35 @@ async fn spawn_mqtt_operation_converter(
         SimpleMessageBoxBuilder::new("MQTT", 5);
     let mut script_builder: SimpleMessageBoxBuilder<Execute, std::io::Result<Output>> =
         SimpleMessageBoxBuilder::new("Script", 5);
-
-    let workflows = WorkflowSupervisor::default();
+    let mut timer_builder = TimerActor::builder();
 
     let converter_actor_builder = TedgeOperationConverterBuilder::new(
         "te",
         device_topic_id.parse().expect("Invalid topic id"),
         workflows,
-        "/tmp".into(),
+        tmp_dir.utf8_path_buf(),
+        tmp_dir.utf8_path_buf(),
+        tmp_dir.utf8_path_buf(),
         &mut software_builder,
         &mut restart_builder,
         &mut mqtt_builder,
         &mut script_builder,
+        &mut timer_builder,
     );
 
     let software_box = software_builder.build().with_timeout(TEST_TIMEOUT_MS);
     let restart_box = restart_builder.build().with_timeout(TEST_TIMEOUT_MS);
     let mqtt_message_box = mqtt_builder.build().with_timeout(TEST_TIMEOUT_MS);
+    let script_box = script_builder.build().with_timeout(TEST_TIMEOUT_MS);
 
     let converter_actor = converter_actor_builder.build();
-    tokio::spawn(async move { converter_actor.run().await });
+    tokio::spawn(async move { timer_builder.build().run().await });
+    tokio::spawn(async move {
+        let _tmp_dir = tmp_dir;
+        converter_actor.run().await
+    });
 
-    Ok((software_box, restart_box, mqtt_message_box))
+    Ok((software_box, restart_box, mqtt_message_box, script_box))
 }
 
 async fn skip_capability_messages(mqtt: &mut impl MessageReceiver<MqttMessage>, device: &str) {
@@ -0,0 +1,239 @@
+//! An append-only journal persisting the entities registered in an [EntityStore].
+//!
+//! Each line of the journal is a JSON object recording either:
+//!
+//! - the registration of an entity: the registration payload (`@type`, `@id`, `@parent`, ...)
+//!   extended with the `@topic-id` of the entity,
+//! - or the deregistration of an entity: an object with the `@topic-id` of the entity and nothing else,
+//!   mirroring the empty retained message used to deregister an entity over MQTT.
+//!
+//! [EntityStore]: crate::entity_store::EntityStore
+
+use crate::entity_store::EntityRegistrationMessage;
+use crate::mqtt_topics::EntityTopicId;
+use log::warn;
+use serde_json::Map;
+use serde_json::Value as JsonValue;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+const TOPIC_ID_KEY: &str = "@topic-id";
+
+/// A change recorded in the entity journal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalEntry {
+    Registered(EntityRegistrationMessage),
+    Deregistered(EntityTopicId),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum JournalError {
+    #[error("Fail to access the entity journal {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+pub struct EntityJournal {
+    path: PathBuf,
+    file: File,
+}
+
+impl EntityJournal {
+    /// Opens the journal stored at the given path, creating it if missing.
+    ///
+    /// Returns the journal along with the entries already recorded in the file.
+    /// Lines that cannot be parsed, as a line partially written on a crash, are skipped.
+    pub fn open(path: impl AsRef<Path>) -> Result<(Self, Vec<JournalEntry>), JournalError> {
+        let path = path.as_ref().to_path_buf();
+        let io_error = |source| JournalError::Io {
+            path: path.clone(),
+            source,
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(io_error)?;
+        }
+
+        let mut entries = vec![];
+        if path.exists() {
+            let reader = BufReader::new(File::open(&path).map_err(io_error)?);
+            for line in reader.lines() {
+                let line = line.map_err(io_error)?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match JournalEntry::parse(&line) {
+                    Some(entry) => entries.push(entry),
+                    None => warn!("Ignoring invalid entry in entity journal {path:?}: {line}"),
+                }
+            }
+        }
+
+        let file = Self::open_for_append(&path).map_err(io_error)?;
+        Ok((EntityJournal { path, file }, entries))
+    }
+
+    /// Appends an entry to the journal
+    pub fn append(&mut self, entry: &JournalEntry) -> Result<(), JournalError> {
+        writeln!(self.file, "{}", entry.to_json_line())
+            .and_then(|_| self.file.flush())
+            .map_err(|source| JournalError::Io {
+                path: self.path.clone(),
+                source,
+            })
+    }
+
+    /// Replaces the content of the journal with the given entries
+    ///
+    /// The new content is first written to a temporary file which then atomically replaces the journal.
+    pub fn compact(&mut self, entries: &[JournalEntry]) -> Result<(), JournalError> {
+        let io_error = |source| JournalError::Io {
+            path: self.path.clone(),
+            source,
+        };
+
+        let tmp_path = self.path.with_extension("tmp");
+        let mut content = String::new();
+        for entry in entries {
+            content.push_str(&entry.to_json_line());
+            content.push('\n');
+        }
+        std::fs::write(&tmp_path, content).map_err(io_error)?;
+        std::fs::rename(&tmp_path, &self.path).map_err(io_error)?;
+
+        self.file = Self::open_for_append(&self.path).map_err(io_error)?;
+        Ok(())
+    }
+
+    fn open_for_append(path: &Path) -> std::io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+}
+
+impl JournalEntry {
+    fn parse(line: &str) -> Option<Self> {
+        let JsonValue::Object(mut payload) = serde_json::from_str(line).ok()? else {
+            return None;
+        };
+        let JsonValue::String(topic_id) = payload.remove(TOPIC_ID_KEY)? else {
+            return None;
+        };
+        let topic_id: EntityTopicId = topic_id.parse().ok()?;
+
+        if payload.is_empty() {
+            Some(JournalEntry::Deregistered(topic_id))
+        } else {
+            EntityRegistrationMessage::from_json(topic_id, JsonValue::Object(payload))
+                .map(JournalEntry::Registered)
+        }
+    }
+
+    fn to_json_line(&self) -> String {
+        let payload = match self {
+            JournalEntry::Registered(message) => {
+                let mut payload = Map::new();
+                payload.insert(TOPIC_ID_KEY.to_string(), message.topic_id.as_str().into());
+                payload.append(&mut message.clone().into_json());
+                payload
+            }
+            JournalEntry::Deregistered(topic_id) => {
+                let mut payload = Map::new();
+                payload.insert(TOPIC_ID_KEY.to_string(), topic_id.as_str().into());
+                payload
+            }
+        };
+        JsonValue::Object(payload).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity_store::EntityType;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn entries_are_persisted_across_restarts() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("mapper/entity_store.jsonl");
+
+        let child = EntityRegistrationMessage::new_custom(
+            "device/child1//".parse().unwrap(),
+            EntityType::ChildDevice,
+        )
+        .with_external_id("child1".into())
+        .with_other_fragment("name".to_string(), json!("Child 1"));
+        let entries = vec![
+            JournalEntry::Registered(child.clone()),
+            JournalEntry::Deregistered("device/child1//".parse().unwrap()),
+        ];
+
+        let (mut journal, recorded) = EntityJournal::open(&path).unwrap();
+        assert!(recorded.is_empty());
+        for entry in entries.iter() {
+            journal.append(entry).unwrap();
+        }
+        drop(journal);
+
+        let (_, recorded) = EntityJournal::open(&path).unwrap();
+        assert_eq!(recorded, entries);
+    }
+
+    #[test]
+    fn invalid_lines_are_skipped() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("entity_store.jsonl");
+        std::fs::write(
+            &path,
+            r#"{"@topic-id":"device/child1//","@type":"child-device","@id":"child1"}
+{"@topic-id":"device/child2//","@type":"child-dev"#,
+        )
+        .unwrap();
+
+        let (_, recorded) = EntityJournal::open(&path).unwrap();
+        assert_eq!(recorded.len(), 1);
+    }
+
+    #[test]
+    fn compaction_replaces_the_content_of_the_journal() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("entity_store.jsonl");
+
+        let (mut journal, _) = EntityJournal::open(&path).unwrap();
+        journal
+            .append(&JournalEntry::Deregistered(
+                "device/child1//".parse().unwrap(),
+            ))
+            .unwrap();
+
+        let service = EntityRegistrationMessage::new_custom(
+            "device/main/service/collectd".parse().unwrap(),
+            EntityType::Service,
+        );
+        journal
+            .compact(&[JournalEntry::Registered(service.clone())])
+            .unwrap();
+        journal
+            .append(&JournalEntry::Deregistered(service.topic_id.clone()))
+            .unwrap();
+        drop(journal);
+
+        let (_, recorded) = EntityJournal::open(&path).unwrap();
+        assert_eq!(
+            recorded,
+            vec![
+                JournalEntry::Registered(service.clone()),
+                JournalEntry::Deregistered(service.topic_id),
+            ]
+        );
+    }
+}
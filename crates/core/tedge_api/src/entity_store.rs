@@ -7,21 +7,31 @@
 
 // TODO: move entity business logic to its own module
 
+use crate::entity_journal::EntityJournal;
+use crate::entity_journal::JournalEntry;
+use crate::entity_journal::JournalError;
 use crate::entity_store;
+use crate::entity_topic_scheme::TopicSchemes;
 use crate::mqtt_topics::Channel;
 use crate::mqtt_topics::EntityTopicId;
 use crate::mqtt_topics::MqttSchema;
 use crate::mqtt_topics::TopicIdError;
+use crate::pending_entity_store::DroppedMessages;
 use crate::pending_entity_store::PendingEntityData;
 use crate::pending_entity_store::PendingEntityStore;
+use crate::pending_entity_store::PendingEntityStoreConfig;
 use log::debug;
+use log::warn;
 use mqtt_channel::Message;
 use serde_json::json;
 use serde_json::Map;
 use serde_json::Value as JsonValue;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
 use thiserror::Error;
 
 /// Represents an "Entity topic identifier" portion of the MQTT topic
@@ -136,6 +146,8 @@ pub struct EntityStore {
     // TODO: this is a c8y cloud specific concern and it'd be better to put it somewhere else.
     default_service_type: String,
     pending_entity_store: PendingEntityStore,
+    journal: Option<EntityJournal>,
+    topic_schemes: TopicSchemes,
 }
 
 impl EntityStore {
@@ -158,7 +170,7 @@ impl EntityStore {
             "service".to_string(),
             external_id_mapper_fn,
             external_id_validator_fn,
-            100,
+            PendingEntityStoreConfig::default(),
         )
     }
 
@@ -169,7 +181,7 @@ impl EntityStore {
         default_service_type: String,
         external_id_mapper_fn: MF,
         external_id_validator_fn: SF,
-        telemetry_cache_size: usize,
+        pending_entity_store_config: PendingEntityStoreConfig,
     ) -> Option<Self>
     where
         MF: Fn(&EntityTopicId, &EntityExternalId) -> EntityExternalId,
@@ -198,10 +210,56 @@ impl EntityStore {
             external_id_mapper: Box::new(external_id_mapper_fn),
             external_id_validator_fn: Box::new(external_id_validator_fn),
             default_service_type,
-            pending_entity_store: PendingEntityStore::new(mqtt_schema, telemetry_cache_size),
+            pending_entity_store: PendingEntityStore::new(mqtt_schema, pending_entity_store_config),
+            journal: None,
+            topic_schemes: TopicSchemes::default(),
         })
     }
 
+    /// Auto-registers the entities matching the given topic schemes
+    /// along with their ancestors, as inferred from these schemes.
+    pub fn with_topic_schemes(self, topic_schemes: TopicSchemes) -> Self {
+        EntityStore {
+            topic_schemes,
+            ..self
+        }
+    }
+
+    /// Persists the entity store in an append-only journal stored at the given path.
+    ///
+    /// The entities already recorded in the journal are first restored,
+    /// so the store is populated with the full entity hierarchy known before a restart,
+    /// even if the retained registration messages have been cleared in the meantime.
+    /// The journal is then compacted, keeping only the currently registered entities,
+    /// and any subsequent registration or deregistration is appended to it.
+    pub fn with_journal(mut self, path: impl AsRef<Path>) -> Result<Self, JournalError> {
+        let (mut journal, entries) = EntityJournal::open(path)?;
+
+        for entry in entries {
+            match entry {
+                JournalEntry::Registered(message) => {
+                    let topic_id = message.topic_id.clone();
+                    if let Err(err) = self.register_entity(message) {
+                        warn!("Fail to restore entity {topic_id} from the journal: {err}");
+                    }
+                }
+                JournalEntry::Deregistered(topic_id) => {
+                    self.deregister_entity(&topic_id);
+                }
+            }
+        }
+
+        let current_entities: Vec<JournalEntry> = self
+            .hierarchy()
+            .into_iter()
+            .map(|entity| JournalEntry::Registered(entity.into()))
+            .collect();
+        journal.compact(&current_entities)?;
+
+        self.journal = Some(journal);
+        Ok(self)
+    }
+
     /// Returns information about an entity under a given MQTT entity topic identifier.
     pub fn get(&self, entity_topic_id: &EntityTopicId) -> Option<&EntityMetadata> {
         self.entities.get(entity_topic_id)
@@ -372,6 +430,16 @@ impl EntityStore {
         }
     }
 
+    /// Registers or updates an entity, failing if its parent is not registered yet.
+    ///
+    /// Unlike [EntityStore::update], the registration is not postponed till the parent is registered.
+    pub fn try_register(
+        &mut self,
+        message: EntityRegistrationMessage,
+    ) -> Result<Vec<EntityTopicId>, Error> {
+        self.register_entity(message)
+    }
+
     fn register_entity(
         &mut self,
         message: EntityRegistrationMessage,
@@ -449,12 +517,16 @@ impl EntityStore {
                     ..entity_metadata
                 };
 
+                let journal_entry = JournalEntry::Registered((&merged_entity).into());
                 occupied.insert(merged_entity);
                 affected_entities.push(topic_id);
+                self.record(journal_entry);
             }
             Entry::Vacant(vacant) => {
+                let journal_entry = JournalEntry::Registered((&entity_metadata).into());
                 vacant.insert(entity_metadata);
                 self.entity_id_index.insert(external_id, topic_id);
+                self.record(journal_entry);
             }
         }
         debug!("Updated entity map: {:?}", self.entities);
@@ -463,6 +535,102 @@ impl EntityStore {
         Ok(affected_entities)
     }
 
+    /// Removes an entity from the store along with all its descendants,
+    /// i.e. the child devices and services registered under this entity.
+    ///
+    /// Returns the removed entities, starting with the given entity and listing parents before children.
+    /// The main device cannot be deregistered, and nothing is returned for an unknown entity.
+    pub fn deregister_entity(&mut self, topic_id: &EntityTopicId) -> Vec<EntityMetadata> {
+        if topic_id == &self.main_device {
+            warn!("The main device {topic_id} cannot be deregistered");
+            return vec![];
+        }
+
+        let mut removed_entities = vec![];
+        let mut to_be_removed = VecDeque::from([topic_id.clone()]);
+        while let Some(topic_id) = to_be_removed.pop_front() {
+            let Some(entity) = self.entities.remove(&topic_id) else {
+                continue;
+            };
+            self.entity_id_index.remove(&entity.external_id);
+            self.record(JournalEntry::Deregistered(topic_id.clone()));
+
+            to_be_removed.extend(
+                self.entities
+                    .values()
+                    .filter(|e| e.parent.as_ref() == Some(&topic_id))
+                    .map(|e| e.topic_id.clone()),
+            );
+            removed_entities.push(entity);
+        }
+        debug!("Deregistered entities: {:?}", removed_entities);
+
+        removed_entities
+    }
+
+    /// Tracks the entity hierarchy from an entity metadata message, returning the messages forwarding deregistrations.
+    ///
+    /// This is what a mapper that only needs the entity hierarchy to forward deregistrations to the cloud does.
+    /// A registration message is recorded in the store, with no output.
+    /// An empty message deregisters the entity along with its child devices and services:
+    /// for each of these entities, the cloud notification built by `notify` is returned,
+    /// followed for the children by the message clearing their registration,
+    /// so other components are notified of the cascading deregistration.
+    pub fn forward_deregistrations<E>(
+        &mut self,
+        mqtt_schema: &MqttSchema,
+        input: &Message,
+        source: &EntityTopicId,
+        mut notify: impl FnMut(&EntityMetadata) -> Result<Message, E>,
+    ) -> Result<Vec<Message>, E> {
+        if !input.payload_bytes().is_empty() {
+            if let Ok(registration) = EntityRegistrationMessage::try_from(input) {
+                if let Err(err) = self.update(registration) {
+                    warn!("Entity registration failed: {err}");
+                }
+            }
+            return Ok(vec![]);
+        }
+
+        let mut messages = vec![];
+        for removed in self.deregister_entity(source) {
+            messages.push(notify(&removed)?);
+            if &removed.topic_id != source {
+                let topic = mqtt_schema.topic_for(&removed.topic_id, &Channel::EntityMetadata);
+                messages.push(Message::new(&topic, "").with_retain());
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Returns all the registered entities, listing parents before children.
+    fn hierarchy(&self) -> Vec<&EntityMetadata> {
+        let mut entities = vec![];
+        let mut to_be_listed = VecDeque::from([&self.main_device]);
+        while let Some(topic_id) = to_be_listed.pop_front() {
+            let Some(entity) = self.entities.get(topic_id) else {
+                continue;
+            };
+            entities.push(entity);
+            to_be_listed.extend(
+                self.entities
+                    .values()
+                    .filter(|e| e.parent.as_ref() == Some(topic_id))
+                    .map(|e| &e.topic_id),
+            );
+        }
+        entities
+    }
+
+    /// Appends an entry to the journal, if any
+    fn record(&mut self, entry: JournalEntry) {
+        if let Some(journal) = self.journal.as_mut() {
+            if let Err(err) = journal.append(&entry) {
+                warn!("Fail to update the entity journal: {err}");
+            }
+        }
+    }
+
     /// An iterator over all registered entities.
     pub fn iter(&self) -> impl Iterator<Item = (&EntityTopicId, &EntityMetadata)> {
         self.entities.iter()
@@ -481,6 +649,10 @@ impl EntityStore {
         &mut self,
         entity_topic_id: &EntityTopicId,
     ) -> Result<Vec<EntityRegistrationMessage>, entity_store::Error> {
+        if let Some(registrations) = self.topic_schemes.infer_registrations(entity_topic_id) {
+            return self.auto_register_inferred_entities(registrations);
+        }
+
         if entity_topic_id.matches_default_topic_scheme() {
             if entity_topic_id.is_default_main_device() {
                 return Ok(vec![]); // Do nothing as the main device is always pre-registered
@@ -536,6 +708,40 @@ impl EntityStore {
         }
     }
 
+    /// Registers the entities inferred from a custom topic scheme, skipping those already registered
+    fn auto_register_inferred_entities(
+        &mut self,
+        registrations: Vec<EntityRegistrationMessage>,
+    ) -> Result<Vec<EntityRegistrationMessage>, entity_store::Error> {
+        let mut register_messages = vec![];
+        for mut registration in registrations {
+            if self.get(&registration.topic_id).is_some() {
+                continue;
+            }
+
+            if registration.parent.is_none() {
+                registration.parent = Some(self.main_device.clone());
+            }
+            if registration.external_id.is_none() {
+                registration.external_id = Some((self.external_id_mapper)(
+                    &registration.topic_id,
+                    &self.main_device_external_id(),
+                ));
+            }
+            if registration.r#type == EntityType::Service {
+                registration
+                    .other
+                    .entry("type")
+                    .or_insert_with(|| self.default_service_type.clone().into());
+            }
+
+            register_messages.push(registration.clone());
+            self.update(registration)?;
+        }
+
+        Ok(register_messages)
+    }
+
     /// Updates the entity twin data with the provided fragment data.
     /// Returns `true`, if the twin data got updated with the new fragment value.
     /// If the provided fragment already existed, `false` is returned.
@@ -566,6 +772,11 @@ impl EntityStore {
     pub fn cache_early_data_message(&mut self, message: Message) {
         self.pending_entity_store.cache_early_data_message(message)
     }
+
+    /// Returns the number of early messages dropped so far by the pending entity store
+    pub fn dropped_pending_messages(&self) -> DroppedMessages {
+        self.pending_entity_store.dropped_messages()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -598,6 +809,21 @@ impl Display for EntityType {
     }
 }
 
+impl FromStr for EntityType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "device" => Ok(EntityType::MainDevice),
+            "child-device" => Ok(EntityType::ChildDevice),
+            "service" => Ok(EntityType::Service),
+            _ => Err(format!(
+                "Invalid entity type: {s:?}, expecting 'device', 'child-device' or 'service'"
+            )),
+        }
+    }
+}
+
 impl EntityMetadata {
     /// Creates a entity metadata for the main device.
     pub fn main_device(device_id: String) -> Self {
@@ -611,6 +837,15 @@ impl EntityMetadata {
         }
     }
 
+    /// The payload notifying the cloud that this entity has been deregistered
+    pub fn deregistration_payload(&self) -> Map<String, JsonValue> {
+        let mut payload = Map::new();
+        payload.insert("@topic-id".into(), self.topic_id.as_str().into());
+        payload.insert("@type".into(), self.r#type.to_string().into());
+        payload.insert("deregistered".into(), true.into());
+        payload
+    }
+
     /// Creates a entity metadata for a child device.
     pub fn child_device(child_device_id: String) -> Result<Self, TopicIdError> {
         Ok(Self {
@@ -685,6 +920,12 @@ impl EntityRegistrationMessage {
 
         let payload = parse_entity_register_payload(message.payload_bytes())?;
 
+        Self::from_json(topic_id.parse().ok()?, payload)
+    }
+
+    /// Builds a registration message for the given entity from a registration payload.
+    #[must_use]
+    pub fn from_json(topic_id: EntityTopicId, payload: JsonValue) -> Option<Self> {
         let JsonValue::Object(mut properties) = payload else {
             return None;
         };
@@ -693,11 +934,8 @@ impl EntityRegistrationMessage {
             return None;
         };
 
-        let r#type = match r#type.as_str() {
-            "device" => EntityType::MainDevice,
-            "child-device" => EntityType::ChildDevice,
-            "service" => EntityType::Service,
-            _ => return None,
+        let Ok(r#type) = r#type.parse() else {
+            return None;
         };
 
         let parent = properties.remove("@parent");
@@ -736,7 +974,7 @@ impl EntityRegistrationMessage {
         assert_eq!(other.get("@parent"), None);
 
         Some(Self {
-            topic_id: topic_id.parse().ok()?,
+            topic_id,
             external_id: entity_id,
             r#type,
             parent,
@@ -781,7 +1019,15 @@ impl EntityRegistrationMessage {
     }
 
     // TODO: manual serialize impl
-    pub fn to_mqtt_message(mut self, mqtt_schema: &MqttSchema) -> Message {
+    pub fn to_mqtt_message(self, mqtt_schema: &MqttSchema) -> Message {
+        let message_topic = mqtt_schema.topic_for(&self.topic_id, &Channel::EntityMetadata);
+        let message = serde_json::to_string(&self.into_json()).unwrap();
+
+        Message::new(&message_topic, message).with_retain()
+    }
+
+    /// Returns the registration payload of this message
+    pub fn into_json(mut self) -> Map<String, JsonValue> {
         let mut props = serde_json::Map::new();
 
         props.insert("@type".to_string(), self.r#type.to_string().into());
@@ -795,11 +1041,19 @@ impl EntityRegistrationMessage {
         }
 
         props.append(&mut self.other);
+        props
+    }
+}
 
-        let message = serde_json::to_string(&props).unwrap();
-
-        let message_topic = mqtt_schema.topic_for(&self.topic_id, &Channel::EntityMetadata);
-        Message::new(&message_topic, message).with_retain()
+impl From<&EntityMetadata> for EntityRegistrationMessage {
+    fn from(entity: &EntityMetadata) -> Self {
+        EntityRegistrationMessage {
+            topic_id: entity.topic_id.clone(),
+            external_id: Some(entity.external_id.clone()),
+            r#type: entity.r#type.clone(),
+            parent: entity.parent.clone(),
+            other: entity.other.clone(),
+        }
     }
 }
 
@@ -832,6 +1086,7 @@ mod tests {
     use mqtt_channel::Topic;
     use serde_json::json;
     use std::collections::HashSet;
+    use std::convert::Infallible;
     use std::str::FromStr;
 
     fn dummy_external_id_mapper(
@@ -1457,6 +1712,209 @@ mod tests {
         );
     }
 
+    #[test]
+    fn deregistering_an_entity_removes_its_descendants() {
+        let mut store = new_entity_store();
+        for (topic_id, payload) in [
+            ("device/child1//", json!({"@type": "child-device"})),
+            (
+                "device/child11//",
+                json!({"@type": "child-device", "@parent": "device/child1//"}),
+            ),
+            (
+                "device/child1/service/collectd",
+                json!({"@type": "service"}),
+            ),
+            ("device/child2//", json!({"@type": "child-device"})),
+        ] {
+            store
+                .update(registration_message(topic_id, payload))
+                .unwrap();
+        }
+
+        let removed: Vec<String> = store
+            .deregister_entity(&EntityTopicId::default_child_device("child1").unwrap())
+            .into_iter()
+            .map(|entity| entity.topic_id.to_string())
+            .collect();
+
+        assert_eq!(removed[0], "device/child1//");
+        assert_eq!(
+            removed.iter().collect::<HashSet<_>>(),
+            HashSet::from([
+                &"device/child1//".to_string(),
+                &"device/child11//".to_string(),
+                &"device/child1/service/collectd".to_string()
+            ])
+        );
+        assert!(store.get_by_external_id(&"device:child11".into()).is_none());
+        assert_eq!(
+            store.child_devices(&EntityTopicId::default_main_device()),
+            vec![&EntityTopicId::default_child_device("child2").unwrap()]
+        );
+    }
+
+    #[test]
+    fn deregistrations_are_forwarded_for_the_entity_and_its_children() {
+        let mut store = new_entity_store();
+        let mqtt_schema = MqttSchema::default();
+        let notify = |entity: &EntityMetadata| {
+            let payload = JsonValue::Object(entity.deregistration_payload()).to_string();
+            Ok::<_, Infallible>(Message::new(&Topic::new_unchecked("cloud/out"), payload))
+        };
+
+        for (topic, payload) in [
+            ("te/device/child1//", r#"{"@type":"child-device"}"#),
+            ("te/device/child1/service/app", r#"{"@type":"service"}"#),
+        ] {
+            let registration = Message::new(&Topic::new_unchecked(topic), payload);
+            let (source, _) = mqtt_schema.entity_channel_of(&registration.topic).unwrap();
+            let output = store
+                .forward_deregistrations(&mqtt_schema, &registration, &source, notify)
+                .unwrap();
+            assert!(output.is_empty());
+        }
+
+        let child1 = EntityTopicId::default_child_device("child1").unwrap();
+        let deregistration =
+            Message::new(&Topic::new_unchecked("te/device/child1//"), "").with_retain();
+        let output = store
+            .forward_deregistrations(&mqtt_schema, &deregistration, &child1, notify)
+            .unwrap();
+
+        assert_eq!(output.len(), 3);
+        assert_eq!(output[0].topic.name, "cloud/out");
+        assert_eq!(
+            serde_json::from_str::<JsonValue>(output[0].payload_str().unwrap()).unwrap(),
+            json!({"@topic-id": "device/child1//", "@type": "child-device", "deregistered": true})
+        );
+        assert_eq!(output[1].topic.name, "cloud/out");
+        assert_eq!(output[2].topic.name, "te/device/child1/service/app");
+        assert!(output[2].payload_bytes().is_empty() && output[2].retain);
+    }
+
+    #[test]
+    fn main_device_cannot_be_deregistered() {
+        let mut store = new_entity_store();
+
+        assert!(store
+            .deregister_entity(&EntityTopicId::default_main_device())
+            .is_empty());
+        assert!(store.get(&EntityTopicId::default_main_device()).is_some());
+    }
+
+    #[test]
+    fn entities_are_restored_from_the_journal() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let journal = temp_dir.path().join("entity_store.jsonl");
+
+        let mut store = new_entity_store().with_journal(&journal).unwrap();
+        store
+            .update(registration_message(
+                "device/child1//",
+                json!({"@type": "child-device", "@id": "child-1", "name": "Child 1"}),
+            ))
+            .unwrap();
+        store
+            .update(registration_message(
+                "device/child2//",
+                json!({"@type": "child-device", "@parent": "device/child1//"}),
+            ))
+            .unwrap();
+        store
+            .update(registration_message(
+                "device/child2/service/collectd",
+                json!({"@type": "service"}),
+            ))
+            .unwrap();
+        store
+            .update(registration_message(
+                "device/child3//",
+                json!({"@type": "child-device"}),
+            ))
+            .unwrap();
+        store.deregister_entity(&"device/child3//".parse().unwrap());
+        drop(store);
+
+        let store = new_entity_store().with_journal(&journal).unwrap();
+        let child1 = store.get(&"device/child1//".parse().unwrap()).unwrap();
+        assert_eq!(child1.external_id.as_ref(), "child-1");
+        assert_eq!(child1.other.get("name"), Some(&json!("Child 1")));
+        assert_eq!(
+            store.ancestors_external_ids(&"device/child2/service/collectd".parse().unwrap()),
+            Ok(vec![
+                "device:child2".to_string(),
+                "child-1".to_string(),
+                "test-device".to_string()
+            ])
+        );
+        assert!(store.get(&"device/child3//".parse().unwrap()).is_none());
+
+        // The journal has been compacted, keeping only the registered entities
+        let content = std::fs::read_to_string(&journal).unwrap();
+        assert_eq!(content.lines().count(), 4);
+        assert!(!content.contains("device/child3//"));
+    }
+
+    #[test]
+    fn auto_register_entities_matching_a_custom_topic_scheme() {
+        let schemes: TopicSchemes = toml::from_str(
+            r#"
+[[scheme]]
+name = "factory"
+
+[[scheme.entity]]
+topic_id = "site/{machine}//"
+type = "child-device"
+
+[[scheme.entity]]
+topic_id = "site/{machine}/{sensor}/"
+type = "service"
+"#,
+        )
+        .unwrap();
+        let mut store = new_entity_store().with_topic_schemes(schemes);
+
+        let sensor_topic_id = EntityTopicId::from_str("site/press7/temperature/").unwrap();
+        let res = store.auto_register_entity(&sensor_topic_id).unwrap();
+        assert_eq!(
+            res,
+            [
+                EntityRegistrationMessage {
+                    topic_id: EntityTopicId::from_str("site/press7//").unwrap(),
+                    r#type: EntityType::ChildDevice,
+                    external_id: Some("site:press7".into()),
+                    parent: Some(EntityTopicId::default_main_device()),
+                    other: json!({ "name": "press7" }).as_object().unwrap().to_owned(),
+                },
+                EntityRegistrationMessage {
+                    topic_id: sensor_topic_id.clone(),
+                    r#type: EntityType::Service,
+                    external_id: Some("site:press7:temperature".into()),
+                    parent: Some(EntityTopicId::from_str("site/press7//").unwrap()),
+                    other: json!({ "name": "temperature", "type": "service" })
+                        .as_object()
+                        .unwrap()
+                        .to_owned(),
+                }
+            ]
+        );
+        assert_eq!(
+            store.ancestors(&sensor_topic_id).unwrap(),
+            ["site/press7//", "device/main//"]
+        );
+
+        // The ancestors already registered are not registered twice
+        let other_sensor_topic_id = EntityTopicId::from_str("site/press7/pressure/").unwrap();
+        let res = store.auto_register_entity(&other_sensor_topic_id).unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].topic_id, other_sensor_topic_id);
+    }
+
+    fn registration_message(topic_id: &str, payload: JsonValue) -> EntityRegistrationMessage {
+        EntityRegistrationMessage::from_json(topic_id.parse().unwrap(), payload).unwrap()
+    }
+
     fn new_entity_store() -> EntityStore {
         EntityStore::with_main_device(
             EntityRegistrationMessage {
@@ -0,0 +1,737 @@
+//! Custom topic schemes, used to auto-register entities that don't follow the default topic scheme.
+//!
+//! The default topic scheme `device/<device-id>/service/<service-id>` tells on its own
+//! how an entity relates to the main device. This is not the case for brownfield devices
+//! that use their own layout, e.g. `site/<line>/<machine>/<sensor>`.
+//!
+//! A topic scheme declares the entity hierarchy of such a layout,
+//! with one template per level, from the top-most entity down to the leaves.
+//! The named segments of these templates, as `{line}`, are bound to the actual topic segments
+//! when an entity topic id matches one of the levels.
+//!
+//! ```toml
+//! [[scheme]]
+//! name = "factory"
+//!
+//! [[scheme.entity]]
+//! topic_id = "site/{line}//"
+//! type = "child-device"
+//! name = "Line {line}"
+//!
+//! [[scheme.entity]]
+//! topic_id = "site/{line}/{machine}/"
+//! type = "child-device"
+//! external_id = "{line}-{machine}"
+//!
+//! [[scheme.entity]]
+//! topic_id = "site/{line}/{machine}/{sensor}"
+//! type = "service"
+//! ```
+//!
+//! With this scheme, a measurement published on `te/site/line1/press7/temperature/m/env`
+//! leads to the registration of `site/line1//` as a child device of the main device,
+//! of `site/line1/press7/` as a child device of `site/line1//`,
+//! and of `site/line1/press7/temperature` as a service of `site/line1/press7/`.
+//!
+//! A topic scheme can also translate the topics used by brownfield publishers,
+//! which are not under the thin-edge root, into canonical thin-edge topics:
+//!
+//! ```toml
+//! [[scheme.topic]]
+//! topic = "site/{line}/{machine}/{sensor}"
+//! entity = "site/{line}/{machine}/{sensor}"
+//! channel = "m/{sensor}"
+//! ```
+//!
+//! With this translation rule, a message published on `site/line1/press7/temperature`
+//! is re-published unchanged on `te/site/line1/press7/temperature/m/temperature`.
+
+use crate::entity_store::EntityRegistrationMessage;
+use crate::entity_store::EntityType;
+use crate::mqtt_topics::Channel;
+use crate::mqtt_topics::EntityTopicId;
+use crate::mqtt_topics::ENTITY_ID_SEGMENTS;
+use serde::Deserialize;
+use serde::Deserializer;
+use std::collections::HashMap;
+
+/// The values bound to the named segments of a template
+type Bindings = HashMap<String, String>;
+
+/// A set of topic schemes, tried in order
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TopicSchemes {
+    #[serde(default, rename = "scheme")]
+    schemes: Vec<TopicScheme>,
+}
+
+impl TopicSchemes {
+    pub fn new(schemes: Vec<TopicScheme>) -> Self {
+        TopicSchemes { schemes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.schemes.is_empty()
+    }
+
+    /// Returns the registration messages of the entity with the given topic id and of its ancestors,
+    /// as inferred by the first matching scheme, parents first.
+    ///
+    /// The top-most entity is given no parent, and the external ids are only set if templated.
+    pub fn infer_registrations(
+        &self,
+        topic_id: &EntityTopicId,
+    ) -> Option<Vec<EntityRegistrationMessage>> {
+        self.schemes
+            .iter()
+            .find_map(|scheme| scheme.infer_registrations(topic_id))
+    }
+
+    /// Returns the filters of the brownfield topics to be translated into thin-edge topics
+    pub fn topic_filters(&self) -> Vec<String> {
+        self.schemes
+            .iter()
+            .flat_map(|scheme| scheme.translations.iter())
+            .map(|translation| translation.topic.topic_filter())
+            .collect()
+    }
+
+    /// Translates a brownfield topic into the entity topic id and channel of the thin-edge topic,
+    /// using the first matching translation rule.
+    pub fn translate(&self, topic: &str) -> Option<(EntityTopicId, Channel)> {
+        self.schemes
+            .iter()
+            .flat_map(|scheme| scheme.translations.iter())
+            .find_map(|translation| translation.translate(topic))
+    }
+}
+
+/// A topic scheme, defining the templates of the entities from the top-most down to the leaves
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawTopicScheme")]
+pub struct TopicScheme {
+    name: String,
+    levels: Vec<EntityTemplate>,
+    translations: Vec<TopicTranslation>,
+}
+
+#[derive(Deserialize)]
+struct RawTopicScheme {
+    name: String,
+    #[serde(default, rename = "entity")]
+    entities: Vec<EntityTemplate>,
+    #[serde(default, rename = "topic")]
+    topics: Vec<TopicTranslation>,
+}
+
+impl TryFrom<RawTopicScheme> for TopicScheme {
+    type Error = TopicSchemeError;
+
+    fn try_from(raw: RawTopicScheme) -> Result<Self, Self::Error> {
+        TopicScheme::try_new(raw.name, raw.entities, raw.topics)
+    }
+}
+
+impl TopicScheme {
+    /// Builds a topic scheme, checking that the templates of each level can be rendered
+    /// from the segments bound by the topic id of this level,
+    /// and that the templates of each translation rule can be rendered from the segments of the translated topic.
+    pub fn try_new(
+        name: String,
+        levels: Vec<EntityTemplate>,
+        translations: Vec<TopicTranslation>,
+    ) -> Result<Self, TopicSchemeError> {
+        let invalid = |reason: String| TopicSchemeError::InvalidScheme {
+            scheme: name.clone(),
+            reason,
+        };
+
+        if levels.is_empty() && translations.is_empty() {
+            return Err(invalid("no entity nor topic is defined".to_string()));
+        }
+
+        for translation in translations.iter() {
+            let bound_variables = translation.topic.variables();
+            let used_variables = translation
+                .entity
+                .variables()
+                .into_iter()
+                .chain(translation.channel.variables());
+            for variable in used_variables {
+                if !bound_variables.contains(&variable) {
+                    return Err(invalid(format!(
+                        "{{{variable}}} is not bound by {}",
+                        translation.topic.template
+                    )));
+                }
+            }
+        }
+
+        for (depth, level) in levels.iter().enumerate() {
+            let has_children = depth + 1 < levels.len();
+            match level.r#type {
+                EntityType::MainDevice => {
+                    return Err(invalid(format!(
+                        "{} cannot be the main device",
+                        level.topic_id.0.template
+                    )))
+                }
+                EntityType::Service if has_children => {
+                    return Err(invalid(format!(
+                        "the service {} cannot have children",
+                        level.topic_id.0.template
+                    )))
+                }
+                _ => {}
+            }
+
+            let bound_variables = level.topic_id.variables();
+            let parent_variables = depth
+                .checked_sub(1)
+                .map(|parent| levels[parent].topic_id.variables())
+                .unwrap_or_default();
+            let used_variables = level
+                .external_id
+                .iter()
+                .chain(level.name.iter())
+                .flat_map(Template::variables)
+                .chain(parent_variables);
+            for variable in used_variables {
+                if !bound_variables.contains(&variable) {
+                    return Err(invalid(format!(
+                        "{{{variable}}} is not bound by {}",
+                        level.topic_id.0.template
+                    )));
+                }
+            }
+        }
+
+        Ok(TopicScheme {
+            name,
+            levels,
+            translations,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn infer_registrations(
+        &self,
+        topic_id: &EntityTopicId,
+    ) -> Option<Vec<EntityRegistrationMessage>> {
+        let (depth, bindings) = self
+            .levels
+            .iter()
+            .enumerate()
+            .find_map(|(depth, level)| level.topic_id.matches(topic_id).map(|b| (depth, b)))?;
+
+        let mut registrations: Vec<EntityRegistrationMessage> = vec![];
+        for level in &self.levels[..=depth] {
+            let parent = registrations.last().map(|parent| parent.topic_id.clone());
+            registrations.push(level.render(&bindings, parent)?);
+        }
+        Some(registrations)
+    }
+}
+
+/// The template of the entities at some level of a topic scheme
+#[derive(Debug, Clone, Deserialize)]
+pub struct EntityTemplate {
+    pub topic_id: TopicIdTemplate,
+
+    #[serde(rename = "type", deserialize_with = "deserialize_entity_type")]
+    pub r#type: EntityType,
+
+    /// The external id of the entity, which defaults to the one derived from its topic id
+    #[serde(default)]
+    pub external_id: Option<Template>,
+
+    /// The name of the entity, which defaults to the value of the last named segment of its topic id
+    #[serde(default)]
+    pub name: Option<Template>,
+}
+
+impl EntityTemplate {
+    fn render(
+        &self,
+        bindings: &Bindings,
+        parent: Option<EntityTopicId>,
+    ) -> Option<EntityRegistrationMessage> {
+        let topic_id = self.topic_id.render(bindings)?;
+        let name = match &self.name {
+            Some(name) => name.render(bindings)?,
+            None => self
+                .topic_id
+                .default_name(bindings)
+                .unwrap_or_else(|| topic_id.to_string()),
+        };
+
+        let mut registration = EntityRegistrationMessage::new_custom(topic_id, self.r#type.clone())
+            .with_other_fragment("name".to_string(), name.into());
+        if let Some(parent) = parent {
+            registration = registration.with_parent(parent);
+        }
+        if let Some(external_id) = &self.external_id {
+            registration = registration.with_external_id(external_id.render(bindings)?.into());
+        }
+        Some(registration)
+    }
+}
+
+fn deserialize_entity_type<'de, D>(deserializer: D) -> Result<EntityType, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let entity_type = String::deserialize(deserializer)?;
+    entity_type.parse().map_err(serde::de::Error::custom)
+}
+
+/// A rule translating brownfield topics into thin-edge topics
+#[derive(Debug, Clone, Deserialize)]
+pub struct TopicTranslation {
+    /// The template of the translated topics, e.g. `site/{line}/{machine}/{sensor}`
+    pub topic: TopicTemplate,
+
+    /// The template of the entity topic id of the thin-edge topic, e.g. `site/{line}/{machine}/{sensor}`
+    pub entity: TopicIdTemplate,
+
+    /// The template of the channel of the thin-edge topic, e.g. `m/{sensor}`
+    pub channel: Template,
+}
+
+impl TopicTranslation {
+    fn translate(&self, topic: &str) -> Option<(EntityTopicId, Channel)> {
+        let bindings = self.topic.matches(topic)?;
+        let entity = self.entity.render(&bindings)?;
+        let channel = self.channel.render(&bindings)?.parse().ok()?;
+        Some((entity, channel))
+    }
+}
+
+/// The template of an MQTT topic, e.g. `site/{line}/{machine}/{sensor}`
+///
+/// Each segment is either a literal, possibly empty, or a named segment matching any non-empty value.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TopicTemplate {
+    template: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Named(String),
+}
+
+impl TryFrom<String> for TopicTemplate {
+    type Error = TopicSchemeError;
+
+    fn try_from(template: String) -> Result<Self, Self::Error> {
+        let invalid = |reason: &str| TopicSchemeError::InvalidTemplate {
+            template: template.clone(),
+            reason: reason.to_string(),
+        };
+
+        let mut segments = vec![];
+        for segment in template.split('/') {
+            if let Some(name) = segment
+                .strip_prefix('{')
+                .and_then(|name| name.strip_suffix('}'))
+            {
+                if !is_valid_name(name) {
+                    return Err(invalid("invalid segment name"));
+                }
+                segments.push(Segment::Named(name.to_string()));
+            } else if segment.contains(['{', '}', '+', '#']) {
+                return Err(invalid("a named segment must span a whole topic segment"));
+            } else {
+                segments.push(Segment::Literal(segment.to_string()));
+            }
+        }
+
+        Ok(TopicTemplate { template, segments })
+    }
+}
+
+impl TopicTemplate {
+    fn variables(&self) -> Vec<&str> {
+        self.segments
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Named(name) => Some(name.as_str()),
+                Segment::Literal(_) => None,
+            })
+            .collect()
+    }
+
+    /// Returns the values bound to the named segments, if the topic matches this template
+    fn matches(&self, topic: &str) -> Option<Bindings> {
+        let values: Vec<&str> = topic.split('/').collect();
+        if values.len() != self.segments.len() {
+            return None;
+        }
+
+        let mut bindings = Bindings::new();
+        for (segment, value) in self.segments.iter().zip(values) {
+            match segment {
+                Segment::Literal(literal) if literal == value => {}
+                Segment::Named(name) if !value.is_empty() => {
+                    let bound = bindings.entry(name.clone()).or_insert(value.to_string());
+                    if bound != value {
+                        return None;
+                    }
+                }
+                _ => return None,
+            }
+        }
+        Some(bindings)
+    }
+
+    fn render(&self, bindings: &Bindings) -> Option<String> {
+        let segments: Option<Vec<&str>> = self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(literal) => Some(literal.as_str()),
+                Segment::Named(name) => bindings.get(name).map(String::as_str),
+            })
+            .collect();
+        Some(segments?.join("/"))
+    }
+
+    /// The MQTT topic filter matching the topics of this template
+    fn topic_filter(&self) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(literal) => literal.as_str(),
+                Segment::Named(_) => "+",
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// The template of an entity topic id, e.g. `site/{line}/{machine}/`
+///
+/// This is a [TopicTemplate] with exactly 4 segments.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TopicIdTemplate(TopicTemplate);
+
+impl TryFrom<String> for TopicIdTemplate {
+    type Error = TopicSchemeError;
+
+    fn try_from(template: String) -> Result<Self, Self::Error> {
+        let template = TopicTemplate::try_from(template)?;
+        if template.segments.len() != ENTITY_ID_SEGMENTS {
+            return Err(TopicSchemeError::InvalidTemplate {
+                template: template.template,
+                reason: "expecting 4 topic segments".to_string(),
+            });
+        }
+        Ok(TopicIdTemplate(template))
+    }
+}
+
+impl TopicIdTemplate {
+    fn variables(&self) -> Vec<&str> {
+        self.0.variables()
+    }
+
+    /// Returns the values bound to the named segments, if the topic id matches this template
+    fn matches(&self, topic_id: &EntityTopicId) -> Option<Bindings> {
+        self.0.matches(topic_id.as_str())
+    }
+
+    fn render(&self, bindings: &Bindings) -> Option<EntityTopicId> {
+        self.0.render(bindings)?.parse().ok()
+    }
+
+    fn default_name(&self, bindings: &Bindings) -> Option<String> {
+        self.variables()
+            .last()
+            .and_then(|name| bindings.get(*name))
+            .cloned()
+    }
+}
+
+/// A string template with named placeholders, e.g. `{line}-{machine}`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Template(String);
+
+impl TryFrom<String> for Template {
+    type Error = TopicSchemeError;
+
+    fn try_from(template: String) -> Result<Self, Self::Error> {
+        let mut rest = template.as_str();
+        while let Some(start) = rest.find(['{', '}']) {
+            let name = rest[start..]
+                .strip_prefix('{')
+                .and_then(|tail| tail.split_once('}'));
+            match name {
+                Some((name, tail)) if is_valid_name(name) => rest = tail,
+                _ => {
+                    return Err(TopicSchemeError::InvalidTemplate {
+                        template: template.clone(),
+                        reason: "unbalanced or invalid placeholder".to_string(),
+                    })
+                }
+            }
+        }
+        Ok(Template(template))
+    }
+}
+
+impl Template {
+    fn variables(&self) -> Vec<&str> {
+        self.0
+            .split('{')
+            .skip(1)
+            .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+            .collect()
+    }
+
+    fn render(&self, bindings: &Bindings) -> Option<String> {
+        let mut rendered = String::new();
+        let mut parts = self.0.split('{');
+        rendered.push_str(parts.next().unwrap_or_default());
+        for part in parts {
+            let (name, tail) = part.split_once('}')?;
+            rendered.push_str(bindings.get(name)?);
+            rendered.push_str(tail);
+        }
+        Some(rendered)
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum TopicSchemeError {
+    #[error("Invalid template {template:?}: {reason}")]
+    InvalidTemplate { template: String, reason: String },
+
+    #[error("Invalid topic scheme {scheme:?}: {reason}")]
+    InvalidScheme { scheme: String, reason: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const FACTORY_SCHEME: &str = r#"
+[[scheme]]
+name = "factory"
+
+[[scheme.entity]]
+topic_id = "site/{line}//"
+type = "child-device"
+name = "Line {line}"
+
+[[scheme.entity]]
+topic_id = "site/{line}/{machine}/"
+type = "child-device"
+external_id = "{line}-{machine}"
+
+[[scheme.entity]]
+topic_id = "site/{line}/{machine}/{sensor}"
+type = "service"
+"#;
+
+    #[test]
+    fn infer_the_hierarchy_of_an_entity() {
+        let schemes: TopicSchemes = toml::from_str(FACTORY_SCHEME).unwrap();
+
+        let registrations = schemes
+            .infer_registrations(&"site/line1/press7/temperature".parse().unwrap())
+            .unwrap();
+
+        assert_eq!(
+            registrations,
+            vec![
+                EntityRegistrationMessage::new_custom(
+                    "site/line1//".parse().unwrap(),
+                    EntityType::ChildDevice
+                )
+                .with_other_fragment("name".to_string(), json!("Line line1")),
+                EntityRegistrationMessage::new_custom(
+                    "site/line1/press7/".parse().unwrap(),
+                    EntityType::ChildDevice
+                )
+                .with_parent("site/line1//".parse().unwrap())
+                .with_external_id("line1-press7".into())
+                .with_other_fragment("name".to_string(), json!("press7")),
+                EntityRegistrationMessage::new_custom(
+                    "site/line1/press7/temperature".parse().unwrap(),
+                    EntityType::Service
+                )
+                .with_parent("site/line1/press7/".parse().unwrap())
+                .with_other_fragment("name".to_string(), json!("temperature")),
+            ]
+        );
+    }
+
+    #[test]
+    fn infer_the_hierarchy_of_an_intermediate_entity() {
+        let schemes: TopicSchemes = toml::from_str(FACTORY_SCHEME).unwrap();
+
+        let registrations = schemes
+            .infer_registrations(&"site/line1/press7/".parse().unwrap())
+            .unwrap();
+
+        let topic_ids: Vec<_> = registrations
+            .iter()
+            .map(|registration| registration.topic_id.as_str())
+            .collect();
+        assert_eq!(topic_ids, vec!["site/line1//", "site/line1/press7/"]);
+    }
+
+    #[test]
+    fn topic_ids_not_matching_any_scheme_are_ignored() {
+        let schemes: TopicSchemes = toml::from_str(FACTORY_SCHEME).unwrap();
+
+        for topic_id in ["device/child1//", "site///", "plant/line1/press7/"] {
+            assert_eq!(
+                schemes.infer_registrations(&topic_id.parse().unwrap()),
+                None,
+                "{topic_id}"
+            );
+        }
+    }
+
+    #[test]
+    fn templates_must_be_bound_by_the_topic_id() {
+        let error = toml::from_str::<TopicSchemes>(
+            r#"
+[[scheme]]
+name = "factory"
+
+[[scheme.entity]]
+topic_id = "site/{line}//"
+type = "child-device"
+external_id = "{machine}"
+"#,
+        )
+        .unwrap_err();
+
+        assert!(
+            error.to_string().contains("{machine} is not bound"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn services_cannot_have_children() {
+        let error = toml::from_str::<TopicSchemes>(
+            r#"
+[[scheme]]
+name = "factory"
+
+[[scheme.entity]]
+topic_id = "site/{line}//"
+type = "service"
+
+[[scheme.entity]]
+topic_id = "site/{line}/{machine}/"
+type = "child-device"
+"#,
+        )
+        .unwrap_err();
+
+        assert!(
+            error.to_string().contains("cannot have children"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn translate_brownfield_topics() {
+        let schemes: TopicSchemes = toml::from_str(
+            r#"
+[[scheme]]
+name = "factory"
+
+[[scheme.topic]]
+topic = "site/{line}/{machine}/{sensor}"
+entity = "site/{line}/{machine}/{sensor}"
+channel = "m/{sensor}"
+
+[[scheme.topic]]
+topic = "site/{line}/{machine}/alarms/{alarm}"
+entity = "site/{line}/{machine}/"
+channel = "a/{alarm}"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            schemes.topic_filters(),
+            vec!["site/+/+/+", "site/+/+/alarms/+"]
+        );
+        assert_eq!(
+            schemes.translate("site/line1/press7/temperature"),
+            Some((
+                "site/line1/press7/temperature".parse().unwrap(),
+                Channel::Measurement {
+                    measurement_type: "temperature".to_string()
+                }
+            ))
+        );
+        assert_eq!(
+            schemes.translate("site/line1/press7/alarms/overheat"),
+            Some((
+                "site/line1/press7/".parse().unwrap(),
+                Channel::Alarm {
+                    alarm_type: "overheat".to_string()
+                }
+            ))
+        );
+        for topic in [
+            "site/line1/press7",
+            "site/line1//temperature",
+            "plant/a/b/c",
+        ] {
+            assert_eq!(schemes.translate(topic), None, "{topic}");
+        }
+    }
+
+    #[test]
+    fn translations_must_be_bound_by_the_topic() {
+        let error = toml::from_str::<TopicSchemes>(
+            r#"
+[[scheme]]
+name = "factory"
+
+[[scheme.topic]]
+topic = "site/{line}/{machine}"
+entity = "site/{line}/{machine}/"
+channel = "m/{sensor}"
+"#,
+        )
+        .unwrap_err();
+
+        assert!(
+            error.to_string().contains("{sensor} is not bound"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn named_segments_must_span_whole_segments() {
+        assert!(TopicIdTemplate::try_from("site/line-{line}//".to_string()).is_err());
+        assert!(TopicIdTemplate::try_from("site/{line}/".to_string()).is_err());
+        assert!(Template::try_from("{line".to_string()).is_err());
+        assert!(Template::try_from("line}".to_string()).is_err());
+    }
+}
@@ -73,6 +73,9 @@ pub enum SoftwareError {
         name: SoftwareName,
     },
 
+    #[error("Invalid dependencies between software updates: {reason}")]
+    InvalidDependencies { reason: String },
+
     #[error("Unknown software type: {software_type:?}")]
     UnknownSoftwareType { software_type: SoftwareType },
 
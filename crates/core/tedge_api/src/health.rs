@@ -7,6 +7,8 @@ use log::error;
 use mqtt_channel::Message;
 use mqtt_channel::Topic;
 use serde_json::json;
+use serde_json::Map;
+use serde_json::Value as JsonValue;
 use std::process;
 use std::sync::Arc;
 
@@ -46,6 +48,11 @@ impl ServiceHealthTopic {
     }
 
     pub fn up_message(&self) -> Message {
+        self.up_message_with_details(Map::new())
+    }
+
+    /// Returns an up message extended with service specific details
+    pub fn up_message_with_details(&self, details: Map<String, JsonValue>) -> Message {
         let now = WallClock.now();
         let timestamp = now
             .format(&time::format_description::well_known::Rfc3339)
@@ -56,12 +63,15 @@ impl ServiceHealthTopic {
                 format!("{}", now)
             });
 
-        let health_status = json!({
+        let mut health_status = json!({
             "status": "up",
             "pid": process::id(),
             "time": timestamp
-        })
-        .to_string();
+        });
+        if let JsonValue::Object(status) = &mut health_status {
+            status.extend(details);
+        }
+        let health_status = health_status.to_string();
 
         let response_topic_health = Topic::new_unchecked(self.as_str());
 
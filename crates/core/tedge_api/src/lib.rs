@@ -1,7 +1,9 @@
 pub mod alarm;
 pub mod builder;
 pub mod data;
+pub mod entity_journal;
 pub mod entity_store;
+pub mod entity_topic_scheme;
 pub mod error;
 pub mod event;
 pub mod group;
@@ -252,10 +252,35 @@ pub struct SoftwareUpdateCommandPayload {
 
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub failures: Vec<SoftwareRequestResponseSoftwareList>,
+
+    /// Progress reported by the plugins while the update is executing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<SoftwareUpdateProgress>,
+
+    /// When set, the updates already applied are reverted if a later one fails
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub rollback: bool,
 }
 
 impl<'a> Jsonify<'a> for SoftwareUpdateCommandPayload {}
 
+/// Progress of a software update, as reported by a plugin implementing the version 2 of the plugin API
+#[derive(Debug, Clone, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SoftwareUpdateProgress {
+    #[serde(rename = "type")]
+    pub plugin_type: SoftwareType,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module: Option<SoftwareName>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub percent: Option<u8>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phase: Option<String>,
+}
+
 impl CommandPayload for SoftwareUpdateCommandPayload {
     fn operation_type() -> OperationType {
         OperationType::SoftwareUpdate
@@ -328,21 +353,23 @@ impl SoftwareUpdateCommand {
             .find(|&items| items.plugin_type == module_type)
         {
             for item in items.modules.iter() {
-                let module = SoftwareModule {
-                    module_type: Some(module_type.to_string()),
-                    name: item.name.clone(),
-                    version: item.version.clone(),
-                    url: item.url.clone(),
-                    file_path: None,
-                };
-                match item.action {
-                    None => {}
-                    Some(SoftwareModuleAction::Install) => {
-                        updates.push(SoftwareModuleUpdate::install(module));
-                    }
-                    Some(SoftwareModuleAction::Remove) => {
-                        updates.push(SoftwareModuleUpdate::remove(module));
-                    }
+                if let Some(update) = item.update(module_type) {
+                    updates.push(update);
+                }
+            }
+        }
+
+        updates
+    }
+
+    /// All the updates of this command, in the request order, along with the modules each update depends on
+    pub fn updates_with_dependencies(&self) -> Vec<(SoftwareModuleUpdate, Vec<SoftwareModuleRef>)> {
+        let mut updates = vec![];
+
+        for items in self.payload.update_list.iter() {
+            for item in items.modules.iter() {
+                if let Some(update) = item.update(&items.plugin_type) {
+                    updates.push((update, item.depends_on.clone()));
                 }
             }
         }
@@ -350,6 +377,21 @@ impl SoftwareUpdateCommand {
         updates
     }
 
+    /// Return true if some of the updates depend on others
+    pub fn has_dependencies(&self) -> bool {
+        self.payload
+            .update_list
+            .iter()
+            .flat_map(|items| items.modules.iter())
+            .any(|item| !item.depends_on.is_empty())
+    }
+
+    /// Attach the progress reported by a plugin to this command
+    pub fn with_progress(mut self, progress: SoftwareUpdateProgress) -> Self {
+        self.payload.progress = Some(progress);
+        self
+    }
+
     pub fn add_errors(&mut self, plugin_type: &str, errors: Vec<SoftwareError>) {
         self.payload
             .failures
@@ -400,6 +442,50 @@ pub struct SoftwareModuleItem {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reason: Option<String>,
+
+    /// Modules, possibly of other types, that must be updated before this one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<SoftwareModuleRef>,
+}
+
+/// Reference to a module of a software update.
+///
+/// When no type is given, the reference matches the modules of any type with that name.
+#[derive(Debug, Clone, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SoftwareModuleRef {
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub module_type: Option<SoftwareType>,
+    pub name: SoftwareName,
+}
+
+impl SoftwareModuleRef {
+    /// Check if this reference matches the given module
+    pub fn matches(&self, module_type: &str, name: &str) -> bool {
+        self.name == name
+            && self
+                .module_type
+                .as_ref()
+                .map_or(true, |expected| expected == module_type)
+    }
+}
+
+impl SoftwareModuleItem {
+    /// The update requested by this item, if any
+    fn update(&self, module_type: &str) -> Option<SoftwareModuleUpdate> {
+        let module = SoftwareModule {
+            module_type: Some(module_type.to_string()),
+            name: self.name.clone(),
+            version: self.version.clone(),
+            url: self.url.clone(),
+            file_path: None,
+        };
+        match self.action {
+            None => None,
+            Some(SoftwareModuleAction::Install) => Some(SoftwareModuleUpdate::install(module)),
+            Some(SoftwareModuleAction::Remove) => Some(SoftwareModuleUpdate::remove(module)),
+        }
+    }
 }
 
 impl From<SoftwareModule> for SoftwareModuleItem {
@@ -410,6 +496,7 @@ impl From<SoftwareModule> for SoftwareModuleItem {
             url: module.url,
             action: None,
             reason: None,
+            depends_on: vec![],
         }
     }
 }
@@ -423,6 +510,7 @@ impl From<SoftwareModuleUpdate> for SoftwareModuleItem {
                 url: module.url,
                 action: Some(SoftwareModuleAction::Install),
                 reason: None,
+                depends_on: vec![],
             },
             SoftwareModuleUpdate::Remove { module } => SoftwareModuleItem {
                 name: module.name,
@@ -430,6 +518,7 @@ impl From<SoftwareModuleUpdate> for SoftwareModuleItem {
                 url: module.url,
                 action: Some(SoftwareModuleAction::Remove),
                 reason: None,
+                depends_on: vec![],
             },
         }
     }
@@ -444,6 +533,7 @@ impl From<SoftwareError> for Option<SoftwareModuleItem> {
                 url: module.url,
                 action: Some(SoftwareModuleAction::Install),
                 reason: Some(reason),
+                depends_on: vec![],
             }),
             SoftwareError::Remove { module, reason } => Some(SoftwareModuleItem {
                 name: module.name,
@@ -451,6 +541,7 @@ impl From<SoftwareError> for Option<SoftwareModuleItem> {
                 url: module.url,
                 action: Some(SoftwareModuleAction::Remove),
                 reason: Some(reason),
+                depends_on: vec![],
             }),
             _ => None,
         }
@@ -777,6 +868,7 @@ mod tests {
             action: Some(SoftwareModuleAction::Install),
             url: None,
             reason: None,
+            depends_on: vec![],
         };
 
         let debian_module2 = SoftwareModuleItem {
@@ -785,6 +877,7 @@ mod tests {
             action: Some(SoftwareModuleAction::Install),
             url: None,
             reason: None,
+            depends_on: vec![],
         };
 
         let debian_list = SoftwareRequestResponseSoftwareList {
@@ -798,6 +891,7 @@ mod tests {
             action: Some(SoftwareModuleAction::Remove),
             url: Some("test.com".into()),
             reason: None,
+            depends_on: vec![],
         };
 
         let docker_list = SoftwareRequestResponseSoftwareList {
@@ -809,6 +903,8 @@ mod tests {
             status: CommandStatus::Init,
             update_list: vec![debian_list, docker_list],
             failures: vec![],
+            progress: None,
+            rollback: false,
         };
 
         let expected_json = r#"{"status":"init","updateList":[{"type":"debian","modules":[{"name":"debian1","version":"0.0.1","action":"install"},{"name":"debian2","version":"0.0.2","action":"install"}]},{"type":"docker","modules":[{"name":"docker1","version":"0.0.1","url":"test.com","action":"remove"}]}]}"#;
@@ -821,6 +917,53 @@ mod tests {
         assert_eq!(parsed_request, request);
     }
 
+    #[test]
+    fn serde_software_update_progress() {
+        let json = r#"{"status":"executing","updateList":[],"progress":{"type":"apt","module":"nginx","percent":42,"phase":"download"}}"#;
+        let payload = SoftwareUpdateCommandPayload::from_json(json).unwrap();
+
+        assert_eq!(
+            payload.progress,
+            Some(SoftwareUpdateProgress {
+                plugin_type: "apt".into(),
+                module: Some("nginx".into()),
+                percent: Some(42),
+                phase: Some("download".into()),
+            })
+        );
+        assert_eq!(
+            payload.to_json(),
+            r#"{"status":"executing","progress":{"type":"apt","module":"nginx","percent":42,"phase":"download"}}"#
+        );
+    }
+
+    #[test]
+    fn serde_software_update_dependencies_and_rollback() {
+        let json = r#"{"status":"init","rollback":true,"updateList":[{"type":"apt","modules":[{"name":"app","action":"install","dependsOn":[{"type":"container","name":"db"},{"name":"libfoo"}]}]}]}"#;
+        let payload = SoftwareUpdateCommandPayload::from_json(json).unwrap();
+
+        assert!(payload.rollback);
+        assert_eq!(
+            payload.update_list[0].modules[0].depends_on,
+            vec![
+                SoftwareModuleRef {
+                    module_type: Some("container".into()),
+                    name: "db".into(),
+                },
+                SoftwareModuleRef {
+                    module_type: None,
+                    name: "libfoo".into(),
+                },
+            ]
+        );
+        assert!(payload.update_list[0].modules[0].depends_on[0].matches("container", "db"));
+        assert!(!payload.update_list[0].modules[0].depends_on[0].matches("apt", "db"));
+        assert!(payload.update_list[0].modules[0].depends_on[1].matches("apt", "libfoo"));
+
+        let parsed = SoftwareUpdateCommandPayload::from_json(&payload.to_json()).unwrap();
+        assert_eq!(parsed, payload);
+    }
+
     #[test]
     fn serde_custom_command_status() {
         let request = SoftwareListCommandPayload {
@@ -15,7 +15,7 @@ use std::str::FromStr;
 use time::format_description;
 use time::OffsetDateTime;
 
-const ENTITY_ID_SEGMENTS: usize = 4;
+pub(crate) const ENTITY_ID_SEGMENTS: usize = 4;
 
 /// The MQTT topics are represented by three distinct groups:
 /// - a root prefix, used by all the topics
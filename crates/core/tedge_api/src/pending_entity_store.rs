@@ -4,40 +4,93 @@ use crate::mqtt_topics::EntityTopicId;
 use crate::mqtt_topics::MqttSchema;
 use crate::ring_buffer::RingBuffer;
 use log::error;
+use log::warn;
 use mqtt_channel::Message as MqttMessage;
+use serde::Serialize;
 use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
 
 /// A store for all the entities for which data messages are received before
 /// its registration message itself is received.
 /// It also stores all the child device registration messages received before
 /// their parents themselves are registered, including their data.
+///
+/// Both the telemetry and the metadata caches are bounded in size and time,
+/// the messages dropped to enforce these limits being accounted in [DroppedMessages].
 pub struct PendingEntityStore {
     mqtt_schema: MqttSchema,
+    config: PendingEntityStoreConfig,
     // This orphans map is keyed by the unregistered parent topic id to their children
     orphans: HashMap<EntityTopicId, Vec<EntityTopicId>>,
     entities: HashMap<EntityTopicId, PendingEntityCache>,
-    pub telemetry_cache: RingBuffer<MqttMessage>,
+    telemetry_cache: RingBuffer<(Instant, MqttMessage)>,
+    dropped: DroppedMessages,
+}
+
+/// The limits enforced on the caches of a [PendingEntityStore]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PendingEntityStoreConfig {
+    /// Maximum number of telemetry messages cached for all the pending entities
+    pub telemetry_cache_size: usize,
+
+    /// Time after which a cached telemetry message is dropped
+    pub telemetry_cache_ttl: Duration,
+
+    /// Maximum number of registration and metadata messages cached for all the pending entities
+    pub metadata_cache_size: usize,
+
+    /// Time after which a pending entity, along with its cached metadata, is evicted
+    pub orphan_ttl: Duration,
+}
+
+impl Default for PendingEntityStoreConfig {
+    fn default() -> Self {
+        PendingEntityStoreConfig {
+            telemetry_cache_size: 100,
+            telemetry_cache_ttl: Duration::from_secs(3600),
+            metadata_cache_size: 1000,
+            orphan_ttl: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// Counters of the messages dropped by a [PendingEntityStore] to stay within its limits
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize)]
+pub struct DroppedMessages {
+    /// Telemetry messages dropped because the cache was full or because they expired
+    pub telemetry: u64,
+    /// Metadata messages dropped because the cache was full or because their entity was evicted
+    pub metadata: u64,
+    /// Registration messages dropped because the cache was full or because they expired
+    pub registrations: u64,
 }
 
 /// A cache of all the data messages received before the entity itself is registered.
 /// The telemetry messages are stored in a bounded buffer,
 /// that replaces older values with newer values when full,
 /// to make sure that only the most recent ones are cached to prevent unbounded growth.
-/// Other metadata messages which are are stored in an unbounded vector,
-/// as these are more critical data, none of which can be dropped.
+/// Other metadata messages are more critical data and are never replaced by newer ones:
+/// these are rather rejected when the cache is full.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct PendingEntityCache {
     pub reg_message: Option<EntityRegistrationMessage>,
     pub metadata: Vec<MqttMessage>,
+    since: Instant,
 }
 
 impl PendingEntityCache {
-    fn new() -> Self {
+    fn new(since: Instant) -> Self {
         PendingEntityCache {
             reg_message: None,
             metadata: vec![],
+            since,
         }
     }
+
+    fn len(&self) -> usize {
+        self.metadata.len() + usize::from(self.reg_message.is_some())
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -48,15 +101,22 @@ pub struct PendingEntityData {
 }
 
 impl PendingEntityStore {
-    pub fn new(mqtt_schema: MqttSchema, telemetry_cache_size: usize) -> Self {
+    pub fn new(mqtt_schema: MqttSchema, config: PendingEntityStoreConfig) -> Self {
         Self {
             mqtt_schema,
+            config,
             orphans: HashMap::new(),
             entities: HashMap::new(),
-            telemetry_cache: RingBuffer::new(telemetry_cache_size),
+            telemetry_cache: RingBuffer::new(config.telemetry_cache_size),
+            dropped: DroppedMessages::default(),
         }
     }
 
+    /// Returns the number of messages dropped so far to stay within the cache limits
+    pub fn dropped_messages(&self) -> DroppedMessages {
+        self.dropped
+    }
+
     pub fn take_cached_entity_data(
         &mut self,
         reg_message: EntityRegistrationMessage,
@@ -81,7 +141,9 @@ impl PendingEntityStore {
         let mut children = vec![];
         if let Some(direct_children) = self.orphans.remove(entity_tid) {
             for child in direct_children {
-                let pending_entity_cache = self.entities.remove(&child).unwrap();
+                let Some(pending_entity_cache) = self.entities.remove(&child) else {
+                    continue;
+                };
                 let pending_entity_data = self.pending_data_from_cache(pending_entity_cache);
                 children.push(pending_entity_data);
                 children.append(&mut self.take_cached_child_entities_data(&child));
@@ -107,30 +169,30 @@ impl PendingEntityStore {
         let capacity = self.telemetry_cache.capacity();
         let telemetry_cache =
             std::mem::replace(&mut self.telemetry_cache, RingBuffer::new(capacity));
-        for message in telemetry_cache.into_iter() {
+        for (since, message) in telemetry_cache.into_iter() {
             match self.mqtt_schema.entity_channel_of(&message.topic) {
-                Ok((tid, _)) => {
-                    if &tid == entity_tid {
-                        messages.push(message);
-                    } else {
-                        self.telemetry_cache.push(message)
-                    }
+                Ok((tid, _)) if &tid == entity_tid => messages.push(message),
+                _ => {
+                    self.telemetry_cache.push((since, message));
                 }
-                Err(_) => self.telemetry_cache.push(message),
             }
         }
         messages
     }
 
     pub fn cache_early_data_message(&mut self, message: MqttMessage) {
+        let now = Instant::now();
+        self.evict_expired(now);
+
         if let Ok((topic_id, channel)) = self.mqtt_schema.entity_channel_of(&message.topic) {
-            let entity_cache = self
-                .entities
-                .entry(topic_id)
-                .or_insert_with(PendingEntityCache::new);
             match &channel {
                 Channel::Measurement { .. } | Channel::Event { .. } | Channel::Alarm { .. } => {
-                    self.telemetry_cache.push(message);
+                    self.entities
+                        .entry(topic_id)
+                        .or_insert_with(|| PendingEntityCache::new(now));
+                    if self.telemetry_cache.push((now, message)).is_some() {
+                        self.dropped.telemetry += 1;
+                    }
                 }
                 Channel::EntityTwinData { .. }
                 | Channel::MeasurementMetadata { .. }
@@ -138,7 +200,21 @@ impl PendingEntityStore {
                 | Channel::AlarmMetadata { .. }
                 | Channel::Health
                 | Channel::CommandMetadata { .. }
-                | Channel::Command { .. } => entity_cache.metadata.push(message),
+                | Channel::Command { .. } => {
+                    if self.metadata_cache_is_full() {
+                        warn!(
+                            "Dropping the message on {}: the cache of pending entities is full",
+                            message.topic.name
+                        );
+                        self.dropped.metadata += 1;
+                        return;
+                    }
+                    self.entities
+                        .entry(topic_id)
+                        .or_insert_with(|| PendingEntityCache::new(now))
+                        .metadata
+                        .push(message)
+                }
                 _ => {
                     // Ignore
                 }
@@ -149,19 +225,69 @@ impl PendingEntityStore {
     }
 
     pub fn cache_early_registration_message(&mut self, reg_message: EntityRegistrationMessage) {
+        let now = Instant::now();
+        self.evict_expired(now);
+
         let source = reg_message.topic_id.clone();
         let parent = reg_message.parent.clone().unwrap();
-        self.orphans.entry(parent).or_default().push(source.clone());
+        let is_new_registration = self
+            .entities
+            .get(&source)
+            .map_or(true, |cached_entity| cached_entity.reg_message.is_none());
+        if is_new_registration && self.metadata_cache_is_full() {
+            warn!("Dropping the registration of {source}: the cache of pending entities is full");
+            self.dropped.registrations += 1;
+            return;
+        }
+
+        let siblings = self.orphans.entry(parent).or_default();
+        if !siblings.contains(&source) {
+            siblings.push(source.clone());
+        }
         self.entities
             .entry(source)
-            .and_modify(|cached_entity| {
-                cached_entity.reg_message = Some(reg_message.clone());
-            })
-            .or_insert_with(|| {
-                let mut cached_entity = PendingEntityCache::new();
-                cached_entity.reg_message = Some(reg_message);
-                cached_entity
-            });
+            .or_insert_with(|| PendingEntityCache::new(now))
+            .reg_message = Some(reg_message);
+    }
+
+    /// Drops the telemetry messages and the pending entities that have been cached for too long
+    pub fn evict_expired(&mut self, now: Instant) {
+        let telemetry_ttl = self.config.telemetry_cache_ttl;
+        let expired_telemetry = self
+            .telemetry_cache
+            .retain(|(since, _)| now.saturating_duration_since(*since) <= telemetry_ttl);
+        self.dropped.telemetry += expired_telemetry as u64;
+
+        let orphan_ttl = self.config.orphan_ttl;
+        let expired_entities: Vec<EntityTopicId> = self
+            .entities
+            .iter()
+            .filter(|(_, cache)| now.saturating_duration_since(cache.since) > orphan_ttl)
+            .map(|(topic_id, _)| topic_id.clone())
+            .collect();
+        for topic_id in expired_entities {
+            let Some(cache) = self.entities.remove(&topic_id) else {
+                continue;
+            };
+            self.dropped.metadata += cache.metadata.len() as u64;
+            if let Some(reg_message) = cache.reg_message {
+                warn!("Evicting the registration of {topic_id}: its parent has not been registered in time");
+                self.dropped.registrations += 1;
+                if let Some(parent) = reg_message.parent {
+                    if let Some(siblings) = self.orphans.get_mut(&parent) {
+                        siblings.retain(|sibling| sibling != &topic_id);
+                        if siblings.is_empty() {
+                            self.orphans.remove(&parent);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn metadata_cache_is_full(&self) -> bool {
+        let cached: usize = self.entities.values().map(PendingEntityCache::len).sum();
+        cached >= self.config.metadata_cache_size
     }
 }
 
@@ -170,8 +296,12 @@ mod tests {
     use mqtt_channel::Message;
     use mqtt_channel::Topic;
     use serde_json::json;
+    use std::time::Duration;
+    use std::time::Instant;
 
+    use super::DroppedMessages;
     use super::PendingEntityStore;
+    use super::PendingEntityStoreConfig;
     use crate::entity_store::EntityRegistrationMessage;
     use crate::entity_store::EntityType;
     use crate::mqtt_topics::EntityTopicId;
@@ -352,7 +482,130 @@ mod tests {
         );
     }
 
+    #[test]
+    fn telemetry_messages_are_dropped_when_the_cache_is_full() {
+        let mut store = build_pending_entity_store();
+
+        for i in 0..7 {
+            store.cache_early_data_message(Message::new(
+                &Topic::new_unchecked("te/device/child1///m/environment"),
+                json!({ "temperature": i }).to_string(),
+            ));
+        }
+
+        assert_eq!(
+            store.dropped_messages(),
+            DroppedMessages {
+                telemetry: 2,
+                ..Default::default()
+            }
+        );
+        let cached_entity = store.take_cached_entity_data(EntityRegistrationMessage::new_custom(
+            EntityTopicId::default_child_device("child1").unwrap(),
+            EntityType::ChildDevice,
+        ));
+        assert_eq!(cached_entity.data_messages.len(), 5);
+    }
+
+    #[test]
+    fn metadata_messages_are_rejected_when_the_cache_is_full() {
+        let mut store = PendingEntityStore::new(
+            MqttSchema::default(),
+            PendingEntityStoreConfig {
+                metadata_cache_size: 2,
+                ..Default::default()
+            },
+        );
+
+        store.cache_early_registration_message(
+            EntityRegistrationMessage::new_custom(
+                EntityTopicId::default_child_device("child2").unwrap(),
+                EntityType::ChildDevice,
+            )
+            .with_parent(EntityTopicId::default_child_device("child1").unwrap()),
+        );
+        for twin in ["maintenance_mode", "service_count"] {
+            store.cache_early_data_message(Message::new(
+                &Topic::new_unchecked(&format!("te/device/child2///twin/{twin}")),
+                "true",
+            ));
+        }
+        store.cache_early_registration_message(
+            EntityRegistrationMessage::new_custom(
+                EntityTopicId::default_child_device("child3").unwrap(),
+                EntityType::ChildDevice,
+            )
+            .with_parent(EntityTopicId::default_child_device("child1").unwrap()),
+        );
+
+        assert_eq!(
+            store.dropped_messages(),
+            DroppedMessages {
+                metadata: 1,
+                registrations: 1,
+                ..Default::default()
+            }
+        );
+        let children = store.take_cached_child_entities_data(
+            &EntityTopicId::default_child_device("child1").unwrap(),
+        );
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].data_messages.len(), 1);
+    }
+
+    #[test]
+    fn stale_orphans_are_evicted() {
+        let ttl = Duration::from_secs(60);
+        let mut store = PendingEntityStore::new(
+            MqttSchema::default(),
+            PendingEntityStoreConfig {
+                telemetry_cache_ttl: ttl,
+                orphan_ttl: ttl,
+                ..Default::default()
+            },
+        );
+
+        store.cache_early_registration_message(
+            EntityRegistrationMessage::new_custom(
+                EntityTopicId::default_child_device("child2").unwrap(),
+                EntityType::ChildDevice,
+            )
+            .with_parent(EntityTopicId::default_child_device("child1").unwrap()),
+        );
+        store.cache_early_data_message(Message::new(
+            &Topic::new_unchecked("te/device/child2///twin/maintenance_mode"),
+            "true",
+        ));
+        store.cache_early_data_message(Message::new(
+            &Topic::new_unchecked("te/device/child2///m/environment"),
+            json!({"temperature": 50}).to_string(),
+        ));
+
+        store.evict_expired(Instant::now());
+        assert_eq!(store.dropped_messages(), DroppedMessages::default());
+
+        store.evict_expired(Instant::now() + ttl + Duration::from_secs(1));
+        assert_eq!(
+            store.dropped_messages(),
+            DroppedMessages {
+                telemetry: 1,
+                metadata: 1,
+                registrations: 1,
+            }
+        );
+        let children = store.take_cached_child_entities_data(
+            &EntityTopicId::default_child_device("child1").unwrap(),
+        );
+        assert!(children.is_empty());
+    }
+
     fn build_pending_entity_store() -> PendingEntityStore {
-        PendingEntityStore::new(MqttSchema::default(), 5)
+        PendingEntityStore::new(
+            MqttSchema::default(),
+            PendingEntityStoreConfig {
+                telemetry_cache_size: 5,
+                ..Default::default()
+            },
+        )
     }
 }
@@ -14,15 +14,29 @@ impl<T> RingBuffer<T> {
         RingBuffer { buffer, size }
     }
 
-    pub fn push(&mut self, item: T) {
-        if self.buffer.len() == self.size {
-            self.buffer.pop_front();
+    /// Pushes a new item, returning the oldest one if removed to make room
+    pub fn push(&mut self, item: T) -> Option<T> {
+        if self.size == 0 {
+            return Some(item);
         }
+        let evicted = if self.buffer.len() == self.size {
+            self.buffer.pop_front()
+        } else {
+            None
+        };
         self.buffer.push_back(item);
+        evicted
+    }
+
+    /// Retains only the items satisfying the predicate, returning the number of removed items
+    pub fn retain(&mut self, predicate: impl FnMut(&T) -> bool) -> usize {
+        let len = self.buffer.len();
+        self.buffer.retain(predicate);
+        len - self.buffer.len()
     }
 
     pub fn capacity(&self) -> usize {
-        self.buffer.capacity()
+        self.size
     }
 }
 
@@ -58,9 +72,23 @@ mod tests {
         ring_buffer.push(1);
         ring_buffer.push(2);
         ring_buffer.push(3);
-        ring_buffer.push(4);
+        assert_eq!(ring_buffer.push(4), Some(1));
 
         let result: Vec<_> = ring_buffer.into_iter().collect();
         assert_eq!(result, vec![2, 3, 4]);
     }
+
+    #[test]
+    fn retain_returns_the_number_of_removed_items() {
+        let mut ring_buffer = RingBuffer::new(5);
+        for i in 1..=5 {
+            ring_buffer.push(i);
+        }
+
+        assert_eq!(ring_buffer.retain(|i| i % 2 == 0), 3);
+        assert_eq!(ring_buffer.capacity(), 5);
+
+        let result: Vec<_> = ring_buffer.into_iter().collect();
+        assert_eq!(result, vec![2, 4]);
+    }
 }
@@ -0,0 +1,499 @@
+use crate::workflow::ConditionDefinitionError;
+use crate::workflow::GenericCommandState;
+use crate::workflow::GenericStateUpdate;
+use crate::workflow::StateName;
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+/// A boolean expression evaluated against a command state
+///
+/// The operands are either literals or `${...}` paths,
+/// which are substituted using the values of the command state
+/// (as done for the script arguments, see [GenericCommandState::inject_parameter]).
+///
+/// ```text
+/// ${.payload.version} == ${.payload.current}
+/// ${.payload.force} == true || ${.payload.attempt} < 3
+/// !(${.topic.target} == "device/main//")
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Condition {
+    Compare(Operand, Comparison, Operand),
+    Truthy(Operand),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+/// An operand of a [Condition]: either a literal or a path to be extracted from the command state
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Operand {
+    Literal(String),
+    Variable(String),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Comparison {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+/// Define how to determine the next state of a command by evaluating a list of conditions
+///
+/// The conditions are evaluated in order and the first that holds determines the next state.
+/// If none holds, the command moves to the default state.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConditionalHandlers {
+    conditions: Vec<(Condition, GenericStateUpdate)>,
+    default: Option<GenericStateUpdate>,
+}
+
+impl ConditionalHandlers {
+    pub fn try_new(
+        conditions: Vec<(Condition, GenericStateUpdate)>,
+        default: Option<GenericStateUpdate>,
+    ) -> Result<Self, ConditionDefinitionError> {
+        if conditions.is_empty() {
+            return Err(ConditionDefinitionError::NoConditions);
+        }
+
+        Ok(ConditionalHandlers {
+            conditions,
+            default,
+        })
+    }
+
+    /// Return the state update given by the first condition that holds for the given state
+    pub fn state_update(&self, state: &GenericCommandState) -> GenericStateUpdate {
+        self.conditions
+            .iter()
+            .find(|(condition, _)| condition.eval(state))
+            .map(|(_, update)| update.clone())
+            .or_else(|| self.default.clone())
+            .unwrap_or_else(|| {
+                GenericStateUpdate::failed(format!(
+                    "None of the conditions holds in {} state",
+                    state.status
+                ))
+            })
+    }
+
+    /// The possible next states, labeled by the condition leading to each of them
+    pub fn transitions(&self) -> Vec<(String, StateName)> {
+        let mut transitions: Vec<_> = self
+            .conditions
+            .iter()
+            .map(|(condition, update)| (format!("if {condition}"), update.status.clone()))
+            .collect();
+        let default = self.default.as_ref().map(|update| update.status.clone());
+        transitions.push(("else".to_string(), default.unwrap_or("failed".to_string())));
+        transitions
+    }
+}
+
+impl Condition {
+    /// Evaluate this condition in the context of a command state
+    pub fn eval(&self, state: &GenericCommandState) -> bool {
+        match self {
+            // A comparison involving a path that cannot be resolved doesn't hold,
+            // rather than comparing the unresolved paths as literals
+            Condition::Compare(left, comparison, right) => {
+                match (left.value(state), right.value(state)) {
+                    (Some(left), Some(right)) => comparison.holds(compare(&left, &right)),
+                    _ => false,
+                }
+            }
+            Condition::Truthy(operand) => operand.value(state).as_deref() == Some("true"),
+            Condition::Not(condition) => !condition.eval(state),
+            Condition::And(left, right) => left.eval(state) && right.eval(state),
+            Condition::Or(left, right) => left.eval(state) || right.eval(state),
+        }
+    }
+}
+
+impl Operand {
+    /// The value of this operand, if any in the command state
+    fn value(&self, state: &GenericCommandState) -> Option<String> {
+        match self {
+            Operand::Literal(value) => Some(value.clone()),
+            Operand::Variable(path) => state.extract_parameter(path),
+        }
+    }
+}
+
+impl Comparison {
+    fn holds(&self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Eq => ordering == Ordering::Equal,
+            Comparison::NotEq => ordering != Ordering::Equal,
+            Comparison::Lt => ordering == Ordering::Less,
+            Comparison::LtEq => ordering != Ordering::Greater,
+            Comparison::Gt => ordering == Ordering::Greater,
+            Comparison::GtEq => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// Compare two operand values, numerically if both are numbers, lexicographically otherwise
+fn compare(left: &str, right: &str) -> Ordering {
+    match (left.parse::<f64>(), right.parse::<f64>()) {
+        (Ok(left), Ok(right)) => left.partial_cmp(&right).unwrap_or(Ordering::Equal),
+        _ => left.cmp(right),
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let op = match self {
+            Comparison::Eq => "==",
+            Comparison::NotEq => "!=",
+            Comparison::Lt => "<",
+            Comparison::LtEq => "<=",
+            Comparison::Gt => ">",
+            Comparison::GtEq => ">=",
+        };
+        f.write_str(op)
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operand::Literal(value) => write!(f, "{value:?}"),
+            Operand::Variable(path) => f.write_str(path),
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::Compare(left, comparison, right) => write!(f, "{left} {comparison} {right}"),
+            Condition::Truthy(operand) => operand.fmt(f),
+            Condition::Not(condition) => write!(f, "!({condition})"),
+            Condition::And(left, right) => write!(f, "({left} && {right})"),
+            Condition::Or(left, right) => write!(f, "({left} || {right})"),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = ConditionDefinitionError;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let syntax_error = |reason: String| ConditionDefinitionError::SyntaxError {
+            expression: expression.to_string(),
+            reason,
+        };
+        let tokens = tokenize(expression).map_err(syntax_error)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let condition = parser.parse_or().map_err(syntax_error)?;
+        match parser.next() {
+            None => Ok(condition),
+            Some(token) => Err(syntax_error(format!("unexpected token: {token}"))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Operand(Operand),
+    Comparison(Comparison),
+    Not,
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Operand(operand) => operand.fmt(f),
+            Token::Comparison(comparison) => comparison.fmt(f),
+            Token::Not => f.write_str("!"),
+            Token::And => f.write_str("&&"),
+            Token::Or => f.write_str("||"),
+            Token::Open => f.write_str("("),
+            Token::Close => f.write_str(")"),
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let (token, len) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            ('=', Some('=')) => (Token::Comparison(Comparison::Eq), 2),
+            ('!', Some('=')) => (Token::Comparison(Comparison::NotEq), 2),
+            ('<', Some('=')) => (Token::Comparison(Comparison::LtEq), 2),
+            ('>', Some('=')) => (Token::Comparison(Comparison::GtEq), 2),
+            ('<', _) => (Token::Comparison(Comparison::Lt), 1),
+            ('>', _) => (Token::Comparison(Comparison::Gt), 1),
+            ('!', _) => (Token::Not, 1),
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('$', Some('{')) => {
+                let len = chars[i..]
+                    .iter()
+                    .position(|c| *c == '}')
+                    .ok_or_else(|| "unclosed variable".to_string())?
+                    + 1;
+                let path: String = chars[i..i + len].iter().collect();
+                (Token::Operand(Operand::Variable(path)), len)
+            }
+            ('"', _) | ('\'', _) => {
+                let len = chars[i + 1..]
+                    .iter()
+                    .position(|q| *q == c)
+                    .ok_or_else(|| "unclosed quote".to_string())?;
+                let literal: String = chars[i + 1..i + 1 + len].iter().collect();
+                (Token::Operand(Operand::Literal(literal)), len + 2)
+            }
+            _ => {
+                let len = chars[i..]
+                    .iter()
+                    .position(|c| c.is_whitespace() || "()=!<>&|\"'".contains(*c))
+                    .unwrap_or(chars.len() - i);
+                if len == 0 {
+                    return Err(format!("unexpected character: {c}"));
+                }
+                let literal: String = chars[i..i + len].iter().collect();
+                (Token::Operand(Operand::Literal(literal)), len)
+            }
+        };
+        tokens.push(token);
+        i += len;
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser, with `||` having a lower precedence than `&&`
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Condition, String> {
+        let mut condition = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            let right = self.parse_and()?;
+            condition = Condition::Or(Box::new(condition), Box::new(right));
+        }
+        Ok(condition)
+    }
+
+    fn parse_and(&mut self) -> Result<Condition, String> {
+        let mut condition = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            let right = self.parse_unary()?;
+            condition = Condition::And(Box::new(condition), Box::new(right));
+        }
+        Ok(condition)
+    }
+
+    fn parse_unary(&mut self) -> Result<Condition, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Condition::Not(Box::new(self.parse_unary()?))),
+            Some(Token::Open) => {
+                let condition = self.parse_or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(condition),
+                    _ => Err("missing closing parenthesis".to_string()),
+                }
+            }
+            Some(Token::Operand(left)) => match self.peek() {
+                Some(Token::Comparison(comparison)) => {
+                    let comparison = *comparison;
+                    self.next();
+                    match self.next() {
+                        Some(Token::Operand(right)) => {
+                            Ok(Condition::Compare(left, comparison, right))
+                        }
+                        _ => Err(format!("missing right operand for {comparison}")),
+                    }
+                }
+                _ => Ok(Condition::Truthy(left)),
+            },
+            Some(token) => Err(format!("unexpected token: {token}")),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mqtt_channel::Topic;
+    use serde_json::json;
+
+    fn command_state(payload: serde_json::Value) -> GenericCommandState {
+        GenericCommandState {
+            topic: Topic::new_unchecked("te/device/main///cmd/firmware_update/123"),
+            status: "check".to_string(),
+            payload,
+        }
+    }
+
+    fn eval(expression: &str, state: &GenericCommandState) -> bool {
+        expression.parse::<Condition>().unwrap().eval(state)
+    }
+
+    #[test]
+    fn parse_conditions() {
+        assert_eq!(
+            "${.payload.version} == ${.payload.current}"
+                .parse::<Condition>()
+                .unwrap(),
+            Condition::Compare(
+                Operand::Variable("${.payload.version}".to_string()),
+                Comparison::Eq,
+                Operand::Variable("${.payload.current}".to_string()),
+            )
+        );
+        assert_eq!(
+            "!(${.payload.x} != 'a b') && ${.payload.y} || true"
+                .parse::<Condition>()
+                .unwrap(),
+            Condition::Or(
+                Box::new(Condition::And(
+                    Box::new(Condition::Not(Box::new(Condition::Compare(
+                        Operand::Variable("${.payload.x}".to_string()),
+                        Comparison::NotEq,
+                        Operand::Literal("a b".to_string()),
+                    )))),
+                    Box::new(Condition::Truthy(Operand::Variable(
+                        "${.payload.y}".to_string()
+                    ))),
+                )),
+                Box::new(Condition::Truthy(Operand::Literal("true".to_string()))),
+            )
+        );
+    }
+
+    #[test]
+    fn reject_ill_formed_conditions() {
+        for expression in [
+            "",
+            "${.payload.x} ==",
+            "(${.payload.x} == 1",
+            "${.payload.x} == 1)",
+            "${.payload.x",
+            "${.payload.x} == \"foo",
+            "a b",
+            "&& a",
+        ] {
+            let error = expression.parse::<Condition>().unwrap_err();
+            assert!(
+                matches!(error, ConditionDefinitionError::SyntaxError { .. }),
+                "{expression}: {error:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn eval_conditions() {
+        let state = command_state(json!({
+            "status": "check",
+            "version": "1.2.3",
+            "current": "1.2.3",
+            "attempt": 9,
+            "force": true,
+        }));
+
+        assert!(eval("${.payload.version} == ${.payload.current}", &state));
+        assert!(!eval("${.payload.version} != ${.payload.current}", &state));
+        assert!(eval("${.payload.version} == '1.2.3'", &state));
+        assert!(eval("${.payload.force}", &state));
+        assert!(!eval("${.payload.unknown}", &state));
+        assert!(eval("!${.payload.unknown}", &state));
+        assert!(eval("${.topic.operation} == firmware_update", &state));
+
+        // Comparisons involving unknown paths don't hold, whatever the comparison
+        assert!(!eval("${.payload.unknown} == ${.payload.unknown}", &state));
+        assert!(!eval("${.payload.unknown} == ${.payload.other}", &state));
+        assert!(!eval(
+            "${.payload.unknown} == '${.payload.unknown}'",
+            &state
+        ));
+        assert!(!eval("${.payload.unknown} != 1", &state));
+        assert!(eval("!(${.payload.unknown} == 1)", &state));
+
+        // Numbers are compared numerically
+        assert!(eval("${.payload.attempt} < 10", &state));
+        assert!(eval("${.payload.attempt} >= 9", &state));
+        assert!(!eval("${.payload.attempt} > 9", &state));
+
+        assert!(eval(
+            "${.payload.attempt} > 10 || ${.payload.force} && ${.payload.status} == check",
+            &state
+        ));
+        assert!(!eval(
+            "(${.payload.attempt} > 10 || ${.payload.force}) && ${.payload.status} == init",
+            &state
+        ));
+    }
+
+    #[test]
+    fn first_condition_that_holds_determines_next_state() {
+        let handlers = ConditionalHandlers::try_new(
+            vec![
+                (
+                    "${.payload.version} == ${.payload.current}"
+                        .parse()
+                        .unwrap(),
+                    "successful".to_string().into(),
+                ),
+                (
+                    "${.payload.force}".parse().unwrap(),
+                    "install".to_string().into(),
+                ),
+            ],
+            None,
+        )
+        .unwrap();
+
+        let state = command_state(json!({"version": "1.0", "current": "1.0", "force": true}));
+        assert_eq!(handlers.state_update(&state).status, "successful");
+
+        let state = command_state(json!({"version": "2.0", "current": "1.0", "force": true}));
+        assert_eq!(handlers.state_update(&state).status, "install");
+
+        let state = command_state(json!({"version": "2.0", "current": "1.0"}));
+        let update = handlers.state_update(&state);
+        assert_eq!(update.status, "failed");
+        assert_eq!(
+            update.reason.unwrap(),
+            "None of the conditions holds in check state"
+        );
+    }
+}
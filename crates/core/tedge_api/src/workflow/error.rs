@@ -7,11 +7,30 @@ pub enum WorkflowDefinitionError {
     #[error("Missing transition for state: {state}")]
     MissingTransition { state: String },
 
+    #[error("Unknown state '{state}' referenced by the '{from}' state")]
+    UnknownState { state: String, from: String },
+
+    #[error("Unreachable state: {state}")]
+    UnreachableState { state: String },
+
     #[error(transparent)]
     ScriptDefinitionError(#[from] ScriptDefinitionError),
 
+    #[error(transparent)]
+    ConditionDefinitionError(#[from] ConditionDefinitionError),
+
     #[error("Unknown action: {action}")]
     UnknownAction { action: String },
+
+    #[error(
+        "A retry policy cannot be given for the '{action}' action: only scripts can be retried"
+    )]
+    IncompatibleRetry { action: String },
+
+    #[error(
+        "An input or output cannot be given for the '{action}' action: only sub-operations take these"
+    )]
+    IncompatibleInput { action: String },
 }
 
 /// Error related to a script definition
@@ -33,6 +52,19 @@ pub enum ScriptDefinitionError {
     IncorrectRange { from: u8, to: u8 },
 }
 
+/// Error related to the conditions used to determine the next state
+#[derive(thiserror::Error, Debug, Eq, PartialEq)]
+pub enum ConditionDefinitionError {
+    #[error("Invalid condition '{expression}': {reason}")]
+    SyntaxError { expression: String, reason: String },
+
+    #[error("Conditions cannot be combined with the '{action}' action")]
+    IncompatibleAction { action: String },
+
+    #[error("Empty list of conditions")]
+    NoConditions,
+}
+
 /// Error preventing a workflow to be registered
 #[derive(thiserror::Error, Debug, Eq, PartialEq)]
 pub enum WorkflowRegistrationError {
@@ -1,3 +1,4 @@
+pub mod condition;
 pub mod error;
 pub mod script;
 pub mod state;
@@ -7,6 +8,7 @@ mod toml_config;
 use crate::mqtt_topics::EntityTopicId;
 use crate::mqtt_topics::MqttSchema;
 use crate::mqtt_topics::OperationType;
+pub use condition::*;
 pub use error::*;
 use mqtt_channel::Message;
 use mqtt_channel::QoS;
@@ -35,6 +37,19 @@ pub struct OperationWorkflow {
 
     /// The states of the state machine
     pub states: HashMap<StateName, OperationAction>,
+
+    /// The timeouts set on the states which action is not a script
+    pub timeouts: HashMap<StateName, StateTimeout>,
+}
+
+/// The maximum time a command can stay in a given state
+///
+/// Such a timeout is used for the states which action is not directly controlled by the agent,
+/// as builtin actions, actions delegated to other participants and sub-operations.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StateTimeout {
+    pub timeout: std::time::Duration,
+    pub on_timeout: GenericStateUpdate,
 }
 
 /// What needs to be done to advance an operation request in some state
@@ -91,6 +106,40 @@ pub enum OperationAction {
     /// ```
     BgScript(ShellScript, BgExitHandlers),
 
+    /// Trigger a sub-operation and await its completion
+    ///
+    /// The sub-operation command is created on the same entity, using the given input as payload,
+    /// and the command resumes in the `on_success` or `on_error` state
+    /// when the sub-operation reaches its `successful` or `failed` terminal state.
+    /// Only the values given by the output template, extracted from the final state of the sub-operation,
+    /// are then injected into the command payload.
+    ///
+    /// ```toml
+    /// operation = "<operation>"
+    /// input = { x = "${.payload.y}" }
+    /// output = { z = "${.payload.x}" }
+    /// on_success = "<state>"
+    /// on_error = "<state>"
+    /// ```
+    Operation {
+        operation: OperationType,
+        input: serde_json::Value,
+        output: serde_json::Value,
+        on_success: GenericStateUpdate,
+        on_error: GenericStateUpdate,
+    },
+
+    /// The next state is determined by evaluating conditions on the command state
+    ///
+    /// ```toml
+    /// on_condition = [
+    ///   { if = "${.payload.version} == ${.payload.current}", then = "successful" },
+    ///   { if = "${.payload.force} == true", then = "install" },
+    /// ]
+    /// on_success = "<state>"
+    /// ```
+    Evaluate(ConditionalHandlers),
+
     /// The command has been fully processed and needs to be cleared
     Clear,
 }
@@ -106,6 +155,10 @@ impl Display for OperationAction {
             OperationAction::Restart { .. } => "trigger device restart".to_string(),
             OperationAction::Script(script, _) => script.to_string(),
             OperationAction::BgScript(script, _) => script.to_string(),
+            OperationAction::Operation { operation, .. } => {
+                format!("trigger {operation} operation and await its completion")
+            }
+            OperationAction::Evaluate(_) => "evaluate conditions".to_string(),
             OperationAction::Clear => "wait for the requester to finalize the command".to_string(),
         };
         f.write_str(&str)
@@ -131,7 +184,99 @@ impl OperationWorkflow {
             operation,
             handlers: DefaultHandlers::default(),
             states,
+            timeouts: HashMap::new(),
+        }
+    }
+
+    /// Check the consistency of the state machine, returning all the issues found
+    ///
+    /// - The `init`, `successful` and `failed` states must be defined.
+    /// - All the states referenced as next states must be defined.
+    ///   An exception is made for the `executing` state of builtin actions, which is optional.
+    /// - All the states must be reachable from the `init` state.
+    ///   As the next state of a delegated action is chosen by the delegate,
+    ///   this check is skipped for a workflow with reachable delegated actions.
+    pub fn validate(&self) -> Vec<WorkflowDefinitionError> {
+        let mut errors = vec![];
+        for state in ["init", "successful", "failed"] {
+            if !self.states.contains_key(state) {
+                errors.push(WorkflowDefinitionError::MissingState {
+                    state: state.to_string(),
+                });
+            }
+        }
+
+        let mut states: Vec<_> = self.states.iter().collect();
+        states.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (from, action) in states.iter() {
+            let next_states = action.transitions().unwrap_or_default();
+            for (_, state) in next_states.iter() {
+                let optional = matches!(action, OperationAction::BuiltIn) && state == "executing";
+                if !optional && !self.states.contains_key(state) {
+                    let error = WorkflowDefinitionError::UnknownState {
+                        state: state.clone(),
+                        from: from.to_string(),
+                    };
+                    if !errors.contains(&error) {
+                        errors.push(error)
+                    }
+                }
+            }
+        }
+        errors.extend(self.timeouts_to_unknown_states());
+
+        if let Some(reachable_states) = self.reachable_states("init") {
+            for (state, _) in states {
+                if !reachable_states.contains(state.as_str()) {
+                    errors.push(WorkflowDefinitionError::UnreachableState {
+                        state: state.to_string(),
+                    });
+                }
+            }
+        }
+
+        errors
+    }
+
+    fn timeouts_to_unknown_states(&self) -> Vec<WorkflowDefinitionError> {
+        let mut timeouts: Vec<_> = self.timeouts.iter().collect();
+        timeouts.sort_by(|(a, _), (b, _)| a.cmp(b));
+        timeouts
+            .into_iter()
+            .filter(|(_, timeout)| !self.states.contains_key(&timeout.on_timeout.status))
+            .map(|(from, timeout)| WorkflowDefinitionError::UnknownState {
+                state: timeout.on_timeout.status.clone(),
+                from: from.to_string(),
+            })
+            .collect()
+    }
+
+    /// The states that can be reached from a given state
+    ///
+    /// Returns `None` if a delegated action can be reached, the delegate being free to move the command to any state.
+    fn reachable_states(&self, from: &str) -> Option<std::collections::HashSet<&str>> {
+        let mut reachable = std::collections::HashSet::new();
+        let mut pending = vec![from];
+        while let Some(state) = pending.pop() {
+            let Some((state, action)) = self.states.get_key_value(state) else {
+                continue;
+            };
+            if !reachable.insert(state.as_str()) {
+                continue;
+            }
+            for (_, next_state) in action.transitions()? {
+                if let Some((next_state, _)) = self.states.get_key_value(&next_state) {
+                    pending.push(next_state.as_str());
+                }
+            }
+            if let Some(timeout) = self.timeouts.get(state) {
+                if let Some((next_state, _)) = self.states.get_key_value(&timeout.on_timeout.status)
+                {
+                    pending.push(next_state.as_str());
+                }
+            }
         }
+        Some(reachable)
     }
 
     /// Return the MQTT message to register support for the operation described by this workflow
@@ -181,6 +326,54 @@ impl OperationAction {
         }
     }
 
+    /// Return true if this action is not a script, i.e. if its execution is not controlled by the agent
+    pub fn is_not_script(&self) -> bool {
+        !matches!(
+            self,
+            OperationAction::Script(_, _) | OperationAction::BgScript(_, _)
+        )
+    }
+
+    /// The possible next states, labeled by the outcome leading to each of them
+    ///
+    /// Returns `None` for a delegated action, as the next state is then chosen by the delegate.
+    pub fn transitions(&self) -> Option<Vec<(String, StateName)>> {
+        let transition = |label: &str, state: &StateName| (label.to_string(), state.clone());
+        let transitions = match self {
+            OperationAction::MoveTo(state) => vec![transition("proceed", state)],
+            OperationAction::BuiltIn => vec![
+                transition("builtin", &"executing".to_string()),
+                transition("success", &"successful".to_string()),
+                transition("error", &"failed".to_string()),
+            ],
+            OperationAction::Delegate(_) => return None,
+            OperationAction::Restart {
+                on_exec,
+                on_success,
+                on_error,
+            } => vec![
+                transition("exec", on_exec),
+                transition("success", on_success),
+                transition("error", on_error),
+            ],
+            OperationAction::Script(_, handlers) => handlers.transitions(),
+            OperationAction::BgScript(_, handlers) => {
+                vec![transition("exec", &handlers.on_exec.status)]
+            }
+            OperationAction::Operation {
+                on_success,
+                on_error,
+                ..
+            } => vec![
+                transition("success", &on_success.status),
+                transition("error", &on_error.status),
+            ],
+            OperationAction::Evaluate(handlers) => handlers.transitions(),
+            OperationAction::Clear => vec![],
+        };
+        Some(transitions)
+    }
+
     pub fn inject_state(&self, state: &GenericCommandState) -> Self {
         match self {
             OperationAction::Script(script, handlers) => OperationAction::Script(
@@ -190,7 +383,125 @@ impl OperationAction {
                 },
                 handlers.clone(),
             ),
+            OperationAction::Operation {
+                operation,
+                input,
+                output,
+                on_success,
+                on_error,
+            } => OperationAction::Operation {
+                operation: operation.clone(),
+                input: state.inject_values_into_template(input),
+                output: output.clone(),
+                on_success: on_success.clone(),
+                on_error: on_error.clone(),
+            },
             _ => self.clone(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_workflow() {
+        let workflow: OperationWorkflow = toml::from_str(
+            r#"
+operation = "check"
+
+[init]
+action = "proceed"
+on_success = "check"
+
+[check]
+script = "/some/check.sh"
+on_exit.0 = "successful"
+on_exit.1 = "retry"
+on_exit._ = "failed"
+
+[retry]
+on_condition = [ { if = "${.payload.force} == true", then = "check" } ]
+on_success = "failed"
+
+[successful]
+action = "cleanup"
+
+[failed]
+action = "cleanup"
+"#,
+        )
+        .unwrap();
+        assert_eq!(workflow.validate(), vec![]);
+    }
+
+    #[test]
+    fn report_all_workflow_inconsistencies() {
+        let workflow: OperationWorkflow = toml::from_str(
+            r#"
+operation = "check"
+
+[init]
+action = "proceed"
+on_success = "check"
+
+[check]
+script = "/some/check.sh"
+on_success = "successful"
+on_error = "cleanup"
+
+[orphan]
+action = "proceed"
+on_success = "successful"
+
+[successful]
+action = "cleanup"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            workflow.validate(),
+            vec![
+                WorkflowDefinitionError::MissingState {
+                    state: "failed".to_string()
+                },
+                WorkflowDefinitionError::UnknownState {
+                    state: "cleanup".to_string(),
+                    from: "check".to_string()
+                },
+                WorkflowDefinitionError::UnknownState {
+                    state: "failed".to_string(),
+                    from: "check".to_string()
+                },
+                WorkflowDefinitionError::UnreachableState {
+                    state: "orphan".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn delegated_actions_can_reach_any_state() {
+        let workflow: OperationWorkflow = toml::from_str(
+            r#"
+operation = "check"
+
+[init]
+action = "waiting some-participant"
+
+[check]
+action = "proceed"
+on_success = "successful"
+
+[successful]
+action = "cleanup"
+
+[failed]
+action = "cleanup"
+"#,
+        )
+        .unwrap();
+        assert_eq!(workflow.validate(), vec![]);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::workflow::GenericStateUpdate;
 use crate::workflow::ScriptDefinitionError;
+use crate::workflow::StateName;
 use serde::de::Error;
 use serde::Deserialize;
 use serde::Deserializer;
@@ -67,6 +68,7 @@ pub struct ExitHandlers {
     on_exit: Vec<(u8, u8, GenericStateUpdate)>,
     on_stdout: Vec<String>,
     timeout: Option<Duration>,
+    retry: Option<Box<RetryPolicy>>,
 }
 
 impl ExitHandlers {
@@ -131,9 +133,28 @@ impl ExitHandlers {
             on_exit,
             on_stdout,
             timeout,
+            retry: None,
         })
     }
 
+    pub fn with_retry(self, retry: Option<RetryPolicy>) -> Self {
+        ExitHandlers {
+            retry: retry.map(Box::new),
+            ..self
+        }
+    }
+
+    /// Return the delay to wait before retrying a script, if the outcome of the given attempt can be retried
+    pub fn retry_delay(
+        &self,
+        outcome: &std::io::Result<std::process::Output>,
+        attempt: u32,
+    ) -> Option<Duration> {
+        self.retry
+            .as_ref()
+            .and_then(|retry| retry.retry_delay(outcome, attempt))
+    }
+
     pub fn with_default(mut self, default: &DefaultHandlers) -> Self {
         if self.timeout.is_none() {
             self.timeout = default.timeout
@@ -253,6 +274,41 @@ impl ExitHandlers {
         })
     }
 
+    /// The possible next states, labeled by the outcome leading to each of them
+    ///
+    /// The states reached on an unexpected outcome, as a script killed with no `on_kill` handler,
+    /// are reported as `failed`.
+    pub fn transitions(&self) -> Vec<(String, StateName)> {
+        let mut transitions = vec![];
+        match &self.on_success {
+            Some(update) => transitions.push(("success".to_string(), update.status.clone())),
+            None if self.on_stdout.is_empty() => {
+                transitions.push(("success".to_string(), "successful".to_string()))
+            }
+            None => {
+                for status in self.on_stdout.iter() {
+                    transitions.push(("stdout".to_string(), status.clone()))
+                }
+            }
+        }
+        for (from, to, update) in self.on_exit.iter().filter(|(from, _, _)| *from > 0) {
+            let label = if from == to {
+                format!("exit {from}")
+            } else {
+                format!("exit {from}-{to}")
+            };
+            transitions.push((label, update.status.clone()))
+        }
+        let on_error = self.on_error.as_ref().map(|update| update.status.clone());
+        transitions.push((
+            "error".to_string(),
+            on_error.unwrap_or("failed".to_string()),
+        ));
+        let on_kill = self.on_kill.as_ref().map(|update| update.status.clone());
+        transitions.push(("kill".to_string(), on_kill.unwrap_or("failed".to_string())));
+        transitions
+    }
+
     pub fn graceful_timeout(&self) -> Option<Duration> {
         self.timeout
     }
@@ -274,6 +330,76 @@ fn extract_script_output(stdout: String) -> Option<String> {
     None
 }
 
+/// Define how to retry a script which failed
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// The maximum number of retries
+    pub max: u32,
+
+    /// How the delay between two attempts evolves
+    pub backoff: Backoff,
+
+    /// The delay before the first retry
+    pub delay: Duration,
+
+    /// The ranges of exit codes for which a retry is attempted (any non-zero exit code if empty)
+    pub on: Vec<(u8, u8)>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backoff {
+    /// The delay between two attempts is constant
+    #[default]
+    Fixed,
+
+    /// The delay between two attempts is doubled after each attempt
+    Exponential,
+}
+
+impl RetryPolicy {
+    /// Return the delay to wait before a retry, if the outcome of the given attempt (starting at 1) can be retried
+    ///
+    /// A script is retried only if it returned one of the retryable exit codes
+    /// or, when no exit codes are specified, if it returned a non-zero exit code or has been killed.
+    pub fn retry_delay(
+        &self,
+        outcome: &std::io::Result<std::process::Output>,
+        attempt: u32,
+    ) -> Option<Duration> {
+        if attempt == 0 || attempt > self.max {
+            return None;
+        }
+
+        let retryable = match outcome {
+            Ok(output) => match output.status.code() {
+                Some(0) => false,
+                Some(code) => {
+                    let code = code as u8;
+                    self.on.is_empty()
+                        || self
+                            .on
+                            .iter()
+                            .any(|(from, to)| *from <= code && code <= *to)
+                }
+                None => self.on.is_empty(),
+            },
+            Err(_) => false,
+        };
+        if !retryable {
+            return None;
+        }
+
+        match self.backoff {
+            Backoff::Fixed => Some(self.delay),
+            Backoff::Exponential => {
+                let factor = 2u32.saturating_pow(attempt - 1);
+                Some(self.delay.saturating_mul(factor))
+            }
+        }
+    }
+}
+
 /// Define how to handle a background script
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct BgExitHandlers {
@@ -317,6 +443,44 @@ mod tests {
     use serde_json::json;
     use std::process::Command;
 
+    #[test]
+    fn retry_failed_scripts() {
+        let retry = RetryPolicy {
+            max: 3,
+            backoff: Backoff::Exponential,
+            delay: Duration::from_secs(2),
+            on: vec![(1, 1), (5, 10)],
+        };
+        let exit = |code: i32| {
+            Command::new("sh")
+                .arg("-c")
+                .arg(format!("exit {code}"))
+                .output()
+        };
+
+        assert_eq!(retry.retry_delay(&exit(1), 1), Some(Duration::from_secs(2)));
+        assert_eq!(retry.retry_delay(&exit(7), 2), Some(Duration::from_secs(4)));
+        assert_eq!(
+            retry.retry_delay(&exit(10), 3),
+            Some(Duration::from_secs(8))
+        );
+
+        // Too many attempts
+        assert_eq!(retry.retry_delay(&exit(1), 4), None);
+
+        // Non retryable exit codes
+        assert_eq!(retry.retry_delay(&exit(0), 1), None);
+        assert_eq!(retry.retry_delay(&exit(2), 1), None);
+
+        let retry = RetryPolicy {
+            backoff: Backoff::Fixed,
+            on: vec![],
+            ..retry
+        };
+        assert_eq!(retry.retry_delay(&exit(2), 1), Some(Duration::from_secs(2)));
+        assert_eq!(retry.retry_delay(&exit(2), 3), Some(Duration::from_secs(2)));
+    }
+
     #[test]
     fn successful_exit_code_determines_next_state() {
         let file = r#"
@@ -1,3 +1,4 @@
+use crate::mqtt_topics::OperationType;
 use crate::workflow::ExitHandlers;
 use crate::workflow::WorkflowExecutionError;
 use mqtt_channel::Message;
@@ -109,6 +110,92 @@ impl GenericCommandState {
         }
     }
 
+    /// Resume this command with the outcome of a sub-operation, moving to the given next state
+    ///
+    /// Only the values given by the output template, extracted from the sub-operation state,
+    /// are injected into this command payload.
+    pub fn resume_with_sub_operation_outcome(
+        mut self,
+        sub_operation: &GenericCommandState,
+        output: &Value,
+        update: GenericStateUpdate,
+    ) -> Self {
+        if let (Some(values), Value::Object(new_values)) = (
+            self.payload.as_object_mut(),
+            sub_operation.inject_values_into_template(output),
+        ) {
+            for (k, v) in new_values {
+                if k != "status" {
+                    values.insert(k, v);
+                }
+            }
+        }
+        self.update(update)
+    }
+
+    /// Build the initial state of a sub-operation triggered by this command
+    ///
+    /// The sub-operation command targets the same entity as this command,
+    /// and is given a command id derived from this command operation and id,
+    /// e.g. `te/device/main///cmd/restart/sub-software_update-1234`.
+    pub fn sub_operation_init_state(
+        &self,
+        sub_operation: &OperationType,
+        input: Value,
+    ) -> Option<GenericCommandState> {
+        let root = self.topic.name.split('/').next()?;
+        let target = self.target()?;
+        let operation = self.operation()?;
+        let cmd_id = self.cmd_id()?;
+        let topic = Topic::new_unchecked(&format!(
+            "{root}/{target}/cmd/{sub_operation}/sub-{operation}-{cmd_id}"
+        ));
+
+        let mut payload = match input {
+            Value::Object(_) => input,
+            _ => json!({}),
+        };
+        GenericCommandState::inject_text_property(&mut payload, "status", "init");
+
+        Some(GenericCommandState {
+            topic,
+            status: "init".to_string(),
+            payload,
+        })
+    }
+
+    /// Return the number of the current attempt to execute the action of the current state, starting at 1
+    ///
+    /// The attempts are recorded in the command payload, per state: `"attempts": { "<state>": <count> }`
+    pub fn attempt(&self) -> u32 {
+        self.payload
+            .get("attempts")
+            .and_then(|attempts| attempts.get(&self.status))
+            .and_then(|attempt| attempt.as_u64())
+            .map(|attempt| attempt as u32)
+            .unwrap_or(1)
+    }
+
+    /// Record a new attempt to execute the action of the current state
+    pub fn retry(mut self) -> Self {
+        let attempt = self.attempt() + 1;
+        if let Some(payload) = self.payload.as_object_mut() {
+            let attempts = payload.entry("attempts").or_insert_with(|| json!({}));
+            if !attempts.is_object() {
+                *attempts = json!({});
+            }
+            if let Some(attempts) = attempts.as_object_mut() {
+                attempts.insert(self.status.clone(), attempt.into());
+            }
+        }
+        self
+    }
+
+    /// Return true if this command reached one of the terminal states: `successful` or `failed`
+    pub fn is_terminal(&self) -> bool {
+        self.status == "successful" || self.status == "failed"
+    }
+
     /// Return the error reason if any
     pub fn failure_reason(&self) -> Option<String> {
         GenericCommandState::extract_text_property(&self.payload, "reason")
@@ -145,11 +232,43 @@ impl GenericCommandState {
     /// `${.payload.unknown}` -> `${.payload.unknown}` unchanged
     /// `Not a variable pattern` -> `Not a variable pattern` unchanged
     pub fn inject_parameter(&self, script_parameter: &str) -> String {
+        self.extract_parameter(script_parameter)
+            .unwrap_or_else(|| script_parameter.to_string())
+    }
+
+    /// Extract the value of a `${...}` path from the command state, if any
+    pub fn extract_parameter(&self, script_parameter: &str) -> Option<String> {
         script_parameter
             .strip_prefix("${")
             .and_then(|s| s.strip_suffix('}'))
             .and_then(|path| self.extract(path))
-            .unwrap_or_else(|| script_parameter.to_string())
+    }
+
+    /// Inject values extracted from the message payload into all the string values of a JSON template
+    pub fn inject_values_into_template(&self, template: &Value) -> Value {
+        match template {
+            Value::String(s) => {
+                // Preserve the type of the values extracted from the payload
+                let json_value = s
+                    .strip_prefix("${.payload.")
+                    .and_then(|s| s.strip_suffix('}'))
+                    .and_then(|path| json_value_excerpt(&self.payload, path));
+                json_value.unwrap_or_else(|| Value::String(self.inject_parameter(s)))
+            }
+            Value::Array(values) => Value::Array(
+                values
+                    .iter()
+                    .map(|v| self.inject_values_into_template(v))
+                    .collect(),
+            ),
+            Value::Object(values) => Value::Object(
+                values
+                    .iter()
+                    .map(|(k, v)| (k.clone(), self.inject_values_into_template(v)))
+                    .collect(),
+            ),
+            _ => template.clone(),
+        }
     }
 
     fn extract(&self, path: &str) -> Option<String> {
@@ -179,7 +298,8 @@ impl GenericCommandState {
         }
     }
 
-    fn operation(&self) -> Option<String> {
+    /// Return the operation of this command, as extracted from the command topic
+    pub fn operation(&self) -> Option<String> {
         match self.topic.name.split('/').collect::<Vec<&str>>()[..] {
             [_, _, _, _, _, "cmd", operation, _] => Some(operation.to_string()),
             _ => None,
@@ -271,6 +391,15 @@ fn json_excerpt(value: &Value, path: &str) -> Option<String> {
     }
 }
 
+fn json_value_excerpt(value: &Value, path: &str) -> Option<Value> {
+    match path.split_once('.') {
+        None => value.get(path).cloned(),
+        Some((key, path)) => value
+            .get(key)
+            .and_then(|value| json_value_excerpt(value, path)),
+    }
+}
+
 fn json_as_string(value: &Value) -> String {
     match value {
         Value::Null => "null".to_string(),
@@ -402,6 +531,96 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sub_operation_init_state() {
+        let topic = Topic::new_unchecked("te/device/main///cmd/software_update/123");
+        let payload = r#"{ "status":"restart", "reason":"new kernel", "modules": [] }"#;
+        let command = mqtt_channel::Message::new(&topic, payload);
+        let cmd = GenericCommandState::from_command_message(&command)
+            .expect("parsing error")
+            .expect("no message");
+
+        let input = json!({
+            "reason": "${.payload.reason}",
+            "modules": "${.payload.modules}",
+            "origin": "${.topic.operation}",
+        });
+        let sub_cmd = cmd
+            .sub_operation_init_state(
+                &OperationType::Restart,
+                cmd.inject_values_into_template(&input),
+            )
+            .unwrap();
+        assert_eq!(
+            sub_cmd,
+            GenericCommandState {
+                topic: Topic::new_unchecked("te/device/main///cmd/restart/sub-software_update-123"),
+                status: "init".to_string(),
+                payload: json!({
+                    "status": "init",
+                    "reason": "new kernel",
+                    "modules": [],
+                    "origin": "software_update",
+                })
+            }
+        );
+
+        // Only the values given by the output template are injected into the parent command
+        let sub_cmd = sub_cmd.fail_with("restart failed".to_string()).retry();
+        let output = json!({ "restart_error": "${.payload.reason}" });
+        let resumed_cmd = cmd.clone().resume_with_sub_operation_outcome(
+            &sub_cmd,
+            &output,
+            GenericStateUpdate::failed("cannot restart".to_string()),
+        );
+        assert_eq!(
+            resumed_cmd.payload,
+            json!({
+                "status": "failed",
+                "reason": "cannot restart",
+                "modules": [],
+                "restart_error": "restart failed",
+            })
+        );
+
+        // The reason of the parent command is kept if none is given
+        let resumed_cmd = cmd.resume_with_sub_operation_outcome(
+            &sub_cmd,
+            &json!({}),
+            "restarted".to_string().into(),
+        );
+        assert_eq!(
+            resumed_cmd.payload,
+            json!({ "status": "restarted", "reason": "new kernel", "modules": [] })
+        );
+    }
+
+    #[test]
+    fn record_attempts_per_state() {
+        let topic = Topic::new_unchecked("te/device/main///cmd/make_it/123");
+        let cmd = GenericCommandState {
+            topic,
+            status: "download".to_string(),
+            payload: json!({"status": "download"}),
+        };
+        assert_eq!(cmd.attempt(), 1);
+
+        let cmd = cmd.retry().retry();
+        assert_eq!(cmd.attempt(), 3);
+        assert_eq!(
+            cmd.payload,
+            json!({"status": "download", "attempts": { "download": 3 }})
+        );
+
+        let cmd = cmd.move_to("install".to_string());
+        assert_eq!(cmd.attempt(), 1);
+        let cmd = cmd.retry();
+        assert_eq!(
+            cmd.payload,
+            json!({"status": "install", "attempts": { "download": 3, "install": 2 }})
+        );
+    }
+
     trait JsonContent {
         fn to_json(self) -> Value;
     }
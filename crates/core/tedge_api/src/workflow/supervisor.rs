@@ -6,6 +6,26 @@ use log::info;
 pub struct WorkflowSupervisor {
     /// The user-defined operation workflow definitions
     workflows: HashMap<OperationType, OperationWorkflow>,
+
+    /// The commands awaiting the completion of a sub-operation,
+    /// indexed by the topic name of the sub-operation command
+    pending_sub_operations: HashMap<String, GenericCommandState>,
+
+    /// The latest state of the commands in progress, indexed by command topic name
+    ///
+    /// This is the state for which an action has been triggered,
+    /// and the one to be persisted to resume the command after an agent restart.
+    commands: HashMap<String, GenericCommandState>,
+}
+
+/// What to do with a command that was in progress when the agent stopped
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PendingCommand {
+    /// The action of the current state can be safely re-triggered
+    Resume(GenericCommandState, OperationAction),
+
+    /// The action of the current state has been interrupted and the command has to be marked as failed
+    Fail(GenericCommandState),
 }
 
 impl WorkflowSupervisor {
@@ -70,4 +90,390 @@ impl WorkflowSupervisor {
             })
             .and_then(|workflow| OperationWorkflow::get_operation_current_action(workflow, status))
     }
+
+    /// Create the initial state of a sub-operation triggered by a command,
+    /// and register this command as awaiting the completion of the sub-operation.
+    pub fn start_sub_operation(
+        &mut self,
+        command: &GenericCommandState,
+        sub_operation: &OperationType,
+        input: serde_json::Value,
+    ) -> Option<GenericCommandState> {
+        let sub_command = command.sub_operation_init_state(sub_operation, input)?;
+        self.pending_sub_operations
+            .insert(sub_command.topic.name.clone(), command.clone());
+        Some(sub_command)
+    }
+
+    /// Resume the command awaiting the completion of a sub-operation, if any.
+    ///
+    /// Returns:
+    /// - `Some(state)` when the sub-operation reached a terminal state,
+    ///    with `state` the new state of the command which triggered that sub-operation.
+    /// - `None` if the sub-operation is still in progress or has not been triggered by a known command.
+    pub fn resume_parent_operation(
+        &mut self,
+        sub_command: &GenericCommandState,
+    ) -> Option<GenericCommandState> {
+        if !sub_command.is_terminal() {
+            return None;
+        }
+        let parent = self
+            .pending_sub_operations
+            .remove(&sub_command.topic.name)?;
+        let operation: OperationType = parent.operation()?.as_str().into();
+        let action = self
+            .workflows
+            .get(&operation)
+            .and_then(|workflow| workflow.states.get(&parent.status));
+        let (output, update) = match action {
+            Some(OperationAction::Operation {
+                operation: sub_operation,
+                output,
+                on_success,
+                on_error,
+                ..
+            }) => {
+                if sub_command.status == "successful" {
+                    (output, on_success.clone())
+                } else {
+                    let mut update = on_error.clone();
+                    if update.reason.is_none() {
+                        update.reason = Some(match sub_command.failure_reason() {
+                            Some(reason) => format!("{sub_operation} failed: {reason}"),
+                            None => format!("{sub_operation} failed"),
+                        });
+                    }
+                    (output, update)
+                }
+            }
+            _ => {
+                let reason = format!(
+                    "No sub-operation is expected in {} state of {operation} operation",
+                    parent.status
+                );
+                return Some(parent.fail_with(reason));
+            }
+        };
+        Some(parent.resume_with_sub_operation_outcome(sub_command, output, update))
+    }
+
+    /// Record the new state of a command, for which the action is about to be triggered.
+    ///
+    /// Returns `false` if this state has already been recorded, i.e. if the action has already been triggered.
+    /// Such a duplicate is notably received when the agent restarts and receives retained command messages.
+    /// The builtin actions are never reported as duplicates, as the builtin actors manage their own state.
+    pub fn record_command_state(
+        &mut self,
+        state: &GenericCommandState,
+        action: &OperationAction,
+    ) -> bool {
+        let topic = state.topic.name.clone();
+        match action {
+            OperationAction::Clear => {
+                self.commands.remove(&topic);
+                true
+            }
+            OperationAction::BuiltIn => {
+                self.commands.insert(topic, state.clone());
+                true
+            }
+            _ => self.commands.insert(topic, state.clone()).as_ref() != Some(state),
+        }
+    }
+
+    /// Return true if the given state is the latest recorded state of the command
+    ///
+    /// Used to ignore a timeout or a retry set on a state which the command has already left.
+    pub fn is_current_state(&self, state: &GenericCommandState) -> bool {
+        self.commands.get(&state.topic.name) == Some(state)
+    }
+
+    /// The timeout set on the current state of a command, if any
+    pub fn state_timeout(&self, state: &GenericCommandState) -> Option<StateTimeout> {
+        let operation: OperationType = state.operation()?.as_str().into();
+        self.workflows
+            .get(&operation)?
+            .timeouts
+            .get(&state.status)
+            .cloned()
+    }
+
+    /// Remove a command that has been fully processed, i.e. which topic has been cleared
+    pub fn remove_command(&mut self, topic: &str) {
+        self.commands.remove(topic);
+    }
+
+    /// The latest state of all the commands in progress
+    pub fn pending_commands(&self) -> Vec<GenericCommandState> {
+        // To ease testing the commands are returned in a deterministic order
+        let mut commands = self.commands.values().cloned().collect::<Vec<_>>();
+        commands.sort_by(|a, b| a.topic.name.cmp(&b.topic.name));
+        commands
+    }
+
+    /// Load the commands that were in progress when the agent stopped,
+    /// returning those that have to be resumed or failed.
+    ///
+    /// - The commands waiting for a sub-operation are registered as such, and not returned.
+    /// - The commands processed by builtin actions, delegated or restarting the device are left untouched,
+    ///   as they are resumed by the builtin actors, the delegates and the restart manager.
+    /// - The commands which action has no side effect are resumed.
+    /// - The commands which action has been interrupted, i.e. scripts, are failed.
+    pub fn load_pending_commands(
+        &mut self,
+        commands: Vec<GenericCommandState>,
+    ) -> Vec<PendingCommand> {
+        let mut pending_commands = Vec::new();
+        for state in commands {
+            let Some(operation) = state.operation() else {
+                continue;
+            };
+            let operation: OperationType = operation.as_str().into();
+            let Some(action) = self
+                .workflows
+                .get(&operation)
+                .and_then(|workflow| workflow.states.get(&state.status))
+                .map(|action| action.inject_state(&state))
+            else {
+                continue;
+            };
+            self.commands
+                .insert(state.topic.name.clone(), state.clone());
+
+            match action {
+                OperationAction::Operation {
+                    operation, input, ..
+                } => {
+                    self.start_sub_operation(&state, &operation, input);
+                }
+                OperationAction::MoveTo(_) | OperationAction::Evaluate(_) => {
+                    pending_commands.push(PendingCommand::Resume(state, action))
+                }
+                OperationAction::Script(_, _) | OperationAction::BgScript(_, _) => {
+                    let reason = format!("Agent restarted during {} state", state.status);
+                    pending_commands.push(PendingCommand::Fail(state.fail_with(reason)))
+                }
+                OperationAction::BuiltIn
+                | OperationAction::Delegate(_)
+                | OperationAction::Restart { .. }
+                | OperationAction::Clear => {}
+            }
+        }
+        pending_commands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mqtt_channel::Topic;
+    use serde_json::json;
+
+    #[test]
+    fn resume_command_on_sub_operation_completion() {
+        let workflow: OperationWorkflow = toml::from_str(
+            r#"
+operation = "software_update"
+
+[init]
+action = "proceed"
+on_success = "scheduled"
+
+[scheduled]
+action = "builtin"
+on_success = "executing"
+
+[executing]
+action = "builtin"
+on_success = "restart"
+
+[restart]
+operation = "restart"
+on_success = "successful"
+on_error = "failed_restart"
+
+[successful]
+action = "cleanup"
+
+[failed]
+action = "cleanup"
+"#,
+        )
+        .unwrap();
+        let mut supervisor = WorkflowSupervisor::default();
+        supervisor.register_custom_workflow(workflow).unwrap();
+
+        let command = GenericCommandState {
+            topic: Topic::new_unchecked("te/device/main///cmd/software_update/123"),
+            status: "restart".to_string(),
+            payload: json!({"status": "restart"}),
+        };
+        let sub_command = supervisor
+            .start_sub_operation(&command, &OperationType::Restart, json!({}))
+            .unwrap();
+        assert_eq!(
+            sub_command.topic.name,
+            "te/device/main///cmd/restart/sub-software_update-123"
+        );
+
+        // Nothing to resume till the sub-operation is not completed
+        let sub_command = sub_command.move_to("executing".to_string());
+        assert_eq!(supervisor.resume_parent_operation(&sub_command), None);
+
+        let sub_command = sub_command.fail_with("no reboot".to_string());
+        let resumed_command = supervisor.resume_parent_operation(&sub_command).unwrap();
+        assert_eq!(resumed_command.topic, command.topic);
+        assert_eq!(resumed_command.status, "failed_restart");
+        assert_eq!(
+            resumed_command.failure_reason().unwrap(),
+            "restart failed: no reboot"
+        );
+
+        // The parent command is resumed only once
+        assert_eq!(supervisor.resume_parent_operation(&sub_command), None);
+    }
+
+    #[test]
+    fn ignore_already_processed_command_states() {
+        let mut supervisor = WorkflowSupervisor::default();
+        let state = GenericCommandState {
+            topic: Topic::new_unchecked("te/device/main///cmd/do_something/123"),
+            status: "init".to_string(),
+            payload: json!({"status": "init"}),
+        };
+        let action = OperationAction::MoveTo("scheduled".to_string());
+
+        assert!(supervisor.record_command_state(&state, &action));
+        assert!(!supervisor.record_command_state(&state, &action));
+        assert_eq!(supervisor.pending_commands(), vec![state.clone()]);
+
+        // Builtin actions are always triggered
+        assert!(supervisor.record_command_state(&state, &OperationAction::BuiltIn));
+        assert!(supervisor.record_command_state(&state, &OperationAction::BuiltIn));
+
+        // Terminal states are removed
+        let state = state.move_to("successful".to_string());
+        assert!(supervisor.record_command_state(&state, &OperationAction::Clear));
+        assert_eq!(supervisor.pending_commands(), vec![]);
+    }
+
+    #[test]
+    fn timeouts_are_set_on_current_states() {
+        let mut workflows = WorkflowSupervisor::default();
+        let workflow: OperationWorkflow = toml::from_str(
+            r#"
+operation = "check"
+[init]
+action = "proceed"
+on_success = "waiting"
+[waiting]
+action = "waiting some-participant"
+timeout_second = 60
+on_timeout = "failed"
+on_success = "successful"
+[successful]
+action = "cleanup"
+[failed]
+action = "cleanup"
+"#,
+        )
+        .unwrap();
+        workflows.register_custom_workflow(workflow).unwrap();
+
+        let topic = Topic::new_unchecked("te/device/main///cmd/check/123");
+        let waiting = GenericCommandState {
+            topic: topic.clone(),
+            status: "waiting".to_string(),
+            payload: json!({"status": "waiting"}),
+        };
+        assert_eq!(
+            workflows.state_timeout(&waiting),
+            Some(StateTimeout {
+                timeout: std::time::Duration::from_secs(60),
+                on_timeout: "failed".to_string().into(),
+            })
+        );
+        assert_eq!(
+            workflows.state_timeout(&waiting.clone().move_to("init".into())),
+            None
+        );
+
+        assert!(!workflows.is_current_state(&waiting));
+        workflows.record_command_state(&waiting, &OperationAction::Delegate("x".into()));
+        assert!(workflows.is_current_state(&waiting));
+
+        let successful = waiting.clone().move_to("successful".into());
+        workflows.record_command_state(&successful, &OperationAction::Clear);
+        assert!(!workflows.is_current_state(&waiting));
+    }
+
+    #[test]
+    fn resume_or_fail_pending_commands_on_restart() {
+        let workflow: OperationWorkflow = toml::from_str(
+            r#"
+operation = "do_something"
+
+[init]
+action = "proceed"
+on_success = "scheduled"
+
+[scheduled]
+script = "/some/script.sh"
+on_success = "executing"
+
+[executing]
+action = "builtin"
+
+[restart]
+operation = "restart"
+
+[successful]
+action = "cleanup"
+
+[failed]
+action = "cleanup"
+"#,
+        )
+        .unwrap();
+        let mut supervisor = WorkflowSupervisor::default();
+        supervisor.register_custom_workflow(workflow).unwrap();
+
+        let command = |cmd_id: &str, status: &str| GenericCommandState {
+            topic: Topic::new_unchecked(&format!("te/device/main///cmd/do_something/{cmd_id}")),
+            status: status.to_string(),
+            payload: json!({ "status": status }),
+        };
+
+        let pending_commands = supervisor.load_pending_commands(vec![
+            command("1", "init"),
+            command("2", "scheduled"),
+            command("3", "executing"),
+            command("4", "restart"),
+        ]);
+        assert_eq!(
+            pending_commands,
+            vec![
+                PendingCommand::Resume(
+                    command("1", "init"),
+                    OperationAction::MoveTo("scheduled".to_string())
+                ),
+                PendingCommand::Fail(
+                    command("2", "scheduled")
+                        .fail_with("Agent restarted during scheduled state".to_string())
+                ),
+            ]
+        );
+
+        // All these commands are still in progress
+        assert_eq!(supervisor.pending_commands().len(), 4);
+
+        // The command awaiting a sub-operation is resumed on completion of the latter
+        let sub_command = command("4", "restart")
+            .sub_operation_init_state(&OperationType::Restart, json!({}))
+            .unwrap()
+            .move_to("successful".to_string());
+        let resumed_command = supervisor.resume_parent_operation(&sub_command).unwrap();
+        assert_eq!(resumed_command.status, "successful");
+    }
 }
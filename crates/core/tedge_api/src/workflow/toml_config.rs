@@ -1,13 +1,19 @@
 use crate::mqtt_topics::OperationType;
 use crate::workflow::toml_config::TomlOperationAction::Action;
+use crate::workflow::Backoff;
 use crate::workflow::BgExitHandlers;
+use crate::workflow::Condition;
+use crate::workflow::ConditionDefinitionError;
+use crate::workflow::ConditionalHandlers;
 use crate::workflow::DefaultHandlers;
 use crate::workflow::ExitHandlers;
 use crate::workflow::GenericStateUpdate;
 use crate::workflow::OperationAction;
 use crate::workflow::OperationWorkflow;
+use crate::workflow::RetryPolicy;
 use crate::workflow::ScriptDefinitionError;
 use crate::workflow::ShellScript;
+use crate::workflow::StateTimeout;
 use crate::workflow::WorkflowDefinitionError;
 use serde::de::Error;
 use serde::Deserialize;
@@ -43,23 +49,91 @@ pub struct TomlOperationWorkflow {
 #[derive(Clone, Debug, Deserialize)]
 pub struct TomlOperationState {
     /// The action driving the operation when in that state
+    ///
+    /// When no action is given, the default is to `proceed`
     #[serde(default, flatten)]
     pub action: TomlOperationAction,
 
     /// Handlers used to determine the next state from the action outcome
     #[serde(flatten)]
     pub handlers: TomlExitHandlers,
+
+    /// Conditions used to determine the next state from the command state
+    #[serde(default)]
+    pub on_condition: Vec<TomlCondition>,
+
+    /// The payload of the sub-operation triggered by an `operation` action
+    pub input: Option<serde_json::Value>,
+
+    /// The values extracted from the final state of the sub-operation triggered by an `operation` action,
+    /// to be injected into the command payload
+    pub output: Option<serde_json::Value>,
+
+    /// How to retry a failed script
+    ///
+    /// This is a state-level setting, with no workflow-level default,
+    /// as a state named `retry` would be otherwise ambiguous.
+    pub retry: Option<TomlRetryPolicy>,
+}
+
+/// User-friendly representation of a condition and the next state when this condition holds
+///
+/// `{ if = "${.payload.version} == ${.payload.current}", then = "successful" }`
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+pub struct TomlCondition {
+    #[serde(rename = "if")]
+    pub condition: String,
+
+    pub then: TomlStateUpdate,
 }
 
 /// User-friendly representation of an [OperationAction]
-#[derive(Clone, Debug, Deserialize)]
-#[serde(rename_all = "snake_case")]
+#[derive(Clone, Debug)]
 pub enum TomlOperationAction {
     Script(ShellScript),
     BackgroundScript(ShellScript),
+    Operation(OperationType),
     Action(ShellScript), // TODO use a proper BuiltAction enum
 }
 
+/// The keys used to define the action of a state, at most one being set
+///
+/// Deserializing these keys as a struct, rather than as an externally tagged enum,
+/// lets an ill-formed action be rejected, while still defaulting to `proceed` when no action is given.
+#[derive(Deserialize)]
+struct TomlActionKeys {
+    script: Option<ShellScript>,
+    background_script: Option<ShellScript>,
+    operation: Option<OperationType>,
+    action: Option<ShellScript>,
+}
+
+impl<'de> Deserialize<'de> for TomlOperationAction {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let keys = TomlActionKeys::deserialize(deserializer)?;
+        let mut actions = [
+            keys.script.map(TomlOperationAction::Script),
+            keys.background_script
+                .map(TomlOperationAction::BackgroundScript),
+            keys.operation.map(TomlOperationAction::Operation),
+            keys.action.map(TomlOperationAction::Action),
+        ]
+        .into_iter()
+        .flatten();
+
+        match (actions.next(), actions.next()) {
+            (None, _) => Ok(TomlOperationAction::default()),
+            (Some(action), None) => Ok(action),
+            (Some(_), Some(_)) => Err(D::Error::custom(
+                "only one of `script`, `background_script`, `operation` or `action` can be given",
+            )),
+        }
+    }
+}
+
 impl Default for TomlOperationAction {
     fn default() -> Self {
         Action(ShellScript {
@@ -98,15 +172,97 @@ impl TryFrom<TomlOperationState> for OperationAction {
     type Error = WorkflowDefinitionError;
 
     fn try_from(input: TomlOperationState) -> Result<Self, Self::Error> {
+        if input.input.is_some() || input.output.is_some() {
+            match &input.action {
+                TomlOperationAction::Operation(_) => {}
+                TomlOperationAction::Script(script)
+                | TomlOperationAction::BackgroundScript(script)
+                | Action(script) => {
+                    return Err(WorkflowDefinitionError::IncompatibleInput {
+                        action: script.to_string(),
+                    })
+                }
+            }
+        }
+
+        if !input.on_condition.is_empty() {
+            // Conditions are only evaluated by a `proceed` action, which can be neither retried nor given an input
+            if let Action(ShellScript { command, .. }) = &input.action {
+                if command == "proceed" && input.retry.is_some() {
+                    return Err(WorkflowDefinitionError::IncompatibleRetry {
+                        action: command.to_string(),
+                    });
+                }
+            }
+
+            return match input.action {
+                Action(ShellScript { command, .. }) if command == "proceed" => {
+                    let handlers = TryInto::<ConditionalHandlers>::try_into((
+                        input.on_condition,
+                        input.handlers.on_success,
+                    ))?;
+                    Ok(OperationAction::Evaluate(handlers))
+                }
+                TomlOperationAction::Script(script)
+                | TomlOperationAction::BackgroundScript(script)
+                | Action(script) => Err(ConditionDefinitionError::IncompatibleAction {
+                    action: script.to_string(),
+                }
+                .into()),
+                TomlOperationAction::Operation(operation) => {
+                    Err(ConditionDefinitionError::IncompatibleAction {
+                        action: operation.to_string(),
+                    }
+                    .into())
+                }
+            };
+        }
+
+        if input.retry.is_some() {
+            match &input.action {
+                TomlOperationAction::Script(_) => {}
+                TomlOperationAction::BackgroundScript(script) | Action(script) => {
+                    return Err(WorkflowDefinitionError::IncompatibleRetry {
+                        action: script.to_string(),
+                    })
+                }
+                TomlOperationAction::Operation(operation) => {
+                    return Err(WorkflowDefinitionError::IncompatibleRetry {
+                        action: operation.to_string(),
+                    })
+                }
+            }
+        }
+
         match input.action {
             TomlOperationAction::Script(script) => {
                 let handlers = TryInto::<ExitHandlers>::try_into(input.handlers)?;
-                Ok(OperationAction::Script(script, handlers))
+                let retry = input.retry.map(|retry| retry.into());
+                Ok(OperationAction::Script(script, handlers.with_retry(retry)))
             }
             TomlOperationAction::BackgroundScript(script) => {
                 let handlers = TryInto::<BgExitHandlers>::try_into(input.handlers)?;
                 Ok(OperationAction::BgScript(script, handlers))
             }
+            TomlOperationAction::Operation(operation) => {
+                let on_success: GenericStateUpdate = input
+                    .handlers
+                    .on_success
+                    .map(|u| u.into())
+                    .unwrap_or_else(|| "successful".to_string().into());
+                let on_error: GenericStateUpdate = input
+                    .handlers
+                    .on_error
+                    .map(|u| u.into())
+                    .unwrap_or_else(|| "failed".to_string().into());
+                Ok(OperationAction::Operation {
+                    operation,
+                    input: input.input.unwrap_or_else(|| serde_json::json!({})),
+                    output: input.output.unwrap_or_else(|| serde_json::json!({})),
+                    on_success,
+                    on_error,
+                })
+            }
             TomlOperationAction::Action(ShellScript { command, args }) => match command.as_str() {
                 "builtin" => Ok(OperationAction::BuiltIn),
                 "cleanup" => Ok(OperationAction::Clear),
@@ -160,8 +316,24 @@ impl TryFrom<TomlOperationWorkflow> for OperationWorkflow {
         let operation = input.operation;
         let default_handlers = TryInto::<DefaultHandlers>::try_into(input.handlers)?;
         let mut states = HashMap::new();
+        let mut timeouts = HashMap::new();
         for (state, action_spec) in input.states.into_iter() {
+            let timeout = action_spec.handlers.timeout_second.map(Duration::from_secs);
+            let on_timeout: Option<GenericStateUpdate> =
+                action_spec.handlers.on_timeout.clone().map(|u| u.into());
             let action = TryInto::<OperationAction>::try_into(action_spec)?;
+            if let (Some(timeout), true) = (timeout, action.is_not_script()) {
+                let on_timeout = on_timeout
+                    .or_else(|| default_handlers.on_timeout.clone())
+                    .unwrap_or_else(|| GenericStateUpdate::failed(format!("{state} timeout")));
+                timeouts.insert(
+                    state.clone(),
+                    StateTimeout {
+                        timeout,
+                        on_timeout,
+                    },
+                );
+            }
             states.insert(state, action.with_default(&default_handlers));
         }
 
@@ -170,10 +342,30 @@ impl TryFrom<TomlOperationWorkflow> for OperationWorkflow {
             built_in: false,
             handlers: default_handlers,
             states,
+            timeouts,
         })
     }
 }
 
+impl TryFrom<(Vec<TomlCondition>, Option<TomlStateUpdate>)> for ConditionalHandlers {
+    type Error = ConditionDefinitionError;
+
+    fn try_from(
+        (conditions, default): (Vec<TomlCondition>, Option<TomlStateUpdate>),
+    ) -> Result<Self, Self::Error> {
+        let conditions = conditions
+            .into_iter()
+            .map(|TomlCondition { condition, then }| {
+                let condition = condition.parse::<Condition>()?;
+                Ok((condition, then.into()))
+            })
+            .collect::<Result<Vec<_>, ConditionDefinitionError>>()?;
+        let default = default.map(|u| u.into());
+
+        ConditionalHandlers::try_new(conditions, default)
+    }
+}
+
 /// User-Friendly representation of an [ExitHandlers]; as used in the operation TOML definition files
 ///
 /// A user don't have to give a handler for all possible exit code.
@@ -215,13 +407,55 @@ pub struct TomlExitHandlers {
     on_exec: Option<TomlStateUpdate>,
 }
 
+/// User-friendly representation of a [RetryPolicy]
+///
+/// `retry = { max = 3, backoff = "exponential", delay_second = 5, on = [1, "5-10"] }`
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+pub struct TomlRetryPolicy {
+    max: u32,
+
+    #[serde(default)]
+    backoff: Backoff,
+
+    #[serde(default = "default_retry_delay_second")]
+    delay_second: u64,
+
+    #[serde(default)]
+    on: Vec<ExitCodes>,
+}
+
+fn default_retry_delay_second() -> u64 {
+    1
+}
+
+impl From<TomlRetryPolicy> for RetryPolicy {
+    fn from(value: TomlRetryPolicy) -> Self {
+        let on = value
+            .on
+            .into_iter()
+            .map(|code| match code {
+                ExitCodes::Code(x) => (x, x),
+                ExitCodes::Range { from, to } => (from, to),
+                ExitCodes::AnyError => (1, u8::MAX),
+            })
+            .collect();
+        RetryPolicy {
+            max: value.max,
+            backoff: value.backoff,
+            delay: Duration::from_secs(value.delay_second),
+            on,
+        }
+    }
+}
+
 impl TryFrom<TomlExitHandlers> for ExitHandlers {
     type Error = ScriptDefinitionError;
 
     fn try_from(value: TomlExitHandlers) -> Result<Self, Self::Error> {
         let on_error = value.on_error.map(|u| u.into());
         let on_success = value.on_success.map(|u| u.into());
-        let on_kill = value.on_kill.map(|u| u.into());
+        // A script is killed when it times out
+        let on_kill = value.on_kill.or(value.on_timeout).map(|u| u.into());
         let on_stdout = value.on_stdout;
         let wildcard = value
             .on_exit
@@ -284,10 +518,19 @@ impl<'de> Deserialize<'de> for ExitCodes {
     where
         D: Deserializer<'de>,
     {
-        let exit_code = String::deserialize(deserializer)?;
-        exit_code
-            .parse()
-            .map_err(|err| D::Error::custom(format!("invalid exit: {exit_code}: {err}")))
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum ExitCode {
+            Code(u8),
+            Text(String),
+        }
+
+        match ExitCode::deserialize(deserializer)? {
+            ExitCode::Code(code) => Ok(ExitCodes::Code(code)),
+            ExitCode::Text(exit_code) => exit_code
+                .parse()
+                .map_err(|err| D::Error::custom(format!("invalid exit: {exit_code}: {err}"))),
+        }
     }
 }
 
@@ -529,4 +772,273 @@ on_error = "failed_reboot"
             Some(TomlStateUpdate::Simple("timeout".to_string()))
         );
     }
+
+    #[test]
+    fn parse_retry_policy() {
+        let file = r#"
+script = "/some/download.sh"
+retry = { max = 3, backoff = "exponential", delay_second = 5, on = [1, "5-10"] }
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        let retry: RetryPolicy = input.retry.clone().unwrap().into();
+        assert_eq!(
+            retry,
+            RetryPolicy {
+                max: 3,
+                backoff: Backoff::Exponential,
+                delay: Duration::from_secs(5),
+                on: vec![(1, 1), (5, 10)],
+            }
+        );
+
+        let file = r#"
+script = "/some/download.sh"
+retry = { max = 2 }
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        let retry: RetryPolicy = input.retry.clone().unwrap().into();
+        assert_eq!(
+            retry,
+            RetryPolicy {
+                max: 2,
+                backoff: Backoff::Fixed,
+                delay: Duration::from_secs(1),
+                on: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn forbid_retry_on_non_script_states() {
+        let file = r#"
+background_script = "/some/script.sh"
+retry = { max = 3 }
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        let error = TryInto::<OperationAction>::try_into(input).unwrap_err();
+        assert_eq!(
+            error,
+            WorkflowDefinitionError::IncompatibleRetry {
+                action: "/some/script.sh".to_string()
+            }
+        );
+
+        let file = r#"
+operation = "restart"
+retry = { max = 3 }
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        assert!(TryInto::<OperationAction>::try_into(input).is_err());
+
+        let file = r#"
+action = "builtin"
+retry = { max = 3 }
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        assert!(TryInto::<OperationAction>::try_into(input).is_err());
+    }
+
+    #[test]
+    fn parse_state_timeouts() {
+        let file = r#"
+operation = "check"
+on_timeout = "timeout"
+
+[init]
+script = "/some/script.sh"
+timeout_second = 10
+on_timeout = "script_timeout"
+on_success = "waiting"
+
+[waiting]
+action = "waiting some-participant"
+timeout_second = 60
+on_success = "successful"
+
+[successful]
+action = "cleanup"
+"#;
+        let workflow: OperationWorkflow = toml::from_str(file).unwrap();
+        assert_eq!(
+            workflow.timeouts,
+            HashMap::from([(
+                "waiting".to_string(),
+                StateTimeout {
+                    timeout: Duration::from_secs(60),
+                    on_timeout: "timeout".to_string().into(),
+                }
+            )])
+        );
+
+        // The script is killed on timeout
+        let Some(OperationAction::Script(_, handlers)) = workflow.states.get("init") else {
+            panic!("Expecting a script")
+        };
+        assert_eq!(
+            handlers.state_update_on_kill("/some/script.sh", 9).status,
+            "script_timeout"
+        );
+    }
+
+    #[test]
+    fn parse_conditional_state() {
+        let file = r#"
+on_condition = [
+    { if = "${.payload.version} == ${.payload.current}", then = "successful" },
+    { if = "${.payload.force}", then = { status = "install", reason = "forced" } },
+]
+on_success = "check_version"
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        assert_eq!(
+            input.on_condition,
+            vec![
+                TomlCondition {
+                    condition: "${.payload.version} == ${.payload.current}".to_string(),
+                    then: TomlStateUpdate::Simple("successful".to_string()),
+                },
+                TomlCondition {
+                    condition: "${.payload.force}".to_string(),
+                    then: TomlStateUpdate::Detailed(GenericStateUpdate {
+                        status: "install".to_string(),
+                        reason: Some("forced".to_string())
+                    }),
+                },
+            ]
+        );
+
+        let action = TryInto::<OperationAction>::try_into(input).unwrap();
+        assert!(matches!(action, OperationAction::Evaluate(_)));
+    }
+
+    #[test]
+    fn parse_sub_operation_state() {
+        let file = r#"
+operation = "restart"
+input.reason = "${.payload.reason}"
+output.restarted_at = "${.payload.time}"
+on_success = "restarted"
+on_error = { status = "failed", reason = "restart failed" }
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        let action = TryInto::<OperationAction>::try_into(input).unwrap();
+        assert_eq!(
+            action,
+            OperationAction::Operation {
+                operation: OperationType::Restart,
+                input: serde_json::json!({ "reason": "${.payload.reason}" }),
+                output: serde_json::json!({ "restarted_at": "${.payload.time}" }),
+                on_success: "restarted".to_string().into(),
+                on_error: GenericStateUpdate {
+                    status: "failed".to_string(),
+                    reason: Some("restart failed".to_string()),
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn reject_invalid_actions() {
+        // Ill-typed action
+        let file = r#"
+operation = 42
+on_success = "successful"
+"#;
+        assert!(toml::from_str::<TomlOperationState>(file).is_err());
+
+        // Ill-formed script
+        let file = r#"
+script = "/some/script.sh 'unclosed"
+"#;
+        assert!(toml::from_str::<TomlOperationState>(file).is_err());
+
+        // Several actions
+        let file = r#"
+script = "/some/script.sh"
+action = "proceed"
+"#;
+        assert!(toml::from_str::<TomlOperationState>(file).is_err());
+    }
+
+    #[test]
+    fn no_action_is_to_proceed() {
+        let file = r#"
+on_success = "next"
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        let action = TryInto::<OperationAction>::try_into(input).unwrap();
+        assert_eq!(action, OperationAction::MoveTo("next".to_string()));
+    }
+
+    #[test]
+    fn reject_ill_formed_condition() {
+        let file = r#"
+on_condition = [{ if = "${.payload.version} ==", then = "successful" }]
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        let error = TryInto::<OperationAction>::try_into(input).unwrap_err();
+        assert_eq!(
+            error,
+            WorkflowDefinitionError::ConditionDefinitionError(
+                ConditionDefinitionError::SyntaxError {
+                    expression: "${.payload.version} ==".to_string(),
+                    reason: "missing right operand for ==".to_string()
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn forbid_retry_and_input_on_conditional_states() {
+        let file = r#"
+on_condition = [{ if = "${.payload.force}", then = "install" }]
+retry = { max = 3 }
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        let error = TryInto::<OperationAction>::try_into(input).unwrap_err();
+        assert_eq!(
+            error,
+            WorkflowDefinitionError::IncompatibleRetry {
+                action: "proceed".to_string()
+            }
+        );
+
+        let file = r#"
+on_condition = [{ if = "${.payload.force}", then = "install" }]
+input = { force = true }
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        let error = TryInto::<OperationAction>::try_into(input).unwrap_err();
+        assert_eq!(
+            error,
+            WorkflowDefinitionError::IncompatibleInput {
+                action: "proceed".to_string()
+            }
+        );
+
+        let file = r#"
+script = "/some/script.sh"
+input = { force = true }
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        assert!(TryInto::<OperationAction>::try_into(input).is_err());
+    }
+
+    #[test]
+    fn forbid_conditions_on_script_states() {
+        let file = r#"
+script = "/some/script.sh"
+on_condition = [{ if = "${.payload.force}", then = "install" }]
+"#;
+        let input: TomlOperationState = toml::from_str(file).unwrap();
+        let error = TryInto::<OperationAction>::try_into(input).unwrap_err();
+        assert_eq!(
+            error,
+            WorkflowDefinitionError::ConditionDefinitionError(
+                ConditionDefinitionError::IncompatibleAction {
+                    action: "/some/script.sh".to_string(),
+                }
+            )
+        );
+    }
 }
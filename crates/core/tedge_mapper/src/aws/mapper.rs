@@ -1,5 +1,8 @@
 use crate::core::component::TEdgeComponent;
+use crate::core::mapper::cloud_queue;
 use crate::core::mapper::start_basic_actors;
+use crate::core::mapper::start_built_in_bridge;
+use crate::core::mapper::CloudConnection;
 use async_trait::async_trait;
 use aws_mapper_ext::converter::AwsConverter;
 use clock::WallClock;
@@ -9,31 +12,61 @@ use tedge_actors::ConvertingActor;
 use tedge_actors::MessageSink;
 use tedge_actors::MessageSource;
 use tedge_actors::NoConfig;
+use tedge_api::mqtt_topics::ChannelFilter;
+use tedge_api::mqtt_topics::EntityFilter;
 use tedge_api::mqtt_topics::MqttSchema;
+use tedge_cloud_queue::Priority;
 use tedge_config::TEdgeConfig;
+use tedge_mqtt_ext::MqttMessage;
 use tracing::warn;
 
-const AWS_MAPPER_NAME: &str = "tedge-mapper-aws";
+const ENTITY_JOURNAL: &str = "entity_store.jsonl";
 
-pub struct AwsMapper;
+pub struct AwsMapper {
+    connection: CloudConnection,
+}
+
+impl AwsMapper {
+    pub fn new(connection: CloudConnection) -> Self {
+        AwsMapper { connection }
+    }
+}
 
 #[async_trait]
 impl TEdgeComponent for AwsMapper {
     fn session_name(&self) -> &str {
-        AWS_MAPPER_NAME
+        &self.connection.mapper_name
     }
 
     async fn start(
         &self,
         tedge_config: TEdgeConfig,
-        _config_dir: &Path,
+        config_dir: &Path,
     ) -> Result<(), anyhow::Error> {
-        let (mut runtime, mut mqtt_actor) =
-            start_basic_actors(self.session_name(), &tedge_config).await?;
+        let (mut runtime, mut mqtt_actor) = start_basic_actors(
+            self.session_name(),
+            &tedge_config,
+            self.connection.topic_prefixes(&tedge_config),
+        )
+        .await?;
+        start_built_in_bridge(
+            &mut runtime,
+            &tedge_config,
+            config_dir,
+            &self.connection.bridge_config_file(),
+        )
+        .await?;
         let clock = Box::new(WallClock);
         let mqtt_schema = MqttSchema::with_root(tedge_config.mqtt.topic_root.clone());
         let aws_converter =
-            AwsConverter::new(tedge_config.aws.mapper.timestamp, clock, mqtt_schema);
+            AwsConverter::new(tedge_config.aws.mapper.timestamp, clock, mqtt_schema)
+                .with_entity_journal(
+                    tedge_config
+                        .data
+                        .path
+                        .join(format!(".{}", self.session_name()))
+                        .join(ENTITY_JOURNAL),
+                )?;
         let mut aws_converting_actor = ConvertingActor::builder(
             "AwsConverter",
             aws_converter,
@@ -41,9 +74,22 @@ impl TEdgeComponent for AwsMapper {
         );
 
         aws_converting_actor.add_input(&mut mqtt_actor);
-        aws_converting_actor.register_peer(NoConfig, mqtt_actor.get_sender());
+        let cloud_queue = cloud_queue(
+            self.session_name(),
+            "aws",
+            &tedge_config,
+            aws_priority,
+            &mut mqtt_actor,
+        )?;
+        match &cloud_queue {
+            Some(queue) => aws_converting_actor.register_peer(NoConfig, queue.get_sender()),
+            None => aws_converting_actor.register_peer(NoConfig, mqtt_actor.get_sender()),
+        }
 
         runtime.spawn(aws_converting_actor).await?;
+        if let Some(queue) = cloud_queue {
+            runtime.spawn(queue).await?;
+        }
         runtime.spawn(mqtt_actor).await?;
         runtime.run_to_completion().await?;
         Ok(())
@@ -57,5 +103,20 @@ fn get_topic_filter(tedge_config: &TEdgeConfig) -> TopicFilter {
             warn!("The configured topic '{topic}' is invalid and ignored.");
         }
     }
+
+    // The entity registrations are required to forward deregistrations,
+    // whatever the configured topics
+    let mqtt_schema = MqttSchema::with_root(tedge_config.mqtt.topic_root.clone());
+    topics.add_all(mqtt_schema.topics(EntityFilter::AnyEntity, ChannelFilter::EntityMetadata));
     topics
 }
+
+/// Alarms are sent first and measurements last when the bridge is back
+fn aws_priority(message: &MqttMessage) -> Priority {
+    // The telemetry topics are aws/td/{source}/{kind}/{type}
+    match message.topic.name.split('/').nth(3) {
+        Some("a") => Priority::High,
+        Some("m") => Priority::Low,
+        _ => Priority::Normal,
+    }
+}
@@ -1,5 +1,8 @@
 use crate::core::component::TEdgeComponent;
+use crate::core::mapper::cloud_queue;
 use crate::core::mapper::start_basic_actors;
+use crate::core::mapper::start_built_in_bridge;
+use crate::core::mapper::CloudConnection;
 use async_trait::async_trait;
 use az_mapper_ext::converter::AzureConverter;
 use clock::WallClock;
@@ -9,40 +12,83 @@ use tedge_actors::ConvertingActor;
 use tedge_actors::MessageSink;
 use tedge_actors::MessageSource;
 use tedge_actors::NoConfig;
+use tedge_api::mqtt_topics::ChannelFilter;
+use tedge_api::mqtt_topics::EntityFilter;
 use tedge_api::mqtt_topics::MqttSchema;
+use tedge_cloud_queue::Priority;
 use tedge_config::TEdgeConfig;
+use tedge_mqtt_ext::MqttMessage;
 use tracing::warn;
 
-const AZURE_MAPPER_NAME: &str = "tedge-mapper-az";
+const ENTITY_JOURNAL: &str = "entity_store.jsonl";
 
-pub struct AzureMapper;
+pub struct AzureMapper {
+    connection: CloudConnection,
+}
+
+impl AzureMapper {
+    pub fn new(connection: CloudConnection) -> Self {
+        AzureMapper { connection }
+    }
+}
 
 #[async_trait]
 impl TEdgeComponent for AzureMapper {
     fn session_name(&self) -> &str {
-        AZURE_MAPPER_NAME
+        &self.connection.mapper_name
     }
 
     async fn start(
         &self,
         tedge_config: TEdgeConfig,
-        _config_dir: &Path,
+        config_dir: &Path,
     ) -> Result<(), anyhow::Error> {
-        let (mut runtime, mut mqtt_actor) =
-            start_basic_actors(self.session_name(), &tedge_config).await?;
+        let (mut runtime, mut mqtt_actor) = start_basic_actors(
+            self.session_name(),
+            &tedge_config,
+            self.connection.topic_prefixes(&tedge_config),
+        )
+        .await?;
+        start_built_in_bridge(
+            &mut runtime,
+            &tedge_config,
+            config_dir,
+            &self.connection.bridge_config_file(),
+        )
+        .await?;
         let mqtt_schema = MqttSchema::with_root(tedge_config.mqtt.topic_root.clone());
         let az_converter = AzureConverter::new(
             tedge_config.az.mapper.timestamp,
             Box::new(WallClock),
             mqtt_schema,
-        );
+        )
+        .with_entity_journal(
+            tedge_config
+                .data
+                .path
+                .join(format!(".{}", self.session_name()))
+                .join(ENTITY_JOURNAL),
+        )?;
         let mut az_converting_actor =
             ConvertingActor::builder("AzConverter", az_converter, get_topic_filter(&tedge_config));
         az_converting_actor.add_input(&mut mqtt_actor);
 
-        az_converting_actor.register_peer(NoConfig, mqtt_actor.get_sender());
+        let cloud_queue = cloud_queue(
+            self.session_name(),
+            "az",
+            &tedge_config,
+            az_priority,
+            &mut mqtt_actor,
+        )?;
+        match &cloud_queue {
+            Some(queue) => az_converting_actor.register_peer(NoConfig, queue.get_sender()),
+            None => az_converting_actor.register_peer(NoConfig, mqtt_actor.get_sender()),
+        }
 
         runtime.spawn(az_converting_actor).await?;
+        if let Some(queue) = cloud_queue {
+            runtime.spawn(queue).await?;
+        }
         runtime.spawn(mqtt_actor).await?;
         runtime.run_to_completion().await?;
         Ok(())
@@ -56,5 +102,15 @@ fn get_topic_filter(tedge_config: &TEdgeConfig) -> TopicFilter {
             warn!("The configured topic '{topic}' is invalid and ignored.");
         }
     }
+
+    // The entity registrations are required to forward deregistrations,
+    // whatever the configured topics
+    let mqtt_schema = MqttSchema::with_root(tedge_config.mqtt.topic_root.clone());
+    topics.add_all(mqtt_schema.topics(EntityFilter::AnyEntity, ChannelFilter::EntityMetadata));
     topics
 }
+
+/// All the telemetry data is published on the same Azure topic, hence with the same priority
+fn az_priority(_message: &MqttMessage) -> Priority {
+    Priority::Normal
+}
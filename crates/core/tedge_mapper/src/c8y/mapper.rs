@@ -1,42 +1,67 @@
 use crate::core::component::TEdgeComponent;
+use crate::core::mapper::cloud_queue;
 use crate::core::mapper::start_basic_actors;
+use crate::core::mapper::start_built_in_bridge;
+use crate::core::mapper::CloudConnection;
 use anyhow::Context;
 use async_trait::async_trait;
+use batcher::BatchingActorBuilder;
 use c8y_auth_proxy::actor::C8yAuthProxyBuilder;
 use c8y_http_proxy::credentials::C8YJwtRetriever;
 use c8y_http_proxy::C8YHttpProxyBuilder;
 use c8y_mapper_ext::actor::C8yMapperBuilder;
+use c8y_mapper_ext::cloud_entities::CloudEntityFetcher;
 use c8y_mapper_ext::compatibility_adapter::OldAgentAdapter;
 use c8y_mapper_ext::config::C8yMapperConfig;
 use c8y_mapper_ext::converter::CumulocityConverter;
+use c8y_mapper_ext::deletion::ManagedObjectDeleterBuilder;
 use mqtt_channel::Config;
 use std::path::Path;
 use tedge_api::entity_store::EntityExternalId;
 use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_cloud_queue::MqttThroughQueue;
+use tedge_cloud_queue::Priority;
 use tedge_config::TEdgeConfig;
 use tedge_downloader_ext::DownloaderActor;
 use tedge_file_system_ext::FsWatchActorBuilder;
 use tedge_http_ext::HttpActor;
 use tedge_mqtt_ext::MqttActorBuilder;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::Topic;
+use tedge_mqtt_ext::TopicPrefixes;
 use tedge_timer_ext::TimerActor;
 use tedge_uploader_ext::UploaderActor;
 
-const CUMULOCITY_MAPPER_NAME: &str = "tedge-mapper-c8y";
+pub struct CumulocityMapper {
+    connection: CloudConnection,
+}
 
-pub struct CumulocityMapper;
+impl CumulocityMapper {
+    pub fn new(connection: CloudConnection) -> Self {
+        CumulocityMapper { connection }
+    }
+}
 
 #[async_trait]
 impl TEdgeComponent for CumulocityMapper {
     fn session_name(&self) -> &str {
-        CUMULOCITY_MAPPER_NAME
+        &self.connection.mapper_name
     }
 
     async fn start(&self, tedge_config: TEdgeConfig, cfg_dir: &Path) -> Result<(), anyhow::Error> {
+        let topic_prefixes = self.connection.topic_prefixes(&tedge_config);
         let (mut runtime, mut mqtt_actor) =
-            start_basic_actors(self.session_name(), &tedge_config).await?;
+            start_basic_actors(self.session_name(), &tedge_config, topic_prefixes.clone()).await?;
+        start_built_in_bridge(
+            &mut runtime,
+            &tedge_config,
+            cfg_dir,
+            &self.connection.bridge_config_file(),
+        )
+        .await?;
 
         let mqtt_config = tedge_config.mqtt_config()?;
-        let mut jwt_actor = C8YJwtRetriever::builder(mqtt_config.clone());
+        let mut jwt_actor = C8YJwtRetriever::builder(mqtt_config.clone(), &self.connection.name());
         let mut http_actor = HttpActor::new().builder();
         let c8y_http_config = (&tedge_config).try_into()?;
         let mut c8y_http_proxy_actor =
@@ -46,31 +71,68 @@ impl TEdgeComponent for CumulocityMapper {
 
         let mut fs_watch_actor = FsWatchActorBuilder::new();
         let mut timer_actor = TimerActor::builder();
+        let mut scheduler_actor = TimerActor::builder();
 
         let identity = tedge_config.http.client.auth.identity()?;
         let mut uploader_actor = UploaderActor::new(identity.clone()).builder();
         let mut downloader_actor = DownloaderActor::new(identity).builder();
 
-        let c8y_mapper_config = C8yMapperConfig::from_tedge_config(cfg_dir, &tedge_config)?;
+        let cloud_queue = cloud_queue(
+            self.session_name(),
+            "c8y",
+            &tedge_config,
+            c8y_priority,
+            &mut mqtt_actor,
+        )?;
+
+        let mut c8y_mapper_config = C8yMapperConfig::from_tedge_config(cfg_dir, &tedge_config)?;
+        c8y_mapper_config.mapper_name = self.session_name().to_string();
+
+        // Batcher grouping the measurements to send in bulk
+        let mut bulk_measurement_batcher = BatchingActorBuilder::default();
+        if let Some(bulk_measurements) = &c8y_mapper_config.bulk_measurements {
+            bulk_measurement_batcher = bulk_measurement_batcher
+                .with_batching_window(bulk_measurements.batching_window())
+                .with_maximum_message_delay(bulk_measurements.maximum_message_delay());
+        }
+
+        // Actor deleting the managed objects of the deregistered entities from Cumulocity
+        let mut deleter_actor = ManagedObjectDeleterBuilder::new(&mut c8y_http_proxy_actor);
+
+        // Actor listing the child devices and services registered on Cumulocity, to be reconciled
+        let mut cloud_entity_fetcher_actor = CloudEntityFetcher::builder(&mut c8y_http_proxy_actor);
+
         let c8y_mapper_actor = C8yMapperBuilder::try_new(
             c8y_mapper_config,
-            &mut mqtt_actor,
+            &mut MqttThroughQueue::new(&mut mqtt_actor, cloud_queue.as_ref()),
             &mut c8y_http_proxy_actor,
             &mut timer_actor,
+            &mut scheduler_actor,
             &mut uploader_actor,
             &mut downloader_actor,
             &mut fs_watch_actor,
+            &mut bulk_measurement_batcher,
+            &mut deleter_actor,
+            &mut cloud_entity_fetcher_actor,
         )?;
 
         // Adaptor translating commands sent on te/device/main///cmd/+/+ into requests on tedge/commands/req/+/+
         // and translating the responses received on tedge/commands/res/+/+ to te/device/main///cmd/+/+
-        let old_to_new_agent_adapter = OldAgentAdapter::builder(&mut mqtt_actor);
+        // Only the mapper of the default connection runs this adaptor, not to translate the commands twice.
+        let old_to_new_agent_adapter = self
+            .connection
+            .profile
+            .is_none()
+            .then(|| OldAgentAdapter::builder(&mut mqtt_actor));
 
         // MQTT client dedicated to set service down status on shutdown, using a last-will message
         // A separate MQTT actor/client is required as the last will message of the main MQTT actor
         // is used to send down status to health topic
-        let service_monitor_actor =
-            MqttActorBuilder::new(service_monitor_client_config(&tedge_config)?);
+        let service_monitor_actor = MqttActorBuilder::new(service_monitor_client_config(
+            &tedge_config,
+            &self.connection,
+            &topic_prefixes,
+        )?);
 
         runtime.spawn(mqtt_actor).await?;
         runtime.spawn(jwt_actor).await?;
@@ -79,18 +141,52 @@ impl TEdgeComponent for CumulocityMapper {
         runtime.spawn(c8y_auth_proxy_actor).await?;
         runtime.spawn(fs_watch_actor).await?;
         runtime.spawn(timer_actor).await?;
+        runtime.spawn(scheduler_actor).await?;
         runtime.spawn(c8y_mapper_actor).await?;
+        runtime.spawn(bulk_measurement_batcher).await?;
+        runtime.spawn(deleter_actor).await?;
+        runtime.spawn(cloud_entity_fetcher_actor).await?;
+        if let Some(queue) = cloud_queue {
+            runtime.spawn(queue).await?;
+        }
         runtime.spawn(service_monitor_actor).await?;
         runtime.spawn(uploader_actor).await?;
         runtime.spawn(downloader_actor).await?;
-        runtime.spawn(old_to_new_agent_adapter).await?;
+        if let Some(old_to_new_agent_adapter) = old_to_new_agent_adapter {
+            runtime.spawn(old_to_new_agent_adapter).await?;
+        }
         runtime.run_to_completion().await?;
 
         Ok(())
     }
 }
 
-pub fn service_monitor_client_config(tedge_config: &TEdgeConfig) -> Result<Config, anyhow::Error> {
+/// Alarms and operation status updates are sent first and measurements last when the bridge is back
+fn c8y_priority(message: &MqttMessage) -> Priority {
+    let topic = message.topic.name.as_str();
+    if topic.starts_with("c8y/alarm/") {
+        return Priority::High;
+    }
+    if topic.starts_with("c8y/measurement/") {
+        return Priority::Low;
+    }
+    if topic.starts_with("c8y/s/us") {
+        // SmartREST 30x messages are alarms, 50x are operation status updates and 20x are measurements
+        return match message.payload_bytes().first() {
+            Some(b'3') | Some(b'5') => Priority::High,
+            Some(b'2') => Priority::Low,
+            _ => Priority::Normal,
+        };
+    }
+    Priority::Normal
+}
+
+pub fn service_monitor_client_config(
+    tedge_config: &TEdgeConfig,
+    connection: &CloudConnection,
+    topic_prefixes: &TopicPrefixes,
+) -> Result<Config, anyhow::Error> {
+    let mapper_name = connection.mapper_name.as_str();
     let main_device_xid: EntityExternalId = tedge_config.device.id.try_read(tedge_config)?.into();
     let service_type = &tedge_config.service.ty;
     let service_type = if service_type.is_empty() {
@@ -111,23 +207,26 @@ pub fn service_monitor_client_config(tedge_config: &TEdgeConfig) -> Result<Confi
         .context("Invalid device_topic_id")?;
 
     let mapper_service_topic_id = entity_topic_id
-        .default_service_for_device(CUMULOCITY_MAPPER_NAME)
+        .default_service_for_device(mapper_name)
         .context("Can't derive service name if device topic id not in default scheme")?;
 
     let mapper_service_external_id =
         CumulocityConverter::map_to_c8y_external_id(&mapper_service_topic_id, &main_device_xid);
 
-    let last_will_message = c8y_api::smartrest::inventory::service_creation_message(
+    let mut last_will_message = c8y_api::smartrest::inventory::service_creation_message(
         mapper_service_external_id.as_ref(),
-        CUMULOCITY_MAPPER_NAME,
+        mapper_name,
         service_type.as_str(),
         "down",
         &[],
     )?;
+    // This message is not published through the main MQTT actor, hence has to be renamed here
+    last_will_message.topic =
+        Topic::new_unchecked(&topic_prefixes.topic_to_broker(&last_will_message.topic.name));
 
     let mqtt_config = tedge_config
         .mqtt_config()?
-        .with_session_name("last_will_c8y_mapper")
+        .with_session_name(format!("last_will_{}_mapper", connection.name()))
         .with_last_will_message(last_will_message);
     Ok(mqtt_config)
 }
@@ -9,6 +9,7 @@ use mqtt_channel::TopicFilter;
 use std::path::Path;
 use tedge_actors::MessageSink;
 use tedge_config::TEdgeConfig;
+use tedge_mqtt_ext::TopicPrefixes;
 
 const COLLECTD_MAPPER_NAME: &str = "tedge-mapper-collectd";
 const COLLECTD_INPUT_TOPICS: &str = "collectd/#";
@@ -38,7 +39,8 @@ impl TEdgeComponent for CollectdMapper {
         _config_dir: &Path,
     ) -> Result<(), anyhow::Error> {
         let (mut runtime, mut mqtt_actor) =
-            start_basic_actors(self.session_name(), &tedge_config).await?;
+            start_basic_actors(self.session_name(), &tedge_config, TopicPrefixes::default())
+                .await?;
 
         let input_topic = CollectdMapper::input_topics();
         let output_topic = CollectdMapper::output_topic();
@@ -1,24 +1,102 @@
+use std::path::Path;
 #[cfg(test)]
 use std::result::Result::Ok;
+use std::time::Duration;
 use tedge_actors::Runtime;
+use tedge_api::mqtt_topics::Channel;
 use tedge_api::mqtt_topics::DeviceTopicId;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
 use tedge_api::mqtt_topics::Service;
 use tedge_api::mqtt_topics::ServiceTopicId;
+use tedge_cloud_queue::CloudQueueBuilder;
+use tedge_cloud_queue::CloudQueueConfig;
+use tedge_cloud_queue::Priority;
+use tedge_config::ProfileName;
+use tedge_config::ProfiledCloud;
 use tedge_config::TEdgeConfig;
 use tedge_health_ext::HealthMonitorBuilder;
+use tedge_mqtt_bridge::BridgeConfigFile;
+use tedge_mqtt_bridge::MqttBridgeActorBuilder;
 use tedge_mqtt_ext::MqttActorBuilder;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::TopicPrefixes;
 use tedge_signal_ext::SignalActor;
+use tracing::info;
+use tracing::warn;
+
+/// Directory where `tedge connect` writes the configuration of the built-in bridges
+const BUILT_IN_BRIDGE_CONF_DIR_PATH: &str = "bridge";
+
+/// Interval at which the statistics of the cloud queue are published while messages are queued
+const CLOUD_QUEUE_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A connection to a cloud, possibly using a named profile
+///
+/// The mapper of a profile runs under its own name, e.g. `tedge-mapper-c8y@staging`,
+/// and exchanges with the bridge of the profile on its own topics, e.g. `c8y@staging/#`.
+/// Internally, the mapper actors are unaware of the profile and use the default cloud topics:
+/// these are renamed by the MQTT actor (see [CloudConnection::topic_prefixes]).
+#[derive(Clone, Debug)]
+pub struct CloudConnection {
+    pub cloud: ProfiledCloud,
+    pub profile: Option<ProfileName>,
+    pub mapper_name: String,
+}
+
+impl CloudConnection {
+    pub fn new(cloud: ProfiledCloud, profile: Option<ProfileName>) -> Self {
+        let mapper_name = format!("tedge-mapper-{}", cloud.connection_name(profile.as_ref()));
+        CloudConnection {
+            cloud,
+            profile,
+            mapper_name,
+        }
+    }
+
+    /// The name of the connection, e.g. `c8y` or `c8y@staging`
+    pub fn name(&self) -> String {
+        self.cloud.connection_name(self.profile.as_ref())
+    }
+
+    /// The name of the bridge configuration file written by `tedge connect`
+    pub fn bridge_config_file(&self) -> String {
+        format!("{}-bridge.conf", self.name())
+    }
+
+    /// The topic renaming rules used to exchange with the bridge of a profile
+    ///
+    /// - `{cloud}/` topics are renamed `{cloud}@{profile}/`
+    /// - `{cloud}-internal/` topics, used by a mapper to persist its state, are renamed `{cloud}@{profile}-internal/`
+    /// - the `mosquitto-{cloud}-bridge` service topics are renamed `mosquitto-{cloud}@{profile}-bridge`
+    pub fn topic_prefixes(&self, config: &TEdgeConfig) -> TopicPrefixes {
+        if self.profile.is_none() {
+            return TopicPrefixes::default();
+        }
+        let root = &config.mqtt.topic_root;
+        let cloud = self.cloud.as_str();
+        let name = self.name();
+        TopicPrefixes::default()
+            .with_prefix(
+                format!("{root}/device/main/service/mosquitto-{cloud}-bridge/"),
+                format!("{root}/device/main/service/mosquitto-{name}-bridge/"),
+            )
+            .with_prefix(format!("{cloud}/"), format!("{name}/"))
+            .with_prefix(format!("{cloud}-internal/"), format!("{name}-internal/"))
+    }
+}
 
 pub async fn start_basic_actors(
     mapper_name: &str,
     config: &TEdgeConfig,
+    topic_prefixes: TopicPrefixes,
 ) -> Result<(Runtime, MqttActorBuilder), anyhow::Error> {
     let runtime_events_logger = None;
     let mut runtime = Runtime::try_new(runtime_events_logger).await?;
 
-    let mut mqtt_actor = get_mqtt_actor(mapper_name, config).await?;
+    let mut mqtt_actor = get_mqtt_actor(mapper_name, config)
+        .await?
+        .with_topic_prefixes(topic_prefixes);
 
     //Instantiate health monitor actor
     let service = Service {
@@ -43,6 +121,84 @@ pub async fn start_basic_actors(
     Ok((runtime, mqtt_actor))
 }
 
+/// Start the built-in bridge to the cloud, when enabled and configured by `tedge connect`
+pub async fn start_built_in_bridge(
+    runtime: &mut Runtime,
+    config: &TEdgeConfig,
+    config_dir: &Path,
+    bridge_config_file: &str,
+) -> Result<(), anyhow::Error> {
+    if !config.mqtt.bridge.built_in {
+        return Ok(());
+    }
+
+    let path = config_dir
+        .join(BUILT_IN_BRIDGE_CONF_DIR_PATH)
+        .join(bridge_config_file);
+    if !path.exists() {
+        warn!(
+            "The built-in bridge is enabled but not configured: {} not found. Run `tedge connect` first.",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    info!(
+        "Starting the built-in bridge configured by {}",
+        path.display()
+    );
+    let bridge_config = BridgeConfigFile::read(&path)?.bridge_config(config.mqtt_config()?)?;
+    runtime
+        .spawn(MqttBridgeActorBuilder::new(bridge_config))
+        .await?;
+    Ok(())
+}
+
+/// Create the queue buffering the messages published to the cloud while the bridge is down, when enabled
+///
+/// The messages published on `{cloud}/#` are buffered
+/// while the `mosquitto-{cloud}-bridge` service is not up.
+pub fn cloud_queue(
+    mapper_name: &str,
+    cloud: &str,
+    config: &TEdgeConfig,
+    priority: fn(&MqttMessage) -> Priority,
+    mqtt_actor: &mut MqttActorBuilder,
+) -> Result<Option<CloudQueueBuilder>, anyhow::Error> {
+    let queue_config = &config.mqtt.bridge.queue;
+    if !queue_config.enable {
+        return Ok(None);
+    }
+
+    let mqtt_schema = MqttSchema::with_root(config.mqtt.topic_root.clone());
+    let bridge_service = format!("mosquitto-{cloud}-bridge");
+    let bridge_health_topic = mqtt_schema.topic_for(
+        &EntityTopicId::default_main_service(&bridge_service)?,
+        &Channel::Health,
+    );
+    let queue_topic = mqtt_schema.topic_for(
+        &EntityTopicId::default_main_service(mapper_name)?,
+        &Channel::Measurement {
+            measurement_type: "queue".to_string(),
+        },
+    );
+    let queue_config = CloudQueueConfig {
+        cloud_topic_prefix: format!("{cloud}/"),
+        bridge_health_topic,
+        queue_topic,
+        queue_dir: config
+            .data
+            .path
+            .join(format!(".{mapper_name}/queue"))
+            .into(),
+        max_size: queue_config.max_size as usize,
+        max_age: queue_config.max_age.duration(),
+        report_interval: CLOUD_QUEUE_REPORT_INTERVAL,
+        priority,
+    };
+    Ok(Some(CloudQueueBuilder::try_new(queue_config, mqtt_actor)?))
+}
+
 async fn get_mqtt_actor(
     session_name: &str,
     tedge_config: &TEdgeConfig,
@@ -3,12 +3,15 @@ use crate::az::mapper::AzureMapper;
 use crate::c8y::mapper::CumulocityMapper;
 use crate::collectd::mapper::CollectdMapper;
 use crate::core::component::TEdgeComponent;
+use crate::core::mapper::CloudConnection;
 use clap::Parser;
 use flockfile::check_another_instance_is_not_running;
 use std::fmt;
 use std::path::PathBuf;
 use tedge_config::system_services::get_log_level;
 use tedge_config::system_services::set_log_level;
+use tedge_config::ProfileName;
+use tedge_config::ProfiledCloud;
 use tedge_config::DEFAULT_TEDGE_CONFIG_PATH;
 use tracing::log::warn;
 
@@ -19,11 +22,13 @@ mod collectd;
 mod core;
 
 fn lookup_component(component_name: &MapperName) -> Box<dyn TEdgeComponent> {
-    match component_name {
-        MapperName::Az => Box::new(AzureMapper),
-        MapperName::Aws => Box::new(AwsMapper),
-        MapperName::Collectd => Box::new(CollectdMapper),
-        MapperName::C8y => Box::new(CumulocityMapper),
+    match component_name.cloud_connection() {
+        Some(connection) => match connection.cloud {
+            ProfiledCloud::Az => Box::new(AzureMapper::new(connection)),
+            ProfiledCloud::Aws => Box::new(AwsMapper::new(connection)),
+            ProfiledCloud::C8y => Box::new(CumulocityMapper::new(connection)),
+        },
+        None => Box::new(CollectdMapper),
     }
 }
 
@@ -63,19 +68,42 @@ pub struct MapperOpt {
 
 #[derive(Debug, clap::Subcommand)]
 pub enum MapperName {
-    Az,
-    Aws,
-    C8y,
+    Az {
+        /// The cloud profile to use, as configured under `az.profiles.<profile>`
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
+    Aws {
+        /// The cloud profile to use, as configured under `aws.profiles.<profile>`
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
+    C8y {
+        /// The cloud profile to use, as configured under `c8y.profiles.<profile>`
+        #[clap(long)]
+        profile: Option<ProfileName>,
+    },
     Collectd,
 }
 
+impl MapperName {
+    /// The cloud connection of a cloud mapper
+    fn cloud_connection(&self) -> Option<CloudConnection> {
+        let (cloud, profile) = match self {
+            MapperName::Az { profile } => (ProfiledCloud::Az, profile),
+            MapperName::Aws { profile } => (ProfiledCloud::Aws, profile),
+            MapperName::C8y { profile } => (ProfiledCloud::C8y, profile),
+            MapperName::Collectd => return None,
+        };
+        Some(CloudConnection::new(cloud, profile.clone()))
+    }
+}
+
 impl fmt::Display for MapperName {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            MapperName::Az => write!(f, "tedge-mapper-az"),
-            MapperName::Aws => write!(f, "tedge-mapper-aws"),
-            MapperName::C8y => write!(f, "tedge-mapper-c8y"),
-            MapperName::Collectd => write!(f, "tedge-mapper-collectd"),
+        match self.cloud_connection() {
+            Some(connection) => write!(f, "{}", connection.mapper_name),
+            None => write!(f, "tedge-mapper-collectd"),
         }
     }
 }
@@ -85,7 +113,11 @@ pub async fn run(mapper_opt: MapperOpt) -> anyhow::Result<()> {
 
     let tedge_config_location =
         tedge_config::TEdgeConfigLocation::from_custom_root(&mapper_opt.config_dir);
-    let config = tedge_config::TEdgeConfigRepository::new(tedge_config_location.clone()).load()?;
+    let mut config =
+        tedge_config::TEdgeConfigRepository::new(tedge_config_location.clone()).load()?;
+    if let Some(connection) = mapper_opt.name.cloud_connection() {
+        config = config.for_profile(connection.cloud, connection.profile.as_ref())?;
+    }
 
     let log_level = if mapper_opt.debug {
         tracing::Level::DEBUG
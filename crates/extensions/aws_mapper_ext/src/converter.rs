@@ -3,10 +3,14 @@ use log::error;
 use serde_json::Map;
 use serde_json::Value;
 use std::convert::Infallible;
+use std::path::Path;
 use tedge_actors::Converter;
+use tedge_api::entity_journal::JournalError;
+use tedge_api::entity_store::EntityRegistrationMessage;
 use tedge_api::mqtt_topics::Channel;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::EntityStore;
 use tedge_mqtt_ext::MqttMessage;
 use tedge_mqtt_ext::Topic;
 
@@ -21,19 +25,39 @@ pub struct AwsConverter {
     pub(crate) clock: Box<dyn Clock>,
     pub(crate) size_threshold: SizeThreshold,
     pub mqtt_schema: MqttSchema,
+    pub(crate) entity_store: EntityStore,
 }
 
 impl AwsConverter {
     pub fn new(add_timestamp: bool, clock: Box<dyn Clock>, mqtt_schema: MqttSchema) -> Self {
         let size_threshold = SizeThreshold(AWS_MQTT_THRESHOLD);
+        // AWS only needs the entity hierarchy to forward deregistrations,
+        // hence entities are simply identified by their topic id
+        let entity_store = EntityStore::with_main_device(
+            EntityRegistrationMessage::main_device("main".into()),
+            |topic_id, _| topic_id.as_str().into(),
+            |id| Ok(id.into()),
+        )
+        .expect("a main device registration message");
         AwsConverter {
             add_timestamp,
             clock,
             size_threshold,
             mqtt_schema: mqtt_schema.clone(),
+            entity_store,
         }
     }
 
+    /// Persists the registered entities in a journal,
+    /// so deregistrations can be forwarded even after a restart
+    pub fn with_entity_journal(self, path: impl AsRef<Path>) -> Result<Self, JournalError> {
+        let entity_store = self.entity_store.with_journal(path)?;
+        Ok(Self {
+            entity_store,
+            ..self
+        })
+    }
+
     pub fn with_threshold(self, size_threshold: SizeThreshold) -> Self {
         Self {
             size_threshold,
@@ -79,10 +103,31 @@ impl AwsConverter {
 
             Channel::Health => self.convert_health_message(&source, &channel, input),
 
+            Channel::EntityMetadata => self.convert_entity_metadata(input, &source),
+
             _ => Ok(vec![]),
         }
     }
 
+    /// Keeps track of the entity hierarchy, forwarding entity deregistrations to AWS.
+    fn convert_entity_metadata(
+        &mut self,
+        input: &MqttMessage,
+        source: &EntityTopicId,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
+        let mut timestamp = Map::new();
+        self.add_timestamp(&mut timestamp)?;
+        self.entity_store
+            .forward_deregistrations(&self.mqtt_schema, input, source, |removed| {
+                let out_topic =
+                    format!("aws/td/{}/deregistered", normalize_name(&removed.topic_id));
+                let mut payload_json = removed.deregistration_payload();
+                payload_json.extend(timestamp.clone());
+                let payload = serde_json::to_string(&payload_json)?;
+                Ok(MqttMessage::new(&Topic::new_unchecked(&out_topic), payload))
+            })
+    }
+
     fn convert_health_message(
         &self,
         source: &EntityTopicId,
@@ -130,10 +175,14 @@ impl AwsConverter {
     }
 
     fn with_time_stamp(&self, input: &MqttMessage) -> Result<String, ConversionError> {
-        let default_timestamp = self.add_timestamp.then(|| self.clock.now());
         let mut payload_json: Map<String, Value> =
             serde_json::from_slice(input.payload.as_bytes())?;
+        self.add_timestamp(&mut payload_json)?;
+        Ok(serde_json::to_string(&payload_json)?)
+    }
 
+    fn add_timestamp(&self, payload_json: &mut Map<String, Value>) -> Result<(), ConversionError> {
+        let default_timestamp = self.add_timestamp.then(|| self.clock.now());
         if let Some(timestamp) = default_timestamp {
             let timestamp = timestamp
                 .format(&time::format_description::well_known::Rfc3339)?
@@ -141,7 +190,7 @@ impl AwsConverter {
                 .into();
             payload_json.entry("time").or_insert(timestamp);
         }
-        Ok(serde_json::to_string(&payload_json)?)
+        Ok(())
     }
 
     fn wrap_errors(
@@ -745,4 +794,45 @@ mod tests {
         let res = result.unwrap();
         assert_eq!(res[0], expected_msg);
     }
+
+    #[test]
+    fn deregistration_is_forwarded_for_the_entity_and_its_children() {
+        let mut converter = AwsConverter::new(true, Box::new(TestClock), MqttSchema::default());
+        for (topic, payload) in [
+            ("te/device/child1//", r#"{"@type":"child-device"}"#),
+            ("te/device/child1/service/app", r#"{"@type":"service"}"#),
+        ] {
+            let output =
+                converter.try_convert(&MqttMessage::new(&Topic::new_unchecked(topic), payload));
+            assert!(output.unwrap().is_empty());
+        }
+
+        let output = converter
+            .try_convert(
+                &MqttMessage::new(&Topic::new_unchecked("te/device/child1//"), "").with_retain(),
+            )
+            .unwrap();
+
+        assert_eq!(output.len(), 3);
+        assert_eq!(output[0].topic.name, "aws/td/device:child1/deregistered");
+        assert_json_eq!(
+            serde_json::from_str::<serde_json::Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({
+                "@topic-id": "device/child1//",
+                "@type": "child-device",
+                "deregistered": true,
+                "time": "2021-04-08T00:00:00+05:00"
+            })
+        );
+        assert_eq!(
+            output[1].topic.name,
+            "aws/td/device:child1:service:app/deregistered"
+        );
+        assert_eq!(output[2].topic.name, "te/device/child1/service/app");
+        assert!(output[2].payload_bytes().is_empty() && output[2].retain);
+
+        // The cleared registration of the service is ignored as already deregistered
+        let output = converter.try_convert(&output[2]).unwrap();
+        assert!(output.is_empty());
+    }
 }
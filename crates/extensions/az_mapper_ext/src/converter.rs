@@ -5,10 +5,14 @@ use log::error;
 use serde_json::Map;
 use serde_json::Value;
 use std::convert::Infallible;
+use std::path::Path;
 use tedge_actors::Converter;
+use tedge_api::entity_journal::JournalError;
+use tedge_api::entity_store::EntityRegistrationMessage;
 use tedge_api::mqtt_topics::Channel;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::EntityStore;
 use tedge_mqtt_ext::MqttMessage;
 use tedge_mqtt_ext::Topic;
 
@@ -27,6 +31,7 @@ pub struct AzureConverter {
     pub(crate) size_threshold: SizeThreshold,
     pub(crate) mapper_config: MapperConfig,
     pub mqtt_schema: MqttSchema,
+    pub(crate) entity_store: EntityStore,
 }
 
 impl AzureConverter {
@@ -36,15 +41,34 @@ impl AzureConverter {
             errors_topic: mqtt_schema.error_topic(),
         };
         let size_threshold = SizeThreshold(AZ_MQTT_THRESHOLD);
+        // Azure only needs the entity hierarchy to forward deregistrations,
+        // hence entities are simply identified by their topic id
+        let entity_store = EntityStore::with_main_device(
+            EntityRegistrationMessage::main_device("main".into()),
+            |topic_id, _| topic_id.as_str().into(),
+            |id| Ok(id.into()),
+        )
+        .expect("a main device registration message");
         AzureConverter {
             add_timestamp,
             clock,
             size_threshold,
             mapper_config,
             mqtt_schema: MqttSchema::default(),
+            entity_store,
         }
     }
 
+    /// Persists the registered entities in a journal,
+    /// so deregistrations can be forwarded even after a restart
+    pub fn with_entity_journal(self, path: impl AsRef<Path>) -> Result<Self, JournalError> {
+        let entity_store = self.entity_store.with_journal(path)?;
+        Ok(Self {
+            entity_store,
+            ..self
+        })
+    }
+
     pub fn with_threshold(self, size_threshold: SizeThreshold) -> Self {
         Self {
             size_threshold,
@@ -90,15 +114,37 @@ impl AzureConverter {
                 let output = MqttMessage::new(&self.mapper_config.out_topic, payload);
                 Ok(vec![output])
             }
+            Channel::EntityMetadata => self.convert_entity_metadata(input, entity),
             _ => Ok(vec![]),
         }
     }
 
+    /// Keeps track of the entity hierarchy, forwarding entity deregistrations to Azure.
+    fn convert_entity_metadata(
+        &mut self,
+        input: &MqttMessage,
+        entity: &EntityTopicId,
+    ) -> Result<Vec<MqttMessage>, ConversionError> {
+        let mut timestamp = Map::new();
+        self.add_timestamp(&mut timestamp)?;
+        self.entity_store
+            .forward_deregistrations(&self.mqtt_schema, input, entity, |removed| {
+                let mut payload_json = removed.deregistration_payload();
+                payload_json.extend(timestamp.clone());
+                let payload = serde_json::to_string(&payload_json)?;
+                Ok(MqttMessage::new(&self.mapper_config.out_topic, payload))
+            })
+    }
+
     fn with_timestamp(&mut self, input: &MqttMessage) -> Result<String, ConversionError> {
-        let default_timestamp = self.add_timestamp.then(|| self.clock.now());
         let mut payload_json: Map<String, Value> =
             serde_json::from_slice(input.payload.as_bytes())?;
+        self.add_timestamp(&mut payload_json)?;
+        Ok(serde_json::to_string(&payload_json)?)
+    }
 
+    fn add_timestamp(&self, payload_json: &mut Map<String, Value>) -> Result<(), ConversionError> {
+        let default_timestamp = self.add_timestamp.then(|| self.clock.now());
         if let Some(timestamp) = default_timestamp {
             let timestamp = timestamp
                 .format(&time::format_description::well_known::Rfc3339)?
@@ -106,7 +152,7 @@ impl AzureConverter {
                 .into();
             payload_json.entry("time").or_insert(timestamp);
         }
-        Ok(serde_json::to_string(&payload_json)?)
+        Ok(())
     }
 
     fn wrap_errors(
@@ -422,4 +468,42 @@ mod tests {
         let res = result.unwrap();
         assert_eq!(res[0], expected_msg);
     }
+
+    #[test]
+    fn deregistration_is_forwarded_for_the_entity_and_its_children() {
+        let mut converter = AzureConverter::new(true, Box::new(TestClock), MqttSchema::default());
+        for (topic, payload) in [
+            ("te/device/child1//", r#"{"@type":"child-device"}"#),
+            ("te/device/child1/service/app", r#"{"@type":"service"}"#),
+        ] {
+            let output =
+                converter.try_convert(&MqttMessage::new(&Topic::new_unchecked(topic), payload));
+            assert!(output.unwrap().is_empty());
+        }
+
+        let output = converter
+            .try_convert(
+                &MqttMessage::new(&Topic::new_unchecked("te/device/child1//"), "").with_retain(),
+            )
+            .unwrap();
+
+        assert_eq!(output.len(), 3);
+        assert_eq!(output[0].topic.name, "az/messages/events/");
+        assert_json_eq!(
+            serde_json::from_str::<serde_json::Value>(output[0].payload_str().unwrap()).unwrap(),
+            json!({
+                "@topic-id": "device/child1//",
+                "@type": "child-device",
+                "deregistered": true,
+                "time": "2021-04-08T00:00:00+05:00"
+            })
+        );
+        assert_eq!(output[1].topic.name, "az/messages/events/");
+        assert_eq!(output[2].topic.name, "te/device/child1/service/app");
+        assert!(output[2].payload_bytes().is_empty() && output[2].retain);
+
+        // The cleared registration of the service is ignored as already deregistered
+        let output = converter.try_convert(&output[2]).unwrap();
+        assert!(output.is_empty());
+    }
 }
@@ -5,9 +5,14 @@ use crate::messages::C8YConnectionError;
 use crate::messages::C8YRestError;
 use crate::messages::C8YRestRequest;
 use crate::messages::C8YRestResult;
+use crate::messages::ChildKind;
+use crate::messages::ChildManagedObject;
+use crate::messages::ChildManagedObjects;
 use crate::messages::CreateEvent;
+use crate::messages::DeleteManagedObject;
 use crate::messages::DownloadFile;
 use crate::messages::EventId;
+use crate::messages::GetChildManagedObjects;
 use crate::messages::SoftwareListResponse;
 use crate::messages::Unit;
 use crate::messages::UploadFile;
@@ -19,7 +24,9 @@ use async_trait::async_trait;
 use c8y_api::http_proxy::C8yEndPoint;
 use c8y_api::json_c8y::C8yCreateEvent;
 use c8y_api::json_c8y::C8yEventResponse;
+use c8y_api::json_c8y::C8yExternalIds;
 use c8y_api::json_c8y::C8yManagedObject;
+use c8y_api::json_c8y::C8yManagedObjectReferences;
 use c8y_api::json_c8y::InternalIdResponse;
 use c8y_api::OffsetDateTime;
 use download::Auth;
@@ -29,7 +36,7 @@ use http::status::StatusCode;
 use log::debug;
 use log::error;
 use log::info;
-use reqwest::Identity;
+use rustls::ClientConfig;
 use std::collections::HashMap;
 use std::future::ready;
 use std::future::Future;
@@ -52,7 +59,7 @@ const RETRY_TIMEOUT_SECS: u64 = 20;
 pub struct C8YHttpProxyActor {
     pub(crate) end_point: C8yEndPoint,
     peers: C8YHttpProxyMessageBox,
-    identity: Option<Identity>,
+    identity: Option<ClientConfig>,
 }
 
 pub struct C8YHttpProxyMessageBox {
@@ -121,6 +128,16 @@ impl Actor for C8YHttpProxyActor {
                     .download_file(request)
                     .await
                     .map(|response| response.into()),
+
+                C8YRestRequest::DeleteManagedObject(request) => self
+                    .delete_managed_object(request)
+                    .await
+                    .map(|response| response.into()),
+
+                C8YRestRequest::GetChildManagedObjects(request) => self
+                    .get_child_managed_objects(request)
+                    .await
+                    .map(|response| response.into()),
             };
             self.peers.clients.send((client_id, result)).await?;
         }
@@ -239,7 +256,7 @@ impl C8YHttpProxyActor {
         let resp = self.peers.http.await_response(request).await?;
         match resp {
             Ok(response) => match response.status() {
-                StatusCode::OK | StatusCode::CREATED => Ok(Ok(response)),
+                StatusCode::OK | StatusCode::CREATED | StatusCode::NO_CONTENT => Ok(Ok(response)),
                 StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
                     self.try_request_with_fresh_token(build_request).await
                 }
@@ -348,6 +365,95 @@ impl C8YHttpProxyActor {
         Ok(())
     }
 
+    async fn delete_managed_object(
+        &mut self,
+        request: DeleteManagedObject,
+    ) -> Result<Unit, C8YRestError> {
+        let device_id = request.device_id;
+
+        // Always get a fresh internal id, as the managed object might have been re-created
+        self.get_and_set_internal_id(device_id.clone()).await?;
+
+        let build_request = |end_point: &C8yEndPoint| {
+            let internal_id = end_point
+                .get_internal_id(device_id.clone())
+                .map_err(|e| C8YRestError::CustomError(e.to_string()));
+            let url = internal_id.map(|id| end_point.get_url_for_managed_object(&id));
+            async {
+                Ok::<_, C8YRestError>(
+                    HttpRequestBuilder::delete(url?).header("Accept", "application/json"),
+                )
+            }
+        };
+
+        let http_result = self.execute(device_id.clone(), build_request).await?;
+        http_result.error_for_status()?;
+        Ok(())
+    }
+
+    async fn get_child_managed_objects(
+        &mut self,
+        request: GetChildManagedObjects,
+    ) -> Result<ChildManagedObjects, C8YRestError> {
+        let device_id = request.device_id;
+
+        // Get and set child device internal id
+        if device_id.ne(&self.end_point.device_id)
+            && self.end_point.get_internal_id(device_id.clone()).is_err()
+        {
+            self.get_and_set_internal_id(device_id.clone()).await?;
+        }
+
+        let mut children = vec![];
+        for kind in [ChildKind::Device, ChildKind::Addition] {
+            let build_request = |end_point: &C8yEndPoint| {
+                let internal_id = end_point
+                    .get_internal_id(device_id.clone())
+                    .map_err(|e| C8YRestError::CustomError(e.to_string()));
+                let url = internal_id.map(|id| match kind {
+                    ChildKind::Device => end_point.get_url_for_child_devices(&id),
+                    ChildKind::Addition => end_point.get_url_for_child_additions(&id),
+                });
+                async {
+                    Ok::<_, C8YRestError>(
+                        HttpRequestBuilder::get(url?).header("Accept", "application/json"),
+                    )
+                }
+            };
+            let http_result = self.execute(device_id.clone(), build_request).await?;
+            let references: C8yManagedObjectReferences =
+                http_result.error_for_status()?.json().await?;
+
+            for reference in references.references {
+                let internal_id = reference.managed_object.id;
+                // The children not registered by thin-edge have no c8y_Serial external id
+                if let Some(external_id) = self.get_external_id(&device_id, &internal_id).await? {
+                    children.push(ChildManagedObject { external_id, kind });
+                }
+            }
+        }
+
+        Ok(children)
+    }
+
+    /// Get the `c8y_Serial` external id of a managed object, if any
+    async fn get_external_id(
+        &mut self,
+        device_id: &str,
+        internal_id: &str,
+    ) -> Result<Option<String>, C8YRestError> {
+        let build_request = |end_point: &C8yEndPoint| {
+            let url = end_point.get_url_for_external_ids(internal_id);
+            ready(Ok::<_, C8YRestError>(
+                HttpRequestBuilder::get(url).header("Accept", "application/json"),
+            ))
+        };
+        let http_result = self.execute(device_id.to_string(), build_request).await?;
+        let external_ids: C8yExternalIds = http_result.error_for_status()?.json().await?;
+
+        Ok(external_ids.serial().map(|id| id.to_string()))
+    }
+
     async fn upload_log_binary(
         &mut self,
         request: UploadLogBinary,
@@ -19,10 +19,12 @@ pub struct C8YJwtRetriever {
 }
 
 impl C8YJwtRetriever {
+    /// Build a retriever requesting the tokens over `{topic_prefix}/s/uat`, i.e. `c8y/s/uat` by default
     pub fn builder(
         mqtt_config: mqtt_channel::Config,
+        topic_prefix: &str,
     ) -> ServerActorBuilder<C8YJwtRetriever, Sequential> {
-        let mqtt_retriever = C8yMqttJwtTokenRetriever::new(mqtt_config);
+        let mqtt_retriever = C8yMqttJwtTokenRetriever::with_topic_prefix(mqtt_config, topic_prefix);
         let server = C8YJwtRetriever { mqtt_retriever };
         ServerActorBuilder::new(server, &ServerConfig::default(), Sequential)
     }
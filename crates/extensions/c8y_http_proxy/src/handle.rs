@@ -2,7 +2,10 @@ use crate::messages::C8YRestError;
 use crate::messages::C8YRestRequest;
 use crate::messages::C8YRestResponse;
 use crate::messages::C8YRestResult;
+use crate::messages::ChildManagedObjects;
 use crate::messages::CreateEvent;
+use crate::messages::DeleteManagedObject;
+use crate::messages::GetChildManagedObjects;
 use crate::messages::GetFreshJwtToken;
 use crate::messages::GetJwtToken;
 use crate::messages::SoftwareListResponse;
@@ -128,4 +131,23 @@ impl C8YHttpProxy {
             unexpected => Err(unexpected.into()),
         }
     }
+
+    pub async fn get_child_managed_objects(
+        &mut self,
+        device_id: String,
+    ) -> Result<ChildManagedObjects, C8YRestError> {
+        let request: C8YRestRequest = GetChildManagedObjects { device_id }.into();
+        match self.c8y.await_response(request).await? {
+            Ok(C8YRestResponse::ChildManagedObjects(children)) => Ok(children),
+            unexpected => Err(unexpected.into()),
+        }
+    }
+
+    pub async fn delete_managed_object(&mut self, device_id: String) -> Result<(), C8YRestError> {
+        let request: C8YRestRequest = DeleteManagedObject { device_id }.into();
+        match self.c8y.await_response(request).await? {
+            Ok(C8YRestResponse::Unit(())) => Ok(()),
+            unexpected => Err(unexpected.into()),
+        }
+    }
 }
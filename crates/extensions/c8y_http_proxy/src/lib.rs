@@ -4,7 +4,7 @@ use crate::credentials::JwtResult;
 use crate::credentials::JwtRetriever;
 use crate::messages::C8YRestRequest;
 use crate::messages::C8YRestResult;
-use reqwest::Identity;
+use rustls::ClientConfig;
 use std::convert::Infallible;
 use std::path::PathBuf;
 use tedge_actors::Builder;
@@ -35,7 +35,7 @@ pub struct C8YHttpConfig {
     pub c8y_host: String,
     pub device_id: String,
     pub tmp_dir: PathBuf,
-    identity: Option<Identity>,
+    identity: Option<ClientConfig>,
 }
 
 impl TryFrom<&NewTEdgeConfig> for C8YHttpConfig {
@@ -7,9 +7,9 @@ use tedge_actors::ChannelError;
 use tedge_http_ext::HttpError;
 use tedge_utils::file::PermissionEntry;
 
-fan_in_message_type!(C8YRestRequest[GetJwtToken, GetFreshJwtToken, CreateEvent, SoftwareListResponse, UploadLogBinary, UploadFile, DownloadFile]: Debug, PartialEq, Eq);
+fan_in_message_type!(C8YRestRequest[GetJwtToken, GetFreshJwtToken, CreateEvent, SoftwareListResponse, UploadLogBinary, UploadFile, DownloadFile, DeleteManagedObject, GetChildManagedObjects]: Debug, PartialEq, Eq);
 //HIPPO Rename EventId to String as there could be many other String responses as well and this macro doesn't allow another String variant
-fan_in_message_type!(C8YRestResponse[EventId, Url, Unit]: Debug);
+fan_in_message_type!(C8YRestResponse[EventId, Url, Unit, ChildManagedObjects]: Debug);
 
 #[derive(thiserror::Error, Debug)]
 pub enum C8YRestError {
@@ -93,6 +93,36 @@ pub struct DownloadFile {
     pub file_permissions: PermissionEntry,
 }
 
+/// Request the deletion of a managed object along with its external id
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DeleteManagedObject {
+    /// C8y's external ID of the device or service
+    pub device_id: String,
+}
+
+/// Request the child devices and the child additions of a managed object
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct GetChildManagedObjects {
+    /// C8y's external ID of the device
+    pub device_id: String,
+}
+
+/// A child device or a child addition, as a service, of a managed object
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ChildManagedObject {
+    /// C8y's external ID of the child
+    pub external_id: String,
+    pub kind: ChildKind,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ChildKind {
+    Device,
+    Addition,
+}
+
+pub type ChildManagedObjects = Vec<ChildManagedObject>;
+
 pub type EventId = String;
 
 pub type Unit = ();
@@ -2,14 +2,18 @@ use crate::credentials::ConstJwtRetriever;
 use crate::credentials::JwtRequest;
 use crate::credentials::JwtResult;
 use crate::handle::C8YHttpProxy;
+use crate::messages::ChildKind;
+use crate::messages::ChildManagedObject;
 use crate::messages::CreateEvent;
 use crate::C8YHttpConfig;
 use crate::C8YHttpProxyBuilder;
 use async_trait::async_trait;
 use c8y_api::json_c8y::C8yEventResponse;
+use c8y_api::json_c8y::C8yManagedObjectReferences;
 use c8y_api::json_c8y::C8yUpdateSoftwareListResponse;
 use c8y_api::json_c8y::InternalIdResponse;
 use mockito::Matcher;
+use serde_json::json;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use tedge_actors::Actor;
@@ -356,6 +360,157 @@ async fn retry_software_list_once_with_fresh_internal_id() {
     .await;
 }
 
+#[tokio::test]
+async fn delete_managed_object_using_its_external_id() {
+    let c8y_host = "c8y.tenant.io";
+    let device_id = "device-001";
+    let token = "JWT token";
+    let child_id = "child-001";
+    let tmp_dir = "/tmp";
+
+    let (mut proxy, mut c8y) =
+        spawn_c8y_http_proxy(c8y_host.into(), device_id.into(), tmp_dir.into(), token).await;
+
+    // skip the internal id request of the main device
+    c8y.recv().await;
+    let c8y_response = HttpResponseBuilder::new()
+        .status(200)
+        .json(&InternalIdResponse::new(device_id, device_id))
+        .build()
+        .unwrap();
+    c8y.send(Ok(c8y_response)).await.unwrap();
+
+    let deletion = tokio::spawn(async move {
+        // NOTE: this is done in the background because this call awaits for the response.
+        proxy.delete_managed_object(child_id.into()).await
+    });
+
+    // The proxy first requests the internal id of the managed object
+    c8y.assert_recv(Some(
+        HttpRequestBuilder::get(format!(
+            "https://{c8y_host}/identity/externalIds/c8y_Serial/{child_id}"
+        ))
+        .bearer_auth(token)
+        .build()
+        .unwrap(),
+    ))
+    .await;
+    let c8y_response = HttpResponseBuilder::new()
+        .status(200)
+        .json(&InternalIdResponse::new("12345", child_id))
+        .build()
+        .unwrap();
+    c8y.send(Ok(c8y_response)).await.unwrap();
+
+    // Then deletes the managed object
+    c8y.assert_recv(Some(
+        HttpRequestBuilder::delete(format!("https://{c8y_host}/inventory/managedObjects/12345"))
+            .header("accept", "application/json")
+            .bearer_auth(token)
+            .build()
+            .unwrap(),
+    ))
+    .await;
+    let c8y_response = HttpResponseBuilder::new().status(204).build().unwrap();
+    c8y.send(Ok(c8y_response)).await.unwrap();
+
+    assert!(deletion.await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn list_the_child_devices_and_child_additions_of_a_device() {
+    let c8y_host = "c8y.tenant.io";
+    let device_id = "device-001";
+    let token = "JWT token";
+    let tmp_dir = "/tmp";
+
+    let (mut proxy, mut c8y) =
+        spawn_c8y_http_proxy(c8y_host.into(), device_id.into(), tmp_dir.into(), token).await;
+
+    // skip the internal id request of the main device
+    c8y.recv().await;
+    let c8y_response = HttpResponseBuilder::new()
+        .status(200)
+        .json(&InternalIdResponse::new("100", device_id))
+        .build()
+        .unwrap();
+    c8y.send(Ok(c8y_response)).await.unwrap();
+
+    let listing = tokio::spawn(async move {
+        // NOTE: this is done in the background because this call awaits for the response.
+        proxy.get_child_managed_objects(device_id.into()).await
+    });
+
+    for (children_url, internal_ids, external_ids) in [
+        (
+            "childDevices",
+            vec!["101", "102"],
+            vec![
+                json!([{"externalId": "child-001", "type": "c8y_Serial"}]),
+                // Not registered by thin-edge
+                json!([{"externalId": "0123456789", "type": "c8y_IMEI"}]),
+            ],
+        ),
+        (
+            "childAdditions",
+            vec!["103"],
+            vec![
+                json!([{"externalId": "device-001:device:main:service:collectd", "type": "c8y_Serial"}]),
+            ],
+        ),
+    ] {
+        c8y.assert_recv(Some(
+            HttpRequestBuilder::get(format!(
+                "https://{c8y_host}/inventory/managedObjects/100/{children_url}?pageSize=2000"
+            ))
+            .header("accept", "application/json")
+            .bearer_auth(token)
+            .build()
+            .unwrap(),
+        ))
+        .await;
+        let c8y_response = HttpResponseBuilder::new()
+            .status(200)
+            .json(&C8yManagedObjectReferences::new(&internal_ids))
+            .build()
+            .unwrap();
+        c8y.send(Ok(c8y_response)).await.unwrap();
+
+        for (internal_id, external_ids) in internal_ids.into_iter().zip(external_ids) {
+            c8y.assert_recv(Some(
+                HttpRequestBuilder::get(format!(
+                    "https://{c8y_host}/identity/globalIds/{internal_id}/externalIds"
+                ))
+                .header("accept", "application/json")
+                .bearer_auth(token)
+                .build()
+                .unwrap(),
+            ))
+            .await;
+            let c8y_response = HttpResponseBuilder::new()
+                .status(200)
+                .json(&json!({ "externalIds": external_ids }))
+                .build()
+                .unwrap();
+            c8y.send(Ok(c8y_response)).await.unwrap();
+        }
+    }
+
+    assert_eq!(
+        listing.await.unwrap().unwrap(),
+        vec![
+            ChildManagedObject {
+                external_id: "child-001".to_string(),
+                kind: ChildKind::Device,
+            },
+            ChildManagedObject {
+                external_id: "device-001:device:main:service:collectd".to_string(),
+                kind: ChildKind::Addition,
+            },
+        ]
+    );
+}
+
 #[tokio::test]
 async fn auto_retry_upload_log_binary_when_internal_id_expires() {
     let c8y_host = "c8y.tenant.io";
@@ -1,6 +1,12 @@
 use super::config::C8yMapperConfig;
 use super::converter::CumulocityConverter;
 use super::dynamic_discovery::process_inotify_events;
+use crate::bulk_measurements::PendingMeasurementBatch;
+use crate::bulk_measurements::PendingMeasurementInput;
+use crate::cloud_entities::CloudEntities;
+use crate::cloud_entities::FetchCloudEntities;
+use crate::converter::DROPPED_MESSAGES_REPORT_INTERVAL;
+use crate::deletion::DeleteManagedObject;
 use crate::operations::FtsDownloadOperationType;
 use async_trait::async_trait;
 use c8y_api::smartrest::smartrest_deserializer::SmartRestOperationVariant;
@@ -15,6 +21,7 @@ use c8y_http_proxy::messages::C8YRestResult;
 use serde_json::json;
 use std::path::PathBuf;
 use std::time::Duration;
+use std::time::Instant;
 use tedge_actors::adapt;
 use tedge_actors::fan_in_message_type;
 use tedge_actors::Actor;
@@ -56,13 +63,26 @@ const SYNC_WINDOW: Duration = Duration::from_secs(3);
 pub type SyncStart = SetTimeout<()>;
 pub type SyncComplete = Timeout<()>;
 
+/// The tasks the mapper schedules using a timer
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ScheduledTask {
+    /// Publish the number of messages dropped by the entity store, if updated
+    ReportDroppedMessages,
+
+    /// Publish the measurements aggregated over the time windows ended by now
+    FlushAggregationWindows,
+}
+
+pub type ScheduleTask = SetTimeout<ScheduledTask>;
+pub type ScheduledTaskDue = Timeout<ScheduledTask>;
+
 pub(crate) type CmdId = String;
 pub(crate) type IdUploadRequest = (CmdId, UploadRequest);
 pub(crate) type IdUploadResult = (CmdId, UploadResult);
 pub(crate) type IdDownloadResult = (CmdId, DownloadResult);
 pub(crate) type IdDownloadRequest = (CmdId, DownloadRequest);
 
-fan_in_message_type!(C8yMapperInput[MqttMessage, FsWatchEvent, SyncComplete, IdUploadResult, IdDownloadResult] : Debug);
+fan_in_message_type!(C8yMapperInput[MqttMessage, FsWatchEvent, SyncComplete, ScheduledTaskDue, IdUploadResult, IdDownloadResult, PendingMeasurementBatch, CloudEntities] : Debug);
 type C8yMapperOutput = MqttMessage;
 
 pub struct C8yMapperActor {
@@ -70,6 +90,9 @@ pub struct C8yMapperActor {
     messages: SimpleMessageBox<C8yMapperInput, C8yMapperOutput>,
     mqtt_publisher: LoggingSender<MqttMessage>,
     timer_sender: LoggingSender<SyncStart>,
+    scheduler_sender: LoggingSender<ScheduleTask>,
+    deletion_sender: LoggingSender<DeleteManagedObject>,
+    cloud_entities_sender: LoggingSender<FetchCloudEntities>,
 }
 
 #[async_trait]
@@ -89,6 +112,14 @@ impl Actor for C8yMapperActor {
             .send(SyncStart::new(SYNC_WINDOW, ()))
             .await?;
 
+        // Start the periodic report of the dropped messages
+        self.scheduler_sender
+            .send(ScheduleTask::new(
+                DROPPED_MESSAGES_REPORT_INTERVAL,
+                ScheduledTask::ReportDroppedMessages,
+            ))
+            .await?;
+
         while let Some(event) = self.messages.recv().await {
             match event {
                 C8yMapperInput::MqttMessage(message) => {
@@ -100,13 +131,29 @@ impl Actor for C8yMapperActor {
                 C8yMapperInput::SyncComplete(_) => {
                     self.process_sync_timeout().await?;
                 }
+                C8yMapperInput::ScheduledTaskDue(timeout) => {
+                    self.process_scheduled_task(timeout.event).await?;
+                }
                 C8yMapperInput::IdUploadResult((cmd_id, result)) => {
                     self.process_upload_result(cmd_id, result).await?;
                 }
                 C8yMapperInput::IdDownloadResult((cmd_id, result)) => {
                     self.process_download_result(cmd_id, result).await?;
                 }
+                C8yMapperInput::PendingMeasurementBatch(batch) => {
+                    self.process_pending_measurements(batch).await?;
+                }
+                C8yMapperInput::CloudEntities(cloud_entities) => {
+                    self.process_cloud_entities(cloud_entities).await?;
+                }
             }
+            self.send_pending_deletions().await?;
+            self.schedule_aggregation_flushes().await?;
+        }
+
+        // Publish the measurements aggregated so far, not to lose them
+        for aggregated_message in self.converter.flush_all_aggregated_measurements() {
+            self.mqtt_publisher.send(aggregated_message).await?;
         }
         Ok(())
     }
@@ -118,13 +165,40 @@ impl C8yMapperActor {
         messages: SimpleMessageBox<C8yMapperInput, C8yMapperOutput>,
         mqtt_publisher: LoggingSender<MqttMessage>,
         timer_sender: LoggingSender<SyncStart>,
+        scheduler_sender: LoggingSender<ScheduleTask>,
+        deletion_sender: LoggingSender<DeleteManagedObject>,
+        cloud_entities_sender: LoggingSender<FetchCloudEntities>,
     ) -> Self {
         Self {
             converter,
             messages,
             mqtt_publisher,
             timer_sender,
+            scheduler_sender,
+            deletion_sender,
+            cloud_entities_sender,
+        }
+    }
+
+    async fn send_pending_deletions(&mut self) -> Result<(), RuntimeError> {
+        for deletion in self.converter.take_pending_deletions() {
+            self.deletion_sender.send(deletion).await?;
         }
+
+        Ok(())
+    }
+
+    async fn schedule_aggregation_flushes(&mut self) -> Result<(), RuntimeError> {
+        for window in self.converter.take_opened_aggregation_windows() {
+            self.scheduler_sender
+                .send(ScheduleTask::new(
+                    window,
+                    ScheduledTask::FlushAggregationWindows,
+                ))
+                .await?;
+        }
+
+        Ok(())
     }
 
     async fn process_mqtt_message(&mut self, message: MqttMessage) -> Result<(), RuntimeError> {
@@ -137,6 +211,40 @@ impl C8yMapperActor {
         Ok(())
     }
 
+    async fn process_scheduled_task(&mut self, task: ScheduledTask) -> Result<(), RuntimeError> {
+        match task {
+            ScheduledTask::ReportDroppedMessages => {
+                if let Some(report) = self.converter.flush_dropped_messages() {
+                    self.mqtt_publisher.send(report).await?;
+                }
+                self.scheduler_sender
+                    .send(ScheduleTask::new(DROPPED_MESSAGES_REPORT_INTERVAL, task))
+                    .await?;
+            }
+            ScheduledTask::FlushAggregationWindows => {
+                let now = Instant::now();
+                for aggregated_message in self.converter.flush_aggregated_measurements(now) {
+                    self.mqtt_publisher.send(aggregated_message).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn process_pending_measurements(
+        &mut self,
+        batch: PendingMeasurementBatch,
+    ) -> Result<(), RuntimeError> {
+        let bulk_messages = self.converter.convert_pending_measurements(batch.into());
+
+        for bulk_message in bulk_messages.into_iter() {
+            self.mqtt_publisher.send(bulk_message).await?;
+        }
+
+        Ok(())
+    }
+
     /// Registers the entity under a given MQTT topic.
     ///
     /// If a given entity was registered previously, the function will do
@@ -229,6 +337,30 @@ impl C8yMapperActor {
             self.process_mqtt_message(message).await?;
         }
 
+        // Then request the child devices and services registered on Cumulocity,
+        // to be reconciled with the local entities
+        if let Some(request) = self.converter.cloud_entities_request() {
+            self.cloud_entities_sender.send(request).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn process_cloud_entities(
+        &mut self,
+        cloud_entities: CloudEntities,
+    ) -> Result<(), RuntimeError> {
+        match cloud_entities {
+            Ok(cloud_entities) => {
+                for message in self.converter.reconcile_cloud_entities(cloud_entities) {
+                    self.mqtt_publisher.send(message).await?;
+                }
+            }
+            Err(err) => {
+                error!("Fail to list the child devices and services registered on Cumulocity, skipping reconciliation: {err}")
+            }
+        }
+
         Ok(())
     }
 
@@ -258,7 +390,10 @@ impl C8yMapperActor {
                 let clear_local_cmd = Message::new(&queued_data.clear_cmd_topic, "")
                     .with_retain()
                     .with_qos(QoS::AtLeastOnce);
-                for converted_message in [c8y_notification, clear_local_cmd] {
+                let converted_messages = self
+                    .converter
+                    .adapt_operation_status(&cmd_id, vec![c8y_notification, clear_local_cmd]);
+                for converted_message in converted_messages {
                     self.mqtt_publisher.send(converted_message).await?
                 }
             }
@@ -315,7 +450,7 @@ impl C8yMapperActor {
                 SmartRestOperationVariant::DownloadConfigFile(smartrest) => {
                     self.converter
                         .process_download_result_for_config_update(
-                            cmd_id.into(),
+                            cmd_id.clone().into(),
                             &smartrest,
                             result,
                         )
@@ -327,6 +462,9 @@ impl C8yMapperActor {
 
         match operation_result {
             Ok(converted_messages) => {
+                let converted_messages = self
+                    .converter
+                    .adapt_operation_status(&cmd_id, converted_messages);
                 for converted_message in converted_messages.into_iter() {
                     self.mqtt_publisher.send(converted_message).await?
                 }
@@ -345,21 +483,34 @@ pub struct C8yMapperBuilder {
     box_builder: SimpleMessageBoxBuilder<C8yMapperInput, C8yMapperOutput>,
     mqtt_publisher: DynSender<MqttMessage>,
     http_proxy: C8YHttpProxy,
+    deletion_sender: DynSender<DeleteManagedObject>,
+    cloud_entities_sender: DynSender<FetchCloudEntities>,
     timer_sender: DynSender<SyncStart>,
+    scheduler_sender: DynSender<ScheduleTask>,
     upload_sender: DynSender<IdUploadRequest>,
     download_sender: DynSender<IdDownloadRequest>,
+    bulk_measurements_sender: DynSender<PendingMeasurementInput>,
     auth_proxy: ProxyUrlGenerator,
 }
 
 impl C8yMapperBuilder {
+    #[allow(clippy::too_many_arguments)]
     pub fn try_new(
         config: C8yMapperConfig,
         mqtt: &mut impl ServiceProvider<MqttMessage, MqttMessage, TopicFilter>,
         http: &mut impl ServiceProvider<C8YRestRequest, C8YRestResult, NoConfig>,
         timer: &mut impl ServiceProvider<SyncStart, SyncComplete, NoConfig>,
+        scheduler: &mut impl ServiceProvider<ScheduleTask, ScheduledTaskDue, NoConfig>,
         uploader: &mut impl ServiceProvider<IdUploadRequest, IdUploadResult, NoConfig>,
         downloader: &mut impl ServiceProvider<IdDownloadRequest, IdDownloadResult, NoConfig>,
         fs_watcher: &mut impl MessageSource<FsWatchEvent, PathBuf>,
+        bulk_batcher: &mut impl ServiceProvider<
+            PendingMeasurementInput,
+            PendingMeasurementBatch,
+            NoConfig,
+        >,
+        deleter: &mut impl MessageSink<DeleteManagedObject, NoConfig>,
+        cloud_entity_fetcher: &mut impl ServiceProvider<FetchCloudEntities, CloudEntities, NoConfig>,
     ) -> Result<Self, FileError> {
         Self::init(&config)?;
 
@@ -368,11 +519,18 @@ impl C8yMapperBuilder {
         let mqtt_publisher =
             mqtt.connect_consumer(config.topics.clone(), adapt(&box_builder.get_sender()));
         let http_proxy = C8YHttpProxy::new("C8yMapper => C8YHttpProxy", http);
+        let deletion_sender = deleter.get_sender();
+        let cloud_entities_sender =
+            cloud_entity_fetcher.connect_consumer(NoConfig, adapt(&box_builder.get_sender()));
         let timer_sender = timer.connect_consumer(NoConfig, adapt(&box_builder.get_sender()));
+        let scheduler_sender =
+            scheduler.connect_consumer(NoConfig, adapt(&box_builder.get_sender()));
         let upload_sender = uploader.connect_consumer(NoConfig, adapt(&box_builder.get_sender()));
         let download_sender =
             downloader.connect_consumer(NoConfig, adapt(&box_builder.get_sender()));
         fs_watcher.register_peer(config.ops_dir.clone(), adapt(&box_builder.get_sender()));
+        let bulk_measurements_sender =
+            bulk_batcher.connect_consumer(NoConfig, adapt(&box_builder.get_sender()));
         let auth_proxy = ProxyUrlGenerator::new(
             config.auth_proxy_addr.clone(),
             config.auth_proxy_port,
@@ -384,9 +542,13 @@ impl C8yMapperBuilder {
             box_builder,
             mqtt_publisher,
             http_proxy,
+            deletion_sender,
+            cloud_entities_sender,
             timer_sender,
+            scheduler_sender,
             upload_sender,
             download_sender,
+            bulk_measurements_sender,
             auth_proxy,
         })
     }
@@ -412,10 +574,24 @@ impl Builder<C8yMapperActor> for C8yMapperBuilder {
     fn try_build(self) -> Result<C8yMapperActor, Self::Error> {
         let mqtt_publisher = LoggingSender::new("C8yMapper => Mqtt".into(), self.mqtt_publisher);
         let timer_sender = LoggingSender::new("C8yMapper => Timer".into(), self.timer_sender);
+        let scheduler_sender =
+            LoggingSender::new("C8yMapper => Scheduler".into(), self.scheduler_sender);
         let uploader_sender =
             LoggingSender::new("C8yMapper => Uploader".into(), self.upload_sender);
         let downloader_sender =
             LoggingSender::new("C8yMapper => Downloader".into(), self.download_sender);
+        let bulk_measurements_sender = LoggingSender::new(
+            "C8yMapper => BulkMeasurements".into(),
+            self.bulk_measurements_sender,
+        );
+        let deletion_sender = LoggingSender::new(
+            "C8yMapper => ManagedObjectDeleter".into(),
+            self.deletion_sender,
+        );
+        let cloud_entities_sender = LoggingSender::new(
+            "C8yMapper => CloudEntityFetcher".into(),
+            self.cloud_entities_sender,
+        );
 
         let converter = CumulocityConverter::new(
             self.config,
@@ -425,7 +601,8 @@ impl Builder<C8yMapperActor> for C8yMapperBuilder {
             uploader_sender.clone(),
             downloader_sender.clone(),
         )
-        .map_err(|err| RuntimeError::ActorError(Box::new(err)))?;
+        .map_err(|err| RuntimeError::ActorError(Box::new(err)))?
+        .with_bulk_measurements(bulk_measurements_sender);
 
         let message_box = self.box_builder.build();
 
@@ -434,6 +611,9 @@ impl Builder<C8yMapperActor> for C8yMapperBuilder {
             message_box,
             mqtt_publisher,
             timer_sender,
+            scheduler_sender,
+            deletion_sender,
+            cloud_entities_sender,
         ))
     }
 }
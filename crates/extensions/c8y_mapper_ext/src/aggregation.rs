@@ -0,0 +1,676 @@
+//! Aggregation of measurements over time windows, before these measurements are sent to Cumulocity.
+//!
+//! The measurements of the aggregated types are not forwarded one-to-one to Cumulocity.
+//! Instead, these are added as [MeasurementSample]s to an [Aggregator] owned by the mapper,
+//! which groups them by entity and measurement type over time windows.
+//! At the end of a window, a single Cumulocity measurement is built,
+//! applying the configured [AggregationFunction]s to the values of each measurement series.
+//! The mapper actor uses a timer to flush each window once ended,
+//! and flushes all the open windows when stopped.
+//!
+//! The length of the windows and the functions applied can be set per measurement type,
+//! and the functions per measurement series, in an [AggregationSettings] file.
+
+use serde::Deserialize;
+use serde_json::Map;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::time::Duration;
+use std::time::Instant;
+use tedge_api::measurement::MeasurementVisitor;
+use tedge_api::mqtt_topics::EntityTopicId;
+use tedge_api::parser::parse_str;
+use tedge_api::parser::ThinEdgeJsonParserError;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// The measurements to aggregate and how
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AggregationConfig {
+    /// The length of the time windows over which measurements are aggregated,
+    /// unless set for the measurement type
+    pub window: Duration,
+
+    /// The functions applied to the values of each measurement series,
+    /// unless set for the measurement type or the series
+    pub functions: Vec<AggregationFunction>,
+
+    /// The measurement types to aggregate, all the types if empty
+    pub types: Vec<String>,
+
+    /// The settings of specific measurement types
+    pub settings: AggregationSettings,
+}
+
+impl AggregationConfig {
+    /// Tell if the measurements of a type are aggregated.
+    ///
+    /// A type is aggregated when there is no list of types to aggregate,
+    /// when it is listed, or when it has its own settings.
+    pub fn aggregates(&self, measurement_type: &str) -> bool {
+        self.types.is_empty()
+            || self.types.iter().any(|t| t == measurement_type)
+            || self.settings.types.contains_key(measurement_type)
+    }
+
+    /// The length of the time windows for a measurement type
+    pub fn window(&self, measurement_type: &str) -> Duration {
+        self.settings
+            .types
+            .get(measurement_type)
+            .and_then(|settings| settings.window)
+            .map_or(self.window, Duration::from_secs)
+    }
+
+    /// The functions applied to a measurement series,
+    /// named `name` for a single-value series and `group.name` for a series of a group.
+    pub fn functions(&self, measurement_type: &str, series: &str) -> &[AggregationFunction] {
+        let Some(settings) = self.settings.types.get(measurement_type) else {
+            return &self.functions;
+        };
+        settings
+            .series
+            .get(series)
+            .or(settings.functions.as_ref())
+            .unwrap_or(&self.functions)
+    }
+}
+
+/// The aggregation settings of specific measurement types, as read from a TOML file
+///
+/// ```toml
+/// [types.environment]
+/// window = 300
+/// functions = ["min", "max", "avg"]
+///
+/// [types.environment.series]
+/// humidity = ["last"]
+/// "location.lat" = ["last"]
+/// ```
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AggregationSettings {
+    #[serde(default)]
+    pub types: HashMap<String, TypeAggregationSettings>,
+}
+
+/// The aggregation settings of a measurement type
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TypeAggregationSettings {
+    /// The length of the time windows, in seconds
+    pub window: Option<u64>,
+
+    /// The functions applied to the values of the series of this type
+    pub functions: Option<Vec<AggregationFunction>>,
+
+    /// The functions applied to the values of specific series
+    #[serde(default)]
+    pub series: HashMap<String, Vec<AggregationFunction>>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AggregationFunction {
+    Min,
+    Max,
+    Avg,
+    Last,
+    Count,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Unknown aggregation function {0:?}: expected min, max, avg, last or count")]
+pub struct UnknownAggregationFunction(String);
+
+impl FromStr for AggregationFunction {
+    type Err = UnknownAggregationFunction;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "min" => Ok(AggregationFunction::Min),
+            "max" => Ok(AggregationFunction::Max),
+            "avg" => Ok(AggregationFunction::Avg),
+            "last" => Ok(AggregationFunction::Last),
+            "count" => Ok(AggregationFunction::Count),
+            _ => Err(UnknownAggregationFunction(value.to_string())),
+        }
+    }
+}
+
+impl AggregationFunction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AggregationFunction::Min => "min",
+            AggregationFunction::Max => "max",
+            AggregationFunction::Avg => "avg",
+            AggregationFunction::Last => "last",
+            AggregationFunction::Count => "count",
+        }
+    }
+
+    /// Apply the function to the values of a series, given in the order these values have been received
+    fn apply(&self, values: &[f64]) -> f64 {
+        match self {
+            AggregationFunction::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+            AggregationFunction::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            AggregationFunction::Avg => values.iter().sum::<f64>() / values.len() as f64,
+            AggregationFunction::Last => values.last().copied().unwrap_or_default(),
+            AggregationFunction::Count => values.len() as f64,
+        }
+    }
+}
+
+/// A measurement received from an entity, waiting to be aggregated with the others of the same time window
+#[derive(Debug)]
+pub struct MeasurementSample {
+    received: OffsetDateTime,
+    source: EntityTopicId,
+    measurement_type: String,
+    time: Option<OffsetDateTime>,
+    values: Vec<SampleValue>,
+}
+
+#[derive(Debug)]
+struct SampleValue {
+    group: Option<String>,
+    name: String,
+    value: f64,
+}
+
+impl MeasurementSample {
+    /// Parse a thin-edge JSON measurement.
+    ///
+    /// The `type` property of the measurement, if any, overrides the type given by the topic.
+    pub fn parse(
+        received: OffsetDateTime,
+        source: &EntityTopicId,
+        measurement_type: &str,
+        payload: &str,
+    ) -> Result<Self, ThinEdgeJsonParserError> {
+        let mut collector = SampleCollector::default();
+        parse_str(payload, &mut collector)?;
+
+        Ok(MeasurementSample {
+            received,
+            source: source.clone(),
+            measurement_type: collector
+                .measurement_type
+                .unwrap_or_else(|| measurement_type.to_string()),
+            time: collector.time,
+            values: collector.values,
+        })
+    }
+
+    pub fn measurement_type(&self) -> &str {
+        &self.measurement_type
+    }
+}
+
+#[derive(Default)]
+struct SampleCollector {
+    measurement_type: Option<String>,
+    time: Option<OffsetDateTime>,
+    group: Option<String>,
+    values: Vec<SampleValue>,
+}
+
+impl MeasurementVisitor for SampleCollector {
+    type Error = Infallible;
+
+    fn visit_timestamp(&mut self, value: OffsetDateTime) -> Result<(), Self::Error> {
+        self.time = Some(value);
+        Ok(())
+    }
+
+    fn visit_measurement(&mut self, name: &str, value: f64) -> Result<(), Self::Error> {
+        self.values.push(SampleValue {
+            group: self.group.clone(),
+            name: name.to_string(),
+            value,
+        });
+        Ok(())
+    }
+
+    fn visit_text_property(&mut self, name: &str, value: &str) -> Result<(), Self::Error> {
+        if name == "type" && self.group.is_none() {
+            self.measurement_type = Some(value.to_string());
+        }
+        Ok(())
+    }
+
+    fn visit_start_group(&mut self, group: &str) -> Result<(), Self::Error> {
+        self.group = Some(group.to_string());
+        Ok(())
+    }
+
+    fn visit_end_group(&mut self) -> Result<(), Self::Error> {
+        self.group = None;
+        Ok(())
+    }
+}
+
+/// A measurement aggregating all the samples of a time window for an entity and a measurement type
+#[derive(Debug, Eq, PartialEq)]
+pub struct AggregatedMeasurement {
+    pub source: EntityTopicId,
+    pub measurement_type: String,
+
+    /// The aggregated values as a thin-edge JSON measurement
+    pub payload: String,
+}
+
+/// The time windows being aggregated, per entity and measurement type.
+///
+/// The aggregated value of a single-value series `temperature` is given by the group `temperature`,
+/// with one value per function: `{"temperature": {"min": 18.5, "max": 21.0}}`.
+/// The aggregated values of a series `lat` of a group `location` are added to that group,
+/// suffixed by the function: `{"location": {"lat_min": 43.2, "lat_max": 43.4}}`.
+///
+/// The timestamp of an aggregated measurement is the timestamp of the last sample.
+pub struct Aggregator {
+    config: AggregationConfig,
+    windows: Vec<Aggregation>,
+}
+
+impl Aggregator {
+    pub fn new(config: AggregationConfig) -> Self {
+        Aggregator {
+            config,
+            windows: vec![],
+        }
+    }
+
+    /// Add a sample to the current window of its entity and measurement type,
+    /// opening a new window if there is none.
+    ///
+    /// Return the length of the window opened for this sample, if any,
+    /// so the caller can schedule the flush of this window.
+    pub fn add(&mut self, sample: MeasurementSample, now: Instant) -> Option<Duration> {
+        let mut opened_window = None;
+        let index = match self.windows.iter().position(|window| {
+            window.source == sample.source && window.measurement_type == sample.measurement_type
+        }) {
+            Some(index) => index,
+            None => {
+                let window = self.config.window(&sample.measurement_type);
+                self.windows.push(Aggregation {
+                    source: sample.source.clone(),
+                    measurement_type: sample.measurement_type.clone(),
+                    deadline: now + window,
+                    time: sample.received,
+                    series: vec![],
+                });
+                opened_window = Some(window);
+                self.windows.len() - 1
+            }
+        };
+        self.windows[index].add(sample);
+        opened_window
+    }
+
+    /// The end of the first window to be closed, if any
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.windows.iter().map(|window| window.deadline).min()
+    }
+
+    /// Close the windows ended by `now`, returning one aggregated measurement per window
+    pub fn flush(&mut self, now: Instant) -> Vec<AggregatedMeasurement> {
+        let (ended, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.windows)
+            .into_iter()
+            .partition(|window| window.deadline <= now);
+        self.windows = pending;
+
+        ended
+            .into_iter()
+            .map(|window| window.into_measurement(&self.config))
+            .collect()
+    }
+
+    /// Close all the windows, even those not ended yet, as when the mapper stops
+    pub fn flush_all(&mut self) -> Vec<AggregatedMeasurement> {
+        std::mem::take(&mut self.windows)
+            .into_iter()
+            .map(|window| window.into_measurement(&self.config))
+            .collect()
+    }
+}
+
+struct Aggregation {
+    source: EntityTopicId,
+    measurement_type: String,
+    deadline: Instant,
+    time: OffsetDateTime,
+    series: Vec<(Option<String>, String, Vec<f64>)>,
+}
+
+impl Aggregation {
+    fn add(&mut self, sample: MeasurementSample) {
+        self.time = sample.time.unwrap_or(sample.received);
+        for value in sample.values {
+            match self
+                .series
+                .iter_mut()
+                .find(|(group, name, _)| group == &value.group && name == &value.name)
+            {
+                Some((_, _, values)) => values.push(value.value),
+                None => self
+                    .series
+                    .push((value.group, value.name, vec![value.value])),
+            }
+        }
+    }
+
+    fn into_measurement(self, config: &AggregationConfig) -> AggregatedMeasurement {
+        let mut payload = Map::new();
+        let time = self
+            .time
+            .format(&Rfc3339)
+            .expect("a timestamp can be formatted as RFC 3339");
+        payload.insert("time".to_string(), Value::String(time));
+
+        for (group, name, values) in self.series {
+            let functions = match &group {
+                None => config.functions(&self.measurement_type, &name),
+                Some(group) => config.functions(&self.measurement_type, &format!("{group}.{name}")),
+            };
+            let (group, prefix) = match group {
+                None => (name, None),
+                Some(group) => (group, Some(name)),
+            };
+            let Value::Object(aggregated) = payload
+                .entry(group)
+                .or_insert_with(|| Value::Object(Map::new()))
+            else {
+                continue;
+            };
+            for function in functions {
+                let key = match &prefix {
+                    None => function.name().to_string(),
+                    Some(name) => format!("{name}_{}", function.name()),
+                };
+                aggregated.insert(key, function.apply(&values).into());
+            }
+        }
+
+        AggregatedMeasurement {
+            source: self.source,
+            measurement_type: self.measurement_type,
+            payload: Value::Object(payload).to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use time::macros::datetime;
+
+    const ALL_FUNCTIONS: [AggregationFunction; 5] = [
+        AggregationFunction::Min,
+        AggregationFunction::Max,
+        AggregationFunction::Avg,
+        AggregationFunction::Last,
+        AggregationFunction::Count,
+    ];
+
+    #[test]
+    fn series_are_aggregated_per_entity_and_type() {
+        let main = EntityTopicId::default_main_device();
+        let child = EntityTopicId::default_child_device("child").unwrap();
+        let mut aggregator = Aggregator::new(config(&ALL_FUNCTIONS, ""));
+        let start = Instant::now();
+        for (id, source, m_type, payload) in [
+            (
+                1,
+                &main,
+                "env",
+                r#"{"temperature": 20, "location": {"lat": 43.1}}"#,
+            ),
+            (2, &child, "env", r#"{"temperature": 10}"#),
+            (
+                3,
+                &main,
+                "env",
+                r#"{"temperature": 22, "location": {"lat": 43.3}}"#,
+            ),
+            (4, &main, "vibration", r#"{"x": 1}"#),
+            (5, &main, "env", r#"{"temperature": 24}"#),
+        ] {
+            aggregator.add(sample(id, source, m_type, payload), start);
+        }
+
+        // Nothing is sent before the end of the window
+        assert!(aggregator.flush(start).is_empty());
+        assert_eq!(
+            aggregator.next_deadline(),
+            Some(start + Duration::from_secs(60))
+        );
+
+        let aggregated = aggregator.flush(start + Duration::from_secs(60));
+        assert_eq!(
+            measurements(aggregated),
+            vec![
+                (
+                    "device/main//".to_string(),
+                    "env".to_string(),
+                    json!({
+                        "time": "2023-11-01T10:00:05Z",
+                        "temperature": {"min": 20.0, "max": 24.0, "avg": 22.0, "last": 24.0, "count": 3.0},
+                        "location": {"lat_min": 43.1, "lat_max": 43.3, "lat_avg": 43.2, "lat_last": 43.3, "lat_count": 2.0},
+                    })
+                ),
+                (
+                    "device/child//".to_string(),
+                    "env".to_string(),
+                    json!({
+                        "time": "2023-11-01T10:00:02Z",
+                        "temperature": {"min": 10.0, "max": 10.0, "avg": 10.0, "last": 10.0, "count": 1.0},
+                    })
+                ),
+                (
+                    "device/main//".to_string(),
+                    "vibration".to_string(),
+                    json!({
+                        "time": "2023-11-01T10:00:04Z",
+                        "x": {"min": 1.0, "max": 1.0, "avg": 1.0, "last": 1.0, "count": 1.0},
+                    })
+                ),
+            ]
+        );
+        assert_eq!(aggregator.next_deadline(), None);
+    }
+
+    #[test]
+    fn open_windows_are_reported_and_flushed_on_demand() {
+        let main = EntityTopicId::default_main_device();
+        let mut aggregator = Aggregator::new(config(
+            &[AggregationFunction::Last],
+            "types.vibration.window = 10",
+        ));
+        let start = Instant::now();
+
+        // Only the first sample of a window opens that window
+        assert_eq!(
+            aggregator.add(sample(1, &main, "env", r#"{"temperature": 20}"#), start),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            aggregator.add(sample(2, &main, "env", r#"{"temperature": 21}"#), start),
+            None
+        );
+        assert_eq!(
+            aggregator.add(sample(3, &main, "vibration", r#"{"x": 1}"#), start),
+            Some(Duration::from_secs(10))
+        );
+
+        // All the windows are closed on demand, even if not ended
+        assert_eq!(
+            measurements(aggregator.flush_all()),
+            vec![
+                (
+                    "device/main//".to_string(),
+                    "env".to_string(),
+                    json!({
+                        "time": "2023-11-01T10:00:02Z",
+                        "temperature": {"last": 21.0},
+                    })
+                ),
+                (
+                    "device/main//".to_string(),
+                    "vibration".to_string(),
+                    json!({
+                        "time": "2023-11-01T10:00:03Z",
+                        "x": {"last": 1.0},
+                    })
+                ),
+            ]
+        );
+        assert_eq!(aggregator.next_deadline(), None);
+    }
+
+    #[test]
+    fn the_timestamp_and_type_of_a_sample_are_taken_from_the_payload() {
+        let main = EntityTopicId::default_main_device();
+        let sample = MeasurementSample::parse(
+            datetime!(2023-11-01 10:00:00 UTC),
+            &main,
+            "",
+            r#"{"type": "env", "time": "2023-10-31T08:00:00Z", "temperature": 20}"#,
+        )
+        .unwrap();
+        assert_eq!(sample.measurement_type(), "env");
+
+        let mut aggregator = Aggregator::new(config(&[AggregationFunction::Avg], ""));
+        let start = Instant::now();
+        aggregator.add(sample, start);
+        let aggregated = aggregator.flush(start + Duration::from_secs(60));
+        assert_eq!(
+            serde_json::from_str::<Value>(&aggregated[0].payload).unwrap(),
+            json!({"time": "2023-10-31T08:00:00Z", "temperature": {"avg": 20.0}})
+        );
+    }
+
+    #[test]
+    fn windows_and_functions_can_be_set_per_type_and_series() {
+        let main = EntityTopicId::default_main_device();
+        let settings = r#"
+            [types.env]
+            window = 300
+            functions = ["min", "max"]
+
+            [types.env.series]
+            humidity = ["last"]
+            "location.lat" = ["count"]
+        "#;
+        let mut aggregator = Aggregator::new(config(&[AggregationFunction::Avg], settings));
+        let start = Instant::now();
+        for (id, m_type, payload) in [
+            (
+                1,
+                "env",
+                r#"{"temperature": 20, "humidity": 50, "location": {"lat": 43.1, "lon": 5.1}}"#,
+            ),
+            (2, "vibration", r#"{"x": 1}"#),
+            (3, "env", r#"{"temperature": 24, "humidity": 60}"#),
+            (4, "vibration", r#"{"x": 2}"#),
+        ] {
+            aggregator.add(sample(id, &main, m_type, payload), start);
+        }
+
+        // The windows of the types without specific settings are closed first
+        assert_eq!(
+            measurements(aggregator.flush(start + Duration::from_secs(60))),
+            vec![(
+                "device/main//".to_string(),
+                "vibration".to_string(),
+                json!({"time": "2023-11-01T10:00:04Z", "x": {"avg": 1.5}})
+            )]
+        );
+        assert_eq!(
+            aggregator.next_deadline(),
+            Some(start + Duration::from_secs(300))
+        );
+
+        assert_eq!(
+            measurements(aggregator.flush(start + Duration::from_secs(300))),
+            vec![(
+                "device/main//".to_string(),
+                "env".to_string(),
+                json!({
+                    "time": "2023-11-01T10:00:03Z",
+                    "temperature": {"min": 20.0, "max": 24.0},
+                    "humidity": {"last": 60.0},
+                    "location": {"lat_count": 1.0, "lon_min": 5.1, "lon_max": 5.1},
+                })
+            )]
+        );
+    }
+
+    #[test]
+    fn aggregation_functions_are_parsed() {
+        assert_eq!(
+            "min".parse::<AggregationFunction>().unwrap(),
+            AggregationFunction::Min
+        );
+        assert_eq!(
+            " count".parse::<AggregationFunction>().unwrap(),
+            AggregationFunction::Count
+        );
+        assert!("median".parse::<AggregationFunction>().is_err());
+        assert!(
+            toml::from_str::<AggregationSettings>(r#"types.env.functions = ["median"]"#).is_err()
+        );
+    }
+
+    #[test]
+    fn only_the_configured_types_are_aggregated() {
+        let all = config(&[AggregationFunction::Avg], "");
+        assert!(all.aggregates("env"));
+
+        let some = AggregationConfig {
+            types: vec!["env".to_string()],
+            ..all
+        };
+        assert!(some.aggregates("env"));
+        assert!(!some.aggregates("vibration"));
+
+        let with_settings = AggregationConfig {
+            settings: toml::from_str("types.vibration.window = 10").unwrap(),
+            ..some
+        };
+        assert!(with_settings.aggregates("env"));
+        assert!(with_settings.aggregates("vibration"));
+        assert!(!with_settings.aggregates("pressure"));
+    }
+
+    fn config(functions: &[AggregationFunction], settings: &str) -> AggregationConfig {
+        AggregationConfig {
+            window: Duration::from_secs(60),
+            functions: functions.to_vec(),
+            types: vec![],
+            settings: toml::from_str(settings).unwrap(),
+        }
+    }
+
+    fn sample(id: i64, source: &EntityTopicId, m_type: &str, payload: &str) -> MeasurementSample {
+        let received = datetime!(2023-11-01 10:00:00 UTC) + time::Duration::seconds(id);
+        MeasurementSample::parse(received, source, m_type, payload).unwrap()
+    }
+
+    fn measurements(aggregated: Vec<AggregatedMeasurement>) -> Vec<(String, String, Value)> {
+        aggregated
+            .into_iter()
+            .map(|m| {
+                (
+                    m.source.to_string(),
+                    m.measurement_type,
+                    serde_json::from_str(&m.payload).unwrap(),
+                )
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,210 @@
+//! Bulk upload of measurements to Cumulocity.
+//!
+//! When enabled, the c8y measurements are not published one by one.
+//! Instead, these are sent as [PendingMeasurement]s to a batching actor,
+//! which holds them back at most for the configured latency.
+//! Each batch is then published entity by entity, using the `{"measurements": [...]}` bulk format,
+//! and split over several messages when the measurements don't fit under the MQTT message size threshold.
+
+use batcher::BatchDriverInput;
+use batcher::BatchDriverOutput;
+use batcher::Batchable;
+use std::time::Duration;
+use tedge_api::mqtt_topics::EntityTopicId;
+use time::OffsetDateTime;
+
+/// The c8y topic for measurements sent in bulk
+pub const C8Y_BULK_MEASUREMENTS_TOPIC: &str = "c8y/measurement/measurements/createBulk";
+
+const BULK_PREFIX: &str = r#"{"measurements":["#;
+const BULK_SUFFIX: &str = "]}";
+
+pub type PendingMeasurementInput = BatchDriverInput<PendingMeasurement>;
+pub type PendingMeasurementBatch = BatchDriverOutput<PendingMeasurement>;
+
+/// How measurements are batched before being sent in bulk to Cumulocity
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BulkMeasurementsConfig {
+    /// The maximum time a measurement is held back before being sent
+    pub max_latency: Duration,
+}
+
+impl BulkMeasurementsConfig {
+    /// The time windows, in milliseconds, over which the measurements are grouped
+    pub fn batching_window(&self) -> u32 {
+        self.max_latency_millis() - self.maximum_message_delay()
+    }
+
+    /// The time, in milliseconds, the batcher waits for the measurements of a closing window.
+    ///
+    /// A quarter of the max latency is reserved for that purpose,
+    /// the remaining being used by the batching window.
+    pub fn maximum_message_delay(&self) -> u32 {
+        self.max_latency_millis() / 4
+    }
+
+    fn max_latency_millis(&self) -> u32 {
+        self.max_latency.as_millis().try_into().unwrap_or(u32::MAX)
+    }
+}
+
+/// A c8y measurement waiting to be sent in bulk with the others received in the same time window
+#[derive(Debug)]
+pub struct PendingMeasurement {
+    /// Distinguishes the measurements, as the batcher keeps a single item per key in a batch
+    id: u64,
+    received: OffsetDateTime,
+    source: EntityTopicId,
+    /// The c8y JSON measurement
+    payload: String,
+}
+
+impl Batchable for PendingMeasurement {
+    type Key = u64;
+
+    fn key(&self) -> Self::Key {
+        self.id
+    }
+
+    fn event_time(&self) -> OffsetDateTime {
+        self.received
+    }
+}
+
+impl PendingMeasurement {
+    pub fn new(id: u64, received: OffsetDateTime, source: &EntityTopicId, payload: String) -> Self {
+        PendingMeasurement {
+            id,
+            received,
+            source: source.clone(),
+            payload,
+        }
+    }
+}
+
+/// Split a batch of measurements into bulks of c8y JSON measurements.
+///
+/// The measurements of a bulk are all related to the same entity and are given in the order these have been received.
+/// A bulk holds as many measurements as possible,
+/// while keeping the size of the [bulk_payload] strictly under the `size_threshold`.
+pub fn split_in_bulks(
+    mut measurements: Vec<PendingMeasurement>,
+    size_threshold: usize,
+) -> Vec<Vec<String>> {
+    measurements.sort_by_key(|measurement| measurement.id);
+
+    let mut entities: Vec<(EntityTopicId, Vec<String>)> = vec![];
+    for measurement in measurements {
+        match entities
+            .iter_mut()
+            .find(|(source, _)| source == &measurement.source)
+        {
+            Some((_, payloads)) => payloads.push(measurement.payload),
+            None => entities.push((measurement.source, vec![measurement.payload])),
+        }
+    }
+
+    let mut bulks = vec![];
+    for (_, payloads) in entities {
+        let mut bulk: Vec<String> = vec![];
+        let mut bulk_size = BULK_PREFIX.len() + BULK_SUFFIX.len();
+        for payload in payloads {
+            // Account for the comma separating the new measurement from the previous one
+            let added_size = payload.len() + usize::from(!bulk.is_empty());
+            if !bulk.is_empty() && bulk_size + added_size >= size_threshold {
+                bulks.push(std::mem::take(&mut bulk));
+                bulk_size = BULK_PREFIX.len() + BULK_SUFFIX.len() + payload.len();
+            } else {
+                bulk_size += added_size;
+            }
+            bulk.push(payload);
+        }
+        if !bulk.is_empty() {
+            bulks.push(bulk);
+        }
+    }
+    bulks
+}
+
+/// Build the c8y JSON payload of a bulk of measurements
+pub fn bulk_payload(measurements: &[String]) -> String {
+    format!("{BULK_PREFIX}{}{BULK_SUFFIX}", measurements.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use serde_json::Value;
+
+    #[test]
+    fn measurements_are_grouped_per_entity_in_order() {
+        let main = EntityTopicId::default_main_device();
+        let child = EntityTopicId::default_child_device("child").unwrap();
+        let measurements = vec![
+            measurement(3, &main, r#"{"m":3}"#),
+            measurement(1, &main, r#"{"m":1}"#),
+            measurement(2, &child, r#"{"c":2}"#),
+            measurement(4, &child, r#"{"c":4}"#),
+        ];
+
+        let bulks = split_in_bulks(measurements, 1024);
+
+        assert_eq!(
+            bulks,
+            vec![
+                vec![r#"{"m":1}"#.to_string(), r#"{"m":3}"#.to_string()],
+                vec![r#"{"c":2}"#.to_string(), r#"{"c":4}"#.to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn bulks_are_kept_under_the_size_threshold() {
+        let main = EntityTopicId::default_main_device();
+        let payload = r#"{"temperature":{"value":21.5}}"#;
+        let measurements = (1..=10).map(|id| measurement(id, &main, payload)).collect();
+        let size_threshold = 100;
+
+        let bulks = split_in_bulks(measurements, size_threshold);
+
+        assert_eq!(bulks.iter().map(Vec::len).sum::<usize>(), 10);
+        assert_eq!(bulks[0].len(), 2);
+        for bulk in bulks {
+            let bulk_payload = bulk_payload(&bulk);
+            assert!(bulk_payload.len() < size_threshold);
+        }
+    }
+
+    #[test]
+    fn bulk_payload_is_a_measurement_collection() {
+        let payload = bulk_payload(&[
+            r#"{"type":"t1","m":{"m":{"value":1}}}"#.to_string(),
+            r#"{"type":"t2","m":{"m":{"value":2}}}"#.to_string(),
+        ]);
+
+        assert_eq!(
+            serde_json::from_str::<Value>(&payload).unwrap(),
+            json!({
+                "measurements": [
+                    {"type":"t1","m":{"m":{"value":1}}},
+                    {"type":"t2","m":{"m":{"value":2}}},
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn max_latency_is_shared_between_window_and_delay() {
+        let config = BulkMeasurementsConfig {
+            max_latency: Duration::from_millis(1000),
+        };
+
+        assert_eq!(config.batching_window(), 750);
+        assert_eq!(config.maximum_message_delay(), 250);
+    }
+
+    fn measurement(id: u64, source: &EntityTopicId, payload: &str) -> PendingMeasurement {
+        PendingMeasurement::new(id, OffsetDateTime::now_utc(), source, payload.to_string())
+    }
+}
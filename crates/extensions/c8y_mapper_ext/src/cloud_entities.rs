@@ -0,0 +1,105 @@
+//! Retrieval of the child devices and services registered on Cumulocity, off the conversion path.
+//!
+//! The mapper doesn't await the HTTP requests walking the device hierarchy on Cumulocity.
+//! Instead, a [FetchCloudEntities] request is sent to a [CloudEntityFetcher] server
+//! that lists, device after device, the child devices and child additions (i.e. services) over the C8Y HTTP proxy,
+//! the mapper being notified with the [CloudEntities] found once the whole hierarchy has been walked.
+use async_trait::async_trait;
+use c8y_http_proxy::handle::C8YHttpProxy;
+use c8y_http_proxy::messages::C8YRestError;
+use c8y_http_proxy::messages::C8YRestRequest;
+use c8y_http_proxy::messages::C8YRestResult;
+use c8y_http_proxy::messages::ChildKind;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use tedge_actors::NoConfig;
+use tedge_actors::Sequential;
+use tedge_actors::Server;
+use tedge_actors::ServerActorBuilder;
+use tedge_actors::ServerConfig;
+use tedge_actors::ServiceProvider;
+use tedge_api::entity_store::EntityType;
+
+/// Request the child devices and services registered on Cumulocity under the given device
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FetchCloudEntities {
+    /// C8y's external ID of the main device
+    pub device_id: String,
+}
+
+/// A child device or service registered on Cumulocity
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CloudEntity {
+    pub external_id: String,
+
+    /// Either [EntityType::ChildDevice] or [EntityType::Service]
+    pub r#type: EntityType,
+
+    /// The external ids of the ancestors, from the parent up to the main device
+    pub ancestors: Vec<String>,
+}
+
+/// All the descendants of the main device registered on Cumulocity
+pub type CloudEntities = Result<Vec<CloudEntity>, C8YRestError>;
+
+/// Server walking the device hierarchy registered on Cumulocity
+pub struct CloudEntityFetcher {
+    http_proxy: C8YHttpProxy,
+}
+
+impl CloudEntityFetcher {
+    /// Create a fetcher using its own client of the C8Y HTTP proxy
+    pub fn builder(
+        http: &mut impl ServiceProvider<C8YRestRequest, C8YRestResult, NoConfig>,
+    ) -> ServerActorBuilder<CloudEntityFetcher, Sequential> {
+        let http_proxy = C8YHttpProxy::new("CloudEntityFetcher => C8YHttpProxy", http);
+        let server = CloudEntityFetcher { http_proxy };
+        ServerActorBuilder::new(server, &ServerConfig::default(), Sequential)
+    }
+
+    async fn fetch(&mut self, main_device_id: String) -> CloudEntities {
+        let mut entities = vec![];
+        let mut visited = HashSet::from([main_device_id.clone()]);
+
+        // The devices to visit, each given along its ancestors, starting with the device itself
+        let mut devices = VecDeque::from([vec![main_device_id]]);
+        while let Some(lineage) = devices.pop_front() {
+            let device_id = lineage[0].clone();
+            for child in self.http_proxy.get_child_managed_objects(device_id).await? {
+                if !visited.insert(child.external_id.clone()) {
+                    continue;
+                }
+                let r#type = match child.kind {
+                    ChildKind::Device => {
+                        let mut child_lineage = vec![child.external_id.clone()];
+                        child_lineage.extend(lineage.iter().cloned());
+                        devices.push_back(child_lineage);
+                        EntityType::ChildDevice
+                    }
+                    ChildKind::Addition => EntityType::Service,
+                };
+                entities.push(CloudEntity {
+                    external_id: child.external_id,
+                    r#type,
+                    ancestors: lineage.clone(),
+                });
+            }
+        }
+
+        Ok(entities)
+    }
+}
+
+#[async_trait]
+impl Server for CloudEntityFetcher {
+    type Request = FetchCloudEntities;
+    type Response = CloudEntities;
+
+    fn name(&self) -> &str {
+        "CloudEntityFetcher"
+    }
+
+    async fn handle(&mut self, request: Self::Request) -> Self::Response {
+        self.fetch(request.device_id).await
+    }
+}
@@ -1,4 +1,10 @@
+use crate::aggregation::AggregationConfig;
+use crate::aggregation::AggregationFunction;
+use crate::aggregation::AggregationSettings;
+use crate::aggregation::UnknownAggregationFunction;
+use crate::bulk_measurements::BulkMeasurementsConfig;
 use crate::Capabilities;
+use c8y_api::json_c8y_deserializer::C8Y_JSON_OPERATIONS_TOPIC;
 use c8y_api::smartrest::error::OperationsError;
 use c8y_api::smartrest::operations::Operations;
 use c8y_api::smartrest::topic::C8yTopic;
@@ -9,7 +15,9 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
-use tedge_api::mqtt_topics::ChannelFilter::Command;
+use std::time::Duration;
+use tedge_api::entity_topic_scheme::TopicSchemes;
+use tedge_api::mqtt_topics::ChannelFilter::AnyCommand;
 use tedge_api::mqtt_topics::ChannelFilter::CommandMetadata;
 use tedge_api::mqtt_topics::EntityFilter::AnyEntity;
 use tedge_api::mqtt_topics::EntityTopicId;
@@ -17,13 +25,19 @@ use tedge_api::mqtt_topics::MqttSchema;
 use tedge_api::mqtt_topics::OperationType;
 use tedge_api::mqtt_topics::TopicIdError;
 use tedge_api::path::DataDir;
+use tedge_api::pending_entity_store::PendingEntityStoreConfig;
 use tedge_config::ConfigNotSet;
+use tedge_config::OperationsProtocol;
 use tedge_config::ReadError;
+use tedge_config::ReconciliationPolicy;
 use tedge_config::TEdgeConfig;
 use tedge_mqtt_ext::TopicFilter;
 use tracing::log::warn;
 
 pub const MQTT_MESSAGE_SIZE_THRESHOLD: usize = 16184;
+const C8Y_MAPPER_NAME: &str = "tedge-mapper-c8y";
+const TOPIC_SCHEMES_FILE: &str = "topic-schemes.toml";
+const AGGREGATION_SETTINGS_FILE: &str = "c8y-aggregation.toml";
 
 pub struct C8yMapperConfig {
     pub config_dir: PathBuf,
@@ -44,6 +58,18 @@ pub struct C8yMapperConfig {
     pub auth_proxy_protocol: Protocol,
     pub mqtt_schema: MqttSchema,
     pub enable_auto_register: bool,
+    pub pending_entity_store_config: PendingEntityStoreConfig,
+    pub topic_schemes: TopicSchemes,
+    /// The service name of the mapper, e.g. `tedge-mapper-c8y@staging` when connected with a profile
+    pub mapper_name: String,
+    /// How measurements are aggregated before being sent to Cumulocity, if they are
+    pub aggregation: Option<AggregationConfig>,
+    /// How measurements are batched to be sent in bulk to Cumulocity, if they are
+    pub bulk_measurements: Option<BulkMeasurementsConfig>,
+    /// How the child devices registered on Cumulocity are reconciled with the local entities, if they are
+    pub reconciliation: Option<ReconciliationPolicy>,
+    /// Whether operations are received and updated as SmartREST or JSON messages
+    pub operations_protocol: OperationsProtocol,
 }
 
 impl C8yMapperConfig {
@@ -66,6 +92,8 @@ impl C8yMapperConfig {
         auth_proxy_protocol: Protocol,
         mqtt_schema: MqttSchema,
         enable_auto_register: bool,
+        pending_entity_store_config: PendingEntityStoreConfig,
+        topic_schemes: TopicSchemes,
     ) -> Self {
         let ops_dir = config_dir.join("operations").join("c8y");
 
@@ -88,6 +116,13 @@ impl C8yMapperConfig {
             auth_proxy_protocol,
             mqtt_schema,
             enable_auto_register,
+            pending_entity_store_config,
+            topic_schemes,
+            mapper_name: C8Y_MAPPER_NAME.to_string(),
+            aggregation: None,
+            bulk_measurements: None,
+            reconciliation: None,
+            operations_protocol: OperationsProtocol::default(),
         }
     }
 
@@ -129,6 +164,23 @@ impl C8yMapperConfig {
 
         let mut topics = Self::default_internal_topic_filter(&config_dir)?;
         let enable_auto_register = tedge_config.c8y.entity_store.auto_register;
+        let entity_store_config = &tedge_config.c8y.entity_store;
+        let pending_entity_store_config = PendingEntityStoreConfig {
+            telemetry_cache_size: entity_store_config.telemetry_cache_size as usize,
+            telemetry_cache_ttl: entity_store_config.telemetry_cache_ttl.duration(),
+            metadata_cache_size: entity_store_config.metadata_cache_size as usize,
+            orphan_ttl: entity_store_config.orphan_ttl.duration(),
+        };
+        let topic_schemes = Self::load_topic_schemes(&config_dir)?;
+        for topic in topic_schemes.topic_filters() {
+            topics
+                .add(&topic)
+                .map_err(|err| C8yMapperConfigBuildError::InvalidTopicSchemes {
+                    path: config_dir.join(TOPIC_SCHEMES_FILE),
+                    reason: err.to_string(),
+                })?;
+        }
+        let aggregation = Self::aggregation_config(tedge_config, &config_dir)?;
 
         // Add feature topic filters
         for cmd in [
@@ -136,10 +188,14 @@ impl C8yMapperConfig {
             OperationType::SoftwareList,
             OperationType::SoftwareUpdate,
         ] {
-            topics.add_all(mqtt_schema.topics(AnyEntity, Command(cmd.clone())));
             topics.add_all(mqtt_schema.topics(AnyEntity, CommandMetadata(cmd)));
         }
 
+        // Add the commands, including those onto which custom SmartREST operations are mapped.
+        // As the operation files can be updated while the mapper is running, all the commands are subscribed,
+        // the mapper ignoring those it didn't create.
+        topics.add_all(mqtt_schema.topics(AnyEntity, AnyCommand));
+
         if capabilities.log_upload {
             topics.add_all(crate::operations::log_upload::log_upload_topic_filter(
                 &mqtt_schema,
@@ -159,6 +215,13 @@ impl C8yMapperConfig {
             );
         }
 
+        let operations_protocol = tedge_config.c8y.operations.protocol;
+        if operations_protocol == OperationsProtocol::Json {
+            topics
+                .add(C8Y_JSON_OPERATIONS_TOPIC)
+                .expect("a valid topic filter");
+        }
+
         // Add user configurable external topic filters
         for topic in tedge_config.c8y.topics.0.clone() {
             if topics.add(&topic).is_err() {
@@ -166,7 +229,7 @@ impl C8yMapperConfig {
             }
         }
 
-        Ok(C8yMapperConfig::new(
+        let mut config = C8yMapperConfig::new(
             config_dir,
             logs_path,
             data_dir,
@@ -184,7 +247,103 @@ impl C8yMapperConfig {
             auth_proxy_protocol,
             mqtt_schema,
             enable_auto_register,
-        ))
+            pending_entity_store_config,
+            topic_schemes,
+        );
+        config.aggregation = aggregation;
+        config.bulk_measurements = Self::bulk_measurements_config(tedge_config);
+        config.reconciliation = tedge_config
+            .c8y
+            .reconciliation
+            .enable
+            .then_some(tedge_config.c8y.reconciliation.policy);
+        config.operations_protocol = operations_protocol;
+        Ok(config)
+    }
+
+    fn aggregation_config(
+        tedge_config: &TEdgeConfig,
+        config_dir: &Path,
+    ) -> Result<Option<AggregationConfig>, C8yMapperConfigBuildError> {
+        let aggregation = &tedge_config.c8y.aggregation;
+        if !aggregation.enable {
+            return Ok(None);
+        }
+
+        let functions = aggregation
+            .functions
+            .0
+            .iter()
+            .map(|function| function.parse())
+            .collect::<Result<Vec<AggregationFunction>, _>>()?;
+        let types = aggregation
+            .types
+            .or_none()
+            .map(|types| types.0.clone())
+            .unwrap_or_default();
+
+        Ok(Some(AggregationConfig {
+            window: aggregation.window.duration(),
+            functions,
+            types,
+            settings: Self::load_aggregation_settings(config_dir)?,
+        }))
+    }
+
+    /// Load the aggregation settings of specific measurement types, if any
+    pub fn load_aggregation_settings(
+        config_dir: &Path,
+    ) -> Result<AggregationSettings, C8yMapperConfigBuildError> {
+        let path = config_dir.join(AGGREGATION_SETTINGS_FILE);
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(AggregationSettings::default())
+            }
+            Err(err) => {
+                return Err(C8yMapperConfigBuildError::InvalidAggregationSettings {
+                    path,
+                    reason: err.to_string(),
+                })
+            }
+        };
+        toml::from_str(&content).map_err(|err| {
+            C8yMapperConfigBuildError::InvalidAggregationSettings {
+                path,
+                reason: err.to_string(),
+            }
+        })
+    }
+
+    fn bulk_measurements_config(tedge_config: &TEdgeConfig) -> Option<BulkMeasurementsConfig> {
+        let bulk_measurements = &tedge_config.c8y.bulk_measurements;
+        bulk_measurements.enable.then(|| BulkMeasurementsConfig {
+            max_latency: Duration::from_millis(bulk_measurements.max_latency.into()),
+        })
+    }
+
+    /// Loads the custom topic schemes used to auto-register the entities
+    /// that don't follow the default topic scheme
+    pub fn load_topic_schemes(
+        config_dir: &Path,
+    ) -> Result<TopicSchemes, C8yMapperConfigBuildError> {
+        let path = config_dir.join(TOPIC_SCHEMES_FILE);
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(TopicSchemes::default())
+            }
+            Err(err) => {
+                return Err(C8yMapperConfigBuildError::InvalidTopicSchemes {
+                    path,
+                    reason: err.to_string(),
+                })
+            }
+        };
+        toml::from_str(&content).map_err(|err| C8yMapperConfigBuildError::InvalidTopicSchemes {
+            path,
+            reason: err.to_string(),
+        })
     }
 
     pub fn default_internal_topic_filter(
@@ -235,6 +394,15 @@ pub enum C8yMapperConfigBuildError {
 
     #[error(transparent)]
     FromTopicIdError(#[from] TopicIdError),
+
+    #[error("Invalid topic schemes {path:?}: {reason}")]
+    InvalidTopicSchemes { path: PathBuf, reason: String },
+
+    #[error(transparent)]
+    FromUnknownAggregationFunction(#[from] UnknownAggregationFunction),
+
+    #[error("Invalid aggregation settings {path:?}: {reason}")]
+    InvalidAggregationSettings { path: PathBuf, reason: String },
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -6,15 +6,25 @@ use super::service_monitor;
 use crate::actor::CmdId;
 use crate::actor::IdDownloadRequest;
 use crate::actor::IdUploadRequest;
+use crate::aggregation::AggregatedMeasurement;
+use crate::aggregation::Aggregator;
+use crate::aggregation::MeasurementSample;
+use crate::bulk_measurements;
+use crate::bulk_measurements::PendingMeasurement;
+use crate::bulk_measurements::PendingMeasurementInput;
+use crate::bulk_measurements::C8Y_BULK_MEASUREMENTS_TOPIC;
+use crate::deletion::DeleteManagedObject;
 use crate::dynamic_discovery::DiscoverOp;
 use crate::error::ConversionError;
 use crate::json;
 use crate::operations::FtsDownloadOperationData;
+use crate::reconciliation::ReconciliationState;
 use anyhow::anyhow;
 use anyhow::Context;
 use c8y_api::http_proxy::C8yEndPoint;
 use c8y_api::json_c8y::C8yCreateEvent;
 use c8y_api::json_c8y::C8yUpdateSoftwareListResponse;
+use c8y_api::json_c8y_deserializer::C8Y_JSON_OPERATIONS_TOPIC;
 use c8y_api::smartrest::error::OperationsError;
 use c8y_api::smartrest::error::SmartRestDeserializerError;
 use c8y_api::smartrest::inventory::child_device_creation_message;
@@ -27,9 +37,14 @@ use c8y_api::smartrest::message::sanitize_bytes_for_smartrest;
 use c8y_api::smartrest::message::MAX_PAYLOAD_LIMIT_IN_BYTES;
 use c8y_api::smartrest::operations::get_child_ops;
 use c8y_api::smartrest::operations::get_operations;
+use c8y_api::smartrest::operations::Operation;
 use c8y_api::smartrest::operations::Operations;
 use c8y_api::smartrest::operations::ResultFormat;
 use c8y_api::smartrest::smartrest_deserializer::AvailableChildDevices;
+use c8y_api::smartrest::smartrest_deserializer::SmartRestConfigDownloadRequest;
+use c8y_api::smartrest::smartrest_deserializer::SmartRestConfigUploadRequest;
+use c8y_api::smartrest::smartrest_deserializer::SmartRestFirmwareRequest;
+use c8y_api::smartrest::smartrest_deserializer::SmartRestLogRequest;
 use c8y_api::smartrest::smartrest_deserializer::SmartRestOperationVariant;
 use c8y_api::smartrest::smartrest_deserializer::SmartRestRequestGeneric;
 use c8y_api::smartrest::smartrest_deserializer::SmartRestRestartRequest;
@@ -64,8 +79,10 @@ use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Instant;
 use tedge_actors::LoggingSender;
 use tedge_actors::Sender;
+use tedge_api::entity_journal::JournalError;
 use tedge_api::entity_store;
 use tedge_api::entity_store::EntityExternalId;
 use tedge_api::entity_store::EntityMetadata;
@@ -75,6 +92,7 @@ use tedge_api::entity_store::Error;
 use tedge_api::entity_store::InvalidExternalIdError;
 use tedge_api::event::error::ThinEdgeJsonDeserializerError;
 use tedge_api::event::ThinEdgeEvent;
+use tedge_api::health::ServiceHealthTopic;
 use tedge_api::messages::CommandStatus;
 use tedge_api::messages::RestartCommand;
 use tedge_api::messages::SoftwareListCommand;
@@ -84,33 +102,42 @@ use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::IdGenerator;
 use tedge_api::mqtt_topics::MqttSchema;
 use tedge_api::mqtt_topics::OperationType;
+use tedge_api::mqtt_topics::ServiceTopicId;
+use tedge_api::pending_entity_store::DroppedMessages;
 use tedge_api::pending_entity_store::PendingEntityData;
+use tedge_api::workflow::GenericCommandState;
 use tedge_api::DownloadInfo;
 use tedge_api::EntityStore;
+use tedge_config::OperationsProtocol;
 use tedge_config::TEdgeConfigError;
 use tedge_mqtt_ext::Message;
 use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::QoS;
 use tedge_mqtt_ext::Topic;
 use tedge_utils::file::create_directory_with_defaults;
 use tedge_utils::file::create_file_with_defaults;
 use tedge_utils::size_threshold::SizeThreshold;
 use thiserror::Error;
 use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
 use tokio::time::Duration;
 use tracing::debug;
+use tracing::info;
 use tracing::log::error;
 use tracing::trace;
 
 const C8Y_CLOUD: &str = "c8y";
 const SUPPORTED_OPERATIONS_DIRECTORY: &str = "operations";
 const INTERNAL_ALARMS_TOPIC: &str = "c8y-internal/alarms/";
-const C8Y_JSON_MQTT_EVENTS_TOPIC: &str = "c8y/event/events/create";
+pub(crate) const C8Y_JSON_MQTT_EVENTS_TOPIC: &str = "c8y/event/events/create";
 const TEDGE_AGENT_LOG_DIR: &str = "agent";
 const CREATE_EVENT_SMARTREST_CODE: u16 = 400;
 const DEFAULT_EVENT_TYPE: &str = "ThinEdgeEvent";
 const FORBIDDEN_ID_CHARS: [char; 3] = ['/', '+', '#'];
 const REQUESTER_NAME: &str = "c8y-mapper";
-const EARLY_MESSAGE_BUFFER_SIZE: usize = 100;
+pub(crate) const DROPPED_MESSAGES_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+const ENTITY_JOURNAL: &str = "entity_store.jsonl";
+const RECONCILIATION_STATE: &str = "c8y-reconciliation.json";
 
 #[derive(Debug)]
 pub struct MapperConfig {
@@ -121,7 +148,45 @@ pub struct MapperConfig {
 impl CumulocityConverter {
     pub async fn convert(&mut self, input: &Message) -> Vec<Message> {
         let messages_or_err = self.try_convert(input).await;
-        self.wrap_errors(messages_or_err)
+        let mut messages = self.wrap_errors(messages_or_err);
+        messages.extend(self.report_dropped_messages());
+        messages
+    }
+
+    /// Publishes on the mapper health topic the number of messages dropped by the entity store
+    /// while caching the messages of entities not registered yet.
+    ///
+    /// To avoid flooding the health topic, the counters are only published when updated,
+    /// and at most once every [DROPPED_MESSAGES_REPORT_INTERVAL].
+    /// The updates held back are published by [Self::flush_dropped_messages].
+    fn report_dropped_messages(&mut self) -> Option<Message> {
+        if self
+            .last_dropped_messages_report
+            .is_some_and(|last_report| last_report.elapsed() < DROPPED_MESSAGES_REPORT_INTERVAL)
+        {
+            return None;
+        }
+        self.flush_dropped_messages()
+    }
+
+    /// Publishes the number of dropped messages, if updated since the last report.
+    ///
+    /// This is called by the mapper actor every [DROPPED_MESSAGES_REPORT_INTERVAL],
+    /// so the updates held back by [Self::report_dropped_messages] are eventually published,
+    /// even if no more messages are received.
+    pub fn flush_dropped_messages(&mut self) -> Option<Message> {
+        let dropped_messages = self.entity_store.dropped_pending_messages();
+        if dropped_messages == self.reported_dropped_messages {
+            return None;
+        }
+
+        let now = Instant::now();
+        self.reported_dropped_messages = dropped_messages;
+        self.last_dropped_messages_report = Some(now);
+
+        let mut details = Map::new();
+        details.insert("dropped_messages".to_string(), json!(dropped_messages));
+        Some(self.mapper_health_topic.up_message_with_details(details))
     }
 
     pub fn wrap_errors(
@@ -209,6 +274,26 @@ pub struct CumulocityConverter {
     pub pending_fts_download_operations: HashMap<CmdId, FtsDownloadOperationData>,
 
     pub command_id: IdGenerator,
+
+    mapper_health_topic: ServiceHealthTopic,
+    reported_dropped_messages: DroppedMessages,
+    last_dropped_messages_report: Option<Instant>,
+
+    /// The time windows over which measurements are aggregated, if aggregation is enabled
+    aggregator: Option<Aggregator>,
+    last_sample_id: u64,
+
+    /// The lengths of the aggregation windows opened, till their flush is scheduled by the actor
+    opened_aggregation_windows: Vec<Duration>,
+
+    /// Where to send the measurements to batch, if bulk measurements are enabled
+    bulk_measurements_sender: Option<LoggingSender<PendingMeasurementInput>>,
+
+    /// The managed objects to be deleted from Cumulocity, till sent to the deleter actor
+    pending_deletions: Vec<DeleteManagedObject>,
+
+    /// What is known of the stale child devices and services, if the reconciliation is enabled
+    pub(crate) reconciliation_state: Option<ReconciliationState>,
 }
 
 impl CumulocityConverter {
@@ -240,7 +325,7 @@ impl CumulocityConverter {
         let tmp_dir = config.tmp_dir.clone();
 
         let operations = Operations::try_new(ops_dir.clone())?;
-        let children = get_child_ops(ops_dir.clone())?;
+        let mut children = get_child_ops(ops_dir.clone())?;
 
         let alarm_converter = AlarmConverter::new();
 
@@ -263,12 +348,46 @@ impl CumulocityConverter {
             service_type.clone(),
             Self::map_to_c8y_external_id,
             Self::validate_external_id,
-            EARLY_MESSAGE_BUFFER_SIZE,
+            config.pending_entity_store_config,
         )
-        .unwrap();
+        .unwrap()
+        .with_journal(
+            config
+                .data_dir
+                .join(format!(".{}", config.mapper_name))
+                .join(ENTITY_JOURNAL),
+        )?
+        .with_topic_schemes(config.topic_schemes.clone());
+
+        let reconciliation_state = config.reconciliation.map(|_| {
+            ReconciliationState::load(
+                config
+                    .data_dir
+                    .join(format!(".{}", config.mapper_name))
+                    .join(RECONCILIATION_STATE),
+            )
+        });
+
+        // The child devices restored from the journal are known to support no operations
+        // till their supported operations are published again
+        for (_, entity) in entity_store.iter() {
+            if entity.r#type == EntityType::ChildDevice {
+                children
+                    .entry(entity.external_id.as_ref().into())
+                    .or_insert_with(Operations::default);
+            }
+        }
 
         let command_id = IdGenerator::new(REQUESTER_NAME);
 
+        let mapper_service_topic_id =
+            EntityTopicId::default_main_service(&config.mapper_name).expect("a valid service name");
+        let mapper_health_topic = ServiceHealthTopic::from_new_topic(
+            &ServiceTopicId::new(mapper_service_topic_id),
+            &mqtt_schema,
+        );
+        let aggregator = config.aggregation.clone().map(Aggregator::new);
+
         Ok(CumulocityConverter {
             size_threshold,
             config,
@@ -296,9 +415,41 @@ impl CumulocityConverter {
             pending_download_operations: HashMap::new(),
             pending_fts_download_operations: HashMap::new(),
             command_id,
+            mapper_health_topic,
+            reported_dropped_messages: DroppedMessages::default(),
+            last_dropped_messages_report: None,
+            aggregator,
+            opened_aggregation_windows: vec![],
+            last_sample_id: 0,
+            bulk_measurements_sender: None,
+            pending_deletions: vec![],
+            reconciliation_state,
         })
     }
 
+    /// Send the c8y measurements to be batched and published in bulk,
+    /// when bulk measurements are enabled by the config
+    pub fn with_bulk_measurements(
+        mut self,
+        bulk_measurements_sender: LoggingSender<PendingMeasurementInput>,
+    ) -> Self {
+        if self.config.bulk_measurements.is_some() {
+            self.bulk_measurements_sender = Some(bulk_measurements_sender);
+        }
+        self
+    }
+
+    /// Queue the deletion of a managed object from Cumulocity
+    pub(crate) fn delete_managed_object(&mut self, external_id: String) {
+        self.pending_deletions
+            .push(DeleteManagedObject { external_id });
+    }
+
+    /// Take the deletions queued since the last call, to be sent to the deleter actor
+    pub fn take_pending_deletions(&mut self) -> Vec<DeleteManagedObject> {
+        std::mem::take(&mut self.pending_deletions)
+    }
+
     pub fn try_convert_entity_registration(
         &mut self,
         input: &EntityRegistrationMessage,
@@ -423,7 +574,7 @@ impl CumulocityConverter {
         Ok(id.into())
     }
 
-    fn try_convert_measurement(
+    async fn try_convert_measurement(
         &mut self,
         source: &EntityTopicId,
         input: &Message,
@@ -432,27 +583,145 @@ impl CumulocityConverter {
         let mut mqtt_messages: Vec<Message> = Vec::new();
 
         if let Some(entity) = self.entity_store.get(source) {
+            if let (Some(aggregation), Some(aggregator)) =
+                (&self.config.aggregation, &mut self.aggregator)
+            {
+                let sample = MeasurementSample::parse(
+                    OffsetDateTime::now_utc(),
+                    source,
+                    measurement_type,
+                    input.payload_str()?,
+                )?;
+                if aggregation.aggregates(sample.measurement_type()) {
+                    if let Some(window) = aggregator.add(sample, Instant::now()) {
+                        self.opened_aggregation_windows.push(window);
+                    }
+                    return Ok(mqtt_messages);
+                }
+            }
+
             // Need to check if the input Thin Edge JSON is valid before adding a child ID to list
             let c8y_json_payload =
                 json::from_thin_edge_json(input.payload_str()?, entity, measurement_type)?;
-
-            if c8y_json_payload.len() < self.size_threshold.0 {
-                mqtt_messages.push(Message::new(
-                    &self.mapper_config.out_topic,
-                    c8y_json_payload,
-                ));
-            } else {
-                return Err(ConversionError::TranslatedSizeExceededThreshold {
-                    payload: input.payload_str()?.chars().take(50).collect(),
-                    topic: input.topic.name.clone(),
-                    actual_size: c8y_json_payload.len(),
-                    threshold: self.size_threshold.0,
-                });
+            let message = self.measurement_message(
+                c8y_json_payload,
+                input.payload_str()?,
+                &input.topic.name,
+            )?;
+            match &mut self.bulk_measurements_sender {
+                Some(sender) => {
+                    self.last_sample_id += 1;
+                    let measurement = PendingMeasurement::new(
+                        self.last_sample_id,
+                        OffsetDateTime::now_utc(),
+                        source,
+                        message.payload_str()?.to_string(),
+                    );
+                    sender
+                        .send(measurement.into())
+                        .await
+                        .map_err(CumulocityMapperError::from)?;
+                }
+                None => mqtt_messages.push(message),
             }
         }
         Ok(mqtt_messages)
     }
 
+    fn measurement_message(
+        &self,
+        c8y_json_payload: String,
+        input_payload: &str,
+        input_topic: &str,
+    ) -> Result<Message, ConversionError> {
+        if c8y_json_payload.len() < self.size_threshold.0 {
+            Ok(Message::new(
+                &self.mapper_config.out_topic,
+                c8y_json_payload,
+            ))
+        } else {
+            Err(ConversionError::TranslatedSizeExceededThreshold {
+                payload: input_payload.chars().take(50).collect(),
+                topic: input_topic.to_string(),
+                actual_size: c8y_json_payload.len(),
+                threshold: self.size_threshold.0,
+            })
+        }
+    }
+
+    /// Take the lengths of the aggregation windows opened since the last call,
+    /// each window to be flushed once this delay is elapsed
+    pub fn take_opened_aggregation_windows(&mut self) -> Vec<Duration> {
+        std::mem::take(&mut self.opened_aggregation_windows)
+    }
+
+    /// Convert the measurements of the time windows ended by `now` into aggregated c8y measurements
+    pub fn flush_aggregated_measurements(&mut self, now: Instant) -> Vec<Message> {
+        let aggregated = match &mut self.aggregator {
+            Some(aggregator) => aggregator.flush(now),
+            None => vec![],
+        };
+        self.aggregated_measurement_messages(aggregated)
+    }
+
+    /// Convert the measurements of all the open time windows into aggregated c8y measurements
+    pub fn flush_all_aggregated_measurements(&mut self) -> Vec<Message> {
+        let aggregated = match &mut self.aggregator {
+            Some(aggregator) => aggregator.flush_all(),
+            None => vec![],
+        };
+        self.aggregated_measurement_messages(aggregated)
+    }
+
+    fn aggregated_measurement_messages(
+        &self,
+        aggregated: Vec<AggregatedMeasurement>,
+    ) -> Vec<Message> {
+        let mut messages = vec![];
+        for measurement in aggregated {
+            let Some(entity) = self.entity_store.get(&measurement.source) else {
+                continue;
+            };
+            let message = json::from_thin_edge_json(
+                &measurement.payload,
+                entity,
+                &measurement.measurement_type,
+            )
+            .map_err(ConversionError::from)
+            .and_then(|c8y_json_payload| {
+                let topic = self
+                    .mqtt_schema
+                    .topic_for(
+                        &measurement.source,
+                        &Channel::Measurement {
+                            measurement_type: measurement.measurement_type.clone(),
+                        },
+                    )
+                    .name;
+                self.measurement_message(c8y_json_payload, &measurement.payload, &topic)
+            });
+            messages.push(message.unwrap_or_else(|error| self.new_error_message(error)));
+        }
+        messages
+    }
+
+    /// Convert a batch of c8y measurements into bulk messages, grouping the measurements per entity.
+    ///
+    /// A measurement that cannot be grouped with others is published as is.
+    pub fn convert_pending_measurements(
+        &self,
+        measurements: Vec<PendingMeasurement>,
+    ) -> Vec<Message> {
+        let bulk_topic = Topic::new_unchecked(C8Y_BULK_MEASUREMENTS_TOPIC);
+        bulk_measurements::split_in_bulks(measurements, self.size_threshold.0)
+            .into_iter()
+            .map(|bulk| match bulk.as_slice() {
+                [measurement] => Message::new(&self.mapper_config.out_topic, measurement.as_str()),
+                _ => Message::new(&bulk_topic, bulk_measurements::bulk_payload(&bulk)),
+            })
+            .collect()
+    }
+
     async fn try_convert_event(
         &mut self,
         source: &EntityTopicId,
@@ -593,21 +862,50 @@ impl CumulocityConverter {
         match get_smartrest_device_id(payload) {
             Some(device_id) => {
                 match get_smartrest_template_id(payload).as_str() {
+                    "510" | "515" | "522" | "524" | "526" | "528"
+                        if self.config.operations_protocol == OperationsProtocol::Json =>
+                    {
+                        debug!("Ignored. Operations are received as JSON: {payload}");
+                        Ok(vec![])
+                    }
                     // Need a check of capabilities so that user can still use custom template if disabled
                     "522" if self.config.capabilities.log_upload => {
-                        self.convert_log_upload_request(payload)
+                        let log_request = SmartRestLogRequest::from_smartrest(payload)?;
+                        self.convert_log_upload_request(log_request, self.command_id.new_id())
                     }
                     "524" if self.config.capabilities.config_update => {
-                        self.convert_config_update_request(payload).await
+                        let config_request =
+                            SmartRestConfigDownloadRequest::from_smartrest(payload)?;
+                        self.convert_config_update_request(config_request, self.command_id.new_id())
+                            .await
                     }
                     "526" if self.config.capabilities.config_snapshot => {
-                        self.convert_config_snapshot_request(payload)
+                        let snapshot_request =
+                            SmartRestConfigUploadRequest::from_smartrest(payload)?;
+                        self.convert_config_snapshot_request(
+                            snapshot_request,
+                            self.command_id.new_id(),
+                        )
                     }
                     "515" if self.config.capabilities.firmware_update => {
-                        self.convert_firmware_update_request(payload)
+                        let firmware_request = SmartRestFirmwareRequest::from_smartrest(payload)?;
+                        self.convert_firmware_update_request(
+                            firmware_request,
+                            self.command_id.new_id(),
+                        )
+                    }
+                    "528" => {
+                        let update_software = SmartRestUpdateSoftware::from_smartrest(payload)?;
+                        self.forward_software_request(update_software, self.command_id.new_id())
+                            .await
+                    }
+                    "510" => {
+                        let restart_request = SmartRestRestartRequest::from_smartrest(payload)?;
+                        self.forward_restart_request(restart_request, self.command_id.new_id())
+                    }
+                    template if self.is_workflow_template(device_id, template) => {
+                        self.forward_workflow_request(payload, device_id, template)
                     }
-                    "528" => self.forward_software_request(payload).await,
-                    "510" => self.forward_restart_request(payload),
                     template if device_id == self.device_name => {
                         self.forward_operation_request(payload, template).await
                     }
@@ -634,14 +932,13 @@ impl CumulocityConverter {
         }
     }
 
-    async fn forward_software_request(
+    pub(crate) async fn forward_software_request(
         &mut self,
-        smartrest: &str,
+        update_software: SmartRestUpdateSoftware,
+        cmd_id: CmdId,
     ) -> Result<Vec<Message>, CumulocityMapperError> {
-        let update_software = SmartRestUpdateSoftware::from_smartrest(smartrest)?;
         let device_id = &update_software.external_id.clone().into();
         let target = self.entity_store.try_get_by_external_id(device_id)?;
-        let cmd_id = self.command_id.new_id();
         let mut command = update_software.into_software_update_command(&target.topic_id, cmd_id)?;
 
         command.payload.update_list.iter_mut().for_each(|modules| {
@@ -662,14 +959,13 @@ impl CumulocityConverter {
         Ok(vec![message])
     }
 
-    fn forward_restart_request(
+    pub(crate) fn forward_restart_request(
         &mut self,
-        smartrest: &str,
+        request: SmartRestRestartRequest,
+        cmd_id: CmdId,
     ) -> Result<Vec<Message>, CumulocityMapperError> {
-        let request = SmartRestRestartRequest::from_smartrest(smartrest)?;
         let device_id = &request.device.into();
         let target = self.entity_store.try_get_by_external_id(device_id)?;
-        let cmd_id = self.command_id.new_id();
         let command = RestartCommand::new(&target.topic_id, cmd_id);
         let message = command.command_message(&self.mqtt_schema);
         Ok(vec![message])
@@ -681,6 +977,82 @@ impl CumulocityConverter {
         request.command_message(&self.mqtt_schema)
     }
 
+    fn is_workflow_template(&self, device_id: &str, template: &str) -> bool {
+        self.matching_workflow_template(device_id, template)
+            .is_some()
+    }
+
+    /// The custom operation of a device mapping a SmartREST template onto a thin-edge command.
+    ///
+    /// The operations declared for the device itself take precedence over those of the main device.
+    fn matching_workflow_template(&self, device_id: &str, template: &str) -> Option<Operation> {
+        self.device_operations(device_id)
+            .and_then(|operations| operations.matching_smartrest_template(template))
+            .filter(|operation| operation.workflow().is_some())
+            .or_else(|| {
+                self.operations
+                    .matching_smartrest_template(template)
+                    .filter(|operation| operation.workflow().is_some())
+            })
+    }
+
+    /// The custom operation of a device onto which a thin-edge operation is mapped
+    fn matching_workflow(&self, device_id: &str, tedge_operation: &str) -> Option<&Operation> {
+        self.device_operations(device_id)
+            .and_then(|operations| operations.matching_workflow(tedge_operation))
+            .or_else(|| self.operations.matching_workflow(tedge_operation))
+    }
+
+    /// The custom operations declared for a device, given its external id
+    fn device_operations(&self, device_id: &str) -> Option<&Operations> {
+        if device_id == self.device_name {
+            Some(&self.operations)
+        } else {
+            self.children.get(device_id)
+        }
+    }
+
+    /// Map a SmartREST request of a custom operation onto a thin-edge command
+    fn forward_workflow_request(
+        &mut self,
+        smartrest: &str,
+        device_id: &str,
+        template: &str,
+    ) -> Result<Vec<Message>, CumulocityMapperError> {
+        let Some(workflow) = self
+            .matching_workflow_template(device_id, template)
+            .and_then(|operation| operation.workflow().cloned())
+        else {
+            return Ok(vec![]);
+        };
+        let payload = workflow.command_payload(smartrest)?;
+        let cmd_id = self.command_id.new_id();
+        let message =
+            self.workflow_command_message(device_id, &workflow.operation, payload, cmd_id)?;
+        Ok(vec![message])
+    }
+
+    /// Build the init message of the thin-edge command onto which a custom operation is mapped
+    pub(crate) fn workflow_command_message(
+        &self,
+        device_id: &str,
+        operation: &str,
+        payload: serde_json::Value,
+        cmd_id: CmdId,
+    ) -> Result<Message, CumulocityMapperError> {
+        let target = self
+            .entity_store
+            .try_get_by_external_id(&device_id.into())?;
+        let channel = Channel::Command {
+            operation: OperationType::Custom(operation.to_string()),
+            cmd_id,
+        };
+        let topic = self.mqtt_schema.topic_for(&target.topic_id, &channel);
+        Ok(Message::new(&topic, payload.to_string())
+            .with_retain()
+            .with_qos(QoS::AtLeastOnce))
+    }
+
     async fn forward_operation_request(
         &mut self,
         payload: &str,
@@ -919,6 +1291,9 @@ pub enum CumulocityConverterBuildError {
 
     #[error(transparent)]
     OperationLogsError(#[from] OperationLogsError),
+
+    #[error(transparent)]
+    JournalError(#[from] JournalError),
 }
 
 impl CumulocityConverter {
@@ -934,7 +1309,26 @@ impl CumulocityConverter {
         trace!("Message content: {:?}", message.payload_str());
         match self.mqtt_schema.entity_channel_of(&message.topic) {
             Ok((source, channel)) => self.try_convert_te_topics(source, channel, message).await,
-            Err(_) => self.try_convert_tedge_topics(message).await,
+            Err(_) => match self.config.topic_schemes.translate(&message.topic.name) {
+                Some((source, channel)) => Ok(vec![self.translate_topic(source, channel, message)]),
+                None => self.try_convert_tedge_topics(message).await,
+            },
+        }
+    }
+
+    /// Re-publishes a message received on a brownfield topic on the matching thin-edge topic,
+    /// as defined by the custom topic schemes
+    fn translate_topic(
+        &self,
+        source: EntityTopicId,
+        channel: Channel,
+        message: &Message,
+    ) -> Message {
+        let topic = self.mqtt_schema.topic_for(&source, &channel);
+        debug!("Translating {} into {}", message.topic.name, topic.name);
+        Message {
+            topic,
+            ..message.clone()
         }
     }
 
@@ -947,6 +1341,9 @@ impl CumulocityConverter {
         let mut registration_messages: Vec<Message> = vec![];
         match &channel {
             Channel::EntityMetadata => {
+                if message.payload_bytes().is_empty() {
+                    return Ok(self.deregister_entity(&source));
+                }
                 if let Ok(register_message) = EntityRegistrationMessage::try_from(message) {
                     match self.entity_store.update(register_message.clone()) {
                         Err(e) => {
@@ -1021,13 +1418,14 @@ impl CumulocityConverter {
         channel: Channel,
         message: &Message,
     ) -> Result<Vec<Message>, ConversionError> {
-        match &channel {
+        let messages = match &channel {
             Channel::EntityTwinData { fragment_key } => {
                 self.try_convert_entity_twin_data(&source, message, fragment_key)
             }
 
             Channel::Measurement { measurement_type } => {
                 self.try_convert_measurement(&source, message, measurement_type)
+                    .await
             }
 
             Channel::Event { event_type } => {
@@ -1122,9 +1520,21 @@ impl CumulocityConverter {
                     .await
             }
 
+            Channel::Command {
+                operation: OperationType::Custom(operation),
+                cmd_id,
+            } if self.command_id.is_generator_of(cmd_id) => {
+                self.publish_workflow_operation_status(&source, operation, message)
+            }
+
             Channel::Health => self.process_health_status_message(&source, message).await,
 
             _ => Ok(vec![]),
+        }?;
+
+        match &channel {
+            Channel::Command { cmd_id, .. } => Ok(self.adapt_operation_status(cmd_id, messages)),
+            _ => Ok(messages),
         }
     }
 
@@ -1174,16 +1584,23 @@ impl CumulocityConverter {
     ) -> Result<Vec<Message>, ConversionError> {
         let entity_topic_id = &registration_message.topic_id;
         self.entity_store.update(registration_message.clone())?;
-        if registration_message.r#type == EntityType::ChildDevice {
-            self.children.insert(
-                self.entity_store
-                    .get(entity_topic_id)
-                    .expect("Should have been registered in the previous step")
-                    .external_id
-                    .as_ref()
-                    .into(),
-                Operations::default(),
-            );
+        if matches!(
+            registration_message.r#type,
+            EntityType::ChildDevice | EntityType::Service
+        ) {
+            let external_id: String = self
+                .entity_store
+                .get(entity_topic_id)
+                .expect("Should have been registered in the previous step")
+                .external_id
+                .as_ref()
+                .into();
+            if let Some(state) = self.reconciliation_state.as_mut() {
+                state.record_registration(&external_id);
+            }
+            if registration_message.r#type == EntityType::ChildDevice {
+                self.children.entry(external_id).or_default();
+            }
         }
 
         let mut registration_messages = vec![];
@@ -1194,6 +1611,35 @@ impl CumulocityConverter {
         Ok(registration_messages)
     }
 
+    /// Deregisters an entity along with all its child devices and services,
+    /// queuing the deletion of the matching managed objects from Cumulocity.
+    ///
+    /// Returns the messages clearing the registration of the child devices and services,
+    /// so other components are notified of the cascading deregistration.
+    fn deregister_entity(&mut self, source: &EntityTopicId) -> Vec<Message> {
+        let mut messages = vec![];
+        for entity in self.entity_store.deregister_entity(source) {
+            let external_id: String = entity.external_id.clone().into();
+            if entity.r#type == EntityType::ChildDevice {
+                self.children.remove(&external_id);
+            }
+            if let Some(state) = self.reconciliation_state.as_mut() {
+                state.record_deregistration(&external_id);
+            }
+
+            info!("Deleting {} {} from Cumulocity", entity.r#type, external_id);
+            self.delete_managed_object(external_id);
+
+            if &entity.topic_id != source {
+                let topic = self
+                    .mqtt_schema
+                    .topic_for(&entity.topic_id, &Channel::EntityMetadata);
+                messages.push(Message::new(&topic, "").with_retain());
+            }
+        }
+        messages
+    }
+
     fn convert_entity_registration_message(&self, value: &EntityRegistrationMessage) -> Message {
         let entity_topic_id = value.topic_id.clone();
 
@@ -1232,6 +1678,9 @@ impl CumulocityConverter {
                 self.alarm_converter.process_internal_alarm(message);
                 Ok(vec![])
             }
+            topic if topic.name == C8Y_JSON_OPERATIONS_TOPIC => {
+                self.parse_json_operation(message).await
+            }
             topic if C8yTopic::accept(topic) => self.parse_c8y_topics(message).await,
             _ => {
                 error!("Unsupported topic: {}", message.topic.name);
@@ -1453,6 +1902,64 @@ impl CumulocityConverter {
         }
     }
 
+    /// Translate the state of a command created for a custom SmartREST operation
+    fn publish_workflow_operation_status(
+        &mut self,
+        target: &EntityTopicId,
+        operation: &str,
+        message: &Message,
+    ) -> Result<Vec<Message>, ConversionError> {
+        let Some(entity) = self.entity_store.get(target) else {
+            return Ok(vec![]);
+        };
+        let Some(c8y_operation) = self
+            .matching_workflow(entity.external_id.as_ref(), operation)
+            .map(|operation| operation.name.clone())
+        else {
+            return Ok(vec![]);
+        };
+        let Some(command) = GenericCommandState::from_command_message(message)? else {
+            // The command has been fully processed
+            return Ok(vec![]);
+        };
+        let topic = self
+            .entity_store
+            .get(target)
+            .and_then(C8yTopic::smartrest_response_topic)
+            .ok_or_else(|| Error::UnknownEntity(target.to_string()))?;
+        let clearing_message = Message::new(&command.topic, vec![])
+            .with_retain()
+            .with_qos(QoS::AtLeastOnce);
+
+        match command.status.as_str() {
+            "executing" => {
+                let smartrest_set_operation = set_operation_executing(c8y_operation.as_str());
+                Ok(vec![Message::new(&topic, smartrest_set_operation)])
+            }
+            "successful" => {
+                let smartrest_set_operation = succeed_operation_no_payload(c8y_operation.as_str());
+                Ok(vec![
+                    clearing_message,
+                    Message::new(&topic, smartrest_set_operation),
+                ])
+            }
+            "failed" => {
+                let reason = command
+                    .failure_reason()
+                    .unwrap_or_else(|| "Unknown reason".to_string());
+                let smartrest_set_operation = fail_operation(c8y_operation.as_str(), &reason);
+                Ok(vec![
+                    clearing_message,
+                    Message::new(&topic, smartrest_set_operation),
+                ])
+            }
+            _ => {
+                // The other states are ignored
+                Ok(vec![])
+            }
+        }
+    }
+
     async fn register_software_list_operation(
         &self,
         _target: &EntityTopicId,
@@ -1507,6 +2014,10 @@ impl CumulocityConverter {
                 // The command has not been processed yet
                 Ok(vec![])
             }
+            CommandStatus::Executing if response.payload.progress.is_some() => {
+                // The operation has already been marked as executing
+                Ok(vec![])
+            }
             CommandStatus::Executing => {
                 let smartrest_set_operation_status =
                     set_operation_executing(CumulocitySupportedOperations::C8ySoftwareUpdate);
@@ -1646,11 +2157,13 @@ pub(crate) mod tests {
     use tedge_api::entity_store::EntityRegistrationMessage;
     use tedge_api::entity_store::EntityType;
     use tedge_api::entity_store::InvalidExternalIdError;
+    use tedge_api::entity_topic_scheme::TopicSchemes;
     use tedge_api::mqtt_topics::ChannelFilter;
     use tedge_api::mqtt_topics::EntityFilter;
     use tedge_api::mqtt_topics::EntityTopicId;
     use tedge_api::mqtt_topics::MqttSchema;
     use tedge_api::mqtt_topics::OperationType;
+    use tedge_api::pending_entity_store::PendingEntityStoreConfig;
     use tedge_api::SoftwareUpdateCommand;
     use tedge_mqtt_ext::test_helpers::assert_messages_matching;
     use tedge_mqtt_ext::Message;
@@ -1857,6 +2370,91 @@ pub(crate) mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn convert_measurement_of_an_entity_matching_a_custom_topic_scheme() {
+        let tmp_dir = TempTedgeDir::new();
+        let mut config = c8y_converter_config(&tmp_dir);
+        config.topic_schemes = toml::from_str(
+            r#"
+[[scheme]]
+name = "factory"
+
+[[scheme.entity]]
+topic_id = "site/{line}//"
+type = "child-device"
+
+[[scheme.entity]]
+topic_id = "site/{line}/{machine}/"
+type = "child-device"
+external_id = "{line}-{machine}"
+"#,
+        )
+        .unwrap();
+        let (mut converter, _http_proxy) = create_c8y_converter_from_config(config);
+
+        let in_message = Message::new(
+            &Topic::new_unchecked("te/site/line1/press7//m/"),
+            json!({
+                "temp": 1,
+                "time": "2021-11-16T17:45:40.571760714+01:00"
+            })
+            .to_string(),
+        );
+
+        let messages = converter.convert(&in_message).await;
+
+        assert_messages_matching(
+            &messages,
+            [
+                (
+                    "te/site/line1//",
+                    json!({
+                        "@type":"child-device",
+                        "@id":"test-device:site:line1",
+                        "@parent":"device/main//",
+                        "name":"line1"
+                    })
+                    .into(),
+                ),
+                (
+                    "c8y/s/us",
+                    "101,test-device:site:line1,line1,thin-edge.io-child".into(),
+                ),
+                (
+                    "te/site/line1/press7/",
+                    json!({
+                        "@type":"child-device",
+                        "@id":"line1-press7",
+                        "@parent":"site/line1//",
+                        "name":"press7"
+                    })
+                    .into(),
+                ),
+                (
+                    "c8y/s/us/test-device:site:line1",
+                    "101,line1-press7,press7,thin-edge.io-child".into(),
+                ),
+                (
+                    "c8y/measurement/measurements/create",
+                    json!({
+                        "externalSource":{
+                            "externalId":"line1-press7",
+                            "type":"c8y_Serial"
+                        },
+                        "temp":{
+                            "temp":{
+                                "value":1.0
+                            }
+                        },
+                        "time":"2021-11-16T17:45:40.571760714+01:00",
+                        "type":"ThinEdgeMeasurement"
+                    })
+                    .into(),
+                ),
+            ],
+        );
+    }
+
     #[tokio::test]
     async fn convert_measurement_with_nested_child_device() {
         let tmp_dir = TempTedgeDir::new();
@@ -3200,6 +3798,60 @@ pub(crate) mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn dropped_early_messages_are_reported_on_the_mapper_health_topic() {
+        let tmp_dir = TempTedgeDir::new();
+        let mut config = c8y_converter_config(&tmp_dir);
+        config.enable_auto_register = false;
+        config.pending_entity_store_config = PendingEntityStoreConfig {
+            telemetry_cache_size: 2,
+            ..Default::default()
+        };
+
+        let (mut converter, _http_proxy) = create_c8y_converter_from_config(config);
+
+        for i in 0..2 {
+            let measurement_message = Message::new(
+                &Topic::new_unchecked("te/device/child1///m/environment"),
+                json!({ "temperature": i }).to_string(),
+            );
+            let mapped_messages = converter.convert(&measurement_message).await;
+            assert!(mapped_messages.is_empty());
+        }
+
+        // The cache being full, the oldest measurement is dropped
+        let measurement_message = Message::new(
+            &Topic::new_unchecked("te/device/child1///m/environment"),
+            json!({ "temperature": 2 }).to_string(),
+        );
+        let mapped_messages = converter.convert(&measurement_message).await;
+        assert_eq!(mapped_messages.len(), 1);
+        assert_eq!(
+            mapped_messages[0].topic.name,
+            "te/device/main/service/tedge-mapper-c8y/status/health"
+        );
+        let health_status: Value =
+            serde_json::from_str(mapped_messages[0].payload_str().unwrap()).unwrap();
+        assert_eq!(health_status["status"], "up");
+        assert_eq!(
+            health_status["dropped_messages"],
+            json!({"telemetry": 1, "metadata": 0, "registrations": 0})
+        );
+
+        // Further drops are not reported right away
+        let mapped_messages = converter.convert(&measurement_message).await;
+        assert!(mapped_messages.is_empty());
+
+        // But on the next flush
+        let report = converter.flush_dropped_messages().unwrap();
+        let health_status: Value = serde_json::from_str(report.payload_str().unwrap()).unwrap();
+        assert_eq!(
+            health_status["dropped_messages"],
+            json!({"telemetry": 2, "metadata": 0, "registrations": 0})
+        );
+        assert!(converter.flush_dropped_messages().is_none());
+    }
+
     #[tokio::test]
     async fn early_child_device_registrations_processed_only_after_parent_registration() {
         let tmp_dir = TempTedgeDir::new();
@@ -3322,6 +3974,8 @@ pub(crate) mod tests {
             auth_proxy_protocol,
             MqttSchema::default(),
             true,
+            PendingEntityStoreConfig::default(),
+            TopicSchemes::default(),
         )
     }
     fn create_c8y_converter_from_config(
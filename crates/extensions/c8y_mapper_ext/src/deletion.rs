@@ -0,0 +1,133 @@
+//! Deletion of managed objects from Cumulocity, off the conversion path.
+//!
+//! The mapper doesn't await the HTTP requests deleting managed objects.
+//! Instead, [DeleteManagedObject] requests are sent to a [ManagedObjectDeleter] actor
+//! that processes these deletions in the background, retrying the failed ones.
+use async_trait::async_trait;
+use c8y_http_proxy::handle::C8YHttpProxy;
+use c8y_http_proxy::messages::C8YRestRequest;
+use c8y_http_proxy::messages::C8YRestResult;
+use std::convert::Infallible;
+use std::time::Duration;
+use tedge_actors::Actor;
+use tedge_actors::Builder;
+use tedge_actors::DynSender;
+use tedge_actors::MessageReceiver;
+use tedge_actors::MessageSink;
+use tedge_actors::NoConfig;
+use tedge_actors::NoMessage;
+use tedge_actors::RuntimeError;
+use tedge_actors::RuntimeRequest;
+use tedge_actors::RuntimeRequestSink;
+use tedge_actors::ServiceProvider;
+use tedge_actors::SimpleMessageBox;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+/// The number of times the deletion of a managed object is attempted before giving up
+const MAX_ATTEMPTS: u32 = 3;
+
+/// The delay before retrying a failed deletion, which is doubled on each new failure
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Request the deletion of the managed object with the given external id
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeleteManagedObject {
+    pub external_id: String,
+}
+
+pub struct ManagedObjectDeleterBuilder {
+    http_proxy: C8YHttpProxy,
+    message_box: SimpleMessageBoxBuilder<DeleteManagedObject, NoMessage>,
+}
+
+impl ManagedObjectDeleterBuilder {
+    /// Create a deleter using its own client of the C8Y HTTP proxy
+    pub fn new(http: &mut impl ServiceProvider<C8YRestRequest, C8YRestResult, NoConfig>) -> Self {
+        let http_proxy = C8YHttpProxy::new("ManagedObjectDeleter => C8YHttpProxy", http);
+        let message_box = SimpleMessageBoxBuilder::new("ManagedObjectDeleter", 64);
+        ManagedObjectDeleterBuilder {
+            http_proxy,
+            message_box,
+        }
+    }
+}
+
+impl MessageSink<DeleteManagedObject, NoConfig> for ManagedObjectDeleterBuilder {
+    fn get_config(&self) -> NoConfig {
+        NoConfig
+    }
+
+    fn get_sender(&self) -> DynSender<DeleteManagedObject> {
+        self.message_box.get_sender()
+    }
+}
+
+impl RuntimeRequestSink for ManagedObjectDeleterBuilder {
+    fn get_signal_sender(&self) -> DynSender<RuntimeRequest> {
+        self.message_box.get_signal_sender()
+    }
+}
+
+impl Builder<ManagedObjectDeleter> for ManagedObjectDeleterBuilder {
+    type Error = Infallible;
+
+    fn try_build(self) -> Result<ManagedObjectDeleter, Self::Error> {
+        Ok(self.build())
+    }
+
+    fn build(self) -> ManagedObjectDeleter {
+        ManagedObjectDeleter {
+            http_proxy: self.http_proxy,
+            message_box: self.message_box.build(),
+        }
+    }
+}
+
+/// Actor deleting managed objects from Cumulocity, one after the other
+pub struct ManagedObjectDeleter {
+    http_proxy: C8YHttpProxy,
+    message_box: SimpleMessageBox<DeleteManagedObject, NoMessage>,
+}
+
+#[async_trait]
+impl Actor for ManagedObjectDeleter {
+    fn name(&self) -> &str {
+        "ManagedObjectDeleter"
+    }
+
+    async fn run(mut self) -> Result<(), RuntimeError> {
+        while let Some(DeleteManagedObject { external_id }) = self.message_box.recv().await {
+            self.delete_managed_object(external_id).await
+        }
+        Ok(())
+    }
+}
+
+impl ManagedObjectDeleter {
+    async fn delete_managed_object(&mut self, external_id: String) {
+        let mut delay = RETRY_DELAY;
+        for attempt in 1..=MAX_ATTEMPTS {
+            match self
+                .http_proxy
+                .delete_managed_object(external_id.clone())
+                .await
+            {
+                Ok(()) => {
+                    info!("Deleted {external_id} from Cumulocity");
+                    return;
+                }
+                Err(err) if attempt < MAX_ATTEMPTS => {
+                    warn!("Fail to delete {external_id} from Cumulocity, retrying in {delay:?}: {err}");
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(err) => {
+                    error!("Fail to delete {external_id} from Cumulocity after {MAX_ATTEMPTS} attempts: {err}");
+                }
+            }
+        }
+    }
+}
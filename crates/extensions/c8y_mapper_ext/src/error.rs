@@ -76,6 +76,9 @@ pub enum ConversionError {
     #[error(transparent)]
     FromSmartRestDeserializerError(#[from] c8y_api::smartrest::error::SmartRestDeserializerError),
 
+    #[error(transparent)]
+    FromWorkflowExecutionError(#[from] tedge_api::workflow::WorkflowExecutionError),
+
     #[error("Unsupported topic: {0}")]
     UnsupportedTopic(String),
 
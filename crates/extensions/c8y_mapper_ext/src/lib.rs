@@ -1,14 +1,19 @@
 pub mod actor;
+pub mod aggregation;
 pub mod alarm_converter;
+pub mod bulk_measurements;
+pub mod cloud_entities;
 pub mod compatibility_adapter;
 pub mod config;
 pub mod converter;
+pub mod deletion;
 pub mod dynamic_discovery;
 pub mod error;
 mod fragments;
 mod inventory;
 pub mod json;
 mod operations;
+mod reconciliation;
 mod serializer;
 pub mod service_monitor;
 #[cfg(test)]
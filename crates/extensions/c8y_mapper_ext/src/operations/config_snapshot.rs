@@ -7,7 +7,6 @@ use crate::error::CumulocityMapperError;
 use crate::operations::FtsDownloadOperationType;
 use anyhow::Context;
 use c8y_api::smartrest::smartrest_deserializer::SmartRestConfigUploadRequest;
-use c8y_api::smartrest::smartrest_deserializer::SmartRestRequestGeneric;
 use c8y_api::smartrest::smartrest_serializer::fail_operation;
 use c8y_api::smartrest::smartrest_serializer::set_operation_executing;
 use c8y_api::smartrest::smartrest_serializer::CumulocitySupportedOperations;
@@ -53,14 +52,13 @@ impl CumulocityConverter {
     /// Command ID is generated here, but it should be replaced by c8y's operation ID in the future.
     pub fn convert_config_snapshot_request(
         &self,
-        smartrest: &str,
+        snapshot_request: SmartRestConfigUploadRequest,
+        cmd_id: CmdId,
     ) -> Result<Vec<Message>, CumulocityMapperError> {
-        let snapshot_request = SmartRestConfigUploadRequest::from_smartrest(smartrest)?;
         let target = self
             .entity_store
             .try_get_by_external_id(&snapshot_request.device.clone().into())?;
 
-        let cmd_id = self.command_id.new_id();
         let channel = Channel::Command {
             operation: OperationType::ConfigSnapshot,
             cmd_id: cmd_id.clone(),
@@ -1,9 +1,9 @@
+use crate::actor::CmdId;
 use crate::converter::CumulocityConverter;
 use crate::error::ConversionError;
 use crate::error::CumulocityMapperError;
 use c8y_api::smartrest::smartrest_deserializer::SmartRestConfigDownloadRequest;
 use c8y_api::smartrest::smartrest_deserializer::SmartRestOperationVariant;
-use c8y_api::smartrest::smartrest_deserializer::SmartRestRequestGeneric;
 use c8y_api::smartrest::smartrest_serializer::fail_operation;
 use c8y_api::smartrest::smartrest_serializer::set_operation_executing;
 use c8y_api::smartrest::smartrest_serializer::succeed_operation_no_payload;
@@ -157,17 +157,16 @@ impl CumulocityConverter {
     /// Upon receiving a SmartREST c8y_DownloadConfigFile request,
     /// - Create a download request if the target file is not available in cache.
     /// - If the file is already available, proceed to create a new ThinEdge config_update command.
-    /// Command ID is generated here, but it should be replaced by c8y's operation ID in the future.
+    /// The command ID is given by the caller, embedding c8y's operation ID when the operation is received as JSON.
     pub async fn convert_config_update_request(
         &mut self,
-        smartrest: &str,
+        smartrest: SmartRestConfigDownloadRequest,
+        cmd_id: CmdId,
     ) -> Result<Vec<Message>, CumulocityMapperError> {
-        let smartrest = SmartRestConfigDownloadRequest::from_smartrest(smartrest)?;
         let target = self
             .entity_store
             .try_get_by_external_id(&smartrest.device.clone().into())?;
 
-        let cmd_id = self.command_id.new_id();
         let remote_url = smartrest.url.as_str();
         let file_cache_key = sha256::digest(remote_url);
         let file_cache_path = self.config.data_dir.cache_dir().join(file_cache_key);
@@ -1,8 +1,8 @@
+use crate::actor::CmdId;
 use crate::converter::CumulocityConverter;
 use crate::error::ConversionError;
 use crate::error::CumulocityMapperError;
 use c8y_api::smartrest::smartrest_deserializer::SmartRestFirmwareRequest;
-use c8y_api::smartrest::smartrest_deserializer::SmartRestRequestGeneric;
 use c8y_api::smartrest::smartrest_serializer::fail_operation;
 use c8y_api::smartrest::smartrest_serializer::set_operation_executing;
 use c8y_api::smartrest::smartrest_serializer::succeed_operation_no_payload;
@@ -41,15 +41,13 @@ impl CumulocityConverter {
     /// Command ID is generated here, but it should be replaced by c8y's operation ID in the future.
     pub fn convert_firmware_update_request(
         &self,
-        smartrest: &str,
+        firmware_request: SmartRestFirmwareRequest,
+        cmd_id: CmdId,
     ) -> Result<Vec<Message>, CumulocityMapperError> {
-        let firmware_request = SmartRestFirmwareRequest::from_smartrest(smartrest)?;
-
         let target = self
             .entity_store
             .try_get_by_external_id(&firmware_request.device.clone().into())?;
 
-        let cmd_id = self.command_id.new_id();
         let channel = Channel::Command {
             operation: OperationType::FirmwareUpdate,
             cmd_id: cmd_id.clone(),
@@ -0,0 +1,190 @@
+//! Operations received as JSON over MQTT, when `c8y.operations.protocol` is `json`.
+//!
+//! The requests are converted into thin-edge commands by the same functions as SmartREST requests,
+//! and the status of the operations is then reported as JSON, using the Cumulocity operation id.
+//!
+//! The Cumulocity operation id is carried by the id of the thin-edge command,
+//! so the status of each command is reported for its own operation,
+//! whatever the order in which the commands complete, and even after a restart of the mapper.
+//! To leave the per-operation status handlers untouched, these keep producing SmartREST `501`,
+//! `502` and `503` messages that are translated for the commands created for a JSON operation.
+use crate::actor::CmdId;
+use crate::converter::CumulocityConverter;
+use crate::error::ConversionError;
+use c8y_api::json_c8y::C8yOperationStatus;
+use c8y_api::json_c8y_deserializer::C8yDeviceControlOperation;
+use c8y_api::json_c8y_deserializer::C8yDeviceControlRequest;
+use c8y_api::json_c8y_deserializer::C8Y_JSON_OPERATION_UPDATE_TOPIC;
+use c8y_api::smartrest::smartrest_serializer::C8yOperation;
+use c8y_api::smartrest::topic::SMARTREST_PUBLISH_TOPIC;
+use tedge_api::Jsonify;
+use tedge_mqtt_ext::Message;
+use tedge_mqtt_ext::Topic;
+use tracing::debug;
+use tracing::error;
+
+impl CumulocityConverter {
+    /// Convert an operation received on the `devicecontrol/notifications` topic
+    ///
+    /// If the operation cannot be converted, the operation is reported as failed.
+    pub async fn parse_json_operation(
+        &mut self,
+        message: &Message,
+    ) -> Result<Vec<Message>, ConversionError> {
+        let operation = C8yDeviceControlOperation::from_json(message.payload_str()?)?;
+        match self.convert_json_operation(&operation).await {
+            Ok(messages) => Ok(messages),
+            Err(err) => {
+                error!("{err}");
+                let topic = json_operation_update_topic(&operation.id);
+                let failed = C8yOperationStatus::Failed {
+                    failure_reason: err.to_string(),
+                };
+                Ok(vec![
+                    Message::new(&topic, C8yOperationStatus::Executing.to_json()),
+                    Message::new(&topic, failed.to_json()),
+                ])
+            }
+        }
+    }
+
+    async fn convert_json_operation(
+        &mut self,
+        operation: &C8yDeviceControlOperation,
+    ) -> Result<Vec<Message>, ConversionError> {
+        self.entity_store
+            .try_get_by_external_id(&operation.device().into())?;
+        let cmd_id = self.json_command_id(&operation.id);
+
+        let messages = match operation.request()? {
+            Some(request) => {
+                let capabilities = &self.config.capabilities;
+                match request {
+                    C8yDeviceControlRequest::Restart(request) => {
+                        self.forward_restart_request(request, cmd_id)?
+                    }
+                    C8yDeviceControlRequest::SoftwareUpdate(request) => {
+                        self.forward_software_request(request, cmd_id).await?
+                    }
+                    C8yDeviceControlRequest::LogfileRequest(request) if capabilities.log_upload => {
+                        self.convert_log_upload_request(request, cmd_id)?
+                    }
+                    C8yDeviceControlRequest::UploadConfigFile(request)
+                        if capabilities.config_snapshot =>
+                    {
+                        self.convert_config_snapshot_request(request, cmd_id)?
+                    }
+                    C8yDeviceControlRequest::DownloadConfigFile(request)
+                        if capabilities.config_update =>
+                    {
+                        self.convert_config_update_request(request, cmd_id).await?
+                    }
+                    C8yDeviceControlRequest::Firmware(request) if capabilities.firmware_update => {
+                        self.convert_firmware_update_request(request, cmd_id)?
+                    }
+                    request => {
+                        let operation = request.operation();
+                        debug!("Ignored. The {} operation is disabled", operation.name());
+                        return Ok(vec![]);
+                    }
+                }
+            }
+            None => match self.forward_json_workflow_request(operation, cmd_id)? {
+                Some(messages) => messages,
+                None => {
+                    debug!("Ignored. Operation not supported: {}", operation.id);
+                    return Ok(vec![]);
+                }
+            },
+        };
+
+        Ok(messages)
+    }
+
+    /// Map a custom operation onto a thin-edge command,
+    /// using as command payload the fragment named after an operation file with a `[exec.workflow]` section
+    fn forward_json_workflow_request(
+        &self,
+        operation: &C8yDeviceControlOperation,
+        cmd_id: CmdId,
+    ) -> Result<Option<Vec<Message>>, ConversionError> {
+        for (name, fragment) in &operation.fragments {
+            let Some(workflow) = self
+                .operations
+                .get_operation_by_name(name)
+                .and_then(|operation| operation.workflow())
+            else {
+                continue;
+            };
+
+            let mut payload = match fragment {
+                serde_json::Value::Object(fields) => fields.clone(),
+                _ => serde_json::Map::new(),
+            };
+            payload.insert("status".to_string(), "init".into());
+            let message = self.workflow_command_message(
+                operation.device(),
+                &workflow.operation,
+                payload.into(),
+                cmd_id,
+            )?;
+            return Ok(Some(vec![message]));
+        }
+        Ok(None)
+    }
+
+    /// The id of the command created for an operation received as JSON, embedding the id of this operation
+    fn json_command_id(&self, operation_id: &str) -> CmdId {
+        format!(
+            "{}{JSON_OPERATION_ID_SEPARATOR}{operation_id}",
+            self.command_id.new_id()
+        )
+    }
+
+    /// Translate the SmartREST status updates produced for a command created for an operation received as JSON
+    /// into JSON status updates of that operation
+    ///
+    /// The messages produced for any other command are returned unchanged.
+    pub(crate) fn adapt_operation_status(
+        &self,
+        cmd_id: &str,
+        messages: Vec<Message>,
+    ) -> Vec<Message> {
+        let Some(operation_id) = json_operation_id(cmd_id) else {
+            return messages;
+        };
+
+        messages
+            .into_iter()
+            .map(|message| {
+                if !message.topic.name.starts_with(SMARTREST_PUBLISH_TOPIC) {
+                    return message;
+                }
+                match message
+                    .payload_str()
+                    .ok()
+                    .and_then(C8yOperationStatus::from_smartrest)
+                {
+                    Some((_, status)) => {
+                        Message::new(&json_operation_update_topic(operation_id), status.to_json())
+                    }
+                    None => message,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Separates the id generated for a command from the id of the Cumulocity operation received as JSON
+const JSON_OPERATION_ID_SEPARATOR: &str = "-c8y-op-";
+
+/// The id of the Cumulocity operation for which a command has been created, if received as JSON
+fn json_operation_id(cmd_id: &str) -> Option<&str> {
+    cmd_id
+        .rsplit_once(JSON_OPERATION_ID_SEPARATOR)
+        .map(|(_, operation_id)| operation_id)
+}
+
+fn json_operation_update_topic(operation_id: &str) -> Topic {
+    Topic::new_unchecked(&format!("{C8Y_JSON_OPERATION_UPDATE_TOPIC}/{operation_id}"))
+}
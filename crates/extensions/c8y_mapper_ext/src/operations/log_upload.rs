@@ -7,7 +7,6 @@ use crate::error::ConversionError;
 use crate::error::CumulocityMapperError;
 use anyhow::Context;
 use c8y_api::smartrest::smartrest_deserializer::SmartRestLogRequest;
-use c8y_api::smartrest::smartrest_deserializer::SmartRestRequestGeneric;
 use c8y_api::smartrest::smartrest_serializer::fail_operation;
 use c8y_api::smartrest::smartrest_serializer::set_operation_executing;
 use c8y_api::smartrest::smartrest_serializer::CumulocitySupportedOperations;
@@ -51,18 +50,17 @@ pub fn log_upload_topic_filter(mqtt_schema: &MqttSchema) -> TopicFilter {
 }
 
 impl CumulocityConverter {
-    /// Convert a SmartREST logfile request to a Thin Edge log_upload command
+    /// Convert a c8y_LogfileRequest to a Thin Edge log_upload command
     pub fn convert_log_upload_request(
         &self,
-        smartrest: &str,
+        log_request: SmartRestLogRequest,
+        cmd_id: CmdId,
     ) -> Result<Vec<Message>, CumulocityMapperError> {
-        let log_request = SmartRestLogRequest::from_smartrest(smartrest)?;
         let device_external_id = log_request.device.into();
         let target = self
             .entity_store
             .try_get_by_external_id(&device_external_id)?;
 
-        let cmd_id = self.command_id.new_id();
         let channel = Channel::Command {
             operation: OperationType::LogUpload,
             cmd_id: cmd_id.clone(),
@@ -25,6 +25,7 @@ use tedge_mqtt_ext::MqttMessage;
 pub mod config_snapshot;
 pub mod config_update;
 pub mod firmware_update;
+pub mod json_operations;
 pub mod log_upload;
 
 /// Represents a pending download performed by the downloader from the FTS.
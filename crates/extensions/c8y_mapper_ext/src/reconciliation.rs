@@ -0,0 +1,303 @@
+//! Reconciliation of the child devices and services registered on Cumulocity with the local entities,
+//! when `c8y.reconciliation.enable` is set.
+//!
+//! Once the sync phase is over, giving time to the local entities to be registered,
+//! the mapper requests the whole hierarchy of the main device registered on Cumulocity,
+//! i.e. the nested child devices and the services of all these devices,
+//! which is walked over HTTP by a [CloudEntityFetcher](crate::cloud_entities::CloudEntityFetcher).
+//! The child devices and services registered on Cumulocity but no longer known locally
+//! are then handled according to the configured [ReconciliationPolicy],
+//! and a summary event is sent to Cumulocity.
+//!
+//! With the `delete` policy, a stale child device or service is deleted right away
+//! only if it is known to have been deregistered locally.
+//! Any other stale entity is deleted only once found stale by [DELETION_GRACE_PASSES] consecutive passes,
+//! i.e. over several restarts of the mapper, so an entity that is slow to register again is not deleted.
+//! The deregistered entities and the number of passes each stale entity has been found stale
+//! are persisted in a [ReconciliationState] file.
+use crate::cloud_entities::CloudEntity;
+use crate::cloud_entities::FetchCloudEntities;
+use crate::converter::CumulocityConverter;
+use crate::converter::C8Y_JSON_MQTT_EVENTS_TOPIC;
+use c8y_api::smartrest::inventory::service_status_update_message;
+use c8y_api::smartrest::topic::publish_topic_from_ancestors;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use tedge_api::entity_store::EntityType;
+use tedge_config::ReconciliationPolicy;
+use tedge_mqtt_ext::Message;
+use tedge_mqtt_ext::Topic;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::info;
+use tracing::warn;
+
+/// The type of the event summarizing a reconciliation pass
+const RECONCILIATION_EVENT_TYPE: &str = "c8y_ChildDeviceReconciliation";
+
+/// The status set on the stale services with the `unavailable` policy
+const STALE_SERVICE_STATUS: &str = "down";
+
+/// The required interval, in minutes, set on the stale child devices with the `unavailable` policy.
+///
+/// Cumulocity marks a device as unavailable when no message has been received for that interval.
+const REQUIRED_AVAILABILITY_INTERVAL: u32 = 1;
+
+/// The number of consecutive reconciliation passes a child device or service not known to have been deregistered
+/// has to be found stale before being deleted with the `delete` policy
+pub const DELETION_GRACE_PASSES: u32 = 2;
+
+/// What the mapper knows of the stale child devices and services, persisted across restarts
+#[derive(Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ReconciliationState {
+    #[serde(skip)]
+    path: PathBuf,
+
+    /// The child devices and services deregistered locally, till no longer listed by Cumulocity
+    #[serde(default)]
+    deregistered: BTreeSet<String>,
+
+    /// The number of consecutive passes each stale entity has been found stale
+    #[serde(default)]
+    stale: BTreeMap<String, u32>,
+}
+
+impl ReconciliationState {
+    /// Load the state persisted at the given path, starting afresh if there is none or if it cannot be read
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let state = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|err| {
+                warn!("Ignoring invalid reconciliation state {path:?}: {err}");
+                ReconciliationState::default()
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                ReconciliationState::default()
+            }
+            Err(err) => {
+                warn!("Fail to read the reconciliation state {path:?}: {err}");
+                ReconciliationState::default()
+            }
+        };
+        ReconciliationState { path, ..state }
+    }
+
+    /// Record that a child device or service has been deregistered locally
+    pub fn record_deregistration(&mut self, external_id: &str) {
+        if self.deregistered.insert(external_id.to_string()) {
+            self.save();
+        }
+    }
+
+    /// Record that a child device or service has been registered locally
+    pub fn record_registration(&mut self, external_id: &str) {
+        let deregistered = self.deregistered.remove(external_id);
+        let stale = self.stale.remove(external_id).is_some();
+        if deregistered || stale {
+            self.save();
+        }
+    }
+
+    /// Tell which of the stale entities have to be deleted
+    ///
+    /// Returns the entities to delete now and those to delete after more passes, if still stale.
+    fn deletions(
+        &mut self,
+        cloud_entities: &HashSet<String>,
+        stale_entities: &[String],
+    ) -> (Vec<String>, Vec<String>) {
+        // Forget the entities that are no longer listed by Cumulocity or that are no longer stale
+        self.deregistered
+            .retain(|external_id| cloud_entities.contains(external_id));
+        self.stale
+            .retain(|external_id, _| stale_entities.contains(external_id));
+
+        let mut deleted = vec![];
+        let mut pending = vec![];
+        for external_id in stale_entities {
+            let passes = self.stale.entry(external_id.clone()).or_default();
+            *passes += 1;
+            if self.deregistered.contains(external_id) || *passes >= DELETION_GRACE_PASSES {
+                deleted.push(external_id.clone());
+            } else {
+                pending.push(external_id.clone());
+            }
+        }
+        self.save();
+        (deleted, pending)
+    }
+
+    fn save(&self) {
+        let content = match serde_json::to_string(self) {
+            Ok(content) => content,
+            Err(err) => {
+                warn!("Fail to serialize the reconciliation state: {err}");
+                return;
+            }
+        };
+        if let Some(dir) = self.path.parent() {
+            if let Err(err) = std::fs::create_dir_all(dir) {
+                warn!("Fail to create {dir:?}: {err}");
+                return;
+            }
+        }
+        if let Err(err) = std::fs::write(&self.path, content) {
+            warn!(
+                "Fail to persist the reconciliation state {:?}: {err}",
+                self.path
+            );
+        }
+    }
+}
+
+impl CumulocityConverter {
+    /// The request for the child devices and services registered on Cumulocity,
+    /// if these have to be reconciled with the local entities
+    pub(crate) fn cloud_entities_request(&self) -> Option<FetchCloudEntities> {
+        self.config.reconciliation.map(|_| FetchCloudEntities {
+            device_id: self.device_name.clone(),
+        })
+    }
+
+    /// Handle the child devices and services registered on Cumulocity but no longer known locally,
+    /// returning the messages to publish, including the summary event.
+    pub fn reconcile_cloud_entities(&mut self, cloud_entities: Vec<CloudEntity>) -> Vec<Message> {
+        let Some(policy) = self.config.reconciliation else {
+            return vec![];
+        };
+
+        // The child devices declared only by an operation directory are not in the entity store
+        let local_entities: HashSet<String> = self
+            .entity_store
+            .iter()
+            .filter(|(_, entity)| entity.r#type != EntityType::MainDevice)
+            .map(|(_, entity)| entity.external_id.as_ref().to_string())
+            .chain(self.children.keys().cloned())
+            .collect();
+        let mut stale_entities: Vec<&CloudEntity> = cloud_entities
+            .iter()
+            .filter(|entity| !local_entities.contains(&entity.external_id))
+            .collect();
+        stale_entities.sort_by(|a, b| a.external_id.cmp(&b.external_id));
+        let stale_external_ids: Vec<String> = stale_entities
+            .iter()
+            .map(|entity| entity.external_id.clone())
+            .collect();
+
+        let mut messages = vec![];
+        let mut pending = vec![];
+        match policy {
+            ReconciliationPolicy::Report => {
+                for entity in stale_entities.iter() {
+                    info!(
+                        "{} {} is registered on Cumulocity but no longer known locally",
+                        entity.r#type, entity.external_id
+                    );
+                }
+            }
+            ReconciliationPolicy::Unavailable => {
+                for entity in stale_entities.iter() {
+                    info!(
+                        "Marking stale {} {} as unavailable on Cumulocity",
+                        entity.r#type, entity.external_id
+                    );
+                    let mut external_ids = vec![entity.external_id.clone()];
+                    external_ids.extend(entity.ancestors.iter().cloned());
+                    if entity.r#type == EntityType::Service {
+                        messages.push(service_status_update_message(
+                            &external_ids,
+                            STALE_SERVICE_STATUS,
+                        ));
+                    } else {
+                        messages.push(Message::new(
+                            &publish_topic_from_ancestors(&external_ids),
+                            format!("117,{REQUIRED_AVAILABILITY_INTERVAL}"),
+                        ));
+                    }
+                }
+            }
+            ReconciliationPolicy::Delete => {
+                let cloud_external_ids: HashSet<String> = cloud_entities
+                    .iter()
+                    .map(|entity| entity.external_id.clone())
+                    .collect();
+                let (deleted, not_yet_deleted) = match self.reconciliation_state.as_mut() {
+                    Some(state) => state.deletions(&cloud_external_ids, &stale_external_ids),
+                    None => (vec![], stale_external_ids.clone()),
+                };
+                for external_id in deleted {
+                    info!("Deleting stale {external_id} from Cumulocity");
+                    self.delete_managed_object(external_id);
+                }
+                for external_id in not_yet_deleted.iter() {
+                    info!("{external_id} is registered on Cumulocity but no longer known locally: \
+                        deleting it if still stale after {DELETION_GRACE_PASSES} reconciliation passes");
+                }
+                pending = not_yet_deleted;
+            }
+        }
+
+        messages.push(reconciliation_summary_event(
+            policy,
+            &stale_entities,
+            &pending,
+        ));
+        messages
+    }
+}
+
+/// Build the event summarizing a reconciliation pass, to be sent for the main device
+fn reconciliation_summary_event(
+    policy: ReconciliationPolicy,
+    stale_entities: &[&CloudEntity],
+    pending: &[String],
+) -> Message {
+    let stale_external_ids = |entity_type: EntityType| -> Vec<&str> {
+        stale_entities
+            .iter()
+            .filter(|entity| entity.r#type == entity_type)
+            .map(|entity| entity.external_id.as_str())
+            .collect()
+    };
+    let count = stale_entities.len();
+    let handled = count - pending.len();
+    let text = match (count, policy) {
+        (0, _) => "No stale child devices nor services registered on Cumulocity".to_string(),
+        (count, ReconciliationPolicy::Report) => {
+            format!("{count} child device(s) and service(s) registered on Cumulocity but no longer known locally")
+        }
+        (count, ReconciliationPolicy::Unavailable) => {
+            format!("{count} stale child device(s) and service(s) marked as unavailable")
+        }
+        (count, ReconciliationPolicy::Delete) => {
+            format!(
+                "{handled} of {count} stale child device(s) and service(s) queued for deletion from Cumulocity"
+            )
+        }
+    };
+    let time = OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .unwrap_or_default();
+
+    let event = json!({
+        "type": RECONCILIATION_EVENT_TYPE,
+        "text": text,
+        "time": time,
+        RECONCILIATION_EVENT_TYPE: {
+            "policy": policy.to_string(),
+            "staleChildDevices": stale_external_ids(EntityType::ChildDevice),
+            "staleServices": stale_external_ids(EntityType::Service),
+            "pending": pending,
+        }
+    });
+    Message::new(
+        &Topic::new_unchecked(C8Y_JSON_MQTT_EVENTS_TOPIC),
+        event.to_string(),
+    )
+}
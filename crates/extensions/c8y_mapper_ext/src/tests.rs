@@ -6,13 +6,25 @@ use crate::actor::IdDownloadRequest;
 use crate::actor::IdDownloadResult;
 use crate::actor::IdUploadRequest;
 use crate::actor::IdUploadResult;
+use crate::aggregation::AggregationConfig;
+use crate::aggregation::AggregationFunction;
+use crate::aggregation::AggregationSettings;
+use crate::bulk_measurements::BulkMeasurementsConfig;
+use crate::cloud_entities::CloudEntityFetcher;
+use crate::deletion::ManagedObjectDeleterBuilder;
 use crate::Capabilities;
 use assert_json_diff::assert_json_include;
+use batcher::BatchingActorBuilder;
 use c8y_api::smartrest::topic::C8yTopic;
 use c8y_auth_proxy::url::Protocol;
+use c8y_http_proxy::messages::C8YRestError;
 use c8y_http_proxy::messages::C8YRestRequest;
+use c8y_http_proxy::messages::C8YRestResponse;
 use c8y_http_proxy::messages::C8YRestResult;
+use c8y_http_proxy::messages::ChildKind;
+use c8y_http_proxy::messages::ChildManagedObject;
 use serde_json::json;
+use std::collections::HashSet;
 use std::fs;
 use std::fs::File;
 use std::io::Read;
@@ -25,14 +37,23 @@ use tedge_actors::Builder;
 use tedge_actors::MessageReceiver;
 use tedge_actors::NoMessage;
 use tedge_actors::Sender;
+use tedge_actors::ServerMessageBox;
+use tedge_actors::ServerMessageBoxBuilder;
+use tedge_actors::ServiceConsumer;
 use tedge_actors::SimpleMessageBox;
 use tedge_actors::SimpleMessageBoxBuilder;
 use tedge_actors::WrappedInput;
+use tedge_api::entity_topic_scheme::TopicSchemes;
+use tedge_api::messages::SoftwareUpdateProgress;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
+use tedge_api::pending_entity_store::PendingEntityStoreConfig;
 use tedge_api::CommandStatus;
 use tedge_api::SoftwareUpdateCommand;
+use tedge_config::OperationsProtocol;
+use tedge_config::ReconciliationPolicy;
 use tedge_file_system_ext::FsWatchEvent;
+use tedge_mqtt_ext::test_helpers::assert_message_includes_json;
 use tedge_mqtt_ext::test_helpers::assert_received_contains_str;
 use tedge_mqtt_ext::test_helpers::assert_received_includes_json;
 use tedge_mqtt_ext::MqttMessage;
@@ -40,6 +61,7 @@ use tedge_mqtt_ext::Topic;
 use tedge_test_utils::fs::with_exec_permission;
 use tedge_test_utils::fs::TempTedgeDir;
 use tedge_timer_ext::Timeout;
+use tedge_timer_ext::TimerActor;
 
 const TEST_TIMEOUT_MS: Duration = Duration::from_millis(5000);
 
@@ -147,6 +169,428 @@ async fn child_device_registration_mapping() {
     .await;
 }
 
+#[tokio::test]
+async fn child_device_deregistration_cascades_to_its_children() {
+    let cfg_dir = TempTedgeDir::new();
+    let (mqtt, mut http, _fs, mut timer, _ul, _dl) = spawn_c8y_mapper_actor(&cfg_dir, true).await;
+
+    timer.send(Timeout::new(())).await.unwrap(); // Complete sync phase so that alarm mapping starts
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    for (topic, payload) in [
+        ("te/device/child1//", r#"{ "@type": "child-device" }"#),
+        (
+            "te/device/child2//",
+            r#"{ "@type": "child-device", "@parent": "device/child1//" }"#,
+        ),
+        ("te/device/child1/service/app", r#"{ "@type": "service" }"#),
+    ] {
+        mqtt.send(MqttMessage::new(&Topic::new_unchecked(topic), payload))
+            .await
+            .unwrap();
+        mqtt.skip(1).await;
+    }
+
+    // Deregister child1
+    mqtt.send(MqttMessage::new(&Topic::new_unchecked("te/device/child1//"), "").with_retain())
+        .await
+        .unwrap();
+
+    // All the entities are deleted from the cloud
+    let mut deleted = HashSet::new();
+    for _ in 0..3 {
+        match http.recv().await {
+            Some(C8YRestRequest::DeleteManagedObject(request)) => {
+                deleted.insert(request.device_id);
+                http.send(Ok(C8YRestResponse::Unit(()))).await.unwrap();
+            }
+            unexpected => panic!("Unexpected request: {unexpected:?}"),
+        }
+    }
+    assert_eq!(
+        deleted,
+        HashSet::from([
+            "test-device:device:child1".to_string(),
+            "test-device:device:child2".to_string(),
+            "test-device:device:child1:service:app".to_string(),
+        ])
+    );
+
+    // And the registration messages of the children are cleared
+    let mut cleared = HashSet::new();
+    for _ in 0..2 {
+        let message = mqtt.recv().await.unwrap();
+        assert!(message.payload_bytes().is_empty() && message.retain);
+        cleared.insert(message.topic.name);
+    }
+    assert_eq!(
+        cleared,
+        HashSet::from([
+            "te/device/child2//".to_string(),
+            "te/device/child1/service/app".to_string()
+        ])
+    );
+}
+
+#[tokio::test]
+async fn entities_are_restored_on_restart() {
+    let cfg_dir = TempTedgeDir::new();
+    let (mqtt, _http, _fs, mut timer, _ul, _dl) = spawn_c8y_mapper_actor(&cfg_dir, true).await;
+
+    timer.send(Timeout::new(())).await.unwrap(); // Complete sync phase so that alarm mapping starts
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/child1//"),
+        r#"{ "@type": "child-device", "@id": "child-1" }"#,
+    ))
+    .await
+    .unwrap();
+    assert_received_contains_str(&mut mqtt, [("c8y/s/us", "101,child-1")]).await;
+
+    // Restart the mapper, with no retained registration message
+    let (mqtt, mut http, _fs, mut timer, _ul, _dl) = spawn_c8y_mapper_actor(&cfg_dir, true).await;
+    timer.send(Timeout::new(())).await.unwrap();
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    // The child device is known by the mapper and can be deregistered
+    mqtt.send(MqttMessage::new(&Topic::new_unchecked("te/device/child1//"), "").with_retain())
+        .await
+        .unwrap();
+    match http.recv().await {
+        Some(C8YRestRequest::DeleteManagedObject(request)) => {
+            assert_eq!(request.device_id, "child-1")
+        }
+        unexpected => panic!("Unexpected request: {unexpected:?}"),
+    }
+}
+
+#[tokio::test]
+async fn failed_deletions_are_retried() {
+    let cfg_dir = TempTedgeDir::new();
+    let (mqtt, mut http, _fs, mut timer, _ul, _dl) = spawn_c8y_mapper_actor(&cfg_dir, true).await;
+
+    timer.send(Timeout::new(())).await.unwrap();
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/child1//"),
+        r#"{ "@type": "child-device", "@id": "child-1" }"#,
+    ))
+    .await
+    .unwrap();
+    assert_received_contains_str(&mut mqtt, [("c8y/s/us", "101,child-1")]).await;
+
+    mqtt.send(MqttMessage::new(&Topic::new_unchecked("te/device/child1//"), "").with_retain())
+        .await
+        .unwrap();
+
+    // The first attempt fails
+    match http.recv().await {
+        Some(C8YRestRequest::DeleteManagedObject(request)) => {
+            assert_eq!(request.device_id, "child-1");
+            http.send(Err(C8YRestError::CustomError("timeout".to_string())))
+                .await
+                .unwrap();
+        }
+        unexpected => panic!("Unexpected request: {unexpected:?}"),
+    }
+
+    // The mapper is not blocked while the deletion is pending
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/child2//"),
+        r#"{ "@type": "child-device", "@id": "child-2" }"#,
+    ))
+    .await
+    .unwrap();
+    assert_received_contains_str(&mut mqtt, [("c8y/s/us", "101,child-2")]).await;
+
+    // And the deletion is retried
+    match http.recv().await {
+        Some(C8YRestRequest::DeleteManagedObject(request)) => {
+            assert_eq!(request.device_id, "child-1");
+            http.send(Ok(C8YRestResponse::Unit(()))).await.unwrap();
+        }
+        unexpected => panic!("Unexpected request: {unexpected:?}"),
+    }
+}
+
+#[tokio::test]
+async fn stale_cloud_entities_are_deleted_on_reconciliation() {
+    let cfg_dir = TempTedgeDir::new();
+    let mut config = test_mapper_config(&cfg_dir);
+    config.reconciliation = Some(ReconciliationPolicy::Delete);
+
+    // A previous reconciliation pass already found a child device stale
+    cfg_dir
+        .dir(".tedge-mapper-c8y")
+        .file("c8y-reconciliation.json")
+        .with_raw_content(r#"{ "stale": { "stale-child": 1 } }"#);
+
+    let (mqtt, mut http, _fs, mut timer, _ul, _dl) =
+        spawn_c8y_mapper_actor_with_config(config).await;
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    for (topic, registration, expected) in [
+        (
+            "te/device/child1//",
+            r#"{ "@type": "child-device", "@id": "child-1" }"#,
+            "101,child-1",
+        ),
+        (
+            "te/device/child2//",
+            r#"{ "@type": "child-device", "@id": "child-2" }"#,
+            "101,child-2",
+        ),
+        (
+            "te/device/main/service/collectd",
+            r#"{ "@type": "service" }"#,
+            "102,test-device:device:main:service:collectd",
+        ),
+    ] {
+        mqtt.send(MqttMessage::new(&Topic::new_unchecked(topic), registration))
+            .await
+            .unwrap();
+        assert_received_contains_str(&mut mqtt, [("c8y/s/us", expected)]).await;
+    }
+
+    // A child device and a service are deregistered and deleted from the cloud
+    for (topic, external_id) in [
+        ("te/device/child2//", "child-2"),
+        (
+            "te/device/main/service/collectd",
+            "test-device:device:main:service:collectd",
+        ),
+    ] {
+        mqtt.send(MqttMessage::new(&Topic::new_unchecked(topic), "").with_retain())
+            .await
+            .unwrap();
+        match http.recv().await {
+            Some(C8YRestRequest::DeleteManagedObject(request)) => {
+                assert_eq!(request.device_id, external_id);
+                http.send(Ok(C8YRestResponse::Unit(()))).await.unwrap();
+            }
+            unexpected => panic!("Unexpected request: {unexpected:?}"),
+        }
+    }
+
+    // Once the sync phase is over, Cumulocity lists child devices and services that are no longer known locally,
+    // including the deregistered ones, as a deletion might have failed or not been processed yet
+    timer.send(Timeout::new(())).await.unwrap();
+    respond_with_cloud_children(
+        &mut http,
+        "test-device",
+        &[
+            ("child-1", ChildKind::Device),
+            ("child-2", ChildKind::Device),
+            ("stale-child", ChildKind::Device),
+            ("new-child", ChildKind::Device),
+            (
+                "test-device:device:main:service:collectd",
+                ChildKind::Addition,
+            ),
+        ],
+    )
+    .await;
+    respond_with_cloud_children(&mut http, "child-1", &[("stale-nested", ChildKind::Device)]).await;
+    for device_id in ["child-2", "stale-child", "new-child", "stale-nested"] {
+        respond_with_cloud_children(&mut http, device_id, &[]).await;
+    }
+
+    // Only the entities known to be deregistered or already found stale are deleted from the cloud
+    let mut deleted = HashSet::new();
+    for _ in 0..3 {
+        match http.recv().await {
+            Some(C8YRestRequest::DeleteManagedObject(request)) => {
+                deleted.insert(request.device_id);
+                http.send(Ok(C8YRestResponse::Unit(()))).await.unwrap();
+            }
+            unexpected => panic!("Unexpected request: {unexpected:?}"),
+        }
+    }
+    assert_eq!(
+        deleted,
+        HashSet::from([
+            "child-2".to_string(),
+            "stale-child".to_string(),
+            "test-device:device:main:service:collectd".to_string()
+        ])
+    );
+
+    // And a summary event is sent
+    assert_received_includes_json(
+        &mut mqtt,
+        [(
+            "c8y/event/events/create",
+            json!({
+                "type": "c8y_ChildDeviceReconciliation",
+                "text": "3 of 5 stale child device(s) and service(s) queued for deletion from Cumulocity",
+                "c8y_ChildDeviceReconciliation": {
+                    "policy": "delete",
+                    "staleChildDevices": ["child-2", "new-child", "stale-child", "stale-nested"],
+                    "staleServices": ["test-device:device:main:service:collectd"],
+                    "pending": ["new-child", "stale-nested"],
+                }
+            }),
+        )],
+    )
+    .await;
+
+    // The entities found stale for the first time will be deleted if still stale on the next pass
+    let state: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(
+            cfg_dir
+                .path()
+                .join(".tedge-mapper-c8y/c8y-reconciliation.json"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(state["stale"]["new-child"], json!(1));
+    assert_eq!(state["stale"]["stale-nested"], json!(1));
+}
+
+#[tokio::test]
+async fn stale_cloud_entities_are_marked_unavailable_on_reconciliation() {
+    let cfg_dir = TempTedgeDir::new();
+    let mut config = test_mapper_config(&cfg_dir);
+    config.reconciliation = Some(ReconciliationPolicy::Unavailable);
+    let (mqtt, mut http, _fs, mut timer, _ul, _dl) =
+        spawn_c8y_mapper_actor_with_config(config).await;
+    timer.send(Timeout::new(())).await.unwrap();
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    respond_with_cloud_children(
+        &mut http,
+        "test-device",
+        &[
+            ("stale-child", ChildKind::Device),
+            (
+                "test-device:device:main:service:stale-service",
+                ChildKind::Addition,
+            ),
+        ],
+    )
+    .await;
+    respond_with_cloud_children(
+        &mut http,
+        "stale-child",
+        &[("stale-nested", ChildKind::Device)],
+    )
+    .await;
+    respond_with_cloud_children(&mut http, "stale-nested", &[]).await;
+
+    // A required interval is set on the stale child devices, for Cumulocity to mark them unavailable,
+    // and the stale services are marked down
+    assert_received_contains_str(
+        &mut mqtt,
+        [
+            ("c8y/s/us/stale-child", "117,1"),
+            ("c8y/s/us/stale-child/stale-nested", "117,1"),
+            (
+                "c8y/s/us/test-device:device:main:service:stale-service",
+                "104,down",
+            ),
+        ],
+    )
+    .await;
+    assert_received_includes_json(
+        &mut mqtt,
+        [(
+            "c8y/event/events/create",
+            json!({
+                "type": "c8y_ChildDeviceReconciliation",
+                "c8y_ChildDeviceReconciliation": {
+                    "policy": "unavailable",
+                    "staleChildDevices": ["stale-child", "stale-nested"],
+                    "staleServices": ["test-device:device:main:service:stale-service"],
+                }
+            }),
+        )],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn reconciliation_is_deferred_till_the_end_of_the_sync_phase() {
+    let cfg_dir = TempTedgeDir::new();
+    let mut config = test_mapper_config(&cfg_dir);
+    config.reconciliation = Some(ReconciliationPolicy::Report);
+    let (mqtt, mut http, _fs, mut timer, _ul, _dl) =
+        spawn_c8y_mapper_actor_with_config(config).await;
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    // The local child devices are registered during the sync phase
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/child1//"),
+        r#"{ "@type": "child-device", "@id": "child-1" }"#,
+    ))
+    .await
+    .unwrap();
+    assert_received_contains_str(&mut mqtt, [("c8y/s/us", "101,child-1")]).await;
+
+    // The cloud entities are only requested and reconciled once the sync phase is over
+    timer.send(Timeout::new(())).await.unwrap();
+    respond_with_cloud_children(
+        &mut http,
+        "test-device",
+        &[
+            ("child-1", ChildKind::Device),
+            ("stale-child", ChildKind::Device),
+        ],
+    )
+    .await;
+    respond_with_cloud_children(&mut http, "child-1", &[]).await;
+    respond_with_cloud_children(&mut http, "stale-child", &[]).await;
+    assert_received_includes_json(
+        &mut mqtt,
+        [(
+            "c8y/event/events/create",
+            json!({
+                "type": "c8y_ChildDeviceReconciliation",
+                "text": "1 child device(s) and service(s) registered on Cumulocity but no longer known locally",
+                "c8y_ChildDeviceReconciliation": {
+                    "policy": "report",
+                    "staleChildDevices": ["stale-child"],
+                    "staleServices": [],
+                }
+            }),
+        )],
+    )
+    .await;
+}
+
+/// Respond to the request for the child devices and services of a device registered on Cumulocity
+async fn respond_with_cloud_children(
+    http: &mut SimpleMessageBox<C8YRestRequest, C8YRestResult>,
+    device_id: &str,
+    children: &[(&str, ChildKind)],
+) {
+    match http.recv().await {
+        Some(C8YRestRequest::GetChildManagedObjects(request)) => {
+            assert_eq!(request.device_id, device_id);
+            let children = children
+                .iter()
+                .map(|(external_id, kind)| ChildManagedObject {
+                    external_id: external_id.to_string(),
+                    kind: *kind,
+                })
+                .collect();
+            http.send(Ok(C8YRestResponse::ChildManagedObjects(children)))
+                .await
+                .unwrap();
+        }
+        unexpected => panic!("Unexpected request: {unexpected:?}"),
+    }
+}
+
 #[tokio::test]
 async fn custom_topic_scheme_registration_mapping() {
     let cfg_dir = TempTedgeDir::new();
@@ -204,6 +648,45 @@ async fn custom_topic_scheme_registration_mapping() {
     .await;
 }
 
+#[tokio::test]
+async fn brownfield_topics_are_translated_into_thin_edge_topics() {
+    let cfg_dir = TempTedgeDir::new();
+    let mut config = test_mapper_config(&cfg_dir);
+    config.topic_schemes = toml::from_str(
+        r#"
+[[scheme]]
+name = "factory"
+
+[[scheme.topic]]
+topic = "site/{line}/{machine}/{sensor}"
+entity = "site/{line}/{machine}/{sensor}"
+channel = "m/{sensor}"
+"#,
+    )
+    .unwrap();
+    let (mqtt, _http, _fs, mut timer, _ul, _dl) = spawn_c8y_mapper_actor_with_config(config).await;
+
+    timer.send(Timeout::new(())).await.unwrap();
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("site/line1/press7/temperature"),
+        r#"{"temperature": 21.5}"#,
+    ))
+    .await
+    .unwrap();
+
+    assert_received_contains_str(
+        &mut mqtt,
+        [(
+            "te/site/line1/press7/temperature/m/temperature",
+            r#"{"temperature": 21.5}"#,
+        )],
+    )
+    .await;
+}
+
 #[tokio::test]
 async fn service_registration_mapping() {
     let cfg_dir = TempTedgeDir::new();
@@ -347,6 +830,17 @@ async fn mapper_publishes_software_update_status_onto_c8y_topic() {
     // Expect `501` smartrest message on `c8y/s/us`.
     assert_received_contains_str(&mut mqtt, [("c8y/s/us", "501,c8y_SoftwareUpdate")]).await;
 
+    // The progress reported while executing is not forwarded as a new `501`
+    let progress = response.clone().with_progress(SoftwareUpdateProgress {
+        plugin_type: "apt".into(),
+        module: Some("nginx".into()),
+        percent: Some(50),
+        phase: Some("install".into()),
+    });
+    mqtt.send(progress.command_message(&mqtt_schema))
+        .await
+        .expect("Send failed");
+
     // Prepare and publish a software update response `successful`.
     let response = response.with_status(CommandStatus::Successful);
     mqtt.send(response.command_message(&mqtt_schema))
@@ -1181,6 +1675,176 @@ async fn c8y_mapper_child_service_alarm() {
     .await;
 }
 
+#[tokio::test]
+async fn c8y_mapper_aggregates_measurements_over_time_windows() {
+    let cfg_dir = TempTedgeDir::new();
+    cfg_dir.dir("operations").dir("c8y");
+    let mut config = test_mapper_config(&cfg_dir);
+    config.aggregation = Some(AggregationConfig {
+        window: Duration::from_secs(1),
+        functions: vec![
+            AggregationFunction::Min,
+            AggregationFunction::Max,
+            AggregationFunction::Avg,
+        ],
+        types: vec!["environment".to_string()],
+        settings: AggregationSettings::default(),
+    });
+    let (mqtt, _http, _fs, mut timer, _ul, _dl) = spawn_c8y_mapper_actor_with_config(config).await;
+    timer.send(Timeout::new(())).await.unwrap();
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    for temperature in [20, 24, 19] {
+        mqtt.send(MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///m/environment"),
+            json!({ "temperature": temperature }).to_string(),
+        ))
+        .await
+        .unwrap();
+    }
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/main///m/vibration"),
+        json!({ "x": 0.5 }).to_string(),
+    ))
+    .await
+    .unwrap();
+
+    // The measurements of the types not aggregated are forwarded as is
+    assert_received_includes_json(
+        &mut mqtt,
+        [(
+            "c8y/measurement/measurements/create",
+            json!({
+                "type": "vibration",
+                "x": { "x": { "value": 0.5 } }
+            }),
+        )],
+    )
+    .await;
+
+    // A single measurement is sent for the time window
+    assert_received_includes_json(
+        &mut mqtt,
+        [(
+            "c8y/measurement/measurements/create",
+            json!({
+                "type": "environment",
+                "temperature": {
+                    "min": { "value": 19.0 },
+                    "max": { "value": 24.0 },
+                    "avg": { "value": 21.0 }
+                }
+            }),
+        )],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn c8y_mapper_sends_measurements_in_bulk_per_entity() {
+    let cfg_dir = TempTedgeDir::new();
+    cfg_dir.dir("operations").dir("c8y");
+    let mut config = test_mapper_config(&cfg_dir);
+    config.bulk_measurements = Some(BulkMeasurementsConfig {
+        max_latency: Duration::from_millis(500),
+    });
+    let (mqtt, _http, _fs, mut timer, _ul, _dl) = spawn_c8y_mapper_actor_with_config(config).await;
+    timer.send(Timeout::new(())).await.unwrap();
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    for (topic, temperature) in [
+        ("te/device/main///m/environment", 20.5),
+        ("te/device/child1///m/environment", 30.5),
+        ("te/device/main///m/environment", 21.5),
+        ("te/device/child1///m/environment", 31.5),
+        ("te/device/main///m/environment", 22.5),
+    ] {
+        mqtt.send(MqttMessage::new(
+            &Topic::new_unchecked(topic),
+            json!({ "temperature": temperature, "time": "2024-01-01T00:00:00Z" }).to_string(),
+        ))
+        .await
+        .unwrap();
+    }
+
+    // The measurements are sent in bulk, entity by entity, once the batching window is closed
+    let mut measurement_messages = vec![];
+    while measurement_messages.len() < 2 {
+        let message = mqtt.recv().await.expect("MQTT channel closed");
+        if message.topic.name.starts_with("c8y/measurement") {
+            measurement_messages.push(message);
+        }
+    }
+    assert_message_includes_json(
+        &measurement_messages[0],
+        (
+            "c8y/measurement/measurements/createBulk",
+            json!({
+                "measurements": [
+                    {
+                        "type": "environment",
+                        "time": "2024-01-01T00:00:00Z",
+                        "temperature": { "temperature": { "value": 20.5 } }
+                    },
+                    {
+                        "type": "environment",
+                        "time": "2024-01-01T00:00:00Z",
+                        "temperature": { "temperature": { "value": 21.5 } }
+                    },
+                    {
+                        "type": "environment",
+                        "time": "2024-01-01T00:00:00Z",
+                        "temperature": { "temperature": { "value": 22.5 } }
+                    },
+                ]
+            }),
+        ),
+    );
+    assert_message_includes_json(
+        &measurement_messages[1],
+        (
+            "c8y/measurement/measurements/createBulk",
+            json!({
+                "measurements": [
+                    {
+                        "type": "environment",
+                        "time": "2024-01-01T00:00:00Z",
+                        "externalSource": { "externalId": "test-device:device:child1", "type": "c8y_Serial" },
+                        "temperature": { "temperature": { "value": 30.5 } }
+                    },
+                    {
+                        "type": "environment",
+                        "time": "2024-01-01T00:00:00Z",
+                        "externalSource": { "externalId": "test-device:device:child1", "type": "c8y_Serial" },
+                        "temperature": { "temperature": { "value": 31.5 } }
+                    },
+                ]
+            }),
+        ),
+    );
+
+    // A measurement received alone is sent as is
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/main///m/vibration"),
+        json!({ "x": 0.5 }).to_string(),
+    ))
+    .await
+    .unwrap();
+    assert_received_includes_json(
+        &mut mqtt,
+        [(
+            "c8y/measurement/measurements/create",
+            json!({
+                "type": "vibration",
+                "x": { "x": { "value": 0.5 } }
+            }),
+        )],
+    )
+    .await;
+}
+
 #[tokio::test]
 async fn c8y_mapper_main_service_alarm() {
     let cfg_dir = TempTedgeDir::new();
@@ -2147,6 +2811,422 @@ operation failed due to timeout: duration=1sEOF
     assert_command_exec_log_content(cfg_dir, expected_content);
 }
 
+#[tokio::test]
+async fn custom_operation_mapped_onto_thin_edge_command() {
+    let cfg_dir = TempTedgeDir::new();
+    cfg_dir
+        .dir("operations")
+        .dir("c8y")
+        .file("c8y_SetLed")
+        .with_raw_content(
+            r#"
+            [exec]
+            topic = "c8y/s/dc/led"
+            on_message = "dm101"
+
+            [exec.workflow]
+            operation = "set_led"
+            fields = ["color", "duration"]
+            "#,
+        );
+
+    let (mqtt, _http, _fs, _timer, _ul, _dl) = spawn_c8y_mapper_actor(&cfg_dir, true).await;
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    // Simulate a request using the custom template
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("c8y/s/dc/led"),
+        "dm101,test-device,red,10",
+    ))
+    .await
+    .expect("Send failed");
+
+    // The request is mapped onto a thin-edge command
+    let request = mqtt.recv().await.expect("A set_led command");
+    assert!(request
+        .topic
+        .name
+        .starts_with("te/device/main///cmd/set_led/c8y-mapper-"));
+    assert!(request.retain);
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(request.payload_bytes()).unwrap(),
+        json!({"status": "init", "color": "red", "duration": "10"})
+    );
+
+    // The command states are mapped back onto the c8y operation states
+    mqtt.send(
+        MqttMessage::new(
+            &request.topic,
+            r#"{"status": "executing", "color": "red", "duration": "10"}"#,
+        )
+        .with_retain(),
+    )
+    .await
+    .expect("Send failed");
+    assert_received_contains_str(&mut mqtt, [("c8y/s/us", "501,c8y_SetLed")]).await;
+
+    mqtt.send(
+        MqttMessage::new(
+            &request.topic,
+            r#"{"status": "successful", "color": "red", "duration": "10"}"#,
+        )
+        .with_retain(),
+    )
+    .await
+    .expect("Send failed");
+    assert_received_contains_str(
+        &mut mqtt,
+        [
+            (request.topic.name.as_str(), ""),
+            ("c8y/s/us", "503,c8y_SetLed"),
+        ],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn custom_operation_mapped_onto_child_device_command() {
+    let cfg_dir = TempTedgeDir::new();
+    cfg_dir
+        .dir("operations")
+        .dir("c8y")
+        .file("c8y_SetLed")
+        .with_raw_content(
+            r#"
+            [exec]
+            topic = "c8y/s/dc/led"
+            on_message = "dm101"
+
+            [exec.workflow]
+            operation = "set_led"
+            fields = ["color"]
+            "#,
+        );
+
+    let (mqtt, _http, _fs, _timer, _ul, _dl) = spawn_c8y_mapper_actor(&cfg_dir, true).await;
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/child1//"),
+        r#"{"@type":"child-device","@id":"child1"}"#,
+    ))
+    .await
+    .expect("Send failed");
+    assert_received_contains_str(&mut mqtt, [("c8y/s/us", "101,child1")]).await;
+
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("c8y/s/dc/led"),
+        "dm101,child1,green",
+    ))
+    .await
+    .expect("Send failed");
+
+    let request = mqtt.recv().await.expect("A set_led command");
+    assert!(request
+        .topic
+        .name
+        .starts_with("te/device/child1///cmd/set_led/c8y-mapper-"));
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(request.payload_bytes()).unwrap(),
+        json!({"status": "init", "color": "green"})
+    );
+
+    mqtt.send(
+        MqttMessage::new(
+            &request.topic,
+            r#"{"status": "failed", "reason": "No such color", "color": "green"}"#,
+        )
+        .with_retain(),
+    )
+    .await
+    .expect("Send failed");
+    assert_received_contains_str(
+        &mut mqtt,
+        [
+            (request.topic.name.as_str(), ""),
+            ("c8y/s/us/child1", "502,c8y_SetLed,No such color"),
+        ],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn custom_operation_declared_for_a_child_device_while_the_mapper_is_running() {
+    let cfg_dir = TempTedgeDir::new();
+    let (mqtt, _http, mut fs, _timer, _ul, _dl) = spawn_c8y_mapper_actor(&cfg_dir, true).await;
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/child1//"),
+        r#"{"@type":"child-device","@id":"child1"}"#,
+    ))
+    .await
+    .expect("Send failed");
+    assert_received_contains_str(&mut mqtt, [("c8y/s/us", "101,child1")]).await;
+
+    // The custom operation is only declared for the child device, once the mapper is running
+    let child_ops_dir = cfg_dir.dir("operations").dir("c8y").dir("child1");
+    child_ops_dir.file("c8y_Blink").with_raw_content(
+        r#"
+            [exec]
+            on_message = "dm102"
+
+            [exec.workflow]
+            operation = "blink"
+            fields = ["times"]
+            "#,
+    );
+    fs.send(FsWatchEvent::FileCreated(
+        child_ops_dir.to_path_buf().join("c8y_Blink"),
+    ))
+    .await
+    .expect("Send failed");
+    assert_received_contains_str(&mut mqtt, [("c8y/s/us/child1", "114,c8y_Blink")]).await;
+
+    mqtt.send(MqttMessage::new(
+        &C8yTopic::downstream_topic(),
+        "dm102,child1,3",
+    ))
+    .await
+    .expect("Send failed");
+
+    let request = mqtt.recv().await.expect("A blink command");
+    assert!(request
+        .topic
+        .name
+        .starts_with("te/device/child1///cmd/blink/c8y-mapper-"));
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(request.payload_bytes()).unwrap(),
+        json!({"status": "init", "times": "3"})
+    );
+
+    mqtt.send(
+        MqttMessage::new(&request.topic, r#"{"status": "successful", "times": "3"}"#).with_retain(),
+    )
+    .await
+    .expect("Send failed");
+    assert_received_contains_str(
+        &mut mqtt,
+        [
+            (request.topic.name.as_str(), ""),
+            ("c8y/s/us/child1", "503,c8y_Blink"),
+        ],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn json_operations_are_converted_into_commands_and_updated_as_json() {
+    let cfg_dir = TempTedgeDir::new();
+    cfg_dir.dir("operations").dir("c8y");
+    let mut config = test_mapper_config(&cfg_dir);
+    config.operations_protocol = OperationsProtocol::Json;
+    let (mqtt, _http, _fs, mut timer, _ul, _dl) = spawn_c8y_mapper_actor_with_config(config).await;
+    timer.send(Timeout::new(())).await.unwrap();
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    // SmartREST requests of the operations received as JSON are ignored
+    mqtt.send(MqttMessage::new(
+        &C8yTopic::downstream_topic(),
+        "510,test-device",
+    ))
+    .await
+    .expect("Send failed");
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("te/device/main///m/"),
+        r#"{"temperature": 21}"#,
+    ))
+    .await
+    .expect("Send failed");
+    let next_message = mqtt.recv().await.expect("A measurement");
+    assert_eq!(
+        next_message.topic.name,
+        "c8y/measurement/measurements/create"
+    );
+
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("c8y/devicecontrol/notifications"),
+        json!({
+            "id": "123",
+            "status": "PENDING",
+            "externalSource": {"externalId": "test-device", "type": "c8y_Serial"},
+            "c8y_Restart": {}
+        })
+        .to_string(),
+    ))
+    .await
+    .expect("Send failed");
+
+    let request = mqtt.recv().await.expect("A restart command");
+    assert!(request
+        .topic
+        .name
+        .starts_with("te/device/main///cmd/restart/c8y-mapper-"));
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(request.payload_bytes()).unwrap(),
+        json!({"status": "init"})
+    );
+
+    mqtt.send(MqttMessage::new(&request.topic, r#"{"status":"executing"}"#).with_retain())
+        .await
+        .expect("Send failed");
+    assert_received_includes_json(
+        &mut mqtt,
+        [(
+            "c8y/devicecontrol/operations/update/123",
+            json!({"status": "EXECUTING"}),
+        )],
+    )
+    .await;
+
+    mqtt.send(MqttMessage::new(&request.topic, r#"{"status":"successful"}"#).with_retain())
+        .await
+        .expect("Send failed");
+    assert_received_contains_str(&mut mqtt, [(request.topic.name.as_str(), "")]).await;
+    assert_received_includes_json(
+        &mut mqtt,
+        [(
+            "c8y/devicecontrol/operations/update/123",
+            json!({"status": "SUCCESSFUL"}),
+        )],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn json_operations_are_updated_per_command_whatever_the_completion_order() {
+    let cfg_dir = TempTedgeDir::new();
+    cfg_dir.dir("operations").dir("c8y");
+    let mut config = test_mapper_config(&cfg_dir);
+    config.operations_protocol = OperationsProtocol::Json;
+    let (mqtt, _http, _fs, mut timer, _ul, _dl) = spawn_c8y_mapper_actor_with_config(config).await;
+    timer.send(Timeout::new(())).await.unwrap();
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    let mut requests = vec![];
+    for operation_id in ["101", "102"] {
+        mqtt.send(MqttMessage::new(
+            &Topic::new_unchecked("c8y/devicecontrol/notifications"),
+            json!({
+                "id": operation_id,
+                "externalSource": {"externalId": "test-device", "type": "c8y_Serial"},
+                "c8y_Restart": {}
+            })
+            .to_string(),
+        ))
+        .await
+        .expect("Send failed");
+
+        // The command id carries the id of the c8y operation
+        let request = mqtt.recv().await.expect("A restart command");
+        assert!(request
+            .topic
+            .name
+            .ends_with(&format!("-c8y-op-{operation_id}")));
+        requests.push(request);
+    }
+
+    // The second command completes first
+    mqtt.send(MqttMessage::new(&requests[1].topic, r#"{"status":"successful"}"#).with_retain())
+        .await
+        .expect("Send failed");
+    assert_received_contains_str(&mut mqtt, [(requests[1].topic.name.as_str(), "")]).await;
+    assert_received_includes_json(
+        &mut mqtt,
+        [(
+            "c8y/devicecontrol/operations/update/102",
+            json!({"status": "SUCCESSFUL"}),
+        )],
+    )
+    .await;
+
+    mqtt.send(
+        MqttMessage::new(
+            &requests[0].topic,
+            r#"{"status":"failed","reason":"Not now"}"#,
+        )
+        .with_retain(),
+    )
+    .await
+    .expect("Send failed");
+    assert_received_contains_str(&mut mqtt, [(requests[0].topic.name.as_str(), "")]).await;
+    assert_received_includes_json(
+        &mut mqtt,
+        [(
+            "c8y/devicecontrol/operations/update/101",
+            json!({"status": "FAILED", "failureReason": "Restart Failed: Not now"}),
+        )],
+    )
+    .await;
+
+    // A command created before a restart of the mapper is still updated as JSON
+    mqtt.send(
+        MqttMessage::new(
+            &Topic::new_unchecked(
+                "te/device/main///cmd/restart/c8y-mapper-2024-01-01T00:00:00Z-c8y-op-103",
+            ),
+            r#"{"status":"executing"}"#,
+        )
+        .with_retain(),
+    )
+    .await
+    .expect("Send failed");
+    assert_received_includes_json(
+        &mut mqtt,
+        [(
+            "c8y/devicecontrol/operations/update/103",
+            json!({"status": "EXECUTING"}),
+        )],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn json_operations_that_cannot_be_converted_are_failed() {
+    let cfg_dir = TempTedgeDir::new();
+    cfg_dir.dir("operations").dir("c8y");
+    let mut config = test_mapper_config(&cfg_dir);
+    config.operations_protocol = OperationsProtocol::Json;
+    let (mqtt, _http, _fs, mut timer, _ul, _dl) = spawn_c8y_mapper_actor_with_config(config).await;
+    timer.send(Timeout::new(())).await.unwrap();
+    let mut mqtt = mqtt.with_timeout(TEST_TIMEOUT_MS);
+    skip_init_messages(&mut mqtt).await;
+
+    mqtt.send(MqttMessage::new(
+        &Topic::new_unchecked("c8y/devicecontrol/notifications"),
+        json!({
+            "id": "456",
+            "externalSource": {"externalId": "test-device", "type": "c8y_Serial"},
+            "c8y_SoftwareUpdate": [
+                {"name": "nodered", "version": "1.0.0::debian", "action": "upgrade"}
+            ]
+        })
+        .to_string(),
+    ))
+    .await
+    .expect("Send failed");
+
+    assert_received_includes_json(
+        &mut mqtt,
+        [
+            (
+                "c8y/devicecontrol/operations/update/456",
+                json!({"status": "EXECUTING"}),
+            ),
+            (
+                "c8y/devicecontrol/operations/update/456",
+                json!({"status": "FAILED"}),
+            ),
+        ],
+    )
+    .await;
+}
+
 /// This test aims to verify that when a telemetry message is emitted from an
 /// unknown device or service, the mapper will produce a registration message
 /// for this entity. The registration message shall be published only once, when
@@ -2544,6 +3624,11 @@ pub(crate) async fn spawn_c8y_mapper_actor(
         config_dir.dir("operations").dir("c8y");
     }
 
+    let config = test_mapper_config(config_dir);
+    spawn_c8y_mapper_actor_with_config(config).await
+}
+
+pub(crate) fn test_mapper_config(config_dir: &TempTedgeDir) -> C8yMapperConfig {
     let device_name = "test-device".into();
     let device_topic_id = EntityTopicId::default_main_device();
     let device_type = "test-device-type".into();
@@ -2563,7 +3648,7 @@ pub(crate) async fn spawn_c8y_mapper_actor(
     topics.add_all(crate::operations::config_update::topic_filter(&mqtt_schema));
     topics.add_all(C8yMapperConfig::default_external_topic_filter());
 
-    let config = C8yMapperConfig::new(
+    C8yMapperConfig::new(
         config_dir.to_path_buf(),
         config_dir.utf8_path_buf(),
         config_dir.utf8_path_buf().into(),
@@ -2581,12 +3666,25 @@ pub(crate) async fn spawn_c8y_mapper_actor(
         Protocol::Http,
         MqttSchema::default(),
         true,
-    );
+        PendingEntityStoreConfig::default(),
+        TopicSchemes::default(),
+    )
+}
 
+pub(crate) async fn spawn_c8y_mapper_actor_with_config(
+    config: C8yMapperConfig,
+) -> (
+    SimpleMessageBox<MqttMessage, MqttMessage>,
+    SimpleMessageBox<C8YRestRequest, C8YRestResult>,
+    SimpleMessageBox<NoMessage, FsWatchEvent>,
+    SimpleMessageBox<SyncStart, SyncComplete>,
+    SimpleMessageBox<IdUploadRequest, IdUploadResult>,
+    SimpleMessageBox<IdDownloadRequest, IdDownloadResult>,
+) {
     let mut mqtt_builder: SimpleMessageBoxBuilder<MqttMessage, MqttMessage> =
         SimpleMessageBoxBuilder::new("MQTT", 10);
-    let mut c8y_proxy_builder: SimpleMessageBoxBuilder<C8YRestRequest, C8YRestResult> =
-        SimpleMessageBoxBuilder::new("C8Y", 1);
+    let mut c8y_proxy_builder: ServerMessageBoxBuilder<C8YRestRequest, C8YRestResult> =
+        ServerMessageBoxBuilder::new("C8Y", 1);
     let mut fs_watcher_builder: SimpleMessageBoxBuilder<NoMessage, FsWatchEvent> =
         SimpleMessageBoxBuilder::new("FS", 5);
     let mut uploader_builder: SimpleMessageBoxBuilder<IdUploadRequest, IdUploadResult> =
@@ -2595,24 +3693,45 @@ pub(crate) async fn spawn_c8y_mapper_actor(
         SimpleMessageBoxBuilder::new("Downloader", 5);
     let mut timer_builder: SimpleMessageBoxBuilder<SyncStart, SyncComplete> =
         SimpleMessageBoxBuilder::new("Timer", 5);
+    let mut scheduler_builder = TimerActor::builder();
+    let mut bulk_batcher_builder = BatchingActorBuilder::default();
+    if let Some(bulk_measurements) = &config.bulk_measurements {
+        bulk_batcher_builder = bulk_batcher_builder
+            .with_batching_window(bulk_measurements.batching_window())
+            .with_maximum_message_delay(bulk_measurements.maximum_message_delay());
+    }
+
+    let mut deleter_builder = ManagedObjectDeleterBuilder::new(&mut c8y_proxy_builder);
+    let mut cloud_entity_fetcher_builder = CloudEntityFetcher::builder(&mut c8y_proxy_builder);
 
     let c8y_mapper_builder = C8yMapperBuilder::try_new(
         config,
         &mut mqtt_builder,
         &mut c8y_proxy_builder,
         &mut timer_builder,
+        &mut scheduler_builder,
         &mut uploader_builder,
         &mut downloader_builder,
         &mut fs_watcher_builder,
+        &mut bulk_batcher_builder,
+        &mut deleter_builder,
+        &mut cloud_entity_fetcher_builder,
     )
     .unwrap();
 
     let actor = c8y_mapper_builder.build();
     tokio::spawn(async move { actor.run().await });
+    let deleter = deleter_builder.build();
+    tokio::spawn(async move { deleter.run().await });
+    tokio::spawn(cloud_entity_fetcher_builder.run());
+    let scheduler = scheduler_builder.build();
+    tokio::spawn(async move { scheduler.run().await });
+    let bulk_batcher = bulk_batcher_builder.build();
+    tokio::spawn(async move { bulk_batcher.run().await });
 
     (
         mqtt_builder.build(),
-        c8y_proxy_builder.build(),
+        spawn_fake_c8y_http_proxy(c8y_proxy_builder),
         fs_watcher_builder.build(),
         timer_builder.build(),
         uploader_builder.build(),
@@ -2620,6 +3739,34 @@ pub(crate) async fn spawn_c8y_mapper_actor(
     )
 }
 
+/// Serve the requests of all the clients of the C8Y HTTP proxy one after the other,
+/// using a single message box to check the requests and to send the responses
+fn spawn_fake_c8y_http_proxy(
+    server_builder: ServerMessageBoxBuilder<C8YRestRequest, C8YRestResult>,
+) -> SimpleMessageBox<C8YRestRequest, C8YRestResult> {
+    let mut test_box_builder: SimpleMessageBoxBuilder<C8YRestRequest, C8YRestResult> =
+        SimpleMessageBoxBuilder::new("C8Y", 1);
+    let mut proxy_box: SimpleMessageBox<C8YRestResult, C8YRestRequest> =
+        SimpleMessageBoxBuilder::new("C8Y Clients", 1)
+            .with_connection(&mut test_box_builder)
+            .build();
+    let mut server_box: ServerMessageBox<C8YRestRequest, C8YRestResult> = server_builder.build();
+    tokio::spawn(async move {
+        while let Some((client_id, request)) = server_box.recv().await {
+            if proxy_box.send(request).await.is_err() {
+                break;
+            }
+            let Some(response) = proxy_box.recv().await else {
+                break;
+            };
+            if server_box.send((client_id, response)).await.is_err() {
+                break;
+            }
+        }
+    });
+    test_box_builder.build()
+}
+
 pub(crate) async fn skip_init_messages(mqtt: &mut impl MessageReceiver<MqttMessage>) {
     //Skip all the init messages by still doing loose assertions
     assert_received_contains_str(
@@ -2672,6 +3819,11 @@ pub(crate) fn spawn_dummy_c8y_http_proxy(
                         )))
                         .await;
                 }
+                Some(C8YRestRequest::DeleteManagedObject(_)) => {
+                    let _ = http
+                        .send(Ok(c8y_http_proxy::messages::C8YRestResponse::Unit(())))
+                        .await;
+                }
                 _ => {}
             }
         }
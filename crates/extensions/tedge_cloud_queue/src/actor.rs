@@ -0,0 +1,227 @@
+use crate::CloudQueueConfig;
+use crate::DiskQueue;
+use crate::QueueStats;
+use async_trait::async_trait;
+use log::error;
+use log::info;
+use std::convert::Infallible;
+use std::time::SystemTime;
+use tedge_actors::Actor;
+use tedge_actors::Builder;
+use tedge_actors::DynSender;
+use tedge_actors::MessageReceiver;
+use tedge_actors::MessageSink;
+use tedge_actors::NoConfig;
+use tedge_actors::RuntimeError;
+use tedge_actors::RuntimeRequest;
+use tedge_actors::RuntimeRequestSink;
+use tedge_actors::Sender;
+use tedge_actors::ServiceConsumer;
+use tedge_actors::ServiceProvider;
+use tedge_actors::SimpleMessageBox;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::TopicFilter;
+
+pub struct CloudQueueBuilder {
+    config: CloudQueueConfig,
+    queue: DiskQueue,
+    message_box: SimpleMessageBoxBuilder<MqttMessage, MqttMessage>,
+}
+
+impl CloudQueueBuilder {
+    /// Open the queue persisted on disk and connect it to the MQTT actor
+    pub fn try_new(
+        config: CloudQueueConfig,
+        mqtt: &mut impl ServiceProvider<MqttMessage, MqttMessage, TopicFilter>,
+    ) -> Result<Self, std::io::Error> {
+        let queue = DiskQueue::open(
+            &config.queue_dir,
+            config.max_size,
+            config.max_age,
+            SystemTime::now(),
+        )?;
+        let mut message_box = SimpleMessageBoxBuilder::new("CloudQueue", 16);
+        let health_topic = TopicFilter::new_unchecked(&config.bridge_health_topic.name);
+        let mqtt_sender = mqtt.connect_consumer(health_topic, message_box.get_response_sender());
+        message_box.set_request_sender(mqtt_sender);
+        Ok(CloudQueueBuilder {
+            config,
+            queue,
+            message_box,
+        })
+    }
+}
+
+impl MessageSink<MqttMessage, NoConfig> for CloudQueueBuilder {
+    fn get_config(&self) -> NoConfig {
+        NoConfig
+    }
+
+    fn get_sender(&self) -> DynSender<MqttMessage> {
+        self.message_box.get_sender()
+    }
+}
+
+impl RuntimeRequestSink for CloudQueueBuilder {
+    fn get_signal_sender(&self) -> DynSender<RuntimeRequest> {
+        self.message_box.get_signal_sender()
+    }
+}
+
+impl Builder<CloudQueueActor> for CloudQueueBuilder {
+    type Error = Infallible;
+
+    fn try_build(self) -> Result<CloudQueueActor, Self::Error> {
+        Ok(self.build())
+    }
+
+    fn build(self) -> CloudQueueActor {
+        CloudQueueActor {
+            config: self.config,
+            queue: self.queue,
+            bridge_up: false,
+            published_stats: None,
+            message_box: self.message_box.build(),
+        }
+    }
+}
+
+/// An MQTT service provider publishing the messages through a [CloudQueueActor], if any
+///
+/// The subscriptions are still served by the MQTT actor.
+pub struct MqttThroughQueue<'a, M> {
+    mqtt: &'a mut M,
+    queue: Option<DynSender<MqttMessage>>,
+}
+
+impl<'a, M> MqttThroughQueue<'a, M> {
+    pub fn new(mqtt: &'a mut M, queue: Option<&CloudQueueBuilder>) -> Self {
+        MqttThroughQueue {
+            mqtt,
+            queue: queue.map(|queue| queue.get_sender()),
+        }
+    }
+}
+
+impl<'a, M> ServiceProvider<MqttMessage, MqttMessage, TopicFilter> for MqttThroughQueue<'a, M>
+where
+    M: ServiceProvider<MqttMessage, MqttMessage, TopicFilter>,
+{
+    fn connect_consumer(
+        &mut self,
+        topics: TopicFilter,
+        response_sender: DynSender<MqttMessage>,
+    ) -> DynSender<MqttMessage> {
+        let mqtt_sender = self.mqtt.connect_consumer(topics, response_sender);
+        match &self.queue {
+            Some(queue) => queue.sender_clone(),
+            None => mqtt_sender,
+        }
+    }
+}
+
+/// Store and forward the messages published to the cloud
+///
+/// While the bridge to the cloud is up, all the messages are forwarded as is to the MQTT broker.
+/// While the bridge is down, the cloud-bound messages are queued on disk,
+/// to be published on reconnect by decreasing priority and in their original order.
+/// Messages that are not cloud-bound are always forwarded with no delay.
+pub struct CloudQueueActor {
+    config: CloudQueueConfig,
+    queue: DiskQueue,
+    bridge_up: bool,
+    published_stats: Option<QueueStats>,
+    message_box: SimpleMessageBox<MqttMessage, MqttMessage>,
+}
+
+#[async_trait]
+impl Actor for CloudQueueActor {
+    fn name(&self) -> &str {
+        "CloudQueue"
+    }
+
+    async fn run(mut self) -> Result<(), RuntimeError> {
+        let mut report_interval = tokio::time::interval(self.config.report_interval);
+        loop {
+            tokio::select! {
+                message = self.message_box.recv() => match message {
+                    Some(message) => self.process(message).await?,
+                    None => break,
+                },
+                _ = report_interval.tick() => self.report().await?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CloudQueueActor {
+    async fn process(&mut self, message: MqttMessage) -> Result<(), RuntimeError> {
+        if message.topic == self.config.bridge_health_topic {
+            let bridge_up = message.payload_bytes() == b"1";
+            return self.update_bridge_status(bridge_up).await;
+        }
+
+        if self.bridge_up || !self.config.is_cloud_bound(&message) {
+            return Ok(self.message_box.send(message).await?);
+        }
+
+        let priority = (self.config.priority)(&message);
+        if let Err(err) = self.queue.push(priority, &message, SystemTime::now()) {
+            error!(
+                "Failed to queue a message published on {}: {err}",
+                message.topic.name
+            );
+            self.message_box.send(message).await?;
+        }
+        Ok(())
+    }
+
+    async fn update_bridge_status(&mut self, bridge_up: bool) -> Result<(), RuntimeError> {
+        if bridge_up == self.bridge_up {
+            return Ok(());
+        }
+        self.bridge_up = bridge_up;
+
+        if bridge_up {
+            match self.queue.drain(SystemTime::now()) {
+                Ok(messages) => {
+                    if !messages.is_empty() {
+                        info!(
+                            "The bridge is up: publishing {} queued messages",
+                            messages.len()
+                        );
+                    }
+                    for message in messages {
+                        self.message_box.send(message).await?;
+                    }
+                }
+                Err(err) => error!("Failed to read the queued messages: {err}"),
+            }
+        } else {
+            info!("The bridge is down: queuing the messages published to the cloud");
+        }
+        self.publish_stats().await
+    }
+
+    /// Drop the expired messages and publish the queue statistics if they have changed
+    async fn report(&mut self) -> Result<(), RuntimeError> {
+        if let Err(err) = self.queue.expire(SystemTime::now()) {
+            error!("Failed to drop the expired messages from the queue: {err}");
+        }
+        if self.published_stats != Some(self.queue.stats()) {
+            self.publish_stats().await?;
+        }
+        Ok(())
+    }
+
+    async fn publish_stats(&mut self) -> Result<(), RuntimeError> {
+        let stats = self.queue.stats();
+        let payload = serde_json::to_string(&stats).unwrap_or_default();
+        let message = MqttMessage::new(&self.config.queue_topic, payload);
+        self.message_box.send(message).await?;
+        self.published_stats = Some(stats);
+        Ok(())
+    }
+}
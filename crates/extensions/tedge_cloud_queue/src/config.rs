@@ -0,0 +1,40 @@
+use crate::Priority;
+use std::path::PathBuf;
+use std::time::Duration;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::Topic;
+
+/// Configuration of the queue interposed between a mapper and the MQTT broker
+pub struct CloudQueueConfig {
+    /// Prefix of the topics bridged to the cloud, e.g. `c8y/`
+    ///
+    /// Only messages published on these topics are queued while the bridge is down.
+    pub cloud_topic_prefix: String,
+
+    /// Topic where the bridge publishes `1` when connected to the cloud and `0` when disconnected
+    pub bridge_health_topic: Topic,
+
+    /// Topic where the queue publishes its statistics, as a thin-edge measurement
+    pub queue_topic: Topic,
+
+    /// Directory where the queued messages are persisted
+    pub queue_dir: PathBuf,
+
+    /// Max size in bytes of the queued messages
+    pub max_size: usize,
+
+    /// Max age of the queued messages
+    pub max_age: Duration,
+
+    /// Interval at which the queue statistics are published while messages are queued
+    pub report_interval: Duration,
+
+    /// Classify the cloud-bound messages by priority
+    pub priority: fn(&MqttMessage) -> Priority,
+}
+
+impl CloudQueueConfig {
+    pub(crate) fn is_cloud_bound(&self, message: &MqttMessage) -> bool {
+        message.topic.name.starts_with(&self.cloud_topic_prefix)
+    }
+}
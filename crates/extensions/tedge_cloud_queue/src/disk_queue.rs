@@ -0,0 +1,432 @@
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::QoS;
+use tedge_mqtt_ext::Topic;
+
+/// When the queue is full, messages are dropped till the queue is back to this ratio of its max size,
+/// so the queue files are not rewritten on each new message.
+const DROP_RATIO: f64 = 0.9;
+
+/// Priority class of a cloud-bound message
+///
+/// When the bridge is back, the queued messages are sent by decreasing priority,
+/// and when the queue is full, the messages with the lowest priority are dropped first.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Priority {
+    /// Alarms and operation status updates
+    High,
+
+    /// Events, inventory updates and any message not classified otherwise
+    Normal,
+
+    /// Measurements
+    Low,
+}
+
+impl Priority {
+    /// All the priority classes, from the highest to the lowest
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    fn index(self) -> usize {
+        match self {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Low => 2,
+        }
+    }
+
+    fn file_name(self) -> &'static str {
+        match self {
+            Priority::High => "high.jsonl",
+            Priority::Normal => "normal.jsonl",
+            Priority::Low => "low.jsonl",
+        }
+    }
+}
+
+/// A queue of MQTT messages persisted on disk
+///
+/// The messages are stored in one JSON-lines file per priority class,
+/// the files being appended as messages are pushed and truncated when the queue is drained.
+/// A copy of the messages is kept in memory, so the files are only read on start.
+///
+/// The queue is bounded by size and age:
+/// - when the total size of the stored messages exceeds the max size,
+///   the oldest messages of the lowest priority class are dropped,
+/// - messages older than the max age are dropped.
+pub struct DiskQueue {
+    classes: [QueueClass; 3],
+    max_size: usize,
+    max_age: Duration,
+    dropped: u64,
+}
+
+/// Statistics on a queue
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct QueueStats {
+    /// Number of messages in the queue
+    pub queued: usize,
+
+    /// Size in bytes of the queued messages, as stored on disk
+    pub size: usize,
+
+    /// Number of messages dropped so far because the queue was full or the messages too old
+    pub dropped: u64,
+}
+
+struct QueueClass {
+    path: PathBuf,
+    messages: VecDeque<StoredMessage>,
+    size: usize,
+}
+
+struct StoredMessage {
+    record: Record,
+    line_len: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    time: u64,
+    topic: String,
+    payload: String,
+    qos: u8,
+    retain: bool,
+}
+
+impl DiskQueue {
+    /// Open the queue persisted in the given directory, creating the directory if missing
+    pub fn open(
+        dir: impl AsRef<Path>,
+        max_size: usize,
+        max_age: Duration,
+        now: SystemTime,
+    ) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        let mut queue = DiskQueue {
+            classes: Priority::ALL.map(|priority| QueueClass::load(dir.join(priority.file_name()))),
+            max_size,
+            max_age,
+            dropped: 0,
+        };
+        queue.expire(now)?;
+        Ok(queue)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.classes.iter().all(|class| class.messages.is_empty())
+    }
+
+    pub fn len(&self) -> usize {
+        self.classes.iter().map(|class| class.messages.len()).sum()
+    }
+
+    pub fn size(&self) -> usize {
+        self.classes.iter().map(|class| class.size).sum()
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            queued: self.len(),
+            size: self.size(),
+            dropped: self.dropped,
+        }
+    }
+
+    /// Append a message to the queue, dropping messages of lower priority if the queue is full
+    pub fn push(
+        &mut self,
+        priority: Priority,
+        message: &MqttMessage,
+        now: SystemTime,
+    ) -> io::Result<()> {
+        let record = Record {
+            time: unix_time(now),
+            topic: message.topic.name.clone(),
+            payload: String::from_utf8_lossy(message.payload_bytes()).to_string(),
+            qos: message.qos as u8,
+            retain: message.retain,
+        };
+        self.classes[priority.index()].append(record)?;
+
+        if self.size() > self.max_size {
+            self.drop_lowest_priority_messages()?;
+        }
+        Ok(())
+    }
+
+    /// Remove all the messages from the queue,
+    /// returning those not expired, by decreasing priority and in their original order
+    pub fn drain(&mut self, now: SystemTime) -> io::Result<Vec<MqttMessage>> {
+        let deadline = self.deadline(now);
+        let mut messages = Vec::with_capacity(self.len());
+        for class in self.classes.iter_mut() {
+            for stored in class.messages.drain(..) {
+                if stored.record.time < deadline {
+                    self.dropped += 1;
+                } else {
+                    messages.push(stored.record.into());
+                }
+            }
+            class.size = 0;
+            class.rewrite()?;
+        }
+        Ok(messages)
+    }
+
+    /// Drop the messages older than the max age
+    pub fn expire(&mut self, now: SystemTime) -> io::Result<()> {
+        let deadline = self.deadline(now);
+        for class in self.classes.iter_mut() {
+            let mut expired = false;
+            while let Some(oldest) = class.messages.front() {
+                if oldest.record.time >= deadline {
+                    break;
+                }
+                class.pop_front();
+                self.dropped += 1;
+                expired = true;
+            }
+            if expired {
+                class.rewrite()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn drop_lowest_priority_messages(&mut self) -> io::Result<()> {
+        let target_size = (self.max_size as f64 * DROP_RATIO) as usize;
+        let mut size = self.size();
+        for class in self.classes.iter_mut().rev() {
+            let mut dropped = false;
+            while size > target_size {
+                let Some(line_len) = class.pop_front() else {
+                    break;
+                };
+                size -= line_len;
+                self.dropped += 1;
+                dropped = true;
+            }
+            if dropped {
+                class.rewrite()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn deadline(&self, now: SystemTime) -> u64 {
+        unix_time(now).saturating_sub(self.max_age.as_secs())
+    }
+}
+
+impl QueueClass {
+    /// Load the messages persisted by a previous run, ignoring any line that cannot be parsed
+    fn load(path: PathBuf) -> Self {
+        let mut class = QueueClass {
+            path,
+            messages: VecDeque::new(),
+            size: 0,
+        };
+        if let Ok(file) = File::open(&class.path) {
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                if let Ok(record) = serde_json::from_str(&line) {
+                    class.push_back(record, line.len() + 1);
+                }
+            }
+        }
+        class
+    }
+
+    fn append(&mut self, record: Record) -> io::Result<()> {
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        self.push_back(record, line.len());
+        Ok(())
+    }
+
+    fn rewrite(&self) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(&self.path)?);
+        for stored in self.messages.iter() {
+            serde_json::to_writer(&mut file, &stored.record)?;
+            file.write_all(b"\n")?;
+        }
+        file.flush()
+    }
+
+    fn push_back(&mut self, record: Record, line_len: usize) {
+        self.size += line_len;
+        self.messages.push_back(StoredMessage { record, line_len });
+    }
+
+    fn pop_front(&mut self) -> Option<usize> {
+        let stored = self.messages.pop_front()?;
+        self.size -= stored.line_len;
+        Some(stored.line_len)
+    }
+}
+
+impl From<Record> for MqttMessage {
+    fn from(record: Record) -> Self {
+        let qos = match record.qos {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            _ => QoS::ExactlyOnce,
+        };
+        let message =
+            MqttMessage::new(&Topic::new_unchecked(&record.topic), record.payload).with_qos(qos);
+        if record.retain {
+            message.with_retain()
+        } else {
+            message
+        }
+    }
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tedge_test_utils::fs::TempTedgeDir;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn messages_are_drained_by_priority_and_in_order() {
+        let ttd = TempTedgeDir::new();
+        let now = SystemTime::now();
+        let mut queue = DiskQueue::open(ttd.path(), 10_000, HOUR, now).unwrap();
+
+        queue.push(Priority::Low, &message("m", "1"), now).unwrap();
+        queue.push(Priority::High, &message("a", "2"), now).unwrap();
+        queue
+            .push(Priority::Normal, &message("e", "3"), now)
+            .unwrap();
+        queue.push(Priority::Low, &message("m", "4"), now).unwrap();
+        queue.push(Priority::High, &message("a", "5"), now).unwrap();
+        assert_eq!(queue.len(), 5);
+
+        let drained = queue.drain(now).unwrap();
+        assert_eq!(payloads(&drained), vec!["2", "5", "3", "1", "4"]);
+        assert!(queue.is_empty());
+        assert_eq!(queue.size(), 0);
+    }
+
+    #[test]
+    fn queued_messages_are_persisted() {
+        let ttd = TempTedgeDir::new();
+        let now = SystemTime::now();
+        let mut queue = DiskQueue::open(ttd.path(), 10_000, HOUR, now).unwrap();
+        queue
+            .push(
+                Priority::Low,
+                &message("m", "1").with_qos(QoS::AtMostOnce),
+                now,
+            )
+            .unwrap();
+        queue
+            .push(Priority::High, &message("a", "2").with_retain(), now)
+            .unwrap();
+        let size = queue.size();
+        drop(queue);
+
+        let mut queue = DiskQueue::open(ttd.path(), 10_000, HOUR, now).unwrap();
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.size(), size);
+        let drained = queue.drain(now).unwrap();
+        assert_eq!(
+            drained,
+            vec![
+                message("a", "2").with_retain(),
+                message("m", "1").with_qos(QoS::AtMostOnce)
+            ]
+        );
+
+        let queue = DiskQueue::open(ttd.path(), 10_000, HOUR, now).unwrap();
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn the_lowest_priority_messages_are_dropped_when_the_queue_is_full() {
+        let ttd = TempTedgeDir::new();
+        let now = SystemTime::now();
+        let mut queue = DiskQueue::open(ttd.path(), 1_000, HOUR, now).unwrap();
+
+        for i in 0..10 {
+            queue
+                .push(Priority::High, &message("a", &format!("a{i}")), now)
+                .unwrap();
+            queue
+                .push(Priority::Low, &message("m", &format!("m{i}")), now)
+                .unwrap();
+        }
+        assert!(queue.size() <= 1_000);
+        assert!(queue.stats().dropped > 0);
+
+        let drained = queue.drain(now).unwrap();
+        let payloads = payloads(&drained);
+        let alarms: Vec<_> = payloads.iter().filter(|p| p.starts_with('a')).collect();
+        let measurements: Vec<_> = payloads.iter().filter(|p| p.starts_with('m')).collect();
+        assert_eq!(alarms.len(), 10, "no alarm is dropped");
+        assert!(measurements.len() < 10);
+        // the oldest measurements have been dropped
+        assert_eq!(measurements.last().unwrap().as_str(), "m9");
+    }
+
+    #[test]
+    fn messages_older_than_the_max_age_are_dropped() {
+        let ttd = TempTedgeDir::new();
+        let now = SystemTime::now();
+        let mut queue = DiskQueue::open(ttd.path(), 10_000, HOUR, now).unwrap();
+
+        queue
+            .push(Priority::Normal, &message("e", "old"), now - 2 * HOUR)
+            .unwrap();
+        queue
+            .push(Priority::Normal, &message("e", "recent"), now - HOUR / 2)
+            .unwrap();
+
+        queue.expire(now).unwrap();
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.stats().dropped, 1);
+
+        let drained = queue.drain(now + HOUR).unwrap();
+        assert!(drained.is_empty());
+        assert_eq!(queue.stats().dropped, 2);
+    }
+
+    fn message(topic: &str, payload: &str) -> MqttMessage {
+        MqttMessage::new(&Topic::new_unchecked(topic), payload)
+    }
+
+    fn payloads(messages: &[MqttMessage]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|message| message.payload_str().unwrap().to_string())
+            .collect()
+    }
+}
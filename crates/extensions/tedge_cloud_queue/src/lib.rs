@@ -0,0 +1,14 @@
+//! A store-and-forward queue for the messages published to the cloud
+//!
+//! The queue is interposed between a cloud mapper and the MQTT broker.
+//! While the bridge to the cloud is down, the cloud-bound messages are persisted on disk,
+//! and on reconnect these messages are published by decreasing priority and in their original order.
+mod actor;
+mod config;
+mod disk_queue;
+#[cfg(test)]
+mod tests;
+
+pub use actor::*;
+pub use config::*;
+pub use disk_queue::*;
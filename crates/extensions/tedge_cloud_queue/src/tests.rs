@@ -0,0 +1,133 @@
+use crate::CloudQueueBuilder;
+use crate::CloudQueueConfig;
+use crate::Priority;
+use std::time::Duration;
+use tedge_actors::test_helpers::MessageReceiverExt;
+use tedge_actors::test_helpers::TimedMessageBox;
+use tedge_actors::Actor;
+use tedge_actors::Builder;
+use tedge_actors::DynSender;
+use tedge_actors::MessageReceiver;
+use tedge_actors::MessageSink;
+use tedge_actors::Sender;
+use tedge_actors::SimpleMessageBox;
+use tedge_actors::SimpleMessageBoxBuilder;
+use tedge_mqtt_ext::MqttMessage;
+use tedge_mqtt_ext::Topic;
+use tedge_test_utils::fs::TempTedgeDir;
+
+const TEST_TIMEOUT: Duration = Duration::from_secs(1);
+const HEALTH_TOPIC: &str = "te/device/main/service/mosquitto-c8y-bridge/status/health";
+const QUEUE_TOPIC: &str = "te/device/main/service/tedge-mapper-c8y/m/queue";
+
+#[tokio::test]
+async fn cloud_bound_messages_are_queued_while_the_bridge_is_down() {
+    let ttd = TempTedgeDir::new();
+    let (mut mapper, mut mqtt) = spawn_queue(&ttd).await;
+    mqtt.assert_received([stats(0)]).await;
+
+    // The bridge is up: messages are forwarded
+    mqtt.send(health("1")).await.unwrap();
+    mqtt.skip(1).await;
+    mapper.send(message("c8y/measurement", "m1")).await.unwrap();
+    mqtt.assert_received([message("c8y/measurement", "m1")])
+        .await;
+
+    // The bridge is down: only the local messages are forwarded
+    mqtt.send(health("0")).await.unwrap();
+    mqtt.skip(1).await;
+    mapper.send(message("c8y/measurement", "m2")).await.unwrap();
+    mapper.send(message("c8y/alarm", "a1")).await.unwrap();
+    mapper.send(message("te/errors", "local")).await.unwrap();
+    mapper.send(message("c8y/event", "e1")).await.unwrap();
+    mapper.send(message("c8y/alarm", "a2")).await.unwrap();
+    mqtt.assert_received([message("te/errors", "local")]).await;
+
+    // The bridge is back: the queued messages are sent by priority and in order
+    mqtt.send(health("1")).await.unwrap();
+    mqtt.assert_received([
+        message("c8y/alarm", "a1"),
+        message("c8y/alarm", "a2"),
+        message("c8y/event", "e1"),
+        message("c8y/measurement", "m2"),
+    ])
+    .await;
+    mqtt.assert_received([stats(0)]).await;
+
+    mapper.send(message("c8y/measurement", "m3")).await.unwrap();
+    mqtt.assert_received([message("c8y/measurement", "m3")])
+        .await;
+}
+
+#[tokio::test]
+async fn queued_messages_are_sent_after_a_restart() {
+    let ttd = TempTedgeDir::new();
+    {
+        let (mut mapper, mut mqtt) = spawn_queue(&ttd).await;
+        mqtt.skip(1).await;
+        mapper.send(message("c8y/event", "e1")).await.unwrap();
+        mapper.send(message("c8y/event", "e2")).await.unwrap();
+
+        // The messages are queued as the bridge status is unknown
+        mapper.send(message("te/errors", "local")).await.unwrap();
+        mqtt.assert_received([message("te/errors", "local")]).await;
+    }
+
+    let (_mapper, mut mqtt) = spawn_queue(&ttd).await;
+    let stats = mqtt.recv().await.unwrap();
+    assert_eq!(stats.topic.name, QUEUE_TOPIC);
+    assert!(stats.payload_str().unwrap().starts_with(r#"{"queued":2,"#));
+    mqtt.send(health("1")).await.unwrap();
+    mqtt.assert_received([message("c8y/event", "e1"), message("c8y/event", "e2")])
+        .await;
+}
+
+async fn spawn_queue(
+    ttd: &TempTedgeDir,
+) -> (
+    DynSender<MqttMessage>,
+    TimedMessageBox<SimpleMessageBox<MqttMessage, MqttMessage>>,
+) {
+    let config = CloudQueueConfig {
+        cloud_topic_prefix: "c8y/".to_string(),
+        bridge_health_topic: Topic::new_unchecked(HEALTH_TOPIC),
+        queue_topic: Topic::new_unchecked(QUEUE_TOPIC),
+        queue_dir: ttd.path().join("queue"),
+        max_size: 10_000,
+        max_age: Duration::from_secs(3600),
+        report_interval: Duration::from_secs(3600),
+        priority: test_priority,
+    };
+    let mut mqtt = SimpleMessageBoxBuilder::new("MQTT", 16);
+    let queue = CloudQueueBuilder::try_new(config, &mut mqtt).unwrap();
+    let mapper = queue.get_sender();
+    let actor = queue.build();
+    tokio::spawn(async move { actor.run().await });
+
+    (mapper, mqtt.build().with_timeout(TEST_TIMEOUT))
+}
+
+fn test_priority(message: &MqttMessage) -> Priority {
+    if message.topic.name.contains("alarm") {
+        Priority::High
+    } else if message.topic.name.contains("measurement") {
+        Priority::Low
+    } else {
+        Priority::Normal
+    }
+}
+
+fn message(topic: &str, payload: &str) -> MqttMessage {
+    MqttMessage::new(&Topic::new_unchecked(topic), payload)
+}
+
+fn health(status: &str) -> MqttMessage {
+    message(HEALTH_TOPIC, status)
+}
+
+fn stats(queued: usize) -> MqttMessage {
+    message(
+        QUEUE_TOPIC,
+        &format!(r#"{{"queued":{queued},"size":0,"dropped":0}}"#),
+    )
+}
@@ -0,0 +1,228 @@
+use crate::config::health_message;
+use crate::config::MqttBridgeConfig;
+use async_trait::async_trait;
+use log::info;
+use log::warn;
+use mqtt_channel::Connection;
+use mqtt_channel::Message;
+use mqtt_channel::MqttError;
+use mqtt_channel::SinkExt;
+use mqtt_channel::StreamExt;
+use mqtt_channel::UnboundedSender;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use tedge_actors::Actor;
+use tedge_actors::Builder;
+use tedge_actors::DynSender;
+use tedge_actors::MessageReceiver;
+use tedge_actors::NoMessage;
+use tedge_actors::RuntimeError;
+use tedge_actors::RuntimeRequest;
+use tedge_actors::RuntimeRequestSink;
+use tedge_actors::SimpleMessageBox;
+use tedge_actors::SimpleMessageBoxBuilder;
+
+/// Number of forwarded messages that are remembered to detect their echo
+const ECHO_WINDOW: usize = 100;
+
+pub struct MqttBridgeActorBuilder {
+    config: MqttBridgeConfig,
+    message_box: SimpleMessageBoxBuilder<NoMessage, NoMessage>,
+}
+
+impl MqttBridgeActorBuilder {
+    pub fn new(config: MqttBridgeConfig) -> Self {
+        MqttBridgeActorBuilder {
+            config,
+            message_box: SimpleMessageBoxBuilder::new("MqttBridge", 1),
+        }
+    }
+}
+
+impl RuntimeRequestSink for MqttBridgeActorBuilder {
+    fn get_signal_sender(&self) -> DynSender<RuntimeRequest> {
+        self.message_box.get_signal_sender()
+    }
+}
+
+impl Builder<MqttBridgeActor> for MqttBridgeActorBuilder {
+    type Error = Infallible;
+
+    fn try_build(self) -> Result<MqttBridgeActor, Self::Error> {
+        Ok(self.build())
+    }
+
+    fn build(self) -> MqttBridgeActor {
+        MqttBridgeActor {
+            config: self.config,
+            message_box: self.message_box.build(),
+            local_echoes: EchoFilter::default(),
+            remote_echoes: EchoFilter::default(),
+        }
+    }
+}
+
+/// Forward messages between the local MQTT broker and a cloud MQTT endpoint
+///
+/// Both connections use persistent sessions (unless configured otherwise),
+/// so QoS 1 and 2 messages are not lost while one side is disconnected:
+/// the local broker retains the messages published while the bridge is down,
+/// and the messages published to the cloud are re-sent on reconnect till acknowledged.
+pub struct MqttBridgeActor {
+    config: MqttBridgeConfig,
+    message_box: SimpleMessageBox<NoMessage, NoMessage>,
+
+    /// Messages forwarded to the local broker that might be received back from the local broker
+    local_echoes: EchoFilter,
+
+    /// Messages forwarded to the cloud that might be received back from the cloud
+    remote_echoes: EchoFilter,
+}
+
+#[async_trait]
+impl Actor for MqttBridgeActor {
+    fn name(&self) -> &str {
+        "MqttBridge"
+    }
+
+    async fn run(mut self) -> Result<(), RuntimeError> {
+        let mut local = tokio::select! {
+            connection = Connection::new(&self.config.local) => connection.map_err(Box::new)?,
+            Some(RuntimeRequest::Shutdown) = self.message_box.recv_signal() => return Ok(()),
+        };
+        let mut remote = tokio::select! {
+            connection = Connection::new(&self.config.remote) => connection.map_err(Box::new)?,
+            Some(RuntimeRequest::Shutdown) = self.message_box.recv_signal() => {
+                local.close().await;
+                return Ok(())
+            }
+        };
+        info!(
+            "Bridge connected to {}:{}",
+            self.config.remote.broker.host, self.config.remote.broker.port
+        );
+        self.publish_health(&mut local.published, true).await?;
+        let mut remote_up = true;
+
+        loop {
+            tokio::select! {
+                Some(message) = local.received.next() => {
+                    self.forward_to_remote(message, &mut remote.published).await?;
+                }
+                Some(message) = remote.received.next() => {
+                    self.forward_to_local(message, &mut local.published).await?;
+                }
+                Some(err) = local.errors.next() => {
+                    warn!("Bridge connection error with the local broker: {err}");
+                }
+                Some(err) = remote.errors.next() => {
+                    warn!("Bridge connection error with the cloud: {err}");
+                    if remote_up && matches!(err, MqttError::ConnectionError(_)) {
+                        remote_up = false;
+                        self.publish_health(&mut local.published, false).await?;
+                    }
+                }
+                Some(()) = remote.reconnections.next() => {
+                    info!(
+                        "Bridge reconnected to {}:{}",
+                        self.config.remote.broker.host, self.config.remote.broker.port
+                    );
+                    if !remote_up {
+                        remote_up = true;
+                        self.publish_health(&mut local.published, true).await?;
+                    }
+                }
+                Some(RuntimeRequest::Shutdown) = self.message_box.recv_signal() => break,
+                else => break,
+            }
+        }
+
+        self.publish_health(&mut local.published, false).await?;
+        remote.close().await;
+        local.close().await;
+        Ok(())
+    }
+}
+
+impl MqttBridgeActor {
+    async fn forward_to_remote(
+        &mut self,
+        message: Message,
+        remote: &mut UnboundedSender<Message>,
+    ) -> Result<(), RuntimeError> {
+        if self.local_echoes.is_echo(&message) {
+            return Ok(());
+        }
+        if let Some(forwarded) = self.config.rules.to_remote(&message) {
+            if self.config.remote.subscriptions.accept(&forwarded) {
+                self.remote_echoes.expect(&forwarded);
+            }
+            remote.send(forwarded).await.map_err(Box::new)?;
+        }
+        Ok(())
+    }
+
+    async fn forward_to_local(
+        &mut self,
+        message: Message,
+        local: &mut UnboundedSender<Message>,
+    ) -> Result<(), RuntimeError> {
+        if self.remote_echoes.is_echo(&message) {
+            return Ok(());
+        }
+        if let Some(forwarded) = self.config.rules.to_local(&message) {
+            if self.config.local.subscriptions.accept(&forwarded) {
+                self.local_echoes.expect(&forwarded);
+            }
+            local.send(forwarded).await.map_err(Box::new)?;
+        }
+        Ok(())
+    }
+
+    async fn publish_health(
+        &mut self,
+        local: &mut UnboundedSender<Message>,
+        up: bool,
+    ) -> Result<(), RuntimeError> {
+        if let Some(topic) = &self.config.health_topic {
+            local
+                .send(health_message(topic, up))
+                .await
+                .map_err(Box::new)?;
+        }
+        Ok(())
+    }
+}
+
+/// Detect messages sent back by a broker to the bridge that has just published them
+///
+/// A message forwarded along a bidirectional rule is received back by the bridge,
+/// because MQTT 3.1.1 provides no way to not receive its own messages.
+/// Such an echo must not be forwarded, otherwise the message would loop forever.
+#[derive(Default)]
+struct EchoFilter {
+    expected: VecDeque<(String, Vec<u8>)>,
+}
+
+impl EchoFilter {
+    fn expect(&mut self, message: &Message) {
+        if self.expected.len() >= ECHO_WINDOW {
+            self.expected.pop_front();
+        }
+        self.expected
+            .push_back((message.topic.name.clone(), message.payload_bytes().to_vec()));
+    }
+
+    fn is_echo(&mut self, message: &Message) -> bool {
+        let position = self.expected.iter().position(|(topic, payload)| {
+            topic == &message.topic.name && payload.as_slice() == message.payload_bytes()
+        });
+        match position {
+            Some(index) => {
+                self.expected.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use crate::rules::BridgeRule;
+use crate::rules::BridgeRules;
+use certificate::key_uri::KeyUri;
+use certificate::CertificateError;
+use mqtt_channel::Message;
+use mqtt_channel::QoS;
+use mqtt_channel::Topic;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+const DEFAULT_REMOTE_PORT: u16 = 8883;
+
+#[derive(thiserror::Error, Debug)]
+pub enum BridgeConfigError {
+    #[error("Invalid bridge rule {rule:?}: {reason}")]
+    InvalidRule { rule: String, reason: String },
+
+    #[error("Invalid bridge setting {setting:?}: {reason}")]
+    InvalidSetting { setting: String, reason: String },
+
+    #[error("Missing bridge setting: {setting}")]
+    MissingSetting { setting: &'static str },
+
+    #[error("Fail to read the bridge configuration {path:?}: {error}")]
+    Read {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+
+    #[error(transparent)]
+    Certificate(#[from] CertificateError),
+}
+
+/// The configuration of a bridge between the local MQTT broker and a cloud MQTT endpoint
+#[derive(Debug, Clone)]
+pub struct MqttBridgeConfig {
+    /// Connection to the local broker, subscribed to the local topics to be forwarded
+    pub local: mqtt_channel::Config,
+
+    /// Connection to the cloud, subscribed to the remote topics to be forwarded
+    pub remote: mqtt_channel::Config,
+
+    pub rules: BridgeRules,
+
+    /// Local topic where the bridge publishes `1` when connected to the cloud, and `0` when not
+    pub health_topic: Option<Topic>,
+}
+
+/// The settings of a bridge, as written by `tedge connect` in a mosquitto bridge configuration file
+///
+/// Only the settings that make sense for the built-in bridge are retained, the others being ignored.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BridgeConfigFile {
+    pub connection: String,
+    pub address: String,
+    pub remote_username: Option<String>,
+    pub remote_clientid: String,
+    pub local_clientid: String,
+    pub cafile: Option<PathBuf>,
+    pub capath: Option<PathBuf>,
+    pub certfile: PathBuf,
+    pub keyfile: String,
+    pub clean_session: bool,
+    pub local_clean_session: Option<bool>,
+    pub notifications: bool,
+    pub notification_topic: Option<String>,
+    pub rules: Vec<BridgeRule>,
+}
+
+impl BridgeConfigFile {
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, BridgeConfigError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|error| BridgeConfigError::Read {
+            path: path.to_owned(),
+            error,
+        })?;
+        content.parse()
+    }
+
+    /// Build the MQTT configurations of the bridge
+    ///
+    /// The local connection is derived from the given config,
+    /// which is expected to define the local broker and its authentication.
+    pub fn bridge_config(
+        &self,
+        local: mqtt_channel::Config,
+    ) -> Result<MqttBridgeConfig, BridgeConfigError> {
+        let rules = BridgeRules::from(self.rules.clone());
+        let health_topic = match (&self.notifications, &self.notification_topic) {
+            (true, Some(topic)) => {
+                Some(
+                    Topic::new(topic).map_err(|err| BridgeConfigError::InvalidSetting {
+                        setting: "notification_topic".to_string(),
+                        reason: err.to_string(),
+                    })?,
+                )
+            }
+            _ => None,
+        };
+
+        let mut local = local
+            .with_session_name(&self.local_clientid)
+            .with_clean_session(self.local_clean_session.unwrap_or(self.clean_session))
+            .with_subscriptions(rules.local_subscriptions());
+        if let Some(topic) = &health_topic {
+            local = local.with_last_will_message(health_message(topic, false));
+        }
+
+        let (host, port) = match self.address.rsplit_once(':') {
+            None => (self.address.as_str(), DEFAULT_REMOTE_PORT),
+            Some((host, port)) => {
+                let port = port
+                    .parse()
+                    .map_err(|_| BridgeConfigError::InvalidSetting {
+                        setting: "address".to_string(),
+                        reason: format!("invalid port {port:?}"),
+                    })?;
+                (host, port)
+            }
+        };
+        let mut remote = mqtt_channel::Config::default()
+            .with_host(host)
+            .with_port(port)
+            .with_session_name(&self.remote_clientid)
+            .with_clean_session(self.clean_session)
+            .with_subscriptions(rules.remote_subscriptions());
+        if let Some(username) = &self.remote_username {
+            remote = remote.with_username(username);
+        }
+        if let Some(cafile) = &self.cafile {
+            remote.with_cafile(cafile)?;
+        }
+        if let Some(capath) = &self.capath {
+            remote.with_cadir(capath)?;
+        }
+        let key_uri: KeyUri = self.keyfile.parse()?;
+        remote.with_client_identity(&self.certfile, &key_uri)?;
+
+        Ok(MqttBridgeConfig {
+            local,
+            remote,
+            rules,
+            health_topic,
+        })
+    }
+}
+
+pub(crate) fn health_message(topic: &Topic, up: bool) -> Message {
+    let payload = if up { "1" } else { "0" };
+    Message::new(topic, payload)
+        .with_qos(QoS::AtLeastOnce)
+        .with_retain()
+}
+
+impl FromStr for BridgeConfigFile {
+    type Err = BridgeConfigError;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        let mut connection = None;
+        let mut address = None;
+        let mut remote_username = None;
+        let mut remote_clientid = None;
+        let mut local_clientid = None;
+        let mut cafile = None;
+        let mut capath = None;
+        let mut certfile = None;
+        let mut keyfile = None;
+        let mut clean_session = None;
+        let mut local_clean_session = None;
+        let mut notifications = None;
+        let mut notification_topic = None;
+        let mut rules = Vec::new();
+
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (setting, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim().to_string();
+            match setting {
+                "connection" => connection = Some(value),
+                "address" => address = Some(value),
+                "remote_username" => remote_username = Some(value),
+                "remote_clientid" => remote_clientid = Some(value),
+                "local_clientid" => local_clientid = Some(value),
+                "bridge_cafile" => cafile = Some(PathBuf::from(value)),
+                "bridge_capath" => capath = Some(PathBuf::from(value)),
+                "bridge_certfile" => certfile = Some(PathBuf::from(value)),
+                "bridge_keyfile" => keyfile = Some(value),
+                "cleansession" => clean_session = Some(parse_bool(setting, &value)?),
+                "local_cleansession" => local_clean_session = Some(parse_bool(setting, &value)?),
+                "notifications" => notifications = Some(parse_bool(setting, &value)?),
+                "notification_topic" => notification_topic = Some(value),
+                "topic" => rules.push(value.parse()?),
+                _ => {}
+            }
+        }
+
+        let connection = connection.ok_or_else(|| missing("connection"))?;
+        Ok(BridgeConfigFile {
+            address: address.ok_or_else(|| missing("address"))?,
+            remote_username,
+            remote_clientid: remote_clientid.unwrap_or_else(|| connection.clone()),
+            local_clientid: local_clientid.unwrap_or_else(|| format!("local.{connection}")),
+            connection,
+            cafile,
+            capath,
+            certfile: certfile.ok_or_else(|| missing("bridge_certfile"))?,
+            keyfile: keyfile.ok_or_else(|| missing("bridge_keyfile"))?,
+            clean_session: clean_session.unwrap_or(false),
+            local_clean_session,
+            notifications: notifications.unwrap_or(true),
+            notification_topic,
+            rules,
+        })
+    }
+}
+
+fn parse_bool(setting: &str, value: &str) -> Result<bool, BridgeConfigError> {
+    value
+        .parse()
+        .map_err(|_| BridgeConfigError::InvalidSetting {
+            setting: setting.to_string(),
+            reason: format!("expecting true or false, found {value:?}"),
+        })
+}
+
+fn missing(setting: &'static str) -> BridgeConfigError {
+    BridgeConfigError::MissingSetting { setting }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Direction;
+
+    #[test]
+    fn parse_a_bridge_configuration_file() {
+        let config: BridgeConfigFile = r#"
+### Bridge
+connection edge_to_c8y
+address example.cumulocity.com:8883
+bridge_cafile /etc/ssl/certs/ca-certificates.crt
+remote_clientid my-device
+local_clientid Cumulocity
+bridge_certfile /etc/tedge/device-certs/tedge-certificate.pem
+bridge_keyfile pkcs11:token=tedge;object=device-key?module-path=/usr/lib/softhsm/libsofthsm2.so
+try_private false
+start_type automatic
+cleansession true
+local_cleansession false
+notifications true
+notifications_local_only true
+notification_topic te/device/main/service/mosquitto-c8y-bridge/status/health
+bridge_attempt_unsubscribe false
+
+### Topics
+topic s/us out 2 c8y/ ""
+topic s/ds in 2 c8y/ ""
+"#
+        .parse()
+        .unwrap();
+
+        assert_eq!(config.address, "example.cumulocity.com:8883");
+        assert_eq!(config.remote_clientid, "my-device");
+        assert_eq!(config.remote_username, None);
+        assert_eq!(
+            config.cafile,
+            Some(PathBuf::from("/etc/ssl/certs/ca-certificates.crt"))
+        );
+        assert!(config.keyfile.starts_with("pkcs11:"));
+        assert!(config.clean_session);
+        assert_eq!(config.local_clean_session, Some(false));
+        assert_eq!(
+            config.notification_topic.as_deref(),
+            Some("te/device/main/service/mosquitto-c8y-bridge/status/health")
+        );
+        assert_eq!(config.rules.len(), 2);
+        assert_eq!(config.rules[1].direction, Direction::In);
+    }
+
+    #[test]
+    fn reject_incomplete_or_invalid_configurations() {
+        assert!(matches!(
+            "connection edge_to_c8y".parse::<BridgeConfigFile>(),
+            Err(BridgeConfigError::MissingSetting { setting: "address" })
+        ));
+        assert!(matches!(
+            "connection c\naddress a:8883\ncleansession maybe".parse::<BridgeConfigFile>(),
+            Err(BridgeConfigError::InvalidSetting { .. })
+        ));
+        assert!(matches!(
+            "connection c\naddress a:8883\ntopic a/# out 4".parse::<BridgeConfigFile>(),
+            Err(BridgeConfigError::InvalidRule { .. })
+        ));
+    }
+}
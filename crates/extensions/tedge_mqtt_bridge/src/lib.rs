@@ -0,0 +1,14 @@
+//! A bridge between the local MQTT broker and a cloud MQTT endpoint
+//!
+//! This is an alternative to the mosquitto bridge for devices where the local broker
+//! cannot be configured to bridge messages to the cloud.
+//! The bridge forwards messages along the rules of a mosquitto bridge configuration,
+//! as written by `tedge connect`.
+mod actor;
+mod config;
+pub mod rules;
+#[cfg(test)]
+mod tests;
+
+pub use actor::*;
+pub use config::*;
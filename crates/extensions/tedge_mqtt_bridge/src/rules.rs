@@ -0,0 +1,324 @@
+//! Bridge rules, using the syntax of the `topic` entries of a mosquitto bridge configuration:
+//!
+//! ```text
+//! topic pattern [[[ out | in | both ] qos-level] local-prefix remote-prefix]
+//! ```
+//!
+//! A message published locally on `{local-prefix}{pattern}` is forwarded to the cloud
+//! on `{remote-prefix}{pattern}` when the direction is `out` or `both`,
+//! and conversely a message received from the cloud is forwarded locally
+//! when the direction is `in` or `both`.
+//! An empty prefix or pattern is given as `""`.
+use crate::BridgeConfigError;
+use mqtt_channel::Message;
+use mqtt_channel::QoS;
+use mqtt_channel::Topic;
+use mqtt_channel::TopicFilter;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Direction {
+    In,
+    Out,
+    Both,
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "in" => Ok(Direction::In),
+            "out" => Ok(Direction::Out),
+            "both" => Ok(Direction::Both),
+            _ => Err(format!("unknown direction {s:?}")),
+        }
+    }
+}
+
+/// A rule forwarding messages between the local broker and the cloud
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BridgeRule {
+    pub pattern: String,
+    pub direction: Direction,
+    pub qos: QoS,
+    pub local_prefix: String,
+    pub remote_prefix: String,
+    local_filter: TopicFilter,
+    remote_filter: TopicFilter,
+}
+
+impl BridgeRule {
+    pub fn new(
+        pattern: &str,
+        direction: Direction,
+        qos: QoS,
+        local_prefix: &str,
+        remote_prefix: &str,
+    ) -> Result<Self, BridgeConfigError> {
+        let rule = format!("{pattern:?} {direction:?} {qos:?} {local_prefix:?} {remote_prefix:?}");
+        let invalid_rule = |reason: &str| BridgeConfigError::InvalidRule {
+            rule: rule.clone(),
+            reason: reason.to_string(),
+        };
+
+        if local_prefix.contains(['+', '#']) || remote_prefix.contains(['+', '#']) {
+            return Err(invalid_rule("wildcards are not allowed in prefixes"));
+        }
+        let local_filter = TopicFilter::new(&format!("{local_prefix}{pattern}"))
+            .map_err(|err| invalid_rule(&err.to_string()))?;
+        let remote_filter = TopicFilter::new(&format!("{remote_prefix}{pattern}"))
+            .map_err(|err| invalid_rule(&err.to_string()))?;
+
+        Ok(BridgeRule {
+            pattern: pattern.to_string(),
+            direction,
+            qos,
+            local_prefix: local_prefix.to_string(),
+            remote_prefix: remote_prefix.to_string(),
+            local_filter,
+            remote_filter,
+        })
+    }
+
+    /// The local topics this rule forwards to the cloud, if any
+    pub fn local_subscription(&self) -> Option<&TopicFilter> {
+        matches!(self.direction, Direction::Out | Direction::Both).then_some(&self.local_filter)
+    }
+
+    /// The cloud topics this rule forwards to the local broker, if any
+    pub fn remote_subscription(&self) -> Option<&TopicFilter> {
+        matches!(self.direction, Direction::In | Direction::Both).then_some(&self.remote_filter)
+    }
+
+    /// The cloud topic a local message has to be forwarded to, if any
+    pub fn to_remote(&self, local_topic: &Topic) -> Option<Topic> {
+        self.local_subscription()
+            .filter(|filter| filter.accept_topic(local_topic))
+            .and_then(|_| remap(local_topic, &self.local_prefix, &self.remote_prefix))
+    }
+
+    /// The local topic a cloud message has to be forwarded to, if any
+    pub fn to_local(&self, remote_topic: &Topic) -> Option<Topic> {
+        self.remote_subscription()
+            .filter(|filter| filter.accept_topic(remote_topic))
+            .and_then(|_| remap(remote_topic, &self.remote_prefix, &self.local_prefix))
+    }
+}
+
+fn remap(topic: &Topic, from_prefix: &str, to_prefix: &str) -> Option<Topic> {
+    let suffix = topic.name.strip_prefix(from_prefix)?;
+    Some(Topic::new_unchecked(&format!("{to_prefix}{suffix}")))
+}
+
+impl FromStr for BridgeRule {
+    type Err = BridgeConfigError;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let invalid_rule = |reason: &str| BridgeConfigError::InvalidRule {
+            rule: rule.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let fields: Vec<&str> = rule
+            .split_whitespace()
+            .map(|field| if field == "\"\"" { "" } else { field })
+            .collect();
+        let (pattern, direction, qos, local_prefix, remote_prefix) = match fields.as_slice() {
+            [pattern] => (*pattern, "out", "0", "", ""),
+            [pattern, direction] => (*pattern, *direction, "0", "", ""),
+            [pattern, direction, qos] => (*pattern, *direction, *qos, "", ""),
+            [pattern, direction, qos, local_prefix, remote_prefix] => {
+                (*pattern, *direction, *qos, *local_prefix, *remote_prefix)
+            }
+            _ => return Err(invalid_rule(
+                "expecting: pattern [[[ out | in | both ] qos-level] local-prefix remote-prefix]",
+            )),
+        };
+
+        let direction = direction
+            .parse()
+            .map_err(|err: String| invalid_rule(&err))?;
+        let qos = match qos {
+            "0" => QoS::AtMostOnce,
+            "1" => QoS::AtLeastOnce,
+            "2" => QoS::ExactlyOnce,
+            _ => return Err(invalid_rule(&format!("invalid QoS level {qos:?}"))),
+        };
+
+        BridgeRule::new(pattern, direction, qos, local_prefix, remote_prefix).map_err(|err| {
+            match err {
+                BridgeConfigError::InvalidRule { reason, .. } => invalid_rule(&reason),
+                err => err,
+            }
+        })
+    }
+}
+
+/// The set of rules of a bridge
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct BridgeRules {
+    rules: Vec<BridgeRule>,
+}
+
+impl From<Vec<BridgeRule>> for BridgeRules {
+    fn from(rules: Vec<BridgeRule>) -> Self {
+        BridgeRules { rules }
+    }
+}
+
+impl BridgeRules {
+    /// The local topics to be forwarded to the cloud
+    pub fn local_subscriptions(&self) -> TopicFilter {
+        let mut topics = TopicFilter::empty();
+        for filter in self.rules.iter().filter_map(BridgeRule::local_subscription) {
+            topics.add_all(filter.clone());
+        }
+        topics
+    }
+
+    /// The cloud topics to be forwarded to the local broker
+    pub fn remote_subscriptions(&self) -> TopicFilter {
+        let mut topics = TopicFilter::empty();
+        for filter in self
+            .rules
+            .iter()
+            .filter_map(BridgeRule::remote_subscription)
+        {
+            topics.add_all(filter.clone());
+        }
+        topics
+    }
+
+    /// Translate a local message into a cloud message, using the first matching rule
+    pub fn to_remote(&self, message: &Message) -> Option<Message> {
+        self.rules.iter().find_map(|rule| {
+            let topic = rule.to_remote(&message.topic)?;
+            Some(forwarded(message, topic, rule.qos))
+        })
+    }
+
+    /// Translate a cloud message into a local message, using the first matching rule
+    pub fn to_local(&self, message: &Message) -> Option<Message> {
+        self.rules.iter().find_map(|rule| {
+            let topic = rule.to_local(&message.topic)?;
+            Some(forwarded(message, topic, rule.qos))
+        })
+    }
+}
+
+/// A message is forwarded with the lowest QoS of the received message and the rule
+fn forwarded(message: &Message, topic: Topic, qos: QoS) -> Message {
+    Message {
+        topic,
+        payload: message.payload.clone(),
+        qos: if message.qos < qos { message.qos } else { qos },
+        retain: message.retain,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mosquitto_topic_rules() {
+        let rule: BridgeRule = "s/us out 2 c8y/ \"\"".parse().unwrap();
+        assert_eq!(rule.pattern, "s/us");
+        assert_eq!(rule.direction, Direction::Out);
+        assert_eq!(rule.qos, QoS::ExactlyOnce);
+        assert_eq!(rule.local_prefix, "c8y/");
+        assert_eq!(rule.remote_prefix, "");
+
+        let rule: BridgeRule = "shadow/# both 1 aws/ $aws/things/my-device/"
+            .parse()
+            .unwrap();
+        assert_eq!(rule.direction, Direction::Both);
+        assert_eq!(rule.remote_prefix, "$aws/things/my-device/");
+
+        let rule: BridgeRule = "a/b".parse().unwrap();
+        assert_eq!(rule.direction, Direction::Out);
+        assert_eq!(rule.qos, QoS::AtMostOnce);
+        assert_eq!(rule.local_prefix, "");
+    }
+
+    #[test]
+    fn reject_invalid_topic_rules() {
+        for rule in [
+            "",
+            "a/# sideways 1 x/ y/",
+            "a/# out 3 x/ y/",
+            "a/# out 1 x/",
+            "a/#/b out 1 x/ y/",
+            "\"\" out 1 x/+ y/",
+        ] {
+            assert!(
+                matches!(
+                    rule.parse::<BridgeRule>(),
+                    Err(BridgeConfigError::InvalidRule { .. })
+                ),
+                "{rule:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn remap_topics_along_the_rule_direction() {
+        let rule: BridgeRule = "measurement/# out 1 az/ devices/my-device/"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            rule.to_remote(&Topic::new_unchecked("az/measurement/temp")),
+            Some(Topic::new_unchecked("devices/my-device/measurement/temp"))
+        );
+        assert_eq!(rule.to_remote(&Topic::new_unchecked("az/other/temp")), None);
+        assert_eq!(
+            rule.to_local(&Topic::new_unchecked("devices/my-device/measurement/temp")),
+            None
+        );
+    }
+
+    #[test]
+    fn an_empty_pattern_maps_a_single_topic() {
+        let rule: BridgeRule = "\"\" out 1 aws/test-connection thinedge/my-device/test-connection"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            rule.to_remote(&Topic::new_unchecked("aws/test-connection")),
+            Some(Topic::new_unchecked("thinedge/my-device/test-connection"))
+        );
+        assert_eq!(
+            rule.to_remote(&Topic::new_unchecked("aws/test-connection/x")),
+            None
+        );
+    }
+
+    #[test]
+    fn messages_are_forwarded_with_the_lowest_qos() {
+        let rules = BridgeRules::from(vec![
+            "s/us out 0 c8y/ \"\"".parse().unwrap(),
+            "s/ds in 2 c8y/ \"\"".parse().unwrap(),
+        ]);
+
+        let message = Message::new(&Topic::new_unchecked("c8y/s/us"), "200,temp,T,25")
+            .with_qos(QoS::AtLeastOnce);
+        let forwarded = rules.to_remote(&message).unwrap();
+        assert_eq!(forwarded.topic.name, "s/us");
+        assert_eq!(forwarded.qos, QoS::AtMostOnce);
+
+        let message =
+            Message::new(&Topic::new_unchecked("s/ds"), "510,my-device").with_qos(QoS::AtLeastOnce);
+        let forwarded = rules.to_local(&message).unwrap();
+        assert_eq!(forwarded.topic.name, "c8y/s/ds");
+        assert_eq!(forwarded.qos, QoS::AtLeastOnce);
+
+        assert_eq!(
+            rules.local_subscriptions().patterns,
+            vec!["c8y/s/us".to_string()]
+        );
+        assert_eq!(
+            rules.remote_subscriptions().patterns,
+            vec!["s/ds".to_string()]
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use crate::rules::BridgeRules;
+use crate::MqttBridgeActorBuilder;
+use crate::MqttBridgeConfig;
+use mqtt_channel::StreamExt;
+use mqtt_channel::Topic;
+use mqtt_tests::with_timeout::WithTimeout;
+use std::time::Duration;
+use tedge_actors::Actor;
+use tedge_actors::Builder;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+async fn forward_messages_along_the_bridge_rules() {
+    let broker = mqtt_tests::test_mqtt_broker();
+    let health_topic = "test-1/bridge/health";
+    let mut health = broker.messages_published_on(health_topic).await;
+    spawn_bridge(
+        "test-1",
+        health_topic,
+        &[
+            "a/# out 1 test-1/local/ test-1/remote/",
+            "b/# in 1 test-1/local/ test-1/remote/",
+        ],
+    );
+    mqtt_tests::assert_received(&mut health, TIMEOUT, vec!["1"]).await;
+
+    // Using a single broker, the local and remote topics are distinguished by their prefixes
+    let mut cloud = broker.messages_published_on("test-1/remote/a/#").await;
+    broker
+        .publish("test-1/local/a/x", "to the cloud")
+        .await
+        .unwrap();
+    mqtt_tests::assert_received(&mut cloud, TIMEOUT, vec!["to the cloud"]).await;
+
+    let mut device = broker.messages_published_on("test-1/local/b/#").await;
+    broker
+        .publish("test-1/remote/b/y", "from the cloud")
+        .await
+        .unwrap();
+    mqtt_tests::assert_received(&mut device, TIMEOUT, vec!["from the cloud"]).await;
+
+    // Messages not matching a rule direction are not forwarded
+    broker
+        .publish("test-1/remote/a/z", "not forwarded")
+        .await
+        .unwrap();
+    broker
+        .publish("test-1/local/b/z", "not forwarded")
+        .await
+        .unwrap();
+    broker
+        .publish("test-1/local/a/x", "forwarded")
+        .await
+        .unwrap();
+    mqtt_tests::assert_received(&mut cloud, TIMEOUT, vec!["not forwarded", "forwarded"]).await;
+    assert!(cloud
+        .next()
+        .with_timeout(Duration::from_millis(500))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn messages_forwarded_in_both_directions_do_not_loop() {
+    let broker = mqtt_tests::test_mqtt_broker();
+    let health_topic = "test-2/bridge/health";
+    let mut health = broker.messages_published_on(health_topic).await;
+    spawn_bridge(
+        "test-2",
+        health_topic,
+        &["shadow/# both 1 test-2/local/ test-2/remote/"],
+    );
+    mqtt_tests::assert_received(&mut health, TIMEOUT, vec!["1"]).await;
+
+    let mut cloud = broker.messages_published_on("test-2/remote/shadow/#").await;
+    let mut device = broker.messages_published_on("test-2/local/shadow/#").await;
+
+    broker
+        .publish("test-2/local/shadow/update", "ping")
+        .await
+        .unwrap();
+    mqtt_tests::assert_received(&mut cloud, TIMEOUT, vec!["ping"]).await;
+    mqtt_tests::assert_received(&mut device, TIMEOUT, vec!["ping"]).await;
+
+    broker
+        .publish("test-2/remote/shadow/delta", "pong")
+        .await
+        .unwrap();
+    mqtt_tests::assert_received(&mut device, TIMEOUT, vec!["pong"]).await;
+    mqtt_tests::assert_received(&mut cloud, TIMEOUT, vec!["pong"]).await;
+
+    // No message is bounced back
+    assert!(cloud
+        .next()
+        .with_timeout(Duration::from_millis(500))
+        .await
+        .is_err());
+    assert!(device
+        .next()
+        .with_timeout(Duration::from_millis(500))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn health_status_follows_the_cloud_connection() {
+    let broker = mqtt_tests::test_mqtt_broker();
+    let health_topic = "test-3/bridge/health";
+    let mut health = broker.messages_published_on(health_topic).await;
+    let proxy = TcpProxy::spawn(broker.port).await;
+    spawn_bridge_via(
+        "test-3",
+        health_topic,
+        &["a/# out 1 test-3/local/ test-3/remote/"],
+        proxy.port,
+    );
+    mqtt_tests::assert_received(&mut health, TIMEOUT, vec!["1"]).await;
+
+    // The bridge is down while the cloud is unreachable, and up again on reconnect
+    proxy.cut_connections();
+    mqtt_tests::assert_received(&mut health, TIMEOUT, vec!["0", "1"]).await;
+}
+
+fn spawn_bridge(name: &str, health_topic: &str, rules: &[&str]) {
+    let broker = mqtt_tests::test_mqtt_broker();
+    spawn_bridge_via(name, health_topic, rules, broker.port)
+}
+
+/// Spawn a bridge, connecting the cloud side on the given port
+fn spawn_bridge_via(name: &str, health_topic: &str, rules: &[&str], remote_port: u16) {
+    let broker = mqtt_tests::test_mqtt_broker();
+    let rules = BridgeRules::from(
+        rules
+            .iter()
+            .map(|rule| rule.parse().unwrap())
+            .collect::<Vec<_>>(),
+    );
+    let local = mqtt_channel::Config::default()
+        .with_port(broker.port)
+        .with_session_name(format!("{name}-local"))
+        .with_subscriptions(rules.local_subscriptions());
+    let remote = mqtt_channel::Config::default()
+        .with_port(remote_port)
+        .with_session_name(format!("{name}-remote"))
+        .with_subscriptions(rules.remote_subscriptions());
+    let config = MqttBridgeConfig {
+        local,
+        remote,
+        rules,
+        health_topic: Some(Topic::new_unchecked(health_topic)),
+    };
+
+    let actor = MqttBridgeActorBuilder::new(config).build();
+    tokio::spawn(async move { actor.run().await });
+}
+
+/// A TCP proxy to the test broker, which connections can be cut to simulate a network outage
+struct TcpProxy {
+    port: u16,
+    cut: broadcast::Sender<()>,
+}
+
+impl TcpProxy {
+    async fn spawn(target_port: u16) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (cut, _) = broadcast::channel(1);
+        let cut_sender = cut.clone();
+        tokio::spawn(async move {
+            while let Ok((mut inbound, _)) = listener.accept().await {
+                let mut cut = cut_sender.subscribe();
+                tokio::spawn(async move {
+                    let Ok(mut outbound) = TcpStream::connect(("127.0.0.1", target_port)).await
+                    else {
+                        return;
+                    };
+                    tokio::select! {
+                        _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound) => {}
+                        _ = cut.recv() => {}
+                    }
+                });
+            }
+        });
+        TcpProxy { port, cut }
+    }
+
+    fn cut_connections(&self) {
+        let _ = self.cut.send(());
+    }
+}
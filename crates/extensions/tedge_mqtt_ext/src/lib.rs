@@ -39,6 +39,7 @@ pub struct MqttActorBuilder {
     publish_sender: mpsc::Sender<MqttMessage>,
     pub subscriber_addresses: Vec<(TopicFilter, LoggingSender<MqttMessage>)>,
     signal_sender: mpsc::Sender<RuntimeRequest>,
+    topic_prefixes: TopicPrefixes,
 }
 
 impl MqttActorBuilder {
@@ -53,17 +54,114 @@ impl MqttActorBuilder {
             publish_sender,
             subscriber_addresses: Vec::new(),
             signal_sender,
+            topic_prefixes: TopicPrefixes::default(),
         }
     }
 
+    /// Rename the topics of the messages exchanged with the broker
+    ///
+    /// The peers of the MQTT actor are unaware of the renaming:
+    /// they publish and subscribe using their own topic names.
+    pub fn with_topic_prefixes(mut self, topic_prefixes: TopicPrefixes) -> Self {
+        self.topic_prefixes = topic_prefixes;
+        self
+    }
+
     pub(crate) fn build_actor(self) -> MqttActor {
         let mut combined_topic_filter = TopicFilter::empty();
         for (topic_filter, _) in self.subscriber_addresses.iter() {
-            combined_topic_filter.add_all(topic_filter.to_owned());
+            combined_topic_filter.add_all(self.topic_prefixes.filter_to_broker(topic_filter));
         }
         let mqtt_config = self.mqtt_config.with_subscriptions(combined_topic_filter);
 
-        MqttActor::new(mqtt_config, self.input_receiver, self.subscriber_addresses)
+        MqttActor::new(
+            mqtt_config,
+            self.input_receiver,
+            self.subscriber_addresses,
+            self.topic_prefixes,
+        )
+    }
+}
+
+/// Rules to rename the topics of the messages exchanged by the actors with an MQTT broker
+///
+/// Each rule maps a topic prefix, as used by the actors, to the prefix used on the broker.
+/// This lets several instances of the same actors run side by side on distinct topics,
+/// e.g. a mapper publishing on `c8y/` while its messages are actually published on `c8y@staging/`.
+///
+/// The messages received from the broker on a topic starting with a prefix used by the actors,
+/// but not renamed by a rule, are ignored, as these messages are meant to another instance.
+#[derive(Clone, Debug, Default)]
+pub struct TopicPrefixes {
+    rules: Vec<(String, String)>,
+}
+
+impl TopicPrefixes {
+    /// Add a rule renaming the topics starting with `actor_prefix` to topics starting with `broker_prefix`
+    pub fn with_prefix(
+        mut self,
+        actor_prefix: impl Into<String>,
+        broker_prefix: impl Into<String>,
+    ) -> Self {
+        self.rules.push((actor_prefix.into(), broker_prefix.into()));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The topic name to be used on the broker for a topic used by the actors
+    pub fn topic_to_broker(&self, topic: &str) -> String {
+        for (actor_prefix, broker_prefix) in self.rules.iter() {
+            if let Some(suffix) = topic.strip_prefix(actor_prefix.as_str()) {
+                return format!("{broker_prefix}{suffix}");
+            }
+        }
+        topic.to_string()
+    }
+
+    /// The topic name to be used by the actors for a topic used on the broker
+    ///
+    /// Return `None` if the topic is to be ignored by the actors.
+    pub fn topic_from_broker(&self, topic: &str) -> Option<String> {
+        for (actor_prefix, broker_prefix) in self.rules.iter() {
+            if let Some(suffix) = topic.strip_prefix(broker_prefix.as_str()) {
+                return Some(format!("{actor_prefix}{suffix}"));
+            }
+        }
+        if self
+            .rules
+            .iter()
+            .any(|(actor_prefix, _)| topic.starts_with(actor_prefix.as_str()))
+        {
+            return None;
+        }
+        Some(topic.to_string())
+    }
+
+    /// The topic filter to be used on the broker for a topic filter used by the actors
+    pub fn filter_to_broker(&self, filter: &TopicFilter) -> TopicFilter {
+        let mut broker_filter = TopicFilter::empty().with_qos(filter.qos);
+        for pattern in filter.patterns.iter() {
+            broker_filter.add_unchecked(&self.topic_to_broker(pattern));
+        }
+        broker_filter
+    }
+
+    fn message_to_broker(&self, mut message: MqttMessage) -> MqttMessage {
+        if !self.is_empty() {
+            message.topic = Topic::new_unchecked(&self.topic_to_broker(&message.topic.name));
+        }
+        message
+    }
+
+    fn message_from_broker(&self, mut message: MqttMessage) -> Option<MqttMessage> {
+        if !self.is_empty() {
+            let topic = self.topic_from_broker(&message.topic.name)?;
+            message.topic = Topic::new_unchecked(&topic);
+        }
+        Some(message)
     }
 }
 
@@ -122,10 +220,12 @@ impl Builder<MqttActor> for MqttActorBuilder {
 
 pub struct FromPeers {
     input_receiver: LoggingReceiver<MqttMessage>,
+    topic_prefixes: TopicPrefixes,
 }
 
 pub struct ToPeers {
     peer_senders: Vec<(TopicFilter, LoggingSender<MqttMessage>)>,
+    topic_prefixes: TopicPrefixes,
 }
 
 impl FromPeers {
@@ -135,7 +235,10 @@ impl FromPeers {
     ) -> Result<(), RuntimeError> {
         loop {
             match self.try_recv().await {
-                Ok(Some(message)) => outgoing_mqtt.send(message).await.map_err(Box::new)?,
+                Ok(Some(message)) => {
+                    let message = self.topic_prefixes.message_to_broker(message);
+                    outgoing_mqtt.send(message).await.map_err(Box::new)?
+                }
                 Ok(None) | Err(RuntimeRequest::Shutdown) => break Ok(()),
             }
         }
@@ -154,6 +257,9 @@ impl ToPeers {
     }
 
     async fn send(&mut self, message: MqttMessage) -> Result<(), ChannelError> {
+        let Some(message) = self.topic_prefixes.message_from_broker(message) else {
+            return Ok(());
+        };
         for (topic_filter, peer_sender) in self.peer_senders.iter_mut() {
             if topic_filter.accept(&message) {
                 peer_sender.send(message.clone()).await?;
@@ -193,11 +299,18 @@ impl MqttActor {
         mqtt_config: mqtt_channel::Config,
         input_receiver: LoggingReceiver<MqttMessage>,
         peer_senders: Vec<(TopicFilter, LoggingSender<MqttMessage>)>,
+        topic_prefixes: TopicPrefixes,
     ) -> Self {
         MqttActor {
             mqtt_config,
-            from_peers: FromPeers { input_receiver },
-            to_peers: ToPeers { peer_senders },
+            from_peers: FromPeers {
+                input_receiver,
+                topic_prefixes: topic_prefixes.clone(),
+            },
+            to_peers: ToPeers {
+                peer_senders,
+                topic_prefixes,
+            },
         }
     }
 }
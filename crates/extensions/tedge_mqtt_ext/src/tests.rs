@@ -182,6 +182,77 @@ async fn communicate_over_mqtt() {
     assert_eq!(messages, vec!["1", "2", "3", "A", "B", "C"])
 }
 
+#[test]
+fn topic_prefixes_rename_topics_and_filters() {
+    let prefixes = TopicPrefixes::default()
+        .with_prefix(
+            "te/device/main/service/mosquitto-c8y-bridge/",
+            "te/device/main/service/mosquitto-c8y@staging-bridge/",
+        )
+        .with_prefix("c8y/", "c8y@staging/");
+
+    assert_eq!(prefixes.topic_to_broker("c8y/s/us"), "c8y@staging/s/us");
+    assert_eq!(
+        prefixes.topic_to_broker("te/device/main///m/"),
+        "te/device/main///m/"
+    );
+    assert_eq!(
+        prefixes.topic_from_broker("c8y@staging/s/ds"),
+        Some("c8y/s/ds".to_string())
+    );
+    assert_eq!(
+        prefixes
+            .topic_from_broker("te/device/main/service/mosquitto-c8y@staging-bridge/status/health"),
+        Some("te/device/main/service/mosquitto-c8y-bridge/status/health".to_string())
+    );
+    assert_eq!(
+        prefixes.topic_from_broker("te/device/main///m/"),
+        Some("te/device/main///m/".to_string())
+    );
+
+    // Messages sent to the default connection are not forwarded to the actors
+    assert_eq!(prefixes.topic_from_broker("c8y/s/ds"), None);
+    assert_eq!(
+        prefixes.topic_from_broker("te/device/main/service/mosquitto-c8y-bridge/status/health"),
+        None
+    );
+
+    let filter: TopicFilter = vec!["c8y/s/ds", "te/#"].try_into().unwrap();
+    assert_eq!(
+        prefixes.filter_to_broker(&filter).patterns,
+        vec!["c8y@staging/s/ds", "te/#"]
+    );
+}
+
+#[tokio::test]
+async fn messages_are_exchanged_with_the_broker_on_renamed_topics() {
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = MqttConfig::default().with_port(broker.port);
+    let mut mqtt = MqttActorBuilder::new(mqtt_config)
+        .with_topic_prefixes(TopicPrefixes::default().with_prefix("cloud/", "cloud@test/"));
+
+    let downstream = Topic::new_unchecked("cloud/downstream");
+    let mut mapper: MqttClient = MqttClientBuilder::new("Mapper", &downstream)
+        .with_connection(&mut mqtt)
+        .build();
+    let mut upstream = broker.messages_published_on("cloud@test/upstream").await;
+
+    tokio::spawn(mqtt_actor(mqtt));
+
+    let message = MqttMessage::new(&Topic::new_unchecked("cloud/upstream"), "up");
+    mapper.send(message).await.unwrap();
+    assert_eq!(upstream.next().await, Some("up".to_string()));
+
+    broker
+        .publish("cloud@test/downstream", "down")
+        .await
+        .unwrap();
+    assert_eq!(
+        mapper.recv().await,
+        Some(MqttMessage::new(&downstream, "down"))
+    );
+}
+
 async fn mqtt_actor(builder: MqttActorBuilder) {
     let mqtt_actor = builder.build();
     mqtt_actor.run().await.unwrap()
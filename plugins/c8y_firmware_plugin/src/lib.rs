@@ -83,7 +83,7 @@ async fn run_with(tedge_config: TEdgeConfig) -> Result<(), anyhow::Error> {
 
     // Create actor instances
     let mqtt_config = tedge_config.mqtt_config()?;
-    let mut jwt_actor = C8YJwtRetriever::builder(mqtt_config.clone());
+    let mut jwt_actor = C8YJwtRetriever::builder(mqtt_config.clone(), "c8y");
     let mut timer_actor = TimerActor::builder();
     let identity = tedge_config.http.client.auth.identity()?;
     let mut downloader_actor = DownloaderActor::new(identity).builder();
@@ -128,7 +128,7 @@ async fn run(config_dir: impl AsRef<Path>, tedge_config: TEdgeConfig) -> Result<
         tedge_config.service.ty.clone(),
     );
 
-    let mut jwt_actor = C8YJwtRetriever::builder(base_mqtt_config);
+    let mut jwt_actor = C8YJwtRetriever::builder(base_mqtt_config, "c8y");
     let mut http_actor = HttpActor::new().builder();
     let mut c8y_http_proxy_actor =
         C8YHttpProxyBuilder::new(c8y_http_config, &mut http_actor, &mut jwt_actor);
@@ -0,0 +1,134 @@
+use crate::error::InternalError;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Stdio;
+
+/// The container engine CLIs looked for, in that order, when none is configured
+const KNOWN_ENGINES: [&str; 2] = ["docker", "podman"];
+
+/// A container engine CLI, `docker` or `podman`, which are used with the same arguments
+#[derive(Clone, Debug)]
+pub struct ContainerEngine {
+    cli: PathBuf,
+}
+
+impl ContainerEngine {
+    pub fn new(cli: impl Into<PathBuf>) -> Self {
+        ContainerEngine { cli: cli.into() }
+    }
+
+    /// Use the configured CLI if any, otherwise the first of `docker` and `podman` found in the path
+    pub fn detect(configured: Option<PathBuf>) -> Result<Self, InternalError> {
+        if let Some(cli) = configured {
+            return Ok(ContainerEngine::new(cli));
+        }
+        KNOWN_ENGINES
+            .iter()
+            .find_map(|cli| which::which(cli).ok())
+            .map(ContainerEngine::new)
+            .ok_or(InternalError::NoContainerEngine)
+    }
+
+    /// List the containers as pairs of container name and image
+    pub fn list_containers(&self) -> Result<(ExitStatus, Vec<(String, String)>), InternalError> {
+        let output = self
+            .command(&["ps", "--all", "--format", "{{.Names}}\t{{.Image}}"])
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|err| self.exec_error(err))?;
+
+        let stdout = String::from_utf8(output.stdout)?;
+        let containers = stdout
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(name, image)| (name.to_string(), image.to_string()))
+            .collect();
+        Ok((output.status, containers))
+    }
+
+    /// Check if there is a container with that name
+    pub fn container_exists(&self, name: &str) -> Result<bool, InternalError> {
+        let status = self
+            .command(&["container", "inspect", name])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|err| self.exec_error(err))?;
+        Ok(status.success())
+    }
+
+    pub fn pull(&self, image: &str) -> Result<ExitStatus, InternalError> {
+        self.run(&["pull", image])
+    }
+
+    /// Load an image from an archive file
+    pub fn load(&self, file: &str) -> Result<ExitStatus, InternalError> {
+        self.run(&["load", "--input", file])
+    }
+
+    /// Run a detached container, restarted unless explicitly stopped
+    pub fn run_container(&self, name: &str, image: &str) -> Result<ExitStatus, InternalError> {
+        self.run(&[
+            "run",
+            "--detach",
+            "--name",
+            name,
+            "--restart",
+            "unless-stopped",
+            image,
+        ])
+    }
+
+    pub fn remove_container(&self, name: &str) -> Result<ExitStatus, InternalError> {
+        self.run(&["rm", "--force", name])
+    }
+
+    /// Start or update the containers of a compose project
+    pub fn compose_up(&self, project: &str, file: &str) -> Result<ExitStatus, InternalError> {
+        self.run(&[
+            "compose",
+            "--project-name",
+            project,
+            "--file",
+            file,
+            "up",
+            "--detach",
+            "--remove-orphans",
+        ])
+    }
+
+    /// Stop and remove the containers of a compose project
+    pub fn compose_down(&self, project: &str, file: &str) -> Result<ExitStatus, InternalError> {
+        self.run(&[
+            "compose",
+            "--project-name",
+            project,
+            "--file",
+            file,
+            "down",
+            "--remove-orphans",
+        ])
+    }
+
+    /// Remove the images no more used by any container
+    pub fn prune_images(&self) -> Result<ExitStatus, InternalError> {
+        self.run(&["image", "prune", "--force"])
+    }
+
+    fn run(&self, args: &[&str]) -> Result<ExitStatus, InternalError> {
+        self.command(args)
+            .status()
+            .map_err(|err| self.exec_error(err))
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(&self.cli);
+        command.args(args).stdin(Stdio::null());
+        command
+    }
+
+    fn exec_error(&self, err: std::io::Error) -> InternalError {
+        InternalError::exec_error(self.cli.display().to_string(), err)
+    }
+}
@@ -0,0 +1,32 @@
+#[derive(thiserror::Error, Debug)]
+pub enum InternalError {
+    #[error("Fail to run `{cmd}`: {from}")]
+    ExecError { cmd: String, from: std::io::Error },
+
+    #[error("No container engine found: install docker or podman, or set `container.runtime`")]
+    NoContainerEngine,
+
+    #[error("A compose file is required to install the container group `{name}`")]
+    MissingComposeFile { name: String },
+
+    #[error("Invalid module name: {name:?}")]
+    InvalidModuleName { name: String },
+
+    #[error(transparent)]
+    FromIo(#[from] std::io::Error),
+
+    #[error(transparent)]
+    FromUtf8(#[from] std::string::FromUtf8Error),
+
+    #[error(transparent)]
+    FromCsv(#[from] csv::Error),
+}
+
+impl InternalError {
+    pub fn exec_error(cmd: impl Into<String>, from: std::io::Error) -> InternalError {
+        InternalError::ExecError {
+            cmd: cmd.into(),
+            from,
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::error::InternalError;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
+
+const COMPOSE_FILE: &str = "docker-compose.yaml";
+const VERSION_FILE: &str = "version";
+
+/// The compose projects installed by the plugin.
+///
+/// Each project is stored in a directory named after the project,
+/// holding the compose file of the project and the installed version.
+#[derive(Clone, Debug)]
+pub struct GroupStore {
+    dir: PathBuf,
+}
+
+/// A compose project as stored before an update, used to restore that project if the update fails
+pub struct StoredGroup {
+    dir: PathBuf,
+    compose_file: Option<Vec<u8>>,
+    version: Option<Vec<u8>>,
+}
+
+impl GroupStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        GroupStore { dir: dir.into() }
+    }
+
+    /// List the installed projects as pairs of project name and version
+    pub fn list(&self) -> Result<Vec<(String, String)>, InternalError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut groups = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if !path.join(COMPOSE_FILE).is_file() {
+                continue;
+            }
+            if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                let version = fs::read_to_string(path.join(VERSION_FILE)).unwrap_or_default();
+                groups.push((name.to_string(), version.trim().to_string()));
+            }
+        }
+        groups.sort();
+        Ok(groups)
+    }
+
+    /// The path of the compose file of a project, if that project is installed
+    pub fn compose_file(&self, name: &str) -> Result<Option<PathBuf>, InternalError> {
+        let file = self.group_dir(name)?.join(COMPOSE_FILE);
+        Ok(file.is_file().then_some(file))
+    }
+
+    /// Store the compose file and version of a project, returning the former content to restore on failure
+    pub fn store(
+        &self,
+        name: &str,
+        compose_file: &Path,
+        version: Option<&str>,
+    ) -> Result<(PathBuf, StoredGroup), InternalError> {
+        let dir = self.group_dir(name)?;
+        let previous = StoredGroup {
+            compose_file: fs::read(dir.join(COMPOSE_FILE)).ok(),
+            version: fs::read(dir.join(VERSION_FILE)).ok(),
+            dir: dir.clone(),
+        };
+
+        fs::create_dir_all(&dir)?;
+        let stored_file = dir.join(COMPOSE_FILE);
+        fs::copy(compose_file, &stored_file)?;
+        fs::write(dir.join(VERSION_FILE), version.unwrap_or_default())?;
+        Ok((stored_file, previous))
+    }
+
+    pub fn remove(&self, name: &str) -> Result<(), InternalError> {
+        match fs::remove_dir_all(self.group_dir(name)?) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn group_dir(&self, name: &str) -> Result<PathBuf, InternalError> {
+        let is_valid = !name.is_empty()
+            && name != "."
+            && name != ".."
+            && !name.contains(std::path::is_separator);
+        if is_valid {
+            Ok(self.dir.join(name))
+        } else {
+            Err(InternalError::InvalidModuleName {
+                name: name.to_string(),
+            })
+        }
+    }
+}
+
+impl StoredGroup {
+    /// Restore the project as stored before the update, removing it if it was not installed
+    pub fn restore(self) -> Result<(), InternalError> {
+        match (self.compose_file, self.version) {
+            (Some(compose_file), version) => {
+                fs::write(self.dir.join(COMPOSE_FILE), compose_file)?;
+                fs::write(self.dir.join(VERSION_FILE), version.unwrap_or_default())?;
+            }
+            (None, _) => {
+                let _ = fs::remove_dir_all(&self.dir);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,637 @@
+mod engine;
+mod error;
+mod group;
+
+pub use crate::engine::ContainerEngine;
+use crate::error::InternalError;
+pub use crate::group::GroupStore;
+use log::warn;
+use serde::Deserialize;
+use std::io::{self};
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use tedge_config::TEdgeConfig;
+use tedge_config::TEdgeConfigLocation;
+use tedge_config::TEdgeConfigRepository;
+use tedge_config::DEFAULT_TEDGE_CONFIG_PATH;
+
+/// Name of the plugin managing containers
+pub const CONTAINER: &str = "container";
+
+/// Name of the plugin managing compose projects
+pub const CONTAINER_GROUP: &str = "container-group";
+
+/// Directory of the compose projects, relative to the `data.path`
+const CONTAINER_GROUP_DIR: &str = "container-group";
+
+#[derive(clap::Parser, Debug)]
+#[clap(
+    name = clap::crate_name!(),
+    version = clap::crate_version!(),
+    about = clap::crate_description!(),
+    arg_required_else_help(true)
+)]
+pub struct ContainerCli {
+    #[clap(long = "config-dir", default_value = DEFAULT_TEDGE_CONFIG_PATH)]
+    config_dir: PathBuf,
+
+    /// The container engine CLI to use, overriding `container.runtime`
+    #[clap(long)]
+    runtime: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    operation: PluginOp,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum PluginOp {
+    /// List all the installed modules
+    List,
+
+    /// Install a module
+    Install {
+        module: String,
+        #[clap(short = 'v', long = "module-version")]
+        version: Option<String>,
+        #[clap(long = "file")]
+        file_path: Option<String>,
+    },
+
+    /// Uninstall a module
+    Remove {
+        module: String,
+        #[clap(short = 'v', long = "module-version")]
+        version: Option<String>,
+    },
+
+    /// Install or remove multiple modules at once
+    UpdateList,
+
+    /// Prepare a sequences of install/remove commands
+    Prepare,
+
+    /// Finalize a sequences of install/remove commands
+    Finalize,
+}
+
+/// The kind of software modules managed by the plugin, given by the name the plugin is called with
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ModuleKind {
+    /// Containers run from images.
+    ///
+    /// The module name is the container name and the module version the image,
+    /// either a full image reference or a tag of the image named after the module.
+    Container,
+
+    /// Compose projects.
+    ///
+    /// The module name is the project name and the module file is the compose file of the project.
+    ContainerGroup,
+}
+
+impl ModuleKind {
+    pub fn from_executable_name(name: &str) -> ModuleKind {
+        if name == CONTAINER_GROUP {
+            ModuleKind::ContainerGroup
+        } else {
+            ModuleKind::Container
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum UpdateAction {
+    Install,
+    Remove,
+}
+
+#[derive(Debug, Deserialize)]
+struct SoftwareModuleUpdate {
+    pub action: UpdateAction,
+    pub name: String,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+/// The plugin operations, applied using a container engine
+pub struct ContainerPlugin {
+    kind: ModuleKind,
+    engine: ContainerEngine,
+    groups: GroupStore,
+}
+
+impl ContainerPlugin {
+    pub fn new(kind: ModuleKind, engine: ContainerEngine, groups: GroupStore) -> Self {
+        ContainerPlugin {
+            kind,
+            engine,
+            groups,
+        }
+    }
+
+    fn run_op(&self, operation: PluginOp) -> Result<ExitStatus, InternalError> {
+        match operation {
+            PluginOp::List => {
+                let (status, modules) = self.list()?;
+                for (name, version) in modules {
+                    println!("{name}\t{version}");
+                }
+                Ok(status)
+            }
+
+            PluginOp::Install {
+                module,
+                version,
+                file_path,
+            } => self.install(&module, version.as_deref(), file_path.as_deref()),
+
+            PluginOp::Remove { module, .. } => self.remove(&module),
+
+            PluginOp::UpdateList => {
+                let mut updates: Vec<SoftwareModuleUpdate> = Vec::new();
+                let mut rdr = csv::ReaderBuilder::new()
+                    .has_headers(false)
+                    .delimiter(b'\t')
+                    .flexible(true)
+                    .from_reader(io::stdin());
+                for result in rdr.deserialize() {
+                    updates.push(result?);
+                }
+                self.update_list(updates)
+            }
+
+            PluginOp::Prepare => Ok(success()),
+
+            PluginOp::Finalize => self.engine.prune_images(),
+        }
+    }
+
+    /// List the installed modules as pairs of name and version
+    pub fn list(&self) -> Result<(ExitStatus, Vec<(String, String)>), InternalError> {
+        match self.kind {
+            ModuleKind::Container => self.engine.list_containers(),
+            ModuleKind::ContainerGroup => Ok((success(), self.groups.list()?)),
+        }
+    }
+
+    pub fn install(
+        &self,
+        module: &str,
+        version: Option<&str>,
+        file_path: Option<&str>,
+    ) -> Result<ExitStatus, InternalError> {
+        match self.kind {
+            ModuleKind::Container => {
+                let image = image_reference(module, version);
+                let status = match file_path {
+                    Some(file_path) => self.engine.load(file_path)?,
+                    None => self.engine.pull(&image)?,
+                };
+                if !status.success() {
+                    return Ok(status);
+                }
+
+                if self.engine.container_exists(module)? {
+                    let status = self.engine.remove_container(module)?;
+                    if !status.success() {
+                        return Ok(status);
+                    }
+                }
+                self.engine.run_container(module, &image)
+            }
+
+            ModuleKind::ContainerGroup => {
+                let Some(file_path) = file_path else {
+                    return Err(InternalError::MissingComposeFile {
+                        name: module.to_string(),
+                    });
+                };
+                let version = version.filter(|version| *version != "latest");
+                let (compose_file, previous) =
+                    self.groups.store(module, Path::new(file_path), version)?;
+
+                let status = self
+                    .engine
+                    .compose_up(module, &compose_file.to_string_lossy())?;
+                if !status.success() {
+                    previous.restore()?;
+                }
+                Ok(status)
+            }
+        }
+    }
+
+    pub fn remove(&self, module: &str) -> Result<ExitStatus, InternalError> {
+        match self.kind {
+            ModuleKind::Container => {
+                if self.engine.container_exists(module)? {
+                    self.engine.remove_container(module)
+                } else {
+                    Ok(success())
+                }
+            }
+
+            ModuleKind::ContainerGroup => {
+                let Some(compose_file) = self.groups.compose_file(module)? else {
+                    return Ok(success());
+                };
+                let status = self
+                    .engine
+                    .compose_down(module, &compose_file.to_string_lossy())?;
+                if status.success() {
+                    self.groups.remove(module)?;
+                }
+                Ok(status)
+            }
+        }
+    }
+
+    /// Apply all the updates, returning the status of the last failed update if any
+    fn update_list(&self, updates: Vec<SoftwareModuleUpdate>) -> Result<ExitStatus, InternalError> {
+        let mut outcome = success();
+        for update in updates {
+            let status = match update.action {
+                UpdateAction::Install => self.install(
+                    &update.name,
+                    non_empty(&update.version),
+                    non_empty(&update.path),
+                ),
+                UpdateAction::Remove => self.remove(&update.name),
+            };
+            match status {
+                Ok(status) if status.success() => {}
+                Ok(status) => outcome = status,
+                Err(err) => {
+                    eprintln!("ERROR: {err}");
+                    outcome = failure();
+                }
+            }
+        }
+        Ok(outcome)
+    }
+}
+
+/// The image of a container module: the version if a full image reference, or else the module image with that tag
+fn image_reference(module: &str, version: Option<&str>) -> String {
+    match version {
+        None | Some("") => module.to_string(),
+        Some(version) if version.contains([':', '/', '@']) => version.to_string(),
+        Some(tag) => format!("{module}:{tag}"),
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().filter(|value| !value.is_empty())
+}
+
+fn success() -> ExitStatus {
+    std::os::unix::process::ExitStatusExt::from_raw(0)
+}
+
+fn failure() -> ExitStatus {
+    // Exit code 2, encoded as by waitpid
+    std::os::unix::process::ExitStatusExt::from_raw(2 << 8)
+}
+
+fn get_config(config_dir: PathBuf) -> Option<TEdgeConfig> {
+    let tedge_config_location = TEdgeConfigLocation::from_custom_root(config_dir);
+
+    match TEdgeConfigRepository::new(tedge_config_location).load() {
+        Ok(config) => Some(config),
+        Err(err) => {
+            warn!("Failed to load TEdgeConfig: {}", err);
+            None
+        }
+    }
+}
+
+pub fn run_and_exit(cli: Result<ContainerCli, clap::Error>, kind: ModuleKind) -> ! {
+    let cli = match cli {
+        Ok(cli) => cli,
+        Err(err) => {
+            err.print().expect("Failed to print help message");
+            // re-write the clap exit_status from 2 to 1, if parse fails
+            std::process::exit(1)
+        }
+    };
+
+    let config = get_config(cli.config_dir);
+    let runtime = cli.runtime.or_else(|| {
+        config
+            .as_ref()
+            .and_then(|config| config.container.runtime.or_none().map(PathBuf::from))
+    });
+    let data_dir = config
+        .as_ref()
+        .map(|config| config.data.path.as_std_path().to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("/var/tedge"));
+
+    let outcome = ContainerEngine::detect(runtime).and_then(|engine| {
+        let groups = GroupStore::new(data_dir.join(CONTAINER_GROUP_DIR));
+        ContainerPlugin::new(kind, engine, groups).run_op(cli.operation)
+    });
+
+    match outcome {
+        Ok(status) if status.success() => {
+            std::process::exit(0);
+        }
+
+        Ok(status) => {
+            if status.code().is_some() {
+                std::process::exit(2);
+            } else {
+                eprintln!("Interrupted by a signal!");
+                std::process::exit(4);
+            }
+        }
+
+        Err(err) => {
+            eprintln!("ERROR: {}", err);
+            std::process::exit(5);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    /// A fake container engine CLI, which records its arguments and simulates a few containers
+    struct FakeEngine {
+        dir: TempDir,
+    }
+
+    impl FakeEngine {
+        fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let cli = dir.path().join("docker");
+            let log = dir.path().join("calls");
+            fs::write(
+                &cli,
+                format!(
+                    r#"#!/bin/sh
+echo "$@" >> {log}
+case "$1" in
+    ps) printf 'nginx\tnginx:1.25\nmqtt\teclipse-mosquitto:2.0\n' ;;
+    container) [ "$3" = nginx ] ;;
+    pull) [ "$2" != "broken:latest" ] ;;
+    compose) ! grep -q invalid "$5" ;;
+esac
+"#,
+                    log = log.display()
+                ),
+            )
+            .unwrap();
+            fs::set_permissions(&cli, fs::Permissions::from_mode(0o755)).unwrap();
+            FakeEngine { dir }
+        }
+
+        fn plugin(&self, kind: ModuleKind) -> ContainerPlugin {
+            ContainerPlugin::new(
+                kind,
+                ContainerEngine::new(self.dir.path().join("docker")),
+                GroupStore::new(self.dir.path().join("groups")),
+            )
+        }
+
+        fn calls(&self) -> Vec<String> {
+            fs::read_to_string(self.dir.path().join("calls"))
+                .unwrap_or_default()
+                .lines()
+                .map(str::to_string)
+                .collect()
+        }
+
+        fn compose_file(&self, content: &str) -> PathBuf {
+            let file = self.dir.path().join("compose.yaml");
+            fs::write(&file, content).unwrap();
+            file
+        }
+    }
+
+    #[test]
+    fn containers_are_listed_with_their_images() {
+        let engine = FakeEngine::new();
+        let plugin = engine.plugin(ModuleKind::Container);
+
+        let (status, modules) = plugin.list().unwrap();
+
+        assert!(status.success());
+        assert_eq!(
+            modules,
+            vec![
+                ("nginx".to_string(), "nginx:1.25".to_string()),
+                ("mqtt".to_string(), "eclipse-mosquitto:2.0".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn installing_a_container_pulls_the_image_and_replaces_the_container() {
+        let engine = FakeEngine::new();
+        let plugin = engine.plugin(ModuleKind::Container);
+
+        let status = plugin.install("nginx", Some("1.26"), None).unwrap();
+
+        assert!(status.success());
+        assert_eq!(
+            engine.calls(),
+            vec![
+                "pull nginx:1.26",
+                "container inspect nginx",
+                "rm --force nginx",
+                "run --detach --name nginx --restart unless-stopped nginx:1.26",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_container_can_be_installed_from_a_full_image_reference_or_an_archive() {
+        let engine = FakeEngine::new();
+        let plugin = engine.plugin(ModuleKind::Container);
+
+        plugin
+            .install("broker", Some("eclipse-mosquitto:2.0"), None)
+            .unwrap();
+        plugin
+            .install("app", Some("registry.local/app:1.0"), Some("/tmp/app.tar"))
+            .unwrap();
+
+        assert_eq!(
+            engine.calls(),
+            vec![
+                "pull eclipse-mosquitto:2.0",
+                "container inspect broker",
+                "run --detach --name broker --restart unless-stopped eclipse-mosquitto:2.0",
+                "load --input /tmp/app.tar",
+                "container inspect app",
+                "run --detach --name app --restart unless-stopped registry.local/app:1.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_failed_pull_stops_the_install() {
+        let engine = FakeEngine::new();
+        let plugin = engine.plugin(ModuleKind::Container);
+
+        let status = plugin.install("broken", Some("latest"), None).unwrap();
+
+        assert!(!status.success());
+        assert_eq!(engine.calls(), vec!["pull broken:latest"]);
+    }
+
+    #[test]
+    fn removing_a_missing_container_is_a_no_op() {
+        let engine = FakeEngine::new();
+        let plugin = engine.plugin(ModuleKind::Container);
+
+        assert!(plugin.remove("unknown").unwrap().success());
+        assert!(plugin.remove("nginx").unwrap().success());
+
+        assert_eq!(
+            engine.calls(),
+            vec![
+                "container inspect unknown",
+                "container inspect nginx",
+                "rm --force nginx",
+            ]
+        );
+    }
+
+    #[test]
+    fn compose_projects_are_stored_started_and_removed() {
+        let engine = FakeEngine::new();
+        let plugin = engine.plugin(ModuleKind::ContainerGroup);
+        let compose_file = engine.compose_file("services: {}");
+        let stored_file = engine.dir.path().join("groups/app/docker-compose.yaml");
+
+        let status = plugin
+            .install("app", Some("1.0"), Some(compose_file.to_str().unwrap()))
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(fs::read_to_string(&stored_file).unwrap(), "services: {}");
+        assert_eq!(
+            plugin.list().unwrap().1,
+            vec![("app".to_string(), "1.0".to_string())]
+        );
+
+        let status = plugin.remove("app").unwrap();
+        assert!(status.success());
+        assert!(plugin.list().unwrap().1.is_empty());
+
+        let stored_file = stored_file.display();
+        assert_eq!(
+            engine.calls(),
+            vec![
+                format!(
+                    "compose --project-name app --file {stored_file} up --detach --remove-orphans"
+                ),
+                format!("compose --project-name app --file {stored_file} down --remove-orphans"),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_failed_compose_project_update_restores_the_former_project() {
+        let engine = FakeEngine::new();
+        let plugin = engine.plugin(ModuleKind::ContainerGroup);
+        let stored_file = engine.dir.path().join("groups/app/docker-compose.yaml");
+
+        let compose_file = engine.compose_file("services: {}");
+        let status = plugin
+            .install("app", Some("1.0"), Some(compose_file.to_str().unwrap()))
+            .unwrap();
+        assert!(status.success());
+
+        let compose_file = engine.compose_file("invalid");
+        let status = plugin
+            .install("app", Some("2.0"), Some(compose_file.to_str().unwrap()))
+            .unwrap();
+        assert!(!status.success());
+        assert_eq!(fs::read_to_string(stored_file).unwrap(), "services: {}");
+        assert_eq!(
+            plugin.list().unwrap().1,
+            vec![("app".to_string(), "1.0".to_string())]
+        );
+
+        // A new project that fails to start is not kept
+        let status = plugin
+            .install("other", Some("1.0"), Some(compose_file.to_str().unwrap()))
+            .unwrap();
+        assert!(!status.success());
+        assert_eq!(plugin.list().unwrap().1.len(), 1);
+    }
+
+    #[test]
+    fn a_compose_file_is_required_to_install_a_group() {
+        let engine = FakeEngine::new();
+        let plugin = engine.plugin(ModuleKind::ContainerGroup);
+
+        assert!(matches!(
+            plugin.install("app", Some("1.0"), None),
+            Err(InternalError::MissingComposeFile { .. })
+        ));
+        assert!(matches!(
+            plugin.install("../app", None, Some("/tmp/compose.yaml")),
+            Err(InternalError::InvalidModuleName { .. })
+        ));
+    }
+
+    #[test]
+    fn update_list_applies_all_the_updates() {
+        let engine = FakeEngine::new();
+        let plugin = engine.plugin(ModuleKind::Container);
+
+        let status = plugin
+            .update_list(vec![
+                SoftwareModuleUpdate {
+                    action: UpdateAction::Install,
+                    name: "broken".into(),
+                    version: Some("latest".into()),
+                    path: None,
+                },
+                SoftwareModuleUpdate {
+                    action: UpdateAction::Remove,
+                    name: "nginx".into(),
+                    version: None,
+                    path: Some("".into()),
+                },
+            ])
+            .unwrap();
+
+        // The failure of an update doesn't prevent the others to be applied
+        assert!(!status.success());
+        assert_eq!(
+            engine.calls(),
+            vec![
+                "pull broken:latest",
+                "container inspect nginx",
+                "rm --force nginx"
+            ]
+        );
+    }
+
+    #[test]
+    fn module_kind_is_given_by_the_executable_name() {
+        assert_eq!(
+            ModuleKind::from_executable_name("container-group"),
+            ModuleKind::ContainerGroup
+        );
+        assert_eq!(
+            ModuleKind::from_executable_name("container"),
+            ModuleKind::Container
+        );
+        assert_eq!(
+            ModuleKind::from_executable_name("tedge-container-plugin"),
+            ModuleKind::Container
+        );
+    }
+}
@@ -0,0 +1,13 @@
+use clap::Parser;
+use std::path::PathBuf;
+use tedge_container_plugin::ModuleKind;
+
+fn main() {
+    let executable_name = std::env::args_os()
+        .next()
+        .map(PathBuf::from)
+        .and_then(|path| Some(path.file_stem()?.to_str()?.to_owned()))
+        .unwrap_or_default();
+    let cli = tedge_container_plugin::ContainerCli::try_parse();
+    tedge_container_plugin::run_and_exit(cli, ModuleKind::from_executable_name(&executable_name));
+}